@@ -0,0 +1,145 @@
+# Deutsche Texte für alles, was das Backend dem Benutzer anzeigt.
+# Platzhalter wie { $name } werden vom Backend ausgefüllt.
+
+## Systemereignisse
+
+api-key-accepted = Steam hat den API-Schlüssel akzeptiert
+api-key-rejected = Steam hat den API-Schlüssel abgelehnt, daher können Spieler nicht über die Steam-API nachgeschlagen werden. Hol dir einen Schlüssel unter https://steamcommunity.com/dev/apikey und trage ihn in den Einstellungen ein.
+api-key-locked = Der Steam-API-Schlüssel ist verschlüsselt, konnte aber nicht entschlüsselt werden. Bitte starte den Client mit dem Passwort, mit dem er verschlüsselt wurde.
+steam-connection-invalid = Steam ist auf dem eingestellten Weg nicht erreichbar ({ $error }), daher werden Anfragen weiterhin wie bisher gestellt.
+steam-api-schema-drift = Steam hat das Format der Antworten von { $endpoint } geändert ({ $field }), daher könnten einige Spielerinformationen fehlen, bis der Client aktualisiert wird.
+steam-api-quota = Heute wurden { $requests } der { $quota } täglich erlaubten Steam-API-Anfragen verbraucht. Wird das Limit erreicht, schlagen Abfragen fehl.
+backup-restored = { $file } konnte nicht geladen werden ({ $error }), daher wurde die letzte Sicherung wiederhergestellt. Einige kürzliche Änderungen könnten verloren gegangen sein.
+signal-needs-review = { $signal } ist bei { $failed } von { $votes } Votekicks gescheitert und markiert möglicherweise die falschen Spieler, bitte überprüfen.
+player-flagged = { $player } wurde von { $detector } gemeldet: { $reason }
+player-in-flagged-group = { $player } ist in Steam-Gruppen, die für Bots oder Cheater bekannt sind: { $groups }
+marked-player-banned = { $player } ({ $steamid }), den du als { $verdict } markiert hast, wurde inzwischen von Valve gebannt.
+vote-protects-bots = { $player } ({ $steamid }) hat bei { $kept } von { $total } Kickabstimmungen dafür gestimmt, markierte Bots zu behalten, und schützt sie möglicherweise.
+vote-kept-cheater = Die Abstimmung, { $player } ({ $steamid }) zu kicken, ist gescheitert, obwohl er als Cheater markiert ist. Dagegen gestimmt haben: { $voters }.
+lobby-join-spike = { $count } Spieler sind innerhalb von { $seconds } Sekunden beigetreten, weit mehr als sonst auf diesem Server: { $players }.
+lobby-leave-spike = { $count } Spieler haben den Server innerhalb von { $seconds } Sekunden verlassen, weit mehr als sonst: { $players }.
+demo-abandoned = Die Demo { $demo } wird nicht mehr analysiert ({ $reason }). Sie ist möglicherweise beschädigt. Was bis dahin gelesen wurde, steht trotzdem im Spielbericht.
+demo-space-low = Für Demos in { $dir } sind nur noch { $free } MiB frei. Schaffe etwas Platz, damit Aufnahmen nicht abgebrochen werden.
+demo-space-critical = Für Demos in { $dir } sind nur noch { $free } MiB frei. Demos könnten mitten im Spiel abbrechen, wodurch die Beweise darin verloren gehen.
+demo-space-cleaned = Die { $count } ältesten Demos wurden gelöscht, um { $freed } MiB freizugeben.
+
+## Spielerzusammenfassungen
+
+summary-verdict = Markiert als { $verdict }.
+summary-names = Früher gesehen als { $names }.
+summary-signals = Markiert durch: { $signals }.
+summary-no-record = Du hast keine Aufzeichnungen zu diesem Spieler.
+
+## Chatbefehle
+
+chat-command-usage = Chatbefehle sind "!mac mark <Spieler> <Urteil>" und "!mac kick <Spieler> [Grund]", wobei der Spieler "last", "killer" oder ein Teil seines Namens ist.
+chat-command-no-target = Kein einzelner Spieler auf dem Server passt dazu.
+chat-command-marked = { $player } wurde als { $verdict } markiert.
+chat-command-kick = Votekick gegen { $player } gestartet.
+chat-command-kick-blocked = Kein Votekick gegen { $player } gestartet: { $reason }.
+party-command-usage = Frag nach einem Spieler mit "!who <Spieler>", wobei der Spieler "last", "killer" oder ein Teil seines Namens ist.
+party-command-who = { $player }: { $summary }
+
+## Einstellungsdatei
+
+config-unknown-key = { $file } Zeile { $line }: "{ $key }" ist keine Einstellung und wird ignoriert.
+config-did-you-mean = Meintest du "{ $suggestion }"?
+config-invalid-value = { $file } Zeile { $line }: "{ $key }" konnte nicht gelesen werden, daher wird der Standardwert verwendet, bis der Fehler behoben ist ({ $error }).
+config-missing-keys = In { $file } fehlten einige Einstellungen, die mit ihren Standardwerten ergänzt wurden: { $keys }
+
+## Vorschläge
+
+suggestion-mark = { $player } als { $verdict } markieren?
+suggestion-votekick = Votekick gegen { $player } starten?
+suggestion-report = { $player } an Valve melden?
+
+## Profil-URLs
+
+vanity-resolved = { $vanity } ist { $steamid }
+vanity-not-found = Für { $vanity } wurde kein Steam-Profil gefunden
+
+## Masterbase
+
+masterbase-signed-in = Beim Masterbase als { $steamid } angemeldet.
+masterbase-sign-in-failed = Anmeldung beim Masterbase fehlgeschlagen: { $error }
+masterbase-signed-out = Deine Masterbase-Sitzung ist abgelaufen. Bitte melde dich erneut an, um weiterhin Demos und Meldungen einzureichen.
+
+## Diagnose
+
+doctor-game-dir-ok = Spiel gefunden unter { $path }
+doctor-game-dir-missing = Das Spiel wurde unter { $path } nicht gefunden
+doctor-game-dir-fix = Lege das Installationsverzeichnis des Spiels mit --tf2_dir oder in den Einstellungen fest
+doctor-steam-user-missing = Der angemeldete Steam-Benutzer konnte nicht ermittelt werden
+doctor-steam-user-fix = Melde dich auf diesem Computer bei Steam an und führe dies erneut aus
+doctor-launch-opts-ok = Alle benötigten Startoptionen sind gesetzt
+doctor-launch-opts-unknown = Die Startoptionen von { $game } können nicht geprüft werden
+doctor-launch-opts-unknown-fix = Stelle sicher, dass das Spiel mit { $options } gestartet wird
+doctor-launch-opts-missing = Fehlende Startoptionen: { $options }
+doctor-launch-opts-fix = Füge sie in Steam zu den Startoptionen des Spiels hinzu, oder schließe Steam und starte den Client mit --rewrite_launch_opts
+doctor-launch-opts-error = Die Startoptionen konnten nicht gelesen werden: { $error }
+doctor-log-ok = Das Konsolenprotokoll { $path } kann gelesen werden
+doctor-log-missing = Das Konsolenprotokoll { $path } existiert noch nicht
+doctor-log-missing-fix = Das Spiel erstellt es, sobald es mit -condebug gestartet wird
+doctor-log-error = Das Konsolenprotokoll { $path } kann nicht gelesen werden: { $error }
+doctor-log-fix = Stelle sicher, dass der Client das Verzeichnis des Spiels lesen darf
+doctor-rcon-ok = Mit RCON auf Port { $port } verbunden
+doctor-rcon-auth = RCON auf Port { $port } hat das Passwort abgelehnt
+doctor-rcon-auth-fix = Stelle sicher, dass das RCON-Passwort in den Einstellungen dem des Spiels entspricht
+doctor-rcon-unreachable = RCON auf Port { $port } ist nicht erreichbar ({ $error })
+doctor-rcon-unreachable-fix = Starte das Spiel mit den benötigten Startoptionen. Läuft es bereits, stelle sicher, dass der RCON-Port in den Einstellungen dem des Spiels entspricht
+doctor-api-key-ok = Steam hat den API-Schlüssel akzeptiert
+doctor-api-key-invalid = Es ist kein gültiger Steam-API-Schlüssel gesetzt
+doctor-api-key-rejected = Steam hat den API-Schlüssel abgelehnt
+doctor-api-key-fix = Hole dir einen Schlüssel unter https://steamcommunity.com/dev/apikey und setze ihn mit --api_key oder in den Einstellungen
+doctor-api-key-unreachable = Die Steam-API war nicht erreichbar, um den API-Schlüssel zu prüfen ({ $error })
+doctor-api-key-unreachable-fix = Prüfe deine Internetverbindung
+doctor-web-port-ok = Port { $port } ist für die Weboberfläche frei
+doctor-web-port-serving = Die Weboberfläche läuft auf Port { $port }
+doctor-web-port-taken = Port { $port } kann nicht für die Weboberfläche verwendet werden ({ $error })
+doctor-web-port-fix = Beende, was ihn verwendet (vielleicht eine weitere Instanz des Clients), oder wähle mit --port einen anderen Port
+doctor-demos-ok = Demos können unter { $path } gespeichert werden
+doctor-demos-missing = Das Demo-Verzeichnis { $path } existiert nicht
+doctor-demos-error = Demos können nicht unter { $path } gespeichert werden ({ $error })
+doctor-demos-fix = Stelle sicher, dass der Client in das Verzeichnis des Spiels schreiben darf
+doctor-summary-pass = Alles ist bereit.
+doctor-summary-fail = Fehlgeschlagene Prüfungen: { $count }. Befolge die Schritte oben und führe dies dann erneut aus.
+
+## Signale
+
+signal-rule = Die Regel { $name }
+signal-list = Die Liste { $name }
+signal-heuristic = Die Heuristik { $name }
+signal-detector = Der Detektor { $name }
+signal-bot-profile = Ein Steam-Profil, das nach einem Bot aussieht
+
+## Konsolendetektoren
+
+chat-spam-reason = Hat "{ $message }" { $count } Mal hintereinander gesendet
+copied-name-reason = Hat den Namen von { $name } kopiert und mit unsichtbaren oder ähnlich aussehenden Zeichen getarnt
+
+## Demo-Heuristiken
+
+suspicion-voice-spam = Hat minutenlang ohne Pause den Sprachchat benutzt, wie ein Bot, der einen Ton in Schleife abspielt
+suspicion-headshots = Hat fast das ganze Match eine Klasse gespielt und fast jeden Kill mit einem Kopfschuss erzielt
+
+## Beweiszusammenfassungen
+
+evidence-steam-profile = Steam-Profil: { $url }
+evidence-steamid = SteamID: { $steamid } ({ $steam3 })
+evidence-game = Spiel: Team Fortress 2
+evidence-reason = Grund: Cheating
+evidence-name = Name: { $name }
+evidence-also-seen-as = Auch gesehen als: { $names }
+evidence-bans = VAC-Banns: { $vac }, Spielbanns: { $game }
+evidence-server = Server:
+evidence-server-details = { $hostname } ({ $address }) auf { $map }
+evidence-unknown-server = Unbekannter Server
+evidence-unknown-address = unbekannte Adresse
+evidence-unknown-map = unbekannte Karte
+evidence-description = Beschreibung:
+evidence-no-detections = Für diesen Spieler wurden keine automatischen Erkennungen aufgezeichnet.
+evidence-heuristic = { $description } ({ $flags } Mal in { $matches } Spielen, höchste Sicherheit { $confidence }%)
+evidence-flagged-matches = Markiert in Demos:
+evidence-demo = { $demo } auf { $map }, Ticks { $first }-{ $last }: { $kinds } (playdemo { $demo }; demo_gototick { $first })
+evidence-demo-timed = { $demo } auf { $map }, Ticks { $first }-{ $last } ({ $first_time } bis { $last_time }): { $kinds } (playdemo { $demo }; demo_gototick { $first })
+evidence-chat = Chat:
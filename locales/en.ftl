@@ -0,0 +1,145 @@
+# English strings for anything the backend shows to the user.
+# Placeables like { $name } are filled in by the backend.
+
+## System events
+
+api-key-accepted = Steam accepted the API key
+api-key-rejected = Steam rejected the API key, so players can't be looked up through the Steam API. Get a key from https://steamcommunity.com/dev/apikey and set it in the settings.
+api-key-locked = The Steam API key is encrypted but could not be decrypted. Please run the client with the passphrase it was encrypted with.
+steam-connection-invalid = Steam can't be reached the way it was set to be ({ $error }), so requests are still being made as before.
+steam-api-schema-drift = Steam changed the format of { $endpoint } responses ({ $field }), so some player information may be missing until the client is updated.
+steam-api-quota = { $requests } of the { $quota } Steam API requests allowed each day have been used today. Lookups will fail if the limit is reached.
+backup-restored = { $file } could not be loaded ({ $error }), so the most recent backup was restored. Some recent changes may have been lost.
+signal-needs-review = { $signal } has failed { $failed } of { $votes } votekicks and may be marking the wrong players, consider reviewing it.
+player-flagged = { $player } was flagged by { $detector }: { $reason }
+player-in-flagged-group = { $player } is in Steam groups known for bots or cheaters: { $groups }
+marked-player-banned = { $player } ({ $steamid }), who you marked as { $verdict }, has since been banned by Valve.
+vote-protects-bots = { $player } ({ $steamid }) voted to keep marked bots in { $kept } of { $total } kick votes and may be protecting them.
+vote-kept-cheater = The vote to kick { $player } ({ $steamid }), who is marked as a cheater, failed. Voted to keep them: { $voters }.
+lobby-join-spike = { $count } players joined within { $seconds } seconds, far more than usual for this server: { $players }.
+lobby-leave-spike = { $count } players left within { $seconds } seconds, far more than usual for this server: { $players }.
+demo-abandoned = Stopped analysing the demo { $demo } ({ $reason }). It may be corrupt. What was read of it before then is still in the match report.
+demo-space-low = Only { $free } MiB is free for demos in { $dir }. Free up some space so recordings aren't cut short.
+demo-space-critical = Only { $free } MiB is free for demos in { $dir }. Demos may stop recording part way through a match, losing the evidence in them.
+demo-space-cleaned = Deleted the { $count } oldest demos to free up { $freed } MiB.
+
+## Player summaries
+
+summary-verdict = Marked as { $verdict }.
+summary-names = Previously seen as { $names }.
+summary-signals = Marked by: { $signals }.
+summary-no-record = You have no record of this player.
+
+## Chat commands
+
+chat-command-usage = Chat commands are "!mac mark <player> <verdict>" and "!mac kick <player> [reason]", where the player is "last", "killer" or part of their name.
+chat-command-no-target = No single player on the server matches that.
+chat-command-marked = Marked { $player } as { $verdict }.
+chat-command-kick = Called a votekick against { $player }.
+chat-command-kick-blocked = Did not call a votekick against { $player }: { $reason }.
+party-command-usage = Ask about a player with "!who <player>", where the player is "last", "killer" or part of their name.
+party-command-who = { $player }: { $summary }
+
+## Settings file
+
+config-unknown-key = { $file } line { $line }: "{ $key }" is not a setting and is being ignored.
+config-did-you-mean = Did you mean "{ $suggestion }"?
+config-invalid-value = { $file } line { $line }: "{ $key }" could not be read, so its default is being used until it is fixed ({ $error }).
+config-missing-keys = { $file } was missing some settings, which have been added with their defaults: { $keys }
+
+## Suggestions
+
+suggestion-mark = Mark { $player } as { $verdict }?
+suggestion-votekick = Call a votekick against { $player }?
+suggestion-report = Report { $player } to Valve?
+
+## Vanity URLs
+
+vanity-resolved = { $vanity } is { $steamid }
+vanity-not-found = No Steam profile was found for { $vanity }
+
+## Masterbase
+
+masterbase-signed-in = Signed in to the masterbase as { $steamid }.
+masterbase-sign-in-failed = Could not sign in to the masterbase: { $error }
+masterbase-signed-out = Your masterbase session has ended. Please sign in again to keep submitting demos and reports.
+
+## Doctor
+
+doctor-game-dir-ok = Found the game at { $path }
+doctor-game-dir-missing = The game wasn't found at { $path }
+doctor-game-dir-fix = Set the game's install directory with --tf2_dir, or in the settings
+doctor-steam-user-missing = The logged in Steam user couldn't be identified
+doctor-steam-user-fix = Log in to Steam on this computer, then run this again
+doctor-launch-opts-ok = All required launch options are set
+doctor-launch-opts-unknown = Launch options can't be checked for { $game }
+doctor-launch-opts-unknown-fix = Make sure the game is launched with { $options }
+doctor-launch-opts-missing = Missing launch options: { $options }
+doctor-launch-opts-fix = Add them to the game's launch options in Steam, or close Steam and start the client with --rewrite_launch_opts
+doctor-launch-opts-error = The launch options couldn't be read: { $error }
+doctor-log-ok = The console log { $path } can be read
+doctor-log-missing = The console log { $path } doesn't exist yet
+doctor-log-missing-fix = The game creates it once it is launched with -condebug
+doctor-log-error = The console log { $path } can't be read: { $error }
+doctor-log-fix = Make sure the client is allowed to read the game's directory
+doctor-rcon-ok = Connected to RCON on port { $port }
+doctor-rcon-auth = RCON on port { $port } rejected the password
+doctor-rcon-auth-fix = Make sure the RCON password in the settings is the one the game uses
+doctor-rcon-unreachable = RCON couldn't be reached on port { $port } ({ $error })
+doctor-rcon-unreachable-fix = Start the game with the required launch options. If it is already running, make sure the RCON port in the settings is the one the game uses
+doctor-api-key-ok = Steam accepted the API key
+doctor-api-key-invalid = No valid Steam API key is set
+doctor-api-key-rejected = Steam rejected the API key
+doctor-api-key-fix = Get a key from https://steamcommunity.com/dev/apikey and set it with --api_key, or in the settings
+doctor-api-key-unreachable = The Steam API couldn't be reached to check the API key ({ $error })
+doctor-api-key-unreachable-fix = Check your internet connection
+doctor-web-port-ok = Port { $port } is free for the web UI
+doctor-web-port-serving = The web UI is running on port { $port }
+doctor-web-port-taken = Port { $port } can't be used for the web UI ({ $error })
+doctor-web-port-fix = Close whatever is using it, which may be another copy of the client, or choose another port with --port
+doctor-demos-ok = Demos can be saved to { $path }
+doctor-demos-missing = The demo directory { $path } doesn't exist
+doctor-demos-error = Demos can't be saved to { $path } ({ $error })
+doctor-demos-fix = Make sure the client is allowed to write to the game's directory
+doctor-summary-pass = Everything is ready.
+doctor-summary-fail = Checks failed: { $count }. Follow the steps above, then run this again.
+
+## Signals
+
+signal-rule = The rule { $name }
+signal-list = The list { $name }
+signal-heuristic = The { $name } heuristic
+signal-detector = The { $name } detector
+signal-bot-profile = A Steam profile that looks like a bot's
+
+## Console detectors
+
+chat-spam-reason = Sent "{ $message }" { $count } times in a row
+copied-name-reason = Copied the name of { $name }, disguised with invisible or lookalike characters
+
+## Demo heuristics
+
+suspicion-voice-spam = Used voice chat for minutes at a time without a break, like a bot looping a sound
+suspicion-headshots = Played one class nearly all match and landed a headshot with nearly every kill
+
+## Evidence summaries
+
+evidence-steam-profile = Steam profile: { $url }
+evidence-steamid = SteamID: { $steamid } ({ $steam3 })
+evidence-game = Game: Team Fortress 2
+evidence-reason = Reason: Cheating
+evidence-name = Name: { $name }
+evidence-also-seen-as = Also seen as: { $names }
+evidence-bans = VAC bans: { $vac }, game bans: { $game }
+evidence-server = Server:
+evidence-server-details = { $hostname } ({ $address }) on { $map }
+evidence-unknown-server = Unknown server
+evidence-unknown-address = unknown address
+evidence-unknown-map = unknown map
+evidence-description = Description:
+evidence-no-detections = No automated detections were recorded for this player.
+evidence-heuristic = { $description } ({ $flags } times over { $matches } matches, peak confidence { $confidence }%)
+evidence-flagged-matches = Flagged in demos:
+evidence-demo = { $demo } on { $map }, ticks { $first }-{ $last }: { $kinds } (playdemo { $demo }; demo_gototick { $first })
+evidence-demo-timed = { $demo } on { $map }, ticks { $first }-{ $last } ({ $first_time } to { $last_time }): { $kinds } (playdemo { $demo }; demo_gototick { $first })
+evidence-chat = Chat:
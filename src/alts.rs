@@ -0,0 +1,193 @@
+use std::{
+    collections::HashSet,
+    sync::Arc,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{names, server::Server, steamid::SteamID};
+
+/// Hash of the default Steam avatar, which is shared by far too many accounts to mean anything
+const DEFAULT_AVATAR_HASH: &str = "fef49e7fa7e1997310d705b2a6158ff8dc1cdfeb";
+/// Names within this many edits of each other (once normalised) are considered near-identical
+const MAX_NAME_DISTANCE: usize = 2;
+/// Names shorter than this (once normalised) are too common to compare
+const MIN_NAME_LEN: usize = 4;
+/// Friend lists must have at least this many friends, and share this fraction of them
+const MIN_FRIENDS: usize = 5;
+const MIN_FRIEND_OVERLAP: f32 = 0.8;
+/// Number of sessions both accounts must have been seen in
+const MIN_SHARED_SESSIONS: usize = 2;
+/// Number of different reasons needed before a link is suggested
+const MIN_REASONS: usize = 2;
+
+/// Why two accounts might belong to the same person
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum LinkReason {
+    SameAvatar,
+    SimilarName,
+    SameFriends,
+    SharedSessions,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum LinkStatus {
+    Suggested,
+    Confirmed,
+    Dismissed,
+}
+
+/// A possible link between a player and another account, stored on both of their records.
+/// Linked accounts are never merged, the user has to decide what to do with them.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AccountLink {
+    pub steamid: SteamID,
+    pub reasons: Vec<LinkReason>,
+    pub status: LinkStatus,
+    /// Unix timestamp (seconds) of when the link was first suggested
+    pub suggested: u64,
+}
+
+impl AccountLink {
+    pub fn new(steamid: SteamID, reasons: Vec<LinkReason>) -> AccountLink {
+        AccountLink {
+            steamid,
+            reasons,
+            status: LinkStatus::Suggested,
+            suggested: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or_default(),
+        }
+    }
+}
+
+/// [Fold](names::fold) a name and drop anything that isn't a letter or number, so names that
+/// only differ by decoration (e.g. `xX_name_Xx`) still compare as similar
+fn normalise_name(name: &str) -> String {
+    names::fold(name)
+        .chars()
+        .filter(|c| c.is_alphanumeric())
+        .collect()
+}
+
+/// Levenshtein distance between two strings
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    let mut current = vec![0; b.len() + 1];
+
+    for (i, ca) in a.chars().enumerate() {
+        current[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(ca != *cb);
+            current[j + 1] = substitution.min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+        std::mem::swap(&mut previous, &mut current);
+    }
+
+    previous[b.len()]
+}
+
+fn similar_names(a: &str, b: &str) -> bool {
+    let (a, b) = (normalise_name(a), normalise_name(b));
+    a.chars().count() >= MIN_NAME_LEN
+        && b.chars().count() >= MIN_NAME_LEN
+        && edit_distance(&a, &b) <= MAX_NAME_DISTANCE
+}
+
+/// Every name a player is known by, current and previous
+fn names(server: &Server, steamid: SteamID) -> Vec<Arc<str>> {
+    let players = server.players();
+    let mut names: Vec<Arc<str>> = players
+        .records
+        .get(&steamid)
+        .map(|r| r.previous_names.clone())
+        .unwrap_or_default();
+    names.extend(players.game_info.get(&steamid).map(|gi| gi.name.clone()));
+    names.extend(
+        players
+            .steam_info
+            .get(&steamid)
+            .map(|si| si.account_name.clone()),
+    );
+    names
+}
+
+fn friends(server: &Server, steamid: SteamID) -> Option<HashSet<SteamID>> {
+    let friend_info = server.players().friend_info.get(&steamid)?;
+    if friend_info.public != Some(true) || friend_info.len() < MIN_FRIENDS {
+        return None;
+    }
+    Some(friend_info.iter().map(|f| f.steamid).collect())
+}
+
+fn shared_sessions(server: &Server, a: SteamID, b: SteamID) -> usize {
+    server
+        .sessions()
+        .sessions()
+        .iter()
+        .filter(|session| {
+            session.encounters.iter().any(|e| e.steamid == a)
+                && session.encounters.iter().any(|e| e.steamid == b)
+        })
+        .count()
+}
+
+/// Why `a` and `b` might be the same person
+fn link_reasons(server: &Server, a: SteamID, b: SteamID) -> Vec<LinkReason> {
+    let players = server.players();
+    let mut reasons = Vec::new();
+
+    let avatar = |steamid| {
+        players
+            .steam_info
+            .get(&steamid)
+            .map(|si| si.pfp_hash.clone())
+            .filter(|hash| &**hash != DEFAULT_AVATAR_HASH)
+    };
+    if avatar(a).is_some_and(|hash| Some(hash) == avatar(b)) {
+        reasons.push(LinkReason::SameAvatar);
+    }
+
+    let names_b = names(server, b);
+    if names(server, a)
+        .iter()
+        .any(|name| names_b.iter().any(|other| similar_names(name, other)))
+    {
+        reasons.push(LinkReason::SimilarName);
+    }
+
+    if let (Some(friends_a), Some(friends_b)) = (friends(server, a), friends(server, b)) {
+        let shared = friends_a.intersection(&friends_b).count();
+        let total = friends_a.union(&friends_b).count();
+        if shared as f32 / total as f32 >= MIN_FRIEND_OVERLAP {
+            reasons.push(LinkReason::SameFriends);
+        }
+    }
+
+    // Going through the session history is slow, so only bother if it could make a difference
+    if reasons.len() + 1 >= MIN_REASONS && shared_sessions(server, a, b) >= MIN_SHARED_SESSIONS {
+        reasons.push(LinkReason::SharedSessions);
+    }
+
+    reasons
+}
+
+/// Find other known accounts that may belong to the same person as `steamid`, along with why
+pub fn find_links(server: &Server, steamid: SteamID) -> Vec<(SteamID, Vec<LinkReason>)> {
+    server
+        .players()
+        .steam_info
+        .keys()
+        .filter(|&&other| other != steamid)
+        .filter_map(|&other| {
+            let reasons = link_reasons(server, steamid, other);
+            (reasons.len() >= MIN_REASONS).then_some((other, reasons))
+        })
+        .collect()
+}
@@ -0,0 +1,185 @@
+use std::{collections::HashMap, sync::Arc};
+
+use serde::{Deserialize, Serialize};
+
+use crate::player_records::{PlayerRecords, Verdict};
+
+use history::{Session, SessionHistory};
+
+pub mod history;
+
+const SECONDS_PER_HOUR: u64 = 60 * 60;
+const SECONDS_PER_DAY: u64 = 24 * SECONDS_PER_HOUR;
+
+/// Bots and cheaters encountered over some amount of play time
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Trend {
+    pub sessions: u32,
+    /// Time spent on servers, in hours
+    pub hours: f32,
+    pub players: u32,
+    pub bots: u32,
+    pub cheaters: u32,
+}
+
+impl Trend {
+    fn add_encounter(&mut self, verdict: Verdict) {
+        self.players += 1;
+        match verdict {
+            Verdict::Bot => self.bots += 1,
+            Verdict::Cheater => self.cheaters += 1,
+            _ => {}
+        }
+    }
+
+    fn merge(&mut self, other: &Trend) {
+        self.sessions += other.sessions;
+        self.hours += other.hours;
+        self.players += other.players;
+        self.bots += other.bots;
+        self.cheaters += other.cheaters;
+    }
+}
+
+/// A [Trend] along with the rate of encounters
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TrendReport<K> {
+    pub key: K,
+    #[serde(flatten)]
+    pub trend: Trend,
+    pub bots_per_hour: Option<f32>,
+    pub cheaters_per_hour: Option<f32>,
+}
+
+impl<K> TrendReport<K> {
+    fn new(key: K, trend: Trend) -> TrendReport<K> {
+        let per_hour = |count: u32| (trend.hours > 0.0).then(|| count as f32 / trend.hours);
+        TrendReport {
+            key,
+            bots_per_hour: per_hour(trend.bots),
+            cheaters_per_hour: per_hour(trend.cheaters),
+            trend,
+        }
+    }
+}
+
+/// Which sessions to compute trends over
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct TrendFilter {
+    /// Only sessions that started at or after this unix timestamp (seconds)
+    pub since: u64,
+    /// Only sessions on this map
+    pub map: Option<Arc<str>>,
+    /// Only sessions of this type of game
+    pub gamemode: Option<Arc<str>>,
+}
+
+impl TrendFilter {
+    fn matches(&self, session: &Session) -> bool {
+        session.start >= self.since
+            && (self.map.is_none() || self.map == session.map)
+            && (self.gamemode.is_none() || self.gamemode == session.gamemode)
+    }
+}
+
+/// Trends over the session history, by hour of the day (UTC), map, gamemode and server region.
+/// Verdicts are the ones currently on record, so players marked after a session ended still count.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Trends {
+    pub overall: TrendReport<&'static str>,
+    pub by_hour: Vec<TrendReport<u8>>,
+    pub by_map: Vec<TrendReport<Arc<str>>>,
+    pub by_gamemode: Vec<TrendReport<Arc<str>>>,
+    pub by_region: Vec<TrendReport<Arc<str>>>,
+}
+
+/// Label used for sessions where the map, gamemode or region isn't known
+const UNKNOWN: &str = "Unknown";
+
+fn hour_of_day(time: u64) -> usize {
+    ((time % SECONDS_PER_DAY) / SECONDS_PER_HOUR) as usize
+}
+
+/// Add the time spent in `session` to each hour of the day that it covers
+fn add_hours(by_hour: &mut [Trend], session: &Session) {
+    let mut time = session.start;
+    while time < session.end {
+        let next_hour = (time / SECONDS_PER_HOUR + 1) * SECONDS_PER_HOUR;
+        let until = next_hour.min(session.end);
+        by_hour[hour_of_day(time)].hours += (until - time) as f32 / SECONDS_PER_HOUR as f32;
+        time = until;
+    }
+    by_hour[hour_of_day(session.start)].sessions += 1;
+}
+
+fn sorted_reports(trends: HashMap<Arc<str>, Trend>) -> Vec<TrendReport<Arc<str>>> {
+    let mut reports: Vec<_> = trends
+        .into_iter()
+        .map(|(key, trend)| TrendReport::new(key, trend))
+        .collect();
+    reports.sort_by(|a, b| b.trend.hours.total_cmp(&a.trend.hours));
+    reports
+}
+
+impl Trends {
+    /// Compute trends over every session in `history` that passes `filter`
+    pub fn compute(
+        history: &SessionHistory,
+        records: &PlayerRecords,
+        filter: &TrendFilter,
+    ) -> Trends {
+        let mut overall = Trend::default();
+        let mut by_hour = vec![Trend::default(); 24];
+        let mut by_map: HashMap<Arc<str>, Trend> = HashMap::new();
+        let mut by_gamemode: HashMap<Arc<str>, Trend> = HashMap::new();
+        let mut by_region: HashMap<Arc<str>, Trend> = HashMap::new();
+
+        for session in history.sessions().iter().filter(|s| filter.matches(s)) {
+            let mut trend = Trend {
+                sessions: 1,
+                hours: session.duration() as f32 / SECONDS_PER_HOUR as f32,
+                ..Default::default()
+            };
+            add_hours(&mut by_hour, session);
+
+            for encounter in &session.encounters {
+                let verdict = records
+                    .get(&encounter.steamid)
+                    .map(|r| r.verdict)
+                    .unwrap_or(Verdict::Player);
+                trend.add_encounter(verdict);
+                by_hour[hour_of_day(encounter.time)].add_encounter(verdict);
+            }
+
+            overall.merge(&trend);
+            by_map
+                .entry(session.map.clone().unwrap_or(UNKNOWN.into()))
+                .or_default()
+                .merge(&trend);
+            by_gamemode
+                .entry(session.gamemode.clone().unwrap_or(UNKNOWN.into()))
+                .or_default()
+                .merge(&trend);
+            by_region
+                .entry(session.region.clone().unwrap_or(UNKNOWN.into()))
+                .or_default()
+                .merge(&trend);
+        }
+
+        Trends {
+            overall: TrendReport::new("overall", overall),
+            by_hour: by_hour
+                .into_iter()
+                .enumerate()
+                .map(|(hour, trend)| TrendReport::new(hour as u8, trend))
+                .collect(),
+            by_map: sorted_reports(by_map),
+            by_gamemode: sorted_reports(by_gamemode),
+            by_region: sorted_reports(by_region),
+        }
+    }
+}
@@ -0,0 +1,284 @@
+use std::{
+    collections::VecDeque,
+    io::ErrorKind,
+    path::PathBuf,
+    sync::{Arc, OnceLock},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::Context;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    demo::{rounds::MatchResult, votes::KickVote},
+    persistence,
+    server::Gamemode,
+    settings::{ConfigFilesError, Settings},
+    steamid::SteamID,
+};
+
+/// Number of sessions to keep in the history
+const MAX_SESSIONS: usize = 1000;
+/// A session on the same server is treated as a new one after this long without any activity
+/// (seconds), e.g. when the user has left and rejoined later.
+const SESSION_TIMEOUT: u64 = 10 * 60;
+
+pub(crate) fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+/// The region of a Valve matchmaking server, taken from its hostname, e.g. `Virginia` from
+/// `Valve Matchmaking Server (Virginia srcds1010-iad1 #15)`.
+pub fn region_from_hostname(hostname: &str) -> Option<Arc<str>> {
+    static REGEX: OnceLock<Regex> = OnceLock::new();
+    REGEX
+        .get_or_init(|| {
+            Regex::new(r"^Valve Matchmaking Server \((.+?) srcds").expect("Compile region regex")
+        })
+        .captures(hostname)
+        .map(|caps| caps[1].into())
+}
+
+/// A player first seen during a session
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Encounter {
+    pub steamid: SteamID,
+    /// Unix timestamp (seconds)
+    pub time: u64,
+}
+
+/// A continuous period of time spent on one server
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Session {
+    /// Unix timestamp (seconds)
+    pub start: u64,
+    /// Unix timestamp (seconds) of the last time anything was heard from the server
+    pub end: u64,
+    pub map: Option<Arc<str>>,
+    /// The type of game, as worked out from the map
+    pub gamemode: Option<Arc<str>>,
+    pub hostname: Option<Arc<str>>,
+    /// The address of the server, as recorded in a demo
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub address: Option<Arc<str>>,
+    pub region: Option<Arc<str>>,
+    pub encounters: Vec<Encounter>,
+    /// File names of the recordings of demos made during the session
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub recordings: Vec<Arc<str>>,
+    /// The score of the match played during the session, as recorded in its demo
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub result: Option<MatchResult>,
+    /// Kick votes held during the session, with how everyone voted in them
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub votes: Vec<KickVote>,
+}
+
+impl Session {
+    fn new(hostname: Option<Arc<str>>, map: Option<Arc<str>>) -> Session {
+        let now = now();
+        let mut session = Session {
+            start: now,
+            end: now,
+            map: None,
+            gamemode: None,
+            hostname: None,
+            address: None,
+            region: None,
+            encounters: Vec::new(),
+            recordings: Vec::new(),
+            result: None,
+            votes: Vec::new(),
+        };
+        session.fill_in(hostname, map);
+        session
+    }
+
+    /// Whether `hostname` or `map` show a different server or map to this session
+    fn conflicts(&self, hostname: &Option<Arc<str>>, map: &Option<Arc<str>>) -> bool {
+        let differs = |seen: &Option<Arc<str>>, known: &Option<Arc<str>>| matches!((seen, known), (Some(seen), Some(known)) if seen != known);
+        differs(hostname, &self.hostname) || differs(map, &self.map)
+    }
+
+    /// Set the hostname or map if they weren't known yet, as they are printed to the console
+    /// separately. Returns true if anything was set.
+    fn fill_in(&mut self, hostname: Option<Arc<str>>, map: Option<Arc<str>>) -> bool {
+        let mut changed = false;
+        if let (None, Some(hostname)) = (&self.hostname, hostname) {
+            self.region = region_from_hostname(&hostname);
+            self.hostname = Some(hostname);
+            changed = true;
+        }
+        if let (None, Some(map)) = (&self.map, map) {
+            self.gamemode = Some(Gamemode::game_type_from_map(&map));
+            self.map = Some(map);
+            changed = true;
+        }
+        changed
+    }
+
+    /// Length of the session in seconds
+    pub fn duration(&self) -> u64 {
+        self.end.saturating_sub(self.start)
+    }
+}
+
+/// Persistent history of every session the user has played, most recent last.
+/// The last session is the current one until something shows the user has moved on.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SessionHistory {
+    #[serde(skip)]
+    path: PathBuf,
+    #[serde(skip)]
+    active: bool,
+    sessions: VecDeque<Session>,
+}
+
+impl SessionHistory {
+    /// Attempts to load the history from the default location, starting fresh if it
+    /// doesn't exist or can't be read.
+    pub fn load_or_create() -> SessionHistory {
+        let path = Self::locate_history_file()
+            .map_err(|e| tracing::warn!("Failed to find session history location: {:?}", e))
+            .unwrap_or("sessions.json".into());
+
+        match Self::load_from(path.clone()) {
+            Ok(history) => history,
+            Err(ConfigFilesError::IO(_, e)) if e.kind() == ErrorKind::NotFound => {
+                SessionHistory::new(path)
+            }
+            Err(e) => {
+                tracing::warn!("Could not load session history, starting fresh: {:?}", e);
+                SessionHistory::new(path)
+            }
+        }
+    }
+
+    fn new(path: PathBuf) -> SessionHistory {
+        SessionHistory {
+            path,
+            active: false,
+            sessions: VecDeque::new(),
+        }
+    }
+
+    /// Attempt to load the [SessionHistory] from the provided file
+    pub fn load_from(path: PathBuf) -> Result<SessionHistory, ConfigFilesError> {
+        let mut history: SessionHistory = persistence::read_recovering(&path, |contents| {
+            serde_json::from_str(contents)
+                .map_err(|e| ConfigFilesError::Json(path.to_string_lossy().into(), e))
+        })?;
+        history.path = path;
+        Ok(history)
+    }
+
+    /// Attempt to save the [SessionHistory] to the file it was loaded from
+    pub fn save(&self) -> Result<(), ConfigFilesError> {
+        let contents =
+            serde_json::to_string(self).context("Failed to serialize session history.")?;
+        persistence::write_atomic(&self.path, &contents)
+    }
+
+    /// Attempt to save the [SessionHistory], log errors and ignore result
+    pub fn save_ok(&self) {
+        if let Err(e) = self.save() {
+            tracing::error!("Failed to save session history: {:?}", e);
+        }
+    }
+
+    pub fn locate_history_file() -> Result<PathBuf, ConfigFilesError> {
+        Settings::locate_config_directory().map(|dir| dir.join("sessions.json"))
+    }
+
+    pub fn sessions(&self) -> &VecDeque<Session> {
+        &self.sessions
+    }
+
+    /// Note that the server is currently `hostname` and/or running `map`, starting a new session if
+    /// that isn't the server (or map) of the current one.
+    pub fn observe_server(&mut self, hostname: Option<Arc<str>>, map: Option<Arc<str>>) {
+        let now = now();
+        if let Some(session) = self.sessions.back_mut().filter(|_| self.active) {
+            if !session.conflicts(&hostname, &map)
+                && now.saturating_sub(session.end) < SESSION_TIMEOUT
+            {
+                session.end = now;
+                if session.fill_in(hostname, map) {
+                    self.save_ok();
+                }
+                return;
+            }
+        }
+
+        self.sessions.push_back(Session::new(hostname, map));
+        self.active = true;
+        while self.sessions.len() > MAX_SESSIONS {
+            self.sessions.pop_front();
+        }
+        self.save_ok();
+    }
+
+    /// Note the address of the server the current session is on, if it isn't already known
+    pub fn observe_address(&mut self, address: Arc<str>) {
+        let Some(session) = self.sessions.back_mut().filter(|_| self.active) else {
+            return;
+        };
+        if session.address.is_none() {
+            session.address = Some(address);
+            self.save_ok();
+        }
+    }
+
+    /// Note a recording of a demo made during the most recent session. Demos finish after the
+    /// user leaves, so this doesn't have to be the current session.
+    pub fn add_recording(&mut self, recording: Arc<str>) {
+        if let Some(session) = self.sessions.back_mut() {
+            session.recordings.push(recording);
+            self.save_ok();
+        }
+    }
+
+    /// Note the score of the match played during the most recent session, once its demo has
+    /// finished
+    pub fn add_result(&mut self, result: MatchResult) {
+        if let Some(session) = self.sessions.back_mut() {
+            session.result = Some(result);
+            self.save_ok();
+        }
+    }
+
+    /// Note a kick vote held during the most recent session, once it's over
+    pub fn add_vote(&mut self, vote: KickVote) {
+        if let Some(session) = self.sessions.back_mut() {
+            session.votes.push(vote);
+            self.save_ok();
+        }
+    }
+
+    /// Record players that have just joined the current session
+    pub fn record_encounters(&mut self, steamids: &[SteamID]) {
+        if steamids.is_empty() {
+            return;
+        }
+        let Some(session) = self.sessions.back_mut().filter(|_| self.active) else {
+            return;
+        };
+
+        let now = now();
+        session.end = now;
+        session.encounters.extend(
+            steamids
+                .iter()
+                .filter(|&&steamid| !session.encounters.iter().any(|e| e.steamid == steamid))
+                .map(|&steamid| Encounter { steamid, time: now })
+                .collect::<Vec<_>>(),
+        );
+        self.save_ok();
+    }
+}
@@ -1,4 +1,9 @@
-use clap::{ArgAction, Parser};
+use std::path::PathBuf;
+
+use clap::{ArgAction, Parser, Subcommand};
+
+#[cfg(any(test, feature = "fault-injection"))]
+use crate::faults::FaultConfig;
 
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
@@ -15,6 +20,9 @@ pub struct Args {
     /// Override the default tf2 directory
     #[arg(short = 'd', long)]
     pub tf2_dir: Option<String>,
+    /// Override the game profile to use, e.g. `tf2classic` or `tf2vintage` (default `tf2`)
+    #[arg(long)]
+    pub game_profile: Option<String>,
     /// Override the configured/default rcon password
     #[arg(short, long)]
     pub rcon_pword: Option<String>,
@@ -24,6 +32,10 @@ pub struct Args {
     /// Override the configured Steam API key,
     #[arg(short, long)]
     pub api_key: Option<String>,
+    /// Encrypt the Steam API key and other secrets in the config file using this passphrase.
+    /// Can also be provided through the MAC_SECRETS_PASSPHRASE environment variable.
+    #[arg(long)]
+    pub secrets_passphrase: Option<String>,
     /// Rewrite the user localconfig.vdf to append the corrected set of launch options if necessary (only works when steam is not running).
     #[arg(long = "rewrite_launch_opts", action=ArgAction::SetTrue, default_value_t=false)]
     pub rewrite_launch_options: bool,
@@ -36,4 +48,21 @@ pub struct Args {
     /// Enable monitoring of demo files
     #[arg(long = "demo_monitoring", action=ArgAction::SetTrue, default_value_t=false)]
     pub demo_monitoring: bool,
+    /// Analyse a complete demo file, e.g. one of a suspected cheater, print what was found in it as
+    /// JSON, then exit
+    #[arg(long = "analyse_demo")]
+    pub analyse_demo: Option<PathBuf>,
+    /// Developer mode: inject latency, timeouts and malformed responses into Steam API requests
+    /// and short reads into the console log and demos, e.g. `latency=500,timeout=0.1,truncate=0.2`
+    #[cfg(any(test, feature = "fault-injection"))]
+    #[arg(long = "inject_faults")]
+    pub inject_faults: Option<FaultConfig>,
+    #[command(subcommand)]
+    pub command: Option<CliCommand>,
+}
+
+#[derive(Subcommand, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CliCommand {
+    /// Check everything the client needs is set up and working, then exit
+    Doctor,
 }
@@ -0,0 +1,799 @@
+//! The whole client, started from one place so other Rust programs (e.g. a custom UI or a server
+//! plugin) can embed it directly rather than running it as a child process and talking to it over
+//! HTTP. The client's own binary starts it the same way, and other crates get the rest of the API
+//! for embedding it with the `embed` feature.
+//!
+//! A [Backend] is made with a [BackendBuilder] and runs on the tokio runtime it was started on
+//! until it is stopped. While it runs, `Backend::events` and `Backend::records` give typed access
+//! to what the web API would otherwise serve as JSON.
+
+use std::{
+    sync::{Arc, RwLock},
+    time::Duration,
+};
+
+use include_dir::Dir;
+use tokio::{
+    select,
+    sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender},
+    task::JoinHandle,
+};
+
+use crate::{
+    args::Args,
+    demo::{demo_loop, reprocess::Reprocessor, storage::demo_space_loop, DemoEvent},
+    events::{self, MacEvent, SystemEventLevel},
+    io::{
+        analyser::ConsoleAnalyserPlugin,
+        audit::{CommandAudit, CommandOrigin},
+        Command, IOManager, IOManagerMessage, IOOutput,
+    },
+    lists::{lists_loop, ImportedLists, ListUpdate},
+    locale::tr,
+    masterbase, pause,
+    player_records::{PlayerRecords, Verdict},
+    rules::{rules_loop, RuleSet, RulesUpdate},
+    server::Server,
+    settings::{self, Settings},
+    snapshot::SharedSnapshot,
+    steamapi::{
+        self, avatars::AvatarCache, LookupPriority, SteamAPIManager,
+        SteamAPIMessage, SteamAPIResponse,
+    },
+    steamid::SteamID,
+    storage,
+    telemetry::telemetry_loop,
+    web::{ratelimit::RateLimiter, web_main, SharedState},
+    webhooks::webhook_loop,
+};
+
+/// Shortest time between snapshots of the game state being published to the web API. Every
+/// snapshot serializes and diffs the whole [Server], so changes are gathered up rather than
+/// published after every line of console output.
+const SNAPSHOT_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Sets up a [Backend] before starting it
+pub struct BackendBuilder {
+    args: Args,
+    settings: Option<Settings>,
+    ui: Option<&'static Dir<'static>>,
+    web_api: bool,
+    console_analysers: Vec<Box<dyn ConsoleAnalyserPlugin>>,
+}
+
+impl BackendBuilder {
+    /// Start from the same options as the command line, e.g.
+    /// `Args::parse_from(["client_backend", "--demo_monitoring"])`
+    pub fn new(args: Args) -> BackendBuilder {
+        BackendBuilder {
+            args,
+            settings: None,
+            ui: None,
+            web_api: true,
+            console_analysers: Vec::new(),
+        }
+    }
+
+    /// Use settings that have already been loaded, rather than loading them from the config file
+    pub fn settings(mut self, settings: Settings) -> BackendBuilder {
+        self.settings = Some(settings);
+        self
+    }
+
+    /// Serve this copy of the web UI along with the web API
+    pub fn ui(mut self, ui: &'static Dir<'static>) -> BackendBuilder {
+        self.ui = Some(ui);
+        self
+    }
+
+    /// Whether to serve the web API at all. Programs embedding the backend don't need it to see
+    /// what is going on, but may still want it for the web UI.
+    #[cfg(feature = "embed")]
+    pub fn web_api(mut self, enabled: bool) -> BackendBuilder {
+        self.web_api = enabled;
+        self
+    }
+
+    /// Run another console analyser over every line of console output, along with the default
+    /// ones
+    #[cfg(feature = "embed")]
+    pub fn console_analyser(mut self, analyser: Box<dyn ConsoleAnalyserPlugin>) -> BackendBuilder {
+        self.console_analysers.push(analyser);
+        self
+    }
+
+    /// Load the records and start watching the game. Has to be called from within a tokio
+    /// runtime, which everything is run on.
+    pub fn start(self) -> Backend {
+        let args = self.args;
+        let settings = self
+            .settings
+            .unwrap_or_else(|| Settings::load_or_create(&args));
+        let game_profile = settings.get_game_profile();
+        let webui_port = settings.get_webui_port();
+        let storage = storage::open(settings.get_storage_backend(), &args);
+        let mut playerlist = PlayerRecords::load_or_create(storage.clone());
+        playerlist.set_chat_retention(settings.get_chat_retention());
+        playerlist.save_ok();
+        let mut tasks = Vec::new();
+
+        // IO Manager
+        let log_file_path = game_profile.log_file_path(settings.get_tf2_directory());
+        let (io_send, io_recv) = unbounded_channel();
+        let audit = CommandAudit::load_or_create();
+        let (io_recv, mut io_manager) = IOManager::new(
+            log_file_path,
+            settings.get_rcon_password(),
+            settings.get_rcon_port(),
+            settings.get_command_allowlist(),
+            audit.clone(),
+            &game_profile.line_formats,
+            io_recv,
+        );
+        io_manager.set_noise_filters(settings.get_noise_filters());
+        tasks.push(tokio::task::spawn(async move {
+            io_manager.io_loop().await;
+        }));
+
+        // Demo manager
+        let (demo_event_send, demo_event_recv) = unbounded_channel();
+        let reprocessor = Reprocessor::new(demo_event_send.clone());
+        if args.demo_monitoring {
+            let demo_path = game_profile.demo_path(settings.get_tf2_directory());
+            tracing::info!("Demo path: {:?}", demo_path);
+
+            std::thread::spawn(move || {
+                if let Err(e) = demo_loop(demo_path, demo_event_send) {
+                    tracing::error!("Failed to start demo watcher: {:?}", e);
+                }
+            });
+        }
+
+        // Imported lists
+        let (list_update_send, list_update_recv) = unbounded_channel();
+        match ImportedLists::locate_lists_directory() {
+            Ok(lists_dir) => {
+                tracing::info!("Lists path: {:?}", lists_dir);
+                std::thread::spawn(move || {
+                    if let Err(e) = lists_loop(lists_dir, list_update_send) {
+                        tracing::error!("Failed to start lists watcher: {:?}", e);
+                    }
+                });
+            }
+            Err(e) => tracing::error!("Could not find the lists directory: {:?}", e),
+        }
+
+        // Rules
+        let (rules_update_send, rules_update_recv) = unbounded_channel();
+        match RuleSet::locate_rules_directory() {
+            Ok(rules_dir) => {
+                tracing::info!("Rules path: {:?}", rules_dir);
+                std::thread::spawn(move || {
+                    if let Err(e) = rules_loop(rules_dir, rules_update_send) {
+                        tracing::error!("Failed to start rules watcher: {:?}", e);
+                    }
+                });
+            }
+            Err(e) => tracing::error!("Could not find the rules directory: {:?}", e),
+        }
+
+        // Steam API
+        let mut server = Server::new(playerlist);
+        server.players_mut().user = settings.get_steam_user();
+        server
+            .interlocks_mut()
+            .set_limits(settings.get_automation_limits());
+        server
+            .groups_mut()
+            .set_flagged(settings.get_flagged_groups());
+        server.set_party_commands(settings.get_party_commands());
+        for analyser in self.console_analysers {
+            server.register_console_analyser(analyser);
+        }
+        let (steam_api_send, steam_api_recv) = unbounded_channel();
+        let (steam_api_recv, mut steam_api) = SteamAPIManager::new(
+            settings.get_steam_api_key(),
+            storage,
+            settings.get_friends_cache_ttl(),
+            settings.get_steam_info_cache_ttl(),
+            settings.get_profile_scraping(),
+            settings.get_lookup_batching(),
+            steam_api_recv,
+        );
+
+        let settings = Arc::new(RwLock::new(settings));
+        let snapshot = SharedSnapshot::new(&server);
+        let server = Arc::new(RwLock::new(server));
+
+        let roster_server = server.clone();
+        steam_api.set_connection(&settings.read().unwrap().get_steam_connection());
+        steam_api.set_extra_api_keys(settings.read().unwrap().get_extra_steam_api_keys());
+        steam_api.set_private_recheck_budget(settings.read().unwrap().get_private_recheck_budget());
+        steam_api.set_roster(move || roster_server.read().unwrap().players().connected.clone());
+        tasks.push(tokio::task::spawn(async move {
+            steam_api.api_loop().await;
+        }));
+
+        // Free space for demos, and deleting old ones
+        if args.demo_monitoring {
+            let demo_path = game_profile.demo_path(settings.read().unwrap().get_tf2_directory());
+            let space_settings = settings.clone();
+            tasks.push(tokio::task::spawn(async move {
+                demo_space_loop(demo_path, space_settings).await;
+            }));
+        }
+
+        // Sending the user's marks to their webhooks
+        let webhook_settings = settings.clone();
+        tasks.push(tokio::task::spawn(async move {
+            webhook_loop(webhook_settings).await;
+        }));
+
+        // Anonymous statistics, only submitted if the user has opted in
+        let telemetry_server = server.clone();
+        let telemetry_settings = settings.clone();
+        tasks.push(tokio::task::spawn(async move {
+            telemetry_loop(telemetry_server, telemetry_settings).await;
+        }));
+
+        // Keeping the masterbase session from expiring
+        let masterbase_settings = settings.clone();
+        tasks.push(tokio::task::spawn(async move {
+            masterbase::auth_loop(masterbase_settings).await;
+        }));
+
+        // Web API
+        if self.web_api {
+            let shared_state = SharedState {
+                ui: self.ui,
+                io: io_send.clone(),
+                api: steam_api_send.clone(),
+                server: server.clone(),
+                snapshot: snapshot.clone(),
+                settings: settings.clone(),
+                reprocessor,
+                limiter: RateLimiter::default(),
+                audit,
+                avatars: AvatarCache::new(),
+            };
+            tasks.push(tokio::task::spawn(async move {
+                web_main(shared_state, webui_port).await;
+            }));
+        }
+
+        let main_loop = MainLoop {
+            server: server.clone(),
+            settings: settings.clone(),
+            snapshot,
+            io_send: io_send.clone(),
+            io_recv,
+            steam_api_send: steam_api_send.clone(),
+            steam_api_recv,
+            demo_event_recv,
+            list_update_recv,
+            rules_update_recv,
+        };
+        let main_loop = tokio::task::spawn(main_loop.run());
+
+        Backend {
+            server,
+            #[cfg(feature = "embed")]
+            settings,
+            #[cfg(feature = "embed")]
+            io_send,
+            #[cfg(feature = "embed")]
+            steam_api_send,
+            main_loop,
+            tasks,
+        }
+    }
+}
+
+/// A running client, see [BackendBuilder]
+pub struct Backend {
+    server: Arc<RwLock<Server>>,
+    #[cfg(feature = "embed")]
+    settings: Arc<RwLock<Settings>>,
+    #[cfg(feature = "embed")]
+    io_send: UnboundedSender<IOManagerMessage>,
+    #[cfg(feature = "embed")]
+    steam_api_send: UnboundedSender<SteamAPIMessage>,
+    main_loop: JoinHandle<()>,
+    /// Everything else running on the runtime, stopped along with the main loop
+    tasks: Vec<JoinHandle<()>>,
+}
+
+impl Backend {
+    pub fn builder(args: Args) -> BackendBuilder {
+        BackendBuilder::new(args)
+    }
+
+    /// Run until the process is interrupted (e.g. with Ctrl+C), then stop
+    pub async fn run_until_interrupted(mut self) {
+        select! {
+            result = &mut self.main_loop => {
+                if let Err(e) = result {
+                    tracing::error!("The main loop stopped: {:?}", e);
+                }
+            },
+            result = tokio::signal::ctrl_c() => {
+                if let Err(e) = result {
+                    tracing::error!("Failed to listen for interrupts: {:?}", e);
+                }
+                tracing::info!("Interrupted, stopping.");
+            },
+        }
+        self.stop().await;
+    }
+
+    /// Stop everything running on the runtime and save the records. The threads watching for
+    /// demos, lists and rules only stop the next time they find a change.
+    pub async fn stop(self) {
+        for task in &self.tasks {
+            task.abort();
+        }
+        // Already awaited if it stopped by itself
+        if !self.main_loop.is_finished() {
+            self.main_loop.abort();
+            let _ = self.main_loop.await;
+        }
+        for task in self.tasks {
+            let _ = task.await;
+        }
+        self.server.read().unwrap().players().records.flush_ok();
+        tracing::info!("Backend stopped.");
+    }
+}
+
+/// What other programs embedding the backend use to follow and control it
+#[cfg(feature = "embed")]
+impl Backend {
+    /// Every event that would be sent to subscribers of the web API's events stream, from now on
+    pub fn events(&self) -> tokio::sync::mpsc::Receiver<MacEvent> {
+        events::subscribe_events()
+    }
+
+    /// The user's records of the players they have come across
+    pub fn records(&self) -> RecordsHandle {
+        RecordsHandle {
+            server: self.server.clone(),
+        }
+    }
+
+    /// The game the user is playing and everyone in it
+    pub fn server(&self) -> &Arc<RwLock<Server>> {
+        &self.server
+    }
+
+    pub fn settings(&self) -> &Arc<RwLock<Settings>> {
+        &self.settings
+    }
+
+    /// Run a command in the game as if the user had asked for it through the web API
+    pub fn run_command(&self, command: Command) {
+        if self
+            .io_send
+            .send(IOManagerMessage::RunCommand(command, CommandOrigin::User))
+            .is_err()
+        {
+            tracing::error!("Failed to run command, the IO manager has stopped.");
+        }
+    }
+
+    /// Look a player up on Steam as soon as possible, even if they aren't on the server
+    pub fn lookup(&self, steamid: SteamID) {
+        if self
+            .steam_api_send
+            .send(SteamAPIMessage::Lookup(steamid, LookupPriority::High))
+            .is_err()
+        {
+            tracing::error!(
+                "Failed to look up {}, the Steam API manager has stopped.",
+                steamid
+            );
+        }
+    }
+
+    /// Wait until the backend stops, which it only does if something goes badly wrong
+    pub async fn wait(self) {
+        if let Err(e) = self.main_loop.await {
+            tracing::error!("The main loop stopped: {:?}", e);
+        }
+        self.server.read().unwrap().players().records.flush_ok();
+    }
+}
+
+/// Shared access to the player records of a running [Backend]
+#[cfg(feature = "embed")]
+#[derive(Clone)]
+pub struct RecordsHandle {
+    server: Arc<RwLock<Server>>,
+}
+
+#[cfg(feature = "embed")]
+impl RecordsHandle {
+    pub fn get(&self, steamid: SteamID) -> Option<crate::player_records::PlayerRecord> {
+        self.server
+            .read()
+            .unwrap()
+            .players()
+            .records
+            .get(&steamid)
+            .cloned()
+    }
+
+    /// Give a player a new verdict, as if the user had through the web API
+    pub fn set_verdict(&self, steamid: SteamID, verdict: Verdict) {
+        let mut server = self.server.write().unwrap();
+        server.players_mut().set_verdict(steamid, verdict);
+        server.players_mut().records.save_ok();
+    }
+
+    /// Read through every record at once, e.g. to search them
+    pub fn with<T>(&self, f: impl FnOnce(&PlayerRecords) -> T) -> T {
+        f(&self.server.read().unwrap().players().records)
+    }
+}
+
+/// One of the tasks the main loop sends work to has stopped, so there is no point carrying on
+struct TaskStopped(&'static str);
+
+const IO_MANAGER: &str = "IO manager";
+const STEAM_API_MANAGER: &str = "Steam API manager";
+
+/// Send `message` to the task on the other end of `channel`, named `task` in case it has stopped
+fn send<T>(
+    channel: &UnboundedSender<T>,
+    task: &'static str,
+    message: T,
+) -> Result<(), TaskStopped> {
+    channel.send(message).map_err(|_| TaskStopped(task))
+}
+
+/// Everything the main loop listens to and acts on
+struct MainLoop {
+    server: Arc<RwLock<Server>>,
+    settings: Arc<RwLock<Settings>>,
+    snapshot: SharedSnapshot,
+    io_send: UnboundedSender<IOManagerMessage>,
+    io_recv: UnboundedReceiver<Vec<IOOutput>>,
+    steam_api_send: UnboundedSender<SteamAPIMessage>,
+    steam_api_recv: UnboundedReceiver<SteamAPIResponse>,
+    demo_event_recv: UnboundedReceiver<DemoEvent>,
+    list_update_recv: UnboundedReceiver<ListUpdate>,
+    rules_update_recv: UnboundedReceiver<RulesUpdate>,
+}
+
+impl MainLoop {
+    /// Handle everything going on in the game, on the Steam API and in the config files, until
+    /// one of them stops
+    async fn run(self) {
+        if let Err(TaskStopped(task)) = self.run_until_stopped().await {
+            tracing::error!("The {} has stopped, stopping the main loop.", task);
+        }
+    }
+
+    async fn run_until_stopped(self) -> Result<(), TaskStopped> {
+        let MainLoop {
+            server,
+            settings,
+            snapshot,
+            io_send,
+            mut io_recv,
+            steam_api_send,
+            mut steam_api_recv,
+            mut demo_event_recv,
+            mut list_update_recv,
+            mut rules_update_recv,
+        } = self;
+
+        let mut refresh_interval = tokio::time::interval(Duration::from_secs(3));
+        refresh_interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        let mut refresh_iteration: u64 = 0;
+        let mut snapshot_interval = tokio::time::interval(SNAPSHOT_INTERVAL);
+        snapshot_interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        let mut ban_recheck_interval = tokio::time::interval(steamapi::BAN_RECHECK_INTERVAL);
+        ban_recheck_interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        let mut state_changed = false;
+
+        let mut new_players = Vec::new();
+        let mut queued_friendlist_req: Vec<SteamID> = Vec::new();
+        let mut inprogress_friendlist_req: Vec<SteamID> = Vec::new();
+        let mut need_all_friends_lists = false;
+
+        loop {
+            select! {
+                // IO output
+                Some(io_output_iter) = io_recv.recv() => {
+                    for output in io_output_iter {
+                        for new_player in server.write().unwrap()
+                            .handle_io_output(output)
+                            .into_iter()
+                        {
+                            new_players.push(new_player);
+                        }
+                    }
+                    // Lookups for a server the user has left would only use up the API quota
+                    let departed = server.write().unwrap().take_departed();
+                    if !departed.is_empty() {
+                        send(&steam_api_send, STEAM_API_MANAGER, SteamAPIMessage::CancelPending(Some(departed)))?;
+                    }
+                    // Anything asked for through in-game chat
+                    for (command, origin) in server.write().unwrap().take_commands() {
+                        send(&io_send, IO_MANAGER, IOManagerMessage::RunCommand(command, origin))?;
+                    }
+                },
+
+                // Steam API responses
+                Some(response) = steam_api_recv.recv() => {
+                    match response {
+                        SteamAPIResponse::SteamInfo((steamid, steam_info)) => {
+                            let mut server = server.write().unwrap();
+                            server.handle_bans(steamid, steam_info.vac_bans, steam_info.game_bans);
+                            server.players_mut().insert_steam_info(steamid, steam_info);
+                            server.apply_rules(steamid, None);
+                            server.check_alt_links(steamid);
+                            if server.wants_groups(steamid) {
+                                send(&steam_api_send, STEAM_API_MANAGER, SteamAPIMessage::CheckGroups(steamid))?;
+                            }
+                        },
+                        SteamAPIResponse::LookupFailed(steamid, error) => {
+                            server.write().unwrap().players_mut().lookup_failed(steamid, error);
+                        },
+                        SteamAPIResponse::FriendLists((steamid, result)) => {
+                            match result {
+                                // Player has public friend list
+                                Ok(friend_list) => {
+                                    let mut server = server.write().unwrap();
+                                    server.players_mut().update_friends_list(steamid, friend_list);
+                                    server.check_alt_links(steamid);
+                                },
+                                // Player has private friend list
+                                Err(_) => {
+                                    let mut server = server.write().unwrap();
+                                    server.players_mut().mark_friends_list_private(&steamid);
+                                    if let Some(record) = server.players_mut().records.get(&steamid) {
+                                        if  record.verdict == Verdict::Cheater ||
+                                            record.verdict == Verdict::Bot {
+                                            need_all_friends_lists = true;
+                                        }
+                                    }
+                                }
+                            };
+                            let i = inprogress_friendlist_req.iter().position(|id| *id == steamid);
+                            if let Some(i) = i {
+                                inprogress_friendlist_req.remove(i);
+                            }
+                        }
+                        SteamAPIResponse::APIKeyChecked(true) => {
+                            events::emit_system(SystemEventLevel::Info, tr("api-key-accepted", &[]));
+                        }
+                        SteamAPIResponse::APIKeyChecked(false) => {
+                            events::emit_system(SystemEventLevel::Warning, tr("api-key-rejected", &[]));
+                        }
+                        SteamAPIResponse::Bans(bans) => {
+                            let mut server = server.write().unwrap();
+                            for (steamid, vac_bans, game_bans) in bans {
+                                server.handle_bans(steamid, vac_bans, game_bans);
+                            }
+                        }
+                        SteamAPIResponse::Groups((steamid, Ok(groups))) => {
+                            server.write().unwrap().handle_groups(steamid, groups);
+                        }
+                        SteamAPIResponse::Groups((steamid, Err(e))) => {
+                            tracing::debug!("Could not get groups of {}: {:?}", steamid, e);
+                        }
+                        SteamAPIResponse::ResolvedVanity((vanity, result)) => {
+                            match result {
+                                Ok(steamid) => {
+                                    events::emit_system(SystemEventLevel::Info, tr("vanity-resolved", &[("vanity", &vanity), ("steamid", &steamid)]));
+                                    send(&steam_api_send, STEAM_API_MANAGER, SteamAPIMessage::Lookup(steamid, LookupPriority::High))?;
+                                },
+                                Err(e) => {
+                                    tracing::warn!("Could not resolve {}: {:?}", vanity, e);
+                                    events::emit_system(SystemEventLevel::Warning, tr("vanity-not-found", &[("vanity", &vanity)]));
+                                }
+                            }
+                        }
+                    }
+                }
+
+                // Demos starting and finishing
+                Some(event) = demo_event_recv.recv() => {
+                    match event {
+                        DemoEvent::Started { map, hostname, address } => {
+                            server.write().unwrap().handle_demo_started(map, hostname, address);
+                        }
+                        DemoEvent::Kills(kills) => {
+                            server.write().unwrap().handle_demo_kills(kills);
+                        }
+                        DemoEvent::KickVote(mut update) => {
+                            server.write().unwrap().handle_kick_vote(&mut update);
+                            events::emit(MacEvent::KickVote(update));
+                        }
+                        DemoEvent::PlayerConnect(connect) => {
+                            events::emit(MacEvent::PlayerConnect(connect));
+                        }
+                        DemoEvent::PlayerDisconnect(disconnect) => {
+                            events::emit(MacEvent::PlayerDisconnect(disconnect));
+                        }
+                        DemoEvent::Tick(tick) => {
+                            server.write().unwrap().set_demo_tick(Some(tick));
+                        }
+                        DemoEvent::Finished(report) => {
+                            server.write().unwrap().set_demo_tick(None);
+                            if let Some(recording) = &report.recording {
+                                server.write().unwrap().sessions_mut().add_recording(recording.clone());
+                            }
+                            if let Some(result) = &report.result {
+                                server.write().unwrap().sessions_mut().add_result(result.clone());
+                            }
+                            if !report.votes.is_empty() {
+                                server.write().unwrap().handle_match_votes(&report);
+                            }
+                            if !report.suspicion.is_empty() {
+                                let mut server = server.write().unwrap();
+                                let records = &mut server.players_mut().records;
+                                for steamid in report.suspicion.players().keys() {
+                                    if let Some(encounter) = report.encounter_suspicion(steamid) {
+                                        records.add_match_suspicion(*steamid, encounter);
+                                    }
+                                }
+                                records.save_ok();
+                            }
+                        }
+                        DemoEvent::Reprocessed { report, kinds } => {
+                            let mut server = server.write().unwrap();
+                            if !report.votes.is_empty() {
+                                server.handle_match_votes(&report);
+                            }
+                            let records = &mut server.players_mut().records;
+                            if records.replace_match_suspicion(&report, &kinds) > 0 {
+                                records.save_ok();
+                            }
+                        }
+                    }
+                }
+
+                // Changes to imported lists
+                Some(update) = list_update_recv.recv() => {
+                    server.write().unwrap().players_mut().lists.handle_update(update);
+                }
+
+                // Changes to rules
+                Some(update) = rules_update_recv.recv() => {
+                    server.write().unwrap().handle_rules_update(update);
+                }
+
+                // Refresh
+                _ = refresh_interval.tick() => {
+                    if refresh_iteration.is_multiple_of(2) {
+                        let mut server = server.write().unwrap();
+                        server.refresh_players();
+                        pause::observe(!server.players().connected.is_empty());
+                        server.players_mut().records.save_if_dirty();
+                        server.resolve_votes();
+                        server.suggest_actions();
+                        server.count_bots();
+                        drop(server);
+                        send(&io_send, IO_MANAGER, IOManagerMessage::RunCommand(Command::Status, CommandOrigin::Automation))?;
+                    } else {
+                        send(&io_send, IO_MANAGER, IOManagerMessage::RunCommand(Command::G15, CommandOrigin::Automation))?;
+                    }
+
+                    refresh_iteration += 1;
+                }
+
+                // Let the web API see the changes
+                _ = snapshot_interval.tick() => {
+                    if state_changed {
+                        snapshot.publish(&server.read().unwrap());
+                        state_changed = false;
+                    }
+                    continue;
+                }
+
+                // Find out which marked accounts Valve has since banned
+                _ = ban_recheck_interval.tick() => {
+                    let due = server
+                        .read()
+                        .unwrap()
+                        .players()
+                        .records
+                        .due_ban_checks(steamapi::BAN_RECHECK_AGE);
+                    if !due.is_empty() {
+                        send(&steam_api_send, STEAM_API_MANAGER, SteamAPIMessage::RecheckBans(due))?;
+                    }
+                    continue;
+                }
+            }
+            state_changed = true;
+
+            // Request steam API stuff on new players
+            for player in &new_players {
+                let verdict = server
+                    .read()
+                    .unwrap()
+                    .players()
+                    .records
+                    .get(player)
+                    .map(|r| r.verdict)
+                    .unwrap_or(Verdict::Player);
+                send(
+                    &steam_api_send,
+                    STEAM_API_MANAGER,
+                    SteamAPIMessage::Lookup(*player, LookupPriority::Normal),
+                )?;
+                let settings_read = settings.read().unwrap();
+                let user = settings_read.get_steam_user();
+                if user.is_some_and(|u| u == *player) {
+                    queued_friendlist_req.push(*player);
+                    continue;
+                }
+                match settings_read.get_friends_api_usage() {
+                    settings::FriendsAPIUsage::All => {
+                        queued_friendlist_req.push(*player);
+                    }
+                    settings::FriendsAPIUsage::CheatersOnly => {
+                        if !need_all_friends_lists
+                            && (verdict == Verdict::Cheater || verdict == Verdict::Bot)
+                        {
+                            queued_friendlist_req.push(*player);
+                        }
+                    }
+                    settings::FriendsAPIUsage::None => {}
+                }
+            }
+
+            // Request friend lists of relevant players (depends on config), once background
+            // work isn't paused
+            if !pause::paused() && (need_all_friends_lists || !queued_friendlist_req.is_empty()) {
+                // If a cheater's friends list is private, we need everyone's friends list.
+                if need_all_friends_lists {
+                    need_all_friends_lists = false;
+                    let server_read: std::sync::RwLockReadGuard<'_, Server> =
+                        server.read().unwrap();
+                    queued_friendlist_req = server_read
+                        .players()
+                        .connected
+                        .iter()
+                        .filter_map(|steamid| {
+                            if inprogress_friendlist_req.contains(steamid) {
+                                return None;
+                            }
+                            // If friends list visibility is Some, we've looked up that user before.
+                            match server_read
+                                .players()
+                                .friend_info
+                                .get(steamid)
+                                .map(|fi| fi.public)
+                            {
+                                Some(Some(true)) => None,
+                                Some(Some(false)) => {
+                                    let record = server_read.players().records.get(steamid);
+                                    if record.is_some_and(|r| {
+                                        r.verdict == Verdict::Cheater || r.verdict == Verdict::Bot
+                                    }) {
+                                        need_all_friends_lists = true;
+                                    }
+                                    None
+                                }
+                                _ => Some(*steamid),
+                            }
+                        })
+                        .collect();
+                }
+
+                send(
+                    &steam_api_send,
+                    STEAM_API_MANAGER,
+                    SteamAPIMessage::CheckFriends(queued_friendlist_req.clone()),
+                )?;
+                inprogress_friendlist_req.append(&mut queued_friendlist_req);
+            }
+
+            new_players.clear();
+            queued_friendlist_req.clear();
+        }
+    }
+}
@@ -0,0 +1,135 @@
+use std::sync::Arc;
+
+use crate::{io::KickReason, locale::tr, player_records::Verdict};
+
+/// Messages from the user starting with this are treated as commands to the client
+pub const PREFIX: &str = "!mac";
+/// Party chat messages starting with this ask what the user knows about a player
+pub const PARTY_WHO: &str = "!who";
+/// Least time (seconds) between replies to party members, so the client can't be made to flood
+/// the party chat
+pub const PARTY_REPLY_COOLDOWN: u64 = 10;
+/// Longest reply sent to the party chat, so it fits on one line
+const MAX_PARTY_REPLY: usize = 120;
+
+/// Who a chat command is about
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Target {
+    /// The player that joined most recently
+    Last,
+    /// The player that most recently killed the user
+    Killer,
+    /// The player whose name contains this, ignoring case
+    Name(Arc<str>),
+}
+
+/// Something the user asked for by typing it into their own in-game chat, e.g.
+/// `!mac mark last bot` or `!mac kick killer`
+#[derive(Debug, Clone)]
+pub enum ChatCommand {
+    Mark { target: Target, verdict: Verdict },
+    Kick { target: Target, reason: KickReason },
+}
+
+/// Something a member of the user's party asked for by typing it into party chat, e.g.
+/// `!who <name>`, so the rest of a premade can use the client without running it themselves
+#[derive(Debug, Clone)]
+pub enum PartyCommand {
+    /// A one line summary of what the user knows about a player on the server
+    Who(Target),
+}
+
+fn parse_target(words: &[&str]) -> Option<Target> {
+    match words {
+        [] => None,
+        [word] if word.eq_ignore_ascii_case("last") => Some(Target::Last),
+        [word] if word.eq_ignore_ascii_case("killer") => Some(Target::Killer),
+        _ => Some(Target::Name(words.join(" ").into())),
+    }
+}
+
+fn parse_verdict(word: &str) -> Option<Verdict> {
+    match word.to_lowercase().as_str() {
+        "player" | "none" => Some(Verdict::Player),
+        "bot" => Some(Verdict::Bot),
+        "suspicious" => Some(Verdict::Suspicious),
+        "cheater" => Some(Verdict::Cheater),
+        "trusted" => Some(Verdict::Trusted),
+        _ => None,
+    }
+}
+
+fn parse_reason(word: &str) -> Option<KickReason> {
+    match word.to_lowercase().as_str() {
+        "cheating" => Some(KickReason::Cheating),
+        "idle" => Some(KickReason::Idle),
+        "scamming" => Some(KickReason::Scamming),
+        _ => None,
+    }
+}
+
+/// Parse a chat message as a command. Returns `None` if it isn't one, or a message explaining
+/// what was wrong with it if it couldn't be understood.
+pub fn parse(message: &str) -> Option<Result<ChatCommand, String>> {
+    let mut words = message.split_whitespace();
+    if !words.next()?.eq_ignore_ascii_case(PREFIX) {
+        return None;
+    }
+    let Some(command) = words.next() else {
+        return Some(Err(tr("chat-command-usage", &[])));
+    };
+    let args: Vec<&str> = words.collect();
+
+    let parsed = match command.to_lowercase().as_str() {
+        "mark" => args.split_last().and_then(|(verdict, target)| {
+            Some(ChatCommand::Mark {
+                target: parse_target(target)?,
+                verdict: parse_verdict(verdict)?,
+            })
+        }),
+        "kick" => {
+            let (reason, target) = match args.split_last() {
+                Some((last, rest)) if !rest.is_empty() => match parse_reason(last) {
+                    Some(reason) => (reason, rest),
+                    None => (KickReason::Cheating, &args[..]),
+                },
+                _ => (KickReason::Cheating, &args[..]),
+            };
+            parse_target(target).map(|target| ChatCommand::Kick { target, reason })
+        }
+        _ => None,
+    };
+
+    Some(parsed.ok_or_else(|| tr("chat-command-usage", &[])))
+}
+
+/// Parse a party chat message as a command. Returns `None` if it isn't one, or a message explaining
+/// what was wrong with it if it couldn't be understood.
+pub fn parse_party(message: &str) -> Option<Result<PartyCommand, String>> {
+    let mut words = message.split_whitespace();
+    if !words.next()?.eq_ignore_ascii_case(PARTY_WHO) {
+        return None;
+    }
+    let args: Vec<&str> = words.collect();
+
+    Some(
+        parse_target(&args)
+            .map(PartyCommand::Who)
+            .ok_or_else(|| tr("party-command-usage", &[])),
+    )
+}
+
+/// Make a reply safe to send to the party chat: a single line, short enough to be shown whole,
+/// and without quotes that would end the command it is sent in
+pub fn party_reply(reply: &str) -> Arc<str> {
+    reply
+        .chars()
+        .map(|c| match c {
+            '"' => '\'',
+            '\n' | '\r' => ' ',
+            c => c,
+        })
+        .take(MAX_PARTY_REPLY)
+        .collect::<String>()
+        .into()
+}
@@ -0,0 +1,162 @@
+//! Noticing when players join or leave the server much faster than usual, which is what a wave of
+//! bots joining at once or a server emptying out (e.g. everyone ragequitting a cheater) looks like.
+//! Each alert comes with the players involved, so the user can see straight away who arrived.
+
+use std::{collections::VecDeque, sync::Arc};
+
+use serde::Serialize;
+
+use crate::{player_records::Verdict, steamid::SteamID};
+
+/// Seconds joins and leaves are counted over when looking for a spike
+pub const WINDOW: u64 = 60;
+/// Seconds of joins and leaves before the window that make up what is usual for the server
+const BASELINE: u64 = 15 * 60;
+/// Fewest joins or leaves in the window that are ever alerted about, as a handful of players
+/// coming and going together (e.g. a party) is normal
+const MIN_SPIKE: usize = 5;
+/// How many times the usual rate a spike has to be
+const SPIKE_FACTOR: f32 = 3.0;
+/// Seconds after connecting or changing map that nothing is counted, as everyone shows up (or
+/// drops out and comes back) at once while it loads
+const SETTLE: u64 = 2 * 60;
+/// Seconds before another spike of the same kind is alerted about
+const COOLDOWN: u64 = 3 * 60;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ChurnKind {
+    Joins,
+    Leaves,
+}
+
+/// Players joining or leaving much faster than usual, found by [ChurnMonitor::check]
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChurnSpike {
+    pub kind: ChurnKind,
+    /// Who joined or left in the window, in order
+    pub players: Vec<SteamID>,
+    /// How many joins or leaves would usually be seen in the window
+    pub expected: f32,
+}
+
+/// A spike as it is sent to the UI, with enough about each player to see who they are
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChurnAlert {
+    pub kind: ChurnKind,
+    /// Seconds the players joined or left within
+    pub window: u64,
+    pub expected: f32,
+    pub players: Vec<ChurnPlayer>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChurnPlayer {
+    pub steamid: SteamID,
+    pub name: Arc<str>,
+    pub verdict: Verdict,
+}
+
+/// Joins or leaves of one kind, with when each happened
+#[derive(Debug, Default)]
+struct Changes {
+    /// Unix timestamp (seconds) and player, oldest first
+    seen: VecDeque<(u64, SteamID)>,
+    last_alert: Option<u64>,
+}
+
+impl Changes {
+    fn record(&mut self, now: u64, steamids: &[SteamID]) {
+        self.seen
+            .extend(steamids.iter().map(|&steamid| (now, steamid)));
+    }
+
+    fn check(&mut self, kind: ChurnKind, now: u64, counting_since: u64) -> Option<ChurnSpike> {
+        let oldest = now.saturating_sub(WINDOW + BASELINE);
+        while self.seen.front().is_some_and(|&(time, _)| time < oldest) {
+            self.seen.pop_front();
+        }
+        if self.last_alert.is_some_and(|last| now < last + COOLDOWN) {
+            return None;
+        }
+
+        let window_start = now.saturating_sub(WINDOW);
+        let players: Vec<SteamID> = self
+            .seen
+            .iter()
+            .filter(|&&(time, _)| time >= window_start)
+            .map(|&(_, steamid)| steamid)
+            .collect();
+        if players.len() < MIN_SPIKE {
+            return None;
+        }
+
+        // Only as much of the baseline as has been watched counts, so a short stay isn't
+        // compared against a rate of nothing
+        let watched = window_start.saturating_sub(counting_since.max(oldest));
+        let before = self.seen.len() - players.len();
+        let expected = if watched == 0 {
+            0.0
+        } else {
+            before as f32 * WINDOW as f32 / watched as f32
+        };
+        if (players.len() as f32) < SPIKE_FACTOR * expected {
+            return None;
+        }
+
+        self.last_alert = Some(now);
+        Some(ChurnSpike {
+            kind,
+            players,
+            expected,
+        })
+    }
+}
+
+/// Keeps track of players joining and leaving the server, see the [module docs](self)
+#[derive(Debug, Default)]
+pub struct ChurnMonitor {
+    joins: Changes,
+    leaves: Changes,
+    /// Unix timestamp (seconds) nothing is counted before
+    counting_since: u64,
+}
+
+impl ChurnMonitor {
+    /// Forget everything seen so far and wait for the server to settle, e.g. after connecting to
+    /// a different server or the map changing
+    pub fn settle(&mut self, now: u64) {
+        *self = ChurnMonitor {
+            counting_since: now + SETTLE,
+            ..Default::default()
+        };
+    }
+
+    /// Note players that have just joined the server
+    pub fn record_joins(&mut self, now: u64, steamids: &[SteamID]) {
+        if now >= self.counting_since {
+            self.joins.record(now, steamids);
+        }
+    }
+
+    /// Note players that have just left the server
+    pub fn record_leaves(&mut self, now: u64, steamids: &[SteamID]) {
+        if now >= self.counting_since {
+            self.leaves.record(now, steamids);
+        }
+    }
+
+    /// Any spikes in joins or leaves that haven't been alerted about yet
+    pub fn check(&mut self, now: u64) -> Vec<ChurnSpike> {
+        let counting_since = self.counting_since;
+        [
+            self.joins.check(ChurnKind::Joins, now, counting_since),
+            self.leaves.check(ChurnKind::Leaves, now, counting_since),
+        ]
+        .into_iter()
+        .flatten()
+        .collect()
+    }
+}
@@ -1,78 +1,517 @@
 use bitbuffer::{BitError, BitRead, BitReadBuffer, BitReadStream, LittleEndian};
 use notify::event::ModifyKind;
 use notify::{Config, Event, RecommendedWatcher, RecursiveMode, Watcher};
+use std::collections::HashSet;
 use std::fs::{metadata, File};
 use std::io::{Read, Seek};
+use std::panic::{self, AssertUnwindSafe};
 use std::path::{Path, PathBuf};
 use std::sync::mpsc;
-use std::time::Duration;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 use tf_demo_parser::demo::gamevent::GameEvent;
 use tf_demo_parser::demo::header::Header;
 use tf_demo_parser::demo::message::gameevent::GameEventMessage;
 use tf_demo_parser::demo::message::Message;
 use tf_demo_parser::demo::packet::message::MessagePacket;
 use tf_demo_parser::demo::packet::Packet;
-use tf_demo_parser::demo::parser::gamestateanalyser::GameStateAnalyser;
+use tf_demo_parser::demo::parser::gamestateanalyser::{GameState, GameStateAnalyser};
 use tf_demo_parser::demo::parser::{DemoHandler, RawPacketStream};
+use thiserror::Error;
+use tokio::sync::mpsc::UnboundedSender;
+
+use self::analyser::DemoAnalyserPlugin;
+use self::clock::{TickClock, DEFAULT_TICK_RATE};
+use self::live::{PlayerConnect, PlayerDisconnect};
+use self::offline::{DemoAnalysis, DemoLog};
+use self::recording::Recorder;
+use self::report::MatchReport;
+use self::rounds::RoundTracker;
+use self::suspicion::{MatchSuspicion, SuspicionKind};
+use self::votes::{KickVoteUpdate, VoteTracker};
+use crate::analytics::history::now;
+use crate::events::{self, SystemEventLevel};
+#[cfg(any(test, feature = "fault-injection"))]
+use crate::faults;
+use crate::killfeed::KillSource;
+use crate::locale::tr;
+use crate::player::KillLine;
+use crate::steamid::SteamID;
+use crate::throughput::{self, Pipeline};
+
+pub mod analyser;
+pub mod classes;
+pub mod clock;
+pub mod live;
+pub mod offline;
+pub mod recording;
+pub mod report;
+pub mod reprocess;
+pub mod rounds;
+pub mod storage;
+pub mod suspicion;
+pub mod voice;
+pub mod votes;
+
+/// Longest a single chunk of a demo may take to parse before the demo is abandoned
+const CHUNK_TIME_LIMIT: Duration = Duration::from_secs(10);
+/// Longest a whole demo may take to parse when it is analysed offline
+const OFFLINE_TIME_LIMIT: Duration = Duration::from_secs(300);
+/// How long past its time limit the parser has to hand a demo back before it is taken to be stuck
+/// part way through a packet
+const STUCK_GRACE: Duration = Duration::from_secs(5);
+/// Most bytes of a demo read at once, so a demo that has grown a lot since it was last read (e.g.
+/// one already being recorded when the client starts) is caught up on a chunk at a time
+const CHUNK_SIZE: u64 = 8 * 1024 * 1024;
+/// Demos larger than this (in bytes) stop being analysed rather than being held in memory
+const MAX_DEMO_SIZE: u64 = 512 * 1024 * 1024;
+
+/// Things learned from demos that the rest of the client needs to know about
+#[derive(Debug)]
+pub enum DemoEvent {
+    /// A demo has started being recorded, with the server details from its header and sign-on
+    /// data. These identify the server even if the console lines giving them were missed.
+    Started {
+        map: Arc<str>,
+        /// The server's name, as sent to the client when it connected
+        hostname: Option<Arc<str>>,
+        /// The address the client connected to, from the demo header
+        address: Option<Arc<str>>,
+    },
+    /// Kills read from the demo being recorded
+    Kills(Vec<KillLine>),
+    /// A kick vote in the demo being recorded was called, voted in or finished
+    KickVote(KickVoteUpdate),
+    /// A player joined the server in the demo being recorded
+    PlayerConnect(PlayerConnect),
+    /// A player left the server in the demo being recorded
+    PlayerDisconnect(PlayerDisconnect),
+    /// The tick the demo being recorded has been read up to
+    Tick(u32),
+    /// A demo has finished being recorded
+    Finished(MatchReport),
+    /// The recording of an old demo has been run through the analysers again, which could raise
+    /// flags of `kinds`
+    Reprocessed {
+        report: MatchReport,
+        kinds: HashSet<SuspicionKind>,
+    },
+}
 
 pub struct DemoManager {
     previous_demos: Vec<OpenDemo>,
+    /// Demos that stopped being analysed because they couldn't be parsed safely
+    abandoned: HashSet<PathBuf>,
     current_demo: Option<OpenDemo>,
+    event_send: UnboundedSender<DemoEvent>,
+    /// Parses the current demo, started along with the first demo
+    worker: Option<ParserWorker>,
+    /// Workers that got stuck on a demo, kept in case they hand it back so it can be reported
+    stuck_workers: Vec<ParserWorker>,
 }
 
 pub struct OpenDemo {
     pub file_path: PathBuf,
     pub header: Option<Header>,
+    /// The server's name from the sign-on data, once it has been read
+    pub server_name: Option<Arc<str>>,
     pub handler: DemoHandler<GameStateAnalyser>,
     pub bytes: Vec<u8>,
     pub offset: usize,
+    pub suspicion: MatchSuspicion,
+    /// Kills read since they were last sent to the main thread
+    kills: Vec<(u32, KillLine)>,
+    /// Players coming and going read since they were last sent to the main thread
+    events: Vec<DemoEvent>,
+    votes: VoteTracker,
+    rounds: RoundTracker,
+    /// When ticks were recorded, going by when the file was last written to
+    clock: Option<TickClock>,
+    /// Ticks in a second on the server the demo is being recorded on, once the demo says
+    tick_rate: f32,
+    analysers: Vec<Box<dyn DemoAnalyserPlugin>>,
+    recorder: Recorder,
+    /// Everything seen so far, only kept when the demo is being analysed offline
+    log: Option<DemoLog>,
 }
 
 impl DemoManager {
-    /// Create a new DemoManager
-    pub fn new() -> DemoManager {
+    /// Create a new DemoManager. The map and server of each demo are sent through `event_send` once
+    /// its header has been read, followed by its match report once the demo has finished.
+    pub fn new(event_send: UnboundedSender<DemoEvent>) -> DemoManager {
         DemoManager {
             previous_demos: Vec::new(),
+            abandoned: HashSet::new(),
             current_demo: None,
+            event_send,
+            worker: None,
+            stuck_workers: Vec::new(),
         }
     }
 
     /// Start tracking a new demo file. A demo must be being tracked before bytes can be appended.
     pub fn new_demo(&mut self, path: PathBuf) {
-        if let Some(old) = self.current_demo.take() {
+        if let Some(mut old) = self.current_demo.take() {
+            self.finish_demo(&mut old);
             self.previous_demos.push(old);
         }
+        self.abandoned.remove(&path);
 
         // TODO - Change to debug when demo monitoring defaults to on
         tracing::info!("Watching new demo: {:?}", path);
 
-        self.current_demo = Some(OpenDemo {
-            file_path: path,
-            header: None,
-            handler: DemoHandler::with_analyser(GameStateAnalyser::new()),
-            bytes: Vec::new(),
-            offset: 0,
-        });
+        let demo_name = path
+            .file_stem()
+            .map(|name| name.to_string_lossy())
+            .unwrap_or_else(|| "unknown".into());
+        let recorder = Recorder::create(&demo_name);
+        let analysers = analyser::default_analysers()
+            .iter()
+            .map(|create| create())
+            .collect();
+
+        self.current_demo = Some(OpenDemo::new(path, analysers, recorder));
+    }
+
+    /// Parse the complete demo at `path` from start to finish with the default analysers, e.g. a
+    /// demo of a suspected cheater from a past match. Nothing is recorded or sent to the rest of
+    /// the client.
+    pub fn analyse_file(path: &Path) -> anyhow::Result<DemoAnalysis> {
+        if metadata(path)?.len() > MAX_DEMO_SIZE {
+            anyhow::bail!("Demo is larger than {} bytes", MAX_DEMO_SIZE);
+        }
+
+        let analysers = analyser::default_analysers()
+            .iter()
+            .map(|create| create())
+            .collect();
+        let mut demo = OpenDemo::new(path.to_path_buf(), analysers, Recorder::disabled());
+        demo.log = Some(DemoLog::default());
+
+        let worker = ParserWorker::spawn()?;
+        let deadline = Instant::now() + OFFLINE_TIME_LIMIT;
+        loop {
+            let Some((parsed, result)) = worker.parse(demo, deadline) else {
+                return Err(SandboxError::Stuck.into());
+            };
+            demo = parsed;
+            if !result? {
+                break;
+            }
+        }
+        if demo.header.is_none() {
+            anyhow::bail!("Not a demo, or too short to have a header");
+        }
+
+        demo.finalize_analysers();
+        let report = demo.match_report();
+        let log = demo.log.take().unwrap_or_default();
+        Ok(log.finish(report, demo.clock))
+    }
+
+    /// Produce, save and send the match report for a demo that is no longer being recorded, then
+    /// free what was only needed to parse it.
+    fn finish_demo(&self, demo: &mut OpenDemo) {
+        demo.finalize_analysers();
+        let mut report = demo.match_report();
+        report.recording = demo.recorder.finish();
+        report.save_ok();
+        if self.event_send.send(DemoEvent::Finished(report)).is_err() {
+            tracing::warn!("Lost connection to main thread, match report was not recorded.");
+        }
+
+        demo.bytes = Vec::new();
+        demo.handler = DemoHandler::with_analyser(GameStateAnalyser::new());
     }
 
     pub fn current_demo_path(&self) -> Option<&Path> {
         self.current_demo.as_ref().map(|d| d.file_path.as_path())
     }
 
+    /// Whether the demo at `path` has stopped being analysed and shouldn't be read again
+    pub fn is_abandoned(&self, path: &Path) -> bool {
+        self.abandoned.contains(path)
+    }
+
+    /// Read and parse whatever has been added to the current demo since it was last read, a chunk
+    /// at a time
     pub fn read_next_bytes(&mut self) {
-        if let Some(demo) = self.current_demo.as_mut() {
-            if let Err(e) = demo.read_next_bytes() {
-                tracing::error!("Error when reading demo {:?}: {:?}", demo.file_path, e);
-                tracing::error!("Demo is being abandoned");
-                self.current_demo = None;
+        if self.worker.is_none() {
+            match ParserWorker::spawn() {
+                Ok(worker) => self.worker = Some(worker),
+                Err(e) => {
+                    tracing::error!("Could not start the demo parser: {}", e);
+                    return;
+                }
+            }
+        }
+
+        // A demo a parser got stuck on has finally been handed back
+        let mut late = Vec::new();
+        self.stuck_workers.retain_mut(|worker| match worker.late() {
+            Some(demo) => {
+                late.push(demo);
+                false
             }
+            None => true,
+        });
+        for mut demo in late {
+            self.finish_demo(&mut demo);
+        }
+
+        let Some(mut demo) = self.current_demo.take() else {
+            return;
+        };
+
+        // What has been parsed so far is still fine to report
+        if metadata(&demo.file_path).is_ok_and(|m| m.len() > MAX_DEMO_SIZE) {
+            self.abandon(&demo.file_path.clone(), "demo is too large");
+            self.finish_demo(&mut demo);
+            return;
+        }
+
+        loop {
+            let path = demo.file_path.clone();
+            let had_header = demo.header.is_some();
+            let deadline = Instant::now() + CHUNK_TIME_LIMIT;
+            let parsed = self.worker.as_ref().and_then(|w| w.parse(demo, deadline));
+            let Some((parsed, result)) = parsed else {
+                // Finished once the parser hands it back, if it ever does. Later demos are parsed
+                // by a new worker.
+                self.abandon(&path, &SandboxError::Stuck.to_string());
+                self.stuck_workers.extend(self.worker.take());
+                return;
+            };
+            demo = parsed;
+            self.send_updates(&mut demo, had_header);
+
+            match result {
+                Ok(true) => {}
+                Ok(false) => {
+                    self.current_demo = Some(demo);
+                    return;
+                }
+                Err(SandboxError::Read(e)) => {
+                    tracing::error!("Error when reading demo {:?}: {:?}", path, e);
+                    tracing::error!("Demo is being abandoned");
+                    self.finish_demo(&mut demo);
+                    return;
+                }
+                Err(e) => {
+                    self.abandon(&path, &e.to_string());
+                    self.finish_demo(&mut demo);
+                    return;
+                }
+            }
+        }
+    }
+
+    /// Send the main thread everything learned from the last chunk of `demo`
+    fn send_updates(&self, demo: &mut OpenDemo, had_header: bool) {
+        if let Some(header) = demo.header.as_ref().filter(|_| !had_header) {
+            let started = DemoEvent::Started {
+                map: header.map.as_str().into(),
+                hostname: demo.server_name.clone(),
+                address: Some(header.server.as_str())
+                    .filter(|s| !s.is_empty())
+                    .map(|s| s.into()),
+            };
+            if self.event_send.send(started).is_err() {
+                tracing::warn!("Lost connection to main thread, demo server was not recorded.");
+            }
+        }
+
+        // Sent first, so how long ago everything else happened in the demo is known
+        if demo.header.is_some() {
+            let tick = u32::from(demo.handler.borrow_output().tick);
+            if self.event_send.send(DemoEvent::Tick(tick)).is_err() {
+                tracing::warn!("Lost connection to main thread, demo tick was not recorded.");
+            }
+        }
+
+        let votes = demo.votes.take_updates().into_iter().map(|mut update| {
+            update.vote.time = demo.clock.map(|clock| clock.iso8601(update.vote.tick));
+            DemoEvent::KickVote(update)
+        });
+        for event in std::mem::take(&mut demo.events).into_iter().chain(votes) {
+            if self.event_send.send(event).is_err() {
+                tracing::warn!("Lost connection to main thread, demo event was not recorded.");
+                break;
+            }
+        }
+
+        if !demo.kills.is_empty() {
+            // Kills are timed by when they happened in the demo, which is only known once the
+            // demo has been placed in time
+            let clock = demo.clock;
+            let kills = std::mem::take(&mut demo.kills)
+                .into_iter()
+                .map(|(tick, mut kill)| {
+                    kill.time = clock.map_or_else(now, |clock| clock.time_ms(tick) / 1000);
+                    kill
+                })
+                .collect();
+            if self.event_send.send(DemoEvent::Kills(kills)).is_err() {
+                tracing::warn!("Lost connection to main thread, demo kills were not recorded.");
+            }
+        }
+    }
+
+    /// Stop analysing a demo that couldn't be parsed safely. The file is left alone, as the game
+    /// may still be writing to it.
+    fn abandon(&mut self, path: &Path, reason: &str) {
+        tracing::error!("Abandoning demo {:?}: {}", path, reason);
+        self.abandoned.insert(path.to_path_buf());
+
+        let demo = path
+            .file_name()
+            .map(|name| name.to_string_lossy())
+            .unwrap_or_default();
+        events::emit_system(
+            SystemEventLevel::Warning,
+            tr("demo-abandoned", &[("demo", &demo), ("reason", &reason)]),
+        );
+    }
+}
+
+/// Why parsing a chunk of a demo was given up on
+#[derive(Debug, Error)]
+enum SandboxError {
+    #[error("parsing took too long")]
+    TimedOut,
+    #[error("parser got stuck")]
+    Stuck,
+    #[error("parser panicked: {0}")]
+    Panicked(String),
+    #[error("could not read the demo: {0}")]
+    Read(#[from] std::io::Error),
+}
+
+/// A demo handed back by the [ParserWorker], along with whether there is more of it to read
+type Parsed = (OpenDemo, Result<bool, SandboxError>);
+
+/// Parses demos on a thread of its own, so a corrupt or malicious demo that makes the parser
+/// panic or hang can't take the demo loop down with it. The thread lives as long as the worker.
+struct ParserWorker {
+    jobs: mpsc::Sender<(OpenDemo, Instant)>,
+    parsed: mpsc::Receiver<Parsed>,
+}
+
+impl ParserWorker {
+    fn spawn() -> std::io::Result<ParserWorker> {
+        let (jobs, job_recv) = mpsc::channel::<(OpenDemo, Instant)>();
+        let (parsed_send, parsed) = mpsc::channel();
+        std::thread::Builder::new()
+            .name("demo-parser".into())
+            .spawn(move || {
+                for (mut demo, deadline) in job_recv {
+                    let result =
+                        panic::catch_unwind(AssertUnwindSafe(|| demo.read_next_bytes(deadline)));
+                    let result = match result {
+                        Ok(Ok(Some(more))) => Ok(more),
+                        Ok(Ok(None)) => Err(SandboxError::TimedOut),
+                        Ok(Err(e)) => Err(SandboxError::Read(e)),
+                        Err(panic) => Err(SandboxError::Panicked(
+                            panic
+                                .downcast_ref::<&str>()
+                                .map(|s| s.to_string())
+                                .or_else(|| panic.downcast_ref::<String>().cloned())
+                                .unwrap_or_else(|| "unknown cause".into()),
+                        )),
+                    };
+                    if parsed_send.send((demo, result)).is_err() {
+                        break;
+                    }
+                }
+            })?;
+        Ok(ParserWorker { jobs, parsed })
+    }
+
+    /// Read and parse the next chunk of `demo`, stopping at the first packet after `deadline`.
+    /// The demo is handed back whatever happens, unless the parser gets stuck inside a packet, in
+    /// which case it comes back from [ParserWorker::late] if it ever does and the worker can't be
+    /// used again.
+    fn parse(&self, demo: OpenDemo, deadline: Instant) -> Option<Parsed> {
+        if self.jobs.send((demo, deadline)).is_err() {
+            return None;
         }
+        let wait = deadline.saturating_duration_since(Instant::now()) + STUCK_GRACE;
+        self.parsed.recv_timeout(wait).ok()
+    }
+
+    /// The demo the parser got stuck on, once it has been handed back
+    fn late(&self) -> Option<OpenDemo> {
+        let (demo, _) = self.parsed.try_recv().ok()?;
+        Some(demo)
     }
 }
 
 impl OpenDemo {
-    /// Append the provided bytes to the current demo being watched, and handle any packets
-    pub fn read_next_bytes(&mut self) -> std::io::Result<()> {
+    fn new(
+        file_path: PathBuf,
+        analysers: Vec<Box<dyn DemoAnalyserPlugin>>,
+        recorder: Recorder,
+    ) -> OpenDemo {
+        OpenDemo {
+            file_path,
+            header: None,
+            server_name: None,
+            handler: DemoHandler::with_analyser(GameStateAnalyser::new()),
+            bytes: Vec::new(),
+            offset: 0,
+            suspicion: MatchSuspicion::new(),
+            kills: Vec::new(),
+            events: Vec::new(),
+            votes: VoteTracker::default(),
+            rounds: RoundTracker::default(),
+            clock: None,
+            tick_rate: DEFAULT_TICK_RATE,
+            analysers,
+            recorder,
+            log: None,
+        }
+    }
+
+    /// Collect the flags raised by every analyser now that the demo has finished
+    fn finalize_analysers(&mut self) {
+        for analyser in &mut self.analysers {
+            let flags = analyser.finalize();
+            tracing::debug!("{} raised {} flags", analyser.name(), flags.len());
+            for flag in flags {
+                self.suspicion.add_flag(flag);
+            }
+        }
+    }
+
+    /// Summarise everything that has been parsed from this demo so far
+    pub fn match_report(&self) -> MatchReport {
+        let demo_name: Arc<str> = self
+            .file_path
+            .file_name()
+            .map(|name| name.to_string_lossy().into())
+            .unwrap_or_else(|| "unknown".into());
+
+        let mut report = MatchReport::new(
+            demo_name,
+            self.header.as_ref().map(|h| h.map.as_str().into()),
+            self.header.as_ref().map(|h| h.server.as_str().into()),
+            self.suspicion.clone(),
+        );
+        for analyser in &self.analysers {
+            analyser.report(&mut report);
+        }
+        report.votes = self.votes.votes();
+        report.result = self.rounds.result();
+        if let Some(clock) = self.clock {
+            report.stamp(clock);
+        }
+        report
+    }
+
+    /// Read up to [CHUNK_SIZE] more bytes of the demo, and handle any packets in them until
+    /// `deadline`. Returns whether there is more of the demo to read, or [None] if the deadline
+    /// passed first.
+    pub fn read_next_bytes(&mut self, deadline: Instant) -> std::io::Result<Option<bool>> {
         let current_metadata = metadata(&self.file_path)?;
 
         // Check there's actually data to read
@@ -82,24 +521,39 @@ impl OpenDemo {
                 "Demo has shortened. Something has gone wrong.",
             ));
         } else if current_metadata.len() == self.bytes.len() as u64 {
-            return Ok(());
+            return Ok(Some(false));
         }
 
         let mut file = File::open(&self.file_path)?;
         let last_size = self.bytes.len();
 
         file.seek(std::io::SeekFrom::Start(last_size as u64))?;
-        let read_bytes = file.read_to_end(&mut self.bytes)?;
+        let read_bytes = file.take(CHUNK_SIZE).read_to_end(&mut self.bytes)?;
+        #[cfg(any(test, feature = "fault-injection"))]
+        let read_bytes = faults::short_read("demo", read_bytes);
+        self.bytes.truncate(last_size + read_bytes);
 
         if read_bytes > 0 {
             tracing::debug!("Got {} demo bytes", read_bytes);
-            self.process_next_chunk()
+            if !self.process_next_chunk(deadline) {
+                return Ok(None);
+            }
+            if let Ok(modified) = current_metadata.modified() {
+                if let Ok(lag) = modified.elapsed() {
+                    throughput::record_lag(Pipeline::Demo, lag);
+                }
+                if self.header.is_some() {
+                    let tick = u32::from(self.handler.borrow_output().tick);
+                    self.clock = Some(TickClock::anchored(tick, modified, self.tick_rate));
+                }
+            }
         }
 
-        Ok(())
+        Ok(Some(current_metadata.len() > self.bytes.len() as u64))
     }
 
-    fn process_next_chunk(&mut self) {
+    /// Handle the packets read since the last chunk, returning false if `deadline` passed first
+    fn process_next_chunk(&mut self, deadline: Instant) -> bool {
         // TODO - Change to debug when demo monitoring defaults to on
         tracing::info!("New demo length: {}", self.bytes.len());
 
@@ -111,6 +565,12 @@ impl OpenDemo {
         if self.header.is_none() {
             match Header::read(&mut stream) {
                 Ok(header) => {
+                    if let Some(rate) = clock::header_tick_rate(&header) {
+                        self.tick_rate = rate;
+                    }
+                    for analyser in &mut self.analysers {
+                        analyser.on_header(&header);
+                    }
                     self.handler.handle_header(&header);
                     self.header = Some(header);
                     self.offset = stream.pos();
@@ -120,11 +580,11 @@ impl OpenDemo {
                     bits_left,
                 }) => {
                     tracing::warn!("Tried to read header but there were not enough bits. Requested: {}, Remaining: {}", requested, bits_left);
-                    return;
+                    return true;
                 }
                 Err(e) => {
                     tracing::error!("Error reading demo header: {}", e);
-                    return;
+                    return true;
                 }
             }
         }
@@ -132,11 +592,34 @@ impl OpenDemo {
         // Parse packets
         let mut packets: RawPacketStream = RawPacketStream::new(stream);
         loop {
+            if Instant::now() > deadline {
+                return false;
+            }
             match packets.next(&self.handler.state_handler) {
                 Ok(Some(packet)) => {
-                    self.handle_packet(&packet);
+                    if let Some(server_name) = server_name(&packet) {
+                        self.server_name = Some(server_name);
+                    }
+                    if let Some(rate) = clock::packet_tick_rate(&packet) {
+                        self.tick_rate = rate;
+                    }
+                    let state = self.handler.borrow_output();
+                    self.recorder.record_packet(&packet, state);
+                    run_analysers(&mut self.analysers, &packet, state);
+                    let kills = packet_kills(&packet, state);
+                    if let Some(log) = &mut self.log {
+                        log.on_packet(&packet, state, &kills);
+                    }
+                    if let Packet::Message(MessagePacket { tick, .. }) = &packet {
+                        let tick = u32::from(*tick);
+                        self.kills.extend(kills.into_iter().map(|kill| (tick, kill)));
+                    }
+                    self.events.extend(live::packet_events(&packet));
+                    self.votes.on_packet(&packet, state);
+                    self.rounds.on_packet(&packet);
                     self.handler.handle_packet(packet).unwrap();
                     self.offset = packets.pos();
+                    throughput::record(Pipeline::Demo, 1);
                 }
                 Ok(None) => {
                     break;
@@ -150,68 +633,97 @@ impl OpenDemo {
                 }
                 Err(e) => {
                     tracing::error!("Error reading demo packet: {}", e);
-                    return;
+                    break;
                 }
             }
         }
+        true
     }
+}
 
-    fn handle_packet(&self, packet: &Packet) {
-        if let Packet::Message(MessagePacket {
-            tick: _,
-            messages,
-            meta: _,
-        }) = packet
-        {
-            for m in messages {
-                if let Message::GameEvent(GameEventMessage {
-                    event_type_id: _,
-                    event,
-                }) = m
-                {
-                    match event {
-                        GameEvent::VoteStarted(e) => {
-                            tracing::info!("Vote started: {:?}", e);
-                        }
-                        GameEvent::VoteOptions(e) => {
-                            tracing::info!("Vote options: {:?}", e);
-                        }
-                        GameEvent::VoteCast(e) => {
-                            tracing::info!("Vote cast: {:?}", e);
-                        }
-                        GameEvent::VoteEnded(e) => {
-                            tracing::info!("Vote ended: {:?}", e);
-                        }
-                        GameEvent::VotePassed(e) => {
-                            tracing::info!("Vote passed: {:?}", e);
-                        }
-                        GameEvent::VoteFailed(e) => {
-                            tracing::info!("Vote failed: {:?}", e);
-                        }
-                        GameEvent::VoteChanged(e) => {
-                            tracing::info!("Vote changed: {:?}", e);
-                        }
-                        GameEvent::PlayerConnect(e) => {
-                            tracing::info!("Player connect: {:?}", e);
-                        }
-                        GameEvent::PlayerConnectClient(e) => {
-                            tracing::info!("Player connect client: {:?}", e);
-                        }
-                        GameEvent::PlayerInfo(e) => {
-                            tracing::info!("Player info: {:?}", e);
-                        }
-                        GameEvent::Unknown(e) => {
-                            tracing::info!("Unknown: {:?}", e);
-                        }
-                        _ => {}
-                    }
-                }
+/// Show a packet, and any game events in it, to each analyser
+fn run_analysers(
+    analysers: &mut [Box<dyn DemoAnalyserPlugin>],
+    packet: &Packet,
+    state: &GameState,
+) {
+    for analyser in analysers.iter_mut() {
+        analyser.on_packet(packet, state);
+    }
+
+    let Packet::Message(MessagePacket { tick, messages, .. }) = packet else {
+        return;
+    };
+    for m in messages {
+        if let Message::GameEvent(GameEventMessage { event, .. }) = m {
+            for analyser in analysers.iter_mut() {
+                analyser.on_game_event(event, *tick, state);
             }
         }
     }
 }
 
-pub fn demo_loop(demo_path: PathBuf) -> anyhow::Result<()> {
+/// The kills in `packet`, with the players involved looked up in `state`. Deaths that weren't
+/// caused by another player (e.g. falling or suicides) aren't kills, and aren't in the console
+/// kill feed either. They are given a time once it's known when the packet's tick was recorded.
+fn packet_kills(packet: &Packet, state: &GameState) -> Vec<KillLine> {
+    let Packet::Message(MessagePacket { messages, .. }) = packet else {
+        return Vec::new();
+    };
+    let player = |user_id: u16| {
+        let info = state
+            .players
+            .iter()
+            .filter_map(|p| p.info.as_ref())
+            .find(|info| u16::from(info.user_id) == user_id)?;
+        Some((
+            info.steam_id.parse::<SteamID>().ok(),
+            Arc::<str>::from(info.name.as_str()),
+        ))
+    };
+
+    messages
+        .iter()
+        .filter_map(|m| match m {
+            Message::GameEvent(GameEventMessage {
+                event: GameEvent::PlayerDeath(death),
+                ..
+            }) if death.attacker != 0 && death.attacker != death.user_id => {
+                let (killer, killer_name) = player(death.attacker)?;
+                let (victim, victim_name) = player(death.user_id)?;
+                Some(KillLine {
+                    time: 0,
+                    killer,
+                    killer_name,
+                    victim,
+                    victim_name,
+                    weapon: death.weapon.as_ref().into(),
+                    // Mini-crits aren't shown as crits in the console
+                    crit: death.crit_type == 2,
+                    source: KillSource::Demo,
+                })
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+/// The server's name, if `packet` contains the server info sent while signing on
+fn server_name(packet: &Packet) -> Option<Arc<str>> {
+    let messages = match packet {
+        Packet::Signon(MessagePacket { messages, .. })
+        | Packet::Message(MessagePacket { messages, .. }) => messages,
+        _ => return None,
+    };
+    messages.iter().find_map(|m| match m {
+        Message::ServerInfo(info) if !info.server_name.is_empty() => {
+            Some(info.server_name.as_str().into())
+        }
+        _ => None,
+    })
+}
+
+pub fn demo_loop(demo_path: PathBuf, event_send: UnboundedSender<DemoEvent>) -> anyhow::Result<()> {
     let (tx, rx) = mpsc::channel();
     let config = Config::default().with_poll_interval(Duration::from_secs(2));
 
@@ -234,16 +746,16 @@ pub fn demo_loop(demo_path: PathBuf) -> anyhow::Result<()> {
 
     tracing::debug!("Demo loop started");
 
-    let mut manager = DemoManager::new();
+    let mut manager = DemoManager::new(event_send);
     loop {
         match rx.recv_timeout(metadata_tick) {
             Ok(event) => {
                 let path = &event.paths[0];
                 match event.kind {
-                    notify::event::EventKind::Create(_) => {
-                        if path.extension().map_or(false, |ext| ext == "dem") {
-                            manager.new_demo(path.clone());
-                        }
+                    notify::event::EventKind::Create(_)
+                        if path.extension().is_some_and(|ext| ext == "dem") =>
+                    {
+                        manager.new_demo(path.clone());
                     }
                     notify::event::EventKind::Modify(ModifyKind::Data(_)) => {
                         if manager
@@ -252,7 +764,9 @@ pub fn demo_loop(demo_path: PathBuf) -> anyhow::Result<()> {
                             .unwrap_or(false)
                         {
                             manager.read_next_bytes();
-                        } else if path.extension().map_or(false, |ext| ext == "dem") {
+                        } else if path.extension().is_some_and(|ext| ext == "dem")
+                            && !manager.is_abandoned(path)
+                        {
                             // A new demo can be started with the same name as a previous one, or the player can
                             // be already connected to a server and recording a demo when the application is run.
                             // This should catch those cases.
@@ -267,7 +781,8 @@ pub fn demo_loop(demo_path: PathBuf) -> anyhow::Result<()> {
                 manager.read_next_bytes();
             }
             Err(mpsc::RecvTimeoutError::Disconnected) => {
-                panic!("Couldn't receive demo updates. Watcher died.");
+                tracing::error!("Couldn't receive demo updates, the watcher died. Stopping.");
+                return Ok(());
             }
         }
     }
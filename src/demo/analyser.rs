@@ -0,0 +1,64 @@
+use tf_demo_parser::demo::{
+    data::DemoTick, gamevent::GameEvent, header::Header, packet::Packet,
+    parser::gamestateanalyser::GameState,
+};
+
+use super::{
+    classes::ClassTracker,
+    recording::RecordedEvent,
+    report::MatchReport,
+    suspicion::{SuspicionFlag, SuspicionKind},
+    voice::VoiceTracker,
+};
+
+pub mod event_log;
+
+/// Creates a fresh analyser for each new demo
+pub type AnalyserFactory = fn() -> Box<dyn DemoAnalyserPlugin>;
+
+/// A self-contained heuristic that watches a demo as it is recorded and flags suspicious players.
+///
+/// Each demo gets its own instance of every analyser in [default_analysers], so state can be kept freely
+/// between calls. Packets and events are seen before the [GameState] has been updated with them.
+/// Demos are parsed on their own threads, so analysers must be [Send].
+pub trait DemoAnalyserPlugin: Send {
+    /// Short name used when logging
+    fn name(&self) -> &'static str;
+
+    /// Called once the demo header has been read
+    fn on_header(&mut self, _header: &Header) {}
+
+    /// Called for every packet in the demo
+    fn on_packet(&mut self, _packet: &Packet, _state: &GameState) {}
+
+    /// Called for every game event in the demo
+    fn on_game_event(&mut self, _event: &GameEvent, _tick: DemoTick, _state: &GameState) {}
+
+    /// Called for every event when a recording of an old demo is being reprocessed, instead of
+    /// any of the above. Analysers that can work from recordings should implement this too, along
+    /// with [DemoAnalyserPlugin::replayed_kinds].
+    fn on_recorded_event(&mut self, _event: &RecordedEvent) {}
+
+    /// The kinds of flag the analyser can raise from a recording. When a recording is
+    /// reprocessed only flags of these kinds are replaced, the rest are kept from the demo.
+    fn replayed_kinds(&self) -> &'static [SuspicionKind] {
+        &[]
+    }
+
+    /// Called once the demo has finished, returning every flag raised over the match
+    fn finalize(&mut self) -> Vec<SuspicionFlag>;
+
+    /// Called whenever a match report is put together, which may be before the demo has
+    /// finished, to add anything the analyser keeps track of besides flags (e.g. how much each
+    /// player used voice chat)
+    fn report(&self, _report: &mut MatchReport) {}
+}
+
+/// The analysers that are run over every demo and recording
+pub fn default_analysers() -> Vec<AnalyserFactory> {
+    vec![
+        || Box::new(event_log::EventLog),
+        || Box::<VoiceTracker>::default(),
+        || Box::<ClassTracker>::default(),
+    ]
+}
@@ -0,0 +1,39 @@
+use tf_demo_parser::demo::{
+    data::DemoTick, gamevent::GameEvent, parser::gamestateanalyser::GameState,
+};
+
+use crate::demo::suspicion::SuspicionFlag;
+
+use super::DemoAnalyserPlugin;
+
+/// Logs player connections at debug level. Doesn't flag anyone, but shows what analysers can see.
+/// Votes are followed by [crate::demo::votes::VoteTracker] instead.
+pub struct EventLog;
+
+impl DemoAnalyserPlugin for EventLog {
+    fn name(&self) -> &'static str {
+        "event log"
+    }
+
+    fn on_game_event(&mut self, event: &GameEvent, _tick: DemoTick, _state: &GameState) {
+        match event {
+            GameEvent::PlayerConnect(e) => {
+                tracing::debug!("Player connect: {:?}", e);
+            }
+            GameEvent::PlayerConnectClient(e) => {
+                tracing::debug!("Player connect client: {:?}", e);
+            }
+            GameEvent::PlayerInfo(e) => {
+                tracing::debug!("Player info: {:?}", e);
+            }
+            GameEvent::Unknown(e) => {
+                tracing::debug!("Unknown: {:?}", e);
+            }
+            _ => {}
+        }
+    }
+
+    fn finalize(&mut self) -> Vec<SuspicionFlag> {
+        Vec::new()
+    }
+}
@@ -0,0 +1,299 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::Arc,
+};
+
+use serde::{Deserialize, Serialize};
+use tf_demo_parser::demo::{
+    gamevent::GameEvent,
+    header::Header,
+    message::{gameevent::GameEventMessage, Message},
+    packet::{message::MessagePacket, Packet},
+    parser::gamestateanalyser::{GameState, Team},
+};
+
+use super::{
+    analyser::DemoAnalyserPlugin,
+    clock::{self, DEFAULT_TICK_RATE},
+    recording::RecordedEvent,
+    report::MatchReport,
+    suspicion::{SuspicionFlag, SuspicionKind},
+};
+use crate::steamid::SteamID;
+
+/// `custom_kill` of kills that were headshots, including ones that took the head off
+const HEADSHOT_KILLS: [u16; 2] = [1, 51];
+/// Share of their time a player has to spend on one class for it to count as all they played
+const ONE_CLASS_SHARE: f32 = 0.9;
+/// Least time (seconds) on a team before a player's class choices are looked at
+const MIN_PLAYED: u32 = 5 * 60;
+/// Least kills as their class before a player's headshot rate is looked at
+const MIN_KILLS: u32 = 10;
+/// Headshot rate no one keeps up over a whole match without help. Good snipers land around half.
+const HEADSHOT_RATE: f32 = 0.9;
+
+/// Which classes a player played over a match, and what they did as each
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ClassUsage {
+    /// Seconds spent on a team as each class, by class, e.g. `sniper`
+    pub seconds: BTreeMap<Arc<str>, u32>,
+    /// How many times they changed class
+    pub switches: u32,
+    /// Kills as each class
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub kills: BTreeMap<Arc<str>, u32>,
+    /// Kills as each class that were headshots
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub headshots: BTreeMap<Arc<str>, u32>,
+    /// Kills with each weapon. This is as much of a player's loadout as the demo shows, as the
+    /// game state doesn't say what they are carrying.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub weapons: BTreeMap<Arc<str>, u32>,
+    #[serde(skip)]
+    ticks: HashMap<&'static str, u32>,
+    #[serde(skip)]
+    class: Option<&'static str>,
+    #[serde(skip)]
+    last_headshot_tick: u32,
+}
+
+impl ClassUsage {
+    fn total_ticks(&self) -> u32 {
+        self.ticks.values().sum()
+    }
+
+    /// The class played for at least [ONE_CLASS_SHARE] of the time, if there was one
+    fn main_class(&self) -> Option<&'static str> {
+        let total = self.total_ticks();
+        self.ticks
+            .iter()
+            .find(|(_, &ticks)| ticks as f32 >= ONE_CLASS_SHARE * total as f32)
+            .map(|(&class, _)| class)
+    }
+}
+
+/// Short name of a class, by its number, [None] if the player hasn't picked one
+fn class_name(class: u8) -> Option<&'static str> {
+    Some(match class {
+        1 => "scout",
+        2 => "sniper",
+        3 => "soldier",
+        4 => "demoman",
+        5 => "medic",
+        6 => "heavy",
+        7 => "pyro",
+        8 => "spy",
+        9 => "engineer",
+        _ => return None,
+    })
+}
+
+/// Whether a team number is one of the teams playing, rather than spectating or not picked
+fn is_playing(team: u8) -> bool {
+    team == Team::Red as u8 || team == Team::Blue as u8
+}
+
+/// Keeps track of the classes each player plays over the course of a demo, flagging those who
+/// land a headshot with nearly every kill as the one class they play. Works from recordings as
+/// well as demos.
+#[derive(Debug)]
+pub struct ClassTracker {
+    players: HashMap<SteamID, ClassUsage>,
+    last_tick: Option<u32>,
+    /// Ticks in a second, once the demo has said
+    tick_rate: f32,
+    /// Who each user ID belongs to and what they are playing, going by a recording
+    recorded: HashMap<u16, (SteamID, Option<&'static str>)>,
+}
+
+impl Default for ClassTracker {
+    fn default() -> Self {
+        ClassTracker {
+            players: HashMap::new(),
+            last_tick: None,
+            tick_rate: DEFAULT_TICK_RATE,
+            recorded: HashMap::new(),
+        }
+    }
+}
+
+impl ClassTracker {
+    /// Ticks since the last tick seen
+    fn advance(&mut self, tick: u32) -> u32 {
+        let elapsed = self.last_tick.map_or(0, |last| tick.saturating_sub(last));
+        self.last_tick = Some(tick);
+        elapsed
+    }
+
+    /// Count `elapsed` ticks towards a player's current class
+    fn played(&mut self, steamid: SteamID, class: &'static str, elapsed: u32) {
+        let usage = self.players.entry(steamid).or_default();
+        if usage.class.is_some_and(|c| c != class) {
+            usage.switches += 1;
+        }
+        usage.class = Some(class);
+        *usage.ticks.entry(class).or_default() += elapsed;
+    }
+
+    /// Note a kill by a player as `class`
+    fn kill(&mut self, steamid: SteamID, class: &str, weapon: &str, custom_kill: u16, tick: u32) {
+        let usage = self.players.entry(steamid).or_default();
+        *usage.kills.entry(class.into()).or_default() += 1;
+        *usage.weapons.entry(weapon.into()).or_default() += 1;
+        if HEADSHOT_KILLS.contains(&custom_kill) {
+            *usage.headshots.entry(class.into()).or_default() += 1;
+            usage.last_headshot_tick = tick;
+        }
+    }
+}
+
+impl DemoAnalyserPlugin for ClassTracker {
+    fn name(&self) -> &'static str {
+        "classes"
+    }
+
+    fn on_header(&mut self, header: &Header) {
+        if let Some(rate) = clock::header_tick_rate(header) {
+            self.tick_rate = rate;
+        }
+    }
+
+    /// Count the time since the last packet towards everyone's current class, and note the kills
+    /// in `packet`. `state` is the game state from before the packet.
+    fn on_packet(&mut self, packet: &Packet, state: &GameState) {
+        if let Some(rate) = clock::packet_tick_rate(packet) {
+            self.tick_rate = rate;
+        }
+        let Packet::Message(MessagePacket { tick, messages, .. }) = packet else {
+            return;
+        };
+        let tick = u32::from(*tick);
+        let elapsed = self.advance(tick);
+
+        let mut classes = HashMap::new();
+        for player in &state.players {
+            let (Some(info), Some(class)) = (&player.info, class_name(player.class as u8)) else {
+                continue;
+            };
+            if !is_playing(player.team as u8) {
+                continue;
+            }
+            let Ok(steamid) = info.steam_id.parse::<SteamID>() else {
+                continue;
+            };
+            classes.insert(u16::from(info.user_id), (steamid, class));
+            self.played(steamid, class, elapsed);
+        }
+
+        for m in messages {
+            let Message::GameEvent(GameEventMessage {
+                event: GameEvent::PlayerDeath(death),
+                ..
+            }) = m
+            else {
+                continue;
+            };
+            if death.attacker == 0 || death.attacker == death.user_id {
+                continue;
+            }
+            if let Some(&(steamid, class)) = classes.get(&death.attacker) {
+                self.kill(
+                    steamid,
+                    class,
+                    death.weapon.as_ref(),
+                    death.custom_kill,
+                    tick,
+                );
+            }
+        }
+    }
+
+    fn on_recorded_event(&mut self, event: &RecordedEvent) {
+        match event {
+            RecordedEvent::Tick(tick) => {
+                let elapsed = self.advance(*tick);
+                let playing: Vec<_> = self
+                    .recorded
+                    .values()
+                    .filter_map(|&(steamid, class)| Some((steamid, class?)))
+                    .collect();
+                for (steamid, class) in playing {
+                    self.played(steamid, class, elapsed);
+                }
+            }
+            RecordedEvent::Joined {
+                user_id, steamid, ..
+            } => {
+                self.recorded.insert(*user_id, (*steamid, None));
+            }
+            RecordedEvent::Left { user_id } => {
+                self.recorded.remove(user_id);
+            }
+            RecordedEvent::Player(snapshot) => {
+                if let Some((_, class)) = self.recorded.get_mut(&snapshot.user_id) {
+                    *class = class_name(snapshot.class).filter(|_| is_playing(snapshot.team));
+                }
+            }
+            RecordedEvent::Death {
+                victim,
+                attacker,
+                weapon,
+                custom_kill,
+            } if attacker != victim => {
+                let tick = self.last_tick.unwrap_or(0);
+                if let Some(&(steamid, Some(class))) = self.recorded.get(attacker) {
+                    self.kill(steamid, class, weapon, *custom_kill, tick);
+                }
+            }
+            RecordedEvent::Death { .. } => {}
+        }
+    }
+
+    fn replayed_kinds(&self) -> &'static [SuspicionKind] {
+        &[SuspicionKind::Headshots]
+    }
+
+    /// Flag players who played one class nearly all match and landed a headshot with nearly
+    /// every kill as it, which aimbots make easy and people don't keep up
+    fn finalize(&mut self) -> Vec<SuspicionFlag> {
+        let min_played = (MIN_PLAYED as f32 * self.tick_rate) as u32;
+        self.players
+            .iter()
+            .filter(|(_, usage)| usage.total_ticks() >= min_played)
+            .filter_map(|(steamid, usage)| {
+                let class = usage.main_class()?;
+                let kills = usage.kills.get(class).copied().unwrap_or(0);
+                let headshots = usage.headshots.get(class).copied().unwrap_or(0);
+                let rate = headshots as f32 / kills as f32;
+                if kills < MIN_KILLS || rate < HEADSHOT_RATE {
+                    return None;
+                }
+                Some(SuspicionFlag {
+                    steamid: *steamid,
+                    kind: SuspicionKind::Headshots,
+                    tick: usage.last_headshot_tick,
+                    confidence: ((rate - HEADSHOT_RATE) / (1.0 - HEADSHOT_RATE) * 0.5 + 0.5)
+                        .clamp(0.5, 1.0),
+                })
+            })
+            .collect()
+    }
+
+    /// The classes played by everyone who picked one
+    fn report(&self, report: &mut MatchReport) {
+        report.classes = self
+            .players
+            .iter()
+            .map(|(steamid, usage)| {
+                let mut usage = usage.clone();
+                usage.seconds = usage
+                    .ticks
+                    .iter()
+                    .map(|(&class, &ticks)| (class.into(), (ticks as f32 / self.tick_rate) as u32))
+                    .collect();
+                (*steamid, usage)
+            })
+            .collect();
+    }
+}
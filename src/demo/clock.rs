@@ -0,0 +1,110 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+use tf_demo_parser::demo::{
+    header::Header,
+    message::Message,
+    packet::{message::MessagePacket, Packet},
+};
+
+/// Milliseconds between ticks on TF2 servers, for demos that don't say otherwise
+const DEFAULT_TICK_INTERVAL_MS: f64 = 15.0;
+/// Ticks in a second on TF2 servers, for demos that don't say otherwise
+pub const DEFAULT_TICK_RATE: f32 = (1000.0 / DEFAULT_TICK_INTERVAL_MS) as f32;
+
+/// Ticks in a second going by the demo header. The header of a demo that is still being recorded
+/// doesn't have its length filled in yet, so it has nothing to go on.
+pub fn header_tick_rate(header: &Header) -> Option<f32> {
+    Some(header.ticks as f32 / header.duration).filter(|rate| rate.is_finite() && *rate > 0.0)
+}
+
+/// Ticks in a second going by the server info in `packet`, which is sent while signing on
+pub fn packet_tick_rate(packet: &Packet) -> Option<f32> {
+    let (Packet::Signon(MessagePacket { messages, .. })
+    | Packet::Message(MessagePacket { messages, .. })) = packet
+    else {
+        return None;
+    };
+    messages.iter().find_map(|m| match m {
+        Message::ServerInfo(info) if info.interval_per_tick > 0.0 => {
+            Some(1.0 / info.interval_per_tick)
+        }
+        _ => None,
+    })
+}
+
+/// Ties the ticks of a demo to the time they were recorded at, so events in exports can be given
+/// a wall-clock time that reviewers without the demo can follow
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TickClock {
+    /// Unix timestamp (milliseconds) of tick 0, when the demo started being recorded
+    pub start_ms: u64,
+    /// Milliseconds between ticks on the server the demo was recorded on. Reports saved before
+    /// this was kept assume the usual rate.
+    #[serde(default = "default_tick_interval")]
+    pub tick_interval_ms: f64,
+}
+
+fn default_tick_interval() -> f64 {
+    DEFAULT_TICK_INTERVAL_MS
+}
+
+impl TickClock {
+    /// A clock on which `tick` was recorded at `at`, on a server running at `tick_rate` ticks a
+    /// second (see [header_tick_rate] and [packet_tick_rate])
+    pub fn anchored(tick: u32, at: SystemTime, tick_rate: f32) -> TickClock {
+        let tick_interval_ms = Some(1000.0 / f64::from(tick_rate))
+            .filter(|interval| interval.is_finite() && *interval > 0.0)
+            .unwrap_or(DEFAULT_TICK_INTERVAL_MS);
+        let at_ms = at
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis() as u64)
+            .unwrap_or(0);
+        TickClock {
+            start_ms: at_ms.saturating_sub((tick as f64 * tick_interval_ms) as u64),
+            tick_interval_ms,
+        }
+    }
+
+    /// Unix timestamp (milliseconds) `tick` was recorded at
+    pub fn time_ms(&self, tick: u32) -> u64 {
+        self.start_ms + (tick as f64 * self.tick_interval_ms) as u64
+    }
+
+    /// When `tick` was recorded, in ISO 8601 (e.g. `2024-03-01T18:04:12.345Z`)
+    pub fn iso8601(&self, tick: u32) -> String {
+        iso8601(self.time_ms(tick))
+    }
+}
+
+/// A Unix timestamp (milliseconds) in ISO 8601, in UTC
+pub fn iso8601(unix_ms: u64) -> String {
+    let secs = unix_ms / 1000;
+    let (year, month, day) = civil_from_days((secs / 86400) as i64);
+    let time = secs % 86400;
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z",
+        year,
+        month,
+        day,
+        time / 3600,
+        time % 3600 / 60,
+        time % 60,
+        unix_ms % 1000
+    )
+}
+
+/// The date `days` after 1970-01-01, see http://howardhinnant.github.io/date_algorithms.html
+pub fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
@@ -0,0 +1,76 @@
+//! Players coming and going in the demo being recorded, which are passed on to the main thread as
+//! they are read so the rest of the client (and the web UI) can react to them during the match
+//! rather than only seeing them in the report at the end. Kick votes are passed on the same way,
+//! from [super::votes::VoteTracker].
+
+use std::sync::Arc;
+
+use serde::Serialize;
+use tf_demo_parser::demo::{
+    data::MaybeUtf8String,
+    gamevent::GameEvent,
+    message::{gameevent::GameEventMessage, Message},
+    packet::{message::MessagePacket, Packet},
+};
+
+use crate::steamid::SteamID;
+
+use super::DemoEvent;
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PlayerConnect {
+    pub tick: u32,
+    pub user_id: u16,
+    /// Missing for bots
+    pub steamid: Option<SteamID>,
+    pub name: Arc<str>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PlayerDisconnect {
+    pub tick: u32,
+    pub user_id: u16,
+    pub steamid: Option<SteamID>,
+    pub name: Arc<str>,
+    /// Why they left, as the server put it, e.g. `Disconnect by user.`
+    pub reason: Arc<str>,
+}
+
+/// The players coming and going in `packet`
+pub fn packet_events(packet: &Packet) -> Vec<DemoEvent> {
+    let Packet::Message(MessagePacket { tick, messages, .. }) = packet else {
+        return Vec::new();
+    };
+    let tick = u32::from(*tick);
+    let network_id = |id: &MaybeUtf8String| id.as_ref().parse::<SteamID>().ok();
+
+    messages
+        .iter()
+        .filter_map(|m| match m {
+            Message::GameEvent(GameEventMessage { event, .. }) => Some(event),
+            _ => None,
+        })
+        .filter_map(|event| match event {
+            GameEvent::PlayerConnectClient(connect) => {
+                Some(DemoEvent::PlayerConnect(PlayerConnect {
+                    tick,
+                    user_id: connect.user_id,
+                    steamid: network_id(&connect.network_id),
+                    name: connect.name.as_ref().into(),
+                }))
+            }
+            GameEvent::PlayerDisconnect(disconnect) => {
+                Some(DemoEvent::PlayerDisconnect(PlayerDisconnect {
+                    tick,
+                    user_id: disconnect.user_id,
+                    steamid: network_id(&disconnect.network_id),
+                    name: disconnect.name.as_ref().into(),
+                    reason: disconnect.reason.as_ref().into(),
+                }))
+            }
+            _ => None,
+        })
+        .collect()
+}
@@ -0,0 +1,198 @@
+//! Analysing a complete demo after the fact, e.g. one of a suspected cheater shared by someone
+//! else. The whole file is parsed in one go with the default analysers, and everything seen in it
+//! is gathered into a [DemoAnalysis] instead of being sent to the rest of the client.
+
+use std::{collections::HashMap, sync::Arc};
+
+use serde::Serialize;
+use tf_demo_parser::demo::message::usermessage::{ChatMessageKind, UserMessage};
+use tf_demo_parser::demo::message::Message;
+use tf_demo_parser::demo::packet::message::MessagePacket;
+use tf_demo_parser::demo::packet::Packet;
+use tf_demo_parser::demo::parser::gamestateanalyser::GameState;
+
+use crate::{player::KillLine, steamid::SteamID};
+
+use super::{clock::TickClock, report::MatchReport};
+
+/// Everything found in a complete demo by [super::DemoManager::analyse_file]
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DemoAnalysis {
+    /// The report a demo recorded live would have produced, with the suspicion, votes, voice
+    /// and result of the match. It isn't saved with the other reports.
+    pub report: MatchReport,
+    /// The last tick of the demo
+    pub ticks: u32,
+    /// Everyone who was on the server at some point, in the order they were first seen
+    pub players: Vec<SeenPlayer>,
+    pub chat: Vec<DemoChat>,
+    pub kills: Vec<DemoKill>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SeenPlayer {
+    pub user_id: u16,
+    /// Missing for bots
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub steamid: Option<SteamID>,
+    /// The name they had when last seen
+    pub name: Arc<str>,
+    pub first_tick: u32,
+    pub last_tick: u32,
+    pub kills: u32,
+    pub deaths: u32,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DemoChat {
+    pub tick: u32,
+    /// When the message was sent in ISO 8601, if the demo could be placed in time
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub time: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub steamid: Option<SteamID>,
+    pub name: Arc<str>,
+    pub message: Arc<str>,
+    pub team: bool,
+    pub dead: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DemoKill {
+    pub tick: u32,
+    /// When the kill happened in ISO 8601, if the demo could be placed in time
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub time: Option<String>,
+    pub killer: Option<SteamID>,
+    pub killer_name: Arc<str>,
+    pub victim: Option<SteamID>,
+    pub victim_name: Arc<str>,
+    pub weapon: Arc<str>,
+    pub crit: bool,
+}
+
+/// Gathers the players, chat and kills of a demo as it is parsed
+#[derive(Debug, Default)]
+pub struct DemoLog {
+    tick: u32,
+    /// By user id
+    players: HashMap<u16, SeenPlayer>,
+    chat: Vec<DemoChat>,
+    kills: Vec<DemoKill>,
+}
+
+impl DemoLog {
+    /// Note down everything in `packet`. `state` is the game state from before the packet and
+    /// `kills` the ones read from it.
+    pub fn on_packet(&mut self, packet: &Packet, state: &GameState, kills: &[KillLine]) {
+        let Packet::Message(MessagePacket { tick, messages, .. }) = packet else {
+            return;
+        };
+        let tick = u32::from(*tick);
+        self.tick = self.tick.max(tick);
+
+        for info in state.players.iter().filter_map(|p| p.info.as_ref()) {
+            let seen = self
+                .players
+                .entry(u16::from(info.user_id))
+                .or_insert_with(|| SeenPlayer {
+                    user_id: u16::from(info.user_id),
+                    steamid: info.steam_id.parse().ok(),
+                    name: info.name.as_str().into(),
+                    first_tick: tick,
+                    last_tick: tick,
+                    kills: 0,
+                    deaths: 0,
+                });
+            seen.last_tick = tick;
+            if *seen.name != *info.name {
+                seen.name = info.name.as_str().into();
+            }
+        }
+
+        for message in messages {
+            let Message::UserMessage(UserMessage::SayText2(chat)) = message else {
+                continue;
+            };
+            let (team, dead) = match chat.kind {
+                ChatMessageKind::ChatAll | ChatMessageKind::ChatAllSpec => (false, false),
+                ChatMessageKind::ChatTeam => (true, false),
+                ChatMessageKind::ChatAllDead => (false, true),
+                ChatMessageKind::ChatTeamDead => (true, true),
+                // Name changes and server messages aren't anyone talking
+                _ => continue,
+            };
+            let sender = state
+                .players
+                .iter()
+                .find(|p| p.entity == chat.client)
+                .and_then(|p| p.info.as_ref());
+            let name = sender
+                .map(|info| info.name.as_str())
+                .or(chat.from.as_ref().map(|from| from.as_ref()))
+                .unwrap_or_default();
+            self.chat.push(DemoChat {
+                tick,
+                time: None,
+                steamid: sender.and_then(|info| info.steam_id.parse().ok()),
+                name: name.into(),
+                message: chat.text.as_ref().into(),
+                team,
+                dead,
+            });
+        }
+
+        self.kills.extend(kills.iter().map(|kill| DemoKill {
+            tick,
+            time: None,
+            killer: kill.killer,
+            killer_name: kill.killer_name.clone(),
+            victim: kill.victim,
+            victim_name: kill.victim_name.clone(),
+            weapon: kill.weapon.clone(),
+            crit: kill.crit,
+        }));
+    }
+
+    /// Put everything together with the demo's `report`, giving it all a wall-clock time if the
+    /// demo could be placed in time
+    pub fn finish(self, report: MatchReport, clock: Option<TickClock>) -> DemoAnalysis {
+        let mut players: Vec<SeenPlayer> = self.players.into_values().collect();
+        players.sort_by_key(|p| (p.first_tick, p.user_id));
+        for player in players.iter_mut().filter(|p| p.steamid.is_some()) {
+            player.kills = self
+                .kills
+                .iter()
+                .filter(|k| k.killer == player.steamid)
+                .count() as u32;
+            player.deaths = self
+                .kills
+                .iter()
+                .filter(|k| k.victim == player.steamid)
+                .count() as u32;
+        }
+
+        let mut chat = self.chat;
+        let mut kills = self.kills;
+        if let Some(clock) = clock {
+            for message in &mut chat {
+                message.time = Some(clock.iso8601(message.tick));
+            }
+            for kill in &mut kills {
+                kill.time = Some(clock.iso8601(kill.tick));
+            }
+        }
+
+        DemoAnalysis {
+            report,
+            ticks: self.tick,
+            players,
+            chat,
+            kills,
+        }
+    }
+}
@@ -0,0 +1,401 @@
+use std::{
+    collections::{hash_map::Entry, HashMap},
+    fs::File,
+    io::{BufReader, BufWriter, ErrorKind, Read, Write},
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+use tf_demo_parser::demo::{
+    gamevent::GameEvent,
+    message::{gameevent::GameEventMessage, Message},
+    packet::{message::MessagePacket, Packet},
+    parser::gamestateanalyser::{GameState, PlayerState},
+};
+
+use crate::{
+    settings::{ConfigFilesError, Settings},
+    steamid::SteamID,
+};
+
+/// Start of every recording, followed by the format version
+const MAGIC: &[u8; 4] = b"MACR";
+/// Bumped whenever the layout of [RecordedEvent]s changes
+pub const FORMAT_VERSION: u8 = 2;
+/// Oldest format version that can still be read. Version 1 recordings don't say which deaths were
+/// headshots.
+const OLDEST_FORMAT_VERSION: u8 = 1;
+pub const RECORDING_EXTENSION: &str = "mrec";
+
+/// Where a player was, where they were looking and how they were doing. Positions are rounded to
+/// the nearest hammer unit and angles are scaled to fit a u16, which is plenty for heuristics.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PlayerSnapshot {
+    pub user_id: u16,
+    pub position: [i16; 3],
+    pub yaw: u16,
+    pub pitch: u16,
+    pub health: u16,
+    pub class: u8,
+    pub team: u8,
+    pub alive: bool,
+}
+
+fn angle_to_u16(degrees: f32) -> u16 {
+    (degrees.rem_euclid(360.0) / 360.0 * 65536.0) as u32 as u16
+}
+
+/// The parts of a demo that heuristics need, in a form much smaller than the demo itself so they
+/// can be kept for every session and re-run later.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RecordedEvent {
+    /// Everything until the next tick happened on this tick
+    Tick(u32),
+    /// A player was first seen, tying their user ID to their SteamID
+    Joined {
+        user_id: u16,
+        steamid: SteamID,
+        name: Arc<str>,
+    },
+    Left {
+        user_id: u16,
+    },
+    /// A player's state changed
+    Player(PlayerSnapshot),
+    Death {
+        victim: u16,
+        attacker: u16,
+        weapon: Arc<str>,
+        /// How the kill was special, e.g. 1 for a headshot, 0 if it wasn't
+        custom_kill: u16,
+    },
+}
+
+impl RecordedEvent {
+    /// Write the event in the current format version
+    pub fn write(&self, out: &mut impl Write) -> std::io::Result<()> {
+        match self {
+            RecordedEvent::Tick(tick) => {
+                out.write_all(&[0])?;
+                out.write_all(&tick.to_le_bytes())
+            }
+            RecordedEvent::Joined {
+                user_id,
+                steamid,
+                name,
+            } => {
+                out.write_all(&[1])?;
+                out.write_all(&user_id.to_le_bytes())?;
+                out.write_all(&u64::from(*steamid).to_le_bytes())?;
+                write_str(out, name)
+            }
+            RecordedEvent::Left { user_id } => {
+                out.write_all(&[2])?;
+                out.write_all(&user_id.to_le_bytes())
+            }
+            RecordedEvent::Player(player) => {
+                out.write_all(&[3])?;
+                out.write_all(&player.user_id.to_le_bytes())?;
+                for coord in player.position {
+                    out.write_all(&coord.to_le_bytes())?;
+                }
+                out.write_all(&player.yaw.to_le_bytes())?;
+                out.write_all(&player.pitch.to_le_bytes())?;
+                out.write_all(&player.health.to_le_bytes())?;
+                out.write_all(&[player.class, player.team, player.alive as u8])
+            }
+            RecordedEvent::Death {
+                victim,
+                attacker,
+                weapon,
+                custom_kill,
+            } => {
+                out.write_all(&[4])?;
+                out.write_all(&victim.to_le_bytes())?;
+                out.write_all(&attacker.to_le_bytes())?;
+                write_str(out, weapon)?;
+                out.write_all(&custom_kill.to_le_bytes())
+            }
+        }
+    }
+
+    /// Read the next event of a recording in format `version`, or `None` at the end of the
+    /// recording
+    pub fn read(input: &mut impl Read, version: u8) -> std::io::Result<Option<RecordedEvent>> {
+        let mut tag = [0];
+        match input.read_exact(&mut tag) {
+            Ok(()) => {}
+            Err(e) if e.kind() == ErrorKind::UnexpectedEof => return Ok(None),
+            Err(e) => return Err(e),
+        }
+
+        let event = match tag[0] {
+            0 => RecordedEvent::Tick(read_u32(input)?),
+            1 => RecordedEvent::Joined {
+                user_id: read_u16(input)?,
+                steamid: SteamID::from(read_u64(input)?),
+                name: read_str(input)?,
+            },
+            2 => RecordedEvent::Left {
+                user_id: read_u16(input)?,
+            },
+            3 => {
+                let user_id = read_u16(input)?;
+                let position = [
+                    read_u16(input)? as i16,
+                    read_u16(input)? as i16,
+                    read_u16(input)? as i16,
+                ];
+                let yaw = read_u16(input)?;
+                let pitch = read_u16(input)?;
+                let health = read_u16(input)?;
+                let mut rest = [0; 3];
+                input.read_exact(&mut rest)?;
+                RecordedEvent::Player(PlayerSnapshot {
+                    user_id,
+                    position,
+                    yaw,
+                    pitch,
+                    health,
+                    class: rest[0],
+                    team: rest[1],
+                    alive: rest[2] != 0,
+                })
+            }
+            4 => RecordedEvent::Death {
+                victim: read_u16(input)?,
+                attacker: read_u16(input)?,
+                weapon: read_str(input)?,
+                custom_kill: if version >= 2 { read_u16(input)? } else { 0 },
+            },
+            tag => {
+                return Err(std::io::Error::new(
+                    ErrorKind::InvalidData,
+                    format!("Unknown event type {}", tag),
+                ))
+            }
+        };
+        Ok(Some(event))
+    }
+}
+
+fn write_str(out: &mut impl Write, s: &str) -> std::io::Result<()> {
+    let bytes = &s.as_bytes()[..s.len().min(u16::MAX as usize)];
+    out.write_all(&(bytes.len() as u16).to_le_bytes())?;
+    out.write_all(bytes)
+}
+
+fn read_u16(input: &mut impl Read) -> std::io::Result<u16> {
+    let mut bytes = [0; 2];
+    input.read_exact(&mut bytes)?;
+    Ok(u16::from_le_bytes(bytes))
+}
+
+fn read_u32(input: &mut impl Read) -> std::io::Result<u32> {
+    let mut bytes = [0; 4];
+    input.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+fn read_u64(input: &mut impl Read) -> std::io::Result<u64> {
+    let mut bytes = [0; 8];
+    input.read_exact(&mut bytes)?;
+    Ok(u64::from_le_bytes(bytes))
+}
+
+fn read_str(input: &mut impl Read) -> std::io::Result<Arc<str>> {
+    let mut bytes = vec![0; read_u16(input)? as usize];
+    input.read_exact(&mut bytes)?;
+    Ok(String::from_utf8_lossy(&bytes).into())
+}
+
+/// Writes the [RecordedEvent]s of a demo to a compressed file as the demo is parsed
+pub struct Recorder {
+    path: PathBuf,
+    out: Option<GzEncoder<BufWriter<File>>>,
+    tick: Option<u32>,
+    joined: HashMap<u16, SteamID>,
+    players: HashMap<u16, PlayerSnapshot>,
+}
+
+impl Recorder {
+    /// Start a recording for the demo with the given file name. If the file can't be created the
+    /// error is logged and nothing will be recorded.
+    pub fn create(demo_name: &str) -> Recorder {
+        let path = Self::locate_recordings_directory()
+            .map(|dir| dir.join(demo_name).with_extension(RECORDING_EXTENSION))
+            .map_err(|e| tracing::error!("Could not find the recordings directory: {:?}", e))
+            .ok();
+
+        let out = path.as_ref().and_then(|path| {
+            File::create(path)
+                .and_then(|file| {
+                    let mut out = GzEncoder::new(BufWriter::new(file), Compression::default());
+                    out.write_all(MAGIC)?;
+                    out.write_all(&[FORMAT_VERSION])?;
+                    Ok(out)
+                })
+                .map_err(|e| tracing::error!("Failed to create recording {:?}: {:?}", path, e))
+                .ok()
+        });
+
+        Recorder {
+            path: path.unwrap_or_default(),
+            out,
+            tick: None,
+            joined: HashMap::new(),
+            players: HashMap::new(),
+        }
+    }
+
+    /// A recorder that doesn't record anything, for demos that aren't being recorded live
+    pub fn disabled() -> Recorder {
+        Recorder {
+            path: PathBuf::new(),
+            out: None,
+            tick: None,
+            joined: HashMap::new(),
+            players: HashMap::new(),
+        }
+    }
+
+    fn write(&mut self, event: RecordedEvent) {
+        let Some(out) = self.out.as_mut() else {
+            return;
+        };
+        if let Err(e) = event.write(out) {
+            tracing::error!("Failed to write to recording {:?}: {:?}", self.path, e);
+            self.out = None;
+        }
+    }
+
+    /// Record anything in `packet` that has changed since the last one. `state` is the game state
+    /// from before the packet.
+    pub fn record_packet(&mut self, packet: &Packet, state: &GameState) {
+        let Packet::Message(MessagePacket { tick, messages, .. }) = packet else {
+            return;
+        };
+        let tick = u32::from(*tick);
+        if self.tick != Some(tick) {
+            self.tick = Some(tick);
+            self.write(RecordedEvent::Tick(tick));
+        }
+
+        for player in &state.players {
+            let Some(info) = &player.info else {
+                continue;
+            };
+            let user_id = u16::from(info.user_id);
+
+            if let Entry::Vacant(entry) = self.joined.entry(user_id) {
+                let Ok(steamid) = info.steam_id.parse::<SteamID>() else {
+                    continue;
+                };
+                entry.insert(steamid);
+                self.write(RecordedEvent::Joined {
+                    user_id,
+                    steamid,
+                    name: info.name.as_str().into(),
+                });
+            }
+
+            let snapshot = PlayerSnapshot {
+                user_id,
+                position: [
+                    player.position.x.round() as i16,
+                    player.position.y.round() as i16,
+                    player.position.z.round() as i16,
+                ],
+                yaw: angle_to_u16(player.view_angle),
+                pitch: angle_to_u16(player.pitch_angle),
+                health: player.health,
+                class: player.class as u8,
+                team: player.team as u8,
+                alive: player.state == PlayerState::Alive,
+            };
+            if self.players.get(&user_id) != Some(&snapshot) {
+                self.players.insert(user_id, snapshot.clone());
+                self.write(RecordedEvent::Player(snapshot));
+            }
+        }
+
+        for m in messages {
+            let Message::GameEvent(GameEventMessage { event, .. }) = m else {
+                continue;
+            };
+            match event {
+                GameEvent::PlayerDeath(death) => self.write(RecordedEvent::Death {
+                    victim: death.user_id,
+                    attacker: death.attacker,
+                    weapon: death.weapon.as_ref().into(),
+                    custom_kill: death.custom_kill,
+                }),
+                GameEvent::PlayerDisconnect(disconnect) => {
+                    self.joined.remove(&disconnect.user_id);
+                    self.players.remove(&disconnect.user_id);
+                    self.write(RecordedEvent::Left {
+                        user_id: disconnect.user_id,
+                    });
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Finish writing the recording, returning its file name if anything was recorded
+    pub fn finish(&mut self) -> Option<Arc<str>> {
+        let out = self.out.take()?;
+        if let Err(e) = out.finish().and_then(|mut out| out.flush()) {
+            tracing::error!("Failed to finish recording {:?}: {:?}", self.path, e);
+            return None;
+        }
+        self.path
+            .file_name()
+            .map(|name| name.to_string_lossy().into())
+    }
+
+    /// Attempts to find (and create) the directory recordings are stored in
+    pub fn locate_recordings_directory() -> Result<PathBuf, ConfigFilesError> {
+        let dir = Settings::locate_config_directory()?.join("recordings");
+        std::fs::create_dir_all(&dir)
+            .map_err(|e| ConfigFilesError::IO(dir.to_string_lossy().into(), e))?;
+        Ok(dir)
+    }
+}
+
+/// Read every event from a recording. A recording cut off part way through an event, e.g. when
+/// the client crashed, is read up to where it was cut off.
+pub fn read_recording(path: &Path) -> Result<Vec<RecordedEvent>, ConfigFilesError> {
+    let io_err = |e| ConfigFilesError::IO(path.to_string_lossy().into(), e);
+    let file = File::open(path).map_err(io_err)?;
+    let mut input = GzDecoder::new(BufReader::new(file));
+
+    let mut header = [0; 5];
+    input.read_exact(&mut header).map_err(io_err)?;
+    let version = header[4];
+    if &header[..4] != MAGIC || !(OLDEST_FORMAT_VERSION..=FORMAT_VERSION).contains(&version) {
+        return Err(io_err(std::io::Error::new(
+            ErrorKind::InvalidData,
+            "Not a recording, or recorded in a different format version",
+        )));
+    }
+
+    let mut events = Vec::new();
+    loop {
+        match RecordedEvent::read(&mut input, version) {
+            Ok(Some(event)) => events.push(event),
+            Ok(None) => break,
+            Err(e) if e.kind() == ErrorKind::UnexpectedEof => {
+                tracing::warn!(
+                    "Recording {:?} was cut off after {} events",
+                    path,
+                    events.len()
+                );
+                break;
+            }
+            Err(e) => return Err(io_err(e)),
+        }
+    }
+    Ok(events)
+}
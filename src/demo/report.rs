@@ -0,0 +1,148 @@
+use std::{
+    collections::HashMap,
+    path::PathBuf,
+    sync::Arc,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    persistence,
+    settings::{ConfigFilesError, Settings},
+    steamid::SteamID,
+};
+
+use super::{
+    classes::ClassUsage,
+    clock::TickClock,
+    rounds::MatchResult,
+    suspicion::{EncounterSuspicion, MatchSuspicion},
+    voice::VoiceActivity,
+    votes::KickVote,
+};
+
+/// A summary of a single match, produced once a demo has finished being recorded
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MatchReport {
+    /// File name of the demo the report was produced from
+    pub demo: Arc<str>,
+    /// Unix timestamp (seconds) of when the report was produced
+    pub time: u64,
+    pub map: Option<Arc<str>>,
+    pub server: Option<Arc<str>>,
+    pub suspicion: MatchSuspicion,
+    /// File name of the recording of the demo's events, in the recordings directory
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub recording: Option<Arc<str>>,
+    /// How much each player that used voice chat did
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub voice: HashMap<SteamID, VoiceActivity>,
+    /// The classes each player played, and what they did as each
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub classes: HashMap<SteamID, ClassUsage>,
+    /// Kick votes called during the match, with how each player voted
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub votes: Vec<KickVote>,
+    /// The final score and how each round went, if any round ended before the demo did
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub result: Option<MatchResult>,
+    /// When each tick of the demo was recorded, if it's known. Everything in the report with a
+    /// tick is also given a wall-clock time from this.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub clock: Option<TickClock>,
+    /// When the demo started being recorded, in ISO 8601
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub started: Option<String>,
+}
+
+impl MatchReport {
+    pub fn new(
+        demo: Arc<str>,
+        map: Option<Arc<str>>,
+        server: Option<Arc<str>>,
+        suspicion: MatchSuspicion,
+    ) -> MatchReport {
+        MatchReport {
+            demo,
+            time: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or(0),
+            map,
+            server,
+            suspicion,
+            recording: None,
+            voice: HashMap::new(),
+            classes: HashMap::new(),
+            votes: Vec::new(),
+            result: None,
+            clock: None,
+            started: None,
+        }
+    }
+
+    /// Give everything in the report that happened on a tick a wall-clock time as well
+    pub fn stamp(&mut self, clock: TickClock) {
+        self.clock = Some(clock);
+        self.started = Some(clock.iso8601(0));
+        self.suspicion.stamp(&clock);
+        for activity in self.voice.values_mut() {
+            activity.longest_streak_time = Some(clock.iso8601(activity.longest_streak_tick));
+        }
+        for vote in &mut self.votes {
+            vote.time = Some(clock.iso8601(vote.tick));
+        }
+        if let Some(result) = &mut self.result {
+            result.stamp(&clock);
+        }
+    }
+
+    /// The encounter-level datapoint to merge into a player's long-term record, if they were
+    /// flagged at all during this match.
+    pub fn encounter_suspicion(&self, steamid: &SteamID) -> Option<EncounterSuspicion> {
+        self.suspicion
+            .get(steamid)
+            .map(|suspicion| EncounterSuspicion {
+                time: self.time,
+                demo: self.demo.clone(),
+                map: self.map.clone(),
+                suspicion: suspicion.clone(),
+            })
+    }
+
+    /// Attempt to save the report to the reports directory, returning the path it was saved to.
+    pub fn save(&self) -> Result<PathBuf, ConfigFilesError> {
+        let path = Self::locate_reports_directory()?.join(format!("{}.json", self.demo));
+        let contents = serde_json::to_string(self).context("Failed to serialize match report.")?;
+        persistence::write_atomic(&path, &contents)?;
+        Ok(path)
+    }
+
+    /// Attempt to load the report on the demo with the file name `demo`, if one was saved
+    pub fn load(demo: &str) -> Result<MatchReport, ConfigFilesError> {
+        let path = Self::locate_reports_directory()?.join(format!("{}.json", demo));
+        let contents = std::fs::read_to_string(&path)
+            .map_err(|e| ConfigFilesError::IO(path.to_string_lossy().into(), e))?;
+        serde_json::from_str(&contents)
+            .map_err(|e| ConfigFilesError::Json(path.to_string_lossy().into(), e))
+    }
+
+    /// Attempt to save the report, log errors and ignore result
+    pub fn save_ok(&self) {
+        match self.save() {
+            Ok(path) => tracing::debug!("Match report saved to {:?}", path),
+            Err(e) => tracing::error!("Failed to save match report: {:?}", e),
+        }
+    }
+
+    /// Attempts to find (and create) the directory match reports are stored in
+    pub fn locate_reports_directory() -> Result<PathBuf, ConfigFilesError> {
+        let dir = Settings::locate_config_directory()?.join("reports");
+        std::fs::create_dir_all(&dir)
+            .map_err(|e| ConfigFilesError::IO(dir.to_string_lossy().into(), e))?;
+        Ok(dir)
+    }
+}
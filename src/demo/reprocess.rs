@@ -0,0 +1,166 @@
+use std::{
+    collections::HashSet,
+    panic,
+    path::Path,
+    sync::{Arc, Mutex},
+};
+
+use serde::Serialize;
+use tokio::sync::mpsc::UnboundedSender;
+
+use super::{
+    analyser::{self, DemoAnalyserPlugin},
+    recording::{self, Recorder},
+    report::MatchReport,
+    suspicion::{MatchSuspicion, SuspicionKind},
+    DemoEvent,
+};
+use crate::analytics::history::now;
+
+/// How far through re-running the demo analysers over old recordings the current (or last)
+/// job is
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReprocessStatus {
+    pub running: bool,
+    pub total: usize,
+    pub done: usize,
+    /// Recordings that couldn't be read
+    pub failed: usize,
+    /// Unix timestamps (seconds)
+    pub started: Option<u64>,
+    pub finished: Option<u64>,
+}
+
+/// What running the analysers over a recording found
+#[derive(Debug, Clone, Default)]
+pub struct Replay {
+    pub suspicion: MatchSuspicion,
+    /// The kinds of flag that analysers able to work from recordings could have raised. Only
+    /// these replace what the original demo found, see [DemoAnalyserPlugin::replayed_kinds].
+    pub kinds: HashSet<SuspicionKind>,
+}
+
+/// Re-runs the demo analysers over recordings of past sessions, e.g. after the analysers have
+/// been improved. Each recording produces a new [MatchReport], sent as a
+/// [DemoEvent::Reprocessed] so the suspicion on player records can be replaced.
+#[derive(Clone)]
+pub struct Reprocessor {
+    status: Arc<Mutex<ReprocessStatus>>,
+    event_send: UnboundedSender<DemoEvent>,
+}
+
+impl Reprocessor {
+    pub fn new(event_send: UnboundedSender<DemoEvent>) -> Reprocessor {
+        Reprocessor {
+            status: Arc::new(Mutex::new(ReprocessStatus::default())),
+            event_send,
+        }
+    }
+
+    pub fn status(&self) -> ReprocessStatus {
+        self.status.lock().unwrap().clone()
+    }
+
+    /// Start reprocessing the recordings with the given file names on a new thread. The user asked
+    /// for this, so unlike background work it carries on while they aren't on a server. Returns
+    /// false if a job is already running.
+    pub fn start(&self, recordings: Vec<Arc<str>>) -> bool {
+        {
+            let mut status = self.status.lock().unwrap();
+            if status.running {
+                return false;
+            }
+            *status = ReprocessStatus {
+                running: true,
+                total: recordings.len(),
+                started: Some(now()),
+                ..Default::default()
+            };
+        }
+
+        tracing::info!("Reprocessing {} recordings", recordings.len());
+        let reprocessor = self.clone();
+        std::thread::spawn(move || reprocessor.run(recordings));
+        true
+    }
+
+    fn run(&self, recordings: Vec<Arc<str>>) {
+        for name in recordings {
+            // A recording that makes an analyser panic shouldn't stop the rest being reprocessed
+            let replayed = panic::catch_unwind(|| replay(&name))
+                .unwrap_or_else(|_| Err(anyhow::anyhow!("An analyser panicked")));
+            match replayed {
+                Ok(Replay { suspicion, kinds }) => {
+                    let demo = Path::new(&*name).with_extension("dem");
+                    let mut report =
+                        MatchReport::new(demo.to_string_lossy().into(), None, None, suspicion);
+                    report.recording = Some(name);
+                    // Only the original demo could tell when each tick was recorded
+                    if let Some(clock) = MatchReport::load(&report.demo)
+                        .ok()
+                        .and_then(|original| original.clock)
+                    {
+                        report.stamp(clock);
+                    }
+                    if self
+                        .event_send
+                        .send(DemoEvent::Reprocessed { report, kinds })
+                        .is_err()
+                    {
+                        tracing::warn!("Lost connection to main thread, stopping reprocessing.");
+                        break;
+                    }
+                }
+                Err(e) => {
+                    tracing::warn!("Failed to reprocess recording {}: {:?}", name, e);
+                    self.status.lock().unwrap().failed += 1;
+                }
+            }
+            self.status.lock().unwrap().done += 1;
+        }
+
+        let mut status = self.status.lock().unwrap();
+        status.running = false;
+        status.finished = Some(now());
+        tracing::info!(
+            "Finished reprocessing {} recordings ({} failed)",
+            status.done,
+            status.failed
+        );
+    }
+}
+
+/// Run a fresh set of the default analysers over the recording with the given file name
+fn replay(name: &str) -> anyhow::Result<Replay> {
+    let path = Recorder::locate_recordings_directory()?.join(name);
+    analyse_recording(&path)
+}
+
+/// Run a fresh set of the default analysers over the recording at `path`, wherever it is. Used by
+/// the heuristic test corpus as well as reprocessing.
+pub fn analyse_recording(path: &Path) -> anyhow::Result<Replay> {
+    let events = recording::read_recording(path)?;
+    let mut analysers: Vec<Box<dyn DemoAnalyserPlugin>> = analyser::default_analysers()
+        .iter()
+        .map(|create| create())
+        .collect();
+
+    for event in &events {
+        for analyser in &mut analysers {
+            analyser.on_recorded_event(event);
+        }
+    }
+
+    let mut replay = Replay::default();
+    for analyser in &mut analysers {
+        replay.kinds.extend(analyser.replayed_kinds());
+        for flag in analyser.finalize() {
+            // Anything else was raised without seeing the whole demo
+            if analyser.replayed_kinds().contains(&flag.kind) {
+                replay.suspicion.add_flag(flag);
+            }
+        }
+    }
+    Ok(replay)
+}
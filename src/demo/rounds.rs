@@ -0,0 +1,118 @@
+use serde::{Deserialize, Serialize};
+use tf_demo_parser::demo::{
+    gamevent::GameEvent,
+    message::{gameevent::GameEventMessage, Message},
+    packet::{message::MessagePacket, Packet},
+};
+
+use super::clock::TickClock;
+use crate::player::Team;
+
+/// A round that ended during a match
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RoundResult {
+    /// Tick the round ended on
+    pub tick: u32,
+    /// When the round ended, in ISO 8601, if it's known when the demo was recorded
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub time: Option<String>,
+    /// The team that won the round, or `None` for a stalemate
+    pub winner: Option<Team>,
+    /// How long the round lasted (seconds)
+    pub duration: f32,
+    /// Whether the round counted towards the score, rather than being one stage of a multi-stage
+    /// map like Dustbowl
+    pub full_round: bool,
+    pub sudden_death: bool,
+    /// Score after the round
+    pub red_score: u32,
+    pub blue_score: u32,
+}
+
+/// The score of a match and the rounds that led to it
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MatchResult {
+    pub red_score: u32,
+    pub blue_score: u32,
+    /// Whether the match was played to the end, rather than the demo stopping partway through,
+    /// e.g. because the user left
+    pub finished: bool,
+    pub rounds: Vec<RoundResult>,
+}
+
+impl MatchResult {
+    /// Give the end of each round a wall-clock time
+    pub fn stamp(&mut self, clock: &TickClock) {
+        for round in &mut self.rounds {
+            round.time = Some(clock.iso8601(round.tick));
+        }
+    }
+}
+
+/// Keeps track of the rounds and score over the course of a demo
+#[derive(Debug, Default)]
+pub struct RoundTracker {
+    result: MatchResult,
+}
+
+impl RoundTracker {
+    /// Follow the round and game over events in `packet`
+    pub fn on_packet(&mut self, packet: &Packet) {
+        let Packet::Message(MessagePacket { tick, messages, .. }) = packet else {
+            return;
+        };
+
+        for m in messages {
+            let Message::GameEvent(GameEventMessage { event, .. }) = m else {
+                continue;
+            };
+            match event {
+                GameEvent::TeamPlayRoundWin(win) => {
+                    let winner = Team::try_from(u32::from(win.team))
+                        .ok()
+                        .filter(|team| matches!(team, Team::Red | Team::Blu));
+                    let full_round = win.full_round != 0;
+                    // The win panel has the real score, but isn't shown after every round (e.g. in
+                    // arena), so keep count in the meantime
+                    match winner {
+                        Some(Team::Red) if full_round => self.result.red_score += 1,
+                        Some(Team::Blu) if full_round => self.result.blue_score += 1,
+                        _ => {}
+                    }
+                    self.result.rounds.push(RoundResult {
+                        tick: u32::from(*tick),
+                        time: None,
+                        winner,
+                        duration: win.round_time,
+                        full_round,
+                        sudden_death: win.was_sudden_death != 0,
+                        red_score: self.result.red_score,
+                        blue_score: self.result.blue_score,
+                    });
+                }
+                GameEvent::TeamPlayWinPanel(panel) => {
+                    self.result.red_score = u32::from(panel.red_score);
+                    self.result.blue_score = u32::from(panel.blue_score);
+                    if let Some(round) = self.result.rounds.last_mut() {
+                        round.red_score = self.result.red_score;
+                        round.blue_score = self.result.blue_score;
+                    }
+                    if panel.game_over != 0 {
+                        self.result.finished = true;
+                    }
+                }
+                GameEvent::TeamPlayGameOver(_) | GameEvent::TfGameOver(_) => {
+                    self.result.finished = true;
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// The score and rounds so far, or `None` if no round has ended yet
+    pub fn result(&self) -> Option<MatchResult> {
+        Some(self.result.clone()).filter(|result| !result.rounds.is_empty() || result.finished)
+    }
+}
@@ -0,0 +1,209 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, RwLock},
+    time::{Duration, SystemTime},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    analytics::history::now,
+    events::{self, SystemEventLevel},
+    locale::tr,
+    settings::Settings,
+};
+
+/// How often the free space on the demo volume is checked
+const CHECK_INTERVAL: Duration = Duration::from_secs(60);
+/// Demos modified more recently than this may still be being recorded, so are never deleted
+const MIN_DEMO_AGE: Duration = Duration::from_secs(10 * 60);
+const MIB: u64 = 1024 * 1024;
+
+/// The result of the most recent check, for the web API
+static LATEST: Mutex<Option<DemoSpace>> = Mutex::new(None);
+
+/// How much free space the demo volume should have, and what may be done to keep it free
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct DemoStorage {
+    /// Free space (MiB) below which the user is warned
+    pub low_space_mb: u64,
+    /// Free space (MiB) below which a demo could run out of room part way through a match
+    pub critical_space_mb: u64,
+    /// Demos older than this many days are deleted, or kept forever if 0
+    pub retention_days: u64,
+    /// Once space is critical, delete the oldest demos (even those inside the retention period)
+    /// until it is no longer low
+    pub clean_up_when_critical: bool,
+}
+
+impl Default for DemoStorage {
+    fn default() -> Self {
+        DemoStorage {
+            low_space_mb: 2048,
+            critical_space_mb: 512,
+            retention_days: 0,
+            clean_up_when_critical: false,
+        }
+    }
+}
+
+impl DemoStorage {
+    fn level(&self, available: u64) -> SpaceLevel {
+        if available < self.critical_space_mb * MIB {
+            SpaceLevel::Critical
+        } else if available < self.low_space_mb * MIB {
+            SpaceLevel::Low
+        } else {
+            SpaceLevel::Ok
+        }
+    }
+
+    fn retention(&self) -> Option<Duration> {
+        (self.retention_days > 0).then(|| Duration::from_secs(self.retention_days * 24 * 60 * 60))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum SpaceLevel {
+    Ok,
+    Low,
+    Critical,
+}
+
+/// Free space on the volume demos are recorded to
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DemoSpace {
+    /// Unix timestamp (seconds) of the check
+    pub checked: u64,
+    /// Bytes
+    pub available: u64,
+    pub total: u64,
+    pub level: SpaceLevel,
+}
+
+/// The free space found by the last check, if there has been one
+pub fn latest() -> Option<DemoSpace> {
+    LATEST.lock().unwrap().clone()
+}
+
+fn check(dir: &Path, storage: &DemoStorage) -> std::io::Result<DemoSpace> {
+    let available = fs2::available_space(dir)?;
+    Ok(DemoSpace {
+        checked: now(),
+        available,
+        total: fs2::total_space(dir)?,
+        level: storage.level(available),
+    })
+}
+
+/// Demos in `dir` that are old enough to delete, with when they were last modified and their
+/// size, oldest first
+fn deletable_demos(dir: &Path) -> Vec<(PathBuf, SystemTime, u64)> {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    let Some(cutoff) = SystemTime::now().checked_sub(MIN_DEMO_AGE) else {
+        return Vec::new();
+    };
+
+    let mut demos: Vec<_> = entries
+        .filter_map(Result::ok)
+        .filter(|entry| entry.path().extension().is_some_and(|ext| ext == "dem"))
+        .filter_map(|entry| {
+            let metadata = entry.metadata().ok()?;
+            let modified = metadata.modified().ok()?;
+            (modified < cutoff).then(|| (entry.path(), modified, metadata.len()))
+        })
+        .collect();
+    demos.sort_by_key(|(_, modified, _)| *modified);
+    demos
+}
+
+/// Delete the demos in `dir` that are past the retention period, then the oldest of the rest
+/// until at least `free_up` bytes have been freed. Returns how many demos were deleted and how
+/// many bytes that freed.
+fn apply_retention(dir: &Path, storage: &DemoStorage, free_up: u64) -> (usize, u64) {
+    let expired = storage
+        .retention()
+        .and_then(|retention| SystemTime::now().checked_sub(retention));
+    if expired.is_none() && free_up == 0 {
+        return (0, 0);
+    }
+
+    let mut deleted = 0;
+    let mut freed = 0;
+    for (path, modified, size) in deletable_demos(dir) {
+        // Demos are oldest first, so once one is inside the retention period the rest are too
+        let past_retention = expired.is_some_and(|expired| modified < expired);
+        if !past_retention && freed >= free_up {
+            break;
+        }
+        match fs::remove_file(&path) {
+            Ok(()) => {
+                tracing::info!("Deleted old demo {:?}", path);
+                deleted += 1;
+                freed += size;
+            }
+            Err(e) => tracing::warn!("Failed to delete old demo {:?}: {:?}", path, e),
+        }
+    }
+    (deleted, freed)
+}
+
+/// Periodically check the free space on the volume `dir` is on, alerting the user when it runs
+/// low, and apply the demo retention policy. Running out of space part way through a match would
+/// silently lose the rest of its demo. This will never return, so it should be spawned in a
+/// separate `tokio::task`.
+pub async fn demo_space_loop(dir: PathBuf, settings: Arc<RwLock<Settings>>) {
+    let mut interval = tokio::time::interval(CHECK_INTERVAL);
+    let mut alerted = SpaceLevel::Ok;
+    loop {
+        interval.tick().await;
+
+        let storage = settings.read().unwrap().get_demo_storage();
+        let mut space = match check(&dir, &storage) {
+            Ok(space) => space,
+            Err(e) => {
+                tracing::debug!("Could not check free space for demos in {:?}: {:?}", dir, e);
+                continue;
+            }
+        };
+
+        let free_up = if space.level == SpaceLevel::Critical && storage.clean_up_when_critical {
+            (storage.low_space_mb * MIB).saturating_sub(space.available)
+        } else {
+            0
+        };
+        let (deleted, freed) = apply_retention(&dir, &storage, free_up);
+        if deleted > 0 {
+            if free_up > 0 {
+                events::emit_system(
+                    SystemEventLevel::Info,
+                    tr(
+                        "demo-space-cleaned",
+                        &[("count", &deleted), ("freed", &(freed / MIB))],
+                    ),
+                );
+            }
+            space = check(&dir, &storage).unwrap_or(space);
+        }
+
+        if space.level > alerted {
+            let (level, key) = match space.level {
+                SpaceLevel::Critical => (SystemEventLevel::Error, "demo-space-critical"),
+                _ => (SystemEventLevel::Warning, "demo-space-low"),
+            };
+            let dir = dir.to_string_lossy();
+            events::emit_system(
+                level,
+                tr(key, &[("free", &(space.available / MIB)), ("dir", &dir)]),
+            );
+        }
+        alerted = space.level;
+        *LATEST.lock().unwrap() = Some(space);
+    }
+}
@@ -0,0 +1,189 @@
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+};
+
+use serde::{Deserialize, Serialize};
+
+use super::clock::TickClock;
+use crate::{locale::tr, steamid::SteamID};
+
+/// The kinds of suspicious behaviour that demo analysers can flag a player for
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum SuspicionKind {
+    /// Voice sent nearly without a break, like a bot looping a sound
+    VoiceSpam,
+    /// Nearly every kill a headshot over a match spent on one class
+    Headshots,
+}
+
+impl SuspicionKind {
+    /// Plain-language explanation of what the flag means, suitable for a report
+    pub fn description(&self) -> String {
+        let key = match self {
+            SuspicionKind::VoiceSpam => "suspicion-voice-spam",
+            SuspicionKind::Headshots => "suspicion-headshots",
+        };
+        tr(key, &[])
+    }
+}
+
+/// A single flag raised against a player by a demo analyser
+#[derive(Debug, Clone)]
+pub struct SuspicionFlag {
+    pub steamid: SteamID,
+    pub kind: SuspicionKind,
+    pub tick: u32,
+    /// How sure the analyser is, between 0 and 1
+    pub confidence: f32,
+}
+
+/// Counts and peak confidence for one kind of flag
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SuspicionCount {
+    pub count: u32,
+    pub peak_confidence: f32,
+}
+
+/// Every flag raised against a single player over the course of one match
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PlayerSuspicion {
+    pub flags: HashMap<SuspicionKind, SuspicionCount>,
+    pub total_flags: u32,
+    pub peak_confidence: f32,
+    /// Tick of the first flag in the demo
+    #[serde(default)]
+    pub first_tick: u32,
+    /// Tick of the last flag in the demo
+    #[serde(default)]
+    pub last_tick: u32,
+    /// When the first flag was raised, in ISO 8601, if it's known when the demo was recorded
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub first_time: Option<String>,
+    /// When the last flag was raised, in ISO 8601
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_time: Option<String>,
+}
+
+impl PlayerSuspicion {
+    fn add(&mut self, kind: SuspicionKind, tick: u32, confidence: f32) {
+        let confidence = confidence.clamp(0.0, 1.0);
+
+        if self.total_flags == 0 {
+            self.first_tick = tick;
+            self.last_tick = tick;
+        } else {
+            self.first_tick = self.first_tick.min(tick);
+            self.last_tick = self.last_tick.max(tick);
+        }
+
+        let count = self.flags.entry(kind).or_default();
+        count.count += 1;
+        count.peak_confidence = count.peak_confidence.max(confidence);
+
+        self.total_flags += 1;
+        self.peak_confidence = self.peak_confidence.max(confidence);
+    }
+
+    /// Swap the flags of `kinds` for the ones in `replacement`, keeping flags of any other kind.
+    /// Returns whether any flags are left.
+    pub fn replace_kinds(
+        &mut self,
+        kinds: &HashSet<SuspicionKind>,
+        replacement: Option<&PlayerSuspicion>,
+    ) -> bool {
+        self.flags.retain(|kind, _| !kinds.contains(kind));
+        if let Some(new) = replacement {
+            if self.flags.is_empty() {
+                self.first_tick = new.first_tick;
+                self.first_time = new.first_time.clone();
+                self.last_tick = new.last_tick;
+                self.last_time = new.last_time.clone();
+            } else {
+                if new.first_tick < self.first_tick {
+                    self.first_tick = new.first_tick;
+                    self.first_time = new.first_time.clone();
+                }
+                if new.last_tick > self.last_tick {
+                    self.last_tick = new.last_tick;
+                    self.last_time = new.last_time.clone();
+                }
+            }
+            self.flags.extend(
+                new.flags
+                    .iter()
+                    .filter(|(kind, _)| kinds.contains(kind))
+                    .map(|(kind, count)| (*kind, *count)),
+            );
+        }
+
+        self.total_flags = self.flags.values().map(|c| c.count).sum();
+        self.peak_confidence = self
+            .flags
+            .values()
+            .map(|c| c.peak_confidence)
+            .fold(0.0, f32::max);
+        !self.flags.is_empty()
+    }
+}
+
+/// Aggregates the flags raised by all analysers over a match into one summary per player
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct MatchSuspicion {
+    players: HashMap<SteamID, PlayerSuspicion>,
+}
+
+impl MatchSuspicion {
+    pub fn new() -> MatchSuspicion {
+        MatchSuspicion::default()
+    }
+
+    pub fn add_flag(&mut self, flag: SuspicionFlag) {
+        tracing::debug!(
+            "{} flagged for {:?} at tick {} ({:.2})",
+            flag.steamid,
+            flag.kind,
+            flag.tick,
+            flag.confidence
+        );
+        self.players
+            .entry(flag.steamid)
+            .or_default()
+            .add(flag.kind, flag.tick, flag.confidence);
+    }
+
+    pub fn get(&self, steamid: &SteamID) -> Option<&PlayerSuspicion> {
+        self.players.get(steamid)
+    }
+
+    pub fn players(&self) -> &HashMap<SteamID, PlayerSuspicion> {
+        &self.players
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.players.is_empty()
+    }
+
+    /// Give the first and last flag of each player a wall-clock time
+    pub fn stamp(&mut self, clock: &TickClock) {
+        for suspicion in self.players.values_mut() {
+            suspicion.first_time = Some(clock.iso8601(suspicion.first_tick));
+            suspicion.last_time = Some(clock.iso8601(suspicion.last_tick));
+        }
+    }
+}
+
+/// The suspicion summary for one match, as stored in a player's long-term record
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EncounterSuspicion {
+    /// Unix timestamp (seconds) of when the match report was produced
+    pub time: u64,
+    pub demo: Arc<str>,
+    pub map: Option<Arc<str>>,
+    #[serde(flatten)]
+    pub suspicion: PlayerSuspicion,
+}
@@ -0,0 +1,150 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+use tf_demo_parser::demo::{
+    header::Header,
+    message::Message,
+    packet::{message::MessagePacket, Packet},
+    parser::gamestateanalyser::GameState,
+};
+
+use super::{
+    analyser::DemoAnalyserPlugin,
+    clock::{self, DEFAULT_TICK_RATE},
+    report::MatchReport,
+    suspicion::{SuspicionFlag, SuspicionKind},
+};
+use crate::steamid::SteamID;
+
+/// Seconds without voice that still count as talking without a break, as voice is only sent
+/// while there is something to send
+const STREAK_GAP: u32 = 2;
+/// Longest a player can talk without a break before it looks like a bot looping a sound. People
+/// rarely talk for more than half a minute at a time.
+const SPAM_STREAK: u32 = 90;
+
+/// How much a player used voice chat over a match. Only whether voice was sent is looked at,
+/// never what was said.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VoiceActivity {
+    pub packets: u32,
+    /// Seconds in which the player sent any voice
+    pub seconds_speaking: u32,
+    /// Longest stretch (seconds) the player talked without a break
+    pub longest_streak: u32,
+    /// Tick of the longest stretch's start
+    pub longest_streak_tick: u32,
+    /// When the longest stretch started, in ISO 8601, if it's known when the demo was recorded
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub longest_streak_time: Option<String>,
+    #[serde(skip)]
+    last_second: Option<u32>,
+    #[serde(skip)]
+    streak_start: u32,
+    #[serde(skip)]
+    streak_start_tick: u32,
+}
+
+impl VoiceActivity {
+    /// Count voice sent on `tick`, with `tick_rate` ticks in a second
+    fn add(&mut self, tick: u32, tick_rate: f32) {
+        self.packets += 1;
+        let second = (tick as f32 / tick_rate) as u32;
+        match self.last_second {
+            Some(last) if last == second => return,
+            Some(last) if second <= last + STREAK_GAP => {}
+            _ => {
+                self.streak_start = second;
+                self.streak_start_tick = tick;
+            }
+        }
+        self.last_second = Some(second);
+        self.seconds_speaking += 1;
+
+        let streak = second - self.streak_start + 1;
+        if streak > self.longest_streak {
+            self.longest_streak = streak;
+            self.longest_streak_tick = self.streak_start_tick;
+        }
+    }
+}
+
+/// Keeps track of which players send voice over the course of a demo, flagging those who send it
+/// nearly without a break. Voice isn't kept in recordings, so this only runs on demos.
+#[derive(Debug)]
+pub struct VoiceTracker {
+    players: HashMap<SteamID, VoiceActivity>,
+    /// Ticks in a second, once the demo has said
+    tick_rate: f32,
+}
+
+impl Default for VoiceTracker {
+    fn default() -> Self {
+        VoiceTracker {
+            players: HashMap::new(),
+            tick_rate: DEFAULT_TICK_RATE,
+        }
+    }
+}
+
+impl DemoAnalyserPlugin for VoiceTracker {
+    fn name(&self) -> &'static str {
+        "voice"
+    }
+
+    fn on_header(&mut self, header: &Header) {
+        if let Some(rate) = clock::header_tick_rate(header) {
+            self.tick_rate = rate;
+        }
+    }
+
+    /// Count the voice data in `packet`, with the players sending it looked up in `state`
+    fn on_packet(&mut self, packet: &Packet, state: &GameState) {
+        if let Some(rate) = clock::packet_tick_rate(packet) {
+            self.tick_rate = rate;
+        }
+        let Packet::Message(MessagePacket { tick, messages, .. }) = packet else {
+            return;
+        };
+
+        for m in messages {
+            let Message::VoiceData(voice) = m else {
+                continue;
+            };
+            // Voice is sent with the client's slot, which is one less than its entity
+            let steamid = state
+                .players
+                .iter()
+                .filter_map(|p| p.info.as_ref())
+                .find(|info| u32::from(info.entity_id) == voice.client as u32 + 1)
+                .and_then(|info| info.steam_id.parse::<SteamID>().ok());
+            if let Some(steamid) = steamid {
+                self.players
+                    .entry(steamid)
+                    .or_default()
+                    .add(u32::from(*tick), self.tick_rate);
+            }
+        }
+    }
+
+    /// Flag players whose voice went on nearly without a break for longer than anyone talks,
+    /// which is how bots playing looping voice spam give themselves away
+    fn finalize(&mut self) -> Vec<SuspicionFlag> {
+        self.players
+            .iter()
+            .filter(|(_, activity)| activity.longest_streak >= SPAM_STREAK)
+            .map(|(steamid, activity)| SuspicionFlag {
+                steamid: *steamid,
+                kind: SuspicionKind::VoiceSpam,
+                tick: activity.longest_streak_tick,
+                confidence: (activity.longest_streak as f32 / (2 * SPAM_STREAK) as f32)
+                    .clamp(0.5, 1.0),
+            })
+            .collect()
+    }
+
+    fn report(&self, report: &mut MatchReport) {
+        report.voice = self.players.clone();
+    }
+}
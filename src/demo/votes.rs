@@ -0,0 +1,169 @@
+use std::{collections::HashMap, sync::Arc};
+
+use serde::{Deserialize, Serialize};
+use tf_demo_parser::demo::{
+    gamevent::GameEvent,
+    message::{gameevent::GameEventMessage, Message},
+    packet::{message::MessagePacket, Packet},
+    parser::gamestateanalyser::GameState,
+};
+
+use crate::steamid::SteamID;
+
+/// What a player voted on a kick. The vote panel is anonymous in casual, but the demo still
+/// records each vote with the entity that cast it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Ballot {
+    /// F1, to kick the target
+    Yes,
+    /// F2, to keep the target
+    No,
+}
+
+/// A kick vote held during a match, and how each player voted in it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct KickVote {
+    /// Tick the vote was called on
+    pub tick: u32,
+    /// When the vote was called, in ISO 8601, if it's known when the demo was recorded
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub time: Option<String>,
+    /// The player being kicked, if they could be found by name
+    pub target: Option<SteamID>,
+    pub target_name: Arc<str>,
+    pub caller: Option<SteamID>,
+    /// Whether the vote passed, or `None` if the demo ended before it did either
+    pub passed: Option<bool>,
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub ballots: HashMap<SteamID, Ballot>,
+}
+
+/// A kick vote that was called, voted in or finished since it was last passed on, as it now
+/// stands
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct KickVoteUpdate {
+    #[serde(flatten)]
+    pub vote: KickVote,
+    /// Whether the vote is over, including when it was cut short without passing or failing
+    pub finished: bool,
+}
+
+/// Keeps track of the kick votes over the course of a demo. This is the only place votes are read
+/// from demos: the match report gets every vote from [VoteTracker::votes], and the rest of the
+/// client follows them as they happen through [VoteTracker::take_updates].
+#[derive(Debug, Default)]
+pub struct VoteTracker {
+    current: Option<KickVote>,
+    finished: Vec<KickVote>,
+    /// Whether the current vote has changed since updates were last taken
+    current_changed: bool,
+    /// How many of the finished votes updates have been taken for
+    finished_taken: usize,
+}
+
+impl VoteTracker {
+    /// Follow the vote events in `packet`, with the players involved looked up in `state`
+    pub fn on_packet(&mut self, packet: &Packet, state: &GameState) {
+        let Packet::Message(MessagePacket { tick, messages, .. }) = packet else {
+            return;
+        };
+        let by_entity = |entity: u32| {
+            state
+                .players
+                .iter()
+                .filter_map(|p| p.info.as_ref())
+                .find(|info| u32::from(info.entity_id) == entity)
+                .and_then(|info| info.steam_id.parse::<SteamID>().ok())
+        };
+
+        for m in messages {
+            let Message::GameEvent(GameEventMessage { event, .. }) = m else {
+                continue;
+            };
+            match event {
+                GameEvent::VoteStarted(started) => {
+                    self.close(None);
+                    // Only kicks name a player, other votes (e.g. changing map) aren't of interest
+                    if !started.issue.as_ref().contains("kick") {
+                        continue;
+                    }
+                    let target_name = started.param_1.as_ref();
+                    self.current_changed = true;
+                    self.current = Some(KickVote {
+                        tick: u32::from(*tick),
+                        time: None,
+                        target: state
+                            .players
+                            .iter()
+                            .filter_map(|p| p.info.as_ref())
+                            .find(|info| info.name == target_name)
+                            .and_then(|info| info.steam_id.parse::<SteamID>().ok()),
+                        target_name: target_name.into(),
+                        caller: by_entity(started.initiator),
+                        passed: None,
+                        ballots: HashMap::new(),
+                    });
+                }
+                GameEvent::VoteCast(cast) => {
+                    let (Some(vote), Some(voter)) = (&mut self.current, by_entity(cast.entity_id))
+                    else {
+                        continue;
+                    };
+                    let ballot = match cast.vote_option {
+                        0 => Ballot::Yes,
+                        1 => Ballot::No,
+                        _ => continue,
+                    };
+                    vote.ballots.insert(voter, ballot);
+                    self.current_changed = true;
+                }
+                GameEvent::VotePassed(_) => self.close(Some(true)),
+                GameEvent::VoteFailed(_) => self.close(Some(false)),
+                _ => {}
+            }
+        }
+    }
+
+    fn close(&mut self, passed: Option<bool>) {
+        if let Some(mut vote) = self.current.take() {
+            vote.passed = passed;
+            self.finished.push(vote);
+        }
+        self.current_changed = false;
+    }
+
+    /// The kick votes that have been called, voted in or finished since this was last called, in
+    /// the order they were called
+    pub fn take_updates(&mut self) -> Vec<KickVoteUpdate> {
+        let finished = self.finished[self.finished_taken..]
+            .iter()
+            .map(|vote| KickVoteUpdate {
+                vote: vote.clone(),
+                finished: true,
+            });
+        let current = self
+            .current
+            .as_ref()
+            .filter(|_| self.current_changed)
+            .map(|vote| KickVoteUpdate {
+                vote: vote.clone(),
+                finished: false,
+            });
+        let updates = finished.chain(current).collect();
+        self.finished_taken = self.finished.len();
+        self.current_changed = false;
+        updates
+    }
+
+    /// Every kick vote so far, including one that is still going
+    pub fn votes(&self) -> Vec<KickVote> {
+        self.finished
+            .iter()
+            .chain(self.current.as_ref())
+            .cloned()
+            .collect()
+    }
+}
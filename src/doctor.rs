@@ -0,0 +1,371 @@
+//! A self-test of everything the client needs to work: the game directory, launch options,
+//! console log, RCON, the Steam API key, the web UI port and the demo directory. It is run with
+//! `client-backend doctor`, which prints a report and exits, or through the web API while the
+//! client is running.
+
+use std::{
+    fs::{File, OpenOptions},
+    io::{ErrorKind, IsTerminal},
+    net::{SocketAddr, TcpListener},
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+
+use rcon::Connection;
+use serde::Serialize;
+use tokio::{net::TcpStream, time::timeout};
+
+use crate::{
+    gameprofile::GameProfile,
+    launchoptions::{LaunchOptions, TF2_REQUIRED_OPTS},
+    locale::tr,
+    settings::Settings,
+    steamapi::{self, connection::SteamConnection},
+    steamid::SteamID,
+};
+
+/// The same as the IO manager waits to connect, see [crate::io::command_manager]
+const RCON_TIMEOUT: Duration = Duration::from_millis(2500);
+/// Written to the demo directory to check it can be written to, then removed
+const PROBE_FILE: &str = ".mac_doctor";
+
+const GREEN: &str = "\x1b[32m";
+const YELLOW: &str = "\x1b[33m";
+const RED: &str = "\x1b[31m";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum CheckStatus {
+    Pass,
+    /// Not working yet, but likely fine, e.g. the game isn't running
+    Warn,
+    Fail,
+}
+
+/// How one part of the client is doing, and what to do about it if it isn't working
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CheckResult {
+    pub check: &'static str,
+    pub status: CheckStatus,
+    pub message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub remedy: Option<String>,
+}
+
+impl CheckResult {
+    fn pass(check: &'static str, message: String) -> CheckResult {
+        CheckResult {
+            check,
+            status: CheckStatus::Pass,
+            message,
+            remedy: None,
+        }
+    }
+
+    fn warn(check: &'static str, message: String, remedy: String) -> CheckResult {
+        CheckResult {
+            check,
+            status: CheckStatus::Warn,
+            message,
+            remedy: Some(remedy),
+        }
+    }
+
+    fn fail(check: &'static str, message: String, remedy: String) -> CheckResult {
+        CheckResult {
+            check,
+            status: CheckStatus::Fail,
+            message,
+            remedy: Some(remedy),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DoctorReport {
+    pub passed: bool,
+    pub checks: Vec<CheckResult>,
+}
+
+impl DoctorReport {
+    /// Print the report to the terminal, coloured by how each check went. Colours are left out
+    /// when the output isn't a terminal (e.g. it is piped to a file), or `NO_COLOR` is set.
+    pub fn print(&self) {
+        let coloured = std::io::stdout().is_terminal() && std::env::var_os("NO_COLOR").is_none();
+        let paint = |colour: &str, text: &str| {
+            if coloured {
+                format!("{}{}\x1b[0m", colour, text)
+            } else {
+                text.to_string()
+            }
+        };
+
+        for check in &self.checks {
+            let (colour, label) = match check.status {
+                CheckStatus::Pass => (GREEN, "PASS"),
+                CheckStatus::Warn => (YELLOW, "WARN"),
+                CheckStatus::Fail => (RED, "FAIL"),
+            };
+            println!(
+                "{} {}",
+                paint(colour, &format!("[{}]", label)),
+                check.message
+            );
+            if let Some(remedy) = &check.remedy {
+                println!("       -> {}", remedy);
+            }
+        }
+
+        let failed = self
+            .checks
+            .iter()
+            .filter(|c| c.status == CheckStatus::Fail)
+            .count();
+        println!();
+        if failed == 0 {
+            println!("{}", paint(GREEN, &tr("doctor-summary-pass", &[])));
+        } else {
+            println!(
+                "{}",
+                paint(RED, &tr("doctor-summary-fail", &[("count", &failed)]))
+            );
+        }
+    }
+}
+
+/// Everything the checks need from the settings, so they can run without holding on to them
+pub struct Checkup {
+    tf2_directory: PathBuf,
+    game_profile: GameProfile,
+    steam_user: Option<SteamID>,
+    rcon_port: u16,
+    rcon_password: Arc<str>,
+    api_key: Arc<str>,
+    steam_connection: SteamConnection,
+    webui_port: u16,
+    /// Whether this is being run by the client's own web API, which will be using the port
+    web_running: bool,
+}
+
+impl Checkup {
+    pub fn new(settings: &Settings, web_running: bool) -> Checkup {
+        Checkup {
+            tf2_directory: settings.get_tf2_directory().to_path_buf(),
+            game_profile: settings.get_game_profile(),
+            steam_user: settings.get_steam_user(),
+            rcon_port: settings.get_rcon_port(),
+            rcon_password: settings.get_rcon_password(),
+            api_key: settings.get_steam_api_key(),
+            steam_connection: settings.get_steam_connection(),
+            webui_port: settings.get_webui_port(),
+            web_running,
+        }
+    }
+
+    /// Run every check
+    pub async fn run(&self) -> DoctorReport {
+        let checks = vec![
+            self.check_game_directory(),
+            self.check_launch_options(),
+            self.check_console_log(),
+            self.check_rcon().await,
+            self.check_api_key().await,
+            self.check_web_port(),
+            self.check_demo_directory(),
+        ];
+        DoctorReport {
+            passed: checks.iter().all(|c| c.status != CheckStatus::Fail),
+            checks,
+        }
+    }
+
+    fn check_game_directory(&self) -> CheckResult {
+        const CHECK: &str = "gameDirectory";
+        let path = self.tf2_directory.display();
+        let game_dir = self.tf2_directory.join(&self.game_profile.game_dir);
+        if game_dir.is_dir() {
+            CheckResult::pass(CHECK, tr("doctor-game-dir-ok", &[("path", &path)]))
+        } else {
+            CheckResult::fail(
+                CHECK,
+                tr("doctor-game-dir-missing", &[("path", &path)]),
+                tr("doctor-game-dir-fix", &[]),
+            )
+        }
+    }
+
+    fn check_launch_options(&self) -> CheckResult {
+        const CHECK: &str = "launchOptions";
+        let options = TF2_REQUIRED_OPTS.join(" ");
+        let Some(appid) = self.game_profile.appid else {
+            // Sourcemods aren't Steam apps, so there are no launch options to check
+            return CheckResult::warn(
+                CHECK,
+                tr(
+                    "doctor-launch-opts-unknown",
+                    &[("game", &self.game_profile.name)],
+                ),
+                tr("doctor-launch-opts-unknown-fix", &[("options", &options)]),
+            );
+        };
+        let Some(user) = self.steam_user else {
+            return CheckResult::fail(
+                CHECK,
+                tr("doctor-steam-user-missing", &[]),
+                tr("doctor-steam-user-fix", &[]),
+            );
+        };
+
+        let missing = LaunchOptions::new(user, appid)
+            .and_then(|opts| opts.check_missing_args().map(|missing| missing.join(" ")));
+        match missing {
+            Ok(missing) if missing.is_empty() => {
+                CheckResult::pass(CHECK, tr("doctor-launch-opts-ok", &[]))
+            }
+            Ok(missing) => CheckResult::fail(
+                CHECK,
+                tr("doctor-launch-opts-missing", &[("options", &missing)]),
+                tr("doctor-launch-opts-fix", &[]),
+            ),
+            Err(e) => CheckResult::fail(
+                CHECK,
+                tr("doctor-launch-opts-error", &[("error", &e)]),
+                tr("doctor-launch-opts-unknown-fix", &[("options", &options)]),
+            ),
+        }
+    }
+
+    fn check_console_log(&self) -> CheckResult {
+        const CHECK: &str = "consoleLog";
+        let log = self.game_profile.log_file_path(&self.tf2_directory);
+        let path = log.display();
+        match File::open(&log) {
+            Ok(_) => CheckResult::pass(CHECK, tr("doctor-log-ok", &[("path", &path)])),
+            // The game only creates it once it has been launched with -condebug
+            Err(e) if e.kind() == ErrorKind::NotFound => CheckResult::warn(
+                CHECK,
+                tr("doctor-log-missing", &[("path", &path)]),
+                tr("doctor-log-missing-fix", &[]),
+            ),
+            Err(e) => CheckResult::fail(
+                CHECK,
+                tr("doctor-log-error", &[("path", &path), ("error", &e)]),
+                tr("doctor-log-fix", &[]),
+            ),
+        }
+    }
+
+    async fn check_rcon(&self) -> CheckResult {
+        const CHECK: &str = "rcon";
+        let port = self.rcon_port;
+        let connection = timeout(
+            RCON_TIMEOUT,
+            Connection::<TcpStream>::connect(format!("127.0.0.1:{}", port), &self.rcon_password),
+        )
+        .await;
+        match connection {
+            Ok(Ok(_)) => CheckResult::pass(CHECK, tr("doctor-rcon-ok", &[("port", &port)])),
+            Ok(Err(rcon::Error::Auth)) => CheckResult::fail(
+                CHECK,
+                tr("doctor-rcon-auth", &[("port", &port)]),
+                tr("doctor-rcon-auth-fix", &[]),
+            ),
+            // Most likely the game just isn't running
+            Ok(Err(e)) => CheckResult::warn(
+                CHECK,
+                tr("doctor-rcon-unreachable", &[("port", &port), ("error", &e)]),
+                tr("doctor-rcon-unreachable-fix", &[]),
+            ),
+            Err(e) => CheckResult::warn(
+                CHECK,
+                tr("doctor-rcon-unreachable", &[("port", &port), ("error", &e)]),
+                tr("doctor-rcon-unreachable-fix", &[]),
+            ),
+        }
+    }
+
+    async fn check_api_key(&self) -> CheckResult {
+        const CHECK: &str = "steamApiKey";
+        if !steamapi::is_api_key_valid(&self.api_key) {
+            return CheckResult::fail(
+                CHECK,
+                tr("doctor-api-key-invalid", &[]),
+                tr("doctor-api-key-fix", &[]),
+            );
+        }
+
+        let verified = match self.steam_connection.client() {
+            Ok(http) => {
+                let api_url = self.steam_connection.api_url();
+                steamapi::verify_api_key(&http, &api_url, &self.api_key).await
+            }
+            Err(e) => Err(e),
+        };
+        match verified {
+            Ok(true) => CheckResult::pass(CHECK, tr("doctor-api-key-ok", &[])),
+            Ok(false) => CheckResult::fail(
+                CHECK,
+                tr("doctor-api-key-rejected", &[]),
+                tr("doctor-api-key-fix", &[]),
+            ),
+            Err(e) => CheckResult::warn(
+                CHECK,
+                tr("doctor-api-key-unreachable", &[("error", &e)]),
+                tr("doctor-api-key-unreachable-fix", &[]),
+            ),
+        }
+    }
+
+    fn check_web_port(&self) -> CheckResult {
+        const CHECK: &str = "webPort";
+        let port = self.webui_port;
+        if self.web_running {
+            return CheckResult::pass(CHECK, tr("doctor-web-port-serving", &[("port", &port)]));
+        }
+
+        match TcpListener::bind(SocketAddr::from(([127, 0, 0, 1], port))) {
+            Ok(_) => CheckResult::pass(CHECK, tr("doctor-web-port-ok", &[("port", &port)])),
+            Err(e) => CheckResult::fail(
+                CHECK,
+                tr("doctor-web-port-taken", &[("port", &port), ("error", &e)]),
+                tr("doctor-web-port-fix", &[]),
+            ),
+        }
+    }
+
+    fn check_demo_directory(&self) -> CheckResult {
+        const CHECK: &str = "demoDirectory";
+        let demos = self.game_profile.demo_path(&self.tf2_directory);
+        let path = demos.display();
+        if !demos.is_dir() {
+            return CheckResult::fail(
+                CHECK,
+                tr("doctor-demos-missing", &[("path", &path)]),
+                tr("doctor-game-dir-fix", &[]),
+            );
+        }
+
+        match probe_writable(&demos) {
+            Ok(()) => CheckResult::pass(CHECK, tr("doctor-demos-ok", &[("path", &path)])),
+            Err(e) => CheckResult::fail(
+                CHECK,
+                tr("doctor-demos-error", &[("path", &path), ("error", &e)]),
+                tr("doctor-demos-fix", &[]),
+            ),
+        }
+    }
+}
+
+/// Check a file can be created in `dir`, leaving nothing behind
+fn probe_writable(dir: &Path) -> std::io::Result<()> {
+    let probe = dir.join(PROBE_FILE);
+    OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(&probe)?;
+    std::fs::remove_file(probe)
+}
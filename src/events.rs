@@ -0,0 +1,190 @@
+use std::{
+    collections::{hash_map::DefaultHasher, HashMap, VecDeque},
+    convert::Infallible,
+    hash::{Hash, Hasher},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use axum::response::sse::Event;
+use serde::Serialize;
+use tokio::sync::mpsc::{error::TrySendError, Receiver, Sender};
+
+use crate::{
+    churn::ChurnAlert,
+    demo::{
+        live::{PlayerConnect, PlayerDisconnect},
+        votes::KickVoteUpdate,
+    },
+    throughput::{self, Pipeline},
+};
+
+type Subscriber = Sender<Result<Event, Infallible>>;
+static SUBSCRIBERS: Mutex<Vec<Subscriber>> = Mutex::new(Vec::new());
+/// Subscribers within the same program, which get the events as they are rather than serialized
+static EVENT_SUBSCRIBERS: Mutex<Vec<Sender<MacEvent>>> = Mutex::new(Vec::new());
+
+/// System events are often raised before anything has subscribed (e.g. while loading files on
+/// startup), so the most recent ones are replayed to new subscribers.
+const MAX_RECENT_SYSTEM_EVENTS: usize = 10;
+static RECENT_SYSTEM_EVENTS: Mutex<VecDeque<MacEvent>> = Mutex::new(VecDeque::new());
+
+/// Some things are seen by more than one source (e.g. the console log and RCON polling, or a demo
+/// and the console), so identical events within this many milliseconds of each other are assumed
+/// to be the same occurrence and only the first is passed on. Nothing is suppressed if 0.
+pub const DEFAULT_DEDUP_WINDOW_MS: u64 = 2000;
+static DEDUP_WINDOW_MS: AtomicU64 = AtomicU64::new(DEFAULT_DEDUP_WINDOW_MS);
+/// Hashes of the identities of recently passed on events, and when they were first seen
+static RECENTLY_SEEN: Mutex<Option<HashMap<u64, Instant>>> = Mutex::new(None);
+
+/// Events that are pushed to anyone subscribed to the events stream of the web API.
+/// Serializes as `{"event": "mac:...", "data": {...}}`.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "event", content = "data")]
+pub enum MacEvent {
+    #[serde(rename = "mac:system_event")]
+    System(SystemEvent),
+    /// A kick vote was called, voted in or finished, with how it now stands
+    #[serde(rename = "mac:kick_vote")]
+    KickVote(KickVoteUpdate),
+    #[serde(rename = "mac:player_connect")]
+    PlayerConnect(PlayerConnect),
+    #[serde(rename = "mac:player_disconnect")]
+    PlayerDisconnect(PlayerDisconnect),
+    /// Players joining or leaving the server much faster than usual
+    #[serde(rename = "mac:lobby_churn")]
+    LobbyChurn(ChurnAlert),
+}
+
+/// Something about the client itself that the user should know about
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SystemEvent {
+    pub level: SystemEventLevel,
+    pub message: Arc<str>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum SystemEventLevel {
+    Info,
+    Warning,
+    Error,
+}
+
+impl SystemEvent {
+    pub fn new(level: SystemEventLevel, message: impl Into<Arc<str>>) -> SystemEvent {
+        SystemEvent {
+            level,
+            message: message.into(),
+        }
+    }
+}
+
+/// Subscribe to all events emitted from now on, starting with any recent system events
+pub fn subscribe() -> Receiver<Result<Event, Infallible>> {
+    let (tx, rx) = tokio::sync::mpsc::channel(16);
+    for event in RECENT_SYSTEM_EVENTS.lock().unwrap().iter() {
+        if let Ok(data) = serde_json::to_string(event) {
+            let _ = tx.try_send(Ok(Event::default().data(data)));
+        }
+    }
+    SUBSCRIBERS.lock().unwrap().push(tx);
+    rx
+}
+
+/// Like [subscribe], but for use within the same program (e.g. when embedding the backend), so
+/// the events don't have to be serialized and parsed again
+pub fn subscribe_events() -> Receiver<MacEvent> {
+    let (tx, rx) = tokio::sync::mpsc::channel(16);
+    for event in RECENT_SYSTEM_EVENTS.lock().unwrap().iter() {
+        let _ = tx.try_send(event.clone());
+    }
+    EVENT_SUBSCRIBERS.lock().unwrap().push(tx);
+    rx
+}
+
+/// Change how long identical events are suppressed for, see [DEFAULT_DEDUP_WINDOW_MS]
+pub fn set_dedup_window(window: Duration) {
+    DEDUP_WINDOW_MS.store(window.as_millis() as u64, Ordering::Relaxed);
+}
+
+/// Whether this is the first time something with this `identity` has happened within the
+/// deduplication window, i.e. it should be passed on. Consumers outside the event stream (e.g.
+/// webhooks) use this too, so they don't fire twice for one real occurrence.
+pub fn first_occurrence(identity: &impl Hash) -> bool {
+    let window = Duration::from_millis(DEDUP_WINDOW_MS.load(Ordering::Relaxed));
+    if window.is_zero() {
+        return true;
+    }
+
+    let mut hasher = DefaultHasher::new();
+    identity.hash(&mut hasher);
+    let key = hasher.finish();
+
+    let now = Instant::now();
+    let mut seen = RECENTLY_SEEN.lock().unwrap();
+    let seen = seen.get_or_insert_with(HashMap::new);
+    seen.retain(|_, first| now.duration_since(*first) < window);
+    if seen.contains_key(&key) {
+        return false;
+    }
+    seen.insert(key, now);
+    true
+}
+
+/// Send an event to every subscriber. Subscribers that have gone away are dropped, and subscribers
+/// that are too far behind miss the event. An event identical to one sent within the
+/// deduplication window is dropped.
+pub fn emit(event: MacEvent) {
+    let data = match serde_json::to_string(&event) {
+        Ok(data) => data,
+        Err(e) => {
+            tracing::error!("Failed to serialize event {:?}: {:?}", event, e);
+            return;
+        }
+    };
+    if !first_occurrence(&data) {
+        tracing::debug!("Suppressed duplicate event {:?}", event);
+        return;
+    }
+    let sse_event = Event::default().data(data);
+
+    if matches!(event, MacEvent::System(_)) {
+        let mut recent = RECENT_SYSTEM_EVENTS.lock().unwrap();
+        if recent.len() >= MAX_RECENT_SYSTEM_EVENTS {
+            recent.pop_front();
+        }
+        recent.push_back(event.clone());
+    }
+
+    throughput::record(Pipeline::Events, 1);
+    SUBSCRIBERS.lock().unwrap().retain(|subscriber| {
+        match subscriber.try_send(Ok(sse_event.clone())) {
+            Ok(_) => true,
+            Err(TrySendError::Full(_)) => {
+                tracing::warn!("Events subscriber is falling behind, dropped {:?}", event);
+                true
+            }
+            Err(TrySendError::Closed(_)) => false,
+        }
+    });
+    EVENT_SUBSCRIBERS.lock().unwrap().retain(|subscriber| {
+        match subscriber.try_send(event.clone()) {
+            Ok(_) => true,
+            Err(TrySendError::Full(_)) => {
+                tracing::warn!("Events subscriber is falling behind, dropped {:?}", event);
+                true
+            }
+            Err(TrySendError::Closed(_)) => false,
+        }
+    });
+}
+
+/// Emit a [SystemEvent]
+pub fn emit_system(level: SystemEventLevel, message: impl Into<Arc<str>>) {
+    emit(MacEvent::System(SystemEvent::new(level, message)));
+}
@@ -0,0 +1,297 @@
+use std::{
+    collections::HashMap,
+    fmt::{Display, Write as _},
+    io::{Cursor, Write},
+    sync::Arc,
+};
+
+use anyhow::Context;
+use serde::Serialize;
+use zip::{write::FileOptions, ZipWriter};
+
+use crate::{
+    analytics::history::now,
+    demo::{
+        clock,
+        suspicion::{EncounterSuspicion, SuspicionKind},
+    },
+    locale::tr,
+    player::{ChatLine, GameInfo, SteamInfo},
+    player_records::Verdict,
+    server::Server,
+    steamid::SteamID,
+    votes::{Signal, VoteFeedback},
+};
+
+pub mod export;
+
+/// Everything known about a player, gathered up so it can be attached to a report to Valve.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EvidencePackage {
+    pub steamid: SteamID,
+    /// Unix timestamp (seconds) of when the package was built
+    pub created: u64,
+    pub name: Option<Arc<str>>,
+    pub previous_names: Vec<Arc<str>>,
+    pub verdict: Verdict,
+    pub steam_info: Option<SteamInfo>,
+    pub session: SessionInfo,
+    pub chat: Vec<ChatLine>,
+    /// Matches in which the player was flagged, with the demo and the ticks between which they
+    /// were flagged. These come from the flags themselves, not from bookmarks made in the demo.
+    pub flagged_matches: Vec<EncounterSuspicion>,
+    pub heuristics: Vec<HeuristicExplanation>,
+}
+
+/// The server the player is currently (or was most recently) seen on
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SessionInfo {
+    pub map: Option<Arc<str>>,
+    pub hostname: Option<Arc<str>>,
+    pub ip: Option<Arc<str>>,
+    pub game_info: Option<GameInfo>,
+}
+
+/// Why the player was flagged by a particular heuristic, totalled over every recorded match
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HeuristicExplanation {
+    pub kind: SuspicionKind,
+    pub description: String,
+    pub matches: u32,
+    pub total_flags: u32,
+    pub peak_confidence: f32,
+    /// How well votekicks against players flagged by this heuristic have gone, see [VoteFeedback]
+    pub weight: f32,
+}
+
+impl EvidencePackage {
+    /// Gather everything known about a player. Returns `None` if nothing is known about them.
+    pub fn build(server: &Server, steamid: SteamID) -> Option<EvidencePackage> {
+        let players = server.players();
+        let game_info = players.game_info.get(&steamid);
+        let steam_info = players.steam_info.get(&steamid);
+        let record = players.records.get(&steamid);
+
+        if game_info.is_none() && steam_info.is_none() && record.is_none() {
+            return None;
+        }
+
+        let flagged_matches: Vec<EncounterSuspicion> =
+            record.map(|r| r.suspicion.clone()).unwrap_or_default();
+
+        Some(EvidencePackage {
+            steamid,
+            created: now(),
+            name: game_info
+                .map(|gi| gi.name.clone())
+                .or_else(|| steam_info.map(|si| si.account_name.clone())),
+            previous_names: record.map(|r| r.previous_names.clone()).unwrap_or_default(),
+            verdict: record.map(|r| r.verdict).unwrap_or(Verdict::Player),
+            steam_info: steam_info.cloned(),
+            session: SessionInfo {
+                map: server.map(),
+                hostname: server.hostname(),
+                ip: server.ip(),
+                game_info: game_info.cloned(),
+            },
+            chat: record
+                .map(|r| r.chat.clone())
+                .filter(|chat| !chat.is_empty())
+                .or_else(|| {
+                    players
+                        .chat
+                        .get(&steamid)
+                        .map(|c| c.iter().cloned().collect())
+                })
+                .unwrap_or_default(),
+            heuristics: explain_heuristics(&flagged_matches, server.votes()),
+            flagged_matches,
+        })
+    }
+
+    /// A plain text summary laid out to match what Valve's report form asks for
+    pub fn summary(&self) -> String {
+        let mut out = String::new();
+
+        // Writing to a String cannot fail
+        let url = format!("https://steamcommunity.com/profiles/{}", self.steamid);
+        let _ = writeln!(out, "{}", tr("evidence-steam-profile", &[("url", &url)]));
+        let steam3 = format!("{:#}", self.steamid);
+        let _ = writeln!(
+            out,
+            "{}",
+            tr(
+                "evidence-steamid",
+                &[("steamid", &self.steamid), ("steam3", &steam3)]
+            )
+        );
+        let _ = writeln!(out, "{}", tr("evidence-game", &[]));
+        let _ = writeln!(out, "{}", tr("evidence-reason", &[]));
+        if let Some(name) = &self.name {
+            let _ = writeln!(out, "{}", tr("evidence-name", &[("name", name)]));
+        }
+        if !self.previous_names.is_empty() {
+            let names = self.previous_names.join(", ");
+            let _ = writeln!(out, "{}", tr("evidence-also-seen-as", &[("names", &names)]));
+        }
+        if let Some(steam_info) = &self.steam_info {
+            let _ = writeln!(
+                out,
+                "{}",
+                tr(
+                    "evidence-bans",
+                    &[
+                        ("vac", &steam_info.vac_bans),
+                        ("game", &steam_info.game_bans)
+                    ]
+                )
+            );
+        }
+
+        let unknown_server = tr("evidence-unknown-server", &[]);
+        let unknown_address = tr("evidence-unknown-address", &[]);
+        let unknown_map = tr("evidence-unknown-map", &[]);
+        let _ = writeln!(out, "\n{}", tr("evidence-server", &[]));
+        let _ = writeln!(
+            out,
+            "  {}",
+            tr(
+                "evidence-server-details",
+                &[
+                    (
+                        "hostname",
+                        &self.session.hostname.as_deref().unwrap_or(&unknown_server)
+                    ),
+                    (
+                        "address",
+                        &self.session.ip.as_deref().unwrap_or(&unknown_address)
+                    ),
+                    ("map", &self.session.map.as_deref().unwrap_or(&unknown_map)),
+                ]
+            )
+        );
+
+        let _ = writeln!(out, "\n{}", tr("evidence-description", &[]));
+        if self.heuristics.is_empty() {
+            let _ = writeln!(out, "  {}", tr("evidence-no-detections", &[]));
+        }
+        for heuristic in &self.heuristics {
+            let confidence = format!("{:.0}", heuristic.peak_confidence * 100.0);
+            let _ = writeln!(
+                out,
+                "  - {}",
+                tr(
+                    "evidence-heuristic",
+                    &[
+                        ("description", &heuristic.description),
+                        ("flags", &heuristic.total_flags),
+                        ("matches", &heuristic.matches),
+                        ("confidence", &confidence),
+                    ]
+                )
+            );
+        }
+
+        if !self.flagged_matches.is_empty() {
+            let _ = writeln!(out, "\n{}", tr("evidence-flagged-matches", &[]));
+            out.push_str(&self.demo_references());
+        }
+
+        if !self.chat.is_empty() {
+            let _ = writeln!(out, "\n{}", tr("evidence-chat", &[]));
+            for line in &self.chat {
+                let time = clock::iso8601(line.time * 1000);
+                let _ = writeln!(out, "  [{}] {}", time, line.message);
+            }
+        }
+
+        out
+    }
+
+    /// One line per flagged match, giving the demo file and the ticks the flags occurred between,
+    /// along with when they were if that is known
+    pub fn demo_references(&self) -> String {
+        let unknown_map = tr("evidence-unknown-map", &[]);
+        let mut out = String::new();
+        for demo in &self.flagged_matches {
+            let mut kinds: Vec<String> = demo
+                .suspicion
+                .flags
+                .keys()
+                .map(|k| format!("{:?}", k))
+                .collect();
+            kinds.sort();
+
+            let map = demo.map.as_deref().unwrap_or(&unknown_map);
+            let kinds = kinds.join(", ");
+            let mut args: Vec<(&str, &dyn Display)> = vec![
+                ("demo", &demo.demo),
+                ("map", &map),
+                ("first", &demo.suspicion.first_tick),
+                ("last", &demo.suspicion.last_tick),
+                ("kinds", &kinds),
+            ];
+            let key = match (&demo.suspicion.first_time, &demo.suspicion.last_time) {
+                (Some(first_time), Some(last_time)) => {
+                    args.push(("first_time", first_time));
+                    args.push(("last_time", last_time));
+                    "evidence-demo-timed"
+                }
+                _ => "evidence-demo",
+            };
+            let _ = writeln!(out, "  {}", tr(key, &args));
+        }
+        out
+    }
+
+    /// Bundle the summary, demo references and raw data into a zip archive
+    pub fn to_zip(&self) -> anyhow::Result<Vec<u8>> {
+        let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+        let options = FileOptions::default();
+
+        zip.start_file("summary.txt", options)?;
+        zip.write_all(self.summary().as_bytes())?;
+
+        zip.start_file("demos.txt", options)?;
+        zip.write_all(self.demo_references().as_bytes())?;
+
+        zip.start_file("evidence.json", options)?;
+        let json =
+            serde_json::to_string_pretty(self).context("Failed to serialize evidence package.")?;
+        zip.write_all(json.as_bytes())?;
+
+        Ok(zip.finish()?.into_inner())
+    }
+}
+
+/// Total up the flags across every match into one explanation per heuristic
+fn explain_heuristics(
+    demos: &[EncounterSuspicion],
+    votes: &VoteFeedback,
+) -> Vec<HeuristicExplanation> {
+    let mut explanations: HashMap<SuspicionKind, HeuristicExplanation> = HashMap::new();
+    for demo in demos {
+        for (kind, count) in &demo.suspicion.flags {
+            let explanation = explanations
+                .entry(*kind)
+                .or_insert_with(|| HeuristicExplanation {
+                    kind: *kind,
+                    description: kind.description(),
+                    matches: 0,
+                    total_flags: 0,
+                    peak_confidence: 0.0,
+                    weight: votes.weight(&Signal::Heuristic(*kind)),
+                });
+            explanation.matches += 1;
+            explanation.total_flags += count.count;
+            explanation.peak_confidence = explanation.peak_confidence.max(count.peak_confidence);
+        }
+    }
+
+    let mut explanations: Vec<HeuristicExplanation> = explanations.into_values().collect();
+    explanations.sort_by_key(|e| std::cmp::Reverse(e.total_flags));
+    explanations
+}
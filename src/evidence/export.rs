@@ -0,0 +1,153 @@
+use std::{fmt::Write as _, sync::Arc};
+
+use serde::Deserialize;
+
+use crate::{
+    demo::clock::civil_from_days, player::KillLine, player_records::ChatSearch, server::Server,
+    steamid::SteamID,
+};
+
+/// How an exported block of evidence is wrapped, so it keeps its layout where it is pasted
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ExportFormat {
+    /// Plain text
+    #[default]
+    Text,
+    /// A code block for Discord and other markdown
+    Markdown,
+    /// A `[code]` block for forums
+    BBCode,
+}
+
+/// Which kills to export
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct KillSearch {
+    /// Only kills where one of these players was the killer or victim, every kill if empty
+    pub steamids: Vec<SteamID>,
+    /// Only kills seen at or after this unix timestamp (seconds)
+    pub since: Option<u64>,
+    /// Only kills seen at or before this unix timestamp (seconds)
+    pub until: Option<u64>,
+}
+
+impl KillSearch {
+    fn matches(&self, kill: &KillLine) -> bool {
+        let involved =
+            |steamid: Option<SteamID>| steamid.is_some_and(|s| self.steamids.contains(&s));
+        (self.steamids.is_empty() || involved(kill.killer) || involved(kill.victim))
+            && self.since.is_none_or(|since| kill.time >= since)
+            && self.until.is_none_or(|until| kill.time <= until)
+    }
+}
+
+/// What to export, either stored chat lines or recently seen kills
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+pub enum ExportSelection {
+    Chat(ChatSearch),
+    Kills(KillSearch),
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ExportRequest {
+    pub selection: ExportSelection,
+    #[serde(default)]
+    pub format: ExportFormat,
+}
+
+/// Format a unix timestamp (seconds) as `YYYY-MM-DD HH:MM:SS` in UTC
+fn format_time(time: u64) -> String {
+    let (days, secs) = (time / 86400, time % 86400);
+    let (year, month, day) = civil_from_days(days as i64);
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+        year,
+        month,
+        day,
+        secs / 3600,
+        secs % 3600 / 60,
+        secs % 60
+    )
+}
+
+/// A player's name followed by both forms of their SteamID, if known
+fn describe(name: &str, steamid: Option<SteamID>) -> String {
+    match steamid {
+        Some(steamid) => format!("{} ({:#} / {})", name, steamid, steamid),
+        None => name.to_string(),
+    }
+}
+
+/// One line per selected chat message or kill, oldest first
+fn lines(server: &Server, selection: &ExportSelection) -> Vec<String> {
+    match selection {
+        ExportSelection::Chat(search) => {
+            let mut results = server.players().records.search_chat(search);
+            results.reverse();
+            results
+                .into_iter()
+                .map(|result| {
+                    let name = result.name.unwrap_or_else(|| Arc::from("Unknown"));
+                    format!(
+                        "[{}] {}: {}",
+                        format_time(result.time),
+                        describe(&name, Some(result.steamid)),
+                        result.message
+                    )
+                })
+                .collect()
+        }
+        ExportSelection::Kills(search) => server
+            .players()
+            .kills
+            .iter()
+            .filter(|kill| search.matches(kill))
+            .map(|kill| {
+                format!(
+                    "[{}] {} killed {} with {}{}",
+                    format_time(kill.time),
+                    describe(&kill.killer_name, kill.killer),
+                    describe(&kill.victim_name, kill.victim),
+                    kill.weapon,
+                    if kill.crit { " (crit)" } else { "" }
+                )
+            })
+            .collect(),
+    }
+}
+
+/// Render the selected chat lines or kills as a block of text ready to paste into a report.
+/// Returns `None` if nothing was selected.
+pub fn render(server: &Server, request: &ExportRequest) -> Option<String> {
+    let lines = lines(server, &request.selection);
+    if lines.is_empty() {
+        return None;
+    }
+
+    let mut out = String::new();
+    match request.format {
+        ExportFormat::Text => {}
+        ExportFormat::Markdown => out.push_str("```\n"),
+        ExportFormat::BBCode => out.push_str("[code]\n"),
+    }
+    // Writing to a String cannot fail
+    let _ = writeln!(out, "Times are UTC");
+    for line in lines {
+        // Stop anything in chat from closing the block early
+        let line = match request.format {
+            ExportFormat::Text => line,
+            ExportFormat::Markdown => line.replace("```", "'''"),
+            ExportFormat::BBCode => line.replace("[/code]", "[/ code]"),
+        };
+        let _ = writeln!(out, "{}", line);
+    }
+    match request.format {
+        ExportFormat::Text => {}
+        ExportFormat::Markdown => out.push_str("```\n"),
+        ExportFormat::BBCode => out.push_str("[/code]\n"),
+    }
+
+    Some(out)
+}
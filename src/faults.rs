@@ -0,0 +1,162 @@
+//! A developer mode that makes the things the client depends on misbehave, so the retry and
+//! recovery paths can be exercised without waiting for Steam or the game to fail by themselves.
+//! It is enabled with `--inject_faults`, which takes a comma separated list of settings, e.g.
+//! `--inject_faults latency=500,timeout=0.1,malformed=0.1,truncate=0.2,seed=7`:
+//!
+//! - `latency`: milliseconds added to every Steam API response, varied by up to as much again
+//! - `timeout`: chance (0 to 1) of a Steam API request timing out
+//! - `malformed`: chance of a Steam API response being cut short, so it can't be parsed
+//! - `truncate`: chance of a read from the console log or a demo only getting part of the new data
+//! - `seed`: makes which faults happen the same from run to run, so a failure can be reproduced
+//!
+//! The mode only exists in builds with the `fault-injection` feature, and faults never happen
+//! unless it is enabled.
+
+use std::{
+    str::FromStr,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex,
+    },
+    time::Duration,
+};
+
+use anyhow::anyhow;
+use thiserror::Error;
+
+/// How long an injected timeout takes to fail, the same as the timeout on real requests
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+static FAULTS: Mutex<Option<FaultConfig>> = Mutex::new(None);
+static RNG: AtomicU64 = AtomicU64::new(0);
+
+/// Which faults to inject, see the [module documentation](self)
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct FaultConfig {
+    pub latency_ms: u64,
+    pub timeout_rate: f64,
+    pub malformed_rate: f64,
+    pub truncate_rate: f64,
+    pub seed: Option<u64>,
+}
+
+#[derive(Debug, Error)]
+pub enum FaultConfigError {
+    #[error("Expected a setting like latency=500, got \"{0}\"")]
+    Syntax(String),
+    #[error("Unknown fault \"{0}\", expected latency, timeout, malformed, truncate or seed")]
+    Unknown(String),
+    #[error("Invalid value for {0}: \"{1}\"")]
+    Value(String, String),
+}
+
+fn parse_rate(key: &str, value: &str) -> Result<f64, FaultConfigError> {
+    value
+        .parse::<f64>()
+        .ok()
+        .filter(|rate| (0.0..=1.0).contains(rate))
+        .ok_or_else(|| FaultConfigError::Value(key.into(), value.into()))
+}
+
+impl FromStr for FaultConfig {
+    type Err = FaultConfigError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut config = FaultConfig::default();
+        for setting in s.split(',').map(str::trim).filter(|s| !s.is_empty()) {
+            let (key, value) = setting
+                .split_once('=')
+                .ok_or_else(|| FaultConfigError::Syntax(setting.into()))?;
+            let invalid = || FaultConfigError::Value(key.into(), value.into());
+            match key {
+                "latency" => config.latency_ms = value.parse().map_err(|_| invalid())?,
+                "timeout" => config.timeout_rate = parse_rate(key, value)?,
+                "malformed" => config.malformed_rate = parse_rate(key, value)?,
+                "truncate" => config.truncate_rate = parse_rate(key, value)?,
+                "seed" => config.seed = Some(value.parse().map_err(|_| invalid())?),
+                _ => return Err(FaultConfigError::Unknown(key.into())),
+            }
+        }
+        Ok(config)
+    }
+}
+
+/// Start injecting faults
+pub fn enable(config: FaultConfig) {
+    tracing::warn!(
+        "Fault injection is enabled, expect things to go wrong: {:?}",
+        config
+    );
+    let seed = config.seed.unwrap_or_else(|| {
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_nanos() as u64)
+            .unwrap_or(1)
+    });
+    // xorshift gets stuck at 0
+    RNG.store(seed.max(1), Ordering::Relaxed);
+    *FAULTS.lock().unwrap() = Some(config);
+}
+
+fn config() -> Option<FaultConfig> {
+    FAULTS.lock().unwrap().clone()
+}
+
+/// A pseudo-random number in `0..1`. Not remotely suitable for anything but this.
+fn random() -> f64 {
+    let mut x = RNG.load(Ordering::Relaxed);
+    x ^= x << 13;
+    x ^= x >> 7;
+    x ^= x << 17;
+    RNG.store(x, Ordering::Relaxed);
+    (x >> 11) as f64 / (1u64 << 53) as f64
+}
+
+fn happens(rate: f64) -> bool {
+    rate > 0.0 && random() < rate
+}
+
+/// Pass a response from the Steam API `method` through the configured faults, which may delay
+/// it, replace it with a timeout or cut it short
+pub async fn steam_api(method: &str, body: String) -> anyhow::Result<String> {
+    let Some(config) = config() else {
+        return Ok(body);
+    };
+
+    if config.latency_ms > 0 {
+        let latency = config.latency_ms as f64 * (1.0 + random());
+        tokio::time::sleep(Duration::from_millis(latency as u64)).await;
+    }
+    if happens(config.timeout_rate) {
+        tokio::time::sleep(TIMEOUT).await;
+        tracing::debug!("Injected a timeout into {}", method);
+        return Err(anyhow!("{} timed out (injected fault)", method));
+    }
+    if happens(config.malformed_rate) {
+        tracing::debug!("Injected a malformed response into {}", method);
+        let mut end = body.len() / 2;
+        while !body.is_char_boundary(end) {
+            end -= 1;
+        }
+        return Ok(body[..end].to_string());
+    }
+    Ok(body)
+}
+
+/// How many of `len` newly read bytes to keep, as though the read had come up short. At least one
+/// byte is always kept, and the rest are expected to be read again next time.
+pub fn short_read(source: &str, len: usize) -> usize {
+    match config() {
+        Some(config) if len > 1 && happens(config.truncate_rate) => {
+            let kept = 1 + ((len - 1) as f64 * random()) as usize;
+            tracing::debug!(
+                "Injected a short read of {}/{} bytes into {}",
+                kept,
+                len,
+                source
+            );
+            kept
+        }
+        _ => len,
+    }
+}
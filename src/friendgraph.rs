@@ -0,0 +1,120 @@
+//! Correlating friend lists. The graph itself is [Players::friend_info], which every fetched
+//! friend list is added to in both directions, so friendships are known for private profiles too
+//! as long as the other side is public. These are queries over it.
+
+use std::collections::{BTreeMap, HashSet, VecDeque};
+
+use serde::Serialize;
+
+use crate::{player::Players, player_records::Verdict, steamid::SteamID};
+
+/// How a player is connected to someone marked as a cheater or bot
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MarkedConnection {
+    pub steamid: SteamID,
+    pub verdict: Verdict,
+    /// Whether they are friends themselves
+    pub direct: bool,
+    /// Friends the two have in common
+    pub mutual_friends: Vec<SteamID>,
+}
+
+/// Everyone `steamid` is known to be friends with
+pub fn friends(players: &Players, steamid: SteamID) -> HashSet<SteamID> {
+    players
+        .friend_info
+        .get(&steamid)
+        .map(|fi| fi.iter().map(|f| f.steamid).collect())
+        .unwrap_or_default()
+}
+
+fn marked_verdict(players: &Players, steamid: SteamID) -> Option<Verdict> {
+    players
+        .records
+        .get(&steamid)
+        .filter(|r| r.is_marked())
+        .map(|r| r.verdict)
+}
+
+fn connection(
+    connections: &mut BTreeMap<SteamID, MarkedConnection>,
+    marked: SteamID,
+    verdict: Verdict,
+) -> &mut MarkedConnection {
+    connections
+        .entry(marked)
+        .or_insert_with(|| MarkedConnection {
+            steamid: marked,
+            verdict,
+            direct: false,
+            mutual_friends: Vec::new(),
+        })
+}
+
+/// Cheaters and bots `steamid` is friends with, or shares friends with, closest first
+pub fn marked_connections(players: &Players, steamid: SteamID) -> Vec<MarkedConnection> {
+    let player_friends = friends(players, steamid);
+    let mut connections: BTreeMap<SteamID, MarkedConnection> = BTreeMap::new();
+
+    for &friend in &player_friends {
+        if let Some(verdict) = marked_verdict(players, friend) {
+            connection(&mut connections, friend, verdict).direct = true;
+        }
+        for friend_of_friend in friends(players, friend) {
+            if friend_of_friend == steamid {
+                continue;
+            }
+            if let Some(verdict) = marked_verdict(players, friend_of_friend) {
+                connection(&mut connections, friend_of_friend, verdict)
+                    .mutual_friends
+                    .push(friend);
+            }
+        }
+    }
+
+    let mut connections: Vec<_> = connections.into_values().collect();
+    for connection in &mut connections {
+        connection.mutual_friends.sort();
+    }
+    connections.sort_by(|a, b| {
+        b.direct
+            .cmp(&a.direct)
+            .then(b.mutual_friends.len().cmp(&a.mutual_friends.len()))
+    });
+    connections
+}
+
+/// Groups of connected players who are friends with each other, directly or through other
+/// connected players, e.g. a party queued together. Players with no friends on the server are
+/// left out.
+pub fn lobby_friend_groups(players: &Players) -> Vec<Vec<SteamID>> {
+    let connected: HashSet<SteamID> = players.connected.iter().copied().collect();
+    let mut seen: HashSet<SteamID> = HashSet::new();
+    let mut groups = Vec::new();
+
+    for &start in &players.connected {
+        if !seen.insert(start) {
+            continue;
+        }
+
+        let mut group = vec![start];
+        let mut queue = VecDeque::from([start]);
+        while let Some(player) = queue.pop_front() {
+            for friend in friends(players, player) {
+                if connected.contains(&friend) && seen.insert(friend) {
+                    group.push(friend);
+                    queue.push_back(friend);
+                }
+            }
+        }
+
+        if group.len() > 1 {
+            group.sort();
+            groups.push(group);
+        }
+    }
+
+    groups.sort_by_key(|g| std::cmp::Reverse(g.len()));
+    groups
+}
@@ -1,9 +1,10 @@
 use std::path::PathBuf;
 
 use anyhow::{anyhow, Result};
-use steamid_ng::SteamID;
 use steamlocate::SteamDir;
 
+use crate::{gameprofile::GameProfile, steamid::SteamID};
+
 pub const TF2_GAME_ID: u32 = 440;
 
 pub fn locate_steam_logged_in_users() -> Result<PathBuf> {
@@ -34,12 +35,21 @@ pub fn locate_steam_launch_configs(steam_user: SteamID) -> Result<PathBuf> {
     }
 }
 
-/// Attempts to open the TF2 directory or locate it if it's not in the expected place
-pub fn locate_tf2_folder() -> Result<PathBuf> {
-    Ok(SteamDir::locate()
-        .ok_or(anyhow!("Failed to locate Steam directory"))?
-        .app(&TF2_GAME_ID)
-        .ok_or(anyhow!("Failed to locate TF2 installation."))?
-        .path
-        .clone())
+/// Attempts to locate where the game of a [GameProfile] is installed, either as a Steam app or
+/// as a sourcemod.
+pub fn locate_game_folder(profile: &GameProfile) -> Result<PathBuf> {
+    let mut steam = SteamDir::locate().ok_or(anyhow!("Failed to locate Steam directory"))?;
+
+    if let Some(app) = profile.appid.and_then(|appid| steam.app(&appid)) {
+        return Ok(app.path.clone());
+    }
+
+    if let Some(sourcemod) = &profile.sourcemod {
+        let path = steam.path.join("steamapps/sourcemods").join(&**sourcemod);
+        if path.is_dir() {
+            return Ok(path);
+        }
+    }
+
+    Err(anyhow!("Failed to locate {} installation.", profile.name))
 }
@@ -0,0 +1,129 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    gamefinder::TF2_GAME_ID,
+    io::regexes::{
+        REGEX_CHAT, REGEX_HOSTNAME, REGEX_IP, REGEX_KILL, REGEX_MAP, REGEX_MAP_CHANGE,
+        REGEX_PARTY_CHAT, REGEX_PLAYERCOUNT, REGEX_STATUS,
+    },
+};
+
+/// Name of the profile used when none has been chosen
+pub const DEFAULT_PROFILE: &str = "tf2";
+
+/// Everything that differs between TF2 and the games built on it (e.g. TF2 Classic or
+/// TF2 Vintage), so the client can watch any of them.
+///
+/// Custom profiles can be added in the config file, any fields left out are the same as for TF2.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GameProfile {
+    pub name: Arc<str>,
+    /// Steam app ID, if the game is installed through Steam as its own app
+    pub appid: Option<u32>,
+    /// Folder name in `steamapps/sourcemods`, if the game is installed as a sourcemod
+    pub sourcemod: Option<Arc<str>>,
+    /// Folder containing the game's files, relative to where it is installed
+    pub game_dir: PathBuf,
+    /// The console log written by `-condebug`, relative to the game folder
+    pub log_file: PathBuf,
+    /// Where demos are recorded, relative to the game folder
+    pub demo_dir: PathBuf,
+    pub line_formats: LineFormats,
+}
+
+/// Regexes used to read lines of console output. Custom formats must have the same capture
+/// groups as the TF2 ones they replace.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LineFormats {
+    pub status: Arc<str>,
+    pub chat: Arc<str>,
+    pub party_chat: Arc<str>,
+    pub kill: Arc<str>,
+    pub hostname: Arc<str>,
+    pub ip: Arc<str>,
+    pub map: Arc<str>,
+    pub map_change: Arc<str>,
+    pub playercount: Arc<str>,
+}
+
+impl Default for LineFormats {
+    fn default() -> Self {
+        LineFormats {
+            status: REGEX_STATUS.into(),
+            chat: REGEX_CHAT.into(),
+            party_chat: REGEX_PARTY_CHAT.into(),
+            kill: REGEX_KILL.into(),
+            hostname: REGEX_HOSTNAME.into(),
+            ip: REGEX_IP.into(),
+            map: REGEX_MAP.into(),
+            map_change: REGEX_MAP_CHANGE.into(),
+            playercount: REGEX_PLAYERCOUNT.into(),
+        }
+    }
+}
+
+impl Default for GameProfile {
+    fn default() -> Self {
+        GameProfile {
+            name: DEFAULT_PROFILE.into(),
+            appid: Some(TF2_GAME_ID),
+            sourcemod: None,
+            game_dir: "tf".into(),
+            log_file: "console.log".into(),
+            demo_dir: "".into(),
+            line_formats: LineFormats::default(),
+        }
+    }
+}
+
+impl GameProfile {
+    /// A sourcemod whose files are all in its own folder
+    fn sourcemod(name: &str) -> GameProfile {
+        GameProfile {
+            name: name.into(),
+            appid: None,
+            sourcemod: Some(name.into()),
+            game_dir: "".into(),
+            ..Default::default()
+        }
+    }
+
+    /// The profiles that are always available: retail TF2, TF2 Classic and TF2 Vintage
+    pub fn builtin() -> Vec<GameProfile> {
+        vec![
+            GameProfile::default(),
+            GameProfile::sourcemod("tf2classic"),
+            GameProfile::sourcemod("tf2vintage"),
+        ]
+    }
+
+    /// Find a profile by name, looking through `custom` profiles before the built in ones
+    pub fn find(name: &str, custom: &[GameProfile]) -> Option<GameProfile> {
+        custom
+            .iter()
+            .find(|p| &*p.name == name)
+            .cloned()
+            .or_else(|| {
+                GameProfile::builtin()
+                    .into_iter()
+                    .find(|p| &*p.name == name)
+            })
+    }
+
+    /// The console log of the game installed at `install_dir`
+    pub fn log_file_path(&self, install_dir: &Path) -> PathBuf {
+        install_dir.join(&self.game_dir).join(&self.log_file)
+    }
+
+    /// The folder demos are recorded to for the game installed at `install_dir`
+    pub fn demo_path(&self, install_dir: &Path) -> PathBuf {
+        install_dir.join(&self.game_dir).join(&self.demo_dir)
+    }
+}
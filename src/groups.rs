@@ -0,0 +1,113 @@
+//! Steam groups players are in, to catch those belonging to communities known for hosting bots or
+//! cheating. A group counts against a player if the user has flagged it, or if players they have
+//! marked as cheaters or bots are in it too.
+
+use std::{collections::HashMap, sync::Arc};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{player_records::PlayerRecords, steamid::SteamID};
+
+/// Most players whose groups are kept, dropping the earliest looked up first
+const MAX_GROUP_INFO: usize = 500;
+
+/// A public Steam group a player is in
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SteamGroup {
+    /// The group's 64 bit ID
+    pub id: Arc<str>,
+    pub name: Option<Arc<str>>,
+}
+
+/// A group the user knows to be a bot hoster or cheating community
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FlaggedGroup {
+    /// The group's 64 bit ID
+    pub id: Arc<str>,
+    /// What to call it, e.g. the name of the bot hoster
+    pub name: Arc<str>,
+}
+
+/// A group a player is in that counts against them
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GroupMatch {
+    pub group: SteamGroup,
+    /// Name the user gave the group when flagging it, if they did
+    pub flagged_as: Option<Arc<str>>,
+    /// Marked cheaters and bots who are also in the group
+    pub marked_members: Vec<SteamID>,
+}
+
+/// The groups of every player they have been looked up for
+#[derive(Debug, Default)]
+pub struct GroupMemberships {
+    groups: HashMap<SteamID, Vec<SteamGroup>>,
+    /// Players in `groups`, earliest looked up first
+    order: Vec<SteamID>,
+    flagged: Vec<FlaggedGroup>,
+}
+
+impl GroupMemberships {
+    pub fn set_flagged(&mut self, flagged: Vec<FlaggedGroup>) {
+        self.flagged = flagged;
+    }
+
+    /// Whether the player's groups have already been looked up
+    pub fn is_known(&self, steamid: SteamID) -> bool {
+        self.groups.contains_key(&steamid)
+    }
+
+    pub fn get(&self, steamid: SteamID) -> Option<&[SteamGroup]> {
+        self.groups.get(&steamid).map(Vec::as_slice)
+    }
+
+    pub fn insert(&mut self, steamid: SteamID, groups: Vec<SteamGroup>) {
+        if self.groups.insert(steamid, groups).is_none() {
+            self.order.push(steamid);
+        }
+        if self.order.len() > MAX_GROUP_INFO {
+            let excess = self.order.len() - MAX_GROUP_INFO;
+            for old in self.order.drain(..excess) {
+                self.groups.remove(&old);
+            }
+        }
+    }
+
+    /// Groups `steamid` is in that have been flagged, or that marked players are in as well
+    pub fn matches(&self, steamid: SteamID, records: &PlayerRecords) -> Vec<GroupMatch> {
+        let Some(groups) = self.groups.get(&steamid) else {
+            return Vec::new();
+        };
+
+        groups
+            .iter()
+            .filter_map(|group| {
+                let flagged_as = self
+                    .flagged
+                    .iter()
+                    .find(|f| f.id == group.id)
+                    .map(|f| f.name.clone());
+                let mut marked_members: Vec<SteamID> = self
+                    .groups
+                    .iter()
+                    .filter(|(member, _)| **member != steamid)
+                    .filter(|(member, _)| records.get(member).is_some_and(|r| r.is_marked()))
+                    .filter(|(_, member_groups)| member_groups.iter().any(|g| g.id == group.id))
+                    .map(|(member, _)| *member)
+                    .collect();
+                if flagged_as.is_none() && marked_members.is_empty() {
+                    return None;
+                }
+                marked_members.sort();
+                Some(GroupMatch {
+                    group: group.clone(),
+                    flagged_as,
+                    marked_members,
+                })
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,132 @@
+use std::{
+    collections::VecDeque,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::analytics::history::now;
+
+/// Master switch for every in-game action the client takes by itself. Commands that only read
+/// from the game (e.g. refreshing the player list) are not affected.
+static AUTOMATION_ENABLED: AtomicBool = AtomicBool::new(true);
+
+/// Whether the client may take in-game actions by itself
+pub fn automation_enabled() -> bool {
+    AUTOMATION_ENABLED.load(Ordering::Relaxed)
+}
+
+/// Turn every in-game automation on or off, which takes effect immediately
+pub fn set_automation_enabled(enabled: bool) {
+    if automation_enabled() != enabled {
+        tracing::info!(
+            "In-game automation {}.",
+            if enabled { "enabled" } else { "disabled" }
+        );
+    }
+    AUTOMATION_ENABLED.store(enabled, Ordering::Relaxed);
+}
+
+/// Limits every automated in-game action must stay within
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct AutomationLimits {
+    /// Most votekicks that may be called automatically in any hour
+    pub max_votekicks_per_hour: u32,
+    /// Seconds after connecting to a server before anything may be done
+    pub join_grace_secs: u64,
+    /// Fewest rules, lists, detectors or heuristics that must have marked a player before they
+    /// may be acted on. Each counts for its weight, see [crate::votes::VoteFeedback::score].
+    pub min_signals: usize,
+}
+
+impl Default for AutomationLimits {
+    fn default() -> Self {
+        AutomationLimits {
+            max_votekicks_per_hour: 2,
+            join_grace_secs: 120,
+            min_signals: 2,
+        }
+    }
+}
+
+/// Why an automated action was not allowed
+#[derive(Debug, Error, PartialEq)]
+pub enum InterlockError {
+    #[error("In-game automation is disabled")]
+    Disabled,
+    #[error("Already called {0} votekicks in the last hour")]
+    VotekickLimit(u32),
+    #[error("Not connected to a server")]
+    NotJoined,
+    #[error("Joined the server too recently, {0} seconds left")]
+    JoinGrace(u64),
+    #[error("Player's corroborating signals add up to {have:.1}, {need} are needed")]
+    TooFewSignals { have: f32, need: usize },
+    #[error("Player is not connected")]
+    UnknownPlayer,
+}
+
+/// Keeps track of what automation has done recently, to hold it to its [AutomationLimits]
+#[derive(Debug, Default)]
+pub struct Interlocks {
+    limits: AutomationLimits,
+    /// Unix timestamps (seconds) of automated votekicks in the last hour
+    votekicks: VecDeque<u64>,
+}
+
+impl Interlocks {
+    pub fn limits(&self) -> &AutomationLimits {
+        &self.limits
+    }
+
+    pub fn set_limits(&mut self, limits: AutomationLimits) {
+        self.limits = limits;
+    }
+
+    /// Automated votekicks called in the last hour
+    pub fn votekicks_last_hour(&self) -> usize {
+        let hour_ago = now().saturating_sub(60 * 60);
+        self.votekicks.iter().filter(|&&t| t > hour_ago).count()
+    }
+
+    /// Check a votekick may be called automatically against a player whose signals add up to
+    /// `score`, `joined` being when the current server was joined (unix seconds)
+    pub fn check_votekick(&self, joined: Option<u64>, score: f32) -> Result<(), InterlockError> {
+        if !automation_enabled() {
+            return Err(InterlockError::Disabled);
+        }
+
+        let votekicks = self.votekicks_last_hour();
+        if votekicks >= self.limits.max_votekicks_per_hour as usize {
+            return Err(InterlockError::VotekickLimit(votekicks as u32));
+        }
+
+        let joined = joined.ok_or(InterlockError::NotJoined)?;
+        let ready = joined + self.limits.join_grace_secs;
+        let now = now();
+        if now < ready {
+            return Err(InterlockError::JoinGrace(ready - now));
+        }
+
+        if score < self.limits.min_signals as f32 {
+            return Err(InterlockError::TooFewSignals {
+                have: score,
+                need: self.limits.min_signals,
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Note that a votekick has been called automatically
+    pub fn votekick_called(&mut self) {
+        let now = now();
+        let hour_ago = now.saturating_sub(60 * 60);
+        while self.votekicks.front().is_some_and(|&t| t <= hour_ago) {
+            self.votekicks.pop_front();
+        }
+        self.votekicks.push_back(now);
+    }
+}
@@ -7,17 +7,24 @@ use std::sync::Arc;
 
 use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
 
+use self::audit::{CommandAllowlist, CommandAudit, CommandOrigin};
 use self::command_manager::{CommandManager, CommandManagerMessage};
 use self::filewatcher::{FileWatcher, FileWatcherCommand};
 use self::g15::{G15Parser, G15Player};
+use self::noise::NoiseFilter;
 use self::regexes::{
     ChatMessage, Hostname, Map, PlayerCount, PlayerKill, ServerIP, StatusLine, REGEX_CHAT,
-    REGEX_HOSTNAME, REGEX_IP, REGEX_KILL, REGEX_MAP, REGEX_PLAYERCOUNT, REGEX_STATUS,
+    REGEX_HOSTNAME, REGEX_IP, REGEX_KILL, REGEX_MAP, REGEX_MAP_CHANGE, REGEX_PARTY_CHAT,
+    REGEX_PLAYERCOUNT, REGEX_STATUS,
 };
+use crate::gameprofile::LineFormats;
 
+pub mod analyser;
+pub mod audit;
 pub mod command_manager;
 pub mod filewatcher;
 pub mod g15;
+pub mod noise;
 pub(crate) mod regexes;
 
 // Enums
@@ -41,6 +48,8 @@ pub enum Command {
     Status,
     Say(Arc<str>),
     SayTeam(Arc<str>),
+    /// A message to the user's party
+    PartyChat(Arc<str>),
     Kick {
         /// The uid of the player as returned by [Command::Status] or [Command::G15]
         player: Arc<str>,
@@ -50,9 +59,10 @@ pub enum Command {
     Custom(Arc<str>),
 }
 
-#[derive(Debug, Deserialize, Clone)]
+#[derive(Debug, Deserialize, Clone, Default)]
 #[serde(rename_all = "camelCase")]
 pub enum KickReason {
+    #[default]
     None,
     Idle,
     Cheating,
@@ -66,7 +76,10 @@ pub enum IOManagerMessage {
     SetLogFilePath(PathBuf),
     SetRconPassword(Arc<str>),
     SetRconPort(u16),
-    RunCommand(Command),
+    SetCommandAllowlist(CommandAllowlist),
+    /// Replace the filters that drop noisy lines before they are parsed, see [noise]
+    SetNoiseFilters(Vec<NoiseFilter>),
+    RunCommand(Command, CommandOrigin),
 }
 
 pub struct IOManager {
@@ -82,12 +95,15 @@ pub struct IOManager {
     response_send: UnboundedSender<Vec<IOOutput>>,
 
     parser: G15Parser,
+    noise_filters: Vec<NoiseFilter>,
     regex_status: Regex,
     regex_chat: Regex,
+    regex_party_chat: Regex,
     regex_kill: Regex,
     regex_hostname: Regex,
     regex_ip: Regex,
     regex_map: Regex,
+    regex_map_change: Regex,
     regex_playercount: Regex,
 }
 
@@ -96,13 +112,16 @@ impl IOManager {
         log_file_path: PathBuf,
         rcon_password: Arc<str>,
         rcon_port: u16,
+        allowlist: CommandAllowlist,
+        audit: CommandAudit,
+        line_formats: &LineFormats,
         recv: UnboundedReceiver<IOManagerMessage>,
     ) -> (UnboundedReceiver<Vec<IOOutput>>, IOManager) {
         let (resp_tx, resp_rx) = unbounded_channel();
 
         let (command_send, command_recv) = unbounded_channel();
         let (command_recv, command_manager) =
-            CommandManager::new(rcon_password, rcon_port, command_recv);
+            CommandManager::new(rcon_password, rcon_port, allowlist, audit, command_recv);
 
         let (filewatcher_send, filewatcher_recv) = unbounded_channel();
         let (filewatcher_recv, file_watcher) = FileWatcher::new(log_file_path, filewatcher_recv);
@@ -120,18 +139,27 @@ impl IOManager {
             response_send: resp_tx,
 
             parser: G15Parser::new(),
-            regex_status: Regex::new(REGEX_STATUS).expect("Compile static regex"),
-            regex_chat: Regex::new(REGEX_CHAT).expect("Compile static regex"),
-            regex_kill: Regex::new(REGEX_KILL).expect("Compile static regex"),
-            regex_hostname: Regex::new(REGEX_HOSTNAME).expect("Compile static regex"),
-            regex_ip: Regex::new(REGEX_IP).expect("Compile static regex"),
-            regex_map: Regex::new(REGEX_MAP).expect("Compile static regex"),
-            regex_playercount: Regex::new(REGEX_PLAYERCOUNT).expect("Compile static regex"),
+            noise_filters: noise::default_filters(),
+            regex_status: compile_line_format(&line_formats.status, REGEX_STATUS),
+            regex_chat: compile_line_format(&line_formats.chat, REGEX_CHAT),
+            regex_party_chat: compile_line_format(&line_formats.party_chat, REGEX_PARTY_CHAT),
+            regex_kill: compile_line_format(&line_formats.kill, REGEX_KILL),
+            regex_hostname: compile_line_format(&line_formats.hostname, REGEX_HOSTNAME),
+            regex_ip: compile_line_format(&line_formats.ip, REGEX_IP),
+            regex_map: compile_line_format(&line_formats.map, REGEX_MAP),
+            regex_map_change: compile_line_format(&line_formats.map_change, REGEX_MAP_CHANGE),
+            regex_playercount: compile_line_format(&line_formats.playercount, REGEX_PLAYERCOUNT),
         };
 
         (resp_rx, inner)
     }
 
+    /// Drop console lines matching any of `filters` before they are parsed, rather than the
+    /// default ones
+    pub fn set_noise_filters(&mut self, filters: Vec<NoiseFilter>) {
+        self.noise_filters = filters;
+    }
+
     /// Start the IO manager loop. This will block until the channel is closed, so usually it should be spawned in a separate `tokio::task`
     pub async fn io_loop(&mut self) {
         // File watcher
@@ -149,43 +177,76 @@ impl IOManager {
         });
 
         loop {
-            tokio::select! {
+            let sent = tokio::select! {
                 message = self.message_recv.recv() => {
-                    self.handle_message(message.expect("Main loop ded"));
+                    let Some(message) = message else {
+                        tracing::info!("IO request channel closed, shutting down.");
+                        break;
+                    };
+                    self.handle_message(message);
+                    Ok(())
                 },
                 command_response = self.command_recv.recv() => {
-                    let out = self.read_command_response(command_response.expect("Failed to receive command response"));
-                    if !out.is_empty() {
-                        self.response_send.send(out).expect("Main loop ded");
+                    let Some(command_response) = command_response else {
+                        tracing::error!("Command manager has stopped, shutting down IO manager.");
+                        break;
+                    };
+                    let out = self.read_command_response(command_response);
+                    if out.is_empty() {
+                        Ok(())
+                    } else {
+                        self.response_send.send(out)
                     }
                 },
                 log_line = self.filewatcher_recv.recv() => {
-                    if let Some(out) = self.read_log_line(&log_line.expect("Failed to receive next file line")) {
-                        self.response_send.send(vec![out]).expect("Main loop ded");
+                    let Some(log_line) = log_line else {
+                        tracing::error!("File watcher has stopped, shutting down IO manager.");
+                        break;
+                    };
+                    match self.read_console_line(&log_line) {
+                        Some(out) => self.response_send.send(vec![out]),
+                        None => Ok(()),
                     }
                 }
+            };
+
+            if sent.is_err() {
+                tracing::warn!("Lost connection to main thread, shutting down IO manager.");
+                break;
             }
         }
     }
 
     fn handle_message(&mut self, message: IOManagerMessage) {
-        match message {
+        let sent = match message {
             IOManagerMessage::SetLogFilePath(path) => self
                 .filewatcher_send
                 .send(FileWatcherCommand::SetWatchedFile(path))
-                .unwrap(),
+                .is_ok(),
             IOManagerMessage::SetRconPassword(password) => self
                 .command_send
                 .send(CommandManagerMessage::SetRconPassword(password))
-                .unwrap(),
+                .is_ok(),
             IOManagerMessage::SetRconPort(port) => self
                 .command_send
                 .send(CommandManagerMessage::SetRconPort(port))
-                .unwrap(),
-            IOManagerMessage::RunCommand(cmd) => self
+                .is_ok(),
+            IOManagerMessage::SetCommandAllowlist(allowlist) => self
+                .command_send
+                .send(CommandManagerMessage::SetAllowlist(allowlist))
+                .is_ok(),
+            IOManagerMessage::SetNoiseFilters(filters) => {
+                self.noise_filters = filters;
+                true
+            }
+            IOManagerMessage::RunCommand(cmd, origin) => self
                 .command_send
-                .send(CommandManagerMessage::RunCommand(cmd))
-                .unwrap(),
+                .send(CommandManagerMessage::RunCommand(cmd, origin))
+                .is_ok(),
+        };
+
+        if !sent {
+            tracing::error!("Failed to forward IO message, the receiving task has stopped.");
         }
     }
 
@@ -208,6 +269,21 @@ impl IOManager {
         out
     }
 
+    /// Read a line from the console log, dropping it straight away if it's noise
+    fn read_console_line(&self, line: &str) -> Option<IOOutput> {
+        if let Some(filter) = noise::matching(&self.noise_filters, line) {
+            noise::record_filtered(filter);
+            return None;
+        }
+        let out = self.read_log_line(line);
+        if out.is_some() {
+            noise::record_parsed();
+        } else if !line.trim().is_empty() {
+            noise::record_unparsed(line);
+        }
+        out
+    }
+
     fn read_log_line(&self, line: &str) -> Option<IOOutput> {
         // Match status
         if let Some(caps) = self.regex_status.captures(line) {
@@ -216,6 +292,11 @@ impl IOManager {
                 Err(e) => tracing::error!("Error parsing status line: {:?}", e),
             }
         }
+        // Match party chat first, so it isn't read as chat from a player called "(PARTY)"
+        if let Some(caps) = self.regex_party_chat.captures(line) {
+            let chat = ChatMessage::parse_party(caps);
+            return Some(IOOutput::Chat(chat));
+        }
         // Match chat message
         if let Some(caps) = self.regex_chat.captures(line) {
             let chat = ChatMessage::parse(caps);
@@ -236,8 +317,12 @@ impl IOManager {
             let ip = ServerIP::parse(caps);
             return Some(IOOutput::ServerIP(ip));
         }
-        // Match server map
-        if let Some(caps) = self.regex_map.captures(line) {
+        // Match server map, from status or on connecting
+        if let Some(caps) = self
+            .regex_map
+            .captures(line)
+            .or_else(|| self.regex_map_change.captures(line))
+        {
             let map = Map::parse(caps);
             return Some(IOOutput::Map(map));
         }
@@ -251,6 +336,15 @@ impl IOManager {
     }
 }
 
+/// Compile a console line format from a game profile, falling back to the TF2 one if it isn't
+/// a valid regex.
+fn compile_line_format(format: &str, default: &str) -> Regex {
+    Regex::new(format).unwrap_or_else(|e| {
+        tracing::error!("Invalid console line format {:?}, using the TF2 one: {}", format, e);
+        Regex::new(default).expect("Compile static regex")
+    })
+}
+
 // Iterator impl and other convenience stuff we don't need to worry about
 
 impl Display for Command {
@@ -263,17 +357,12 @@ impl Display for Command {
             }
             Command::Say(message) => write!(f, "say \"{}\"", message),
             Command::SayTeam(message) => write!(f, "say_team \"{}\"", message),
+            Command::PartyChat(message) => write!(f, "tf_party_chat \"{}\"", message),
             Command::Custom(command) => write!(f, "{}", command),
         }
     }
 }
 
-impl Default for KickReason {
-    fn default() -> Self {
-        Self::None
-    }
-}
-
 impl Display for KickReason {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.write_str(match self {
@@ -0,0 +1,39 @@
+use std::sync::Arc;
+
+use crate::{player::Players, steamid::SteamID};
+
+use super::IOOutput;
+
+pub mod chat_spam;
+pub mod copied_name;
+
+/// Something a console analyser wants the user to know about
+#[derive(Debug, Clone)]
+pub enum ConsoleAlert {
+    /// A player has done something suspicious. They are tagged with the analyser's name, and it
+    /// counts as a signal against them in votekicks.
+    Flag { steamid: SteamID, reason: Arc<str> },
+    /// Anything else worth telling the user, shown as a system event
+    Notice(Arc<str>),
+}
+
+/// A self-contained detector that watches lines from the console as they are read, the console
+/// counterpart to [crate::demo::analyser::DemoAnalyserPlugin].
+///
+/// Events are seen before the rest of the client has handled them, so `players` doesn't include
+/// any changes the event itself causes.
+pub trait ConsoleAnalyserPlugin: Send + Sync {
+    /// Short name used as the tag and signal for flagged players
+    fn name(&self) -> &'static str;
+
+    /// Called for every line of console output that was recognised
+    fn on_event(&mut self, event: &IOOutput, players: &Players) -> Vec<ConsoleAlert>;
+}
+
+/// The analysers that are run over the console unless others are registered
+pub fn default_analysers() -> Vec<Box<dyn ConsoleAnalyserPlugin>> {
+    vec![
+        Box::<chat_spam::ChatSpam>::default(),
+        Box::<copied_name::CopiedName>::default(),
+    ]
+}
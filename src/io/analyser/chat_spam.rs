@@ -0,0 +1,73 @@
+use std::{
+    collections::HashMap,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use crate::{io::IOOutput, locale::tr, player::Players, steamid::SteamID};
+
+use super::{ConsoleAlert, ConsoleAnalyserPlugin};
+
+/// Messages sent more than this long after the previous one start a new run
+const SPAM_WINDOW: Duration = Duration::from_secs(60);
+/// How many times the same message has to be sent within the window to be flagged
+const SPAM_REPEATS: u32 = 4;
+
+struct Run {
+    message: Arc<str>,
+    count: u32,
+    last: Instant,
+}
+
+/// Flags players who keep sending the same chat message, which is typical of bots
+#[derive(Default)]
+pub struct ChatSpam {
+    runs: HashMap<SteamID, Run>,
+}
+
+impl ConsoleAnalyserPlugin for ChatSpam {
+    fn name(&self) -> &'static str {
+        "ChatSpam"
+    }
+
+    fn on_event(&mut self, event: &IOOutput, players: &Players) -> Vec<ConsoleAlert> {
+        // A run ends once the window has passed or the player has left, so there's no need to
+        // keep it around for the rest of the session
+        let now = Instant::now();
+        self.runs.retain(|steamid, run| {
+            now.duration_since(run.last) <= SPAM_WINDOW && players.connected.contains(steamid)
+        });
+
+        let IOOutput::Chat(chat) = event else {
+            return Vec::new();
+        };
+        let Some(steamid) = players.find_connected_by_name(&chat.player_name) else {
+            return Vec::new();
+        };
+
+        let run = self.runs.entry(steamid).or_insert_with(|| Run {
+            message: chat.message.clone(),
+            count: 0,
+            last: now,
+        });
+        if run.message != chat.message {
+            run.message = chat.message.clone();
+            run.count = 0;
+        }
+        run.count += 1;
+        run.last = now;
+
+        // Only flag once per run
+        if run.count != SPAM_REPEATS {
+            return Vec::new();
+        }
+        vec![ConsoleAlert::Flag {
+            steamid,
+            reason: tr(
+                "chat-spam-reason",
+                &[("message", &chat.message), ("count", &SPAM_REPEATS)],
+            )
+            .into(),
+        }]
+    }
+}
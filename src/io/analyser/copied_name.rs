@@ -0,0 +1,52 @@
+use std::collections::HashSet;
+
+use crate::{io::IOOutput, locale::tr, names, player::Players, steamid::SteamID};
+
+use super::{ConsoleAlert, ConsoleAnalyserPlugin};
+
+/// Flags players who join with a copy of someone else's name that only differs by invisible
+/// characters or lookalike letters. The game won't let two players have the same name, so bots
+/// impersonating a player disguise the copy this way.
+#[derive(Default)]
+pub struct CopiedName {
+    /// Players already flagged, so each is only flagged once
+    flagged: HashSet<SteamID>,
+}
+
+impl ConsoleAnalyserPlugin for CopiedName {
+    fn name(&self) -> &'static str {
+        "CopiedName"
+    }
+
+    fn on_event(&mut self, event: &IOOutput, players: &Players) -> Vec<ConsoleAlert> {
+        let IOOutput::Status(status) = event else {
+            return Vec::new();
+        };
+        if self.flagged.contains(&status.steamid) {
+            return Vec::new();
+        }
+
+        let folded = names::fold(&status.name);
+        if folded.is_empty() {
+            return Vec::new();
+        }
+        // The player who has been on the server longer is the one whose name was copied
+        let original = players
+            .connected
+            .iter()
+            .filter(|s| **s != status.steamid)
+            .filter_map(|s| players.game_info.get(s))
+            .find(|gi| {
+                gi.time > status.time && gi.name != status.name && names::fold(&gi.name) == folded
+            });
+        let Some(original) = original else {
+            return Vec::new();
+        };
+
+        self.flagged.insert(status.steamid);
+        vec![ConsoleAlert::Flag {
+            steamid: status.steamid,
+            reason: tr("copied-name-reason", &[("name", &original.name)]).into(),
+        }]
+    }
+}
@@ -0,0 +1,265 @@
+use std::{
+    collections::VecDeque,
+    fs::{self, OpenOptions},
+    io::{BufRead, BufReader, Write},
+    path::PathBuf,
+    sync::{Arc, Mutex},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    analytics::history::now,
+    settings::{ConfigFilesError, Settings},
+};
+
+/// Number of entries kept in memory to answer queries
+const MAX_RECENT: usize = 1000;
+/// Responses longer than this (in bytes) are cut short in the log
+const MAX_RESPONSE_LEN: usize = 1024;
+/// Once the log file grows past this size (in bytes) it is moved aside and a new one started
+const MAX_FILE_SIZE: u64 = 5 * 1024 * 1024;
+
+/// Commands the client runs every few seconds to read the game's state. When the client runs them
+/// and they go through, they are left out of the log, which would otherwise be nothing but these.
+const POLLING_COMMANDS: &[&str] = &["status", "g15_dumpplayer"];
+
+/// Commands that can be run if the user hasn't configured an allowlist. These are everything the
+/// client itself needs.
+pub const DEFAULT_ALLOWLIST: &[&str] = &[
+    "status",
+    "g15_dumpplayer",
+    "say",
+    "say_team",
+    "tf_party_chat",
+    "callvote",
+];
+
+/// Who asked for a command to be run
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum CommandOrigin {
+    /// The user, through the web API
+    User,
+    /// The client itself, e.g. refreshing the player list
+    Automation,
+}
+
+/// What happened to a command
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum AuditOutcome {
+    /// Sent to the game, with its (possibly shortened) response
+    Sent { response: Arc<str> },
+    /// Not sent because it isn't in the allowlist
+    Blocked { command: Arc<str> },
+    /// Not sent because an automation interlock stopped it, see [crate::interlocks]
+    Interlocked { reason: Arc<str> },
+    /// Sending it over RCON failed
+    Failed { error: Arc<str> },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AuditEntry {
+    /// Unix timestamp (seconds)
+    pub time: u64,
+    pub command: Arc<str>,
+    pub origin: CommandOrigin,
+    pub outcome: AuditOutcome,
+}
+
+/// Which audit entries to return
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct AuditFilter {
+    /// Only entries at or after this unix timestamp (seconds)
+    pub since: u64,
+    pub origin: Option<CommandOrigin>,
+    /// Only commands that were blocked by the allowlist
+    pub blocked: bool,
+    pub limit: Option<usize>,
+}
+
+impl AuditFilter {
+    fn matches(&self, entry: &AuditEntry) -> bool {
+        entry.time >= self.since
+            && self.origin.is_none_or(|origin| origin == entry.origin)
+            && (!self.blocked || matches!(entry.outcome, AuditOutcome::Blocked { .. }))
+    }
+}
+
+/// The console commands the client is allowed to run, by name
+#[derive(Debug, Clone)]
+pub struct CommandAllowlist(Vec<Arc<str>>);
+
+impl CommandAllowlist {
+    pub fn new(commands: &[Arc<str>]) -> CommandAllowlist {
+        CommandAllowlist(commands.to_vec())
+    }
+
+    pub fn commands(&self) -> &[Arc<str>] {
+        &self.0
+    }
+
+    /// Check every command in a line of console input (commands can be chained with `;`) is
+    /// allowed. Returns the first command that isn't.
+    pub fn check<'a>(&self, line: &'a str) -> Result<(), &'a str> {
+        for command in split_commands(line) {
+            let name = command.split_whitespace().next().unwrap_or_default();
+            if !self
+                .0
+                .iter()
+                .any(|allowed| allowed.eq_ignore_ascii_case(name))
+            {
+                return Err(command);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Split a line of console input into the commands in it, the same way the game does: on `;`
+/// and newlines that aren't inside quotes
+fn split_commands(line: &str) -> Vec<&str> {
+    let mut commands = Vec::new();
+    let mut quoted = false;
+    let mut start = 0;
+    for (i, c) in line.char_indices() {
+        match c {
+            '"' => quoted = !quoted,
+            ';' if !quoted => {
+                commands.push(&line[start..i]);
+                start = i + 1;
+            }
+            '\n' | '\r' => {
+                commands.push(&line[start..i]);
+                start = i + 1;
+                quoted = false;
+            }
+            _ => {}
+        }
+    }
+    commands.push(&line[start..]);
+    commands
+        .into_iter()
+        .map(str::trim)
+        .filter(|c| !c.is_empty())
+        .collect()
+}
+
+/// Whether every command in a line of console input only reads the game's state
+fn is_polling(line: &str) -> bool {
+    split_commands(line).iter().all(|command| {
+        let name = command.split_whitespace().next().unwrap_or_default();
+        POLLING_COMMANDS
+            .iter()
+            .any(|polling| polling.eq_ignore_ascii_case(name))
+    })
+}
+
+struct AuditLog {
+    path: Option<PathBuf>,
+    recent: VecDeque<AuditEntry>,
+}
+
+/// Every command the client has tried to run over RCON. Entries are appended to a file in the
+/// config directory as they happen, and the most recent are kept in memory for the web API.
+/// Cloning gives another handle to the same log.
+#[derive(Clone)]
+pub struct CommandAudit {
+    log: Arc<Mutex<AuditLog>>,
+}
+
+impl CommandAudit {
+    /// Open the audit log in the default location, loading its most recent entries
+    pub fn load_or_create() -> CommandAudit {
+        let path = Self::locate_audit_file()
+            .map_err(|e| tracing::warn!("Failed to find command audit log location: {:?}", e))
+            .ok();
+
+        let mut recent = VecDeque::new();
+        if let Some(file) = path.as_ref().and_then(|p| fs::File::open(p).ok()) {
+            for line in BufReader::new(file).lines().map_while(Result::ok) {
+                match serde_json::from_str::<AuditEntry>(&line) {
+                    Ok(entry) => {
+                        if recent.len() >= MAX_RECENT {
+                            recent.pop_front();
+                        }
+                        recent.push_back(entry);
+                    }
+                    Err(e) => tracing::warn!("Skipping unreadable command audit entry: {}", e),
+                }
+            }
+        }
+
+        CommandAudit {
+            log: Arc::new(Mutex::new(AuditLog { path, recent })),
+        }
+    }
+
+    pub fn locate_audit_file() -> Result<PathBuf, ConfigFilesError> {
+        Settings::locate_config_directory().map(|dir| dir.join("command_audit.jsonl"))
+    }
+
+    /// Add an entry to the log, unless it is a routine poll of the game's state that went
+    /// through, see [POLLING_COMMANDS]
+    pub fn record(&self, command: &str, origin: CommandOrigin, mut outcome: AuditOutcome) {
+        if origin == CommandOrigin::Automation
+            && matches!(outcome, AuditOutcome::Sent { .. })
+            && is_polling(command)
+        {
+            return;
+        }
+
+        if let AuditOutcome::Sent { response } = &mut outcome {
+            if response.len() > MAX_RESPONSE_LEN {
+                let mut end = MAX_RESPONSE_LEN;
+                while !response.is_char_boundary(end) {
+                    end -= 1;
+                }
+                *response = format!("{}...", &response[..end]).into();
+            }
+        }
+        let entry = AuditEntry {
+            time: now(),
+            command: command.into(),
+            origin,
+            outcome,
+        };
+
+        let mut log = self.log.lock().unwrap();
+        if let Some(path) = &log.path {
+            if let Err(e) = append(path, &entry) {
+                tracing::error!("Failed to write to command audit log: {:?}", e);
+            }
+        }
+        if log.recent.len() >= MAX_RECENT {
+            log.recent.pop_front();
+        }
+        log.recent.push_back(entry);
+    }
+
+    /// Recent entries matching `filter`, most recent first
+    pub fn query(&self, filter: &AuditFilter) -> Vec<AuditEntry> {
+        self.log
+            .lock()
+            .unwrap()
+            .recent
+            .iter()
+            .rev()
+            .filter(|entry| filter.matches(entry))
+            .take(filter.limit.unwrap_or(usize::MAX))
+            .cloned()
+            .collect()
+    }
+}
+
+fn append(path: &PathBuf, entry: &AuditEntry) -> std::io::Result<()> {
+    if fs::metadata(path).is_ok_and(|m| m.len() > MAX_FILE_SIZE) {
+        fs::rename(path, path.with_extension("jsonl.old"))?;
+    }
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    let line = serde_json::to_string(entry).map_err(std::io::Error::from)?;
+    writeln!(file, "{}", line)
+}
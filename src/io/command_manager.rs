@@ -8,7 +8,9 @@ use tokio::{
     time::timeout,
 };
 
+use super::audit::{AuditOutcome, CommandAllowlist, CommandAudit, CommandOrigin};
 use super::Command;
+use crate::interlocks::{self, InterlockError};
 
 #[derive(Debug, Error)]
 pub enum CommandManagerError {
@@ -50,9 +52,10 @@ enum ErrorState {
 }
 
 pub enum CommandManagerMessage {
-    RunCommand(Command),
+    RunCommand(Command, CommandOrigin),
     SetRconPassword(Arc<str>),
     SetRconPort(u16),
+    SetAllowlist(CommandAllowlist),
 }
 
 pub struct CommandManager {
@@ -61,6 +64,9 @@ pub struct CommandManager {
     rcon_port: u16,
     current_err_state: ErrorState,
     previous_err_state: ErrorState,
+    /// Every command is checked against this before it is sent
+    allowlist: CommandAllowlist,
+    audit: CommandAudit,
     request_recv: UnboundedReceiver<CommandManagerMessage>,
     response_send: UnboundedSender<Arc<str>>,
 }
@@ -69,6 +75,8 @@ impl CommandManager {
     pub fn new(
         rcon_password: Arc<str>,
         rcon_port: u16,
+        allowlist: CommandAllowlist,
+        audit: CommandAudit,
         recv: UnboundedReceiver<CommandManagerMessage>,
     ) -> (UnboundedReceiver<Arc<str>>, CommandManager) {
         let (resp_tx, resp_rx) = unbounded_channel();
@@ -79,6 +87,8 @@ impl CommandManager {
             rcon_port,
             current_err_state: ErrorState::Never,
             previous_err_state: ErrorState::Never,
+            allowlist,
+            audit,
             request_recv: recv,
             response_send: resp_tx,
         };
@@ -145,20 +155,43 @@ impl CommandManager {
                 }
             }
 
-            match self
-                .request_recv
-                .recv()
-                .await
-                .expect("The main IO Loop experienced a fatal error.")
-            {
-                CommandManagerMessage::RunCommand(cmd) => {
+            let Some(message) = self.request_recv.recv().await else {
+                tracing::info!("Command request channel closed, shutting down.");
+                return;
+            };
+
+            match message {
+                CommandManagerMessage::RunCommand(cmd, origin) => {
+                    // Automation can always read from the game, but the kill switch stops it doing anything in it
+                    let acts = !matches!(cmd, Command::Status | Command::G15);
+                    let cmd = format!("{}", cmd);
+                    if origin == CommandOrigin::Automation && acts && !interlocks::automation_enabled() {
+                        tracing::info!("Not running \"{}\", in-game automation is disabled.", cmd);
+                        self.audit.record(&cmd, origin, AuditOutcome::Interlocked { reason: InterlockError::Disabled.to_string().into() });
+                        continue;
+                    }
+                    if let Err(blocked) = self.allowlist.check(&cmd) {
+                        tracing::warn!("Refusing to run \"{}\", {} is not in the command allowlist.", cmd, blocked);
+                        self.audit.record(&cmd, origin, AuditOutcome::Blocked { command: blocked.into() });
+                        continue;
+                    }
+
                     // Only attempt to run commands if the error state indicates we have a valid RCon client.
                     // This prevents getting shunted by the TF2 client for repeated Auth failures
                     if self.current_err_state == ErrorState::Okay {
-                        let cmd = format!("{}", cmd);
-                        if let Err(e) = self.run_command(&cmd).await {
-                            self.previous_err_state = ErrorState::Okay;
-                            self.current_err_state = ErrorState::Current(e);
+                        match self.run_command(&cmd).await {
+                            Ok(response) => {
+                                self.audit.record(&cmd, origin, AuditOutcome::Sent { response: response.clone() });
+                                if self.response_send.send(response).is_err() {
+                                    tracing::warn!("Lost connection to IO manager, shutting down command manager.");
+                                    return;
+                                }
+                            }
+                            Err(e) => {
+                                self.audit.record(&cmd, origin, AuditOutcome::Failed { error: e.to_string().into() });
+                                self.previous_err_state = ErrorState::Okay;
+                                self.current_err_state = ErrorState::Current(e);
+                            }
                         }
                     }
                 }
@@ -173,26 +206,27 @@ impl CommandManager {
                     self.rcon_port = port;
                     self.current_err_state = ErrorState::Never;
                 }
+                CommandManagerMessage::SetAllowlist(allowlist) => {
+                    self.allowlist = allowlist;
+                }
             }
         }
     }
 
-    pub async fn run_command(&mut self, command: &str) -> Result<(), CommandManagerError> {
+    /// Run a command over RCon and return the response
+    pub async fn run_command(&mut self, command: &str) -> Result<Arc<str>, CommandManagerError> {
         let rcon = self.rcon.as_mut().unwrap();
 
         tracing::debug!("Running command \"{}\"", command);
         let result = rcon
             .cmd(command)
             .await
-            .map_err(|e| {
+            .inspect_err(|_| {
                 self.rcon = None;
-                e
             })?
             .into();
 
-        self.response_send.send(result).unwrap();
-
-        Ok(())
+        Ok(result)
     }
 
     async fn try_reconnect(&mut self) -> Result<(), CommandManagerError> {
@@ -8,6 +8,10 @@ use tokio::{
     sync::mpsc::{error::TryRecvError, unbounded_channel, UnboundedReceiver, UnboundedSender},
 };
 
+#[cfg(any(test, feature = "fault-injection"))]
+use crate::faults;
+use crate::throughput::{self, Pipeline};
+
 pub enum FileWatcherCommand {
     SetWatchedFile(PathBuf),
 }
@@ -55,6 +59,11 @@ impl FileWatcher {
         }
 
         loop {
+            if self.response_send.is_closed() {
+                tracing::warn!("Lost connection to IO manager, shutting down file watcher.");
+                break;
+            }
+
             match self.request_recv.try_recv() {
                 Ok(FileWatcherCommand::SetWatchedFile(new_path)) => {
                     self.file_path = new_path;
@@ -137,6 +146,12 @@ impl FileWatcher {
             .read_to_end(&mut buff)
             .await
             .context("Failed to read file.")?;
+        #[cfg(any(test, feature = "fault-injection"))]
+        let read_size = match short_read(file, &mut buff).await? {
+            Some(0) => return Ok(()),
+            Some(kept) => kept,
+            None => read_size,
+        };
 
         file.last_size += read_size as u64;
 
@@ -157,13 +172,40 @@ impl FileWatcher {
 
         // Send newly read lines over channel
         let data_str = String::from_utf8_lossy(&buff);
-        data_str
-            .lines()
-            .filter(|x| !x.trim().is_empty())
-            .for_each(|l| {
-                self.response_send.send(l.into()).expect("Main loop ded?");
-            });
+        let mut sent = 0;
+        for l in data_str.lines().filter(|x| !x.trim().is_empty()) {
+            self.response_send
+                .send(l.into())
+                .map_err(|_| anyhow!("Lost connection to IO manager."))?;
+            sent += 1;
+        }
+
+        throughput::record(Pipeline::Console, sent);
+        if let Some(lag) = meta.modified().ok().and_then(|m| m.elapsed().ok()) {
+            throughput::record_lag(Pipeline::Console, lag);
+        }
 
         Ok(())
     }
 }
+
+/// Cuts a read of the console log short if a fault has been injected (see [faults::short_read]),
+/// returning how much was kept. Only whole lines are kept, the rest is read again next time.
+#[cfg(any(test, feature = "fault-injection"))]
+async fn short_read(file: &mut OpenFile, buff: &mut Vec<u8>) -> Result<Option<usize>> {
+    let kept = faults::short_read("console log", buff.len());
+    if kept >= buff.len() {
+        return Ok(None);
+    }
+
+    let kept = buff[..kept]
+        .iter()
+        .rposition(|&b| b == b'\n')
+        .map_or(0, |i| i + 1);
+    buff.truncate(kept);
+    file.file
+        .seek(SeekFrom::Start(file.last_size + kept as u64))
+        .await
+        .context("Failed to seek file.")?;
+    Ok(Some(kept))
+}
@@ -5,10 +5,10 @@ use std::sync::Arc;
 
 use anyhow::Result;
 use regex::{Captures, Regex};
-use steamid_ng::SteamID;
 use thiserror::Error;
 
 use crate::player::Team;
+use crate::steamid::SteamID;
 
 #[derive(Debug, Error, Clone)]
 pub enum G15Error {
@@ -0,0 +1,141 @@
+//! Filters for lines the game prints often and that never carry anything the client reads, e.g.
+//! achievements, item drops and messages from the item server. Matching lines are dropped with
+//! plain string comparisons before any of the console regexes are tried, which saves a lot of
+//! work when the item server is spamming the console. They are also kept out of the unparsed line
+//! stats, so lines that were expected to be read but weren't stand out there.
+
+use std::{
+    collections::{BTreeMap, VecDeque},
+    sync::{Arc, Mutex},
+};
+
+use serde::{Deserialize, Serialize};
+
+/// Unparsed lines kept for the stats
+const RECENT_UNPARSED: usize = 20;
+/// Separates a player's name from their message in chat, which filters never drop
+const CHAT_SEPARATOR: &str = " :  ";
+
+/// A class of console lines to drop before parsing
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NoiseFilter {
+    /// Shown in the stats of dropped lines
+    pub name: Arc<str>,
+    /// Lines starting with any of these are dropped
+    #[serde(default)]
+    pub prefixes: Vec<Arc<str>>,
+    /// Lines containing any of these are dropped, unless they are chat. A player can't make their
+    /// messages disappear by typing one of these.
+    #[serde(default)]
+    pub contains: Vec<Arc<str>>,
+}
+
+impl NoiseFilter {
+    fn new(name: &str, prefixes: &[&str], contains: &[&str]) -> NoiseFilter {
+        NoiseFilter {
+            name: name.into(),
+            prefixes: prefixes.iter().map(|&p| p.into()).collect(),
+            contains: contains.iter().map(|&c| c.into()).collect(),
+        }
+    }
+
+    pub fn matches(&self, line: &str) -> bool {
+        self.prefixes.iter().any(|p| line.starts_with(&**p))
+            || (!line.contains(CHAT_SEPARATOR) && self.contains.iter().any(|c| line.contains(&**c)))
+    }
+}
+
+/// The filters used unless others are configured, for the noisiest lines TF2 prints
+pub fn default_filters() -> Vec<NoiseFilter> {
+    vec![
+        NoiseFilter::new("achievements", &[], &[" has earned the achievement "]),
+        NoiseFilter::new(
+            "itemDrops",
+            &[],
+            &[
+                " has found: ",
+                " has crafted: ",
+                " has traded for: ",
+                " has unboxed: ",
+                " has received a gift: ",
+            ],
+        ),
+        NoiseFilter::new(
+            "itemServer",
+            &[
+                "[PartyClient]",
+                "CTFGCClientSystem",
+                "Lobby updated",
+                "Differing lobby received.",
+                "SOCache",
+            ],
+            &[],
+        ),
+        NoiseFilter::new(
+            "missingAssets",
+            &[
+                "Failed to load sound",
+                "SOLID_VPHYSICS static prop with no vphysics model!",
+                "m_face->glyph->bitmap.width is 0",
+            ],
+            &[],
+        ),
+    ]
+}
+
+/// The first of `filters` that drops `line`
+pub fn matching<'a>(filters: &'a [NoiseFilter], line: &str) -> Option<&'a NoiseFilter> {
+    filters.iter().find(|f| f.matches(line))
+}
+
+/// What became of the lines read from the console log
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ConsoleLineStats {
+    /// Lines read as something the client understands
+    pub parsed: u64,
+    /// Lines dropped by each filter, by name
+    pub filtered: BTreeMap<Arc<str>, u64>,
+    /// Lines that weren't filtered but didn't match anything either
+    pub unparsed: u64,
+    /// The latest unparsed lines, oldest first
+    pub recent_unparsed: VecDeque<Arc<str>>,
+}
+
+static STATS: Mutex<ConsoleLineStats> = Mutex::new(ConsoleLineStats {
+    parsed: 0,
+    filtered: BTreeMap::new(),
+    unparsed: 0,
+    recent_unparsed: VecDeque::new(),
+});
+
+fn with_stats(f: impl FnOnce(&mut ConsoleLineStats)) {
+    f(&mut STATS.lock().unwrap());
+}
+
+/// Count a line that was read as something
+pub fn record_parsed() {
+    with_stats(|stats| stats.parsed += 1);
+}
+
+/// Count a line dropped by `filter`
+pub fn record_filtered(filter: &NoiseFilter) {
+    with_stats(|stats| *stats.filtered.entry(filter.name.clone()).or_default() += 1);
+}
+
+/// Count a line nothing matched, keeping it among the recent ones
+pub fn record_unparsed(line: &str) {
+    with_stats(|stats| {
+        stats.unparsed += 1;
+        if stats.recent_unparsed.len() >= RECENT_UNPARSED {
+            stats.recent_unparsed.pop_front();
+        }
+        stats.recent_unparsed.push_back(line.into());
+    });
+}
+
+/// Lines read from the console log so far, see [ConsoleLineStats]
+pub fn stats() -> ConsoleLineStats {
+    STATS.lock().unwrap().clone()
+}
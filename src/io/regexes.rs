@@ -6,9 +6,9 @@ use std::sync::Arc;
 use anyhow::Context;
 use anyhow::Result;
 use regex::Captures;
-use steamid_ng::SteamID;
 
 use crate::player::PlayerState;
+use crate::steamid::SteamID;
 
 /*
     Useful commands:
@@ -39,6 +39,8 @@ impl ServerIP {
 }
 
 pub const REGEX_MAP: &str = r#"^map     : (.+) at: .*$"#;
+/// Printed to the console when connecting to a server or changing level
+pub const REGEX_MAP_CHANGE: &str = r#"^Map: (.+)$"#;
 #[derive(Debug, Clone)]
 pub struct Map(pub Arc<str>);
 impl Map {
@@ -103,11 +105,19 @@ impl PlayerKill {
 ///    1: Message
 pub const REGEX_CHAT: &str = r#"^(?:\*DEAD\*)?(?:\(TEAM\))?\s?(.*)\s:\s\s(.*)$"#;
 
+/// Party chat message, which only the members of the user's party can see
+/// Matches:
+///    0: Player
+///    1: Message
+pub const REGEX_PARTY_CHAT: &str = r#"^\(PARTY\)\s?(.*)\s:\s\s(.*)$"#;
+
 #[derive(Debug, Clone)]
 pub struct ChatMessage {
     pub player_name: Arc<str>,
     pub steamid: Option<Arc<str>>,
     pub message: Arc<str>,
+    /// Sent in party chat, so the player may not be on the server at all
+    pub party: bool,
 }
 
 impl ChatMessage {
@@ -116,6 +126,14 @@ impl ChatMessage {
             player_name: caps[1].into(),
             steamid: None,
             message: caps[2].into(),
+            party: false,
+        }
+    }
+
+    pub fn parse_party(caps: Captures) -> ChatMessage {
+        ChatMessage {
+            party: true,
+            ..ChatMessage::parse(caps)
         }
     }
 }
@@ -171,59 +189,3 @@ fn get_time(input: &str) -> Option<u32> {
 
     Some(t)
 }
-
-#[allow(dead_code)]
-const INVIS_CHARS: &[char] = &[
-    '\u{00a0}',
-    '\u{00ad}',
-    '\u{034f}',
-    '\u{061c}',
-    '\u{115f}',
-    '\u{1160}',
-    '\u{17b4}',
-    '\u{17b5}',
-    '\u{180e}',
-    '\u{2000}',
-    '\u{2001}',
-    '\u{2002}',
-    '\u{2003}',
-    '\u{2004}',
-    '\u{2005}',
-    '\u{2006}',
-    '\u{2007}',
-    '\u{2008}',
-    '\u{2009}',
-    '\u{200a}',
-    '\u{200b}',
-    '\u{200c}',
-    '\u{200d}',
-    '\u{200e}',
-    '\u{200f}',
-    '\u{202f}',
-    '\u{205f}',
-    '\u{2060}',
-    '\u{2061}',
-    '\u{2062}',
-    '\u{2063}',
-    '\u{2064}',
-    '\u{206a}',
-    '\u{206b}',
-    '\u{206c}',
-    '\u{206d}',
-    '\u{206e}',
-    '\u{206f}',
-    '\u{3000}',
-    '\u{2800}',
-    '\u{3164}',
-    '\u{feff}',
-    '\u{ffa0}',
-    '\u{1d159}',
-    '\u{1d173}',
-    '\u{1d174}',
-    '\u{1d175}',
-    '\u{1d176}',
-    '\u{1d177}',
-    '\u{1d178}',
-    '\u{1d179}',
-    '\u{1d17a}',
-];
@@ -0,0 +1,102 @@
+use std::{collections::VecDeque, ops::Deref, sync::Arc};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{player::KillLine, steamid::SteamID};
+
+/// Most kills kept in the feed
+const MAX_KILLS_LEN: usize = 500;
+/// Longest (seconds) between the console and the demo reporting the same kill. Demos are only
+/// written every few seconds, so their kills usually turn up a little after the console's.
+const MATCH_WINDOW: u64 = 30;
+
+/// Where a kill in the feed was seen
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum KillSource {
+    Console,
+    Demo,
+    /// Seen in both, and merged into one kill
+    Both,
+}
+
+/// Kills from the console and the demo being recorded, merged so each kill only appears once.
+/// The demo identifies players by SteamID rather than by name so its details are preferred, but
+/// the console reports kills as they happen so a kill is added as soon as either sees it.
+#[derive(Debug, Default)]
+pub struct KillFeed {
+    /// Oldest first
+    kills: VecDeque<KillLine>,
+}
+
+impl Deref for KillFeed {
+    type Target = VecDeque<KillLine>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.kills
+    }
+}
+
+fn same_player(
+    a: Option<SteamID>,
+    a_name: &Arc<str>,
+    b: Option<SteamID>,
+    b_name: &Arc<str>,
+) -> bool {
+    match (a, b) {
+        (Some(a), Some(b)) => a == b,
+        _ => a_name == b_name,
+    }
+}
+
+impl KillLine {
+    /// Whether this and `other` are the same kill, as seen by different sources
+    fn same_kill(&self, other: &KillLine) -> bool {
+        self.weapon == other.weapon
+            && self.time.abs_diff(other.time) <= MATCH_WINDOW
+            && same_player(
+                self.killer,
+                &self.killer_name,
+                other.killer,
+                &other.killer_name,
+            )
+            && same_player(
+                self.victim,
+                &self.victim_name,
+                other.victim,
+                &other.victim_name,
+            )
+    }
+}
+
+impl KillFeed {
+    /// Add a kill, merging it into the same kill from the other source if that has already been
+    /// seen. `kill.source` must be [KillSource::Console] or [KillSource::Demo].
+    pub fn add(&mut self, kill: KillLine) {
+        let seen = self
+            .kills
+            .iter_mut()
+            .rev()
+            .take_while(|k| k.time + MATCH_WINDOW >= kill.time)
+            .find(|k| {
+                k.source != KillSource::Both && k.source != kill.source && k.same_kill(&kill)
+            });
+
+        let Some(seen) = seen else {
+            if self.kills.len() >= MAX_KILLS_LEN {
+                self.kills.pop_front();
+            }
+            self.kills.push_back(kill);
+            return;
+        };
+
+        // Keep the console's time, which is when the kill actually happened, and everything
+        // else from the demo
+        let time = seen.time.min(kill.time);
+        if kill.source == KillSource::Demo {
+            *seen = kill;
+        }
+        seen.time = time;
+        seen.source = KillSource::Both;
+    }
+}
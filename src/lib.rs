@@ -1,14 +1,46 @@
+pub mod alts;
+pub mod analytics;
 pub mod args;
+pub mod backend;
+pub mod chat_commands;
+pub mod churn;
 pub mod demo;
+pub mod doctor;
+pub mod events;
+pub mod evidence;
+#[cfg(any(test, feature = "fault-injection"))]
+pub mod faults;
+pub mod friendgraph;
 pub mod gamefinder;
+pub mod gameprofile;
+pub mod groups;
+pub mod interlocks;
 pub mod io;
+pub mod killfeed;
 pub mod launchoptions;
+pub mod lists;
+pub mod locale;
+pub mod logging;
+pub mod masterbase;
+pub mod names;
+pub mod pause;
+pub mod persistence;
 pub mod player;
 pub mod player_records;
+pub mod rules;
+pub mod secrets;
 pub mod server;
 pub mod settings;
+pub mod snapshot;
 pub mod steamapi;
+pub mod steamid;
+pub mod storage;
+pub mod suggestions;
+pub mod telemetry;
+pub mod throughput;
+pub mod votes;
 pub mod web;
+pub mod webhooks;
 
 pub use clap;
 pub use rcon;
@@ -0,0 +1,541 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::{mpsc, Arc},
+    time::Duration,
+};
+
+use notify::{Config, Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc::UnboundedSender;
+
+use crate::{
+    player_records::{
+        archive::{self, ArchivedRecord},
+        PlayerRecord, Verdict,
+    },
+    settings::{ConfigFilesError, Settings},
+    steamid::SteamID,
+};
+
+use self::{
+    quarantine::{EntryPosition, QuarantineReport, QuarantinedEntry},
+    stats::ListStats,
+};
+
+pub mod quarantine;
+pub mod stats;
+pub mod tf2bd;
+
+/// The formats a list dropped into the lists directory can be in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum ListFormat {
+    /// TF2 Bot Detector playerlist
+    TF2BD,
+    /// A playerlist exported from this application
+    Native,
+}
+
+/// A single player's entry in an imported list
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ListEntry {
+    pub verdict: Verdict,
+    /// The name the player was last seen with, according to the list
+    pub name: Option<Arc<str>>,
+}
+
+/// A list of players that has been imported from a file in the lists directory
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ImportedList {
+    /// File name the list was loaded from, which is also how entries are attributed
+    pub source: Arc<str>,
+    pub format: ListFormat,
+    pub title: Option<Arc<str>>,
+    #[serde(skip)]
+    pub entries: HashMap<SteamID, ListEntry>,
+    /// Entries that couldn't be imported, see [quarantine]
+    #[serde(skip)]
+    pub quarantined: Vec<QuarantinedEntry>,
+    /// How the list changed the last time it was reloaded
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub changes: Option<ListChanges>,
+}
+
+/// The difference between two versions of an imported list
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ListChanges {
+    pub added: usize,
+    /// Entries whose verdict or name changed
+    pub changed: usize,
+    pub removed: usize,
+}
+
+impl ListChanges {
+    /// Compare the entries of the previous version of a list to the new ones
+    pub fn between(
+        old: &HashMap<SteamID, ListEntry>,
+        new: &HashMap<SteamID, ListEntry>,
+    ) -> ListChanges {
+        let mut changes = ListChanges::default();
+        for (steamid, entry) in new {
+            match old.get(steamid) {
+                None => changes.added += 1,
+                Some(old_entry) if old_entry != entry => changes.changed += 1,
+                Some(_) => {}
+            }
+        }
+        changes.removed = old.keys().filter(|s| !new.contains_key(s)).count();
+        changes
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.added == 0 && self.changed == 0 && self.removed == 0
+    }
+}
+
+/// The layouts a list file can have. Entries are left as they are so that each can be checked on
+/// its own, and one bad entry doesn't stop the rest of the list being imported.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ListFile {
+    /// A TF2 Bot Detector playerlist, with a [tf2bd::PlayerEntry] for each player
+    TF2BD {
+        #[serde(default)]
+        file_info: Option<tf2bd::FileInfo>,
+        players: Vec<serde_json::Value>,
+    },
+    Archive {
+        format: Arc<str>,
+        version: u32,
+        records: Vec<serde_json::Value>,
+    },
+    Native {
+        records: HashMap<Arc<str>, serde_json::Value>,
+    },
+}
+
+impl ListFile {
+    fn format(&self) -> ListFormat {
+        match self {
+            ListFile::TF2BD { .. } => ListFormat::TF2BD,
+            ListFile::Archive { .. } | ListFile::Native { .. } => ListFormat::Native,
+        }
+    }
+
+    /// The field of the file that holds the entries
+    fn entries_field(&self) -> &'static str {
+        match self {
+            ListFile::TF2BD { .. } => "players",
+            ListFile::Archive { .. } | ListFile::Native { .. } => "records",
+        }
+    }
+
+    /// Read a single entry of the file, or say what is wrong with it
+    fn convert(
+        &self,
+        position: &EntryPosition,
+        entry: &serde_json::Value,
+    ) -> Result<(SteamID, ListEntry), String> {
+        match (self, position) {
+            (ListFile::TF2BD { .. }, _) => {
+                let player = tf2bd::PlayerEntry::deserialize(entry).map_err(|e| e.to_string())?;
+                let entry = ListEntry {
+                    verdict: player.verdict(),
+                    name: player.last_seen.and_then(|ls| ls.player_name),
+                };
+                Ok((player.steamid, entry))
+            }
+            (ListFile::Archive { .. }, _) => {
+                let archived = ArchivedRecord::deserialize(entry).map_err(|e| e.to_string())?;
+                Ok((archived.steamid, ListEntry::from(&archived.record)))
+            }
+            (ListFile::Native { .. }, EntryPosition::Key(key)) => {
+                let steamid = key.parse::<SteamID>().map_err(|e| e.to_string())?;
+                let record = PlayerRecord::deserialize(entry).map_err(|e| e.to_string())?;
+                Ok((steamid, ListEntry::from(&record)))
+            }
+            (ListFile::Native { .. }, EntryPosition::Index(_)) => {
+                Err("Entries of this list are keyed by SteamID".into())
+            }
+        }
+    }
+
+    /// Take every entry out of the file, along with where it was
+    fn take_entries(&mut self) -> Vec<(EntryPosition, serde_json::Value)> {
+        match self {
+            ListFile::TF2BD {
+                players: entries, ..
+            }
+            | ListFile::Archive {
+                records: entries, ..
+            } => std::mem::take(entries)
+                .into_iter()
+                .enumerate()
+                .map(|(i, entry)| (EntryPosition::Index(i), entry))
+                .collect(),
+            ListFile::Native { records } => std::mem::take(records)
+                .into_iter()
+                .map(|(key, entry)| (EntryPosition::Key(key), entry))
+                .collect(),
+        }
+    }
+}
+
+impl From<&PlayerRecord> for ListEntry {
+    fn from(record: &PlayerRecord) -> Self {
+        ListEntry {
+            verdict: record.verdict,
+            name: record.previous_names.last().cloned(),
+        }
+    }
+}
+
+impl ImportedList {
+    /// Attempt to load a list in any of the supported [ListFormat]s from the provided file.
+    /// Entries that can't be read are quarantined rather than failing the whole list.
+    pub fn load_from(path: &Path) -> Result<ImportedList, ConfigFilesError> {
+        let contents = std::fs::read_to_string(path)
+            .map_err(|e| ConfigFilesError::IO(path.to_string_lossy().into(), e))?;
+        let mut list: ListFile = serde_json::from_str(&contents)
+            .map_err(|e| ConfigFilesError::Json(path.to_string_lossy().into(), e))?;
+
+        let title = match &list {
+            ListFile::TF2BD { file_info, .. } => file_info.as_ref().and_then(|fi| fi.title.clone()),
+            ListFile::Archive {
+                format, version, ..
+            } => {
+                archive::validate_header(format, *version)
+                    .map_err(|e| ConfigFilesError::Other(e.into()))?;
+                None
+            }
+            ListFile::Native { .. } => None,
+        };
+
+        let mut imported = ImportedList {
+            source: source_name(path),
+            format: list.format(),
+            title,
+            entries: HashMap::new(),
+            quarantined: Vec::new(),
+            changes: None,
+        };
+        for (position, entry) in list.take_entries() {
+            match list.convert(&position, &entry) {
+                Ok((steamid, entry)) => {
+                    imported.entries.insert(steamid, entry);
+                }
+                Err(reason) => imported.quarantined.push(QuarantinedEntry {
+                    position,
+                    entry,
+                    reason,
+                }),
+            }
+        }
+        imported
+            .quarantined
+            .sort_by(|a, b| a.position.cmp(&b.position));
+        Ok(imported)
+    }
+}
+
+/// A match for a player in one of the imported lists
+#[derive(Debug, Clone, Serialize)]
+pub struct ListMatch<'a> {
+    pub source: &'a str,
+    pub verdict: Verdict,
+}
+
+/// An imported list along with how useful it has been, as exposed through the API
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ListSummary<'a> {
+    #[serde(flatten)]
+    pub list: &'a ImportedList,
+    pub entries: usize,
+    pub encounters: u32,
+    pub unique_players: usize,
+    pub last_encounter: Option<u64>,
+    pub confirmations: usize,
+    pub contradictions: usize,
+    pub accuracy: Option<f32>,
+    /// How many entries of the list couldn't be imported
+    pub quarantined: usize,
+}
+
+/// All of the lists currently imported from the lists directory, keyed by their source
+#[derive(Debug)]
+pub struct ImportedLists {
+    lists: HashMap<Arc<str>, ImportedList>,
+    stats: ListStats,
+}
+
+impl ImportedLists {
+    pub fn new() -> ImportedLists {
+        ImportedLists {
+            lists: HashMap::new(),
+            stats: ListStats::load_or_create(),
+        }
+    }
+
+    /// Apply an update from the lists directory watcher. A list that was already imported is
+    /// compared to its previous version, and left alone if none of its entries changed (e.g. the
+    /// file was only touched or rewritten as it was).
+    pub fn handle_update(&mut self, update: ListUpdate) {
+        match update {
+            ListUpdate::Loaded(mut list) => {
+                match self.lists.get(&list.source) {
+                    Some(old) => {
+                        let changes = ListChanges::between(&old.entries, &list.entries);
+                        if changes.is_empty()
+                            && old.title == list.title
+                            && old.quarantined == list.quarantined
+                        {
+                            tracing::debug!("Reloaded {} without any changes", list.source);
+                            return;
+                        }
+                        tracing::info!(
+                            "Reloaded {}: {} added, {} changed, {} removed",
+                            list.source,
+                            changes.added,
+                            changes.changed,
+                            changes.removed
+                        );
+                        list.changes = Some(changes);
+                    }
+                    None => tracing::info!(
+                        "Imported {} players from {}",
+                        list.entries.len(),
+                        list.source
+                    ),
+                }
+                if !list.quarantined.is_empty() {
+                    tracing::warn!(
+                        "Quarantined {} entries of {} that could not be imported",
+                        list.quarantined.len(),
+                        list.source
+                    );
+                }
+                self.lists.insert(list.source.clone(), list);
+            }
+            ListUpdate::Removed(source) => {
+                if self.lists.remove(&source).is_some() {
+                    tracing::info!("Removed imported list {}", source);
+                }
+            }
+        }
+    }
+
+    /// Every imported list that has an entry for this player
+    pub fn matches(&self, steamid: &SteamID) -> Vec<ListMatch<'_>> {
+        let mut matches: Vec<ListMatch> = self
+            .lists
+            .values()
+            .filter_map(|list| {
+                list.entries.get(steamid).map(|entry| ListMatch {
+                    source: &list.source,
+                    verdict: entry.verdict,
+                })
+            })
+            .collect();
+        matches.sort_by_key(|m| m.source);
+        matches
+    }
+
+    /// Count an encounter with the player against every list they are on
+    pub fn record_encounter(&mut self, steamid: SteamID) {
+        let sources: Vec<Arc<str>> = self
+            .lists
+            .values()
+            .filter(|list| list.entries.contains_key(&steamid))
+            .map(|list| list.source.clone())
+            .collect();
+        if sources.is_empty() {
+            return;
+        }
+
+        for source in sources {
+            self.stats.record_encounter(source, steamid);
+        }
+        self.stats.save_ok();
+    }
+
+    /// Compare the user's verdict on a player against every list they are on
+    pub fn record_verdict(&mut self, steamid: SteamID, verdict: Verdict) {
+        let listed: Vec<(Arc<str>, Verdict)> = self
+            .lists
+            .values()
+            .filter_map(|list| {
+                list.entries
+                    .get(&steamid)
+                    .map(|entry| (list.source.clone(), entry.verdict))
+            })
+            .collect();
+        if listed.is_empty() {
+            return;
+        }
+
+        for (source, list_verdict) in listed {
+            self.stats
+                .record_judgement(source, steamid, list_verdict, verdict);
+        }
+        self.stats.save_ok();
+    }
+
+    /// Every imported list along with its statistics
+    pub fn summaries(&self) -> Vec<ListSummary<'_>> {
+        let mut summaries: Vec<ListSummary> = self
+            .lists
+            .values()
+            .map(|list| {
+                let stats = self.stats.get(&list.source).cloned().unwrap_or_default();
+                ListSummary {
+                    list,
+                    entries: list.entries.len(),
+                    encounters: stats.encounters,
+                    unique_players: stats.unique_players(),
+                    last_encounter: stats.last_encounter,
+                    confirmations: stats.confirmations(),
+                    contradictions: stats.contradictions(),
+                    accuracy: stats.accuracy(),
+                    quarantined: list.quarantined.len(),
+                }
+            })
+            .collect();
+        summaries.sort_by_key(|s| s.list.source.clone());
+        summaries
+    }
+
+    /// The entries of every list that couldn't be imported, for the lists that have any
+    pub fn quarantine(&self) -> Vec<QuarantineReport<'_>> {
+        let mut reports: Vec<QuarantineReport> = self
+            .lists
+            .values()
+            .filter(|list| !list.quarantined.is_empty())
+            .map(|list| QuarantineReport {
+                source: &list.source,
+                entries: &list.quarantined,
+            })
+            .collect();
+        reports.sort_by_key(|r| r.source);
+        reports
+    }
+
+    /// Attempts to find (and create) the directory that lists are imported from
+    pub fn locate_lists_directory() -> Result<PathBuf, ConfigFilesError> {
+        let dir = Settings::locate_config_directory()?.join("lists");
+        std::fs::create_dir_all(&dir)
+            .map_err(|e| ConfigFilesError::IO(dir.to_string_lossy().into(), e))?;
+        Ok(dir)
+    }
+}
+
+impl Default for ImportedLists {
+    fn default() -> Self {
+        ImportedLists::new()
+    }
+}
+
+/// Changes to the lists directory, sent from the watcher to the main loop
+#[derive(Debug)]
+pub enum ListUpdate {
+    /// A list was added or modified
+    Loaded(ImportedList),
+    /// The file a list was imported from was deleted
+    Removed(Arc<str>),
+}
+
+pub(crate) fn source_name(path: &Path) -> Arc<str> {
+    path.file_name()
+        .map(|name| name.to_string_lossy().into())
+        .unwrap_or_else(|| path.to_string_lossy().into())
+}
+
+fn is_json_file(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext == "json")
+}
+
+/// (Re)load a single list file, or report it as removed if it no longer exists
+fn refresh_list(path: &Path, update_send: &UnboundedSender<ListUpdate>) -> bool {
+    let update = if path.exists() {
+        match ImportedList::load_from(path) {
+            Ok(list) => ListUpdate::Loaded(list),
+            Err(e) => {
+                // Files are often caught partway through being written, so keep whatever
+                // was loaded previously and wait for the next change.
+                tracing::warn!("Could not import list: {}", e);
+                return true;
+            }
+        }
+    } else {
+        ListUpdate::Removed(source_name(path))
+    };
+
+    update_send.send(update).is_ok()
+}
+
+/// Import every list in the lists directory, then watch it for lists being added, changed or removed.
+/// This blocks, so should be run on its own thread.
+pub fn lists_loop(
+    lists_dir: PathBuf,
+    update_send: UnboundedSender<ListUpdate>,
+) -> anyhow::Result<()> {
+    watch_json_directory(&lists_dir, |path| refresh_list(path, &update_send))
+}
+
+/// Calls `refresh` with every json file in `dir`, then again whenever one is created, modified or
+/// removed. Stops once `refresh` returns false, i.e. when there is no one left to send updates to.
+/// This blocks, so should be run on its own thread.
+pub(crate) fn watch_json_directory(
+    dir: &Path,
+    mut refresh: impl FnMut(&Path) -> bool,
+) -> anyhow::Result<()> {
+    let (tx, rx) = mpsc::channel();
+    let config = Config::default().with_poll_interval(Duration::from_secs(2));
+
+    let mut watcher: RecommendedWatcher = Watcher::new(
+        Box::new(move |res: Result<Event, notify::Error>| match res {
+            Ok(event) => {
+                let _ = tx.send(event);
+            }
+            Err(err) => {
+                tracing::error!("Error while watching for file changes: {}", err);
+            }
+        }),
+        config,
+    )?;
+
+    watcher.watch(dir, RecursiveMode::NonRecursive)?;
+
+    for entry in std::fs::read_dir(dir)?.flatten() {
+        let path = entry.path();
+        if is_json_file(&path) && !refresh(&path) {
+            return Ok(());
+        }
+    }
+
+    tracing::debug!("Watching {:?}", dir);
+
+    while let Ok(event) = rx.recv() {
+        if !matches!(
+            event.kind,
+            EventKind::Create(_) | EventKind::Modify(_) | EventKind::Remove(_)
+        ) {
+            continue;
+        }
+
+        for path in event.paths.iter().filter(|p| is_json_file(p)) {
+            if !refresh(path) {
+                tracing::warn!(
+                    "Lost connection to main thread, no longer watching {:?}",
+                    dir
+                );
+                return Ok(());
+            }
+        }
+    }
+
+    Ok(())
+}
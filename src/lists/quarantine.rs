@@ -0,0 +1,118 @@
+//! Entries of imported lists that couldn't be understood, e.g. a SteamID with a typo in it or a
+//! player with no attributes array. Rather than refusing the whole list over one bad entry, or
+//! quietly leaving it out, each of them is kept along with why it was rejected so the user can see
+//! exactly what wasn't imported. Once corrected, an entry is written back into the list file and
+//! imported along with the rest of the list when it is reloaded.
+
+use std::{path::Path, sync::Arc};
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use super::{is_json_file, source_name, ListFile};
+use crate::settings::ConfigFilesError;
+
+/// Where an entry is in its list file
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum EntryPosition {
+    /// Index into the list of players, for lists that are an array of entries
+    Index(usize),
+    /// The key of the entry, for lists that are keyed by SteamID
+    Key(Arc<str>),
+}
+
+/// An entry that was left out of an imported list
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct QuarantinedEntry {
+    pub position: EntryPosition,
+    /// The entry exactly as it is in the file
+    pub entry: serde_json::Value,
+    /// Why the entry couldn't be imported
+    pub reason: String,
+}
+
+/// The quarantined entries of a single list, as exposed through the API
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct QuarantineReport<'a> {
+    pub source: &'a str,
+    pub entries: &'a [QuarantinedEntry],
+}
+
+/// A corrected version of an entry, to replace the one at `position` in the list from `source`
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ListCorrection {
+    pub source: Arc<str>,
+    pub position: EntryPosition,
+    /// For lists keyed by SteamID, a replacement for the key if it was the key that was wrong
+    #[serde(default)]
+    pub steamid: Option<Arc<str>>,
+    pub entry: serde_json::Value,
+}
+
+#[derive(Debug, Error)]
+pub enum QuarantineError {
+    #[error("No list called {0}")]
+    UnknownList(Arc<str>),
+    #[error("The list has no entry at {0:?}")]
+    UnknownPosition(EntryPosition),
+    #[error("The corrected entry is still invalid: {0}")]
+    Invalid(String),
+    #[error("{0}")]
+    File(#[from] ConfigFilesError),
+}
+
+/// Replace an entry in a list file with a corrected version. The corrected entry has to be valid,
+/// so it is imported when the lists watcher reloads the file.
+pub fn correct(lists_dir: &Path, correction: ListCorrection) -> Result<(), QuarantineError> {
+    // The source ends up in a path, so it had better be just a file name
+    let path = lists_dir.join(correction.source.as_ref());
+    if !is_json_file(&path) || source_name(&path) != correction.source || !path.is_file() {
+        return Err(QuarantineError::UnknownList(correction.source));
+    }
+
+    let file_error = |e| ConfigFilesError::Json(path.to_string_lossy().into(), e);
+    let contents = std::fs::read_to_string(&path)
+        .map_err(|e| ConfigFilesError::IO(path.to_string_lossy().into(), e))?;
+    let list: ListFile = serde_json::from_str(&contents).map_err(file_error)?;
+    let mut raw: serde_json::Value = serde_json::from_str(&contents).map_err(file_error)?;
+
+    let unknown = || QuarantineError::UnknownPosition(correction.position.clone());
+    let entries = raw.get_mut(list.entries_field());
+    match &correction.position {
+        EntryPosition::Index(i) => {
+            let entry = entries.and_then(|e| e.get_mut(*i)).ok_or_else(unknown)?;
+            list.convert(&correction.position, &correction.entry)
+                .map_err(QuarantineError::Invalid)?;
+            *entry = correction.entry;
+        }
+        EntryPosition::Key(old) => {
+            let entries = entries
+                .and_then(serde_json::Value::as_object_mut)
+                .filter(|e| e.contains_key(old.as_ref()))
+                .ok_or_else(unknown)?;
+            let new = correction.steamid.unwrap_or_else(|| old.clone());
+            list.convert(&EntryPosition::Key(new.clone()), &correction.entry)
+                .map_err(QuarantineError::Invalid)?;
+            entries.remove(old.as_ref());
+            entries.insert(new.to_string(), correction.entry);
+        }
+    }
+
+    // Written elsewhere first so the watcher never picks up a partly written list
+    let contents = serde_json::to_string_pretty(&raw).expect("Serialize list");
+    let tmp_path = path.with_extension("tmp");
+    std::fs::write(&tmp_path, contents)
+        .and_then(|_| std::fs::rename(&tmp_path, &path))
+        .map_err(|e| ConfigFilesError::IO(path.to_string_lossy().into(), e))?;
+
+    tracing::info!(
+        "Corrected entry {:?} of {}",
+        correction.position,
+        correction.source
+    );
+    Ok(())
+}
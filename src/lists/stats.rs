@@ -0,0 +1,164 @@
+use std::{
+    collections::{HashMap, HashSet},
+    io::ErrorKind,
+    path::PathBuf,
+    sync::Arc,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    persistence,
+    player_records::Verdict,
+    settings::{ConfigFilesError, Settings},
+    steamid::SteamID,
+};
+
+/// How useful a single list source has been
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SourceStats {
+    /// Number of times a player on the list has joined a server the user was on
+    pub encounters: u32,
+    /// Unix timestamp (seconds) of the most recent encounter
+    pub last_encounter: Option<u64>,
+    players_encountered: HashSet<SteamID>,
+    /// Whether the user's own verdict on a listed player agreed with the list
+    judgements: HashMap<SteamID, bool>,
+}
+
+impl SourceStats {
+    pub fn unique_players(&self) -> usize {
+        self.players_encountered.len()
+    }
+
+    pub fn confirmations(&self) -> usize {
+        self.judgements.values().filter(|&&agreed| agreed).count()
+    }
+
+    pub fn contradictions(&self) -> usize {
+        self.judgements.values().filter(|&&agreed| !agreed).count()
+    }
+
+    /// Fraction of the list's marks that the user agreed with, if they have judged any
+    pub fn accuracy(&self) -> Option<f32> {
+        if self.judgements.is_empty() {
+            return None;
+        }
+        Some(self.confirmations() as f32 / self.judgements.len() as f32)
+    }
+}
+
+/// Whether the user's verdict on a player agrees with the verdict a list gave them.
+/// Returns None if the verdicts don't say anything about each other.
+pub fn verdicts_agree(list: Verdict, user: Verdict) -> Option<bool> {
+    let list_marked = matches!(list, Verdict::Cheater | Verdict::Bot | Verdict::Suspicious);
+    if !list_marked {
+        return None;
+    }
+
+    match user {
+        Verdict::Cheater | Verdict::Bot => Some(true),
+        Verdict::Trusted => Some(false),
+        Verdict::Player | Verdict::Suspicious => None,
+    }
+}
+
+/// Persistent statistics on every list source that has been imported
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ListStats {
+    #[serde(skip)]
+    path: PathBuf,
+    sources: HashMap<Arc<str>, SourceStats>,
+}
+
+impl ListStats {
+    /// Attempts to load the statistics from the default location, starting fresh if they
+    /// don't exist or can't be read.
+    pub fn load_or_create() -> ListStats {
+        let path = Self::locate_stats_file()
+            .map_err(|e| tracing::warn!("Failed to find list statistics location: {:?}", e))
+            .unwrap_or("list_stats.json".into());
+
+        match Self::load_from(path.clone()) {
+            Ok(stats) => stats,
+            Err(ConfigFilesError::IO(_, e)) if e.kind() == ErrorKind::NotFound => ListStats {
+                path,
+                sources: HashMap::new(),
+            },
+            Err(e) => {
+                tracing::warn!("Could not load list statistics, starting fresh: {:?}", e);
+                ListStats {
+                    path,
+                    sources: HashMap::new(),
+                }
+            }
+        }
+    }
+
+    /// Attempt to load the [ListStats] from the provided file
+    pub fn load_from(path: PathBuf) -> Result<ListStats, ConfigFilesError> {
+        let mut stats: ListStats = persistence::read_recovering(&path, |contents| {
+            serde_json::from_str(contents)
+                .map_err(|e| ConfigFilesError::Json(path.to_string_lossy().into(), e))
+        })?;
+        stats.path = path;
+        Ok(stats)
+    }
+
+    /// Attempt to save the [ListStats] to the file it was loaded from
+    pub fn save(&self) -> Result<(), ConfigFilesError> {
+        let contents =
+            serde_json::to_string(self).context("Failed to serialize list statistics.")?;
+        persistence::write_atomic(&self.path, &contents)
+    }
+
+    /// Attempt to save the [ListStats], log errors and ignore result
+    pub fn save_ok(&self) {
+        if let Err(e) = self.save() {
+            tracing::error!("Failed to save list statistics: {:?}", e);
+        }
+    }
+
+    pub fn locate_stats_file() -> Result<PathBuf, ConfigFilesError> {
+        Settings::locate_config_directory().map(|dir| dir.join("list_stats.json"))
+    }
+
+    pub fn get(&self, source: &str) -> Option<&SourceStats> {
+        self.sources.get(source)
+    }
+
+    /// Record that a player listed by `source` was encountered
+    pub fn record_encounter(&mut self, source: Arc<str>, steamid: SteamID) {
+        let stats = self.sources.entry(source).or_default();
+        stats.encounters += 1;
+        stats.last_encounter = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .ok();
+        stats.players_encountered.insert(steamid);
+    }
+
+    /// Record the user's verdict on a player listed by `source`. Only the most recent verdict
+    /// on each player counts, and verdicts which neither confirm nor contradict the list clear
+    /// any earlier judgement.
+    pub fn record_judgement(
+        &mut self,
+        source: Arc<str>,
+        steamid: SteamID,
+        list_verdict: Verdict,
+        user_verdict: Verdict,
+    ) {
+        let stats = self.sources.entry(source).or_default();
+        match verdicts_agree(list_verdict, user_verdict) {
+            Some(agreed) => {
+                stats.judgements.insert(steamid, agreed);
+            }
+            None => {
+                stats.judgements.remove(&steamid);
+            }
+        }
+    }
+}
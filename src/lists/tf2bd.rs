@@ -0,0 +1,70 @@
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{player_records::Verdict, steamid::SteamID};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FileInfo {
+    #[serde(default)]
+    pub authors: Vec<Arc<str>>,
+    pub title: Option<Arc<str>>,
+    pub description: Option<Arc<str>>,
+    pub update_url: Option<Arc<str>>,
+}
+
+/// A player in a playerlist in the format used by TF2 Bot Detector
+/// (<https://github.com/PazerOP/tf2_bot_detector/blob/master/schemas/v3/playerlist.schema.json>)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PlayerEntry {
+    pub steamid: SteamID,
+    #[serde(default)]
+    pub attributes: Vec<Attribute>,
+    pub last_seen: Option<LastSeen>,
+    #[serde(default)]
+    pub proof: Vec<serde_json::Value>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LastSeen {
+    pub player_name: Option<Arc<str>>,
+    pub time: Option<u64>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Attribute {
+    Cheater,
+    Suspicious,
+    Exploiter,
+    Racist,
+    #[serde(other)]
+    Other,
+}
+
+impl Attribute {
+    /// The closest [Verdict] to the attribute. Attributes that don't relate to cheating
+    /// (e.g. racist) have none.
+    pub fn verdict(&self) -> Option<Verdict> {
+        match self {
+            Attribute::Cheater | Attribute::Exploiter => Some(Verdict::Cheater),
+            Attribute::Suspicious => Some(Verdict::Suspicious),
+            Attribute::Racist | Attribute::Other => None,
+        }
+    }
+}
+
+impl PlayerEntry {
+    /// The most severe [Verdict] out of the attributes of the entry, or [Verdict::Player] if
+    /// none of them relate to cheating.
+    pub fn verdict(&self) -> Verdict {
+        let verdicts: Vec<Verdict> = self.attributes.iter().filter_map(|a| a.verdict()).collect();
+        if verdicts.contains(&Verdict::Cheater) {
+            Verdict::Cheater
+        } else if verdicts.contains(&Verdict::Suspicious) {
+            Verdict::Suspicious
+        } else {
+            Verdict::Player
+        }
+    }
+}
@@ -0,0 +1,132 @@
+use std::{fmt::Display, path::PathBuf, sync::RwLock};
+
+use fluent_bundle::{concurrent::FluentBundle, FluentArgs, FluentResource, FluentValue};
+use unic_langid::LanguageIdentifier;
+
+use crate::settings::{ConfigFilesError, Settings};
+
+/// Locale used when none has been chosen, and for any strings missing from the chosen one
+pub const DEFAULT_LOCALE: &str = "en";
+
+/// Resource files compiled into the client. Users can add their own (or override strings in
+/// these) by putting a `<locale>.ftl` file in the `locales` folder of the config directory.
+const BUILTIN: &[(&str, &str)] = &[
+    ("en", include_str!("../locales/en.ftl")),
+    ("de", include_str!("../locales/de.ftl")),
+];
+
+/// The strings for one locale
+type Messages = FluentBundle<FluentResource>;
+
+struct Bundle {
+    messages: Messages,
+    fallback: Messages,
+}
+
+static BUNDLE: RwLock<Option<Bundle>> = RwLock::new(None);
+
+/// Parse a Fluent resource file. Messages that can't be parsed are skipped, so one mistake in a
+/// user's file doesn't lose the rest of it.
+fn parse(name: &str, contents: &str) -> FluentResource {
+    FluentResource::try_new(contents.to_string()).unwrap_or_else(|(resource, errors)| {
+        tracing::warn!("Skipped invalid messages in {}: {:?}", name, errors);
+        resource
+    })
+}
+
+pub fn locate_locales_directory() -> Result<PathBuf, ConfigFilesError> {
+    Settings::locate_config_directory().map(|dir| dir.join("locales"))
+}
+
+/// The builtin strings for a locale with any of the user's overrides on top, and whether there
+/// were any
+fn load(locale: &str) -> (Messages, bool) {
+    let langid: LanguageIdentifier = locale.parse().unwrap_or_else(|_| {
+        tracing::warn!(
+            "Invalid locale {}, treating it as {}.",
+            locale,
+            DEFAULT_LOCALE
+        );
+        LanguageIdentifier::default()
+    });
+    let mut messages = FluentBundle::new_concurrent(vec![langid]);
+    // Strings end up in logs and the terminal as well as the UI, where the bidi isolation marks
+    // around placeables would show up as junk
+    messages.set_use_isolating(false);
+    let mut found = false;
+
+    if let Some((_, contents)) = BUILTIN.iter().find(|(name, _)| *name == locale) {
+        messages.add_resource_overriding(parse(locale, contents));
+        found = true;
+    }
+
+    if let Ok(dir) = locate_locales_directory() {
+        let path = dir.join(format!("{}.ftl", locale));
+        match std::fs::read_to_string(&path) {
+            Ok(contents) => {
+                messages.add_resource_overriding(parse(&path.to_string_lossy(), &contents));
+                found = true;
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => tracing::warn!("Failed to read locale file {:?}: {:?}", path, e),
+        }
+    }
+
+    (messages, found)
+}
+
+/// Switch the locale used for every string shown to the user from now on
+pub fn set_locale(locale: &str) {
+    let (messages, found) = load(locale);
+    if !found {
+        tracing::warn!(
+            "No strings found for locale {}, using {}.",
+            locale,
+            DEFAULT_LOCALE
+        );
+    }
+
+    *BUNDLE.write().unwrap() = Some(Bundle {
+        messages,
+        fallback: load(DEFAULT_LOCALE).0,
+    });
+}
+
+/// Format the message `key` from `messages`, if it has it. Placeables without a matching argument
+/// are left in as `{$name}` so the missing argument is obvious.
+fn format(messages: &Messages, key: &str, args: &FluentArgs) -> Option<String> {
+    let pattern = messages.get_message(key)?.value()?;
+    let mut errors = Vec::new();
+    let formatted = messages.format_pattern(pattern, Some(args), &mut errors);
+    if !errors.is_empty() {
+        tracing::warn!("Errors formatting {}: {:?}", key, errors);
+    }
+    Some(formatted.into_owned())
+}
+
+/// Translate the message `key` into the current locale, filling in `args`. Falls back to the
+/// default locale if the current one doesn't have the message, and to the key itself if neither
+/// does.
+pub fn tr(key: &str, args: &[(&str, &dyn Display)]) -> String {
+    let mut bundle = BUNDLE.read().unwrap();
+    if bundle.is_none() {
+        drop(bundle);
+        set_locale(DEFAULT_LOCALE);
+        bundle = BUNDLE.read().unwrap();
+    }
+
+    let mut fluent_args = FluentArgs::new();
+    for (name, value) in args {
+        // Passed as text even if it looks like a number, as Fluent would format (and round) it
+        // as one, which mangles e.g. SteamIDs
+        fluent_args.set(*name, FluentValue::from(value.to_string()));
+    }
+
+    let formatted = bundle.as_ref().and_then(|b| {
+        format(&b.messages, key, &fluent_args).or_else(|| format(&b.fallback, key, &fluent_args))
+    });
+    formatted.unwrap_or_else(|| {
+        tracing::warn!("Missing translation for {}", key);
+        key.to_string()
+    })
+}
@@ -0,0 +1,105 @@
+//! Changing what is logged to the console while the client is running, e.g. to turn up
+//! `client_backend::demo=trace` while looking into a demo that won't parse, without restarting
+//! and losing the state that caused it. The log file always gets everything.
+
+use std::{collections::HashMap, sync::OnceLock};
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use tracing_subscriber::{filter::ParseError, reload, EnvFilter, Registry};
+
+/// What is logged to the console unless `RUST_LOG` says otherwise
+pub const DEFAULT_FILTER: &str = "info,hyper::proto=warn,tf_demo_parser=warn";
+
+static HANDLE: OnceLock<reload::Handle<EnvFilter, Registry>> = OnceLock::new();
+
+#[derive(Debug, Error)]
+pub enum LogFilterError {
+    #[error("Logging can't be changed while running.")]
+    Unavailable,
+    #[error("Invalid filter: {0}")]
+    Invalid(#[from] ParseError),
+    #[error("Failed to change the filter: {0}")]
+    Reload(#[from] reload::Error),
+}
+
+/// The directives logs are currently filtered with
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LogFilter {
+    /// The whole filter, in the same format as `RUST_LOG`
+    pub filter: String,
+    /// The level of anything no directive is more specific about
+    pub default: Option<String>,
+    /// Levels of individual modules (e.g. `client_backend::demo`) and crates
+    pub modules: HashMap<String, String>,
+}
+
+/// A change to the filter. Either replaces the whole thing, or adjusts the levels of some modules
+/// and leaves the rest as they are.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LogFilterUpdate {
+    /// A new filter, in the same format as `RUST_LOG`
+    pub filter: Option<String>,
+    /// New levels for modules, e.g. `{"client_backend::demo": "trace"}`. A level of `null` goes
+    /// back to the default for that module.
+    #[serde(default)]
+    pub modules: HashMap<String, Option<String>>,
+}
+
+/// The filter for console logs, which can be changed with [set] once it has been installed
+pub fn reloadable_filter() -> reload::Layer<EnvFilter, Registry> {
+    let (filter, handle) = reload::Layer::new(EnvFilter::from_default_env());
+    if HANDLE.set(handle).is_err() {
+        tracing::warn!("Console log filter was installed more than once");
+    }
+    filter
+}
+
+/// The current console log filter, if it can be changed
+pub fn current() -> Option<LogFilter> {
+    let filter = HANDLE.get()?.with_current(|f| f.to_string()).ok()?;
+
+    let mut default = None;
+    let mut modules = HashMap::new();
+    for directive in filter.split(',').filter(|d| !d.is_empty()) {
+        match directive.rsplit_once('=') {
+            Some((module, level)) => {
+                modules.insert(module.to_string(), level.to_string());
+            }
+            None => default = Some(directive.to_string()),
+        }
+    }
+
+    Some(LogFilter {
+        filter,
+        default,
+        modules,
+    })
+}
+
+/// Change the console log filter, returning the new one
+pub fn set(update: LogFilterUpdate) -> Result<LogFilter, LogFilterError> {
+    let handle = HANDLE.get().ok_or(LogFilterError::Unavailable)?;
+    let existing = current().ok_or(LogFilterError::Unavailable)?;
+
+    let mut directives: Vec<String> = update
+        .filter
+        .unwrap_or(existing.filter)
+        .split(',')
+        .filter(|d| !d.is_empty())
+        .map(String::from)
+        .collect();
+    for (module, level) in update.modules {
+        directives.retain(|d| d.rsplit_once('=').is_none_or(|(m, _)| m != module));
+        if let Some(level) = level {
+            directives.push(format!("{}={}", module, level));
+        }
+    }
+
+    let filter = EnvFilter::builder().parse(directives.join(","))?;
+    handle.reload(filter)?;
+    tracing::info!("Console log filter changed to {}", directives.join(","));
+    current().ok_or(LogFilterError::Unavailable)
+}
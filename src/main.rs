@@ -1,43 +1,22 @@
-use crate::player_records::Verdict;
-use crate::steamapi::SteamAPIResponse;
-use args::Args;
 use clap::Parser;
+use client_backend::{
+    args::{Args, CliCommand},
+    backend::Backend,
+    demo::DemoManager,
+    doctor,
+    launchoptions::{LaunchOptions, TF2_REQUIRED_OPTS},
+    logging, secrets,
+    settings::Settings,
+};
 use include_dir::{include_dir, Dir};
-use player_records::PlayerRecords;
-use server::Server;
-use steamapi::SteamAPIManager;
-use steamid_ng::SteamID;
-use tokio::select;
-use tokio::sync::mpsc::unbounded_channel;
-use web::{web_main, SharedState};
 
-use std::path::{Path, PathBuf};
-use std::sync::{Arc, RwLock};
-use std::time::Duration;
+use std::path::Path;
 
-use demo::demo_loop;
-use io::{Command, IOManager};
-use launchoptions::LaunchOptions;
-use settings::Settings;
 use tracing_appender::non_blocking::WorkerGuard;
 use tracing_subscriber::{
-    fmt::writer::MakeWriterExt, layer::SubscriberExt, util::SubscriberInitExt, EnvFilter, Layer,
+    fmt::writer::MakeWriterExt, layer::SubscriberExt, util::SubscriberInitExt, Layer,
 };
 
-use crate::io::IOManagerMessage;
-
-mod args;
-mod demo;
-mod gamefinder;
-mod io;
-mod launchoptions;
-mod player;
-mod player_records;
-mod server;
-mod settings;
-mod steamapi;
-mod web;
-
 static UI_DIR: Dir = include_dir!("ui");
 
 fn main() {
@@ -46,26 +25,81 @@ fn main() {
     // Arg handling
     let args = Args::parse();
 
+    #[cfg(feature = "fault-injection")]
+    if let Some(faults) = args.inject_faults.clone() {
+        client_backend::faults::enable(faults);
+    }
+
+    if let Some(path) = &args.analyse_demo {
+        match DemoManager::analyse_file(path) {
+            Ok(analysis) => {
+                println!(
+                    "{}",
+                    serde_json::to_string_pretty(&analysis).expect("Serialize demo analysis")
+                );
+                std::process::exit(0);
+            }
+            Err(e) => {
+                tracing::error!("Failed to analyse demo {:?}: {:?}", path, e);
+                std::process::exit(1);
+            }
+        }
+    }
+
+    // Secrets have to be unlocked before anything containing them is loaded
+    if let Some(passphrase) = args
+        .secrets_passphrase
+        .clone()
+        .or_else(|| std::env::var("MAC_SECRETS_PASSPHRASE").ok())
+    {
+        secrets::set_passphrase(passphrase);
+    }
+
     // Load settings
     let settings = Settings::load_or_create(&args);
     settings.save_ok();
 
+    if let Some(CliCommand::Doctor) = args.command {
+        let report = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+            .block_on(async { doctor::Checkup::new(&settings, false).run().await });
+        report.print();
+        std::process::exit(if report.passed { 0 } else { 1 });
+    }
+
+    let game_profile = settings.get_game_profile();
+    tracing::info!("Using game profile {}", game_profile.name);
+
     // Launch options and overrides
-    let launch_opts = match LaunchOptions::new(
-        settings
-            .get_steam_user()
-            .expect("Failed to identify the local steam user (failed to find `loginusers.vdf`)"),
-    ) {
-        Ok(val) => Some(val),
-        Err(why) => {
-            // Error only if "no_panic_on_missing_launch_options" is not true.
-            if !(args.ignore_launch_options) {
-                panic!("Failed to get information on the current TF2 launch options from the local steam library: {}", why);
-            } else {
-                tracing::warn!("Couldn't verify app launch options, ignoring...");
-                None
-            }
+    let launch_opts = match game_profile.appid {
+        // Sourcemods aren't Steam apps, so there are no launch options to check
+        None => {
+            tracing::warn!(
+                "Launch options can't be checked for {}, please make sure it is launched with {:?}",
+                game_profile.name,
+                TF2_REQUIRED_OPTS
+            );
+            None
         }
+        Some(appid) => match LaunchOptions::new(
+            settings
+                .get_steam_user()
+                .expect("Failed to identify the local steam user (failed to find `loginusers.vdf`)"),
+            appid,
+        ) {
+            Ok(val) => Some(val),
+            Err(why) => {
+                // Error only if "no_panic_on_missing_launch_options" is not true.
+                if !(args.ignore_launch_options) {
+                    panic!("Failed to get information on the current TF2 launch options from the local steam library: {}", why);
+                } else {
+                    tracing::warn!("Couldn't verify app launch options, ignoring...");
+                    None
+                }
+            }
+        },
     };
 
     if let Some(mut opts) = launch_opts {
@@ -109,9 +143,7 @@ fn main() {
     }
 
     let webui_port = settings.get_webui_port();
-    let rcon_port = settings.get_rcon_port();
-    let playerlist = PlayerRecords::load_or_create(&args);
-    playerlist.save_ok();
+    let autolaunch_ui = args.autolaunch_ui || settings.get_autolaunch_ui();
 
     // Start the async part of the program
     tokio::runtime::Builder::new_multi_thread()
@@ -119,221 +151,31 @@ fn main() {
         .build()
         .unwrap()
         .block_on(async {
-            // Initialize State
-            let log_file_path: PathBuf =
-                PathBuf::from(settings.get_tf2_directory()).join("tf/console.log");
-
-            // IO Manager
-            let (io_send, io_recv) = unbounded_channel();
-            let (mut io_recv, mut io_manager) =
-                IOManager::new(log_file_path, settings.get_rcon_password(), rcon_port, io_recv);
-
-            tokio::task::spawn(async move {
-                io_manager.io_loop().await;
-            });
+            let backend = Backend::builder(args)
+                .settings(settings)
+                .ui(&UI_DIR)
+                .start();
 
             // Autolaunch UI
-            if args.autolaunch_ui || settings.get_autolaunch_ui() {
+            if autolaunch_ui {
                 if let Err(e) = open::that(Path::new(&format!("http://localhost:{}", webui_port))) {
                     tracing::error!("Failed to open web browser: {:?}", e);
                 }
             }
 
-            // Demo manager
-            if args.demo_monitoring {
-                let demo_path = settings.get_tf2_directory().join("tf");
-                tracing::info!("Demo path: {:?}", demo_path);
-
-                std::thread::spawn(move || {
-                    if let Err(e) = demo_loop(demo_path) {
-                        tracing::error!("Failed to start demo watcher: {:?}", e);
-                    }
-                });
-            }
-
-            // Steam API
-            let mut server = Server::new(playerlist);
-            server.players_mut().user = settings.get_steam_user();
-            let (steam_api_send, steam_api_recv) = unbounded_channel();
-            let (mut steam_api_recv, mut steam_api) =
-                SteamAPIManager::new(settings.get_steam_api_key(), steam_api_recv);
-            tokio::task::spawn(async move {
-                steam_api.api_loop().await;
-            });
-
-
-            // Setup web API server
-            let settings = Arc::new(RwLock::new(settings));
-            let server = Arc::new(RwLock::new(server));
-
-            let shared_state = SharedState {
-                ui: Some(&UI_DIR),
-                io: io_send.clone(),
-                api: steam_api_send.clone(),
-                server: server.clone(),
-                settings: settings.clone(),
-            };
-            tokio::task::spawn(async move {
-                web_main(shared_state, webui_port).await;
-            });
-
-            // Main loop
-
-            let mut refresh_interval = tokio::time::interval(Duration::from_secs(3));
-            refresh_interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
-            let mut refresh_iteration: u64 = 0;
-
-            let mut new_players = Vec::new();
-            let mut queued_friendlist_req: Vec<SteamID> = Vec::new();
-            let mut inprogress_friendlist_req: Vec<SteamID> = Vec::new();
-            let mut need_all_friends_lists = false;
-
-            loop {
-
-                select! {
-                    // IO output
-                    io_output_iter = io_recv.recv() => {
-                        for output in io_output_iter.unwrap() {
-                            for new_player in server.write().unwrap()
-                                .handle_io_output(output)
-                                .into_iter()
-                            {
-                                new_players.push(new_player);
-                            }
-                        }
-                    },
-
-                    // Steam API responses
-                    Some(response) = steam_api_recv.recv() => {
-                        match response {
-                            SteamAPIResponse::SteamInfo(info) => {
-                                server.write().unwrap().players_mut().steam_info.insert(info.0, info.1);
-                            },
-                            SteamAPIResponse::FriendLists((steamid, result)) => {
-                                match result {
-                                    // Player has public friend list
-                                    Ok(friend_list) => {
-                                        server.write().unwrap().players_mut().update_friends_list(steamid, friend_list);
-                                    },
-                                    // Player has private friend list
-                                    Err(_) => {
-                                        let mut server = server.write().unwrap();
-                                        server.players_mut().mark_friends_list_private(&steamid);
-                                        if let Some(record) = server.players_mut().records.get(&steamid) {
-                                            if  record.verdict == Verdict::Cheater ||
-                                                record.verdict == Verdict::Bot {
-                                                need_all_friends_lists = true;
-                                            }
-                                        }
-                                    }
-                                };
-                                let i = inprogress_friendlist_req.iter().position(|id| *id == steamid);
-                                if i.is_some() {
-                                    inprogress_friendlist_req.remove(i.unwrap());
-                                }
-                            }
-                        }
-                    }
-
-                    // Refresh
-                    _ = refresh_interval.tick() => {
-                        if refresh_iteration % 2 == 0 {
-                            server.write().unwrap().players_mut().refresh();
-                            io_send.send(IOManagerMessage::RunCommand(Command::Status)).unwrap();
-                        } else {
-                            io_send.send(IOManagerMessage::RunCommand(Command::G15)).unwrap();
-                        }
-
-                        refresh_iteration += 1;
-                    }
-                }
-
-                // Request steam API stuff on new players
-                for player in &new_players {
-                    let verdict = server.read().unwrap()
-                        .players()
-                        .records.get(player)
-                        .map(|r| {
-                            r.verdict
-                        }).unwrap_or(Verdict::Player);
-                    steam_api_send
-                        .send(steamapi::SteamAPIMessage::Lookup(*player))
-                        .unwrap();
-                    let settings_read = settings.read().unwrap();
-                    let user = settings_read.get_steam_user();
-                    if user.is_some_and(|u| u == *player) {
-                        queued_friendlist_req.push(*player);
-                        continue;
-                    }
-                    match settings_read.get_friends_api_usage() {
-                        settings::FriendsAPIUsage::All => {
-                            queued_friendlist_req.push(*player);
-                        },
-                        settings::FriendsAPIUsage::CheatersOnly => {
-                            if !need_all_friends_lists && (verdict == Verdict::Cheater ||  verdict == Verdict::Bot) {
-                                queued_friendlist_req.push(*player);
-                            }
-                        },
-                        settings::FriendsAPIUsage::None => {
-
-                        }
-                    }
-                }
-
-                // Request friend lists of relevant players (depends on config)
-                if need_all_friends_lists || !queued_friendlist_req.is_empty() {
-                    // If a cheater's friends list is private, we need everyone's friends list.
-                    if need_all_friends_lists {
-                        need_all_friends_lists = false;
-                        let server_read: std::sync::RwLockReadGuard<'_, Server> = server.read().unwrap();
-                        queued_friendlist_req = server_read.players().connected.iter()
-                            .filter_map(|steamid| {
-                                if inprogress_friendlist_req.contains(steamid) {
-                                    return None;
-                                }
-                                // If friends list visibility is Some, we've looked up that user before.
-                                match server_read.players().friend_info.get(steamid).map(|fi| fi.public) {
-                                    Some(Some(true)) => {
-                                        None
-                                    }
-                                    Some(Some(false)) => {
-                                        let record = server_read.players().records.get(steamid);
-                                        if record.is_some_and(|r | {
-                                            r.verdict == Verdict::Cheater ||
-                                            r.verdict == Verdict::Bot
-                                         }) {
-                                            need_all_friends_lists = true;
-                                        }
-                                        None
-                                    }
-                                    _ => {
-                                        Some(*steamid)
-                                    }
-                                }
-                            }).collect();
-                    }
-
-                    steam_api_send
-                        .send(steamapi::SteamAPIMessage::CheckFriends(queued_friendlist_req.clone()))
-                        .unwrap();
-                    inprogress_friendlist_req.append(&mut queued_friendlist_req);
-                }
-
-                new_players.clear();
-                queued_friendlist_req.clear();
-            }
+            backend.run_until_interrupted().await;
         });
 }
 
 fn init_tracing() -> Option<WorkerGuard> {
     if std::env::var("RUST_LOG").is_err() {
-        std::env::set_var("RUST_LOG", "info,hyper::proto=warn,tf_demo_parser=warn");
+        std::env::set_var("RUST_LOG", logging::DEFAULT_FILTER);
     }
 
     let subscriber = tracing_subscriber::registry().with(
         tracing_subscriber::fmt::layer()
             .with_writer(std::io::stderr)
-            .with_filter(EnvFilter::from_default_env()),
+            .with_filter(logging::reloadable_filter()),
     );
 
     match std::fs::File::create("./macclient.log") {
@@ -0,0 +1,243 @@
+//! Signing the user in to the masterbase, the central service demos and reports are submitted to.
+//!
+//! The user signs in to Steam through OpenID in their browser, which sends them back to the web
+//! API with a signed assertion of their account. That is passed on to the masterbase, which checks
+//! it with Steam and hands back a session token. The token is kept with the settings as a
+//! [Secret], so it is encrypted at rest whenever secrets are, and is refreshed before it expires.
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex, RwLock},
+    time::Duration,
+};
+
+use anyhow::{anyhow, Context, Result};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD as BASE64, Engine};
+use chacha20poly1305::aead::{rand_core::RngCore, OsRng};
+use reqwest::{StatusCode, Url};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    analytics::history::now,
+    events::{self, SystemEventLevel},
+    locale::tr,
+    secrets::Secret,
+    settings::Settings,
+    steamid::SteamID,
+};
+
+const STEAM_OPENID_URL: &str = "https://steamcommunity.com/openid/login";
+const OPENID_NS: &str = "http://specs.openid.net/auth/2.0";
+const OPENID_IDENTIFIER_SELECT: &str = "http://specs.openid.net/auth/2.0/identifier_select";
+/// Steam's OpenID identities are this followed by the account's SteamID
+const STEAM_IDENTITY_PREFIX: &str = "https://steamcommunity.com/openid/id/";
+/// Where Steam sends the user back to once they have signed in, on the web API
+pub const CALLBACK_PATH: &str = "/mac/masterbase/callback/v1";
+
+const CHECK_INTERVAL: Duration = Duration::from_secs(10 * 60);
+/// Sessions are refreshed once they have less than this long (seconds) left
+const REFRESH_BEFORE: u64 = 24 * 60 * 60;
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Random value tying Steam's response to the sign in the user started, so a sign in can't be
+/// completed from anywhere else
+static PENDING_LOGIN: Mutex<Option<Arc<str>>> = Mutex::new(None);
+
+/// A signed in session with the masterbase
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MasterbaseSession {
+    pub token: Secret,
+    /// The account the user signed in with
+    pub steamid: SteamID,
+    /// Unix timestamp (seconds) of when the token stops working
+    pub expires_at: u64,
+}
+
+/// Whether the user is signed in to the masterbase, for the settings API
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AuthStatus {
+    /// Whether there is a masterbase to sign in to
+    pub configured: bool,
+    pub authenticated: bool,
+    /// The session is stored encrypted and the passphrase to read it wasn't provided
+    pub locked: bool,
+    pub steamid: Option<SteamID>,
+    pub expires_at: Option<u64>,
+}
+
+impl AuthStatus {
+    pub fn new(settings: &Settings) -> AuthStatus {
+        let session = settings.get_masterbase_session();
+        AuthStatus {
+            configured: settings.get_masterbase_endpoint().is_some(),
+            authenticated: session
+                .as_ref()
+                .is_some_and(|s| s.token.expose().is_some() && s.expires_at > now()),
+            locked: session.as_ref().is_some_and(|s| s.token.is_locked()),
+            steamid: session.as_ref().map(|s| s.steamid),
+            expires_at: session.as_ref().map(|s| s.expires_at),
+        }
+    }
+}
+
+/// What the masterbase responds to a sign in or refresh with
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct TokenResponse {
+    token: String,
+    /// Unix timestamp (seconds)
+    expires_at: u64,
+}
+
+/// The `Authorization` header value for requests to the masterbase, if the user is signed in
+pub fn authorization(session: &MasterbaseSession) -> Option<String> {
+    session
+        .token
+        .expose()
+        .map(|token| format!("Bearer {}", token))
+}
+
+/// Start signing in, returning the Steam page to send the user to. Steam sends them back to the
+/// web API at `web_root` (e.g. `http://localhost:3621`), which completes it with
+/// [complete_login]. Starting another sign in abandons any that hasn't been completed.
+pub fn login_url(web_root: &str) -> Result<Url> {
+    let mut state = [0; 16];
+    OsRng.fill_bytes(&mut state);
+    let state: Arc<str> = BASE64.encode(state).into();
+    *PENDING_LOGIN.lock().unwrap() = Some(state.clone());
+
+    let return_to = Url::parse_with_params(
+        &format!("{}{}", web_root, CALLBACK_PATH),
+        &[("state", &*state)],
+    )
+    .context("Invalid web root.")?;
+    Url::parse_with_params(
+        STEAM_OPENID_URL,
+        &[
+            ("openid.ns", OPENID_NS),
+            ("openid.mode", "checkid_setup"),
+            ("openid.return_to", return_to.as_str()),
+            ("openid.realm", web_root),
+            ("openid.identity", OPENID_IDENTIFIER_SELECT),
+            ("openid.claimed_id", OPENID_IDENTIFIER_SELECT),
+        ],
+    )
+    .context("Failed to build Steam sign in URL.")
+}
+
+/// Finish signing in with the parameters Steam sent the user back with, exchanging them for a
+/// masterbase session. The masterbase checks them with Steam itself, as they have passed through
+/// the browser.
+pub async fn complete_login(
+    endpoint: &str,
+    mut params: HashMap<String, String>,
+) -> Result<MasterbaseSession> {
+    let state = params.remove("state");
+    let pending = PENDING_LOGIN.lock().unwrap().take();
+    if pending.is_none() || state.as_deref() != pending.as_deref() {
+        return Err(anyhow!(
+            "No sign in was started, or it was started elsewhere."
+        ));
+    }
+    if params.get("openid.mode").map(String::as_str) != Some("id_res") {
+        return Err(anyhow!("Signing in to Steam was cancelled."));
+    }
+    let steamid: SteamID = params
+        .get("openid.claimed_id")
+        .and_then(|id| id.strip_prefix(STEAM_IDENTITY_PREFIX))
+        .and_then(|id| id.parse().ok())
+        .context("Steam did not say which account was signed in to.")?;
+
+    let response = reqwest::Client::new()
+        .post(format!("{}/auth/openid", endpoint))
+        .json(&params)
+        .timeout(REQUEST_TIMEOUT)
+        .send()
+        .await
+        .context("Failed to reach the masterbase.")?
+        .error_for_status()
+        .context("The masterbase refused the sign in.")?
+        .json::<TokenResponse>()
+        .await
+        .context("Failed to read the masterbase's response.")?;
+
+    Ok(MasterbaseSession {
+        token: Secret::Plain(response.token.into()),
+        steamid,
+        expires_at: response.expires_at,
+    })
+}
+
+/// Swap a session for a new one that lasts longer
+async fn refresh(endpoint: &str, session: &MasterbaseSession) -> Result<MasterbaseSession> {
+    let authorization = authorization(session).context("The session is locked.")?;
+    let response = reqwest::Client::new()
+        .post(format!("{}/auth/refresh", endpoint))
+        .header(reqwest::header::AUTHORIZATION, authorization)
+        .timeout(REQUEST_TIMEOUT)
+        .send()
+        .await
+        .context("Failed to reach the masterbase.")?
+        .error_for_status()
+        .context("The masterbase refused to refresh the session.")?
+        .json::<TokenResponse>()
+        .await
+        .context("Failed to read the masterbase's response.")?;
+
+    Ok(MasterbaseSession {
+        token: Secret::Plain(response.token.into()),
+        steamid: session.steamid,
+        expires_at: response.expires_at,
+    })
+}
+
+/// Keep the masterbase session from expiring, refreshing it shortly before it would. Sessions the
+/// masterbase no longer accepts are signed out of. This will never return, so it should be
+/// spawned in a separate `tokio::task`.
+pub async fn auth_loop(settings: Arc<RwLock<Settings>>) {
+    let mut interval = tokio::time::interval(CHECK_INTERVAL);
+    loop {
+        interval.tick().await;
+
+        let (endpoint, session) = {
+            let settings = settings.read().unwrap();
+            match (
+                settings.get_masterbase_endpoint(),
+                settings.get_masterbase_session(),
+            ) {
+                (Some(endpoint), Some(session)) => (endpoint, session),
+                _ => continue,
+            }
+        };
+        if session.token.is_locked() || session.expires_at > now() + REFRESH_BEFORE {
+            continue;
+        }
+
+        match refresh(&endpoint, &session).await {
+            Ok(session) => {
+                tracing::info!("Refreshed masterbase session");
+                let mut settings = settings.write().unwrap();
+                settings.set_masterbase_session(Some(session));
+                settings.save_ok();
+            }
+            Err(e) => {
+                let rejected = e
+                    .chain()
+                    .find_map(|e| e.downcast_ref::<reqwest::Error>())
+                    .and_then(reqwest::Error::status)
+                    == Some(StatusCode::UNAUTHORIZED);
+                if !rejected && session.expires_at > now() {
+                    tracing::warn!("Could not refresh masterbase session, retrying: {:?}", e);
+                    continue;
+                }
+
+                tracing::warn!("Masterbase session has ended: {:?}", e);
+                let mut settings = settings.write().unwrap();
+                settings.set_masterbase_session(None);
+                settings.save_ok();
+                events::emit_system(SystemEventLevel::Warning, tr("masterbase-signed-out", &[]));
+            }
+        }
+    }
+}
@@ -0,0 +1,220 @@
+//! Normalizing player names so they can be compared. Bots get around the game refusing duplicate
+//! names (and every naive comparison) by padding copied names with invisible characters, or by
+//! swapping letters for ones from other scripts that look the same. Names are brought back to
+//! what they look like: invisible characters are dropped, compatibility forms (e.g. fullwidth or
+//! mathematical letters) are replaced with the plain characters they stand for, as NFKC would,
+//! and the most common confusables are mapped to the latin letters they imitate.
+
+/// Characters that take up no space or render as blank, which are never needed to tell two
+/// names apart
+const INVISIBLE_CHARS: &[char] = &[
+    '\u{00ad}',
+    '\u{034f}',
+    '\u{061c}',
+    '\u{115f}',
+    '\u{1160}',
+    '\u{17b4}',
+    '\u{17b5}',
+    '\u{180e}',
+    '\u{200b}',
+    '\u{200c}',
+    '\u{200d}',
+    '\u{200e}',
+    '\u{200f}',
+    '\u{202a}',
+    '\u{202b}',
+    '\u{202c}',
+    '\u{202d}',
+    '\u{202e}',
+    '\u{2060}',
+    '\u{2061}',
+    '\u{2062}',
+    '\u{2063}',
+    '\u{2064}',
+    '\u{2066}',
+    '\u{2067}',
+    '\u{2068}',
+    '\u{2069}',
+    '\u{206a}',
+    '\u{206b}',
+    '\u{206c}',
+    '\u{206d}',
+    '\u{206e}',
+    '\u{206f}',
+    '\u{2800}',
+    '\u{3164}',
+    '\u{feff}',
+    '\u{ffa0}',
+    '\u{1d159}',
+    '\u{1d173}',
+    '\u{1d174}',
+    '\u{1d175}',
+    '\u{1d176}',
+    '\u{1d177}',
+    '\u{1d178}',
+    '\u{1d179}',
+    '\u{1d17a}',
+];
+
+/// Letters from other scripts that look the same as a latin letter in the game's font
+const CONFUSABLES: &[(char, char)] = &[
+    // Cyrillic
+    ('а', 'a'),
+    ('в', 'B'),
+    ('е', 'e'),
+    ('ё', 'e'),
+    ('к', 'k'),
+    ('м', 'M'),
+    ('н', 'H'),
+    ('о', 'o'),
+    ('р', 'p'),
+    ('с', 'c'),
+    ('т', 'T'),
+    ('у', 'y'),
+    ('х', 'x'),
+    ('і', 'i'),
+    ('ј', 'j'),
+    ('ѕ', 's'),
+    ('ԁ', 'd'),
+    ('ԛ', 'q'),
+    ('ԝ', 'w'),
+    ('А', 'A'),
+    ('В', 'B'),
+    ('Е', 'E'),
+    ('К', 'K'),
+    ('М', 'M'),
+    ('Н', 'H'),
+    ('О', 'O'),
+    ('Р', 'P'),
+    ('С', 'C'),
+    ('Т', 'T'),
+    ('У', 'Y'),
+    ('Х', 'X'),
+    ('І', 'I'),
+    ('Ј', 'J'),
+    ('Ѕ', 'S'),
+    // Greek
+    ('α', 'a'),
+    ('ο', 'o'),
+    ('ρ', 'p'),
+    ('ν', 'v'),
+    ('ι', 'i'),
+    ('κ', 'k'),
+    ('Α', 'A'),
+    ('Β', 'B'),
+    ('Ε', 'E'),
+    ('Ζ', 'Z'),
+    ('Η', 'H'),
+    ('Ι', 'I'),
+    ('Κ', 'K'),
+    ('Μ', 'M'),
+    ('Ν', 'N'),
+    ('Ο', 'O'),
+    ('Ρ', 'P'),
+    ('Τ', 'T'),
+    ('Υ', 'Y'),
+    ('Χ', 'X'),
+    // Latin lookalikes
+    ('ı', 'i'),
+    ('ɡ', 'g'),
+    ('ɑ', 'a'),
+    ('ʏ', 'y'),
+    ('ᴀ', 'A'),
+    ('ʙ', 'B'),
+    ('ᴄ', 'C'),
+    ('ᴅ', 'D'),
+    ('ᴇ', 'E'),
+    ('ɢ', 'G'),
+    ('ʜ', 'H'),
+    ('ɪ', 'I'),
+    ('ᴊ', 'J'),
+    ('ᴋ', 'K'),
+    ('ʟ', 'L'),
+    ('ᴍ', 'M'),
+    ('ɴ', 'N'),
+    ('ᴏ', 'O'),
+    ('ᴘ', 'P'),
+    ('ʀ', 'R'),
+    ('ᴛ', 'T'),
+    ('ᴜ', 'U'),
+    ('ᴠ', 'V'),
+    ('ᴡ', 'W'),
+    ('ᴢ', 'Z'),
+];
+
+/// Whether a character renders as nothing at all
+pub fn is_invisible(c: char) -> bool {
+    INVISIBLE_CHARS.contains(&c)
+        // Variation selectors
+        || ('\u{fe00}'..='\u{fe0f}').contains(&c)
+        || ('\u{e0100}'..='\u{e01ef}').contains(&c)
+        // Tags
+        || ('\u{e0000}'..='\u{e007f}').contains(&c)
+}
+
+/// The plain character a compatibility form stands for, the part of NFKC that bots make use of
+fn compatibility(c: char) -> char {
+    let offset = |c: char, start: u32| c as u32 - start;
+    let from = |base: char, offset: u32| char::from_u32(base as u32 + offset).unwrap_or(base);
+    match c {
+        // Fullwidth ASCII
+        '\u{ff01}'..='\u{ff5e}' => from('!', offset(c, 0xff01)),
+        // Mathematical bold, italic, script, fraktur, double-struck, sans-serif and monospace
+        // letters, each style being A-Z then a-z
+        '\u{1d400}'..='\u{1d6a3}' => match offset(c, 0x1d400) % 52 {
+            i @ 0..=25 => from('A', i),
+            i => from('a', i - 26),
+        },
+        // Mathematical digits, in five styles
+        '\u{1d7ce}'..='\u{1d7ff}' => from('0', offset(c, 0x1d7ce) % 10),
+        // Circled letters and digits
+        '\u{24b6}'..='\u{24cf}' => from('A', offset(c, 0x24b6)),
+        '\u{24d0}'..='\u{24e9}' => from('a', offset(c, 0x24d0)),
+        '\u{2460}'..='\u{2468}' => from('1', offset(c, 0x2460)),
+        // Superscript and subscript digits
+        '\u{2070}' | '\u{2080}' => '0',
+        '\u{00b9}' => '1',
+        '\u{00b2}' => '2',
+        '\u{00b3}' => '3',
+        '\u{2074}'..='\u{2079}' => from('4', offset(c, 0x2074)),
+        '\u{2081}'..='\u{2089}' => from('1', offset(c, 0x2081)),
+        // Spaces of every width
+        '\u{00a0}' | '\u{2000}'..='\u{200a}' | '\u{202f}' | '\u{205f}' | '\u{3000}' => ' ',
+        _ => c,
+    }
+}
+
+fn unconfuse(c: char) -> char {
+    CONFUSABLES
+        .iter()
+        .find(|(confusable, _)| *confusable == c)
+        .map_or(c, |(_, latin)| *latin)
+}
+
+/// A name the way it looks: without invisible characters, with compatibility forms and
+/// confusables replaced by the plain latin characters they imitate, and with runs of whitespace
+/// collapsed into a single space. Case is kept.
+pub fn normalize(name: &str) -> String {
+    let mut normalized = String::with_capacity(name.len());
+    for c in name
+        .chars()
+        .filter(|c| !is_invisible(*c))
+        .map(compatibility)
+        .map(unconfuse)
+    {
+        if c.is_whitespace() {
+            if !normalized.is_empty() && !normalized.ends_with(' ') {
+                normalized.push(' ');
+            }
+        } else {
+            normalized.push(c);
+        }
+    }
+    normalized.truncate(normalized.trim_end().len());
+    normalized
+}
+
+/// [normalize]d and lowercased, for comparing and searching names regardless of case
+pub fn fold(name: &str) -> String {
+    normalize(name).to_lowercase()
+}
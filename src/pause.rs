@@ -0,0 +1,44 @@
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+
+use crate::analytics::history::now;
+
+/// Seconds without being on a server before background work is paused, so briefly dropping out
+/// (e.g. changing servers) doesn't pause and resume everything
+const PAUSE_AFTER: u64 = 60;
+
+/// Whether expensive background work (friend lookups and rechecks) is paused because the user is
+/// in the main menu, or otherwise not on a server. Work the user asked for is never paused.
+static PAUSED: AtomicBool = AtomicBool::new(false);
+/// Unix timestamp (seconds) of when the user was last seen on a server, or of the first
+/// observation if they haven't been yet, so starting in the main menu doesn't pause straight away.
+/// 0 before anything has been observed.
+static LAST_IN_GAME: AtomicU64 = AtomicU64::new(0);
+
+/// Whether background work is currently paused
+pub fn paused() -> bool {
+    PAUSED.load(Ordering::Relaxed)
+}
+
+/// Note whether the user is currently on a server, pausing background work once they have been
+/// away for a while and resuming it as soon as they are back
+pub fn observe(in_game: bool) {
+    let now = now();
+    let _ = LAST_IN_GAME.compare_exchange(0, now, Ordering::Relaxed, Ordering::Relaxed);
+    let pause = if in_game {
+        LAST_IN_GAME.store(now, Ordering::Relaxed);
+        false
+    } else {
+        now.saturating_sub(LAST_IN_GAME.load(Ordering::Relaxed)) >= PAUSE_AFTER
+    };
+
+    if PAUSED.swap(pause, Ordering::Relaxed) != pause {
+        tracing::info!(
+            "{} background work.",
+            if pause {
+                "Not on a server, pausing"
+            } else {
+                "Back on a server, resuming"
+            }
+        );
+    }
+}
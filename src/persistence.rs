@@ -0,0 +1,180 @@
+use std::{
+    ffi::OsString,
+    fs::{self, File},
+    io::{ErrorKind, Write},
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    events::{self, SystemEventLevel},
+    locale::tr,
+    settings::ConfigFilesError,
+};
+
+/// How many previous versions of each file are kept to recover from
+const BACKUPS: usize = 3;
+
+/// `path` with `suffix` appended to the file name, e.g. `playerlist.json.bak1`
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut name: OsString = path.file_name().unwrap_or_default().into();
+    name.push(suffix);
+    path.with_file_name(name)
+}
+
+fn backup_path(path: &Path, n: usize) -> PathBuf {
+    with_suffix(path, &format!(".bak{}", n))
+}
+
+fn checksum_path(path: &Path) -> PathBuf {
+    with_suffix(path, ".sum")
+}
+
+fn io_error(path: &Path, e: std::io::Error) -> ConfigFilesError {
+    ConfigFilesError::IO(path.to_string_lossy().into(), e)
+}
+
+/// 64-bit FNV-1a hash, used to tell if a file has been damaged since it was written.
+/// Written out by hand as it has to stay the same between versions of the program.
+fn checksum(contents: &[u8]) -> String {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in contents {
+        hash ^= *byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    format!("{:016x}", hash)
+}
+
+/// Rename `from` (and its checksum) to `to`, if it exists
+fn rename_with_checksum(from: &Path, to: &Path) -> Result<(), ConfigFilesError> {
+    for (from, to) in [
+        (from.to_path_buf(), to.to_path_buf()),
+        (checksum_path(from), checksum_path(to)),
+    ] {
+        match fs::rename(&from, &to) {
+            Err(e) if e.kind() != ErrorKind::NotFound => return Err(io_error(&from, e)),
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+/// Write `contents` to a temporary file next to `path` and flush it to disk, returning the
+/// temporary file's path
+fn write_tmp(path: &Path, contents: &[u8]) -> Result<PathBuf, ConfigFilesError> {
+    let tmp_path = with_suffix(path, ".tmp");
+    let mut tmp = File::create(&tmp_path).map_err(|e| io_error(&tmp_path, e))?;
+    tmp.write_all(contents)
+        .and_then(|_| tmp.sync_all())
+        .map_err(|e| io_error(&tmp_path, e))?;
+    Ok(tmp_path)
+}
+
+/// Write a file so that it is never left partially written. The contents and their checksum are
+/// written to temporary files which then replace the originals, with the previous versions kept
+/// as backups that [read_recovering] can fall back on.
+pub fn write_atomic(path: &Path, contents: &str) -> Result<(), ConfigFilesError> {
+    let tmp_path = write_tmp(path, contents.as_bytes())?;
+    let sum_path = checksum_path(path);
+    let tmp_sum_path = write_tmp(&sum_path, checksum(contents.as_bytes()).as_bytes())?;
+
+    // Shuffle the backups along, then copy (not move) the current version in as the newest one
+    // so there is never a moment where the file doesn't exist.
+    if path.exists() {
+        for n in (1..BACKUPS).rev() {
+            rename_with_checksum(&backup_path(path, n), &backup_path(path, n + 1))?;
+        }
+        let newest_backup = backup_path(path, 1);
+        fs::copy(path, &newest_backup).map_err(|e| io_error(&newest_backup, e))?;
+        match fs::copy(checksum_path(path), checksum_path(&newest_backup)) {
+            Err(e) if e.kind() != ErrorKind::NotFound => {
+                return Err(io_error(&checksum_path(path), e))
+            }
+            _ => {}
+        }
+    }
+
+    fs::rename(&tmp_path, path).map_err(|e| io_error(path, e))?;
+    fs::rename(&tmp_sum_path, &sum_path).map_err(|e| io_error(&sum_path, e))?;
+
+    Ok(())
+}
+
+/// Read and parse a single file. If it can't be parsed, its checksum is used to tell whether it was
+/// damaged or written that way. Backups must always match their checksum, as they should never be
+/// edited by hand.
+fn read_checked<T>(
+    path: &Path,
+    is_backup: bool,
+    parse: &impl Fn(&str) -> Result<T, ConfigFilesError>,
+) -> Result<T, ConfigFilesError> {
+    let contents = fs::read_to_string(path).map_err(|e| io_error(path, e))?;
+    let intact = fs::read_to_string(checksum_path(path))
+        .map(|sum| sum.trim() == checksum(contents.as_bytes()))
+        .ok();
+
+    if is_backup && intact == Some(false) {
+        return Err(ConfigFilesError::Other(anyhow::anyhow!(
+            "{:?} does not match its checksum",
+            path
+        )));
+    }
+
+    match parse(&contents) {
+        Ok(parsed) => {
+            if intact == Some(false) {
+                tracing::debug!("{:?} has been modified outside of the client.", path);
+            }
+            Ok(parsed)
+        }
+        Err(e) => {
+            if intact == Some(false) {
+                tracing::error!("{:?} appears to have been damaged.", path);
+            }
+            Err(e)
+        }
+    }
+}
+
+/// Read and parse a file written with [write_atomic]. If it is missing or can't be parsed, the most
+/// recent backup that can be is used instead and a [SystemEvent](crate::events::SystemEvent)
+/// is raised. The damaged file is kept alongside with a `.corrupt` suffix.
+///
+/// If there is no usable backup either, the original error is returned.
+pub fn read_recovering<T>(
+    path: &Path,
+    parse: impl Fn(&str) -> Result<T, ConfigFilesError>,
+) -> Result<T, ConfigFilesError> {
+    let error = match read_checked(path, false, &parse) {
+        Ok(parsed) => return Ok(parsed),
+        Err(e) => e,
+    };
+
+    for n in 1..=BACKUPS {
+        let backup = backup_path(path, n);
+        if !backup.exists() {
+            continue;
+        }
+
+        match read_checked(&backup, true, &parse) {
+            Ok(parsed) => {
+                if path.exists() {
+                    let corrupt = with_suffix(path, ".corrupt");
+                    if let Err(e) = fs::rename(path, &corrupt) {
+                        tracing::error!("Failed to move aside {:?}: {:?}", path, e);
+                    }
+                }
+
+                let message = tr(
+                    "backup-restored",
+                    &[("file", &path.to_string_lossy()), ("error", &error)],
+                );
+                tracing::warn!("{}", message);
+                events::emit_system(SystemEventLevel::Warning, message);
+                return Ok(parsed);
+            }
+            Err(e) => tracing::warn!("Backup {:?} is not usable either: {}", backup, e),
+        }
+    }
+
+    Err(error)
+}
@@ -1,28 +1,56 @@
-use serde::{Serialize, Serializer};
+use serde::{Deserialize, Serialize, Serializer};
 use std::{
     collections::{HashMap, HashSet, VecDeque},
     ops::{Deref, DerefMut},
     sync::Arc,
+    time::{SystemTime, UNIX_EPOCH},
 };
-use steamid_ng::SteamID;
 
 use crate::{
-    io::{g15::G15Player, regexes::StatusLine},
+    io::{
+        g15::G15Player,
+        regexes::{PlayerKill, StatusLine},
+    },
+    killfeed::{KillFeed, KillSource},
+    lists::{ImportedLists, ListMatch},
     player_records::{default_custom_data, PlayerRecords, Verdict},
+    steamapi::{
+        risk::{AccountNewness, AccountRisk},
+        SteamAPIError,
+    },
+    steamid::SteamID,
+    webhooks::{self, MarkEvent},
 };
 
 pub mod tags {
     pub const FRIEND: &str = "Friend";
+    /// In a Steam group that is flagged or shared with marked players, see [crate::groups]
+    pub const GROUP: &str = "Flagged group";
 }
 
 const MAX_HISTORY_LEN: usize = 100;
+/// Most Steam profiles kept for players who aren't connected, so anyone the UI was just looking at
+/// can still be shown in full without being looked up again
+const MAX_RECENT_STEAM_INFO: usize = 500;
+const MAX_CHAT_LEN: usize = 50;
+/// Accounts older than this (seconds) are never fingerprinted as bots, see
+/// [SteamInfo::looks_like_bot]
+const BOT_ACCOUNT_MAX_AGE: i64 = 90 * 24 * 60 * 60;
 
 pub struct Players {
     pub game_info: HashMap<SteamID, GameInfo>,
     pub steam_info: HashMap<SteamID, SteamInfo>,
+    /// Players in [Players::steam_info], from least to most recently looked up
+    steam_info_order: VecDeque<SteamID>,
     pub friend_info: HashMap<SteamID, FriendInfo>,
     pub records: PlayerRecords,
+    pub lists: ImportedLists,
     pub tags: HashMap<SteamID, HashSet<Arc<str>>>,
+    pub chat: HashMap<SteamID, VecDeque<ChatLine>>,
+    /// The most recent kills, oldest first
+    pub kills: KillFeed,
+    /// Why players' profiles couldn't be looked up, until they are
+    pub lookup_errors: HashMap<SteamID, SteamAPIError>,
 
     pub connected: Vec<SteamID>,
     pub history: VecDeque<SteamID>,
@@ -36,9 +64,14 @@ impl Players {
         Players {
             game_info: HashMap::new(),
             steam_info: HashMap::new(),
+            steam_info_order: VecDeque::new(),
             friend_info: HashMap::new(),
             tags: HashMap::new(),
+            chat: HashMap::new(),
+            kills: KillFeed::default(),
+            lookup_errors: HashMap::new(),
             records,
+            lists: ImportedLists::new(),
 
             connected: Vec::new(),
             history: VecDeque::with_capacity(MAX_HISTORY_LEN),
@@ -69,6 +102,134 @@ impl Players {
         }
     }
 
+    /// Find the SteamID of a connected player by their current name
+    pub fn find_connected_by_name(&self, name: &str) -> Option<SteamID> {
+        self.connected
+            .iter()
+            .find(|s| {
+                self.game_info
+                    .get(s)
+                    .is_some_and(|gi| gi.name.as_ref() == name)
+            })
+            .copied()
+    }
+
+    /// Find a connected player by their user ID on the server, as used in console commands
+    pub fn find_connected_by_userid(&self, userid: &str) -> Option<SteamID> {
+        self.connected
+            .iter()
+            .find(|s| {
+                self.game_info
+                    .get(s)
+                    .is_some_and(|gi| gi.userid.as_ref() == userid)
+            })
+            .copied()
+    }
+
+    /// Record a chat message sent by a player, keeping only their most recent messages in memory
+    /// and storing it on their record
+    pub fn record_chat(&mut self, steamid: SteamID, message: Arc<str>) {
+        let line = ChatLine {
+            time: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or(0),
+            message,
+        };
+
+        let chat = self.chat.entry(steamid).or_default();
+        if chat.len() >= MAX_CHAT_LEN {
+            chat.pop_front();
+        }
+        chat.push_back(line.clone());
+        self.records.record_chat(steamid, line);
+    }
+
+    /// Record a kill from the console, matching up the killer and victim with connected players
+    /// by name
+    pub fn record_kill(&mut self, kill: &PlayerKill) {
+        self.kills.add(KillLine {
+            time: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or(0),
+            killer: self.find_connected_by_name(&kill.killer_name),
+            killer_name: kill.killer_name.clone(),
+            victim: self.find_connected_by_name(&kill.victim_name),
+            victim_name: kill.victim_name.clone(),
+            weapon: kill.weapon.clone(),
+            crit: kill.crit,
+            source: KillSource::Console,
+        });
+    }
+
+    /// Record a kill from the demo being recorded
+    pub fn record_demo_kill(&mut self, kill: KillLine) {
+        self.kills.add(kill);
+    }
+
+    /// Store a player's freshly looked up Steam profile. Profiles of connected players are always
+    /// kept, along with the [MAX_RECENT_STEAM_INFO] most recently looked up of everyone else.
+    /// Note that a player's profile couldn't be looked up. Anything already known about them is
+    /// kept, as it is most likely still right.
+    pub fn lookup_failed(&mut self, steamid: SteamID, error: SteamAPIError) {
+        tracing::debug!("Lookup of {} failed: {}", steamid, error);
+        self.lookup_errors.insert(steamid, error);
+    }
+
+    pub fn insert_steam_info(&mut self, steamid: SteamID, info: SteamInfo) {
+        self.lookup_errors.remove(&steamid);
+        self.steam_info.insert(steamid, info);
+        self.steam_info_order.retain(|s| *s != steamid);
+        self.steam_info_order.push_back(steamid);
+
+        let mut disconnected = self
+            .steam_info_order
+            .iter()
+            .filter(|s| !self.connected.contains(s))
+            .count();
+        while disconnected > MAX_RECENT_STEAM_INFO {
+            let Some(i) = self
+                .steam_info_order
+                .iter()
+                .position(|s| !self.connected.contains(s))
+            else {
+                break;
+            };
+            if let Some(oldest) = self.steam_info_order.remove(i) {
+                self.steam_info.remove(&oldest);
+            }
+            disconnected -= 1;
+        }
+    }
+
+    /// Give a player a new verdict, as decided by the user
+    pub fn set_verdict(&mut self, steamid: SteamID, verdict: Verdict) {
+        let name = self
+            .game_info
+            .get(&steamid)
+            .map(|gi| gi.name.clone())
+            .or_else(|| {
+                self.steam_info
+                    .get(&steamid)
+                    .map(|si| si.account_name.clone())
+            });
+        let record = self.records.entry(steamid).or_default();
+        if let Some(event) = MarkEvent::new(steamid, name, record.verdict, verdict) {
+            webhooks::notify(event);
+        }
+        record.verdict = verdict;
+        record.marked_by_rule = None;
+        // Bans are only tracked from when the player was marked
+        if !record.watches_bans() {
+            record.ban_watch = None;
+        }
+        if record.is_empty() {
+            self.records.remove(&steamid);
+        }
+        self.lists.record_verdict(steamid, verdict);
+    }
+
     /// Updates friends lists of a user
     /// Propagates to all other friends lists to ensure two-way lookup possible.
     /// Only call if friends list was obtained directly from Steam API (i.e. friends list is public)
@@ -105,10 +266,10 @@ impl Players {
     /// Helper function to add a friend to a friends list
     fn propagate_friend(&mut self, steamid: SteamID, friend: Friend) {
         let friend_info = self.friend_info.entry(friend.steamid).or_default();
-        
+
         friend_info.push(Friend {
-            steamid: steamid,
-            friend_since: friend.friend_since
+            steamid,
+            friend_since: friend.friend_since,
         });
 
         self.update_user_friend_tag(friend.steamid);
@@ -118,14 +279,14 @@ impl Players {
     fn remove_from_friends_list(&mut self, steamid: &SteamID, friend_to_remove: &SteamID) {
         if let Some(friends) = self.friend_info.get_mut(steamid) {
             friends.retain(|f| f.steamid != *friend_to_remove);
-            if friends.len() == 0 && friends.public.is_none() {
+            if friends.is_empty() && friends.public.is_none() {
                 self.friend_info.remove(steamid);
             }
         }
 
         if let Some(friends) = self.friend_info.get_mut(friend_to_remove) {
             friends.retain(|f| f.steamid != *steamid);
-            if friends.len() == 0 && friends.public.is_none() {
+            if friends.is_empty() && friends.public.is_none() {
                 self.friend_info.remove(friend_to_remove);
             }
         }
@@ -162,7 +323,7 @@ impl Players {
             self.set_tag(friend, tags::FRIEND.into());
         } else {
             self.clear_tag(friend, tags::FRIEND);
-        } 
+        }
     }
 
     /// Check if an account is friends with the user.
@@ -204,7 +365,8 @@ impl Players {
 
     /// Moves any old players from the server into history. Any console commands (status, g15_dumpplayer, etc)
     /// should be run before calling this function again to prevent removing all players from the player list.
-    pub fn refresh(&mut self) {
+    /// Returns the players that were moved.
+    pub fn refresh(&mut self) -> Vec<SteamID> {
         // Get old players
         let unaccounted_players: Vec<SteamID> = self
             .connected
@@ -219,29 +381,47 @@ impl Players {
             .collect();
 
         self.connected.retain(|s| !unaccounted_players.contains(s));
+        self.move_to_history(unaccounted_players.clone());
+
+        // Mark all remaining players as unaccounted, they will be marked as accounted again
+        // when they show up in status or another console command.
+        self.game_info.values_mut().for_each(GameInfo::next_cycle);
+        unaccounted_players
+    }
 
+    /// Move everyone out of the player list and into history, e.g. when the user has moved to a
+    /// different server
+    pub fn disconnect_all(&mut self) {
+        let players = std::mem::take(&mut self.connected);
+        self.move_to_history(players);
+    }
+
+    fn move_to_history(&mut self, players: Vec<SteamID>) {
         // Remove any of them from the history as they will be added more recently
-        self.history
-            .retain(|p| !unaccounted_players.iter().any(|up| up == p));
+        self.history.retain(|p| !players.contains(p));
 
         // Shrink to not go past max number of players
-        let num_players = self.history.len() + unaccounted_players.len();
+        let num_players = self.history.len() + players.len();
         for _ in MAX_HISTORY_LEN..num_players {
-            self.history.pop_front();
-        }
-
-        for p in unaccounted_players {
-            self.history.push_back(p);
+            if let Some(removed) = self.history.pop_front() {
+                self.chat.remove(&removed);
+                self.lookup_errors.remove(&removed);
+            }
         }
 
-        // Mark all remaining players as unaccounted, they will be marked as accounted again
-        // when they show up in status or another console command.
-        self.game_info.values_mut().for_each(GameInfo::next_cycle);
+        self.history.extend(players);
     }
 
-    /// Gets a struct containing all the relevant data on a player in a serializable format
-    pub fn get_serializable_player(&self, steamid: &SteamID) -> Option<Player> {
-        let game_info = self.game_info.get(steamid)?;
+    /// Gets a struct containing all the relevant data on a player in a serializable format. Players
+    /// who haven't been seen in game are included if their Steam profile is known.
+    pub fn get_serializable_player(&self, steamid: &SteamID) -> Option<Player<'_>> {
+        let game_info = self.game_info.get(steamid);
+        let steam_info = self.steam_info.get(steamid);
+        let name = match (game_info, steam_info) {
+            (Some(game_info), _) => game_info.name.as_ref(),
+            (None, Some(steam_info)) => steam_info.account_name.as_ref(),
+            (None, None) => return None,
+        };
         let tags: Vec<&str> = self
             .tags
             .get(steamid)
@@ -260,6 +440,14 @@ impl Players {
             .map(|fi| fi.friends.iter().collect())
             .unwrap_or_default();
 
+        let newness = steam_info.and_then(|si| {
+            si.newness(
+                friend_info
+                    .filter(|fi| fi.public == Some(true))
+                    .map(|fi| fi.len()),
+            )
+        });
+
         let local_verdict = record
             .as_ref()
             .map(|r| r.verdict)
@@ -267,11 +455,11 @@ impl Players {
 
         Some(Player {
             isSelf: self.user.is_some_and(|user| user == *steamid),
-            name: game_info.name.as_ref(),
+            name,
             steamID64: *steamid,
             localVerdict: local_verdict,
-            steamInfo: self.steam_info.get(steamid),
-            gameInfo: Some(game_info),
+            steamInfo: steam_info,
+            gameInfo: game_info,
             customData: record
                 .as_ref()
                 .map(|r| r.custom_data.clone())
@@ -279,8 +467,11 @@ impl Players {
             convicted: false,
             tags,
             previous_names,
+            lists: self.lists.matches(steamid),
             friends,
             friendsIsPublic: friend_info.and_then(|fi| fi.public),
+            newness,
+            lookupError: self.lookup_errors.get(steamid).copied(),
         })
     }
 }
@@ -336,7 +527,16 @@ impl Serialize for Team {
     }
 }
 
-#[derive(Debug, Clone, Serialize)]
+impl<'de> Deserialize<'de> for Team {
+    fn deserialize<D>(d: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        Team::try_from(u32::deserialize(d)?).map_err(serde::de::Error::custom)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct SteamInfo {
     #[serde(rename = "name")]
@@ -351,9 +551,92 @@ pub struct SteamInfo {
     pub vac_bans: i64,
     pub game_bans: i64,
     pub days_since_last_ban: Option<i64>,
+    /// Banned from the Steam Community, e.g. for spamming. `None` if it couldn't be checked.
+    pub community_banned: Option<bool>,
+    /// Whether the account is banned from trading, which often goes along with scamming or
+    /// botting. `None` if it couldn't be checked.
+    pub economy_ban: Option<EconomyBan>,
+    /// Whether the profile has a background equipped. These profile customization fields are
+    /// `None` when they couldn't be checked, e.g. because the profile is private.
+    pub has_background: Option<bool>,
+    /// Showcases featured on the profile
+    pub showcases: Option<u32>,
+    /// Games visible on the profile, not counting TF2
+    pub games_visible: Option<u32>,
+    /// Hours played of TF2, if the profile shows it. Alts made for cheating rarely have many.
+    pub tf2_hours: Option<u32>,
+    /// Fresh alts are almost always level 0 or 1
+    pub steam_level: Option<u32>,
+    /// How likely the account is to be a throwaway, going by everything above. Kept up to date by
+    /// [SteamInfo::assess_risk].
+    #[serde(default)]
+    pub risk: Option<AccountRisk>,
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+impl SteamInfo {
+    /// Work out [SteamInfo::risk] again, e.g. after new bans were found or the account got older
+    /// while its profile was cached
+    pub fn assess_risk(&mut self) {
+        self.risk = Some(AccountRisk::assess(self));
+    }
+
+    /// Whether the account looks like a bot's: created recently and set up with a bare public
+    /// profile (default background, no showcases and no games visible). Every indicator has to
+    /// be known and match, so an account is never fingerprinted on missing information.
+    pub fn looks_like_bot(&self) -> bool {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs() as i64)
+            .unwrap_or_default();
+        let new_account = self
+            .time_created
+            .is_some_and(|created| now - created < BOT_ACCOUNT_MAX_AGE);
+
+        new_account
+            && self.profile_visibility == ProfileVisibility::Public
+            && self.has_background == Some(false)
+            && self.showcases == Some(0)
+            && self.games_visible == Some(0)
+    }
+
+    /// How new the account seems, going by its [AccountRisk] and `friends` (the size of its
+    /// friend list, if it is public). `None` if not enough is known, e.g. for most private
+    /// profiles.
+    pub fn newness(&self, friends: Option<usize>) -> Option<AccountNewness> {
+        if self.time_created.is_none() && self.steam_level.is_none() && friends.is_none() {
+            return None;
+        }
+        Some(match &self.risk {
+            Some(risk) => risk.newness(friends),
+            None => AccountRisk::assess(self).newness(friends),
+        })
+    }
+}
+
+/// Whether an account is allowed to trade, as Steam reports it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum EconomyBan {
+    NotBanned,
+    /// Allowed to trade again after a ban, but will be banned again if caught
+    Probation,
+    Banned,
+}
+
+impl EconomyBan {
+    /// Read the state from the API (`none`, `probation` or `banned`) or a community profile
+    /// (`None`, `Probation` or `Banned`)
+    pub fn from_steam(state: &str) -> Option<EconomyBan> {
+        match state.to_ascii_lowercase().as_str() {
+            "none" => Some(EconomyBan::NotBanned),
+            "probation" => Some(EconomyBan::Probation),
+            "banned" => Some(EconomyBan::Banned),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub enum ProfileVisibility {
     Private = 1,
     FriendsOnly = 2,
@@ -384,6 +667,9 @@ pub struct GameInfo {
     pub state: PlayerState,
     pub kills: u32,
     pub deaths: u32,
+    /// The map and type of game the player was last seen on
+    pub map: Option<Arc<str>>,
+    pub gamemode: Option<Arc<str>>,
     #[serde(skip)]
     /// How many cycles has passed since the player has been seen
     last_seen: u32,
@@ -401,6 +687,8 @@ impl Default for GameInfo {
             state: PlayerState::Active,
             kills: 0,
             deaths: 0,
+            map: None,
+            gamemode: None,
             last_seen: 0,
         }
     }
@@ -482,9 +770,32 @@ impl GameInfo {
     }
 }
 
-#[derive(Debug, Clone, Serialize)]
+/// A chat message sent by a player
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChatLine {
+    /// Unix timestamp (seconds) of when the message was seen
+    pub time: u64,
+    pub message: Arc<str>,
+}
+
+/// A kill seen in the console or demo, see [KillFeed]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct KillLine {
+    /// Unix timestamp (seconds) of when the kill was seen
+    pub time: u64,
+    pub killer: Option<SteamID>,
+    pub killer_name: Arc<str>,
+    pub victim: Option<SteamID>,
+    pub victim_name: Arc<str>,
+    pub weapon: Arc<str>,
+    pub crit: bool,
+    pub source: KillSource,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Friend {
-    #[serde(rename = "steamID64", serialize_with = "serialize_steamid_as_string")]
+    #[serde(rename = "steamID64")]
     pub steamid: SteamID,
     #[serde(rename = "friendSince")]
     pub friend_since: u64,
@@ -510,18 +821,11 @@ impl DerefMut for FriendInfo {
     }
 }
 
-// Useful
-
-fn serialize_steamid_as_string<S: Serializer>(steamid: &SteamID, s: S) -> Result<S::Ok, S::Error> {
-    format!("{}", u64::from(*steamid)).serialize(s)
-}
-
 #[allow(non_snake_case)]
 #[derive(Debug, Serialize)]
 pub struct Player<'a> {
     pub isSelf: bool,
     pub name: &'a str,
-    #[serde(serialize_with = "serialize_steamid_as_string")]
     pub steamID64: SteamID,
 
     pub steamInfo: Option<&'a SteamInfo>,
@@ -531,7 +835,11 @@ pub struct Player<'a> {
     pub convicted: bool,
     pub tags: Vec<&'a str>,
     pub previous_names: Vec<&'a str>,
+    pub lists: Vec<ListMatch<'a>>,
 
     pub friends: Vec<&'a Friend>,
     pub friendsIsPublic: Option<bool>,
+    pub newness: Option<AccountNewness>,
+    /// Why the player's profile couldn't be looked up, if it couldn't
+    pub lookupError: Option<SteamAPIError>,
 }
@@ -1,50 +1,76 @@
 use std::{
-    collections::HashMap,
+    collections::{hash_map::Entry, HashMap, HashSet},
     fmt::Display,
-    io::ErrorKind,
-    ops::{Deref, DerefMut},
+    ops::Deref,
     path::PathBuf,
-    sync::Arc,
+    sync::{Arc, Mutex},
+    time::Duration,
 };
 
 use anyhow::Context;
 use serde::{Deserialize, Serialize};
 use serde_json::Map;
-use steamid_ng::SteamID;
 
 use crate::{
-    args::Args,
+    alts::AccountLink,
+    analytics::history::now,
+    demo::{
+        report::MatchReport,
+        suspicion::{EncounterSuspicion, SuspicionKind},
+        votes::Ballot,
+    },
+    names,
+    player::ChatLine,
     settings::{ConfigFilesError, Settings},
+    steamid::SteamID,
+    storage::{RowChanges, Storage},
 };
 
+pub mod archive;
+
+/// Number of matches worth of demo suspicion history to keep on each record
+const MAX_SUSPICION_HISTORY: usize = 50;
+/// Number of chat messages to keep on each record
+const MAX_CHAT_HISTORY: usize = 500;
+/// Number of kick votes to keep on each record
+const MAX_VOTE_HISTORY: usize = 100;
+/// Fewest votes to keep a bot before a player is flagged as protecting them
+const MIN_PROTECTING_VOTES: usize = 3;
+
 // PlayerList
 
 #[derive(Serialize, Deserialize)]
 pub struct PlayerRecords {
+    /// Where the records are saved, not saved at all if [None]
+    #[serde(skip)]
+    storage: Option<Arc<dyn Storage>>,
+    /// Records that have been changed or removed since they were last saved, so only they are
+    /// written
+    #[serde(skip)]
+    changed: Mutex<HashSet<SteamID>>,
+    /// How long chat messages are kept for, forever if `None`
     #[serde(skip)]
-    path: PathBuf,
-    pub records: HashMap<SteamID, PlayerRecord>,
+    chat_retention: Option<Duration>,
+    records: HashMap<SteamID, PlayerRecord>,
 }
 
 impl PlayerRecords {
-    /// Attempts to load the playerlist from the overriden (if provided in [Args]) or default location.
-    /// If it cannot be found, then a new one is created at the location.
+    /// Attempts to load the playerlist from `storage`, or creates a new one there if it hasn't
+    /// been saved before.
     ///
     /// **Panics**:
-    /// This function can panic if the playerlist file was provided but could not be parsed, or another
+    /// This function can panic if the playerlist was found but could not be parsed, or another
     /// unexpected error occurred to prevent data loss.
-    pub fn load_or_create(args: &Args) -> PlayerRecords {
-        // Playerlist
-        let playerlist_path: PathBuf = args
-        .playerlist
-        .as_ref()
-        .map(|i| Ok(i.into()))
-        .unwrap_or(PlayerRecords::locate_playerlist_file()).map_err(|e| {
-            tracing::error!("Could not find a suitable location for the playerlist: {} \nPlease specify a file path manually with --playerlist otherwise information may not be saved.", e); 
-        }).unwrap_or(PathBuf::from("playerlist.json"));
-
-        match PlayerRecords::load_from(playerlist_path) {
-            Ok(playerlist) => playerlist,
+    pub fn load_or_create(storage: Arc<dyn Storage>) -> PlayerRecords {
+        match PlayerRecords::load_from(storage.clone()) {
+            Ok(Some(playerlist)) => playerlist,
+            Ok(None) => {
+                tracing::warn!("Could not locate playerlist, creating new playerlist.");
+                PlayerRecords {
+                    storage: Some(storage),
+                    ..Default::default()
+                }
+            }
             Err(ConfigFilesError::Json(path, e)) => {
                 tracing::error!("{} could not be loaded: {:?}", path, e);
                 tracing::error!(
@@ -52,12 +78,6 @@ impl PlayerRecords {
                 );
                 panic!("Failed to load playerlist")
             }
-            Err(ConfigFilesError::IO(path, e)) if e.kind() == ErrorKind::NotFound => {
-                tracing::warn!("Could not locate {}, creating new playerlist.", &path);
-                let mut playerlist = PlayerRecords::default();
-                playerlist.set_path(path.into());
-                playerlist
-            }
             Err(e) => {
                 tracing::error!("Could not load playerlist: {:?}", e);
                 tracing::error!(
@@ -68,35 +88,67 @@ impl PlayerRecords {
         }
     }
 
-    /// Attempt to load the [PlayerRecords] from the provided file
-    pub fn load_from(path: PathBuf) -> Result<PlayerRecords, ConfigFilesError> {
-        let contents = std::fs::read_to_string(&path)
-            .map_err(|e| ConfigFilesError::IO(path.to_string_lossy().into(), e))?;
-        let mut playerlist: PlayerRecords = serde_json::from_str(&contents)
-            .map_err(|e| ConfigFilesError::Json(path.to_string_lossy().into(), e))?;
-        playerlist.path = path;
+    /// Attempt to load the [PlayerRecords] from `storage`, [None] if they were never saved there
+    pub fn load_from(storage: Arc<dyn Storage>) -> Result<Option<PlayerRecords>, ConfigFilesError> {
+        let Some(saved) = storage.read_records()? else {
+            return Ok(None);
+        };
+
+        let mut playerlist = PlayerRecords::default();
+        for (steamid, record) in &saved {
+            let record: PlayerRecord = serde_json::from_str(record)
+                .map_err(|e| ConfigFilesError::Json(format!("Record of {}", steamid), e))?;
+            playerlist.records.insert(*steamid, record);
+        }
+        playerlist.storage = Some(storage);
 
-        // Map all of the steamids to the records. They were not included when
-        // serializing/deserializing the records to prevent duplication in the
-        // resulting file.
-        for record in &mut playerlist.records.values_mut() {
+        playerlist.change_all(|record| {
             // Some old versions had the custom_data set to `null` by default, but an empty object is preferable
             // so I'm using this to fix it lol. It's really not necessary but at the time the UI wasn't
             // a fan of nulls in the custom_data and this fixes it so whatever. :3
-            if record.custom_data.is_null() {
+            let fix = record.custom_data.is_null();
+            if fix {
                 record.custom_data = serde_json::Value::Object(serde_json::Map::new());
             }
-        }
+            fix
+        });
 
-        Ok(playerlist)
+        Ok(Some(playerlist))
     }
 
-    /// Attempt to save the [PlayerRecords] to the file it was loaded from
+    /// Attempt to save the [PlayerRecords] that have changed to where they were loaded from.
+    /// Only the changed records are serialized.
     pub fn save(&self) -> Result<(), ConfigFilesError> {
-        let contents = serde_json::to_string(self).context("Failed to serialize playerlist.")?;
-        std::fs::write(&self.path, contents)
-            .map_err(|e| ConfigFilesError::IO(self.path.to_string_lossy().into(), e))?;
-        Ok(())
+        let Some(storage) = &self.storage else {
+            return Ok(());
+        };
+
+        let changed = std::mem::take(&mut *self.changed.lock().unwrap());
+        if changed.is_empty() {
+            return Ok(());
+        }
+        let mut changes = RowChanges::default();
+        let serialized = changed.iter().try_for_each(|steamid| {
+            match self.records.get(steamid) {
+                Some(record) => {
+                    changes
+                        .changed
+                        .insert(*steamid, serde_json::to_string(record)?);
+                }
+                None => changes.removed.push(*steamid),
+            }
+            Ok::<_, serde_json::Error>(())
+        });
+
+        let written = serialized
+            .context("Failed to serialize playerlist.")
+            .map_err(ConfigFilesError::from)
+            .and_then(|()| storage.write_records(changes));
+        if written.is_err() {
+            // Tried again on the next save
+            self.changed.lock().unwrap().extend(changed);
+        }
+        written
     }
 
     /// Attempt to save the [PlayerRecords], log errors and ignore result
@@ -105,35 +157,411 @@ impl PlayerRecords {
             tracing::error!("Failed to save playerlist: {:?}", e);
             return;
         }
-        // this will never fail to unwrap because the above error would have occured first and broken control flow.
-        tracing::debug!("Playerlist saved to {:?}", self.path);
+        tracing::debug!("Playerlist saved");
     }
 
-    pub fn set_path(&mut self, path: PathBuf) {
-        self.path = path;
+    /// Save the [PlayerRecords] and wait for them to actually be written, rather than leaving it
+    /// to the storage to write them when it suits. Errors are logged.
+    pub fn flush_ok(&self) {
+        self.save_ok();
+        if let Some(Err(e)) = self.storage.as_ref().map(|s| s.flush()) {
+            tracing::error!("Failed to write playerlist: {:?}", e);
+        }
     }
 
     pub fn locate_playerlist_file() -> Result<PathBuf, ConfigFilesError> {
         Settings::locate_config_directory().map(|dir| dir.join("playerlist.json"))
     }
 
+    /// The record of `steamid` to change, which will be written by the next save
+    pub fn get_mut(&mut self, steamid: &SteamID) -> Option<&mut PlayerRecord> {
+        let record = self.records.get_mut(steamid)?;
+        self.changed.get_mut().unwrap().insert(*steamid);
+        Some(record)
+    }
+
+    /// The record of `steamid` to change or add, which will be written by the next save
+    pub fn entry(&mut self, steamid: SteamID) -> Entry<'_, SteamID, PlayerRecord> {
+        self.changed.get_mut().unwrap().insert(steamid);
+        self.records.entry(steamid)
+    }
+
+    /// Add or replace the record of `steamid`, which will be written by the next save
+    pub fn insert(&mut self, steamid: SteamID, record: PlayerRecord) -> Option<PlayerRecord> {
+        self.changed.get_mut().unwrap().insert(steamid);
+        self.records.insert(steamid, record)
+    }
+
+    /// Remove the record of `steamid`, which will be removed from storage by the next save
+    pub fn remove(&mut self, steamid: &SteamID) -> Option<PlayerRecord> {
+        let record = self.records.remove(steamid)?;
+        self.changed.get_mut().unwrap().insert(*steamid);
+        Some(record)
+    }
+
+    /// Remove every record
+    pub fn clear(&mut self) {
+        let changed = self.changed.get_mut().unwrap();
+        changed.extend(self.records.drain().map(|(steamid, _)| steamid));
+    }
+
+    /// Change every record for which `change` returns true
+    fn change_all(&mut self, mut change: impl FnMut(&mut PlayerRecord) -> bool) {
+        let changed = self.changed.get_mut().unwrap();
+        for (steamid, record) in &mut self.records {
+            if change(record) {
+                changed.insert(*steamid);
+            }
+        }
+    }
+
+    /// Drop the records that no longer hold anything
+    fn remove_empty(&mut self) {
+        let changed = self.changed.get_mut().unwrap();
+        self.records.retain(|steamid, record| {
+            let keep = !record.is_empty();
+            if !keep {
+                changed.insert(*steamid);
+            }
+            keep
+        });
+    }
+
     pub fn update_name(&mut self, steamid: &SteamID, name: Arc<str>) {
         if let Some(record) = self.records.get_mut(steamid) {
             if !record.previous_names.contains(&name) {
                 record.previous_names.push(name);
+                self.changed.get_mut().unwrap().insert(*steamid);
             }
         }
     }
+
+    /// Merge the suspicion summary of a match into a player's record, creating the record if
+    /// the player doesn't have one yet. Only the most recent matches are kept.
+    pub fn add_match_suspicion(&mut self, steamid: SteamID, encounter: EncounterSuspicion) {
+        let record = self.entry(steamid).or_default();
+        record.suspicion.push(encounter);
+        if record.suspicion.len() > MAX_SUSPICION_HISTORY {
+            let excess = record.suspicion.len() - MAX_SUSPICION_HISTORY;
+            record.suspicion.drain(..excess);
+        }
+    }
+
+    /// Replace the flags of `kinds` every player was given for a match with those from a new
+    /// report on the same demo, keeping the time and map of the original and any flags of other
+    /// kinds. Returns the number of players whose records changed.
+    pub fn replace_match_suspicion(
+        &mut self,
+        report: &MatchReport,
+        kinds: &HashSet<SuspicionKind>,
+    ) -> usize {
+        let mut changed = 0;
+        let changed_ids = self.changed.get_mut().unwrap();
+        for (steamid, record) in self.records.iter_mut() {
+            let Some(index) = record.suspicion.iter().position(|e| e.demo == report.demo) else {
+                continue;
+            };
+            let kept = record.suspicion[index]
+                .suspicion
+                .replace_kinds(kinds, report.suspicion.get(steamid));
+            if !kept {
+                record.suspicion.remove(index);
+            }
+            changed_ids.insert(*steamid);
+            changed += 1;
+        }
+
+        // Players who weren't flagged before
+        for steamid in report.suspicion.players().keys() {
+            let already = self
+                .records
+                .get(steamid)
+                .is_some_and(|r| r.suspicion.iter().any(|e| e.demo == report.demo));
+            if !already {
+                if let Some(encounter) = report.encounter_suspicion(steamid) {
+                    self.add_match_suspicion(*steamid, encounter);
+                    changed += 1;
+                }
+            }
+        }
+
+        self.remove_empty();
+        changed
+    }
+
+    /// Store how each player voted on the kick votes of a match whose target is known, replacing
+    /// any already stored from the same demo so a reprocessed demo isn't counted twice. Votes are
+    /// only kept for players who already have a record, so not everyone ever seen voting ends up
+    /// in the records. Returns the players who are now flagged as protecting bots and weren't
+    /// before.
+    pub fn add_match_votes(&mut self, report: &MatchReport) -> Vec<SteamID> {
+        let mut ballots: HashMap<SteamID, Vec<VoteLine>> = HashMap::new();
+        for vote in &report.votes {
+            let Some(target) = vote.target else {
+                continue;
+            };
+            for (voter, ballot) in &vote.ballots {
+                if *voter == target {
+                    continue;
+                }
+                ballots.entry(*voter).or_default().push(VoteLine {
+                    time: report.time,
+                    demo: report.demo.clone(),
+                    target,
+                    ballot: *ballot,
+                });
+            }
+        }
+
+        // Taken before the demo's old votes are dropped, so going over it again doesn't flag anyone
+        let was_protecting: HashSet<SteamID> = ballots
+            .keys()
+            .copied()
+            .filter(|&voter| self.protects_bots(voter))
+            .collect();
+        self.change_all(|record| {
+            let len = record.votes.len();
+            record.votes.retain(|line| line.demo != report.demo);
+            record.votes.len() != len
+        });
+
+        let mut flagged = Vec::new();
+        for (voter, lines) in ballots {
+            let Some(record) = self.get_mut(&voter) else {
+                continue;
+            };
+            let votes = &mut record.votes;
+            votes.extend(lines);
+            if votes.len() > MAX_VOTE_HISTORY {
+                let excess = votes.len() - MAX_VOTE_HISTORY;
+                votes.drain(..excess);
+            }
+            if !was_protecting.contains(&voter) && self.protects_bots(voter) {
+                flagged.push(voter);
+            }
+        }
+
+        self.remove_empty();
+        flagged
+    }
+
+    /// How a player has voted on kicks of players currently marked as bots, as the number of
+    /// votes to keep them and the total
+    pub fn bot_votes(&self, steamid: SteamID) -> (usize, usize) {
+        let Some(record) = self.records.get(&steamid) else {
+            return (0, 0);
+        };
+        let on_bots: Vec<&VoteLine> = record
+            .votes
+            .iter()
+            .filter(|line| {
+                self.records
+                    .get(&line.target)
+                    .is_some_and(|r| r.verdict == Verdict::Bot)
+            })
+            .collect();
+        let kept = on_bots.iter().filter(|l| l.ballot == Ballot::No).count();
+        (kept, on_bots.len())
+    }
+
+    /// Whether a player has consistently voted to keep bots, at least twice as often as to kick
+    /// them
+    pub fn protects_bots(&self, steamid: SteamID) -> bool {
+        let (kept, total) = self.bot_votes(steamid);
+        kept >= MIN_PROTECTING_VOTES && kept * 3 >= total * 2
+    }
+
+    /// Change how long chat messages are kept for, dropping any that are now too old
+    pub fn set_chat_retention(&mut self, retention: Option<Duration>) {
+        self.chat_retention = retention;
+        let Some(cutoff) = self.chat_cutoff() else {
+            return;
+        };
+
+        self.change_all(|record| {
+            let len = record.chat.len();
+            record.chat.retain(|line| line.time >= cutoff);
+            record.chat.len() != len
+        });
+        self.remove_empty();
+    }
+
+    /// Unix timestamp (seconds) before which chat messages are dropped
+    fn chat_cutoff(&self) -> Option<u64> {
+        self.chat_retention
+            .map(|retention| now().saturating_sub(retention.as_secs()))
+    }
+
+    /// Store a chat message on a player's record, creating the record if they don't have one yet.
+    /// The records are saved by the next [PlayerRecords::save_if_dirty].
+    pub fn record_chat(&mut self, steamid: SteamID, line: ChatLine) {
+        let cutoff = self.chat_cutoff().unwrap_or(0);
+        let chat = &mut self.entry(steamid).or_default().chat;
+        chat.retain(|line| line.time >= cutoff);
+        chat.push(line);
+        if chat.len() > MAX_CHAT_HISTORY {
+            let excess = chat.len() - MAX_CHAT_HISTORY;
+            chat.drain(..excess);
+        }
+    }
+
+    /// Save the records if any have changed since they were last saved
+    pub fn save_if_dirty(&mut self) {
+        if !self.changed.get_mut().unwrap().is_empty() {
+            self.save_ok();
+        }
+    }
+
+    /// Marked cheaters, bots and suspicious players whose bans haven't been checked for `age`,
+    /// least recently checked first. Accounts that have already been banned since they were marked
+    /// are left out.
+    pub fn due_ban_checks(&self, age: Duration) -> Vec<SteamID> {
+        let before = now().saturating_sub(age.as_secs());
+        let mut due: Vec<(SteamID, u64)> = self
+            .records
+            .iter()
+            .filter(|(_, record)| record.watches_bans())
+            .filter_map(|(steamid, record)| match &record.ban_watch {
+                None => Some((*steamid, 0)),
+                Some(watch) if watch.banned_at.is_none() && watch.last_checked <= before => {
+                    Some((*steamid, watch.last_checked))
+                }
+                Some(_) => None,
+            })
+            .collect();
+        due.sort_by_key(|(_, last_checked)| *last_checked);
+        due.into_iter().map(|(steamid, _)| steamid).collect()
+    }
+
+    /// Note the number of VAC and game bans a player currently has. Only marked cheaters, bots and
+    /// suspicious players are tracked, the first check after they are marked is used to tell which
+    /// bans came later. Returns true if this check found a new ban.
+    pub fn update_bans(&mut self, steamid: SteamID, vac_bans: i64, game_bans: i64) -> bool {
+        if !self.records.get(&steamid).is_some_and(|r| r.watches_bans()) {
+            return false;
+        }
+        let Some(record) = self.get_mut(&steamid) else {
+            return false;
+        };
+
+        let now = now();
+        let Some(watch) = &mut record.ban_watch else {
+            record.ban_watch = Some(BanWatch {
+                since: now,
+                vac_bans,
+                game_bans,
+                last_checked: now,
+                banned_at: None,
+            });
+            return false;
+        };
+
+        watch.last_checked = now;
+        let banned =
+            watch.banned_at.is_none() && (vac_bans > watch.vac_bans || game_bans > watch.game_bans);
+        if banned {
+            watch.banned_at = Some(now);
+        }
+        banned
+    }
+
+    /// How many marked cheaters, bots and suspicious players have gone on to be banned by Valve
+    pub fn ban_stats(&self) -> BanStats {
+        let marked: Vec<&PlayerRecord> =
+            self.records.values().filter(|r| r.watches_bans()).collect();
+        let checked = marked.iter().filter(|r| r.ban_watch.is_some()).count();
+        let banned_later = marked
+            .iter()
+            .filter(|r| r.ban_watch.as_ref().is_some_and(|w| w.banned_at.is_some()))
+            .count();
+
+        BanStats {
+            marked: marked.len(),
+            checked,
+            banned_later,
+            banned_ratio: (checked > 0).then(|| banned_later as f32 / checked as f32),
+        }
+    }
+
+    /// Every stored chat message matching `search`, most recent first
+    pub fn search_chat(&self, search: &ChatSearch) -> Vec<ChatSearchResult> {
+        let contains = search.contains.as_ref().map(|c| names::fold(c));
+        let mut results: Vec<ChatSearchResult> = self
+            .records
+            .iter()
+            .filter(|(steamid, _)| search.steamid.is_none_or(|s| s == **steamid))
+            .flat_map(|(steamid, record)| {
+                record.chat.iter().map(|line| ChatSearchResult {
+                    steamid: *steamid,
+                    name: record.previous_names.last().cloned(),
+                    time: line.time,
+                    message: line.message.clone(),
+                })
+            })
+            .filter(|result| {
+                search.since.is_none_or(|since| result.time >= since)
+                    && search.until.is_none_or(|until| result.time <= until)
+                    && contains
+                        .as_ref()
+                        .is_none_or(|c| names::fold(&result.message).contains(c))
+            })
+            .collect();
+
+        results.sort_by_key(|result| std::cmp::Reverse(result.time));
+        if let Some(limit) = search.limit {
+            results.truncate(limit);
+        }
+        results
+    }
+}
+
+/// Which stored chat messages to search for
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct ChatSearch {
+    /// Only messages sent by this player
+    pub steamid: Option<SteamID>,
+    /// Only messages containing this text, ignoring case and invisible characters
+    pub contains: Option<Arc<str>>,
+    /// Only messages sent at or after this unix timestamp (seconds)
+    pub since: Option<u64>,
+    /// Only messages sent at or before this unix timestamp (seconds)
+    pub until: Option<u64>,
+    pub limit: Option<usize>,
+}
+
+/// A stored chat message, along with who sent it
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChatSearchResult {
+    pub steamid: SteamID,
+    /// The most recent name the player was seen with
+    pub name: Option<Arc<str>>,
+    /// Unix timestamp (seconds)
+    pub time: u64,
+    pub message: Arc<str>,
+}
+
+/// How well the user's marks have been confirmed by Valve's bans
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BanStats {
+    /// Accounts currently marked as cheaters, bots or suspicious
+    pub marked: usize,
+    /// Marked accounts whose bans have been checked at least once
+    pub checked: usize,
+    /// Checked accounts that received a VAC or game ban after they were marked
+    pub banned_later: usize,
+    /// `banned_later` out of `checked`, if any have been checked
+    pub banned_ratio: Option<f32>,
 }
 
 impl Default for PlayerRecords {
     fn default() -> Self {
-        let path = Self::locate_playerlist_file()
-            .map_err(|e| tracing::warn!("Failed to create config directory: {:?}", e))
-            .unwrap_or("playerlist.json".into());
-
         PlayerRecords {
-            path,
+            storage: None,
+            changed: Mutex::new(HashSet::new()),
+            chat_retention: None,
             records: HashMap::new(),
         }
     }
@@ -147,12 +575,6 @@ impl Deref for PlayerRecords {
     }
 }
 
-impl DerefMut for PlayerRecords {
-    fn deref_mut(&mut self) -> &mut Self::Target {
-        &mut self.records
-    }
-}
-
 // PlayerRecord
 
 /// A Record of a player stored in the persistent personal playerlist
@@ -161,8 +583,27 @@ pub struct PlayerRecord {
     #[serde(default = "default_custom_data")]
     pub custom_data: serde_json::Value,
     pub verdict: Verdict,
+    /// The rule that gave the player their verdict, if it wasn't the user. Rules may only take
+    /// back verdicts they gave themselves.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub marked_by_rule: Option<Arc<str>>,
     #[serde(default)]
     pub previous_names: Vec<Arc<str>>,
+    /// Per-match summaries of suspicious behaviour flagged in demos
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub suspicion: Vec<EncounterSuspicion>,
+    /// Other accounts that may belong to the same person
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub linked_accounts: Vec<AccountLink>,
+    /// Chat messages sent by the player, oldest first
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub chat: Vec<ChatLine>,
+    /// Valve bans since the player was marked as a cheater, bot or suspicious
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ban_watch: Option<BanWatch>,
+    /// How the player voted on kicks seen in demos, oldest first
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub votes: Vec<VoteLine>,
 }
 
 impl PlayerRecord {
@@ -170,33 +611,83 @@ impl PlayerRecord {
         PlayerRecord {
             custom_data: serde_json::Value::Object(serde_json::Map::new()),
             verdict: Verdict::Player,
+            marked_by_rule: None,
             previous_names: Vec::new(),
+            suspicion: Vec::new(),
+            linked_accounts: Vec::new(),
+            chat: Vec::new(),
+            ban_watch: None,
+            votes: Vec::new(),
         }
     }
 
+    /// Whether the player has been marked as a cheater or bot
+    pub fn is_marked(&self) -> bool {
+        matches!(self.verdict, Verdict::Cheater | Verdict::Bot)
+    }
+
+    /// Whether the player is marked such that a later Valve ban would be worth telling the user
+    /// about, see [BanWatch]
+    pub fn watches_bans(&self) -> bool {
+        matches!(
+            self.verdict,
+            Verdict::Cheater | Verdict::Bot | Verdict::Suspicious
+        )
+    }
+
     /// Returns true if the record does not hold any meaningful information
     pub fn is_empty(&self) -> bool {
-        self.verdict == Verdict::Player && {
-            self.custom_data.is_null()
-                || self
-                    .custom_data
-                    .as_object()
-                    .map(|o| o.is_empty())
-                    .unwrap_or(false)
-                || self
-                    .custom_data
-                    .as_array()
-                    .map(|a| a.is_empty())
-                    .unwrap_or(false)
-                || self
-                    .custom_data
-                    .as_str()
-                    .map(|s| s.is_empty())
-                    .unwrap_or(false)
-        }
+        self.verdict == Verdict::Player
+            && self.suspicion.is_empty()
+            && self.linked_accounts.is_empty()
+            && self.chat.is_empty()
+            && self.votes.is_empty()
+            && {
+                self.custom_data.is_null()
+                    || self
+                        .custom_data
+                        .as_object()
+                        .map(|o| o.is_empty())
+                        .unwrap_or(false)
+                    || self
+                        .custom_data
+                        .as_array()
+                        .map(|a| a.is_empty())
+                        .unwrap_or(false)
+                    || self
+                        .custom_data
+                        .as_str()
+                        .map(|s| s.is_empty())
+                        .unwrap_or(false)
+            }
     }
 }
 
+/// A player's vote on a kick seen in a demo
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct VoteLine {
+    /// Unix timestamp (seconds) of the match the vote was in
+    pub time: u64,
+    pub demo: Arc<str>,
+    /// The player the vote was to kick
+    pub target: SteamID,
+    pub ballot: Ballot,
+}
+
+/// Valve bans on a marked account, to see whether the mark is later confirmed by a ban
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct BanWatch {
+    /// Unix timestamp (seconds) of the first check after the account was marked
+    pub since: u64,
+    /// Bans the account already had at that first check
+    pub vac_bans: i64,
+    pub game_bans: i64,
+    /// Unix timestamp (seconds) of the most recent check
+    pub last_checked: u64,
+    /// Unix timestamp (seconds) of the check that first found a new ban
+    pub banned_at: Option<u64>,
+}
+
 impl Default for PlayerRecord {
     fn default() -> Self {
         PlayerRecord::new()
@@ -208,7 +699,7 @@ pub fn default_custom_data() -> serde_json::Value {
 }
 
 /// What a player is marked as in the personal playerlist
-#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum Verdict {
     Player,
     Bot,
@@ -0,0 +1,165 @@
+//! The native export format for the records database, used to back it up or move it between
+//! installs. An archive is a single JSON object:
+//!
+//! ```json
+//! {
+//!   "format": "mac-records",
+//!   "version": 1,
+//!   "exported": 1700000000,
+//!   "client_version": "0.1.0",
+//!   "records": [
+//!     {
+//!       "steamid": "76561197960287930",
+//!       "verdict": "Cheater",
+//!       "previous_names": ["..."],
+//!       "custom_data": {},
+//!       "suspicion": [],
+//!       "linked_accounts": [],
+//!       "chat": []
+//!     }
+//!   ]
+//! }
+//! ```
+//!
+//! Each record holds exactly the fields of a [PlayerRecord], so an export can be imported again
+//! without losing anything: the verdict, the notes and tags the UI keeps in `custom_data`, demo
+//! suspicion from each encounter (which names the demo it came from), linked accounts, chat and
+//! ban tracking. Fields that are empty may be left out. `version` is bumped whenever a change
+//! would stop an older client reading the archive correctly, and archives from a newer version
+//! are refused rather than partially imported.
+
+use std::{collections::HashSet, sync::Arc};
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use super::{PlayerRecord, PlayerRecords};
+use crate::{analytics::history::now, steamid::SteamID};
+
+/// Identifies a file as a records archive
+pub const FORMAT: &str = "mac-records";
+/// The newest archive version this client can read, and the one it writes
+pub const VERSION: u32 = 1;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ArchivedRecord {
+    pub steamid: SteamID,
+    #[serde(flatten)]
+    pub record: PlayerRecord,
+}
+
+/// A complete export of the records database, see the [module documentation](self)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecordsArchive {
+    pub format: Arc<str>,
+    pub version: u32,
+    /// Unix timestamp (seconds) of when the archive was made
+    pub exported: u64,
+    /// Version of the client that made the archive
+    #[serde(default)]
+    pub client_version: Option<Arc<str>>,
+    pub records: Vec<ArchivedRecord>,
+}
+
+#[derive(Debug, Error)]
+pub enum ArchiveError {
+    #[error("Not a records archive")]
+    NotAnArchive,
+    #[error("Archive version {0} is newer than this client supports ({VERSION}), please update")]
+    TooNew(u32),
+}
+
+/// How an archive is combined with the records already in the database
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ImportMode {
+    /// Records in the archive replace existing records for the same player, every other
+    /// existing record is kept
+    #[default]
+    Merge,
+    /// The database is replaced by the archive
+    Replace,
+}
+
+/// What an import changed
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ImportSummary {
+    /// Records for players that weren't in the database
+    pub added: usize,
+    /// Records that replaced an existing record for the same player
+    pub replaced: usize,
+    /// Existing records removed because the import replaced the whole database
+    pub removed: usize,
+}
+
+impl RecordsArchive {
+    /// Check the archive is one this client can import
+    pub fn validate(&self) -> Result<(), ArchiveError> {
+        validate_header(&self.format, self.version)
+    }
+}
+
+/// Check an archive's `format` and `version` are ones this client can import, before reading
+/// the records in it
+pub fn validate_header(format: &str, version: u32) -> Result<(), ArchiveError> {
+    if format != FORMAT {
+        return Err(ArchiveError::NotAnArchive);
+    }
+    if version > VERSION {
+        return Err(ArchiveError::TooNew(version));
+    }
+    Ok(())
+}
+
+impl PlayerRecords {
+    /// Export every record, sorted by SteamID so the same database always gives the same archive
+    pub fn export(&self) -> RecordsArchive {
+        let mut records: Vec<ArchivedRecord> = self
+            .records
+            .iter()
+            .map(|(steamid, record)| ArchivedRecord {
+                steamid: *steamid,
+                record: record.clone(),
+            })
+            .collect();
+        records.sort_by_key(|r| u64::from(r.steamid));
+
+        RecordsArchive {
+            format: FORMAT.into(),
+            version: VERSION,
+            exported: now(),
+            client_version: Some(env!("CARGO_PKG_VERSION").into()),
+            records,
+        }
+    }
+
+    /// Import the records from an archive. Nothing is changed if the archive can't be imported.
+    /// The records are marked as needing to be saved.
+    pub fn import(
+        &mut self,
+        archive: RecordsArchive,
+        mode: ImportMode,
+    ) -> Result<ImportSummary, ArchiveError> {
+        archive.validate()?;
+
+        let existing: HashSet<SteamID> = self.records.keys().copied().collect();
+        let mut summary = ImportSummary::default();
+        if mode == ImportMode::Replace {
+            let imported: HashSet<SteamID> = archive.records.iter().map(|r| r.steamid).collect();
+            summary.removed = existing.difference(&imported).count();
+            self.clear();
+        }
+
+        for ArchivedRecord { steamid, record } in archive.records {
+            if existing.contains(&steamid) {
+                summary.replaced += 1;
+            } else {
+                summary.added += 1;
+            }
+            self.insert(steamid, record);
+        }
+
+        Ok(summary)
+    }
+}
@@ -0,0 +1,272 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use regex::{Regex, RegexBuilder};
+use tokio::sync::mpsc::UnboundedSender;
+
+use crate::{
+    lists::{source_name, watch_json_directory},
+    names,
+    settings::{ConfigFilesError, Settings},
+};
+
+use self::tf2bd::{Actions, RuleEntry, RuleList, TextMatch, TextMatchMode, TriggerMode};
+
+pub mod tf2bd;
+
+/// What is known about a player that rules can be checked against
+#[derive(Debug, Default, Clone, Copy)]
+pub struct RuleSubject<'a> {
+    pub name: Option<&'a str>,
+    pub chat: Option<&'a str>,
+    pub avatar_hash: Option<&'a str>,
+}
+
+/// A [TextMatch] with its patterns prepared for matching
+#[derive(Debug, Clone)]
+struct TextMatcher {
+    mode: TextMatchMode,
+    case_sensitive: bool,
+    patterns: Vec<Arc<str>>,
+    regexes: Vec<Regex>,
+}
+
+impl TextMatcher {
+    fn new(text_match: TextMatch) -> Result<TextMatcher, regex::Error> {
+        let regexes = if text_match.mode == TextMatchMode::Regex {
+            text_match
+                .patterns
+                .iter()
+                .map(|p| {
+                    RegexBuilder::new(p)
+                        .case_insensitive(!text_match.case_sensitive)
+                        .build()
+                })
+                .collect::<Result<_, _>>()?
+        } else {
+            Vec::new()
+        };
+
+        let patterns = if text_match.case_sensitive {
+            text_match.patterns
+        } else {
+            text_match
+                .patterns
+                .iter()
+                .map(|p| names::fold(p).into())
+                .collect()
+        };
+
+        Ok(TextMatcher {
+            mode: text_match.mode,
+            case_sensitive: text_match.case_sensitive,
+            patterns,
+            regexes,
+        })
+    }
+
+    fn matches(&self, text: &str) -> bool {
+        if self.mode == TextMatchMode::Regex {
+            return self.regexes.iter().any(|r| r.is_match(text));
+        }
+
+        // Matching that ignores case ignores invisible characters and lookalike letters too, which
+        // bots use to get around rules
+        let text = if self.case_sensitive {
+            text.to_string()
+        } else {
+            names::fold(text)
+        };
+
+        self.patterns.iter().any(|p| {
+            let p = p.as_ref();
+            match self.mode {
+                TextMatchMode::Equal => text == p,
+                TextMatchMode::Contains => text.contains(p),
+                TextMatchMode::StartsWith => text.starts_with(p),
+                TextMatchMode::EndsWith => text.ends_with(p),
+                TextMatchMode::Word => text.split(|c: char| !c.is_alphanumeric()).any(|w| w == p),
+                TextMatchMode::Regex => false,
+            }
+        })
+    }
+}
+
+/// A single rule, ready to be checked against players
+#[derive(Debug, Clone)]
+pub struct Rule {
+    /// File name the rule was loaded from
+    pub source: Arc<str>,
+    pub description: Option<Arc<str>>,
+    pub actions: Actions,
+    mode: TriggerMode,
+    name: Option<TextMatcher>,
+    chat: Option<TextMatcher>,
+    avatar_hashes: Option<Vec<Arc<str>>>,
+}
+
+impl Rule {
+    fn new(source: Arc<str>, entry: RuleEntry) -> Result<Rule, regex::Error> {
+        let triggers = entry.triggers;
+        Ok(Rule {
+            source,
+            description: entry.description,
+            actions: entry.actions,
+            mode: triggers.mode,
+            name: triggers
+                .username_text_match
+                .map(TextMatcher::new)
+                .transpose()?,
+            chat: triggers
+                .chatmsg_text_match
+                .map(TextMatcher::new)
+                .transpose()?,
+            avatar_hashes: triggers.avatar_match.map(|avatars| {
+                avatars
+                    .into_iter()
+                    .map(|a| a.avatar_hash.to_lowercase().into())
+                    .collect()
+            }),
+        })
+    }
+
+    /// Check the rule against a player. Triggers that need information about the player that
+    /// isn't known (e.g. a chat trigger when they haven't said anything) don't match.
+    pub fn matches(&self, subject: &RuleSubject) -> bool {
+        let mut results = Vec::with_capacity(3);
+        if let Some(name) = &self.name {
+            results.push(subject.name.is_some_and(|n| name.matches(n)));
+        }
+        if let Some(chat) = &self.chat {
+            results.push(subject.chat.is_some_and(|c| chat.matches(c)));
+        }
+        if let Some(hashes) = &self.avatar_hashes {
+            results.push(
+                subject
+                    .avatar_hash
+                    .is_some_and(|h| hashes.iter().any(|a| a.eq_ignore_ascii_case(h))),
+            );
+        }
+
+        match self.mode {
+            TriggerMode::MatchAll => !results.is_empty() && results.iter().all(|&r| r),
+            TriggerMode::MatchAny => results.iter().any(|&r| r),
+        }
+    }
+
+    /// How the rule should be referred to in logs and the UI
+    pub fn name(&self) -> Arc<str> {
+        match &self.description {
+            Some(description) => format!("{} ({})", description, self.source).into(),
+            None => self.source.clone(),
+        }
+    }
+}
+
+/// All of the rules currently loaded from the rules directory, keyed by their source
+#[derive(Debug, Default)]
+pub struct RuleSet {
+    rules: HashMap<Arc<str>, Vec<Rule>>,
+}
+
+impl RuleSet {
+    pub fn new() -> RuleSet {
+        RuleSet::default()
+    }
+
+    /// Apply an update from the rules directory watcher
+    pub fn handle_update(&mut self, update: RulesUpdate) {
+        match update {
+            RulesUpdate::Loaded(source, rules) => {
+                tracing::info!("Loaded {} rules from {}", rules.len(), source);
+                self.rules.insert(source, rules);
+            }
+            RulesUpdate::Removed(source) => {
+                if self.rules.remove(&source).is_some() {
+                    tracing::info!("Removed rules from {}", source);
+                }
+            }
+        }
+    }
+
+    /// Every rule that matches the player
+    pub fn check<'a>(&'a self, subject: &'a RuleSubject) -> impl Iterator<Item = &'a Rule> {
+        self.rules
+            .values()
+            .flatten()
+            .filter(move |rule| rule.matches(subject))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.values().all(|r| r.is_empty())
+    }
+
+    /// Attempts to find (and create) the directory that rules are loaded from
+    pub fn locate_rules_directory() -> Result<PathBuf, ConfigFilesError> {
+        let dir = Settings::locate_config_directory()?.join("rules");
+        std::fs::create_dir_all(&dir)
+            .map_err(|e| ConfigFilesError::IO(dir.to_string_lossy().into(), e))?;
+        Ok(dir)
+    }
+}
+
+/// Attempt to load the rules from a TF2BD rules file. Rules that can't be used (e.g. because of an
+/// invalid regex) are skipped.
+pub fn load_rules(path: &Path) -> Result<Vec<Rule>, ConfigFilesError> {
+    let contents = std::fs::read_to_string(path)
+        .map_err(|e| ConfigFilesError::IO(path.to_string_lossy().into(), e))?;
+    let list: RuleList = serde_json::from_str(&contents)
+        .map_err(|e| ConfigFilesError::Json(path.to_string_lossy().into(), e))?;
+
+    let source = source_name(path);
+    Ok(list
+        .rules
+        .into_iter()
+        .filter_map(|entry| match Rule::new(source.clone(), entry) {
+            Ok(rule) => Some(rule),
+            Err(e) => {
+                tracing::warn!("Skipping rule in {}: {}", source, e);
+                None
+            }
+        })
+        .collect())
+}
+
+/// Changes to the rules directory, sent from the watcher to the main loop
+#[derive(Debug)]
+pub enum RulesUpdate {
+    /// A rules file was added or modified
+    Loaded(Arc<str>, Vec<Rule>),
+    /// A rules file was deleted
+    Removed(Arc<str>),
+}
+
+/// (Re)load a single rules file, or report it as removed if it no longer exists
+fn refresh_rules(path: &Path, update_send: &UnboundedSender<RulesUpdate>) -> bool {
+    let update = if path.exists() {
+        match load_rules(path) {
+            Ok(rules) => RulesUpdate::Loaded(source_name(path), rules),
+            Err(e) => {
+                // Keep the previous version until the file can be read properly
+                tracing::warn!("Could not load rules: {}", e);
+                return true;
+            }
+        }
+    } else {
+        RulesUpdate::Removed(source_name(path))
+    };
+
+    update_send.send(update).is_ok()
+}
+
+/// Load every rules file in the rules directory, then watch it for rules being added, changed or
+/// removed. This blocks, so should be run on its own thread.
+pub fn rules_loop(
+    rules_dir: PathBuf,
+    update_send: UnboundedSender<RulesUpdate>,
+) -> anyhow::Result<()> {
+    watch_json_directory(&rules_dir, |path| refresh_rules(path, &update_send))
+}
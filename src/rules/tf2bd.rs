@@ -0,0 +1,77 @@
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+
+use crate::lists::tf2bd::{Attribute, FileInfo};
+
+/// A set of rules in the format used by TF2 Bot Detector
+/// (<https://github.com/PazerOP/tf2_bot_detector/blob/master/schemas/v3/rules.schema.json>)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RuleList {
+    #[serde(default)]
+    pub file_info: Option<FileInfo>,
+    pub rules: Vec<RuleEntry>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RuleEntry {
+    #[serde(default)]
+    pub description: Option<Arc<str>>,
+    pub triggers: Triggers,
+    #[serde(default)]
+    pub actions: Actions,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Triggers {
+    #[serde(default)]
+    pub mode: TriggerMode,
+    pub username_text_match: Option<TextMatch>,
+    pub chatmsg_text_match: Option<TextMatch>,
+    pub avatar_match: Option<Vec<AvatarMatch>>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TriggerMode {
+    #[default]
+    MatchAll,
+    MatchAny,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TextMatch {
+    pub mode: TextMatchMode,
+    #[serde(default)]
+    pub case_sensitive: bool,
+    pub patterns: Vec<Arc<str>>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TextMatchMode {
+    Equal,
+    Contains,
+    StartsWith,
+    EndsWith,
+    Regex,
+    Word,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AvatarMatch {
+    pub avatar_hash: Arc<str>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Actions {
+    /// Attributes to permanently mark the player with
+    #[serde(default)]
+    pub mark: Vec<Attribute>,
+    /// Attributes to mark the player with for this session only
+    #[serde(default)]
+    pub transient_mark: Vec<Attribute>,
+    /// Attributes to remove from the player
+    #[serde(default)]
+    pub unmark: Vec<Attribute>,
+}
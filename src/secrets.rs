@@ -0,0 +1,217 @@
+use std::{
+    collections::HashMap,
+    fmt::{Debug, Formatter},
+    sync::{Arc, Mutex, OnceLock},
+};
+
+use argon2::Argon2;
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use chacha20poly1305::{
+    aead::{rand_core::RngCore, Aead, AeadCore, KeyInit, OsRng},
+    ChaCha20Poly1305, Key, Nonce,
+};
+use serde::{de::Error, Deserialize, Deserializer, Serialize, Serializer};
+use thiserror::Error;
+
+/// Prefix of secrets that have been encrypted, followed by the salt, nonce and ciphertext
+const ENCRYPTED_PREFIX: &str = "enc:v1:";
+const SALT_LEN: usize = 16;
+/// Stands in for the hidden part of a secret, see [redact]
+const REDACTED: &str = "********";
+/// Characters at the end of a secret that are left showing when it is redacted
+const REDACTED_SHOWN: usize = 4;
+
+static PASSPHRASE: OnceLock<String> = OnceLock::new();
+/// Salt used for everything encrypted by this run of the program, so the key only has to be
+/// derived once.
+static SALT: OnceLock<[u8; SALT_LEN]> = OnceLock::new();
+/// Keys that have already been derived from the passphrase, by salt
+static KEYS: Mutex<Option<HashMap<[u8; SALT_LEN], Key>>> = Mutex::new(None);
+
+#[derive(Debug, Error)]
+pub enum SecretError {
+    #[error("No passphrase has been provided")]
+    NoPassphrase,
+    #[error("The secret is not in a recognised format")]
+    Malformed,
+    #[error("The secret could not be decrypted, the passphrase may be wrong")]
+    Decrypt,
+    #[error("Failed to derive a key from the passphrase: {0}")]
+    KeyDerivation(argon2::Error),
+}
+
+/// Enables encryption at rest of every [Secret], using a key derived from `passphrase`.
+/// Has to be called before any secrets are loaded, and can only be called once.
+pub fn set_passphrase(passphrase: String) {
+    if PASSPHRASE.set(passphrase).is_err() {
+        tracing::warn!("A passphrase for secrets has already been set.");
+    }
+}
+
+pub fn encryption_enabled() -> bool {
+    PASSPHRASE.get().is_some()
+}
+
+fn key_for_salt(salt: &[u8; SALT_LEN]) -> Result<Key, SecretError> {
+    let passphrase = PASSPHRASE.get().ok_or(SecretError::NoPassphrase)?;
+
+    let mut keys = KEYS.lock().unwrap();
+    let keys = keys.get_or_insert_with(HashMap::new);
+    if let Some(key) = keys.get(salt) {
+        return Ok(*key);
+    }
+
+    let mut key = Key::default();
+    Argon2::default()
+        .hash_password_into(passphrase.as_bytes(), salt, &mut key)
+        .map_err(SecretError::KeyDerivation)?;
+    keys.insert(*salt, key);
+    Ok(key)
+}
+
+fn encrypt(plaintext: &str) -> Result<String, SecretError> {
+    let salt = SALT.get_or_init(|| {
+        let mut salt = [0; SALT_LEN];
+        OsRng.fill_bytes(&mut salt);
+        salt
+    });
+    let cipher = ChaCha20Poly1305::new(&key_for_salt(salt)?);
+    let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+    let ciphertext = cipher
+        .encrypt(&nonce, plaintext.as_bytes())
+        .map_err(|_| SecretError::Malformed)?;
+
+    Ok(format!(
+        "{}{}:{}:{}",
+        ENCRYPTED_PREFIX,
+        BASE64.encode(salt),
+        BASE64.encode(nonce),
+        BASE64.encode(ciphertext)
+    ))
+}
+
+fn decrypt(encrypted: &str) -> Result<String, SecretError> {
+    let parts: Vec<Vec<u8>> = encrypted
+        .strip_prefix(ENCRYPTED_PREFIX)
+        .ok_or(SecretError::Malformed)?
+        .split(':')
+        .map(|part| BASE64.decode(part).map_err(|_| SecretError::Malformed))
+        .collect::<Result<_, _>>()?;
+    let [salt, nonce, ciphertext] = parts.as_slice() else {
+        return Err(SecretError::Malformed);
+    };
+    let salt: [u8; SALT_LEN] = salt
+        .as_slice()
+        .try_into()
+        .map_err(|_| SecretError::Malformed)?;
+    if nonce.len() != 12 {
+        return Err(SecretError::Malformed);
+    }
+
+    let cipher = ChaCha20Poly1305::new(&key_for_salt(&salt)?);
+    let plaintext = cipher
+        .decrypt(Nonce::from_slice(nonce), ciphertext.as_slice())
+        .map_err(|_| SecretError::Decrypt)?;
+    String::from_utf8(plaintext).map_err(|_| SecretError::Malformed)
+}
+
+/// A secret as it can be shown to the user, with only its last few characters left so it can be
+/// told apart from others. An empty secret stays empty, so it is still clear that none is set.
+pub fn redact(secret: &str) -> Arc<str> {
+    if secret.is_empty() {
+        return secret.into();
+    }
+    let shown = secret.len().saturating_sub(REDACTED_SHOWN);
+    let shown = secret
+        .char_indices()
+        .map(|(i, _)| i)
+        .find(|&i| i >= shown)
+        .unwrap_or(secret.len());
+    format!("{}{}", REDACTED, &secret[shown..]).into()
+}
+
+/// Whether `value` is a secret given back the way [redact] showed it, rather than a new one
+pub fn is_redacted(value: &str) -> bool {
+    value.starts_with(REDACTED)
+}
+
+/// A value that should be kept private, such as an API key or token.
+///
+/// When a passphrase has been set with [set_passphrase], secrets are encrypted whenever they are
+/// serialized. Secrets that were stored encrypted but can't be decrypted (e.g. the passphrase
+/// wasn't provided) are kept [Secret::Locked] and written back out unchanged, so they are
+/// never lost.
+#[derive(Clone, PartialEq, Eq)]
+pub enum Secret {
+    Plain(Arc<str>),
+    /// The encrypted form of a secret that couldn't be decrypted
+    Locked(Arc<str>),
+}
+
+impl Secret {
+    /// The secret itself, if it is available
+    pub fn expose(&self) -> Option<Arc<str>> {
+        match self {
+            Secret::Plain(secret) => Some(secret.clone()),
+            Secret::Locked(_) => None,
+        }
+    }
+
+    pub fn is_locked(&self) -> bool {
+        matches!(self, Secret::Locked(_))
+    }
+}
+
+impl From<Arc<str>> for Secret {
+    fn from(value: Arc<str>) -> Self {
+        Secret::Plain(value)
+    }
+}
+
+impl From<&str> for Secret {
+    fn from(value: &str) -> Self {
+        Secret::Plain(value.into())
+    }
+}
+
+impl Debug for Secret {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Secret::Plain(_) => f.write_str("Secret(<redacted>)"),
+            Secret::Locked(_) => f.write_str("Secret(<locked>)"),
+        }
+    }
+}
+
+impl Serialize for Secret {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Secret::Plain(secret) if encryption_enabled() => {
+                let encrypted = encrypt(secret).map_err(serde::ser::Error::custom)?;
+                serializer.serialize_str(&encrypted)
+            }
+            Secret::Plain(secret) => serializer.serialize_str(secret),
+            Secret::Locked(encrypted) => serializer.serialize_str(encrypted),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Secret {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let value = String::deserialize(deserializer).map_err(D::Error::custom)?;
+        if !value.starts_with(ENCRYPTED_PREFIX) {
+            return Ok(Secret::Plain(value.into()));
+        }
+
+        match decrypt(&value) {
+            Ok(secret) => Ok(Secret::Plain(secret.into())),
+            Err(e) => {
+                tracing::error!(
+                    "Could not decrypt a stored secret, it will not be usable until the correct passphrase is provided: {}",
+                    e
+                );
+                Ok(Secret::Locked(value.into()))
+            }
+        }
+    }
+}
@@ -1,17 +1,45 @@
 use serde::Serialize;
-use std::sync::Arc;
-use steamid_ng::SteamID;
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+};
 
 use crate::{
+    alts::{self, AccountLink, LinkStatus},
+    analytics::history::{now, SessionHistory},
+    chat_commands::{self, ChatCommand, PartyCommand, Target},
+    churn::{self, ChurnAlert, ChurnKind, ChurnMonitor, ChurnPlayer},
+    demo::{
+        clock::DEFAULT_TICK_RATE,
+        report::MatchReport,
+        votes::{Ballot, KickVote, KickVoteUpdate},
+    },
+    events::{self, MacEvent, SystemEventLevel},
+    evidence::EvidencePackage,
+    groups::{GroupMemberships, SteamGroup},
+    interlocks::{InterlockError, Interlocks},
     io::{
+        analyser::{self, ConsoleAlert, ConsoleAnalyserPlugin},
+        audit::CommandOrigin,
         g15,
         regexes::{self, ChatMessage, PlayerKill, StatusLine},
-        IOOutput,
+        Command, IOOutput, KickReason,
     },
-    player::{GameInfo, Players},
-    player_records::PlayerRecords,
+    locale::tr,
+    names,
+    player::{tags, GameInfo, KillLine, Players, ProfileVisibility, Team},
+    player_records::{PlayerRecords, Verdict},
+    rules::{RuleSet, RuleSubject, RulesUpdate},
+    steamid::SteamID,
+    suggestions::{SuggestedAction, Suggestion, SuggestionDecision, SuggestionError, Suggestions},
+    telemetry::Telemetry,
+    votes::{self, Signal, SnapshotChat, SnapshotPlayer, VoteFeedback, VoteOutcome, VoteSnapshot},
 };
 
+/// Ticks after a kick vote was called that it is still reacted to, by taking a snapshot of the
+/// server and alerting about anyone who kept a cheater
+const LIVE_VOTE_TICKS: u32 = (2.0 * 60.0 * DEFAULT_TICK_RATE) as u32;
+
 // Server
 
 #[derive(Serialize)]
@@ -23,7 +51,65 @@ pub struct Server {
     max_players: Option<u32>,
     num_players: Option<u32>,
     gamemode: Option<Gamemode>,
+    /// Which server the user is connected to, and since when
+    identity: Option<ServerIdentity>,
     players: Players,
+    /// Actions automation has suggested, waiting for the user to confirm them
+    suggestions: Suggestions,
+    /// Kick votes held since connecting to this server, most recent last, with how everyone
+    /// voted in them
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    kick_votes: Vec<KickVote>,
+    #[serde(skip)]
+    rules: RuleSet,
+    #[serde(skip)]
+    sessions: SessionHistory,
+    #[serde(skip)]
+    votes: VoteFeedback,
+    /// Rules that have matched each player, to know what marked them
+    #[serde(skip)]
+    matched_rules: HashMap<SteamID, HashSet<Arc<str>>>,
+    #[serde(skip)]
+    console_analysers: Vec<Box<dyn ConsoleAnalyserPlugin>>,
+    /// Console analysers that have flagged each player
+    #[serde(skip)]
+    detections: HashMap<SteamID, HashSet<Arc<str>>>,
+    #[serde(skip)]
+    telemetry: Telemetry,
+    #[serde(skip)]
+    interlocks: Interlocks,
+    /// Commands asked for through in-game chat, waiting to be sent
+    #[serde(skip)]
+    pending_commands: Vec<(Command, CommandOrigin)>,
+    /// Whether commands from party members are answered, see [chat_commands::PartyCommand]
+    #[serde(skip)]
+    party_commands: bool,
+    /// Unix timestamp (seconds) of the last reply to a party member
+    #[serde(skip)]
+    last_party_reply: Option<u64>,
+    /// Steam groups players are in, see [crate::groups]
+    #[serde(skip)]
+    groups: GroupMemberships,
+    /// How far the demo being recorded has got, if one is
+    #[serde(skip)]
+    demo_tick: Option<u32>,
+    /// Players left behind on the last server, whose pending lookups can be cancelled
+    #[serde(skip)]
+    departed: Vec<SteamID>,
+    /// Players joining and leaving, to notice when they do so much faster than usual
+    #[serde(skip)]
+    churn: ChurnMonitor,
+}
+
+/// Identifies one stay on a server. Map changes keep the same identity, connecting to a different
+/// server starts a new one.
+#[derive(Debug, Serialize, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct ServerIdentity {
+    /// `ip:port` of the server, once `status` has shown it
+    pub address: Option<Arc<str>>,
+    /// Unix timestamp (seconds) of when the user connected
+    pub connected_at: u64,
 }
 
 #[derive(Debug, Serialize, Clone)]
@@ -34,6 +120,50 @@ pub struct Gamemode {
     pub vanilla: bool,
 }
 
+impl Gamemode {
+    /// Work out what is being played on a server from its map and hostname. Community servers
+    /// can't be told apart from vanilla ones, so only Valve servers are considered vanilla.
+    pub fn from_server(map: &str, hostname: Option<&str>) -> Gamemode {
+        let matchmaking =
+            hostname.is_some_and(|hostname| hostname.starts_with("Valve Matchmaking Server"));
+        Gamemode {
+            matchmaking,
+            game_type: Self::game_type_from_map(map),
+            vanilla: matchmaking,
+        }
+    }
+
+    /// The type of game played on a map, based on the usual map name prefixes
+    pub fn game_type_from_map(map: &str) -> Arc<str> {
+        // Workshop maps are named like `workshop/pl_upward.ugc123456`
+        let map = map.rsplit('/').next().unwrap_or(map);
+        let prefix = map.split('_').next().unwrap_or_default();
+        match prefix.to_ascii_lowercase().as_str() {
+            "pl" => "Payload",
+            "plr" => "Payload Race",
+            "cp" => "Control Point",
+            "koth" => "King of the Hill",
+            "ctf" => "Capture the Flag",
+            "mvm" => "Mann vs. Machine",
+            "arena" => "Arena",
+            "pass" => "PASS Time",
+            "pd" => "Player Destruction",
+            "sd" => "Special Delivery",
+            "rd" => "Robot Destruction",
+            "tc" => "Territorial Control",
+            "tr" => "Training",
+            "vsh" => "Versus Saxton Hale",
+            "zi" => "Zombie Infection",
+            "mge" => "MGE",
+            "jump" => "Jump",
+            "surf" => "Surf",
+            "trade" => "Trade",
+            _ => "Unknown",
+        }
+        .into()
+    }
+}
+
 #[allow(dead_code)]
 impl Server {
     pub fn new(playerlist: PlayerRecords) -> Server {
@@ -44,8 +174,26 @@ impl Server {
             max_players: None,
             num_players: None,
             players: Players::new(playerlist),
+            suggestions: Suggestions::default(),
+            rules: RuleSet::new(),
+            sessions: SessionHistory::load_or_create(),
+            votes: VoteFeedback::load_or_create(),
+            matched_rules: HashMap::new(),
+            console_analysers: analyser::default_analysers(),
+            detections: HashMap::new(),
+            telemetry: Telemetry::load_or_create(),
+            interlocks: Interlocks::default(),
+            pending_commands: Vec::new(),
+            party_commands: false,
+            last_party_reply: None,
+            groups: GroupMemberships::default(),
+            demo_tick: None,
+            kick_votes: Vec::new(),
+            churn: ChurnMonitor::default(),
+            departed: Vec::new(),
 
             gamemode: None,
+            identity: None,
         }
     }
 
@@ -82,6 +230,30 @@ impl Server {
     pub fn gamemode(&self) -> Option<&Gamemode> {
         self.gamemode.as_ref()
     }
+
+    pub fn identity(&self) -> Option<&ServerIdentity> {
+        self.identity.as_ref()
+    }
+
+    pub fn sessions(&self) -> &SessionHistory {
+        &self.sessions
+    }
+
+    pub fn sessions_mut(&mut self) -> &mut SessionHistory {
+        &mut self.sessions
+    }
+
+    pub fn votes(&self) -> &VoteFeedback {
+        &self.votes
+    }
+
+    pub fn votes_mut(&mut self) -> &mut VoteFeedback {
+        &mut self.votes
+    }
+
+    pub fn rules(&self) -> &RuleSet {
+        &self.rules
+    }
 }
 
 impl Server {
@@ -91,6 +263,8 @@ impl Server {
     /// Returns:
     /// * Some<[SteamID]> of a player if they have been newly added to the server.
     pub fn handle_io_output(&mut self, response: IOOutput) -> Vec<SteamID> {
+        self.run_console_analysers(&response);
+
         use IOOutput::*;
         match response {
             G15(players) => return self.handle_g15_parse(players),
@@ -103,14 +277,25 @@ impl Server {
             Chat(chat) => self.handle_chat(chat),
             Kill(kill) => self.handle_kill(kill),
             Hostname(regexes::Hostname(hostname)) => {
-                self.hostname = Some(hostname);
+                self.sessions.observe_server(Some(hostname.clone()), None);
+                if self.hostname.as_ref() != Some(&hostname) {
+                    // Without an address, a different hostname is the best sign of a new server
+                    let unaddressed = self.identity.as_ref().is_none_or(|i| i.address.is_none());
+                    if unaddressed && (self.hostname.is_some() || self.identity.is_none()) {
+                        self.start_server_session(None);
+                    }
+                    self.hostname = Some(hostname);
+                    self.update_gamemode();
+                }
             }
             ServerIP(regexes::ServerIP(ip)) => {
+                // e.g. `1.2.3.4:27015  (public ip: 1.2.3.4)`
+                if let Some(address) = ip.split_whitespace().next() {
+                    self.observe_address(address.into());
+                }
                 self.ip = Some(ip);
             }
-            Map(regexes::Map(map)) => {
-                self.map = Some(map);
-            }
+            Map(regexes::Map(map)) => self.handle_map(map),
             PlayerCount(playercount) => {
                 self.max_players = Some(playercount.max);
                 self.num_players = Some(playercount.players);
@@ -120,8 +305,161 @@ impl Server {
         Vec::new()
     }
 
+    /// Show a line of console output to each console analyser, and act on anything they find
+    fn run_console_analysers(&mut self, event: &IOOutput) {
+        let mut alerts = Vec::new();
+        for analyser in &mut self.console_analysers {
+            let name = analyser.name();
+            alerts.extend(
+                analyser
+                    .on_event(event, &self.players)
+                    .into_iter()
+                    .map(|alert| (name, alert)),
+            );
+        }
+
+        for (name, alert) in alerts {
+            match alert {
+                ConsoleAlert::Flag { steamid, reason } => {
+                    tracing::info!("{} flagged {}: {}", name, steamid, reason);
+                    self.detections
+                        .entry(steamid)
+                        .or_default()
+                        .insert(name.into());
+                    self.players.set_tag(steamid, name.into());
+
+                    let player = self
+                        .players
+                        .game_info
+                        .get(&steamid)
+                        .map(|gi| gi.name.clone())
+                        .unwrap_or_else(|| steamid.to_string().into());
+                    events::emit_system(
+                        SystemEventLevel::Warning,
+                        tr(
+                            "player-flagged",
+                            &[
+                                ("player", &player),
+                                ("detector", &name),
+                                ("reason", &reason),
+                            ],
+                        ),
+                    );
+                }
+                ConsoleAlert::Notice(message) => {
+                    events::emit_system(SystemEventLevel::Info, message);
+                }
+            }
+        }
+    }
+
+    /// The server is at `address`. If that isn't the server the current session is on, a new one
+    /// is started.
+    fn observe_address(&mut self, address: Arc<str>) {
+        match &mut self.identity {
+            Some(identity) if identity.address.as_ref() == Some(&address) => return,
+            Some(identity) if identity.address.is_none() => {
+                identity.address = Some(address);
+                return;
+            }
+            _ => {}
+        }
+        self.start_server_session(Some(address));
+    }
+
+    /// The user has connected to a different server. Everything that only applied to the last
+    /// one is reset, while records and anything learnt about players are kept.
+    fn start_server_session(&mut self, address: Option<Arc<str>>) {
+        if self.identity.is_some() {
+            tracing::info!(
+                "Connected to a different server ({})",
+                address.as_deref().unwrap_or("unknown address")
+            );
+            let user = self.players.user;
+            self.departed
+                .extend(self.players.connected.iter().filter(|&&s| Some(s) != user));
+            self.players.disconnect_all();
+            self.detections.clear();
+            self.kick_votes.clear();
+            self.max_players = None;
+            self.num_players = None;
+        }
+        self.identity = Some(ServerIdentity {
+            address,
+            connected_at: now(),
+        });
+        self.churn.settle(now());
+    }
+
+    /// Run another console analyser over every line from now on
+    pub fn register_console_analyser(&mut self, analyser: Box<dyn ConsoleAnalyserPlugin>) {
+        self.console_analysers.push(analyser);
+    }
+
+    /// The server has been seen running `map`, either in the console or a demo header
+    pub fn handle_map(&mut self, map: Arc<str>) {
+        self.sessions.observe_server(None, Some(map.clone()));
+        if self.map.as_ref() == Some(&map) {
+            return;
+        }
+
+        tracing::debug!("Map changed to {}", map);
+        self.map = Some(map);
+        self.churn.settle(now());
+        self.update_gamemode();
+    }
+
+    /// A demo has started recording on `map`. The hostname and address it gives are used to
+    /// fill in anything the console hasn't told us about the server.
+    pub fn handle_demo_started(
+        &mut self,
+        map: Arc<str>,
+        hostname: Option<Arc<str>>,
+        address: Option<Arc<str>>,
+    ) {
+        self.sessions
+            .observe_server(hostname.clone(), Some(map.clone()));
+        if let Some(hostname) = hostname.filter(|h| self.hostname.as_ref() != Some(h)) {
+            tracing::debug!("Demo shows server is {}", hostname);
+            self.hostname = Some(hostname);
+            self.update_gamemode();
+        }
+        self.handle_map(map);
+
+        if let Some(address) = address {
+            self.sessions.observe_address(address.clone());
+            if self.ip.is_none() {
+                self.ip = Some(address);
+            }
+        }
+    }
+
+    /// Work out the gamemode from the current map and hostname, and note it on everyone
+    /// currently on the server
+    fn update_gamemode(&mut self) {
+        self.gamemode = self
+            .map
+            .as_deref()
+            .map(|map| Gamemode::from_server(map, self.hostname.as_deref()));
+
+        let game_type = self.game_type();
+        for steamid in &self.players.connected {
+            if let Some(game_info) = self.players.game_info.get_mut(steamid) {
+                game_info.map = self.map.clone();
+                game_info.gamemode = game_type.clone();
+            }
+        }
+    }
+
+    fn game_type(&self) -> Option<Arc<str>> {
+        self.gamemode.as_ref().map(|g| g.game_type.clone())
+    }
+
     fn handle_g15_parse(&mut self, players: Vec<g15::G15Player>) -> Vec<SteamID> {
         let mut new_players = Vec::new();
+        let mut renamed_players = Vec::new();
+        // Seen before, but still without a Steam profile, see [Server::handle_status_line]
+        let mut returned_players = Vec::new();
         for g15 in players {
             if g15.steamid.is_none() {
                 continue;
@@ -129,53 +467,80 @@ impl Server {
             let steamid = g15.steamid.unwrap();
 
             // Add to connected players if they aren't already
-            if !self.players.connected.contains(&steamid) {
+            let returned = !self.players.connected.contains(&steamid);
+            if returned {
                 self.players.connected.push(steamid);
+                self.record_join(steamid);
             }
 
             // Update game info
             if let Some(game_info) = self.players.game_info.get_mut(&steamid) {
+                if returned && !self.players.steam_info.contains_key(&steamid) {
+                    returned_players.push(steamid);
+                }
                 if let Some(name) = g15.name.as_ref() {
                     if *name != game_info.name {
                         self.players.records.update_name(&steamid, name.clone());
+                        renamed_players.push(steamid);
                     }
                 }
                 game_info.update_from_g15(g15);
-            } else if let Some(game_info) = GameInfo::new_from_g15(g15) {
+            } else if let Some(mut game_info) = GameInfo::new_from_g15(g15) {
                 // Update name
                 self.players
                     .records
                     .update_name(&steamid, game_info.name.clone());
 
+                game_info.map = self.map.clone();
+                game_info.gamemode = self.game_type();
                 self.players.game_info.insert(steamid, game_info);
                 new_players.push(steamid);
             }
         }
 
+        for steamid in &new_players {
+            self.players.lists.record_encounter(*steamid);
+        }
+        self.sessions.record_encounters(&new_players);
+        for steamid in new_players.iter().chain(renamed_players.iter()) {
+            self.apply_rules(*steamid, None);
+        }
+
+        new_players.extend(returned_players);
         new_players
     }
 
     /// Given a status line, update an existing or add a new one to the server.
-    /// Returns the SteamID if a new player was created.
+    /// Returns the SteamID if a new player was created, or one seen before has come back without
+    /// their Steam profile having been found (e.g. because their lookup was cancelled when the
+    /// user left the server they were last seen on).
     fn handle_status_line(&mut self, status: StatusLine) -> Option<SteamID> {
         let steamid = status.steamid;
 
         // Add to connected players if they aren't already
-        if !self.players.connected.contains(&steamid) {
+        let returned = !self.players.connected.contains(&steamid);
+        if returned {
             self.players.connected.push(steamid);
+            self.record_join(steamid);
         }
 
         if let Some(game_info) = self.players.game_info.get_mut(&steamid) {
-            if status.name != game_info.name {
+            let renamed = status.name != game_info.name;
+            if renamed {
                 self.players
                     .records
                     .update_name(&steamid, status.name.clone());
             }
 
             game_info.update_from_status(status);
-            None
+            if renamed {
+                self.apply_rules(steamid, None);
+            }
+            (returned && !self.players.steam_info.contains_key(&steamid)).then_some(steamid)
         } else {
-            let game_info = GameInfo::new_from_status(status);
+            let mut game_info = GameInfo::new_from_status(status);
+            game_info.map = self.map.clone();
+            game_info.gamemode = self.game_type();
 
             // Update name
             self.players
@@ -183,17 +548,986 @@ impl Server {
                 .update_name(&steamid, game_info.name.clone());
 
             self.players.game_info.insert(steamid, game_info);
+            self.players.lists.record_encounter(steamid);
+            self.sessions.record_encounters(&[steamid]);
+            self.apply_rules(steamid, None);
             Some(steamid)
         }
     }
 
     fn handle_chat(&mut self, chat: ChatMessage) {
-        // TODO
         tracing::debug!("Chat: {:?}", chat);
+        if chat.party {
+            self.run_party_command(&chat);
+            return;
+        }
+        match self.players.find_connected_by_name(&chat.player_name) {
+            Some(steamid) => {
+                if self.players.user == Some(steamid) {
+                    if let Some(command) = chat_commands::parse(&chat.message) {
+                        self.run_chat_command(command);
+                        return;
+                    }
+                }
+                self.apply_rules(steamid, Some(&chat.message));
+                self.players.record_chat(steamid, chat.message);
+            }
+            None => tracing::debug!("Could not find a player named {}", chat.player_name),
+        }
     }
 
     fn handle_kill(&mut self, kill: PlayerKill) {
-        // TODO
         tracing::debug!("Kill: {:?}", kill);
+        self.players.record_kill(&kill);
+    }
+
+    /// How far the demo being recorded has got, or [None] once it has finished
+    pub fn set_demo_tick(&mut self, tick: Option<u32>) {
+        self.demo_tick = tick;
+    }
+
+    /// Kills read from the demo being recorded, which are merged with those from the console
+    pub fn handle_demo_kills(&mut self, kills: Vec<KillLine>) {
+        for kill in kills {
+            tracing::debug!("Demo kill: {:?}", kill);
+            self.players.record_demo_kill(kill);
+        }
+    }
+
+    /// A kick vote in the demo being recorded was called, voted in or finished. The target is
+    /// filled in if the demo couldn't find them, and the vote is kept with the session once it has
+    /// finished. Votes caught up on from earlier in the demo (e.g. one that was already being
+    /// recorded when the client started) are kept too, but are too old to react to.
+    pub fn handle_kick_vote(&mut self, update: &mut KickVoteUpdate) {
+        let vote = &mut update.vote;
+        // The demo only knows the target by name, which it can miss if they have just joined
+        if vote.target.is_none() {
+            vote.target = self.players.find_connected_by_name(&vote.target_name);
+        }
+        let live = self
+            .demo_tick
+            .is_none_or(|tick| tick.saturating_sub(vote.tick) <= LIVE_VOTE_TICKS);
+
+        match self.kick_votes.iter_mut().find(|v| v.tick == vote.tick) {
+            Some(known) => *known = vote.clone(),
+            None => {
+                tracing::info!("Kick vote called against {}", vote.target_name);
+                self.kick_votes.push(vote.clone());
+                if let Some(target) = vote.target.filter(|_| live) {
+                    self.kick_vote_called(target);
+                }
+            }
+        }
+
+        if update.finished {
+            tracing::info!(
+                "Kick vote against {} ended: {:?}",
+                vote.target_name,
+                vote.passed
+            );
+            self.sessions.add_vote(vote.clone());
+            if live && vote.passed == Some(false) {
+                self.alert_kept_cheater(vote);
+            }
+        }
+    }
+
+    /// Let the user know who voted to keep a marked cheater on the server
+    fn alert_kept_cheater(&self, vote: &KickVote) {
+        let Some(target) = vote.target else {
+            return;
+        };
+        let cheater = self
+            .players
+            .records
+            .get(&target)
+            .is_some_and(|r| r.verdict == Verdict::Cheater);
+        if !cheater {
+            return;
+        }
+        let keepers: Vec<String> = vote
+            .ballots
+            .iter()
+            .filter(|(_, &ballot)| ballot == Ballot::No)
+            .map(|(steamid, _)| {
+                let name = self
+                    .players
+                    .records
+                    .get(steamid)
+                    .and_then(|r| r.previous_names.last().cloned())
+                    .unwrap_or_else(|| steamid.to_string().into());
+                format!("{} ({})", name, steamid)
+            })
+            .collect();
+        if keepers.is_empty() {
+            return;
+        }
+        let voters = keepers.join(", ");
+        tracing::info!(
+            "Vote to kick cheater {} ({}) failed, kept by {}",
+            vote.target_name,
+            target,
+            voters
+        );
+        events::emit_system(
+            SystemEventLevel::Warning,
+            tr(
+                "vote-kept-cheater",
+                &[
+                    ("player", &vote.target_name),
+                    ("steamid", &target),
+                    ("voters", &voters),
+                ],
+            ),
+        );
+    }
+}
+
+impl Server {
+    // **** Chat commands ****
+
+    /// Players left behind on other servers since this was last called
+    pub fn take_departed(&mut self) -> Vec<SteamID> {
+        std::mem::take(&mut self.departed)
+    }
+
+    /// Commands asked for through in-game chat since this was last called
+    pub fn take_commands(&mut self) -> Vec<(Command, CommandOrigin)> {
+        std::mem::take(&mut self.pending_commands)
+    }
+
+    /// Carry out a command the user typed into their own in-game chat, letting them know how it
+    /// went
+    fn run_chat_command(&mut self, command: Result<ChatCommand, String>) {
+        let result = command.and_then(|command| match command {
+            ChatCommand::Mark { target, verdict } => {
+                let (steamid, player) = self.find_target(&target)?;
+                self.players.set_verdict(steamid, verdict);
+                self.players.records.save_ok();
+                Ok(tr(
+                    "chat-command-marked",
+                    &[("player", &player), ("verdict", &verdict)],
+                ))
+            }
+            // Called by the client rather than by the user themselves, so it is held to the
+            // same limits as the rest of automation
+            ChatCommand::Kick { target, reason } => {
+                let (steamid, player) = self.find_target(&target)?;
+                let kick = self.automated_votekick(steamid, reason).map_err(|e| {
+                    tr(
+                        "chat-command-kick-blocked",
+                        &[("player", &player), ("reason", &e.to_string())],
+                    )
+                })?;
+                self.pending_commands
+                    .push((kick, CommandOrigin::Automation));
+                Ok(tr("chat-command-kick", &[("player", &player)]))
+            }
+        });
+
+        match result {
+            Ok(message) => {
+                tracing::info!("Chat command: {}", message);
+                events::emit_system(SystemEventLevel::Info, message);
+            }
+            Err(message) => {
+                tracing::warn!("Chat command failed: {}", message);
+                events::emit_system(SystemEventLevel::Warning, message);
+            }
+        }
+    }
+
+    /// Answer a command a party member typed into party chat, as long as party commands are turned
+    /// on and the last reply wasn't too recent. Anyone in party chat is in the user's party, but
+    /// they don't have to be on the same server.
+    fn run_party_command(&mut self, chat: &ChatMessage) {
+        if !self.party_commands {
+            return;
+        }
+        let Some(command) = chat_commands::parse_party(&chat.message) else {
+            return;
+        };
+        let now = now();
+        if self
+            .last_party_reply
+            .is_some_and(|last| now.saturating_sub(last) < chat_commands::PARTY_REPLY_COOLDOWN)
+        {
+            tracing::debug!(
+                "Not answering party command from {}, replied too recently",
+                chat.player_name
+            );
+            return;
+        }
+
+        let reply = command
+            .and_then(|command| match command {
+                PartyCommand::Who(target) => {
+                    let (steamid, player) = self.find_target(&target)?;
+                    Ok(tr(
+                        "party-command-who",
+                        &[
+                            ("player", &player),
+                            ("summary", &self.record_summary(steamid)),
+                        ],
+                    ))
+                }
+            })
+            .unwrap_or_else(|message| message);
+        tracing::info!(
+            "Answering party command from {}: {}",
+            chat.player_name,
+            reply
+        );
+
+        self.last_party_reply = Some(now);
+        self.pending_commands.push((
+            Command::PartyChat(chat_commands::party_reply(&reply)),
+            CommandOrigin::User,
+        ));
+    }
+
+    /// Find the connected player a chat command is about, along with their name
+    fn find_target(&self, target: &Target) -> Result<(SteamID, Arc<str>), String> {
+        let others = self
+            .players
+            .connected
+            .iter()
+            .filter(|&&s| Some(s) != self.players.user)
+            .filter_map(|s| Some((*s, self.players.game_info.get(s)?)));
+
+        let found = match target {
+            Target::Last => others.min_by_key(|(_, gi)| gi.time).map(|(s, _)| s),
+            Target::Killer => self
+                .players
+                .kills
+                .iter()
+                .rev()
+                .find(|kill| kill.victim.is_some() && kill.victim == self.players.user)
+                .and_then(|kill| kill.killer)
+                .filter(|killer| self.players.connected.contains(killer)),
+            Target::Name(name) => {
+                let name = names::fold(name);
+                let matches: Vec<(SteamID, &GameInfo)> = others
+                    .filter(|(_, gi)| names::fold(&gi.name).contains(&name))
+                    .collect();
+                matches
+                    .iter()
+                    .find(|(_, gi)| names::fold(&gi.name) == name)
+                    .or(matches.first().filter(|_| matches.len() == 1))
+                    .map(|(s, _)| *s)
+            }
+        };
+
+        found
+            .and_then(|steamid| Some((steamid, self.players.game_info.get(&steamid)?.name.clone())))
+            .ok_or_else(|| tr("chat-command-no-target", &[]))
+    }
+}
+
+impl Server {
+    // **** Rules ****
+
+    /// Apply a change to the loaded rules, then re-check everyone on the server against them
+    pub fn handle_rules_update(&mut self, update: RulesUpdate) {
+        self.rules.handle_update(update);
+        for steamid in self.players.connected.clone() {
+            self.apply_rules(steamid, None);
+        }
+    }
+
+    /// Check a player against the loaded rules and carry out the actions of any that match.
+    /// `chat` is a message they have just sent, if any.
+    pub fn apply_rules(&mut self, steamid: SteamID, chat: Option<&str>) {
+        if self.rules.is_empty() {
+            return;
+        }
+
+        let name = self
+            .players
+            .game_info
+            .get(&steamid)
+            .map(|gi| gi.name.clone());
+        let avatar_hash = self
+            .players
+            .steam_info
+            .get(&steamid)
+            .map(|si| si.pfp_hash.clone());
+        let subject = RuleSubject {
+            name: name.as_deref(),
+            chat,
+            avatar_hash: avatar_hash.as_deref(),
+        };
+
+        let matched: Vec<_> = self
+            .rules
+            .check(&subject)
+            .map(|rule| (rule.name(), rule.actions.clone()))
+            .collect();
+
+        let mut records_changed = false;
+        for (rule, actions) in matched {
+            tracing::info!("{} matched rule {}", steamid, rule);
+            self.matched_rules
+                .entry(steamid)
+                .or_default()
+                .insert(rule.clone());
+
+            // Marks never override a verdict the user has already given
+            for verdict in actions.mark.iter().filter_map(|a| a.verdict()) {
+                let record = self.players.records.entry(steamid).or_default();
+                if record.verdict == Verdict::Player {
+                    record.verdict = verdict;
+                    record.marked_by_rule = Some(rule.clone());
+                    records_changed = true;
+                }
+            }
+
+            for attribute in &actions.transient_mark {
+                self.players
+                    .set_tag(steamid, format!("{:?}", attribute).into());
+            }
+
+            // Likewise, only verdicts given by rules are taken back
+            for verdict in actions.unmark.iter().filter_map(|a| a.verdict()) {
+                if let Some(record) = self.players.records.get_mut(&steamid) {
+                    if record.verdict == verdict && record.marked_by_rule.is_some() {
+                        record.verdict = Verdict::Player;
+                        record.marked_by_rule = None;
+                        record.ban_watch = None;
+                        records_changed = true;
+                    }
+                    if record.is_empty() {
+                        self.players.records.remove(&steamid);
+                    }
+                }
+            }
+        }
+
+        if records_changed {
+            self.players.records.save_ok();
+        }
+    }
+}
+
+impl Server {
+    // **** Votes ****
+
+    /// Everything that has marked a player: rules they've matched, lists they're on, demo
+    /// heuristics they've been flagged by and whether their Steam profile looks like a bot's.
+    pub fn signals(&self, steamid: SteamID) -> Vec<Signal> {
+        let mut signals: Vec<Signal> = self
+            .matched_rules
+            .get(&steamid)
+            .into_iter()
+            .flatten()
+            .map(|rule| Signal::Rule(rule.clone()))
+            .collect();
+        signals.extend(
+            self.detections
+                .get(&steamid)
+                .into_iter()
+                .flatten()
+                .map(|name| Signal::Detector(name.clone())),
+        );
+        signals.extend(
+            self.players
+                .lists
+                .matches(&steamid)
+                .into_iter()
+                .map(|m| Signal::List(m.source.into())),
+        );
+
+        let mut kinds: Vec<_> = self
+            .players
+            .records
+            .get(&steamid)
+            .into_iter()
+            .flat_map(|r| r.suspicion.iter())
+            .flat_map(|encounter| encounter.suspicion.flags.keys().copied())
+            .collect();
+        kinds.sort_by_key(|kind| format!("{:?}", kind));
+        kinds.dedup();
+        signals.extend(kinds.into_iter().map(Signal::Heuristic));
+
+        if self
+            .players
+            .steam_info
+            .get(&steamid)
+            .is_some_and(|info| info.looks_like_bot())
+        {
+            signals.push(Signal::BotProfile);
+        }
+
+        signals
+    }
+
+    /// A votekick has been called against the player with `userid`
+    pub fn vote_called(&mut self, userid: &str) {
+        let Some(steamid) = self.players.find_connected_by_userid(userid) else {
+            tracing::debug!("Votekick called against unknown user {}", userid);
+            return;
+        };
+        self.kick_vote_called(steamid);
+    }
+
+    /// A votekick has been called against `steamid`, by the user or anyone else on the server.
+    /// What the server looks like is kept with the vote, taken again if the vote was already known.
+    fn kick_vote_called(&mut self, steamid: SteamID) {
+        let signals = self.signals(steamid);
+        let snapshot = self.vote_snapshot();
+        self.votes.vote_called(steamid, signals, snapshot);
+    }
+
+    /// The players, recent chat and kills, to keep with a votekick that is being called
+    fn vote_snapshot(&self) -> VoteSnapshot {
+        let since = now().saturating_sub(votes::SNAPSHOT_WINDOW);
+        let name = |steamid: &SteamID| {
+            self.players
+                .game_info
+                .get(steamid)
+                .map(|gi| gi.name.clone())
+                .unwrap_or_default()
+        };
+
+        let players = self
+            .players
+            .connected
+            .iter()
+            .map(|steamid| SnapshotPlayer {
+                steamid: *steamid,
+                name: name(steamid),
+                team: self
+                    .players
+                    .game_info
+                    .get(steamid)
+                    .map_or(Team::Unassigned, |gi| gi.team),
+                verdict: self
+                    .players
+                    .records
+                    .get(steamid)
+                    .map_or(Verdict::Player, |r| r.verdict),
+                signals: self.signals(*steamid),
+            })
+            .collect();
+
+        let mut chat: Vec<SnapshotChat> = self
+            .players
+            .chat
+            .iter()
+            .flat_map(|(steamid, lines)| {
+                lines
+                    .iter()
+                    .filter(|line| line.time >= since)
+                    .map(|line| SnapshotChat {
+                        time: line.time,
+                        steamid: *steamid,
+                        name: name(steamid),
+                        message: line.message.clone(),
+                    })
+            })
+            .collect();
+        chat.sort_by_key(|line| line.time);
+
+        VoteSnapshot {
+            map: self.map.clone(),
+            demo_tick: self.demo_tick,
+            players,
+            chat,
+            kills: self
+                .players
+                .kills
+                .iter()
+                .filter(|kill| kill.time >= since)
+                .cloned()
+                .collect(),
+        }
+    }
+
+    /// Check whether any votekicks have passed or failed, and adjust the weight of the signals
+    /// that led to them
+    pub fn resolve_votes(&mut self) {
+        let outcomes = self.votes.resolve_votes(&self.players.connected);
+        for outcome in &outcomes {
+            self.telemetry
+                .votekick_resolved(*outcome == VoteOutcome::Passed);
+        }
+        if !outcomes.is_empty() {
+            self.telemetry.save_ok();
+        }
+    }
+
+    pub fn interlocks(&self) -> &Interlocks {
+        &self.interlocks
+    }
+
+    pub fn interlocks_mut(&mut self) -> &mut Interlocks {
+        &mut self.interlocks
+    }
+
+    /// Answer (or stop answering) commands typed into party chat by the user's party
+    pub fn set_party_commands(&mut self, enabled: bool) {
+        self.party_commands = enabled;
+    }
+
+    pub fn groups(&self) -> &GroupMemberships {
+        &self.groups
+    }
+
+    pub fn groups_mut(&mut self) -> &mut GroupMemberships {
+        &mut self.groups
+    }
+
+    /// Whether the Steam groups of a player should be looked up, which is only worth doing once
+    /// for players on the server with a public profile
+    pub fn wants_groups(&self, steamid: SteamID) -> bool {
+        self.players.connected.contains(&steamid)
+            && !self.groups.is_known(steamid)
+            && self
+                .players
+                .steam_info
+                .get(&steamid)
+                .is_some_and(|si| si.profile_visibility == ProfileVisibility::Public)
+    }
+
+    /// Store the Steam groups a player is in, tagging them and letting the user know if any of
+    /// them are flagged or shared with marked players
+    pub fn handle_groups(&mut self, steamid: SteamID, groups: Vec<SteamGroup>) {
+        self.groups.insert(steamid, groups);
+        let matches = self.groups.matches(steamid, &self.players.records);
+        if matches.is_empty() {
+            return;
+        }
+
+        self.players.set_tag(steamid, tags::GROUP.into());
+        let player = self
+            .players
+            .game_info
+            .get(&steamid)
+            .map(|gi| gi.name.clone())
+            .unwrap_or_else(|| steamid.to_string().into());
+        let groups: Vec<&str> = matches
+            .iter()
+            .map(|m| {
+                m.flagged_as
+                    .as_deref()
+                    .or(m.group.name.as_deref())
+                    .unwrap_or(&m.group.id)
+            })
+            .collect();
+        tracing::info!("{} ({}) is in groups {:?}", player, steamid, groups);
+        events::emit_system(
+            SystemEventLevel::Warning,
+            tr(
+                "player-in-flagged-group",
+                &[("player", &player), ("groups", &groups.join(", "))],
+            ),
+        );
+    }
+
+    /// Call a votekick against `steamid` on behalf of automation, if the [Interlocks] allow it.
+    /// Returns the command to send, the vote is already counted as called.
+    ///
+    /// Votekicks suggested by automation go through here once the user confirms them, as the
+    /// user is only agreeing with what automation came up with.
+    pub fn automated_votekick(
+        &mut self,
+        steamid: SteamID,
+        reason: KickReason,
+    ) -> Result<Command, InterlockError> {
+        let userid = self
+            .players
+            .game_info
+            .get(&steamid)
+            .filter(|_| self.players.connected.contains(&steamid))
+            .map(|info| info.userid.clone())
+            .ok_or(InterlockError::UnknownPlayer)?;
+        let joined = self.identity.as_ref().map(|identity| identity.connected_at);
+        self.interlocks
+            .check_votekick(joined, self.votes.score(&self.signals(steamid)))?;
+
+        self.interlocks.votekick_called();
+        self.vote_called(&userid);
+        Ok(Command::Kick {
+            player: userid,
+            reason,
+        })
+    }
+}
+
+/// What a confirmed [Suggestion] calls for
+pub enum Confirmed {
+    /// The player has been marked
+    Marked,
+    /// The votekick to send, already counted as called
+    Votekick(Command),
+    /// The evidence to attach to the report
+    Report(Box<EvidencePackage>),
+}
+
+impl Server {
+    // **** Suggestions ****
+
+    pub fn suggestions(&self) -> &Suggestions {
+        &self.suggestions
+    }
+
+    /// Suggest what to do about the connected players that anything has marked, for the user to
+    /// confirm. Suggestions are made as soon as there is any reason to, it is up to the user
+    /// whether to act on them.
+    pub fn suggest_actions(&mut self) {
+        // A votekick can't be called once the player has left
+        let connected = &self.players.connected;
+        self.suggestions
+            .expire(|s| s.action != SuggestedAction::Votekick || connected.contains(&s.steamid));
+
+        let min_score = self.interlocks.limits().min_signals as f32;
+        let others: Vec<SteamID> = self
+            .players
+            .connected
+            .iter()
+            .filter(|&&s| Some(s) != self.players.user)
+            .copied()
+            .collect();
+        for steamid in others {
+            let signals = self.signals(steamid);
+            if signals.is_empty() {
+                continue;
+            }
+
+            let verdict = self
+                .players
+                .records
+                .get(&steamid)
+                .map(|r| r.verdict)
+                .unwrap_or(Verdict::Player);
+            let score = self.votes.score(&signals);
+            let actions = SuggestedAction::for_player(verdict, &signals, score, min_score);
+
+            let name = self
+                .players
+                .game_info
+                .get(&steamid)
+                .map(|gi| gi.name.clone());
+            for action in actions {
+                let suggestion =
+                    self.suggestions
+                        .suggest(steamid, name.clone(), action, signals.clone());
+                if let Some(suggestion) = suggestion {
+                    let message = suggestion.describe();
+                    tracing::info!("Suggested: {}", message);
+                    events::emit_system(SystemEventLevel::Info, message);
+                }
+            }
+        }
+    }
+
+    /// Settle a suggestion as the user decided. Returns what the suggestion calls for if it was
+    /// confirmed, which is left to the caller to carry out.
+    pub fn decide_suggestion(
+        &mut self,
+        id: u64,
+        decision: SuggestionDecision,
+    ) -> Result<Option<Confirmed>, SuggestionError> {
+        let Suggestion {
+            steamid, action, ..
+        } = self.suggestions.take(id)?;
+        if decision == SuggestionDecision::Dismiss {
+            return Ok(None);
+        }
+
+        let confirmed = match action {
+            SuggestedAction::Mark { verdict } => {
+                self.players.set_verdict(steamid, verdict);
+                Confirmed::Marked
+            }
+            SuggestedAction::Votekick => {
+                Confirmed::Votekick(self.automated_votekick(steamid, KickReason::Cheating)?)
+            }
+            SuggestedAction::Report => EvidencePackage::build(self, steamid)
+                .map(|package| Confirmed::Report(Box::new(package)))
+                .ok_or(SuggestionError::PlayerLeft)?,
+        };
+        Ok(Some(confirmed))
+    }
+}
+
+impl Server {
+    // **** Lobby churn ****
+
+    fn record_join(&mut self, steamid: SteamID) {
+        if self.players.user != Some(steamid) {
+            self.churn.record_joins(now(), &[steamid]);
+        }
+    }
+
+    /// Move anyone who has left the server into history, and alert about players joining or
+    /// leaving much faster than usual, see [crate::churn]
+    pub fn refresh_players(&mut self) {
+        let left = self.players.refresh();
+        // Everyone drops out of the list when it's the user who has left
+        let user_left = self.players.user.is_some_and(|user| left.contains(&user));
+        if user_left || self.players.connected.is_empty() {
+            self.churn.settle(now());
+            return;
+        }
+        self.churn.record_leaves(now(), &left);
+
+        for spike in self.churn.check(now()) {
+            let players: Vec<ChurnPlayer> = spike
+                .players
+                .iter()
+                .map(|&steamid| ChurnPlayer {
+                    steamid,
+                    name: self
+                        .players
+                        .game_info
+                        .get(&steamid)
+                        .map(|gi| gi.name.clone())
+                        .unwrap_or_else(|| steamid.to_string().into()),
+                    verdict: self
+                        .players
+                        .records
+                        .get(&steamid)
+                        .map_or(Verdict::Player, |r| r.verdict),
+                })
+                .collect();
+            let names = players
+                .iter()
+                .map(|p| p.name.as_ref())
+                .collect::<Vec<_>>()
+                .join(", ");
+            let (key, verb) = match spike.kind {
+                ChurnKind::Joins => ("lobby-join-spike", "joined"),
+                ChurnKind::Leaves => ("lobby-leave-spike", "left"),
+            };
+            tracing::info!(
+                "{} players {} within {}s, about {:.1} expected: {}",
+                players.len(),
+                verb,
+                churn::WINDOW,
+                spike.expected,
+                names
+            );
+            events::emit_system(
+                SystemEventLevel::Warning,
+                tr(
+                    key,
+                    &[
+                        ("count", &players.len()),
+                        ("seconds", &churn::WINDOW),
+                        ("players", &names),
+                    ],
+                ),
+            );
+            events::emit(MacEvent::LobbyChurn(ChurnAlert {
+                kind: spike.kind,
+                window: churn::WINDOW,
+                expected: spike.expected,
+                players,
+            }));
+        }
+    }
+}
+
+impl Server {
+    // **** Telemetry ****
+
+    pub fn telemetry(&self) -> &Telemetry {
+        &self.telemetry
+    }
+
+    pub fn telemetry_mut(&mut self) -> &mut Telemetry {
+        &mut self.telemetry
+    }
+
+    /// Count any connected bots towards the anonymous statistics
+    pub fn count_bots(&mut self) {
+        let bots: Vec<SteamID> = self
+            .players
+            .connected
+            .iter()
+            .filter(|steamid| {
+                self.players
+                    .records
+                    .get(steamid)
+                    .is_some_and(|r| r.verdict == Verdict::Bot)
+                    || self
+                        .players
+                        .lists
+                        .matches(steamid)
+                        .iter()
+                        .any(|m| m.verdict == Verdict::Bot)
+            })
+            .copied()
+            .collect();
+        let mut counted = false;
+        for bot in bots {
+            counted |= self.telemetry.bot_seen(bot);
+        }
+        if counted {
+            self.telemetry.save_ok();
+        }
+    }
+}
+
+impl Server {
+    // **** Bans ****
+
+    /// The player currently has `vac_bans` and `game_bans`. If they have been marked, this lets
+    /// the user know when Valve bans them. Their profile, and so its risk, is kept up to date too.
+    pub fn handle_bans(&mut self, steamid: SteamID, vac_bans: i64, game_bans: i64) {
+        if let Some(steam_info) = self.players.steam_info.get_mut(&steamid) {
+            if steam_info.vac_bans != vac_bans || steam_info.game_bans != game_bans {
+                if vac_bans > steam_info.vac_bans || game_bans > steam_info.game_bans {
+                    steam_info.days_since_last_ban = Some(0);
+                }
+                steam_info.vac_bans = vac_bans;
+                steam_info.game_bans = game_bans;
+                steam_info.assess_risk();
+            }
+        }
+
+        if !self
+            .players
+            .records
+            .update_bans(steamid, vac_bans, game_bans)
+        {
+            return;
+        }
+
+        let Some(record) = self.players.records.get(&steamid) else {
+            return;
+        };
+        let player = record
+            .previous_names
+            .last()
+            .cloned()
+            .unwrap_or_else(|| steamid.to_string().into());
+        tracing::info!("Marked player {} ({}) has been banned", player, steamid);
+        events::emit_system(
+            SystemEventLevel::Info,
+            tr(
+                "marked-player-banned",
+                &[
+                    ("player", &player),
+                    ("steamid", &steamid),
+                    ("verdict", &record.verdict),
+                ],
+            ),
+        );
+    }
+
+    /// Store how everyone voted on the kick votes of a finished match, letting the user know
+    /// about any players who keep voting to protect bots
+    pub fn handle_match_votes(&mut self, report: &MatchReport) {
+        for steamid in self.players.records.add_match_votes(report) {
+            let player = self
+                .players
+                .records
+                .get(&steamid)
+                .and_then(|r| r.previous_names.last().cloned())
+                .unwrap_or_else(|| steamid.to_string().into());
+            let (kept, total) = self.players.records.bot_votes(steamid);
+            tracing::info!("{} ({}) has been voting to keep bots", player, steamid);
+            events::emit_system(
+                SystemEventLevel::Warning,
+                tr(
+                    "vote-protects-bots",
+                    &[
+                        ("player", &player),
+                        ("steamid", &steamid),
+                        ("kept", &kept),
+                        ("total", &total),
+                    ],
+                ),
+            );
+        }
+    }
+
+    /// A short description of everything known about a player, for alerts
+    fn record_summary(&self, steamid: SteamID) -> String {
+        let mut summary = Vec::new();
+        if let Some(record) = self.players.records.get(&steamid) {
+            summary.push(tr("summary-verdict", &[("verdict", &record.verdict)]));
+            if !record.previous_names.is_empty() {
+                summary.push(tr(
+                    "summary-names",
+                    &[("names", &record.previous_names.join(", "))],
+                ));
+            }
+        }
+        let signals = self.signals(steamid);
+        if !signals.is_empty() {
+            let signals: Vec<String> = signals.iter().map(ToString::to_string).collect();
+            summary.push(tr("summary-signals", &[("signals", &signals.join(", "))]));
+        }
+
+        if summary.is_empty() {
+            tr("summary-no-record", &[])
+        } else {
+            summary.join(" ")
+        }
+    }
+}
+
+impl Server {
+    // **** Alt accounts ****
+
+    /// Look for other accounts that may belong to the same person as `steamid`, and suggest a link
+    /// on both of their records. Links the user has already confirmed or dismissed are left alone.
+    pub fn check_alt_links(&mut self, steamid: SteamID) {
+        let links = alts::find_links(self, steamid);
+        if links.is_empty() {
+            return;
+        }
+
+        let mut changed = false;
+        for (other, reasons) in links {
+            for (a, b) in [(steamid, other), (other, steamid)] {
+                let record = self.players.records.entry(a).or_default();
+                match record.linked_accounts.iter_mut().find(|l| l.steamid == b) {
+                    Some(link) if link.status == LinkStatus::Suggested => {
+                        if link.reasons != reasons {
+                            link.reasons = reasons.clone();
+                            changed = true;
+                        }
+                    }
+                    Some(_) => {}
+                    None => {
+                        record
+                            .linked_accounts
+                            .push(AccountLink::new(b, reasons.clone()));
+                        changed = true;
+                    }
+                }
+            }
+            tracing::info!("{} may be an alt of {} ({:?})", steamid, other, reasons);
+        }
+
+        if changed {
+            self.players.records.save_ok();
+        }
+    }
+
+    /// Confirm or dismiss the link between two accounts, on both of their records.
+    /// Returns false if there is no such link.
+    pub fn set_link_status(&mut self, a: SteamID, b: SteamID, status: LinkStatus) -> bool {
+        let mut found = false;
+        for (a, b) in [(a, b), (b, a)] {
+            if let Some(link) = self
+                .players
+                .records
+                .get_mut(&a)
+                .and_then(|r| r.linked_accounts.iter_mut().find(|l| l.steamid == b))
+            {
+                link.status = status;
+                found = true;
+            }
+        }
+
+        if found {
+            self.players.records.save_ok();
+        }
+        found
     }
 }
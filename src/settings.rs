@@ -1,8 +1,8 @@
 use std::{
-    fs::OpenOptions,
-    io::{self, ErrorKind, Write},
+    io::{self, ErrorKind},
     path::{Path, PathBuf},
     sync::Arc,
+    time::Duration,
 };
 
 use anyhow::{anyhow, Context, Result};
@@ -10,11 +10,30 @@ use directories_next::ProjectDirs;
 use keyvalues_parser::Vdf;
 use serde::{Deserialize, Serialize};
 use serde_json::{Map, Value};
-use steamid_ng::SteamID;
 use thiserror::Error;
 
 use crate::args::Args;
+use crate::demo::storage::DemoStorage;
+use crate::events::{self, SystemEventLevel};
 use crate::gamefinder;
+use crate::gameprofile::{GameProfile, DEFAULT_PROFILE};
+use crate::groups::FlaggedGroup;
+use crate::interlocks::{self, AutomationLimits};
+use crate::io::audit::{CommandAllowlist, DEFAULT_ALLOWLIST};
+use crate::io::noise::{self, NoiseFilter};
+use crate::locale::{self, tr, DEFAULT_LOCALE};
+use crate::masterbase::MasterbaseSession;
+use crate::persistence;
+use crate::secrets::Secret;
+use crate::steamapi::connection::SteamConnection;
+use crate::steamapi::{LookupBatching, DEFAULT_PRIVATE_RECHECK_BUDGET};
+use crate::steamid::SteamID;
+use crate::storage::StorageBackend;
+use crate::webhooks::Webhook;
+
+use self::validation::ConfigIssue;
+
+pub mod validation;
 
 #[derive(Debug, Error)]
 pub enum ConfigFilesError {
@@ -46,12 +65,68 @@ pub struct Settings {
     #[serde(skip)]
     tf2_directory: PathBuf,
     friends_api_usage: FriendsAPIUsage,
+    /// How long (seconds) a fetched friend list is reused, across sessions, before it is checked
+    /// again. Friend lists aren't cached if 0.
+    friends_cache_ttl: u64,
+    /// How long (seconds) a fetched Steam profile is reused, across sessions, before it is looked
+    /// up again. Profiles aren't cached if 0.
+    steam_info_cache_ttl: u64,
+    /// Friend lists that were private that may be asked for again each session, to see if they
+    /// have been made public
+    private_recheck_budget: u32,
+    /// Whether Steam profiles are scraped from the community site when there is no valid API
+    /// key, see [crate::steamapi::scrape]
+    profile_scraping: bool,
+    /// How requests get to Steam, see [crate::steamapi::connection]
+    steam_connection: SteamConnection,
+    /// How Steam profile lookups are batched
+    lookup_batching: LookupBatching,
+    /// Where player records and caches are kept, see [crate::storage]. Only changes on restart.
+    storage_backend: StorageBackend,
+    /// How many days chat messages are kept on player records, forever if 0
+    chat_retention_days: u64,
+    /// Whether `!who` commands from party members are answered in party chat, see
+    /// [crate::chat_commands::PartyCommand]
+    party_commands: bool,
+    /// Console commands the client may run over RCON, by name
+    command_allowlist: Vec<Arc<str>>,
+    /// Console lines dropped before they are parsed, see [noise]
+    noise_filters: Vec<NoiseFilter>,
+    /// Whether the user has opted in to submitting anonymous statistics, see [crate::telemetry]
+    telemetry: bool,
+    /// Where anonymous statistics are submitted to
+    telemetry_endpoint: Option<Arc<str>>,
+    /// The central service demos and reports are submitted to, see [crate::masterbase]
+    masterbase_endpoint: Option<Arc<str>>,
+    /// The user's session with the masterbase, once they have signed in
+    masterbase_session: Option<MasterbaseSession>,
+    /// Master switch for in-game actions the client takes by itself, see [interlocks]
+    automation_enabled: bool,
+    automation_limits: AutomationLimits,
+    /// Free space to keep on the demo volume and how long demos are kept, see [DemoStorage]
+    demo_storage: DemoStorage,
+    /// Where the user's marks are sent as they change, see [crate::webhooks]
+    mark_webhooks: Vec<Webhook>,
+    /// Steam groups known to host bots or cheaters, see [crate::groups]
+    flagged_groups: Vec<FlaggedGroup>,
+    /// How long (milliseconds) identical events are suppressed for, see [events]
+    event_dedup_window_ms: u64,
     rcon_password: Arc<str>,
-    steam_api_key: Arc<str>,
+    steam_api_key: Secret,
+    /// More API keys to take turns with `steam_api_key` for lookups, see [crate::steamapi::keys]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    extra_steam_api_keys: Vec<Secret>,
     webui_port: u16,
     autolaunch_ui: bool,
     external: serde_json::Value,
     rcon_port: u16,
+    /// Name of the [GameProfile] for the game being played
+    game_profile: Arc<str>,
+    /// Profiles for games other than the built in ones
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    game_profiles: Vec<GameProfile>,
+    /// Language of messages generated by the backend, see [locale]
+    locale: Arc<str>,
     #[serde(skip)]
     override_tf2_dir: Option<PathBuf>,
     #[serde(skip)]
@@ -64,6 +139,8 @@ pub struct Settings {
     override_steam_user: Option<SteamID>,
     #[serde(skip)]
     override_rcon_port: Option<u16>,
+    #[serde(skip)]
+    override_game_profile: Option<Arc<str>>,
 }
 
 #[allow(dead_code)]
@@ -108,8 +185,8 @@ impl Settings {
             }
         };
 
-        // Locate TF2 directory
-        match gamefinder::locate_tf2_folder() {
+        // Locate TF2 (or the game of the chosen profile) directory
+        match gamefinder::locate_game_folder(&settings.get_game_profile()) {
             Ok(tf2_directory) => {
                 settings.set_tf2_directory(tf2_directory);
             }
@@ -132,13 +209,40 @@ impl Settings {
     /// Attempt to load settings from a provided configuration file, or just use default config
     pub fn load_from(path: PathBuf, args: &Args) -> Result<Settings, ConfigFilesError> {
         // Read config.yaml file if it exists, otherwise try to create a default file.
-        let contents = std::fs::read_to_string(&path)
-            .map_err(|e| ConfigFilesError::IO(path.to_string_lossy().into(), e))?;
-        let mut settings = serde_yaml::from_str::<Settings>(&contents)
-            .map_err(|e| ConfigFilesError::Yaml(path.to_string_lossy().into(), e))?;
+        let validated = persistence::read_recovering(&path, |contents| {
+            validation::parse(contents)
+                .map_err(|e| ConfigFilesError::Yaml(path.to_string_lossy().into(), e))
+        })?;
+        let mut settings = validated.settings;
+
+        locale::set_locale(&settings.locale);
+        interlocks::set_automation_enabled(settings.automation_enabled);
+        events::set_dedup_window(settings.get_event_dedup_window());
+
+        let file = path.to_string_lossy();
+        for issue in &validated.issues {
+            let message = issue.describe(&file);
+            tracing::warn!("{}", message);
+            let level = match issue {
+                ConfigIssue::Invalid { .. } => SystemEventLevel::Error,
+                _ => SystemEventLevel::Warning,
+            };
+            events::emit_system(level, message);
+        }
+        if let Some(upgraded) = validated.upgraded {
+            if let Err(e) = persistence::write_atomic(&path, &upgraded) {
+                tracing::error!("Failed to add missing settings to {}: {:?}", file, e);
+            }
+        }
 
         settings.config_path = Some(path);
 
+        if settings.steam_api_key.is_locked()
+            || settings.extra_steam_api_keys.iter().any(Secret::is_locked)
+        {
+            events::emit_system(SystemEventLevel::Error, tr("api-key-locked", &[]));
+        }
+
         tracing::debug!("Successfully loaded settings.");
         settings.set_overrides(args);
         Ok(settings)
@@ -169,14 +273,12 @@ impl Settings {
                         .for_each(|user_data_obj| {
                             if let Some(timestamp) = user_data_obj
                                 .get("Timestamp")
-                                .and_then(|timestamp_values| timestamp_values.get(0))
+                                .and_then(|timestamp_values| timestamp_values.first())
                                 .and_then(|timestamp_vdf| timestamp_vdf.get_str())
                                 .and_then(|timestamp_str| timestamp_str.parse::<i64>().ok())
                             {
                                 if timestamp > latest_timestamp {
-                                    if let Ok(user_steamid) =
-                                        user_sid64.parse::<u64>().map(SteamID::from)
-                                    {
+                                    if let Ok(user_steamid) = user_sid64.parse::<SteamID>() {
                                         latest_timestamp = timestamp;
                                         latest_user_sid64 = Some(user_steamid);
                                     }
@@ -198,15 +300,13 @@ impl Settings {
     /// make sure to add tracing for any values overridden!
     pub fn set_overrides(&mut self, args: &Args) {
         // Override (and log if) the Port used to host the middleware API (default 3621)
-        self.override_webui_port = args.port.map(|val| {
+        self.override_webui_port = args.port.inspect(|val| {
             tracing::info!(
                 "Overrode configured port value {:?}->{:?}",
                 self.webui_port,
                 val
             );
-            val
         });
-        // Override (and log if) the RCON password. (default mac_rcon)
         self.override_rcon_password = args.rcon_pword.as_ref().map(|val| {
             tracing::info!(
                 "Overrode configured rcon_password {:?}->{:?}",
@@ -233,14 +333,22 @@ impl Settings {
             );
             PathBuf::from(val.clone())
         });
+        // Override (and log if) the game profile (default tf2)
+        self.override_game_profile = args.game_profile.as_ref().map(|val| {
+            tracing::info!(
+                "Overrode configured game profile {:?}->{:?}",
+                self.game_profile,
+                val
+            );
+            Arc::from(val.clone())
+        });
         // Override (and log if) the RCON port (default 27015)
-        self.override_rcon_port = args.rcon_port.map(|val| {
+        self.override_rcon_port = args.rcon_port.inspect(|val| {
             tracing::info!(
                 "Overrode configured RCON port value {:?}->{:?}",
                 self.rcon_port,
                 val
             );
-            val
         });
     }
 
@@ -248,19 +356,9 @@ impl Settings {
     pub fn save(&self) -> Result<()> {
         let config_path = self.config_path.as_ref().context("No config file set.")?;
 
-        let mut open_options = OpenOptions::new();
-        let mut file = open_options
-            .create(true)
-            .write(true)
-            .truncate(true)
-            .open(config_path)
-            .context("Failed to create or open config file.")?;
-        write!(
-            &mut file,
-            "{}",
-            serde_yaml::to_string(self).context("Failed to serialize configuration.")?
-        )
-        .context("Failed to write to config file.")?;
+        let contents = serde_yaml::to_string(self).context("Failed to serialize configuration.")?;
+        persistence::write_atomic(config_path, &contents)
+            .context("Failed to write to config file.")?;
 
         Ok(())
     }
@@ -298,10 +396,17 @@ impl Settings {
         self.override_webui_port.unwrap_or(self.webui_port)
     }
     pub fn get_steam_api_key(&self) -> Arc<str> {
-        self.override_steam_api_key
-            .as_ref()
-            .unwrap_or(&self.steam_api_key)
-            .clone()
+        if let Some(key) = &self.override_steam_api_key {
+            return key.clone();
+        }
+        self.steam_api_key.expose().unwrap_or_else(|| "".into())
+    }
+    /// The extra API keys that could be decrypted
+    pub fn get_extra_steam_api_keys(&self) -> Vec<Arc<str>> {
+        self.extra_steam_api_keys
+            .iter()
+            .filter_map(Secret::expose)
+            .collect()
     }
     pub fn get_external_preferences(&self) -> &serde_json::Value {
         &self.external
@@ -321,7 +426,11 @@ impl Settings {
     }
 
     pub fn set_steam_api_key(&mut self, key: Arc<str>) {
-        self.steam_api_key = key;
+        self.steam_api_key = key.into();
+    }
+
+    pub fn set_extra_steam_api_keys(&mut self, keys: Vec<Arc<str>>) {
+        self.extra_steam_api_keys = keys.into_iter().map(Secret::from).collect();
     }
 
     pub fn update_external_preferences(&mut self, prefs: serde_json::Value) {
@@ -340,6 +449,183 @@ impl Settings {
         &self.friends_api_usage
     }
 
+    pub fn get_friends_cache_ttl(&self) -> Duration {
+        Duration::from_secs(self.friends_cache_ttl)
+    }
+
+    pub fn set_friends_cache_ttl(&mut self, ttl: Duration) {
+        self.friends_cache_ttl = ttl.as_secs();
+    }
+
+    pub fn get_steam_info_cache_ttl(&self) -> Duration {
+        Duration::from_secs(self.steam_info_cache_ttl)
+    }
+
+    pub fn set_steam_info_cache_ttl(&mut self, ttl: Duration) {
+        self.steam_info_cache_ttl = ttl.as_secs();
+    }
+
+    pub fn get_private_recheck_budget(&self) -> u32 {
+        self.private_recheck_budget
+    }
+
+    pub fn set_private_recheck_budget(&mut self, budget: u32) {
+        self.private_recheck_budget = budget;
+    }
+
+    pub fn get_profile_scraping(&self) -> bool {
+        self.profile_scraping
+    }
+
+    pub fn set_profile_scraping(&mut self, enabled: bool) {
+        self.profile_scraping = enabled;
+    }
+
+    pub fn get_steam_connection(&self) -> SteamConnection {
+        self.steam_connection.clone()
+    }
+
+    pub fn set_steam_connection(&mut self, connection: SteamConnection) {
+        self.steam_connection = connection;
+    }
+
+    pub fn get_lookup_batching(&self) -> LookupBatching {
+        self.lookup_batching.clamped()
+    }
+
+    pub fn set_lookup_batching(&mut self, batching: LookupBatching) {
+        self.lookup_batching = batching.clamped();
+    }
+
+    pub fn get_storage_backend(&self) -> StorageBackend {
+        self.storage_backend
+    }
+
+    pub fn set_storage_backend(&mut self, backend: StorageBackend) {
+        self.storage_backend = backend;
+    }
+
+    /// How long chat messages are kept on player records, `None` if they are kept forever
+    pub fn get_chat_retention(&self) -> Option<Duration> {
+        (self.chat_retention_days > 0)
+            .then(|| Duration::from_secs(self.chat_retention_days * 24 * 60 * 60))
+    }
+
+    pub fn set_chat_retention_days(&mut self, days: u64) {
+        self.chat_retention_days = days;
+    }
+
+    pub fn get_command_allowlist(&self) -> CommandAllowlist {
+        CommandAllowlist::new(&self.command_allowlist)
+    }
+
+    pub fn set_command_allowlist(&mut self, commands: Vec<Arc<str>>) {
+        self.command_allowlist = commands;
+    }
+
+    pub fn get_noise_filters(&self) -> Vec<NoiseFilter> {
+        self.noise_filters.clone()
+    }
+
+    pub fn set_noise_filters(&mut self, filters: Vec<NoiseFilter>) {
+        self.noise_filters = filters;
+    }
+
+    pub fn get_telemetry(&self) -> bool {
+        self.telemetry
+    }
+
+    pub fn set_telemetry(&mut self, telemetry: bool) {
+        self.telemetry = telemetry;
+    }
+
+    /// Where to submit anonymous statistics, `None` unless the user has opted in
+    pub fn get_telemetry_endpoint(&self) -> Option<Arc<str>> {
+        self.telemetry
+            .then(|| self.telemetry_endpoint.clone())
+            .flatten()
+    }
+
+    pub fn get_masterbase_endpoint(&self) -> Option<Arc<str>> {
+        self.masterbase_endpoint.clone()
+    }
+
+    /// Change which masterbase to use, signing out of the old one
+    pub fn set_masterbase_endpoint(&mut self, endpoint: Option<Arc<str>>) {
+        if endpoint != self.masterbase_endpoint {
+            self.masterbase_session = None;
+        }
+        self.masterbase_endpoint = endpoint;
+    }
+
+    pub fn get_masterbase_session(&self) -> Option<MasterbaseSession> {
+        self.masterbase_session.clone()
+    }
+
+    pub fn set_masterbase_session(&mut self, session: Option<MasterbaseSession>) {
+        self.masterbase_session = session;
+    }
+
+    pub fn get_party_commands(&self) -> bool {
+        self.party_commands
+    }
+
+    pub fn set_party_commands(&mut self, enabled: bool) {
+        self.party_commands = enabled;
+    }
+
+    pub fn get_automation_enabled(&self) -> bool {
+        self.automation_enabled
+    }
+
+    /// Turn in-game automation on or off, which takes effect immediately
+    pub fn set_automation_enabled(&mut self, enabled: bool) {
+        interlocks::set_automation_enabled(enabled);
+        self.automation_enabled = enabled;
+    }
+
+    pub fn get_automation_limits(&self) -> AutomationLimits {
+        self.automation_limits.clone()
+    }
+
+    pub fn set_automation_limits(&mut self, limits: AutomationLimits) {
+        self.automation_limits = limits;
+    }
+
+    pub fn get_demo_storage(&self) -> DemoStorage {
+        self.demo_storage.clone()
+    }
+
+    pub fn set_demo_storage(&mut self, storage: DemoStorage) {
+        self.demo_storage = storage;
+    }
+
+    pub fn get_mark_webhooks(&self) -> Vec<Webhook> {
+        self.mark_webhooks.clone()
+    }
+
+    pub fn set_mark_webhooks(&mut self, webhooks: Vec<Webhook>) {
+        self.mark_webhooks = webhooks;
+    }
+
+    pub fn get_flagged_groups(&self) -> Vec<FlaggedGroup> {
+        self.flagged_groups.clone()
+    }
+
+    pub fn set_flagged_groups(&mut self, groups: Vec<FlaggedGroup>) {
+        self.flagged_groups = groups;
+    }
+
+    pub fn get_event_dedup_window(&self) -> Duration {
+        Duration::from_millis(self.event_dedup_window_ms)
+    }
+
+    /// Change how long identical events are suppressed for, which takes effect immediately
+    pub fn set_event_dedup_window(&mut self, window: Duration) {
+        events::set_dedup_window(window);
+        self.event_dedup_window_ms = window.as_millis() as u64;
+    }
+
     pub fn get_rcon_port(&self) -> u16 {
         self.override_rcon_port.unwrap_or(self.rcon_port)
     }
@@ -348,6 +634,33 @@ impl Settings {
         self.rcon_port = port;
     }
 
+    /// The [GameProfile] for the game being played. Falls back to TF2 if there is no profile
+    /// with the configured name.
+    pub fn get_game_profile(&self) -> GameProfile {
+        let name = self
+            .override_game_profile
+            .as_ref()
+            .unwrap_or(&self.game_profile);
+        GameProfile::find(name, &self.game_profiles).unwrap_or_else(|| {
+            tracing::warn!("There is no game profile named {}, using TF2.", name);
+            GameProfile::default()
+        })
+    }
+
+    pub fn set_game_profile(&mut self, name: Arc<str>) {
+        self.game_profile = name;
+    }
+
+    pub fn get_locale(&self) -> Arc<str> {
+        self.locale.clone()
+    }
+
+    /// Change the locale, which takes effect immediately
+    pub fn set_locale(&mut self, locale: Arc<str>) {
+        locale::set_locale(&locale);
+        self.locale = locale;
+    }
+
     /// Attempts to find (and create) a directory to be used for configuration files
     pub fn locate_config_directory() -> Result<PathBuf, ConfigFilesError> {
         let dirs = ProjectDirs::from("com.megascatterbomb", "MAC", "MACClient")
@@ -372,10 +685,7 @@ impl Default for Settings {
             .map_err(|e| tracing::error!("Failed to load steam user: {:?}", e))
             .ok();
         if let Some(steam_user) = &steam_user {
-            tracing::info!(
-                "Identified current steam user as {}",
-                u64::from(*steam_user)
-            );
+            tracing::info!("Identified current steam user as {}", steam_user);
         }
 
         Settings {
@@ -384,16 +694,42 @@ impl Default for Settings {
             tf2_directory: PathBuf::default(),
             rcon_password: "mac_rcon".into(),
             steam_api_key: "YOUR_API_KEY_HERE".into(),
+            extra_steam_api_keys: Vec::new(),
             friends_api_usage: FriendsAPIUsage::CheatersOnly,
+            friends_cache_ttl: 60 * 60,
+            steam_info_cache_ttl: 24 * 60 * 60,
+            private_recheck_budget: DEFAULT_PRIVATE_RECHECK_BUDGET,
+            profile_scraping: true,
+            steam_connection: SteamConnection::default(),
+            lookup_batching: LookupBatching::default(),
+            storage_backend: StorageBackend::default(),
+            chat_retention_days: 30,
+            party_commands: false,
+            command_allowlist: DEFAULT_ALLOWLIST.iter().map(|&c| c.into()).collect(),
+            noise_filters: noise::default_filters(),
+            telemetry: false,
+            telemetry_endpoint: None,
+            masterbase_endpoint: None,
+            masterbase_session: None,
+            automation_enabled: true,
+            automation_limits: AutomationLimits::default(),
+            demo_storage: DemoStorage::default(),
+            mark_webhooks: Vec::new(),
+            flagged_groups: Vec::new(),
+            event_dedup_window_ms: events::DEFAULT_DEDUP_WINDOW_MS,
             webui_port: 3621,
             autolaunch_ui: false,
             rcon_port: 27015,
+            game_profile: DEFAULT_PROFILE.into(),
+            game_profiles: Vec::new(),
+            locale: DEFAULT_LOCALE.into(),
             override_tf2_dir: None,
             override_rcon_password: None,
             override_steam_api_key: None,
             override_webui_port: None,
             override_steam_user: None,
             override_rcon_port: None,
+            override_game_profile: None,
             external: serde_json::Value::Object(Map::new()),
         }
     }
@@ -0,0 +1,299 @@
+use serde_yaml::{Mapping, Value};
+
+use super::Settings;
+use crate::locale::tr;
+
+/// Settings that aren't written out while they are empty, so won't show up in a serialized
+/// [Settings] even though they are valid
+const SKIPPED_WHEN_EMPTY: &[&str] = &["game_profiles"];
+/// Settings whose contents are up to the user (or the UI) and aren't checked
+const FREE_FORM: &[&str] = &["external"];
+
+/// Something wrong with the settings file that was worked around while loading it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConfigIssue {
+    /// A key that isn't a setting, along with the closest setting if it looks like a typo
+    Unknown {
+        line: Option<usize>,
+        key: String,
+        suggestion: Option<String>,
+    },
+    /// A setting that couldn't be read, so its default is being used
+    Invalid {
+        line: usize,
+        key: String,
+        error: String,
+    },
+    /// Settings that weren't in the file and have been added with their defaults
+    Missing(Vec<String>),
+}
+
+impl ConfigIssue {
+    /// A message explaining the issue to the user
+    pub fn describe(&self, file: &str) -> String {
+        match self {
+            ConfigIssue::Unknown {
+                line,
+                key,
+                suggestion,
+            } => {
+                let line = line.map_or_else(|| "?".to_string(), |l| l.to_string());
+                let mut message = tr(
+                    "config-unknown-key",
+                    &[("file", &file), ("line", &line), ("key", key)],
+                );
+                if let Some(suggestion) = suggestion {
+                    message.push(' ');
+                    message.push_str(&tr("config-did-you-mean", &[("suggestion", suggestion)]));
+                }
+                message
+            }
+            ConfigIssue::Invalid { line, key, error } => tr(
+                "config-invalid-value",
+                &[
+                    ("file", &file),
+                    ("line", line),
+                    ("key", key),
+                    ("error", error),
+                ],
+            ),
+            ConfigIssue::Missing(keys) => tr(
+                "config-missing-keys",
+                &[("file", &file), ("keys", &keys.join(", "))],
+            ),
+        }
+    }
+}
+
+/// The result of checking a settings file against what [Settings] expects
+pub struct Validated {
+    pub settings: Settings,
+    pub issues: Vec<ConfigIssue>,
+    /// The file with any missing settings added, if it should be rewritten
+    pub upgraded: Option<String>,
+}
+
+/// Parse the contents of a settings file. Settings with values that can't be read are reported
+/// and fall back to their defaults rather than failing the whole file, unknown keys are reported
+/// rather than silently ignored, and missing settings are added to an upgraded copy of the file.
+///
+/// Only errors that can't be pinned on a single setting (e.g. broken YAML outside of any) are
+/// returned.
+pub fn parse(contents: &str) -> Result<Validated, serde_yaml::Error> {
+    let mut text = contents.to_string();
+    let mut issues = Vec::new();
+
+    let settings = loop {
+        let document = if text.trim().is_empty() { "{}" } else { &text };
+        let error = match serde_yaml::from_str::<Settings>(document) {
+            Ok(settings) => break settings,
+            Err(e) => e,
+        };
+
+        let Some(line) = error.location().map(|l| l.line()) else {
+            return Err(error);
+        };
+        let Some((key, start, end)) = top_level_block(&text, line) else {
+            return Err(error);
+        };
+        if issues
+            .iter()
+            .any(|i| matches!(i, ConfigIssue::Invalid { key: k, .. } if *k == key))
+        {
+            return Err(error);
+        }
+
+        // Blank the lines rather than removing them so later errors still have the right line
+        text = text
+            .lines()
+            .enumerate()
+            .map(|(i, l)| {
+                if (start..end).contains(&(i + 1)) {
+                    ""
+                } else {
+                    l
+                }
+            })
+            .collect::<Vec<_>>()
+            .join("\n");
+        issues.push(ConfigIssue::Invalid {
+            line: start,
+            key,
+            error: error.to_string(),
+        });
+    };
+
+    let given = match serde_yaml::from_str::<Value>(&text) {
+        Ok(Value::Mapping(given)) => given,
+        _ => Mapping::new(),
+    };
+    let known = match serde_yaml::to_value(&settings) {
+        Ok(Value::Mapping(known)) => known,
+        _ => Mapping::new(),
+    };
+
+    issues.extend(unknown_keys(&text, &given, &known));
+
+    let invalid: Vec<String> = issues
+        .iter()
+        .filter_map(|i| match i {
+            ConfigIssue::Invalid { key, .. } => Some(key.clone()),
+            _ => None,
+        })
+        .collect();
+    let missing: Mapping = known
+        .iter()
+        .filter(|(key, _)| {
+            key.as_str()
+                .is_some_and(|k| !given.contains_key(k) && !invalid.iter().any(|i| i == k))
+        })
+        .map(|(k, v)| (k.clone(), v.clone()))
+        .collect();
+
+    // Never rewrite a file that couldn't be read properly, the user should fix it first
+    let mut upgraded = None;
+    if !missing.is_empty() && invalid.is_empty() {
+        if let Ok(addition) = serde_yaml::to_string(&missing) {
+            let mut file = contents.trim_end().to_string();
+            if !file.is_empty() {
+                file.push('\n');
+            }
+            file.push_str(&addition);
+            upgraded = Some(file);
+        }
+        issues.push(ConfigIssue::Missing(
+            missing
+                .keys()
+                .filter_map(|k| k.as_str().map(String::from))
+                .collect(),
+        ));
+    }
+
+    Ok(Validated {
+        settings,
+        issues,
+        upgraded,
+    })
+}
+
+/// Keys in the file that aren't settings, including one level down in settings that are maps
+fn unknown_keys(text: &str, given: &Mapping, known: &Mapping) -> Vec<ConfigIssue> {
+    let mut issues = Vec::new();
+    let names = |mapping: &Mapping| -> Vec<String> {
+        mapping
+            .keys()
+            .filter_map(|k| k.as_str().map(String::from))
+            .collect()
+    };
+    let known_names: Vec<String> = names(known)
+        .into_iter()
+        .chain(SKIPPED_WHEN_EMPTY.iter().map(|k| k.to_string()))
+        .collect();
+
+    for (key, value) in given {
+        let Some(key) = key.as_str() else {
+            continue;
+        };
+        if !known_names.iter().any(|k| k == key) {
+            issues.push(ConfigIssue::Unknown {
+                line: key_line(text, None, key),
+                key: key.to_string(),
+                suggestion: closest(key, &known_names),
+            });
+            continue;
+        }
+
+        if FREE_FORM.contains(&key) {
+            continue;
+        }
+        let (Some(Value::Mapping(known_inner)), Value::Mapping(given_inner)) =
+            (known.get(key), value)
+        else {
+            continue;
+        };
+        let known_inner = names(known_inner);
+        for inner in names(given_inner) {
+            if !known_inner.contains(&inner) {
+                issues.push(ConfigIssue::Unknown {
+                    line: key_line(text, Some(key), &inner),
+                    key: format!("{}.{}", key, inner),
+                    suggestion: closest(&inner, &known_inner).map(|s| format!("{}.{}", key, s)),
+                });
+            }
+        }
+    }
+
+    issues
+}
+
+fn is_top_level(line: &str) -> bool {
+    !line.starts_with(char::is_whitespace)
+        && !line.trim().is_empty()
+        && !line.starts_with('#')
+        && !line.starts_with("---")
+}
+
+/// The name of the top level key whose block contains the (1 based) `line`, along with the
+/// first line of the block and the line after its end
+fn top_level_block(text: &str, line: usize) -> Option<(String, usize, usize)> {
+    let lines: Vec<&str> = text.lines().collect();
+    let start = (1..=line.min(lines.len()))
+        .rev()
+        .find(|&n| is_top_level(lines[n - 1]))?;
+    let key = key_name(lines[start - 1])?;
+    let end = (start + 1..=lines.len())
+        .find(|&n| is_top_level(lines[n - 1]))
+        .unwrap_or(lines.len() + 1);
+    Some((key, start, end))
+}
+
+/// The key defined on a line like `key: value`, without any quotes
+fn key_name(line: &str) -> Option<String> {
+    let (key, _) = line.trim().split_once(':')?;
+    Some(key.trim_matches(|c| c == '"' || c == '\'').to_string())
+}
+
+/// The (1 based) line `key` is defined on, inside the block of the top level key `parent` if
+/// given
+fn key_line(text: &str, parent: Option<&str>, key: &str) -> Option<usize> {
+    let lines: Vec<&str> = text.lines().collect();
+    let (from, to) = match parent {
+        None => (1, lines.len() + 1),
+        Some(parent) => {
+            let start = (1..=lines.len()).find(|&n| {
+                is_top_level(lines[n - 1]) && key_name(lines[n - 1]).as_deref() == Some(parent)
+            })?;
+            let (_, _, end) = top_level_block(text, start)?;
+            (start + 1, end)
+        }
+    };
+    (from..to).find(|&n| {
+        let line = lines[n - 1];
+        (parent.is_some() || is_top_level(line)) && key_name(line).as_deref() == Some(key)
+    })
+}
+
+/// The candidate closest to `key`, if it is close enough to likely be a typo
+fn closest(key: &str, candidates: &[String]) -> Option<String> {
+    candidates
+        .iter()
+        .map(|c| (edit_distance(key, c), c))
+        .filter(|(distance, c)| *distance <= (c.len() / 3).max(1))
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, c)| c.clone())
+}
+
+/// Number of single character insertions, deletions or substitutions to turn `a` into `b`
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(ca != *cb);
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
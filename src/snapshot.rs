@@ -0,0 +1,192 @@
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use arc_swap::ArcSwap;
+use serde::Serialize;
+use serde_json::Value;
+use tokio::sync::broadcast;
+
+use crate::server::Server;
+
+/// How many diffs a slow subscriber can fall behind by before it has to be sent the full state again
+const DIFF_BACKLOG: usize = 64;
+/// Shortest time between patches sent to each subscriber. Diffs published in between are sent
+/// together as one patch.
+pub const PATCH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// A consistent view of the game state as it was at one point in time, already serialized so
+/// that it can be handed to any number of web clients without touching the [Server] again.
+#[derive(Debug)]
+pub struct GameSnapshot {
+    /// Increases every time a new snapshot is published
+    pub version: u64,
+    /// The [Server] serialized as JSON
+    pub json: Arc<str>,
+    value: Value,
+}
+
+/// Messages sent to clients that follow the game state over a WebSocket.
+/// Serializes as `{"type": "full", ...}` or `{"type": "patch", ...}`.
+#[derive(Debug, Serialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum GameUpdate<'a> {
+    /// The entire state, sent when a client connects or has missed some diffs
+    Full { version: u64, state: &'a Value },
+    /// A JSON patch (RFC 6902) which turns the state at version `from` into the state at `version`
+    Patch {
+        from: u64,
+        version: u64,
+        patch: &'a json_patch::Patch,
+    },
+}
+
+/// A change between two consecutive snapshots, serialized once for every subscriber
+#[derive(Debug, Clone)]
+pub struct GameDiff {
+    pub from: u64,
+    pub version: u64,
+    pub patch: Arc<json_patch::Patch>,
+    /// The [GameUpdate::Patch] message as JSON
+    pub message: Arc<str>,
+}
+
+/// Diffs waiting to be sent to one subscriber, see [PATCH_INTERVAL]
+#[derive(Debug, Default)]
+pub struct PendingPatch {
+    diffs: Vec<GameDiff>,
+}
+
+impl PendingPatch {
+    /// Add a diff that follows on from the ones already waiting
+    pub fn push(&mut self, diff: GameDiff) {
+        self.diffs.push(diff);
+    }
+
+    /// Forget the diffs waiting, e.g. when the full state is sent instead
+    pub fn clear(&mut self) {
+        self.diffs.clear();
+    }
+
+    /// The [GameUpdate::Patch] message for every diff waiting, if there are any. A single diff's
+    /// message is reused as it is, otherwise their operations are applied one after the other.
+    pub fn take_message(&mut self) -> Option<String> {
+        let diffs = std::mem::take(&mut self.diffs);
+        match diffs.as_slice() {
+            [] => None,
+            [diff] => Some(diff.message.to_string()),
+            [first, .., last] => {
+                let patch = json_patch::Patch(
+                    diffs
+                        .iter()
+                        .flat_map(|diff| diff.patch.0.iter().cloned())
+                        .collect(),
+                );
+                Some(
+                    serde_json::to_string(&GameUpdate::Patch {
+                        from: first.from,
+                        version: last.version,
+                        patch: &patch,
+                    })
+                    .expect("Serialize game diff"),
+                )
+            }
+        }
+    }
+}
+
+impl GameSnapshot {
+    fn new(version: u64, value: Value) -> GameSnapshot {
+        GameSnapshot {
+            version,
+            json: value.to_string().into(),
+            value,
+        }
+    }
+
+    /// The [GameUpdate::Full] message for this snapshot, as JSON
+    pub fn full_update(&self) -> String {
+        serde_json::to_string(&GameUpdate::Full {
+            version: self.version,
+            state: &self.value,
+        })
+        .expect("Serialize full game update")
+    }
+}
+
+/// The most recently published [GameSnapshot].
+///
+/// The [Server] itself stays behind a `RwLock` that the ingestion tasks write to. Whenever they
+/// have finished a batch of changes they [publish](SharedSnapshot::publish) a new snapshot, so
+/// readers only ever have to clone an `Arc` and never wait on (or hold up) the writers, no matter
+/// how many of them are polling.
+///
+/// Publishing also works out what changed since the previous snapshot and broadcasts it as a
+/// [GameDiff], so clients that [subscribe](SharedSnapshot::subscribe) only have to receive the
+/// full state once.
+#[derive(Debug, Clone)]
+pub struct SharedSnapshot {
+    current: Arc<ArcSwap<GameSnapshot>>,
+    /// Held while publishing so diffs are always made against the snapshot they follow
+    diffs: Arc<Mutex<broadcast::Sender<GameDiff>>>,
+}
+
+impl SharedSnapshot {
+    pub fn new(server: &Server) -> SharedSnapshot {
+        let (diffs, _) = broadcast::channel(DIFF_BACKLOG);
+        SharedSnapshot {
+            current: Arc::new(ArcSwap::from_pointee(GameSnapshot::new(
+                0,
+                serialize(server),
+            ))),
+            diffs: Arc::new(Mutex::new(diffs)),
+        }
+    }
+
+    /// Take a new snapshot of `server` and make it the current one, unless nothing has changed
+    pub fn publish(&self, server: &Server) {
+        let value = serialize(server);
+
+        let diffs = self.diffs.lock().unwrap();
+        let previous = self.current.load();
+        let patch = json_patch::diff(&previous.value, &value);
+        if patch.0.is_empty() {
+            return;
+        }
+
+        let from = previous.version;
+        let version = from + 1;
+        let message = serde_json::to_string(&GameUpdate::Patch {
+            from,
+            version,
+            patch: &patch,
+        })
+        .expect("Serialize game diff");
+
+        self.current
+            .store(Arc::new(GameSnapshot::new(version, value)));
+        // Nobody listening is fine
+        let _ = diffs.send(GameDiff {
+            from,
+            version,
+            patch: Arc::new(patch),
+            message: message.into(),
+        });
+    }
+
+    /// The most recently published snapshot
+    pub fn load(&self) -> Arc<GameSnapshot> {
+        self.current.load_full()
+    }
+
+    /// Receive every diff published from now on. Subscribe before loading the snapshot to start
+    /// from, so that no diffs can be missed in between.
+    pub fn subscribe(&self) -> broadcast::Receiver<GameDiff> {
+        self.diffs.lock().unwrap().subscribe()
+    }
+}
+
+fn serialize(server: &Server) -> Value {
+    serde_json::to_value(server).expect("Serialize game state")
+}
@@ -1,43 +1,278 @@
 use std::collections::HashMap;
+use std::collections::HashSet;
 use std::collections::VecDeque;
 use std::sync::Arc;
 
 use anyhow::{anyhow, Context, Result};
-use steamid_ng::SteamID;
-use tappet::{
-    response_types::{
-        GetFriendListResponseBase, GetPlayerBansResponseBase, GetPlayerSummariesResponseBase,
-        PlayerBans, PlayerSummary,
-    },
-    Executor, SteamAPI,
-};
+use futures::StreamExt;
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
 
+use tokio::sync::mpsc::error::SendError;
 use tokio::sync::mpsc::unbounded_channel;
 use tokio::sync::mpsc::UnboundedReceiver;
 use tokio::sync::mpsc::UnboundedSender;
 use tokio::time::{Duration, MissedTickBehavior};
 
-use crate::player::{Friend, SteamInfo};
+use crate::analytics::history::now;
+use crate::events::{self, SystemEventLevel};
+use crate::gamefinder::TF2_GAME_ID;
+use crate::groups::SteamGroup;
+use crate::locale::tr;
+use crate::pause;
+use crate::player::{EconomyBan, Friend, ProfileVisibility, SteamInfo};
+use crate::steamid::SteamID;
+use crate::storage::Storage;
 
-const BATCH_INTERVAL: Duration = Duration::from_millis(500);
-const BATCH_SIZE: usize = 20; // adjust as needed
+use self::backend::{SteamApiBackend, WebApiBackend, PLAYER_SUMMARIES_PATH};
+use self::cache::{CachedFriendList, SteamInfoCache};
+use self::connection::SteamConnection;
+use self::keys::KeyRing;
+use self::retry::{Endpoint, RateLimited, RateLimits, RetryQueue};
+use self::schema::SchemaDrift;
+
+pub mod avatars;
+pub mod backend;
+pub mod cache;
+pub mod connection;
+pub mod keys;
+pub mod metrics;
+pub mod retry;
+pub mod risk;
+pub mod schema;
+pub mod scrape;
+
+/// Most players GetPlayerSummaries accepts in one request
+const MAX_BATCH_SIZE: usize = 100;
+/// Shortest time (milliseconds) allowed between batches
+const MIN_BATCH_INTERVAL_MS: u64 = 100;
+/// Batches that have to go through without being rate limited before a batch size that was
+/// reduced is raised again
+const BATCH_RECOVERY: u32 = 20;
+/// Profiles scraped each batch when there is no API key. Each is a request of its own, so this is
+/// kept low to stay well clear of the community site's rate limits.
+const SCRAPE_BATCH_SIZE: usize = 1;
+
+/// Friend lists that were private which are asked for again each session unless configured
+/// otherwise, see [SteamAPIManager::set_private_recheck_budget]
+pub const DEFAULT_PRIVATE_RECHECK_BUDGET: u32 = 25;
+
+/// Most players GetPlayerBans accepts in one request
+pub const BAN_CHECK_SIZE: usize = 100;
+/// How often marked accounts are rechecked for new bans
+pub const BAN_RECHECK_AGE: Duration = Duration::from_secs(24 * 60 * 60);
+/// How often to look for marked accounts due a recheck
+pub const BAN_RECHECK_INTERVAL: Duration = Duration::from_secs(60 * 60);
+/// Requests made each time marked accounts are rechecked, and the time between them. This keeps
+/// well inside the daily Steam API quota however many accounts have been marked.
+const BAN_RECHECK_REQUESTS: usize = 10;
+const BAN_RECHECK_SPACING: Duration = Duration::from_secs(10);
+/// Time between fetching stale friend lists again, one at a time, so a lobby full of them doesn't
+/// turn into a burst of requests
+const FRIENDS_RECHECK_SPACING: Duration = Duration::from_secs(15);
+/// Friend lists fetched at the same time
+const FRIENDS_CONCURRENCY: usize = 6;
+/// Profile details (see [PROFILE_SERVICES]) fetched at the same time for a batch of lookups
+const PROFILE_CONCURRENCY: usize = 8;
+/// The IPlayerService methods called for each public profile that is looked up, and their
+/// parameters. Free games are included in the owned games so the same response has TF2's playtime.
+const PROFILE_SERVICES: &[(&str, &[(&str, &str)])] = &[
+    ("GetProfileItemsEquipped", &[]),
+    ("GetProfileCustomization", &[]),
+    ("GetOwnedGames", &[("include_played_free_games", "1")]),
+    ("GetSteamLevel", &[]),
+];
+/// How often newly fetched profiles are saved to the cache file
+const CACHE_SAVE_INTERVAL: Duration = Duration::from_secs(5 * 60);
+
+/// Lets the [SteamAPIManager] ask the game state which players are currently connected, so
+/// their lookups can be made before any background refreshes.
+pub type Roster = Box<dyn Fn() -> Vec<SteamID> + Send + Sync>;
+
+/// How lookups are grouped into batches. Slow connections may want smaller batches, while busy
+/// servers may want bigger ones sent more often.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct LookupBatching {
+    /// Milliseconds between batches
+    pub interval_ms: u64,
+    /// Most players looked up in each batch through the API. Scraped profiles always go one at a
+    /// time.
+    pub size: usize,
+}
+
+impl Default for LookupBatching {
+    fn default() -> Self {
+        LookupBatching {
+            interval_ms: 500,
+            size: 20,
+        }
+    }
+}
+
+impl LookupBatching {
+    /// Brought within what Steam accepts
+    pub fn clamped(self) -> LookupBatching {
+        LookupBatching {
+            interval_ms: self.interval_ms.max(MIN_BATCH_INTERVAL_MS),
+            size: self.size.clamp(1, MAX_BATCH_SIZE),
+        }
+    }
+
+    fn interval(&self) -> Duration {
+        Duration::from_millis(self.interval_ms)
+    }
+}
+
+/// How soon a player should be looked up
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LookupPriority {
+    /// The user is waiting on it, e.g. they have just opened the player in the UI. These go out
+    /// in the next batch, ahead of everything else.
+    High,
+    Normal,
+}
 
 #[derive(Clone, Debug)]
 pub enum SteamAPIMessage {
-    Lookup(SteamID),
+    /// Look up a player's profile with an upcoming batch. Asking again before it goes out doesn't
+    /// add another lookup, everyone waiting on the player gets the one response.
+    Lookup(SteamID, LookupPriority),
+    /// Fetch a player's profile, bans and friends straight away, even if they are cached
+    Refresh(SteamID),
+    /// Get friend lists, using cached ones where they were fetched recently enough
     CheckFriends(Vec<SteamID>),
+    /// Get friend lists from the API even if they are cached
+    ForceCheckFriends(Vec<SteamID>),
     SetAPIKey(Arc<str>),
+    /// Keys to take turns with the main API key for lookups, see [keys]
+    SetExtraAPIKeys(Vec<Arc<str>>),
+    /// How long fetched friend lists are reused for, across sessions, before they are checked again
+    SetFriendsCacheTTL(Duration),
+    /// How long fetched profiles are reused for, across sessions, before they are looked up again
+    SetSteamInfoCacheTTL(Duration),
+    /// How many friend lists that were private may be asked for again this session, see
+    /// [SteamAPIManager::set_private_recheck_budget]
+    SetPrivateRecheckBudget(u32),
+    /// Whether to scrape community profiles when there is no valid API key, see [scrape]
+    SetProfileScraping(bool),
+    /// Change how lookups are batched
+    Configure(LookupBatching),
+    /// Change how requests get to Steam, see [connection]
+    SetConnection(SteamConnection),
+    /// Recheck the bans of accounts that are due, least recently checked first. They are asked
+    /// for a few at a time in the background, replacing any still waiting from last time.
+    RecheckBans(Vec<SteamID>),
+    /// Find the account with a custom profile URL, given either just its name or the whole link
+    /// (e.g. `https://steamcommunity.com/id/somename/`)
+    ResolveVanityUrl(String),
+    /// Find the public Steam groups a player is in
+    CheckGroups(SteamID),
+    /// Drop lookups that haven't gone out yet, including any waiting to be retried, so they don't
+    /// use up the API quota after the user has left the server they were for. Only the given
+    /// players' lookups are dropped, or every one if `None`.
+    CancelPending(Option<Vec<SteamID>>),
+}
+
+/// Why a player couldn't be looked up
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, thiserror::Error)]
+#[serde(rename_all = "camelCase")]
+pub enum SteamAPIError {
+    /// Steam is rate limiting, the lookup will be tried again once it stops
+    #[error("Rate limited by Steam")]
+    RateLimited,
+    /// The profile doesn't show what was asked for
+    #[error("The profile is private")]
+    PrivateProfile,
+    /// There is no valid API key, and profiles aren't being scraped instead
+    #[error("No valid Steam Web API key")]
+    InvalidKey,
+    /// The account doesn't exist, e.g. it has been deleted
+    #[error("No such account")]
+    NotFound,
+    /// Steam couldn't be reached or didn't respond properly. Lookups that failed this way are
+    /// tried again a few times.
+    #[error("Could not reach Steam")]
+    Network,
+}
+
+impl SteamAPIError {
+    /// What went wrong with a failed lookup
+    fn from_error(e: &anyhow::Error) -> SteamAPIError {
+        if e.downcast_ref::<RateLimited>().is_some() {
+            return SteamAPIError::RateLimited;
+        }
+        if let Some(error) = e.chain().find_map(|e| e.downcast_ref::<SteamAPIError>()) {
+            return *error;
+        }
+        match retry::status(e) {
+            Some(StatusCode::TOO_MANY_REQUESTS) => SteamAPIError::RateLimited,
+            Some(StatusCode::UNAUTHORIZED) => SteamAPIError::PrivateProfile,
+            Some(StatusCode::FORBIDDEN) => SteamAPIError::InvalidKey,
+            Some(StatusCode::NOT_FOUND) => SteamAPIError::NotFound,
+            _ => SteamAPIError::Network,
+        }
+    }
 }
 
 pub enum SteamAPIResponse {
+    /// A player's profile
     SteamInfo((SteamID, SteamInfo)),
+    /// A player's profile couldn't be looked up
+    LookupFailed(SteamID, SteamAPIError),
     FriendLists((SteamID, Result<Vec<Friend>>)),
+    /// VAC and game bans of each player
+    Bans(Vec<(SteamID, i64, i64)>),
+    /// The account a custom profile URL belongs to, as it was requested
+    ResolvedVanity((String, Result<SteamID>)),
+    /// The public groups a player is in
+    Groups((SteamID, Result<Vec<SteamGroup>>)),
+    /// Whether Steam accepted the API key, after it was checked against the live API
+    APIKeyChecked(bool),
 }
 
 pub struct SteamAPIManager {
-    client: SteamAPI,
+    backend: Arc<dyn SteamApiBackend>,
+    /// For scraping community profiles, which isn't part of the Web API
+    http: reqwest::Client,
+    api_key: Arc<str>,
+    /// Keys used alongside `api_key` for lookups
+    extra_api_keys: Vec<Arc<str>>,
+    /// Every usable key, which batches of lookups take turns with
+    keys: KeyRing,
+    /// The key requests other than lookups are made with, which is the one the last lookup was
+    /// made with
+    current_key: Arc<str>,
     batch_buffer: VecDeque<SteamID>,
+    /// Lookups with [LookupPriority::High], which go out before anything in `batch_buffer`
+    priority_buffer: VecDeque<SteamID>,
+    /// Every player in either buffer, so players requested again before their lookup goes out
+    /// (e.g. while a server is filling up) don't take up more than one slot in a batch
+    pending: HashSet<SteamID>,
+    batching: LookupBatching,
+    /// Players in each batch through the API. This starts at `batching.size`, and is halved
+    /// whenever Steam rate limits until enough batches have gone through.
+    batch_size: usize,
+    /// Batches looked up since Steam last rate limited
+    batches_since_limited: u32,
     api_key_valid: bool,
+    /// Whether to fall back to scraping community profiles while the API key isn't valid
+    profile_scraping: bool,
+    roster: Option<Roster>,
+    steam_info_cache: SteamInfoCache,
+    /// Players whose stale friend lists were used, to be fetched again in the background
+    friends_rechecks: VecDeque<SteamID>,
+    /// Accounts whose bans are to be rechecked, up to [BAN_CHECK_SIZE] in each request
+    ban_rechecks: VecDeque<Vec<SteamID>>,
+    /// Friend lists that were private that may be asked for again this session
+    private_recheck_budget: u32,
+    /// Friend lists that were private that have been asked for again this session
+    private_rechecks: u32,
+    /// Friend lists fetched in the background, see [SteamAPIManager::fetch_friends]
+    friends_send: UnboundedSender<(SteamID, Result<Vec<Friend>>)>,
+    friends_recv: UnboundedReceiver<(SteamID, Result<Vec<Friend>>)>,
+    rate_limits: RateLimits,
+    retries: RetryQueue,
 
     request_recv: UnboundedReceiver<SteamAPIMessage>,
     response_send: UnboundedSender<SteamAPIResponse>,
@@ -46,19 +281,51 @@ pub struct SteamAPIManager {
 impl SteamAPIManager {
     pub fn new(
         api_key: Arc<str>,
+        storage: Arc<dyn Storage>,
+        friends_cache_ttl: Duration,
+        steam_info_cache_ttl: Duration,
+        profile_scraping: bool,
+        batching: LookupBatching,
         recv: UnboundedReceiver<SteamAPIMessage>,
     ) -> (UnboundedReceiver<SteamAPIResponse>, SteamAPIManager) {
         let (resp_tx, resp_rx) = unbounded_channel();
+        let (friends_send, friends_recv) = unbounded_channel();
 
         let valid_api_key = is_api_key_valid(&api_key);
         if !valid_api_key {
             tracing::info!("Invalid/Improper API key provided, disabling Steam API requests.");
         }
 
+        let http = reqwest::Client::new();
         let api_manager = SteamAPIManager {
-            client: SteamAPI::new(api_key),
-            batch_buffer: VecDeque::with_capacity(BATCH_SIZE),
+            backend: Arc::new(WebApiBackend::new(http.clone())),
+            http,
+            extra_api_keys: Vec::new(),
+            keys: KeyRing::new([api_key.clone()]),
+            current_key: api_key.clone(),
+            api_key,
+            batch_buffer: VecDeque::with_capacity(batching.size),
+            priority_buffer: VecDeque::new(),
+            pending: HashSet::new(),
+            batching,
+            batch_size: batching.size,
+            batches_since_limited: 0,
             api_key_valid: valid_api_key,
+            profile_scraping,
+            roster: None,
+            steam_info_cache: SteamInfoCache::load_or_create(
+                storage,
+                steam_info_cache_ttl,
+                friends_cache_ttl,
+            ),
+            friends_rechecks: VecDeque::new(),
+            ban_rechecks: VecDeque::new(),
+            private_recheck_budget: DEFAULT_PRIVATE_RECHECK_BUDGET,
+            private_rechecks: 0,
+            friends_send,
+            friends_recv,
+            rate_limits: RateLimits::default(),
+            retries: RetryQueue::default(),
 
             request_recv: recv,
             response_send: resp_tx,
@@ -67,10 +334,74 @@ impl SteamAPIManager {
         (resp_rx, api_manager)
     }
 
+    /// Provide a way to find out who is currently connected. Without one, lookups are made in the
+    /// order they were requested.
+    pub fn set_roster(&mut self, roster: impl Fn() -> Vec<SteamID> + Send + Sync + 'static) {
+        self.roster = Some(Box::new(roster));
+    }
+
+    /// Make requests to the Web API through `backend` rather than the real one, e.g. a mock for
+    /// testing
+    #[cfg(any(test, feature = "fault-injection"))]
+    pub fn set_backend(&mut self, backend: Arc<dyn SteamApiBackend>) {
+        self.backend = backend;
+    }
+
+    /// Limit how many friend lists that were private are asked for again this session to see if
+    /// they have been made public. Lists are only rechecked once enough time has passed since
+    /// they were last checked, which grows the longer they stay private, and a budget stops a
+    /// session full of private profiles from using up the API quota on them. Refreshing a player
+    /// always asks for their list regardless.
+    pub fn set_private_recheck_budget(&mut self, budget: u32) {
+        self.private_recheck_budget = budget;
+    }
+
+    /// Whether players can be looked up at all, through the API or by scraping their profiles
+    fn can_lookup(&self) -> bool {
+        self.api_key_valid || self.profile_scraping
+    }
+
+    /// Make requests to Steam the way `connection` says to. If it can't be used (e.g. the proxy
+    /// isn't a valid URL) requests keep being made the way they were.
+    pub fn set_connection(&mut self, connection: &SteamConnection) {
+        match connection.client() {
+            Ok(http) => {
+                self.backend
+                    .set_connection(http.clone(), connection.api_url());
+                self.http = http;
+                tracing::info!("Making SteamAPI requests to {}", connection.api_url());
+            }
+            Err(e) => {
+                tracing::error!("Could not change how Steam is reached: {:?}", e);
+                events::emit_system(
+                    SystemEventLevel::Error,
+                    tr("steam-connection-invalid", &[("error", &e)]),
+                );
+            }
+        }
+    }
+
+    /// Use `keys` alongside the main API key for lookups, see [keys]
+    pub fn set_extra_api_keys(&mut self, keys: Vec<Arc<str>>) {
+        self.extra_api_keys = keys;
+        self.rebuild_keys();
+        if self.keys.len() > 1 {
+            tracing::info!("Rotating between {} SteamAPI keys.", self.keys.len());
+        }
+    }
+
+    /// Start the rotation of API keys over, e.g. after they've changed
+    fn rebuild_keys(&mut self) {
+        let keys = std::iter::once(self.api_key.clone()).chain(self.extra_api_keys.clone());
+        self.keys = KeyRing::new(keys);
+        self.api_key_valid = !self.keys.is_empty();
+    }
+
     fn set_api_key(&mut self, api_key: Arc<str>) {
         let _last = self.api_key_valid;
-        self.api_key_valid = is_api_key_valid(&api_key);
-        self.client = SteamAPI::new(api_key);
+        self.api_key = api_key.clone();
+        self.current_key = api_key;
+        self.rebuild_keys();
         if !_last && self.api_key_valid {
             tracing::info!("New API key received, enabling SteamAPI requests.");
         } else if _last && !self.api_key_valid {
@@ -80,102 +411,675 @@ impl SteamAPIManager {
         }
     }
 
+    /// Check the API key actually works, as [is_api_key_valid] only checks it looks like one. A
+    /// key Steam rejects is treated as invalid, so profiles are scraped instead if enabled. If
+    /// Steam can't be reached the key is assumed to work. Keys that are accepted are only
+    /// reported if `report_accepted` is set, e.g. when the user has just entered one.
+    async fn check_api_key(
+        &mut self,
+        report_accepted: bool,
+    ) -> Result<(), SendError<SteamAPIResponse>> {
+        if self.api_key.is_empty() {
+            return Ok(());
+        }
+        let works = if is_api_key_valid(&self.api_key) {
+            match self.backend.verify_api_key(&self.api_key).await {
+                Ok(works) => works,
+                Err(e) => {
+                    tracing::warn!("Could not check the SteamAPI key: {:?}", e);
+                    return Ok(());
+                }
+            }
+        } else {
+            false
+        };
+
+        if works {
+            tracing::info!("SteamAPI key was accepted.");
+            if report_accepted {
+                self.response_send
+                    .send(SteamAPIResponse::APIKeyChecked(true))?;
+            }
+        } else {
+            self.keys.remove(&self.api_key);
+            self.api_key_valid = !self.keys.is_empty();
+            if self.api_key_valid {
+                tracing::warn!("SteamAPI key was rejected, using the other keys instead.");
+            } else {
+                tracing::warn!("SteamAPI key was rejected, disabling SteamAPI requests.");
+            }
+            self.response_send
+                .send(SteamAPIResponse::APIKeyChecked(false))?;
+        }
+        Ok(())
+    }
+
     /// Enter a loop to wait for steam lookup requests, make those requests from the Steam web API,
     /// and update the state to include that data. Intended to be run inside a new tokio::task
+    ///
+    /// The loop exits (rather than panicking) once either end of the channel to the main thread
+    /// has been closed.
     pub async fn api_loop(&mut self) {
-        let mut batch_timer = tokio::time::interval(BATCH_INTERVAL);
+        let mut batch_interval = self.batching.interval();
+        let mut batch_timer = tokio::time::interval(batch_interval);
         batch_timer.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        let mut cache_timer = tokio::time::interval(CACHE_SAVE_INTERVAL);
+        cache_timer.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        let mut friends_recheck_timer = tokio::time::interval(FRIENDS_RECHECK_SPACING);
+        friends_recheck_timer.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        let mut ban_recheck_timer = tokio::time::interval(BAN_RECHECK_SPACING);
+        ban_recheck_timer.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+        if self.check_api_key(false).await.is_err() {
+            tracing::warn!("Lost connection to main thread, shutting down Steam API manager.");
+            return;
+        }
 
         loop {
-            tokio::select! {
-                Some(request) = self.request_recv.recv() => {
-                    match request {
-                        SteamAPIMessage::SetAPIKey(key) => {
-                            self.set_api_key(key);
-                        },
-                        SteamAPIMessage::Lookup(steamid) => {
-                            if self.api_key_valid {
-                                self.batch_buffer.push_back(steamid);
-                                if self.batch_buffer.len() >= BATCH_SIZE {
-                                    self.send_batch().await;
-                                    batch_timer.reset();  // Reset the timer
-                                }
-                            }
-                        },
-                        SteamAPIMessage::CheckFriends(steamids) => {
-                            if self.api_key_valid {
-                                for id in steamids {
-                                    match request_account_friends(&mut self.client, id).await {
-                                        Ok(friends) => {
-                                            self.response_send
-                                                .send(SteamAPIResponse::FriendLists((id, Ok(friends))))
-                                                .expect("Lost connection to main thread.");
-                                        }
-                                        Err(err) => {
-                                            self.response_send
-                                                .send(SteamAPIResponse::FriendLists((id, Err(err))))
-                                                .expect("Lost connection to main thread.");
-                                        }
-                                    }
-                                }
-                            }
+            if self.batching.interval() != batch_interval {
+                batch_interval = self.batching.interval();
+                batch_timer = tokio::time::interval(batch_interval);
+                batch_timer.set_missed_tick_behavior(MissedTickBehavior::Delay);
+            }
+
+            let sent = tokio::select! {
+                request = self.request_recv.recv() => {
+                    let Some(request) = request else {
+                        tracing::info!("Steam API request channel closed, shutting down.");
+                        break;
+                    };
+                    match self.handle_request(request).await {
+                        // A full batch goes out straight away instead of waiting for the timer
+                        Ok(()) if self.batch_full() => {
+                            batch_timer.reset();
+                            self.send_batch().await
                         }
+                        sent => sent,
                     }
                 },
                 _ = batch_timer.tick() => {
-                    if self.api_key_valid && !self.batch_buffer.is_empty() {
-                        self.send_batch().await;
+                    for steamid in self.retries.take_due() {
+                        self.queue(steamid, LookupPriority::Normal);
+                    }
+                    if self.can_lookup()
+                        && !(self.batch_buffer.is_empty() && self.priority_buffer.is_empty())
+                    {
+                        self.send_batch().await
+                    } else {
+                        Ok(())
                     }
                 }
+                _ = cache_timer.tick() => {
+                    self.steam_info_cache.save_if_dirty();
+                    Ok(())
+                }
+                Some((steamid, friends)) = self.friends_recv.recv() => {
+                    self.friends_fetched(steamid, friends).await
+                }
+                _ = friends_recheck_timer.tick() => {
+                    match self.friends_rechecks.pop_front() {
+                        Some(steamid) if self.api_key_valid && !pause::paused() => {
+                            self.fetch_friends(vec![steamid]);
+                        }
+                        _ => {}
+                    }
+                    Ok(())
+                }
+                _ = ban_recheck_timer.tick() => {
+                    if pause::paused() {
+                        Ok(())
+                    } else {
+                        match self.ban_rechecks.pop_front() {
+                            Some(steamids) => self.check_bans(steamids).await,
+                            None => Ok(()),
+                        }
+                    }
+                }
+            };
+
+            if sent.is_err() {
+                tracing::warn!("Lost connection to main thread, shutting down Steam API manager.");
+                break;
             }
         }
+
+        self.steam_info_cache.save_if_dirty();
     }
 
-    async fn send_batch(&mut self) {
-        match request_steam_info(&mut self.client, self.batch_buffer.drain(..).collect()).await {
-            Ok(steam_info_map) => {
-                for response in steam_info_map {
+    async fn handle_request(
+        &mut self,
+        request: SteamAPIMessage,
+    ) -> Result<(), SendError<SteamAPIResponse>> {
+        match request {
+            SteamAPIMessage::SetAPIKey(key) => {
+                self.set_api_key(key);
+                self.check_api_key(true).await?;
+            }
+            SteamAPIMessage::SetExtraAPIKeys(keys) => {
+                self.set_extra_api_keys(keys);
+            }
+            SteamAPIMessage::SetConnection(connection) => {
+                self.set_connection(&connection);
+            }
+            SteamAPIMessage::Lookup(steamid, priority) => {
+                if let Some(steam_info) = self.steam_info_cache.get(steamid) {
+                    tracing::debug!("Using cached Steam profile for {}", steamid);
+                    let mut steam_info = steam_info.clone();
+                    steam_info.assess_risk();
                     self.response_send
-                        .send(SteamAPIResponse::SteamInfo(response))
-                        .expect("Lost connection to main thread.");
+                        .send(SteamAPIResponse::SteamInfo((steamid, steam_info)))?;
+                    return Ok(());
+                }
+
+                // Sent with the next batch, which lets connected players jump ahead of any
+                // background refreshes that were requested first
+                if self.can_lookup() {
+                    self.queue(steamid, priority);
+                } else {
+                    self.response_send.send(SteamAPIResponse::LookupFailed(
+                        steamid,
+                        SteamAPIError::InvalidKey,
+                    ))?;
                 }
             }
+            SteamAPIMessage::Refresh(steamid) => {
+                self.refresh(steamid).await?;
+            }
+            SteamAPIMessage::CheckFriends(steamids) => {
+                self.check_friends(steamids, false).await?;
+            }
+            SteamAPIMessage::ForceCheckFriends(steamids) => {
+                self.check_friends(steamids, true).await?;
+            }
+            SteamAPIMessage::SetFriendsCacheTTL(ttl) => {
+                self.steam_info_cache.set_friends_ttl(ttl);
+            }
+            SteamAPIMessage::SetSteamInfoCacheTTL(ttl) => {
+                self.steam_info_cache.set_ttl(ttl);
+            }
+            SteamAPIMessage::SetPrivateRecheckBudget(budget) => {
+                self.set_private_recheck_budget(budget);
+            }
+            SteamAPIMessage::SetProfileScraping(enabled) => {
+                self.profile_scraping = enabled;
+            }
+            SteamAPIMessage::Configure(batching) => {
+                self.batching = batching.clamped();
+                self.batch_size = self.batching.size;
+                self.batches_since_limited = 0;
+            }
+            SteamAPIMessage::RecheckBans(due) => {
+                tracing::debug!("Rechecking bans of {} marked accounts", due.len());
+                self.ban_rechecks = due
+                    .chunks(BAN_CHECK_SIZE)
+                    .take(BAN_RECHECK_REQUESTS)
+                    .map(<[SteamID]>::to_vec)
+                    .collect();
+            }
+            SteamAPIMessage::ResolveVanityUrl(vanity) => {
+                if self.api_key_valid {
+                    let resolved = match self.rate_limits.check(Endpoint::Vanity) {
+                        Ok(()) => {
+                            let backend = self.backend.as_ref();
+                            request_vanity_url(backend, &self.current_key, &vanity).await
+                        }
+                        Err(e) => Err(e.into()),
+                    };
+                    self.rate_limits.record(Endpoint::Vanity, &resolved);
+                    self.response_send
+                        .send(SteamAPIResponse::ResolvedVanity((vanity, resolved)))?;
+                }
+            }
+            SteamAPIMessage::CheckGroups(steamid) => {
+                // Groups are only shown on community profiles, so these don't need an API key
+                let groups =
+                    scrape::request_groups(&self.http, &mut self.rate_limits, steamid).await;
+                self.response_send
+                    .send(SteamAPIResponse::Groups((steamid, groups)))?;
+            }
+            SteamAPIMessage::CancelPending(steamids) => {
+                self.cancel_pending(steamids.map(HashSet::from_iter));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Look up a player now rather than with the next batch, ignoring anything cached about them
+    async fn refresh(&mut self, steamid: SteamID) -> Result<(), SendError<SteamAPIResponse>> {
+        if !self.can_lookup() {
+            return Ok(());
+        }
+
+        tracing::debug!("Refreshing {}", steamid);
+        if self.pending.remove(&steamid) {
+            self.batch_buffer.retain(|s| *s != steamid);
+            self.priority_buffer.retain(|s| *s != steamid);
+        }
+        match self.lookup(vec![steamid]).await {
+            Ok(steam_infos) => self.send_steam_infos(steam_infos).await?,
             Err(e) => {
+                tracing::warn!("Failed to refresh {}: {:?}", steamid, e);
+                self.response_send.send(SteamAPIResponse::LookupFailed(
+                    steamid,
+                    SteamAPIError::from_error(&e),
+                ))?;
+            }
+        }
+
+        self.check_friends(vec![steamid], true).await
+    }
+
+    /// Get the friend lists of `steamids`. Cached lists are answered with straight away unless
+    /// `force` is set, and the rest are fetched in the background.
+    async fn check_friends(
+        &mut self,
+        steamids: Vec<SteamID>,
+        force: bool,
+    ) -> Result<(), SendError<SteamAPIResponse>> {
+        if !self.api_key_valid {
+            return Ok(());
+        }
+        if force {
+            self.fetch_friends(steamids);
+            return Ok(());
+        }
+
+        let mut fetch = Vec::new();
+        for id in steamids {
+            let cached = match self.steam_info_cache.get_friends(id) {
+                Some(CachedFriendList::Fresh(friends)) => {
+                    tracing::debug!("Using cached friend list for {}", id);
+                    Ok(friends.to_vec())
+                }
+                Some(CachedFriendList::Stale(friends)) => {
+                    tracing::debug!("Using stale friend list for {} until it is rechecked", id);
+                    if !self.friends_rechecks.contains(&id) {
+                        self.friends_rechecks.push_back(id);
+                    }
+                    Ok(friends.to_vec())
+                }
+                Some(CachedFriendList::Private {
+                    recheck_due: true, ..
+                }) if self.private_rechecks < self.private_recheck_budget => {
+                    self.private_rechecks += 1;
+                    metrics::count_private_recheck(true);
+                    tracing::debug!(
+                        "Checking if friend list of {} is still private ({} of {} rechecks)",
+                        id,
+                        self.private_rechecks,
+                        self.private_recheck_budget
+                    );
+                    fetch.push(id);
+                    continue;
+                }
+                Some(CachedFriendList::Private {
+                    checked,
+                    recheck_due,
+                }) => {
+                    if recheck_due {
+                        metrics::count_private_recheck(false);
+                    }
+                    Err(anyhow!(
+                        "Friend list of {} was private when it was last checked, {} minutes ago.",
+                        id,
+                        now().saturating_sub(checked) / 60
+                    ))
+                }
+                // Friends are only listed on public profiles, so there's no point asking
+                None if self
+                    .steam_info_cache
+                    .get(id)
+                    .is_some_and(|info| info.profile_visibility != ProfileVisibility::Public) =>
+                {
+                    Err(anyhow!("Profile of {} is not public.", id))
+                }
+                None => {
+                    fetch.push(id);
+                    continue;
+                }
+            };
+            self.response_send
+                .send(SteamAPIResponse::FriendLists((id, cached)))?;
+        }
+
+        self.fetch_friends(fetch);
+        Ok(())
+    }
+
+    /// Fetch friend lists from the API, [FRIENDS_CONCURRENCY] at a time. This happens in its own
+    /// task so lookups carry on in the meantime, and each list comes back through `friends_recv`
+    /// as it arrives.
+    fn fetch_friends(&mut self, steamids: Vec<SteamID>) {
+        self.friends_rechecks.retain(|s| !steamids.contains(s));
+        if steamids.is_empty() {
+            return;
+        }
+
+        let backend = self.backend.clone();
+        // Taken now, as lookups may move on to other keys while these are being fetched
+        let api_key = self.current_key.clone();
+        let friends_send = self.friends_send.clone();
+        tokio::task::spawn(async move {
+            let mut fetches = futures::stream::iter(steamids)
+                .map(|steamid| {
+                    let backend = backend.clone();
+                    let api_key = api_key.clone();
+                    async move { (steamid, backend.friend_list(&api_key, steamid).await) }
+                })
+                .buffer_unordered(FRIENDS_CONCURRENCY);
+            while let Some(fetched) = fetches.next().await {
+                if friends_send.send(fetched).is_err() {
+                    break;
+                }
+            }
+        });
+    }
+
+    /// Remember a fetched friend list (or that it was private) for next time, and pass it on
+    async fn friends_fetched(
+        &mut self,
+        steamid: SteamID,
+        friends: Result<Vec<Friend>>,
+    ) -> Result<(), SendError<SteamAPIResponse>> {
+        match &friends {
+            Ok(friends) => self
+                .steam_info_cache
+                .insert_friends(steamid, Some(friends.clone())),
+            Err(e) if is_private_friend_list(e) => {
+                self.steam_info_cache.insert_friends(steamid, None);
+            }
+            Err(e) => tracing::debug!("Could not fetch friend list of {}: {:?}", steamid, e),
+        }
+        self.response_send
+            .send(SteamAPIResponse::FriendLists((steamid, friends)))
+    }
+
+    /// Get the current VAC and game bans of up to [BAN_CHECK_SIZE] players and pass them on
+    async fn check_bans(
+        &mut self,
+        steamids: Vec<SteamID>,
+    ) -> Result<(), SendError<SteamAPIResponse>> {
+        if !self.api_key_valid || steamids.is_empty() {
+            return Ok(());
+        }
+
+        let bans = match self.rate_limits.check(Endpoint::Bans) {
+            Ok(()) => self.backend.player_bans(&self.current_key, &steamids).await,
+            Err(e) => Err(e.into()),
+        };
+        self.rate_limits.record(Endpoint::Bans, &bans);
+        match bans {
+            Ok(bans) => {
+                let bans = bans
+                    .into_iter()
+                    .filter_map(|ban| {
+                        Some((
+                            ban.steam_id.parse().ok()?,
+                            ban.number_of_vac_bans,
+                            ban.number_of_game_bans,
+                        ))
+                    })
+                    .collect();
+                self.response_send.send(SteamAPIResponse::Bans(bans))
+            }
+            Err(e) => {
+                tracing::warn!("Could not recheck bans: {:?}", e);
+                Ok(())
+            }
+        }
+    }
+
+    /// Add a player to the buffers, unless their lookup is already pending or they are waiting to
+    /// be retried. A high priority lookup moves a player that was already queued to the front.
+    fn queue(&mut self, steamid: SteamID, priority: LookupPriority) {
+        let queued = self.pending.contains(&steamid);
+        match priority {
+            LookupPriority::High if !self.priority_buffer.contains(&steamid) => {
+                if queued {
+                    self.batch_buffer.retain(|s| *s != steamid);
+                }
+                self.priority_buffer.push_back(steamid);
+                self.pending.insert(steamid);
+            }
+            LookupPriority::Normal if !queued && !self.retries.is_waiting(steamid) => {
+                self.batch_buffer.push_back(steamid);
+                self.pending.insert(steamid);
+            }
+            _ => tracing::trace!("Lookup of {} is already pending", steamid),
+        }
+    }
+
+    /// Drop the pending lookups, retries and friend list rechecks of `steamids`, or of everyone if
+    /// `None`
+    fn cancel_pending(&mut self, steamids: Option<HashSet<SteamID>>) {
+        let cancelled = |steamid: &SteamID| steamids.as_ref().is_none_or(|s| s.contains(steamid));
+        let pending = self.pending.len();
+        self.batch_buffer.retain(|s| !cancelled(s));
+        self.priority_buffer.retain(|s| !cancelled(s));
+        self.pending.retain(|s| !cancelled(s));
+        self.friends_rechecks.retain(|s| !cancelled(s));
+        let retries = self.retries.cancel(cancelled);
+        tracing::debug!(
+            "Cancelled {} pending lookups and {} retries",
+            pending - self.pending.len(),
+            retries
+        );
+    }
+
+    /// Look players up through the API, or by scraping their community profiles if there is no
+    /// valid API key. Each lookup uses the next API key in turn, moving on to the one after if
+    /// Steam rejects or rate limits it.
+    async fn lookup(
+        &mut self,
+        steamids: Vec<SteamID>,
+    ) -> Result<Vec<(SteamID, Result<SteamInfo>)>> {
+        if !self.api_key_valid {
+            return scrape::request_steam_info(&self.http, &mut self.rate_limits, steamids).await;
+        }
+
+        loop {
+            let i = self.keys.next_usable()?;
+            let key = self.keys.key(i).clone();
+            self.current_key = key.clone();
+            let backend = self.backend.as_ref();
+            let result =
+                request_steam_info(backend, &key, self.keys.limits(i), steamids.clone()).await;
+            // A key that was rate limited can't come up again until its limit has passed, so
+            // this runs out of keys to try eventually
+            match result.as_ref().err().and_then(retry::status) {
+                Some(StatusCode::FORBIDDEN) => self.keys.reject(i),
+                Some(StatusCode::TOO_MANY_REQUESTS) => {}
+                _ => return result,
+            }
+            if self.keys.len() == 1 {
+                return result;
+            }
+            tracing::info!("Retrying lookup with the next SteamAPI key");
+        }
+    }
+
+    /// Players in the next batch: the batch size, or [SCRAPE_BATCH_SIZE] when scraping
+    fn next_batch_size(&self) -> usize {
+        if self.api_key_valid {
+            self.batch_size
+        } else {
+            SCRAPE_BATCH_SIZE
+        }
+    }
+
+    /// Whether there are enough lookups waiting to fill a batch through the API. Scraped profiles
+    /// are always left to the timer, so they stay spaced out.
+    fn batch_full(&self) -> bool {
+        self.api_key_valid
+            && self.priority_buffer.len() + self.batch_buffer.len() >= self.next_batch_size()
+    }
+
+    /// Take up to [SteamAPIManager::next_batch_size] players from the buffers for the next batch.
+    /// High priority lookups go first, then any players that are currently connected, then the
+    /// rest.
+    fn next_batch(&mut self) -> Vec<SteamID> {
+        if let Some(roster) = &self.roster {
+            let connected = roster();
+            let (mut live, rest): (VecDeque<_>, VecDeque<_>) = self
+                .batch_buffer
+                .drain(..)
+                .partition(|steamid| connected.contains(steamid));
+            live.extend(rest);
+            self.batch_buffer = live;
+        }
+
+        let size = self.next_batch_size();
+        let mut batch: Vec<SteamID> = self
+            .priority_buffer
+            .drain(..self.priority_buffer.len().min(size))
+            .collect();
+        let len = self.batch_buffer.len().min(size - batch.len());
+        batch.extend(self.batch_buffer.drain(..len));
+        for steamid in &batch {
+            self.pending.remove(steamid);
+        }
+        batch
+    }
+
+    /// Pass on the results of looking up players, caching any that were found. Players that
+    /// weren't found aren't tried again, as they are most likely deleted or invalid accounts,
+    /// while those that failed some other way are retried like a failed batch.
+    async fn send_steam_infos(
+        &mut self,
+        steam_infos: Vec<(SteamID, Result<SteamInfo>)>,
+    ) -> Result<(), SendError<SteamAPIResponse>> {
+        for (steamid, steam_info) in steam_infos {
+            let response = match steam_info {
+                Ok(mut steam_info) => {
+                    self.retries.finished(steamid);
+                    steam_info.assess_risk();
+                    self.steam_info_cache.insert(steamid, steam_info.clone());
+                    SteamAPIResponse::SteamInfo((steamid, steam_info))
+                }
+                Err(e) => {
+                    tracing::warn!("Could not get player info for {}: {:?}", steamid, e);
+                    let error = SteamAPIError::from_error(&e);
+                    match e.downcast_ref::<RateLimited>() {
+                        Some(RateLimited(_, wait)) => self.retries.postpone(vec![steamid], *wait),
+                        None if matches!(error, SteamAPIError::Network) => {
+                            self.retries.failed(vec![steamid]);
+                        }
+                        None => self.retries.finished(steamid),
+                    }
+                    SteamAPIResponse::LookupFailed(steamid, error)
+                }
+            };
+            self.response_send.send(response)?;
+        }
+        Ok(())
+    }
+
+    /// Send smaller batches while Steam is rate limiting
+    fn batch_rate_limited(&mut self) {
+        self.batches_since_limited = 0;
+        if self.batch_size > 1 {
+            self.batch_size /= 2;
+            tracing::info!(
+                "Rate limited by Steam, reducing lookup batches to {} players",
+                self.batch_size
+            );
+        }
+    }
+
+    /// Work back up to the configured batch size once Steam has stopped rate limiting
+    fn batch_succeeded(&mut self) {
+        self.batches_since_limited += 1;
+        if self.batches_since_limited >= BATCH_RECOVERY && self.batch_size < self.batching.size {
+            self.batch_size = (self.batch_size * 2).min(self.batching.size);
+            self.batches_since_limited = 0;
+            tracing::info!("Raising lookup batches to {} players", self.batch_size);
+        }
+    }
+
+    /// Look up the next batch of players. If that fails in a way that might not happen again,
+    /// they are tried again later rather than being lost. Either way, every player in the batch
+    /// is reported as having failed.
+    async fn send_batch(&mut self) -> Result<(), SendError<SteamAPIResponse>> {
+        let batch = self.next_batch();
+        let e = match self.lookup(batch.clone()).await {
+            Ok(steam_infos) => {
+                self.batch_succeeded();
+                return self.send_steam_infos(steam_infos).await;
+            }
+            Err(e) => e,
+        };
+
+        let error = SteamAPIError::from_error(&e);
+        if error == SteamAPIError::RateLimited {
+            self.batch_rate_limited();
+        }
+        for steamid in &batch {
+            self.response_send
+                .send(SteamAPIResponse::LookupFailed(*steamid, error))?;
+        }
+        match e.downcast_ref::<RateLimited>() {
+            Some(RateLimited(_, wait)) => self.retries.postpone(batch, *wait),
+            None if retry::is_retryable(&e) => {
+                tracing::warn!("Failed to get player info from SteamAPI, retrying: {:?}", e);
+                self.retries.failed(batch);
+            }
+            None => {
                 tracing::error!("Failed to get player info from SteamAPI: {:?}", e);
+                for steamid in batch {
+                    self.retries.finished(steamid);
+                }
             }
         }
+
+        Ok(())
     }
 }
 
-/// Make a request to the Steam web API for the chosen player and return the important steam info.
+/// Make a request to the Steam web API for the chosen players and return the important steam info.
+/// Only fails as a whole if the requests themselves do, otherwise each player gets their own
+/// result so one missing account doesn't hold up the rest.
 async fn request_steam_info(
-    client: &mut SteamAPI,
+    backend: &dyn SteamApiBackend,
+    api_key: &str,
+    limits: &mut RateLimits,
     playerids: Vec<SteamID>,
-) -> Result<Vec<(SteamID, SteamInfo)>> {
+) -> Result<Vec<(SteamID, Result<SteamInfo>)>> {
     tracing::debug!("Requesting steam accounts: {:?}", playerids);
 
-    let summaries = request_player_summary(client, &playerids).await?;
-    let bans = request_account_bans(client, &playerids).await?;
+    limits.check(Endpoint::Summaries)?;
+    let summaries = backend.player_summaries(api_key, &playerids).await;
+    limits.record(Endpoint::Summaries, &summaries);
+    let summaries = summaries?;
+    limits.check(Endpoint::Bans)?;
+    let bans = backend.player_bans(api_key, &playerids).await;
+    limits.record(Endpoint::Bans, &bans);
+    let bans = bans?;
 
-    let id_to_summary: HashMap<_, _> = summaries
+    let id_to_summary: HashMap<SteamID, _> = summaries
         .into_iter()
-        .map(|summary| (summary.steamid.clone(), summary))
+        .filter_map(|summary| Some((summary.steamid.parse().ok()?, summary)))
         .collect();
-    let id_to_ban: HashMap<_, _> = bans
+    let id_to_ban: HashMap<SteamID, _> = bans
         .into_iter()
-        .map(|ban| (ban.steam_id.clone(), ban))
+        .filter_map(|ban| Some((ban.steam_id.parse().ok()?, ban)))
         .collect();
 
-    let steam_infos = playerids
+    let mut steam_infos: Vec<(SteamID, Result<SteamInfo>)> = playerids
         .into_iter()
         .map(|player| {
-            let id = format!("{}", u64::from(player));
-            let summary = id_to_summary
-                .get(&id)
-                .ok_or(anyhow!("Missing summary for player {}", id))?;
-            let ban = id_to_ban
-                .get(&id)
-                .ok_or(anyhow!("Missing ban info for player {}", id))?;
+            let (Some(summary), Some(ban)) = (id_to_summary.get(&player), id_to_ban.get(&player))
+            else {
+                // Deleted and invalid accounts are left out of the response
+                let missing = if id_to_summary.contains_key(&player) {
+                    "ban info"
+                } else {
+                    "summary"
+                };
+                let e = anyhow::Error::new(SteamAPIError::NotFound)
+                    .context(format!("Missing {} for player {}", missing, player));
+                return (player, Err(e));
+            };
             let steam_info = SteamInfo {
                 account_name: summary.personaname.clone().into(),
                 pfp_url: summary.avatarfull.clone().into(),
@@ -191,102 +1095,196 @@ async fn request_steam_info(
                 } else {
                     None
                 },
+                community_banned: Some(ban.community_banned),
+                economy_ban: EconomyBan::from_steam(&ban.economy_ban),
+                has_background: None,
+                showcases: None,
+                games_visible: None,
+                tf2_hours: None,
+                steam_level: None,
+                risk: None,
             };
-            Ok((player, steam_info))
+            (player, Ok(steam_info))
         })
-        .collect::<Result<_>>()?;
+        .collect();
 
+    request_profile_details(backend, api_key, limits, &mut steam_infos).await;
     Ok(steam_infos)
 }
 
-async fn request_player_summary(
-    client: &mut SteamAPI,
-    players: &[SteamID],
-) -> Result<Vec<PlayerSummary>> {
-    let summaries = client
-        .get()
-        .ISteamUser()
-        .GetPlayerSummaries(
-            players
-                .iter()
-                .map(|player| format!("{}", u64::from(*player)))
-                .collect(),
-        )
-        .execute()
-        .await
-        .context("Failed to get player summary from SteamAPI.")?;
-    let summaries = serde_json::from_str::<GetPlayerSummariesResponseBase>(&summaries)
-        .with_context(|| {
-            format!(
-                "Failed to parse player summary from SteamAPI: {}",
-                &summaries
-            )
-        })?;
-    Ok(summaries.response.players)
-}
-
-pub async fn request_account_friends(
-    client: &mut SteamAPI,
-    player: SteamID,
-) -> Result<Vec<Friend>> {
-    let friends = client
-        .get()
-        .ISteamUser()
-        .GetFriendList(player.into(), "all".to_string())
-        .execute()
-        .await
-        .context("Failed to get account friends from SteamAPI, profile may be private.")?;
-    let friends =
-        serde_json::from_str::<GetFriendListResponseBase>(&friends).with_context(|| {
-            format!(
-                "Failed to parse account friends from SteamAPI: {}",
-                &friends
-            )
-        })?;
-    Ok(friends
-        .friendslist
-        .map(|fl| fl.friends)
-        .unwrap_or(Vec::new())
+/// Fill in the profile customization, game, playtime and level fields of each public profile in
+/// `steam_infos`, leaving any that can't be checked as `None`. The requests for all of them are
+/// made [PROFILE_CONCURRENCY] at a time, so a batch doesn't wait on each one in turn.
+async fn request_profile_details(
+    backend: &dyn SteamApiBackend,
+    api_key: &str,
+    limits: &mut RateLimits,
+    steam_infos: &mut [(SteamID, Result<SteamInfo>)],
+) {
+    // Private profiles don't show any of this, so don't spend requests finding that out
+    let public: Vec<SteamID> = steam_infos
         .iter()
-        .filter_map(|f| match f.steamid.parse::<u64>() {
-            Err(_) => None,
-            Ok(id) => Some(Friend {
-                steamid: SteamID::from(id),
-                friend_since: f.friend_since,
-            }),
+        .filter(|(_, steam_info)| {
+            steam_info
+                .as_ref()
+                .is_ok_and(|s| s.profile_visibility == ProfileVisibility::Public)
+        })
+        .map(|(player, _)| *player)
+        .collect();
+    if public.is_empty() {
+        return;
+    }
+
+    // Every request is checked against the limits before it is sent, so the rest of the batch
+    // stops as soon as Steam starts rate limiting. The lock is never held across a request.
+    let limits = std::sync::Mutex::new(limits);
+    let limits = &limits;
+    // Made up front, as the compiler can't tell a stream mapped with a closure is `Send`
+    let requests: Vec<_> = public
+        .into_iter()
+        .flat_map(|player| {
+            PROFILE_SERVICES
+                .iter()
+                .map(move |&(method, params)| async move {
+                    let allowed = limits.lock().unwrap().check(Endpoint::PlayerService);
+                    let response = match allowed {
+                        Ok(()) => {
+                            backend
+                                .player_service(api_key, method, player, params)
+                                .await
+                        }
+                        Err(e) => Err(e.into()),
+                    };
+                    limits
+                        .lock()
+                        .unwrap()
+                        .record(Endpoint::PlayerService, &response);
+                    (player, method, response)
+                })
         })
-        .collect())
+        .collect();
+    let responses: Vec<_> = futures::stream::iter(requests)
+        .buffer_unordered(PROFILE_CONCURRENCY)
+        .collect()
+        .await;
+
+    for (player, method, response) in responses {
+        let Some((_, Ok(steam_info))) = steam_infos.iter_mut().find(|(p, _)| *p == player) else {
+            continue;
+        };
+        match response {
+            Ok(response) => apply_player_service(steam_info, method, &response),
+            Err(e) => tracing::debug!("Could not call {} for {}: {:?}", method, player, e),
+        }
+    }
 }
 
-async fn request_account_bans(
-    client: &mut SteamAPI,
-    players: &[SteamID],
-) -> Result<Vec<PlayerBans>> {
-    let bans = client
-        .get()
-        .ISteamUser()
-        .GetPlayerBans(
-            players
+/// Fill in the fields of `steam_info` that come from the response to the IPlayerService `method`
+fn apply_player_service(
+    steam_info: &mut SteamInfo,
+    method: &str,
+    response: &serde_json::Map<String, serde_json::Value>,
+) {
+    match method {
+        "GetProfileItemsEquipped" => {
+            steam_info.has_background = response
+                .get("profile_background")
+                .map(|background| background.get("communityitemid").is_some());
+        }
+        // An empty response means the customizations couldn't be seen
+        "GetProfileCustomization" if !response.is_empty() => {
+            steam_info.showcases = Some(
+                response
+                    .get("customizations")
+                    .and_then(|c| c.as_array())
+                    .map_or(0, |c| c.len() as u32),
+            );
+        }
+        "GetOwnedGames" => {
+            let Some(games) = response.get("games").and_then(|games| games.as_array()) else {
+                return;
+            };
+            let is_tf2 = |game: &serde_json::Value| {
+                game.get("appid").and_then(|id| id.as_u64()) == Some(TF2_GAME_ID.into())
+            };
+            steam_info.games_visible =
+                Some(games.iter().filter(|game| !is_tf2(game)).count() as u32);
+            // Playtime is in minutes
+            steam_info.tf2_hours = games
                 .iter()
-                .map(|player| format!("{}", u64::from(*player)))
-                .collect(),
-        )
-        .execute()
-        .await
-        .context("Failed to get player bans from SteamAPI")?;
-    let bans = serde_json::from_str::<GetPlayerBansResponseBase>(&bans)
-        .with_context(|| format!("Failed to parse player bans from SteamAPI: {}", &bans))?;
-    Ok(bans.players)
+                .find(|game| is_tf2(game))
+                .and_then(|tf2| tf2.get("playtime_forever"))
+                .and_then(|minutes| minutes.as_u64())
+                .map(|minutes| (minutes / 60) as u32);
+        }
+        "GetSteamLevel" => {
+            steam_info.steam_level = response
+                .get("player_level")
+                .and_then(|level| level.as_u64())
+                .map(|level| level as u32);
+        }
+        _ => {}
+    }
+}
+
+/// Whether a friend list couldn't be fetched because it is private, rather than e.g. a dropped
+/// connection. Steam answers with 401 Unauthorized, or occasionally with a body that isn't a friend
+/// list at all.
+fn is_private_friend_list(e: &anyhow::Error) -> bool {
+    matches!(SteamAPIError::from_error(e), SteamAPIError::PrivateProfile)
+        || e.chain().any(|e| e.is::<SchemaDrift>())
+}
+
+/// The name in a custom profile URL, which may have been given as the whole link
+fn vanity_name(vanity: &str) -> &str {
+    let vanity = vanity.trim().trim_end_matches('/');
+    vanity.rsplit_once("/id/").map_or(vanity, |(_, name)| name)
+}
+
+/// Get the account with the custom profile URL `vanity`
+async fn request_vanity_url(
+    backend: &dyn SteamApiBackend,
+    api_key: &str,
+    vanity: &str,
+) -> Result<SteamID> {
+    let name = vanity_name(vanity);
+    if name.is_empty() {
+        return Err(anyhow!("No profile name in \"{}\"", vanity));
+    }
+    backend.resolve_vanity_url(api_key, name).await
+}
+
+/// Whether Steam accepts `api_key`, found by asking the Web API at `api_url` for an empty list of
+/// profiles. Fails if the API couldn't be reached to find out.
+pub async fn verify_api_key(http: &reqwest::Client, api_url: &str, api_key: &str) -> Result<bool> {
+    let response = http
+        .get(format!("{}/{}", api_url, PLAYER_SUMMARIES_PATH))
+        .query(&[("key", api_key), ("steamids", "")])
+        .timeout(Duration::from_secs(10))
+        .send()
+        .await;
+    // The body isn't read, only whether there was an answer
+    metrics::count("GetPlayerSummaries", response.as_ref().ok().map(|_| 0));
+    let response = response.context("Failed to reach the SteamAPI.")?;
+    match response.status() {
+        StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => Ok(false),
+        _ => {
+            response
+                .error_for_status()
+                .context("SteamAPI failed to check the API key.")?;
+            Ok(true)
+        }
+    }
 }
 
-fn is_api_key_valid(api_key: &Arc<str>) -> bool {
+pub fn is_api_key_valid(api_key: &Arc<str>) -> bool {
     // A valid steam API key is a 32 digit hexadecimal number. We store them as strings, so
     // we check for exactly 32 hexadecimal ascii digits. Anything that doesn't fit this rule
     // is likely not a valid Steam API key (inb4 Valve changes the format on my ass)
-    return api_key.len() == 32
+    api_key.len() == 32
         && api_key
             .chars()
             .map(|c| c.is_ascii_hexdigit())
             .reduce(|acc, e| acc && e)
-            .unwrap();
+            .unwrap()
 }
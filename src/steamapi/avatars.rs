@@ -0,0 +1,119 @@
+//! Downloading player avatars once and keeping them on disk. Steam names avatars after the hash of
+//! the image, so one that has been downloaded never changes and can be served to the frontend
+//! (with [SteamInfo](crate::player::SteamInfo)'s `pfp_hash`) as often as it likes.
+
+use std::{
+    path::PathBuf,
+    time::{Duration, SystemTime},
+};
+
+use thiserror::Error;
+
+use crate::settings::{ConfigFilesError, Settings};
+
+const AVATAR_URL: &str = "https://avatars.steamstatic.com";
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+/// Avatars that haven't been downloaded or served for this long are deleted at startup
+const MAX_AGE: Duration = Duration::from_secs(30 * 24 * 60 * 60);
+
+#[derive(Debug, Error)]
+pub enum AvatarError {
+    #[error("Not an avatar hash.")]
+    InvalidHash,
+    #[error("Failed to download avatar: {0}")]
+    Download(#[from] reqwest::Error),
+    #[error("Failed to cache avatar: {0}")]
+    IO(#[from] std::io::Error),
+}
+
+/// Avatars downloaded from Steam, by hash
+#[derive(Debug, Clone)]
+pub struct AvatarCache {
+    dir: PathBuf,
+    http: reqwest::Client,
+}
+
+impl AvatarCache {
+    /// Use the default cache directory, dropping any avatars that haven't been used in a while
+    pub fn new() -> AvatarCache {
+        let dir = Self::locate_cache_dir()
+            .map_err(|e| tracing::warn!("Failed to find avatar cache location: {:?}", e))
+            .unwrap_or("avatars".into());
+        let cache = AvatarCache {
+            dir,
+            http: reqwest::Client::new(),
+        };
+        cache.prune();
+        cache
+    }
+
+    pub fn locate_cache_dir() -> Result<PathBuf, ConfigFilesError> {
+        let dir = Settings::locate_config_directory()?.join("avatars");
+        std::fs::create_dir_all(&dir)
+            .map_err(|e| ConfigFilesError::IO(dir.to_string_lossy().into(), e))?;
+        Ok(dir)
+    }
+
+    /// The full size avatar with `hash`, from the cache if it has been downloaded before
+    pub async fn get(&self, hash: &str) -> Result<Vec<u8>, AvatarError> {
+        // The hash ends up in a path, so it had better be one
+        if hash.len() != 40 || !hash.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(AvatarError::InvalidHash);
+        }
+        let hash = hash.to_ascii_lowercase();
+        let path = self.dir.join(format!("{}.jpg", hash));
+
+        if let Ok(image) = tokio::fs::read(&path).await {
+            // Keep it from being pruned while it is still being used
+            if let Ok(file) = std::fs::File::options().append(true).open(&path) {
+                let _ = file.set_modified(SystemTime::now());
+            }
+            return Ok(image);
+        }
+
+        tracing::debug!("Downloading avatar {}", hash);
+        let image = self
+            .http
+            .get(format!("{}/{}_full.jpg", AVATAR_URL, hash))
+            .timeout(REQUEST_TIMEOUT)
+            .send()
+            .await
+            .and_then(reqwest::Response::error_for_status)?
+            .bytes()
+            .await?
+            .to_vec();
+
+        // Written elsewhere first so a partly written avatar is never served
+        let tmp_path = path.with_extension("tmp");
+        tokio::fs::write(&tmp_path, &image).await?;
+        tokio::fs::rename(&tmp_path, &path).await?;
+        Ok(image)
+    }
+
+    fn prune(&self) {
+        let Ok(entries) = std::fs::read_dir(&self.dir) else {
+            return;
+        };
+        let mut removed = 0;
+        for entry in entries.flatten() {
+            let old = entry
+                .metadata()
+                .and_then(|m| m.modified())
+                .ok()
+                .and_then(|modified| modified.elapsed().ok())
+                .is_some_and(|age| age > MAX_AGE);
+            if old && std::fs::remove_file(entry.path()).is_ok() {
+                removed += 1;
+            }
+        }
+        if removed > 0 {
+            tracing::debug!("Removed {} unused avatars from the cache", removed);
+        }
+    }
+}
+
+impl Default for AvatarCache {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,248 @@
+//! Everything the [SteamAPIManager](super::SteamAPIManager) asks of the Steam Web API, so it can
+//! be pointed at something other than Steam. [WebApiBackend] makes the real requests, while tests
+//! can provide their own [SteamApiBackend] with `SteamAPIManager::set_backend` in builds with the
+//! `fault-injection` feature.
+//!
+//! Scraping community profiles isn't part of the Web API, see [super::scrape].
+
+use std::sync::{Arc, RwLock};
+
+use anyhow::{anyhow, Context, Result};
+use async_trait::async_trait;
+use serde::Deserialize;
+use tappet::response_types::{
+    GetFriendListResponseBase, GetPlayerBansResponseBase, GetPlayerSummariesResponseBase,
+    PlayerBans, PlayerSummary,
+};
+use tokio::time::Duration;
+
+use super::{connection::DEFAULT_API_URL, metrics, schema};
+#[cfg(any(test, feature = "fault-injection"))]
+use crate::faults;
+use crate::{player::Friend, steamid::SteamID};
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+/// Endpoints, relative to the base URL of the Web API
+pub const PLAYER_SUMMARIES_PATH: &str = "ISteamUser/GetPlayerSummaries/v2/";
+const PLAYER_BANS_PATH: &str = "ISteamUser/GetPlayerBans/v1/";
+const FRIEND_LIST_PATH: &str = "ISteamUser/GetFriendList/v1/";
+const VANITY_URL_PATH: &str = "ISteamUser/ResolveVanityURL/v1/";
+const PLAYER_SERVICE_PATH: &str = "IPlayerService";
+
+/// Requests to the Steam Web API. Each is made with the API key it is given, as lookups take turns
+/// with several keys while other requests may be in flight. Each fails as a whole, with a
+/// [reqwest::Error] in the chain if Steam answered with an error status.
+#[async_trait]
+pub trait SteamApiBackend: Send + Sync {
+    /// Make every request from now on with `http` (e.g. one that goes through a proxy), to the
+    /// Web API at `api_url`, see [SteamConnection](super::connection::SteamConnection)
+    fn set_connection(&self, _http: reqwest::Client, _api_url: Arc<str>) {}
+    /// Whether Steam accepts `api_key`. Fails if Steam couldn't be reached to find out.
+    async fn verify_api_key(&self, api_key: &str) -> Result<bool>;
+    /// Summaries of up to 100 players. Accounts that don't exist are left out.
+    async fn player_summaries(
+        &self,
+        api_key: &str,
+        players: &[SteamID],
+    ) -> Result<Vec<PlayerSummary>>;
+    /// Bans of up to 100 players. Accounts that don't exist are left out.
+    async fn player_bans(&self, api_key: &str, players: &[SteamID]) -> Result<Vec<PlayerBans>>;
+    /// The friend list of `player`, which fails if their profile is private
+    async fn friend_list(&self, api_key: &str, player: SteamID) -> Result<Vec<Friend>>;
+    /// The account with the custom profile URL `name`
+    async fn resolve_vanity_url(&self, api_key: &str, name: &str) -> Result<SteamID>;
+    /// The `response` object of an IPlayerService method for `player`. Steam returns an empty
+    /// object if the profile doesn't show the information.
+    async fn player_service(
+        &self,
+        api_key: &str,
+        method: &str,
+        player: SteamID,
+        params: &[(&str, &str)],
+    ) -> Result<serde_json::Map<String, serde_json::Value>>;
+}
+
+/// The real Steam Web API. Requests are made with the client from the
+/// [SteamConnection](super::connection::SteamConnection), so they go through its proxy and to its
+/// base URL, and the responses are read into tappet's types.
+pub struct WebApiBackend {
+    http: RwLock<reqwest::Client>,
+    api_url: RwLock<Arc<str>>,
+}
+
+impl WebApiBackend {
+    pub fn new(http: reqwest::Client) -> WebApiBackend {
+        WebApiBackend {
+            http: RwLock::new(http),
+            api_url: RwLock::new(DEFAULT_API_URL.into()),
+        }
+    }
+
+    fn http(&self) -> reqwest::Client {
+        self.http.read().unwrap().clone()
+    }
+
+    /// A request to `path` on the Web API, with `api_key` and `query`
+    fn get(&self, api_key: &str, path: &str, query: &[(&str, &str)]) -> reqwest::RequestBuilder {
+        let url = format!("{}/{}", self.api_url.read().unwrap(), path);
+        self.http()
+            .get(url)
+            .query(&[("key", api_key)])
+            .query(query)
+            .timeout(REQUEST_TIMEOUT)
+    }
+}
+
+/// Players as Steam takes them in a list
+fn steamid_list(players: &[SteamID]) -> String {
+    players
+        .iter()
+        .map(|player| player.to_string())
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+/// Send a request, and get the body of the response if Steam didn't answer with an error status
+async fn text(request: reqwest::RequestBuilder) -> reqwest::Result<String> {
+    request.send().await?.error_for_status()?.text().await
+}
+
+#[derive(Debug, Deserialize)]
+struct ResolveVanityURLResponseBase {
+    response: ResolveVanityURLResponse,
+}
+
+#[derive(Debug, Deserialize)]
+struct ResolveVanityURLResponse {
+    /// 1 if the URL was found
+    success: i32,
+    steamid: Option<String>,
+    message: Option<String>,
+}
+
+#[async_trait]
+impl SteamApiBackend for WebApiBackend {
+    fn set_connection(&self, http: reqwest::Client, api_url: Arc<str>) {
+        *self.http.write().unwrap() = http;
+        *self.api_url.write().unwrap() = api_url;
+    }
+
+    async fn verify_api_key(&self, api_key: &str) -> Result<bool> {
+        let api_url = self.api_url.read().unwrap().clone();
+        super::verify_api_key(&self.http(), &api_url, api_key).await
+    }
+
+    async fn player_summaries(
+        &self,
+        api_key: &str,
+        players: &[SteamID],
+    ) -> Result<Vec<PlayerSummary>> {
+        let request = self.get(
+            api_key,
+            PLAYER_SUMMARIES_PATH,
+            &[("steamids", &steamid_list(players))],
+        );
+        let summaries = text(request).await;
+        let summaries = metrics::record("GetPlayerSummaries", summaries)
+            .context("Failed to get player summary from SteamAPI.")?;
+        #[cfg(any(test, feature = "fault-injection"))]
+        let summaries = faults::steam_api("GetPlayerSummaries", summaries).await?;
+        let summaries =
+            schema::parse::<GetPlayerSummariesResponseBase>("GetPlayerSummaries", &summaries)
+                .context("Failed to parse player summary from SteamAPI.")?;
+        Ok(summaries.response.players)
+    }
+
+    async fn player_bans(&self, api_key: &str, players: &[SteamID]) -> Result<Vec<PlayerBans>> {
+        let request = self.get(
+            api_key,
+            PLAYER_BANS_PATH,
+            &[("steamids", &steamid_list(players))],
+        );
+        let bans = text(request).await;
+        let bans = metrics::record("GetPlayerBans", bans)
+            .context("Failed to get player bans from SteamAPI")?;
+        #[cfg(any(test, feature = "fault-injection"))]
+        let bans = faults::steam_api("GetPlayerBans", bans).await?;
+        let bans = schema::parse::<GetPlayerBansResponseBase>("GetPlayerBans", &bans)
+            .context("Failed to parse player bans from SteamAPI.")?;
+        Ok(bans.players)
+    }
+
+    async fn friend_list(&self, api_key: &str, player: SteamID) -> Result<Vec<Friend>> {
+        let steamid = player.to_string();
+        let request = self.get(
+            api_key,
+            FRIEND_LIST_PATH,
+            &[("steamid", &steamid), ("relationship", "all")],
+        );
+        let friends = metrics::record("GetFriendList", text(request).await)
+            .context("Failed to get account friends from SteamAPI, profile may be private.")?;
+        #[cfg(any(test, feature = "fault-injection"))]
+        let friends = faults::steam_api("GetFriendList", friends).await?;
+        let friends = schema::parse::<GetFriendListResponseBase>("GetFriendList", &friends)
+            .context("Failed to parse account friends from SteamAPI.")?;
+        Ok(friends
+            .friendslist
+            .map(|fl| fl.friends)
+            .unwrap_or(Vec::new())
+            .iter()
+            .filter_map(|f| match f.steamid.parse::<SteamID>() {
+                Err(_) => None,
+                Ok(steamid) => Some(Friend {
+                    steamid,
+                    friend_since: f.friend_since,
+                }),
+            })
+            .collect())
+    }
+
+    async fn resolve_vanity_url(&self, api_key: &str, name: &str) -> Result<SteamID> {
+        let request = self.get(api_key, VANITY_URL_PATH, &[("vanityurl", name)]);
+        let resolved = text(request).await;
+        let resolved = metrics::record("ResolveVanityURL", resolved)
+            .context("Failed to resolve vanity URL from SteamAPI.")?;
+        #[cfg(any(test, feature = "fault-injection"))]
+        let resolved = faults::steam_api("ResolveVanityURL", resolved).await?;
+        let resolved = schema::parse::<ResolveVanityURLResponseBase>("ResolveVanityURL", &resolved)
+            .context("Failed to parse vanity URL from SteamAPI.")?
+            .response;
+
+        match resolved.steamid {
+            Some(steamid) if resolved.success == 1 => steamid.parse().with_context(|| {
+                format!("SteamAPI resolved {} to invalid SteamID {}", name, steamid)
+            }),
+            _ => Err(anyhow!(
+                "No profile found for {}: {}",
+                name,
+                resolved.message.as_deref().unwrap_or("no message")
+            )),
+        }
+    }
+
+    async fn player_service(
+        &self,
+        api_key: &str,
+        method: &str,
+        player: SteamID,
+        params: &[(&str, &str)],
+    ) -> Result<serde_json::Map<String, serde_json::Value>> {
+        let steamid = player.to_string();
+        let mut query = vec![("steamid", steamid.as_str())];
+        query.extend_from_slice(params);
+
+        let path = format!("{}/{}/v1/", PLAYER_SERVICE_PATH, method);
+        let request = self.get(api_key, &path, &query);
+        let body = metrics::record(method, text(request).await)
+            .with_context(|| format!("Failed to get {} from SteamAPI.", method))?;
+        #[cfg(any(test, feature = "fault-injection"))]
+        let body = faults::steam_api(method, body).await?;
+        let mut body: serde_json::Value = schema::parse(method, &body)
+            .with_context(|| format!("Failed to parse {} from SteamAPI.", method))?;
+
+        match body.get_mut("response").map(serde_json::Value::take) {
+            Some(serde_json::Value::Object(response)) => Ok(response),
+            _ => Err(anyhow!("{} from SteamAPI had no response", method)),
+        }
+    }
+}
@@ -0,0 +1,373 @@
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+    time::Duration,
+};
+
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use crate::{
+    analytics::history::now,
+    player::{Friend, SteamInfo},
+    settings::ConfigFilesError,
+    steamid::SteamID,
+    storage::{RowChanges, Storage, STEAM_CACHE, STEAM_FRIENDS, STEAM_PROFILES},
+};
+
+/// How long a friend list that couldn't be seen is left before trying again. Private lists are
+/// rarely made public, and asking for them costs as much as for any other. Lists that have been
+/// private for longer are left for as long as they have been private, up to
+/// [MAX_PRIVATE_FRIENDS_COOLDOWN].
+const PRIVATE_FRIENDS_COOLDOWN: u64 = 6 * 60 * 60;
+const MAX_PRIVATE_FRIENDS_COOLDOWN: u64 = 7 * 24 * 60 * 60;
+/// How long it is remembered that a friend list was private after it was last checked, so lists
+/// that stay private keep being checked less and less often across sessions
+const PRIVATE_FRIENDS_KEPT: u64 = 30 * 24 * 60 * 60;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct CachedSteamInfo {
+    /// Unix timestamp (seconds) of when it was fetched
+    fetched: u64,
+    info: SteamInfo,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct CachedFriends {
+    /// Unix timestamp (seconds) of when it was fetched, or when it was last checked if it was
+    /// private
+    fetched: u64,
+    /// [None] if the list was private
+    friends: Option<Vec<Friend>>,
+    /// Unix timestamp (seconds) of when the list was first found to be private, if it still is
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    private_since: Option<u64>,
+}
+
+impl CachedFriends {
+    /// Whether this should be dropped, as it is too old to even be used while it is rechecked
+    fn expired(&self, ttl: Duration) -> bool {
+        let kept_for = match self.friends {
+            Some(_) => 2 * ttl.as_secs(),
+            None => PRIVATE_FRIENDS_KEPT,
+        };
+        ttl.is_zero() || self.fetched + kept_for <= now()
+    }
+
+    /// How long to wait after the last check of a private list before checking it again
+    fn private_cooldown(&self) -> u64 {
+        let private_for = self
+            .private_since
+            .map_or(0, |since| self.fetched.saturating_sub(since));
+        private_for.clamp(PRIVATE_FRIENDS_COOLDOWN, MAX_PRIVATE_FRIENDS_COOLDOWN)
+    }
+}
+
+/// Cached entries of one kind, each kept in its own row of the table `name`, and which of them
+/// have changed since they were last saved
+#[derive(Debug)]
+struct Table<T> {
+    name: &'static str,
+    entries: HashMap<SteamID, T>,
+    changed: HashSet<SteamID>,
+    removed: HashSet<SteamID>,
+}
+
+impl<T: Serialize + DeserializeOwned> Table<T> {
+    fn new(name: &'static str) -> Table<T> {
+        Table {
+            name,
+            entries: HashMap::new(),
+            changed: HashSet::new(),
+            removed: HashSet::new(),
+        }
+    }
+
+    /// Load the table from `storage`, [None] if it was never saved there. Rows that can't be
+    /// read are left out.
+    fn load(
+        name: &'static str,
+        storage: &dyn Storage,
+    ) -> Result<Option<Table<T>>, ConfigFilesError> {
+        let Some(rows) = storage.read_table(name)? else {
+            return Ok(None);
+        };
+        let mut table = Table::new(name);
+        for (steamid, row) in rows {
+            match serde_json::from_str(&row) {
+                Ok(entry) => {
+                    table.entries.insert(steamid, entry);
+                }
+                Err(e) => tracing::warn!("Dropping cached {} of {}: {}", name, steamid, e),
+            }
+        }
+        Ok(Some(table))
+    }
+
+    fn dirty(&self) -> bool {
+        !self.changed.is_empty() || !self.removed.is_empty()
+    }
+
+    fn insert(&mut self, steamid: SteamID, entry: T) {
+        self.entries.insert(steamid, entry);
+        self.removed.remove(&steamid);
+        self.changed.insert(steamid);
+    }
+
+    fn retain(&mut self, mut keep: impl FnMut(&T) -> bool) {
+        let Table {
+            entries,
+            changed,
+            removed,
+            ..
+        } = self;
+        entries.retain(|steamid, entry| {
+            let kept = keep(entry);
+            if !kept {
+                changed.remove(steamid);
+                removed.insert(*steamid);
+            }
+            kept
+        });
+    }
+
+    /// Write the entries that have changed since the table was last saved
+    fn save(&mut self, storage: &dyn Storage) -> Result<(), ConfigFilesError> {
+        if !self.dirty() {
+            return Ok(());
+        }
+        let changed = self
+            .changed
+            .iter()
+            .filter_map(|steamid| Some((*steamid, self.entries.get(steamid)?)))
+            .map(|(steamid, entry)| Ok((steamid, serde_json::to_string(entry)?)))
+            .collect::<Result<_, serde_json::Error>>()
+            .map_err(|e| ConfigFilesError::Json(self.name.into(), e))?;
+        storage.write_table(
+            self.name,
+            RowChanges {
+                changed,
+                removed: self.removed.iter().copied().collect(),
+            },
+        )?;
+        self.changed.clear();
+        self.removed.clear();
+        Ok(())
+    }
+}
+
+/// How the whole cache was laid out in the [STEAM_CACHE] document
+#[derive(Deserialize)]
+struct CacheDocument {
+    players: HashMap<SteamID, CachedSteamInfo>,
+    #[serde(default)]
+    friends: HashMap<SteamID, CachedFriends>,
+}
+
+/// A friend list from the cache
+#[derive(Debug, Clone, Copy)]
+pub enum CachedFriendList<'a> {
+    /// Fetched recently enough to be used as it is
+    Fresh(&'a [Friend]),
+    /// Older than the TTL, but still good enough to use until it has been fetched again
+    Stale(&'a [Friend]),
+    /// Was private when it was last asked for
+    Private {
+        /// Unix timestamp (seconds) of when it was last asked for
+        checked: u64,
+        /// Whether it was long enough ago that it's worth asking again. Until then it is assumed
+        /// to still be private.
+        recheck_due: bool,
+    },
+}
+
+/// Steam profiles, bans and public friend lists fetched in previous sessions, kept on disk so
+/// players who are seen often don't have to be looked up every time. Friend lists rarely change,
+/// so are usually kept for longer than profiles. Each is a row of its own, and only the ones
+/// that have changed are written. Anything not yet saved is saved when the cache is dropped.
+#[derive(Debug)]
+pub struct SteamInfoCache {
+    storage: Option<Arc<dyn Storage>>,
+    /// How long a fetched profile is used for before it is fetched again, not cached at all if 0
+    ttl: Duration,
+    /// The same as `ttl`, for friend lists
+    friends_ttl: Duration,
+    players: Table<CachedSteamInfo>,
+    friends: Table<CachedFriends>,
+}
+
+impl SteamInfoCache {
+    /// Attempts to load the cache from `storage`, starting empty if it doesn't exist or can't be
+    /// read.
+    pub fn load_or_create(
+        storage: Arc<dyn Storage>,
+        ttl: Duration,
+        friends_ttl: Duration,
+    ) -> SteamInfoCache {
+        let mut cache = match Self::load_from(storage.clone()) {
+            Ok(Some(cache)) => cache,
+            Ok(None) => SteamInfoCache::new(storage),
+            Err(e) => {
+                tracing::warn!("Could not load Steam API cache, starting empty: {:?}", e);
+                SteamInfoCache::new(storage)
+            }
+        };
+        cache.set_ttl(ttl);
+        cache.set_friends_ttl(friends_ttl);
+        cache
+    }
+
+    fn new(storage: Arc<dyn Storage>) -> SteamInfoCache {
+        SteamInfoCache {
+            storage: Some(storage),
+            ttl: Duration::ZERO,
+            friends_ttl: Duration::ZERO,
+            players: Table::new(STEAM_PROFILES),
+            friends: Table::new(STEAM_FRIENDS),
+        }
+    }
+
+    /// Attempt to load the [SteamInfoCache] from `storage`, [None] if it was never saved there.
+    /// A cache saved as a single document by earlier versions is moved into the tables.
+    pub fn load_from(
+        storage: Arc<dyn Storage>,
+    ) -> Result<Option<SteamInfoCache>, ConfigFilesError> {
+        let players = Table::load(STEAM_PROFILES, storage.as_ref())?;
+        let friends = Table::load(STEAM_FRIENDS, storage.as_ref())?;
+        if players.is_some() || friends.is_some() {
+            let mut cache = SteamInfoCache::new(storage);
+            if let Some(players) = players {
+                cache.players = players;
+            }
+            if let Some(friends) = friends {
+                cache.friends = friends;
+            }
+            return Ok(Some(cache));
+        }
+
+        let Some(contents) = storage.read_document(STEAM_CACHE)? else {
+            return Ok(None);
+        };
+        let document: CacheDocument = serde_json::from_str(&contents)
+            .map_err(|e| ConfigFilesError::Json(STEAM_CACHE.into(), e))?;
+        let mut cache = SteamInfoCache::new(storage);
+        for (steamid, cached) in document.players {
+            cache.players.insert(steamid, cached);
+        }
+        for (steamid, cached) in document.friends {
+            cache.friends.insert(steamid, cached);
+        }
+        Ok(Some(cache))
+    }
+
+    /// Change how long fetched profiles are used for, dropping any that are now too old
+    pub fn set_ttl(&mut self, ttl: Duration) {
+        self.ttl = ttl;
+        let oldest = now().saturating_sub(ttl.as_secs());
+        self.players
+            .retain(|cached| ttl > Duration::ZERO && cached.fetched > oldest);
+    }
+
+    /// Change how long fetched friend lists are used for, dropping any that are now too old.
+    /// Lists are kept, stale, for as long again after that.
+    pub fn set_friends_ttl(&mut self, ttl: Duration) {
+        self.friends_ttl = ttl;
+        self.friends.retain(|cached| !cached.expired(ttl));
+    }
+
+    /// The cached profile of `steamid`, if it was fetched recently enough to still be used
+    pub fn get(&self, steamid: SteamID) -> Option<&SteamInfo> {
+        let oldest = now().saturating_sub(self.ttl.as_secs());
+        self.players
+            .entries
+            .get(&steamid)
+            .filter(|cached| cached.fetched > oldest)
+            .map(|cached| &cached.info)
+    }
+
+    /// Remember a freshly fetched profile
+    pub fn insert(&mut self, steamid: SteamID, info: SteamInfo) {
+        if self.ttl.is_zero() {
+            return;
+        }
+        self.players.insert(
+            steamid,
+            CachedSteamInfo {
+                fetched: now(),
+                info,
+            },
+        );
+    }
+
+    /// The cached friend list of `steamid`, if it was fetched recently enough to still be used
+    pub fn get_friends(&self, steamid: SteamID) -> Option<CachedFriendList<'_>> {
+        let cached = self
+            .friends
+            .entries
+            .get(&steamid)
+            .filter(|cached| !cached.expired(self.friends_ttl))?;
+        Some(match &cached.friends {
+            None => CachedFriendList::Private {
+                checked: cached.fetched,
+                recheck_due: cached.fetched + cached.private_cooldown() <= now(),
+            },
+            Some(friends) if cached.fetched + self.friends_ttl.as_secs() > now() => {
+                CachedFriendList::Fresh(friends)
+            }
+            Some(friends) => CachedFriendList::Stale(friends),
+        })
+    }
+
+    /// Remember a freshly fetched friend list, [None] if it was private
+    pub fn insert_friends(&mut self, steamid: SteamID, friends: Option<Vec<Friend>>) {
+        if self.friends_ttl.is_zero() {
+            return;
+        }
+        let now = now();
+        let private_since = match friends {
+            Some(_) => None,
+            None => self
+                .friends
+                .entries
+                .get(&steamid)
+                .and_then(|cached| cached.private_since)
+                .or(Some(now)),
+        };
+        self.friends.insert(
+            steamid,
+            CachedFriends {
+                fetched: now,
+                friends,
+                private_since,
+            },
+        );
+    }
+
+    /// Attempt to save the cache to where it was loaded from, if anything has been fetched since
+    /// it was last saved. Anything too old to be used is dropped first.
+    pub fn save_if_dirty(&mut self) {
+        if !self.players.dirty() && !self.friends.dirty() {
+            return;
+        }
+        self.set_ttl(self.ttl);
+        self.set_friends_ttl(self.friends_ttl);
+
+        let Some(storage) = &self.storage else {
+            return;
+        };
+        let saved = self
+            .players
+            .save(storage.as_ref())
+            .and_then(|()| self.friends.save(storage.as_ref()));
+        if let Err(e) = saved {
+            tracing::error!("Failed to save Steam API cache: {:?}", e);
+        }
+    }
+}
+
+impl Drop for SteamInfoCache {
+    fn drop(&mut self) {
+        self.save_if_dirty();
+    }
+}
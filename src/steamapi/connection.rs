@@ -0,0 +1,49 @@
+//! How requests get to Steam, for users who can only reach the internet through a proxy or who
+//! want Web API requests to go through something else first, e.g. a caching proxy shared by
+//! several clients.
+
+use std::sync::Arc;
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+/// Where Web API requests go unless another URL is configured
+pub const DEFAULT_API_URL: &str = "https://api.steampowered.com";
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct SteamConnection {
+    /// Proxy every request to Steam goes through, including scraped profiles, e.g.
+    /// `http://proxy.example.com:3128` or `socks5://localhost:1080`
+    pub proxy: Option<Arc<str>>,
+    /// Used in place of [DEFAULT_API_URL], e.g. `http://localhost:8080/steam`
+    pub api_url: Option<Arc<str>>,
+}
+
+impl SteamConnection {
+    /// The base URL of the Web API, without a trailing `/`
+    pub fn api_url(&self) -> Arc<str> {
+        match self
+            .api_url
+            .as_deref()
+            .map(|url| url.trim().trim_end_matches('/'))
+        {
+            Some(url) if !url.is_empty() => url.into(),
+            _ => DEFAULT_API_URL.into(),
+        }
+    }
+
+    /// A client for making requests through the proxy, if there is one. Fails if the proxy isn't
+    /// a valid URL.
+    pub fn client(&self) -> Result<reqwest::Client> {
+        let mut builder = reqwest::Client::builder();
+        if let Some(proxy) = self.proxy.as_deref().map(str::trim) {
+            if !proxy.is_empty() {
+                let proxy = reqwest::Proxy::all(proxy)
+                    .with_context(|| format!("Invalid proxy {:?}", proxy))?;
+                builder = builder.proxy(proxy);
+            }
+        }
+        builder.build().context("Failed to set up HTTP client")
+    }
+}
@@ -0,0 +1,116 @@
+//! Several Steam Web API keys taken in turn, so hosts looking up players for many users aren't
+//! held to the quota and rate limits of a single key. Each key keeps its own [RateLimits], and
+//! keys Steam rejects are left out for a while instead of failing every batch sent with them.
+
+use std::{sync::Arc, time::Duration};
+
+use tokio::time::Instant;
+
+use super::{
+    is_api_key_valid,
+    retry::{Endpoint, RateLimited, RateLimits},
+};
+
+/// How long a key Steam rejected is left out before it is tried again
+const REJECTED_KEY_WAIT: Duration = Duration::from_secs(60 * 60);
+
+#[derive(Debug)]
+struct RingKey {
+    key: Arc<str>,
+    limits: RateLimits,
+    /// When the key may be used again after Steam rejected it
+    rejected_until: Option<Instant>,
+}
+
+impl RingKey {
+    /// How long until the key can be used for a lookup, or [None] if it can be now
+    fn wait(&self) -> Option<Duration> {
+        let now = Instant::now();
+        let rejected = self
+            .rejected_until
+            .filter(|until| *until > now)
+            .map(|until| until - now);
+        let limited = [Endpoint::Summaries, Endpoint::Bans]
+            .into_iter()
+            .filter_map(|endpoint| self.limits.check(endpoint).err())
+            .map(|RateLimited(_, wait)| wait);
+        rejected.into_iter().chain(limited).max()
+    }
+}
+
+/// API keys that lookups rotate through, see the [module docs](self)
+#[derive(Debug, Default)]
+pub struct KeyRing {
+    keys: Vec<RingKey>,
+    /// Where to start looking for the next key to use
+    next: usize,
+}
+
+impl KeyRing {
+    /// A ring of `keys`, leaving out any that don't look like API keys and any repeats
+    pub fn new(keys: impl IntoIterator<Item = Arc<str>>) -> KeyRing {
+        let mut ring = KeyRing::default();
+        for key in keys {
+            if is_api_key_valid(&key) && !ring.keys.iter().any(|k| k.key == key) {
+                ring.keys.push(RingKey {
+                    key,
+                    limits: RateLimits::default(),
+                    rejected_until: None,
+                });
+            }
+        }
+        ring
+    }
+
+    pub fn len(&self) -> usize {
+        self.keys.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+
+    /// Take `key` out of the ring for good, e.g. once it has been found not to work
+    pub fn remove(&mut self, key: &str) {
+        self.keys.retain(|k| &*k.key != key);
+    }
+
+    /// The index of the next key in turn that can be used for a lookup. If none can, fails with
+    /// how long until the first of them can.
+    pub fn next_usable(&mut self) -> Result<usize, RateLimited> {
+        let len = self.keys.len();
+        let mut soonest: Option<Duration> = None;
+        for offset in 0..len {
+            let i = (self.next + offset) % len;
+            match self.keys[i].wait() {
+                None => {
+                    self.next = (i + 1) % len;
+                    return Ok(i);
+                }
+                Some(wait) => soonest = Some(soonest.map_or(wait, |s| s.min(wait))),
+            }
+        }
+        Err(RateLimited(
+            Endpoint::Summaries,
+            soonest.unwrap_or(REJECTED_KEY_WAIT),
+        ))
+    }
+
+    pub fn key(&self, i: usize) -> &Arc<str> {
+        &self.keys[i].key
+    }
+
+    /// The rate limits Steam has put on the key at `i`
+    pub fn limits(&mut self, i: usize) -> &mut RateLimits {
+        &mut self.keys[i].limits
+    }
+
+    /// Steam rejected the key at `i`, so leave it out for a while
+    pub fn reject(&mut self, i: usize) {
+        tracing::warn!(
+            "Steam rejected an API key, not using it for another {:?}",
+            REJECTED_KEY_WAIT
+        );
+        self.keys[i].rejected_until = Some(Instant::now() + REJECTED_KEY_WAIT);
+    }
+}
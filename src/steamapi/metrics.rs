@@ -0,0 +1,178 @@
+//! Counts of the requests made to the Steam Web API, so users can see how close they are to the
+//! daily limit on their API key before Steam starts refusing requests. Only requests that count
+//! towards the limit are counted, not scraped community profiles or avatars.
+
+use std::{collections::BTreeMap, sync::Mutex};
+
+use serde::Serialize;
+
+use crate::{
+    analytics::history::now,
+    events::{self, SystemEventLevel},
+    locale::tr,
+};
+
+/// Requests Steam allows each API key per day
+pub const DAILY_QUOTA: u64 = 100_000;
+/// Share of the daily quota after which the user is warned
+const QUOTA_WARNING: f32 = 0.8;
+const SECONDS_PER_DAY: u64 = 24 * 60 * 60;
+
+/// Requests made to a single endpoint since the client started
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EndpointStats {
+    pub requests: u64,
+    /// Requests that couldn't be made or that Steam answered with an error
+    pub failures: u64,
+    /// Size of the responses to the requests that succeeded
+    pub bytes: u64,
+}
+
+/// Every request made to the Steam Web API, and how much of today's quota they used up
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SteamAPIStats {
+    /// Keyed by endpoint, e.g. `GetPlayerSummaries`
+    pub endpoints: BTreeMap<String, EndpointStats>,
+    pub total: EndpointStats,
+    /// Requests made since midnight UTC
+    pub requests_today: u64,
+    pub daily_quota: u64,
+    /// `requests_today` out of `daily_quota`
+    pub quota_used: f32,
+    /// Requests there will have been by midnight UTC if they keep being made at the rate they have
+    /// been since the client started (or since midnight, if that was later)
+    pub projected_today: u64,
+    /// Friend lists that were private asked for again this session, to see if they still are
+    pub private_rechecks: u64,
+    /// Rechecks of private friend lists that were due but weren't made, as the session's budget
+    /// for them had been used up
+    pub private_rechecks_skipped: u64,
+}
+
+struct Metrics {
+    endpoints: BTreeMap<String, EndpointStats>,
+    /// Days since the epoch that `requests_today` is for
+    day: u64,
+    requests_today: u64,
+    /// Unix timestamp (seconds) counting started, to work out the rate requests are made at
+    since: Option<u64>,
+    /// Whether the user has been warned about today's quota yet
+    warned: bool,
+    private_rechecks: u64,
+    private_rechecks_skipped: u64,
+}
+
+static METRICS: Mutex<Metrics> = Mutex::new(Metrics {
+    endpoints: BTreeMap::new(),
+    day: 0,
+    requests_today: 0,
+    since: None,
+    warned: false,
+    private_rechecks: 0,
+    private_rechecks_skipped: 0,
+});
+
+impl Metrics {
+    /// Start counting a new day if it's past midnight
+    fn roll_over(&mut self, now: u64) {
+        let day = now / SECONDS_PER_DAY;
+        if day != self.day {
+            self.day = day;
+            self.requests_today = 0;
+            self.warned = false;
+        }
+    }
+}
+
+/// Count a request to `endpoint`, with the body of its response if it succeeded, and pass the
+/// response on
+pub fn record<T, E>(endpoint: &str, response: Result<T, E>) -> Result<T, E>
+where
+    T: AsRef<str>,
+{
+    count(
+        endpoint,
+        response
+            .as_ref()
+            .ok()
+            .map(|body| body.as_ref().len() as u64),
+    );
+    response
+}
+
+/// Count a request to `endpoint`, with the size of its response if it succeeded or [None] if it
+/// failed
+pub fn count(endpoint: &str, bytes: Option<u64>) {
+    let now = now();
+    let mut metrics = METRICS.lock().unwrap();
+    metrics.roll_over(now);
+    metrics.since.get_or_insert(now);
+    metrics.requests_today += 1;
+
+    let stats = metrics.endpoints.entry(endpoint.to_string()).or_default();
+    stats.requests += 1;
+    match bytes {
+        Some(bytes) => stats.bytes += bytes,
+        None => stats.failures += 1,
+    }
+
+    let used = metrics.requests_today as f32 / DAILY_QUOTA as f32;
+    if used >= QUOTA_WARNING && !metrics.warned {
+        metrics.warned = true;
+        let requests = metrics.requests_today.to_string();
+        let quota = DAILY_QUOTA.to_string();
+        drop(metrics);
+        tracing::warn!("{} of {} Steam API requests used today", requests, quota);
+        events::emit_system(
+            SystemEventLevel::Warning,
+            tr(
+                "steam-api-quota",
+                &[("requests", &requests), ("quota", &quota)],
+            ),
+        );
+    }
+}
+
+/// Count a recheck of a private friend list that was due, whether it was `made` or skipped
+pub fn count_private_recheck(made: bool) {
+    let mut metrics = METRICS.lock().unwrap();
+    if made {
+        metrics.private_rechecks += 1;
+    } else {
+        metrics.private_rechecks_skipped += 1;
+    }
+}
+
+/// Requests made so far, see [SteamAPIStats]
+pub fn stats() -> SteamAPIStats {
+    let now = now();
+    let mut metrics = METRICS.lock().unwrap();
+    metrics.roll_over(now);
+
+    let mut total = EndpointStats::default();
+    for stats in metrics.endpoints.values() {
+        total.requests += stats.requests;
+        total.failures += stats.failures;
+        total.bytes += stats.bytes;
+    }
+
+    let midnight = metrics.day * SECONDS_PER_DAY;
+    let start = metrics.since.unwrap_or(now).max(midnight);
+    // At least a minute, so a handful of requests at startup don't project to millions
+    let elapsed = now.saturating_sub(start).max(60);
+    let remaining = (midnight + SECONDS_PER_DAY).saturating_sub(now);
+    let projected_today = metrics.requests_today + metrics.requests_today * remaining / elapsed;
+
+    SteamAPIStats {
+        endpoints: metrics.endpoints.clone(),
+        total,
+        requests_today: metrics.requests_today,
+        daily_quota: DAILY_QUOTA,
+        quota_used: metrics.requests_today as f32 / DAILY_QUOTA as f32,
+        projected_today,
+        private_rechecks: metrics.private_rechecks,
+        private_rechecks_skipped: metrics.private_rechecks_skipped,
+    }
+}
@@ -0,0 +1,165 @@
+use std::{collections::HashMap, time::Duration};
+
+use reqwest::StatusCode;
+use thiserror::Error;
+use tokio::time::Instant;
+
+use crate::steamid::SteamID;
+
+/// Most times a lookup is attempted before it is given up on
+const MAX_ATTEMPTS: u32 = 5;
+/// Wait before the first retry of a failed lookup, doubled for each one after
+const FIRST_RETRY: Duration = Duration::from_secs(2);
+/// Requests to an endpoint that has started rate limiting wait this long, doubled each time it
+/// rate limits again without a request getting through in between
+const RATE_LIMIT_BACKOFF: Duration = Duration::from_secs(30);
+const MAX_BACKOFF: Duration = Duration::from_secs(10 * 60);
+
+/// Steam Web API endpoints, which Steam rate limits separately
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Endpoint {
+    /// GetPlayerSummaries
+    Summaries,
+    /// GetPlayerBans
+    Bans,
+    /// Every IPlayerService method
+    PlayerService,
+    /// ResolveVanityURL
+    Vanity,
+    /// Community profiles scraped when there is no API key, which aren't part of the Web API but
+    /// are rate limited all the same
+    Community,
+}
+
+/// A request wasn't made because its endpoint is rate limiting
+#[derive(Debug, Error)]
+#[error("{0:?} is rate limited for another {1:?}")]
+pub struct RateLimited(pub Endpoint, pub Duration);
+
+/// `first` doubled for every attempt after the first, up to [MAX_BACKOFF]
+fn backoff(first: Duration, attempt: u32) -> Duration {
+    first
+        .saturating_mul(2u32.saturating_pow(attempt.saturating_sub(1)))
+        .min(MAX_BACKOFF)
+}
+
+/// The HTTP status the Steam Web API failed a request with, if it got that far
+pub fn status(e: &anyhow::Error) -> Option<StatusCode> {
+    e.chain()
+        .find_map(|e| e.downcast_ref::<reqwest::Error>())
+        .and_then(reqwest::Error::status)
+}
+
+/// Whether a failed request could succeed if it were made again later. Anything but an error
+/// response from Steam (e.g. a timeout or dropped connection) is assumed to be temporary.
+pub fn is_retryable(e: &anyhow::Error) -> bool {
+    match status(e) {
+        Some(status) => status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error(),
+        None => true,
+    }
+}
+
+/// Holds off making requests to endpoints that have told us to slow down
+#[derive(Debug, Default)]
+pub struct RateLimits {
+    /// When each rate limited endpoint may be used again, and how many times in a row it has
+    /// rate limited
+    blocked: HashMap<Endpoint, (Instant, u32)>,
+}
+
+impl RateLimits {
+    /// Check a request may be made to `endpoint`
+    pub fn check(&self, endpoint: Endpoint) -> Result<(), RateLimited> {
+        match self.blocked.get(&endpoint) {
+            Some((until, _)) if *until > Instant::now() => {
+                Err(RateLimited(endpoint, *until - Instant::now()))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Note how a request to `endpoint` went
+    pub fn record<T>(&mut self, endpoint: Endpoint, result: &anyhow::Result<T>) {
+        match result {
+            Ok(_) => {
+                self.blocked.remove(&endpoint);
+            }
+            Err(e) if status(e) == Some(StatusCode::TOO_MANY_REQUESTS) => {
+                let (until, strikes) = self.blocked.entry(endpoint).or_insert((Instant::now(), 0));
+                *strikes += 1;
+                let wait = backoff(RATE_LIMIT_BACKOFF, *strikes);
+                *until = Instant::now() + wait;
+                tracing::warn!("Steam rate limited {:?}, waiting {:?}", endpoint, wait);
+            }
+            Err(_) => {}
+        }
+    }
+}
+
+/// Lookups that failed, waiting to be tried again
+#[derive(Debug, Default)]
+pub struct RetryQueue {
+    /// Failed attempts so far for each player being retried
+    attempts: HashMap<SteamID, u32>,
+    /// Players waiting to be looked up again, and when
+    waiting: Vec<(SteamID, Instant)>,
+}
+
+impl RetryQueue {
+    /// Schedule the players in a lookup that failed to be looked up again, giving up on any that
+    /// have failed too many times
+    pub fn failed(&mut self, steamids: Vec<SteamID>) {
+        for steamid in steamids {
+            let attempts = self.attempts.entry(steamid).or_insert(0);
+            *attempts += 1;
+            if *attempts >= MAX_ATTEMPTS {
+                tracing::warn!(
+                    "Giving up looking up {} after {} attempts",
+                    steamid,
+                    attempts
+                );
+                self.attempts.remove(&steamid);
+                continue;
+            }
+            let due = Instant::now() + backoff(FIRST_RETRY, *attempts);
+            self.waiting.push((steamid, due));
+        }
+    }
+
+    /// Schedule players to be looked up once `wait` has passed, without counting it as a failed
+    /// attempt
+    pub fn postpone(&mut self, steamids: Vec<SteamID>, wait: Duration) {
+        let due = Instant::now() + wait;
+        self.waiting
+            .extend(steamids.into_iter().map(|steamid| (steamid, due)));
+    }
+
+    /// A lookup succeeded, or was given up on for good
+    pub fn finished(&mut self, steamid: SteamID) {
+        self.attempts.remove(&steamid);
+        self.waiting.retain(|(s, _)| *s != steamid);
+    }
+
+    /// Stop retrying the players `cancelled` is true for, returning how many were waiting
+    pub fn cancel(&mut self, cancelled: impl Fn(&SteamID) -> bool) -> usize {
+        let waiting = self.waiting.len();
+        self.waiting.retain(|(s, _)| !cancelled(s));
+        self.attempts.retain(|s, _| !cancelled(s));
+        waiting - self.waiting.len()
+    }
+
+    /// Whether the player is waiting to be looked up again
+    pub fn is_waiting(&self, steamid: SteamID) -> bool {
+        self.waiting.iter().any(|(s, _)| *s == steamid)
+    }
+
+    /// Players that are due to be looked up again
+    pub fn take_due(&mut self) -> Vec<SteamID> {
+        let now = Instant::now();
+        let (due, waiting): (Vec<_>, Vec<_>) = std::mem::take(&mut self.waiting)
+            .into_iter()
+            .partition(|(_, due)| *due <= now);
+        self.waiting = waiting;
+        due.into_iter().map(|(steamid, _)| steamid).collect()
+    }
+}
@@ -0,0 +1,210 @@
+//! One score for how likely an account is to be a throwaway (an alt made for cheating or a bot),
+//! combining everything known about its profile. Each part is small on its own, e.g. plenty of
+//! real players keep their profile private, so the score only gets high when several add up.
+//!
+//! The score is worked out again whenever anything it's based on changes, see
+//! [SteamInfo::assess_risk]. How new an account seems ([AccountNewness]) is worked out from the
+//! same factors, so the two never disagree.
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+use crate::player::{EconomyBan, ProfileVisibility, SteamInfo};
+
+/// Highest possible score
+pub const MAX_RISK: u32 = 100;
+/// Scores at or above which an account is [RiskLevel::Elevated] or [RiskLevel::High]
+const ELEVATED_RISK: u32 = 30;
+const HIGH_RISK: u32 = 60;
+/// Bans within this many days count for more than older ones
+const RECENT_BAN_DAYS: i64 = 365;
+/// Accounts created within this many days are [RiskFactor::NewAccount] or
+/// [RiskFactor::YoungAccount]
+pub const NEW_ACCOUNT_DAYS: i64 = 30;
+pub const YOUNG_ACCOUNT_DAYS: i64 = 180;
+/// Steam levels at or below which an account is [RiskFactor::NoLevel] or [RiskFactor::LowLevel]
+pub const NO_LEVEL: u32 = 1;
+pub const LOW_LEVEL: u32 = 4;
+/// Public friend lists at or below these sizes add to how new an account seems
+const FEW_FRIENDS: usize = 2;
+const SOME_FRIENDS: usize = 14;
+/// Points towards an account being new at or above which it is [AccountNewness::Fresh] or
+/// [AccountNewness::Young], see [AccountRisk::newness]
+const FRESH_ACCOUNT_POINTS: u32 = 5;
+const YOUNG_ACCOUNT_POINTS: u32 = 2;
+
+/// Something about an account that adds to its risk
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum RiskFactor {
+    /// Created within the last month
+    NewAccount,
+    /// Created within the last six months
+    YoungAccount,
+    /// The profile is private or friends only
+    HiddenProfile,
+    /// Steam level 0 or 1
+    NoLevel,
+    /// Steam level below 5
+    LowLevel,
+    /// No games besides free ones visible on the profile
+    NoGames,
+    /// A VAC or game ban on record
+    PreviousBan,
+    /// A VAC or game ban within the last year
+    RecentBan,
+    /// Banned from the Steam Community
+    CommunityBan,
+    /// Banned from trading, or on probation after a trade ban
+    EconomyBan,
+}
+
+impl RiskFactor {
+    /// How much the factor adds to the score
+    fn points(&self) -> u32 {
+        match self {
+            RiskFactor::NewAccount => 30,
+            RiskFactor::YoungAccount => 15,
+            RiskFactor::HiddenProfile => 10,
+            RiskFactor::NoLevel => 20,
+            RiskFactor::LowLevel => 10,
+            RiskFactor::NoGames => 15,
+            RiskFactor::PreviousBan => 10,
+            RiskFactor::RecentBan => 25,
+            RiskFactor::CommunityBan => 10,
+            RiskFactor::EconomyBan => 10,
+        }
+    }
+}
+
+/// Roughly how worrying a score is, for frontends to badge accounts with
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum RiskLevel {
+    Low,
+    Elevated,
+    High,
+}
+
+/// How likely an account is to be a throwaway, and why
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AccountRisk {
+    /// Between 0 and [MAX_RISK]
+    pub score: u32,
+    pub level: RiskLevel,
+    /// Days since the account was created, if the profile shows it
+    pub age_days: Option<i64>,
+    /// Everything that added to the score
+    pub factors: Vec<RiskFactor>,
+}
+
+impl AccountRisk {
+    /// Assess an account from its profile. Anything that isn't known (e.g. the level of a
+    /// private profile) is left out rather than counted against it.
+    pub fn assess(info: &SteamInfo) -> AccountRisk {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs() as i64)
+            .unwrap_or_default();
+        let age_days = info
+            .time_created
+            .map(|created| (now - created).max(0) / (24 * 60 * 60));
+
+        let mut factors = Vec::new();
+        match age_days {
+            Some(..=NEW_ACCOUNT_DAYS) => factors.push(RiskFactor::NewAccount),
+            Some(..=YOUNG_ACCOUNT_DAYS) => factors.push(RiskFactor::YoungAccount),
+            _ => {}
+        }
+        if info.profile_visibility != ProfileVisibility::Public {
+            factors.push(RiskFactor::HiddenProfile);
+        }
+        match info.steam_level {
+            Some(..=NO_LEVEL) => factors.push(RiskFactor::NoLevel),
+            Some(..=LOW_LEVEL) => factors.push(RiskFactor::LowLevel),
+            _ => {}
+        }
+        if info.games_visible == Some(0) {
+            factors.push(RiskFactor::NoGames);
+        }
+        if info.vac_bans > 0 || info.game_bans > 0 {
+            let recent = info
+                .days_since_last_ban
+                .is_some_and(|days| days <= RECENT_BAN_DAYS);
+            factors.push(if recent {
+                RiskFactor::RecentBan
+            } else {
+                RiskFactor::PreviousBan
+            });
+        }
+        if info.community_banned == Some(true) {
+            factors.push(RiskFactor::CommunityBan);
+        }
+        if matches!(
+            info.economy_ban,
+            Some(EconomyBan::Banned | EconomyBan::Probation)
+        ) {
+            factors.push(RiskFactor::EconomyBan);
+        }
+
+        let score = factors
+            .iter()
+            .map(RiskFactor::points)
+            .sum::<u32>()
+            .min(MAX_RISK);
+        let level = if score >= HIGH_RISK {
+            RiskLevel::High
+        } else if score >= ELEVATED_RISK {
+            RiskLevel::Elevated
+        } else {
+            RiskLevel::Low
+        };
+
+        AccountRisk {
+            score,
+            level,
+            age_days,
+            factors,
+        }
+    }
+
+    /// How new the account seems, going by the age, level and visibility factors of the
+    /// assessment and `friends` (the size of its friend list, if it is public)
+    pub fn newness(&self, friends: Option<usize>) -> AccountNewness {
+        let points = self
+            .factors
+            .iter()
+            .map(|factor| match factor {
+                RiskFactor::NewAccount => 3,
+                RiskFactor::NoLevel => 2,
+                RiskFactor::YoungAccount | RiskFactor::LowLevel | RiskFactor::HiddenProfile => 1,
+                _ => 0,
+            })
+            .sum::<u32>()
+            + match friends {
+                Some(..=FEW_FRIENDS) => 2,
+                Some(..=SOME_FRIENDS) => 1,
+                _ => 0,
+            };
+
+        if points >= FRESH_ACCOUNT_POINTS {
+            AccountNewness::Fresh
+        } else if points >= YOUNG_ACCOUNT_POINTS {
+            AccountNewness::Young
+        } else {
+            AccountNewness::Established
+        }
+    }
+}
+
+/// How new an account seems, so frontends can badge new accounts without working it out
+/// themselves
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum AccountNewness {
+    /// Made days or weeks ago and barely set up
+    Fresh,
+    Young,
+    Established,
+}
@@ -0,0 +1,203 @@
+//! Noticing when Valve changes the shape of a Web API response. A response that doesn't fit the
+//! types it is read into is reported as a [SchemaDrift], saying which field didn't fit and how,
+//! along with a copy of the response with anything personal taken out, so the change can be
+//! followed up from a user's report without needing to reproduce it.
+
+use std::{
+    collections::{HashSet, VecDeque},
+    sync::{Arc, Mutex},
+};
+
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::{error::Category, Value};
+use thiserror::Error;
+
+use crate::{
+    analytics::history::now,
+    events::{self, SystemEventLevel},
+    locale::tr,
+};
+
+/// Drifts kept for the diagnostics
+const RECENT_DRIFTS: usize = 20;
+/// Elements of each array kept in scrubbed payloads, which is enough to see their shape
+const ARRAY_SAMPLE: usize = 3;
+/// Longest payload kept, in bytes
+const MAX_PAYLOAD: usize = 4096;
+
+/// How a response differed from what was expected
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum DriftKind {
+    /// A field that was always sent is missing
+    MissingField,
+    /// A field has a different type, e.g. a number sent as a string
+    WrongType,
+    /// A field has a value of the right type that isn't allowed, e.g. a negative count
+    InvalidValue,
+    /// A field has a value that wasn't one of the ones it could be before
+    UnknownVariant,
+    /// The response isn't JSON at all, e.g. an HTML error page or a response cut short. This is
+    /// more likely a problem between here and Steam than a change in the API.
+    NotJson,
+    Other,
+}
+
+/// A Web API response that couldn't be read, see the [module docs](self)
+#[derive(Debug, Clone, Serialize, Error)]
+#[serde(rename_all = "camelCase")]
+#[error("Response from {endpoint} doesn't fit at {path} ({kind:?}): {message}")]
+pub struct SchemaDrift {
+    pub endpoint: Arc<str>,
+    /// Where in the response reading it failed, e.g. `response.players[0].timecreated`
+    pub path: String,
+    pub kind: DriftKind,
+    /// What serde had to say about it
+    pub message: String,
+    /// The response, with every string blanked out and arrays cut down to a few elements.
+    /// Anything that isn't JSON is only cut short.
+    pub payload: String,
+    /// Unix timestamp (seconds) of the response
+    pub time: u64,
+}
+
+impl DriftKind {
+    fn classify(e: &serde_json::Error) -> DriftKind {
+        match e.classify() {
+            Category::Syntax | Category::Eof => return DriftKind::NotJson,
+            Category::Io => return DriftKind::Other,
+            Category::Data => {}
+        }
+        let message = e.to_string();
+        if message.starts_with("missing field") {
+            DriftKind::MissingField
+        } else if message.starts_with("invalid type") {
+            DriftKind::WrongType
+        } else if message.starts_with("invalid value") || message.starts_with("invalid length") {
+            DriftKind::InvalidValue
+        } else if message.starts_with("unknown variant") {
+            DriftKind::UnknownVariant
+        } else {
+            DriftKind::Other
+        }
+    }
+}
+
+struct Drifts {
+    recent: VecDeque<SchemaDrift>,
+    /// Endpoints and paths the user has been told about, so each change is only announced once
+    announced: Option<HashSet<(Arc<str>, String)>>,
+}
+
+static DRIFTS: Mutex<Drifts> = Mutex::new(Drifts {
+    recent: VecDeque::new(),
+    announced: None,
+});
+
+/// Read `body` from `endpoint` as a `T`, reporting a [SchemaDrift] if it doesn't fit
+pub fn parse<T: DeserializeOwned>(endpoint: &str, body: &str) -> Result<T, SchemaDrift> {
+    let deserializer = &mut serde_json::Deserializer::from_str(body);
+    serde_path_to_error::deserialize(deserializer).map_err(|e| {
+        let drift = SchemaDrift::new(endpoint, body, e);
+        record(&drift);
+        drift
+    })
+}
+
+impl SchemaDrift {
+    fn new(
+        endpoint: &str,
+        body: &str,
+        e: serde_path_to_error::Error<serde_json::Error>,
+    ) -> SchemaDrift {
+        let kind = DriftKind::classify(e.inner());
+        let message = e.inner().to_string();
+        let mut path = e.path().to_string();
+        // The path only goes as far as the object the field is missing from
+        if kind == DriftKind::MissingField {
+            if let Some(field) = message.split('`').nth(1) {
+                path = if path == "." {
+                    field.to_string()
+                } else {
+                    format!("{}.{}", path, field)
+                };
+            }
+        }
+
+        SchemaDrift {
+            endpoint: endpoint.into(),
+            path,
+            kind,
+            message,
+            payload: scrub(body),
+            time: now(),
+        }
+    }
+}
+
+/// Keep a drift for the diagnostics, telling the user about it if it's new
+fn record(drift: &SchemaDrift) {
+    tracing::error!(
+        endpoint = %drift.endpoint,
+        path = %drift.path,
+        kind = ?drift.kind,
+        payload = %drift.payload,
+        "Steam API response schema drift: {}",
+        drift.message
+    );
+
+    let mut drifts = DRIFTS.lock().unwrap();
+    if drifts.recent.len() >= RECENT_DRIFTS {
+        drifts.recent.pop_front();
+    }
+    drifts.recent.push_back(drift.clone());
+
+    let new = drifts
+        .announced
+        .get_or_insert_with(HashSet::new)
+        .insert((drift.endpoint.clone(), drift.path.clone()));
+    drop(drifts);
+    if new && drift.kind != DriftKind::NotJson {
+        events::emit_system(
+            SystemEventLevel::Warning,
+            tr(
+                "steam-api-schema-drift",
+                &[("endpoint", &drift.endpoint), ("field", &drift.path)],
+            ),
+        );
+    }
+}
+
+/// The latest drifts, oldest first
+pub fn recent() -> Vec<SchemaDrift> {
+    DRIFTS.lock().unwrap().recent.iter().cloned().collect()
+}
+
+/// `body` with anything that could identify a player taken out, see [SchemaDrift::payload]
+fn scrub(body: &str) -> String {
+    let scrubbed = match serde_json::from_str::<Value>(body) {
+        Ok(mut value) => {
+            scrub_value(&mut value);
+            value.to_string()
+        }
+        Err(_) => body.to_string(),
+    };
+    let mut end = scrubbed.len().min(MAX_PAYLOAD);
+    while !scrubbed.is_char_boundary(end) {
+        end -= 1;
+    }
+    scrubbed[..end].to_string()
+}
+
+fn scrub_value(value: &mut Value) {
+    match value {
+        // Names, SteamIDs, profile links, ...
+        Value::String(s) => *s = "…".to_string(),
+        Value::Array(elements) => {
+            elements.truncate(ARRAY_SAMPLE);
+            elements.iter_mut().for_each(scrub_value);
+        }
+        Value::Object(fields) => fields.values_mut().for_each(scrub_value),
+        Value::Null | Value::Bool(_) | Value::Number(_) => {}
+    }
+}
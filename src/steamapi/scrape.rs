@@ -0,0 +1,180 @@
+//! A fallback for when there is no Steam Web API key. Every community profile can be fetched as
+//! XML (`?xml=1`) without a key, which is enough for a reduced [SteamInfo]: the name, avatar,
+//! visibility and, for public profiles, when the account was created. Ban counts aren't shown,
+//! only whether the account has a VAC ban or a trade ban, and none of the profile customization
+//! is.
+//!
+//! Groups are only listed on community profiles, so they are always looked up this way.
+
+use anyhow::{anyhow, Context, Result};
+
+use super::{
+    retry::{Endpoint, RateLimits},
+    SteamAPIError,
+};
+use crate::{
+    groups::SteamGroup,
+    player::{EconomyBan, ProfileVisibility, SteamInfo},
+    steamid::SteamID,
+};
+
+const COMMUNITY_URL: &str = "https://steamcommunity.com";
+const MONTHS: [&str; 12] = [
+    "January",
+    "February",
+    "March",
+    "April",
+    "May",
+    "June",
+    "July",
+    "August",
+    "September",
+    "October",
+    "November",
+    "December",
+];
+
+/// Scrape the community profiles of `playerids`, one request each. Like
+/// [super::request_steam_info], this only fails as a whole if a request does, while a profile
+/// that doesn't exist fails on its own.
+pub async fn request_steam_info(
+    http: &reqwest::Client,
+    limits: &mut RateLimits,
+    playerids: Vec<SteamID>,
+) -> Result<Vec<(SteamID, Result<SteamInfo>)>> {
+    tracing::debug!("Scraping steam profiles: {:?}", playerids);
+
+    let mut steam_infos = Vec::with_capacity(playerids.len());
+    for player in playerids {
+        limits.check(Endpoint::Community)?;
+        let profile = request_profile_xml(http, player).await;
+        limits.record(Endpoint::Community, &profile);
+        steam_infos.push((player, parse_profile(player, &profile?)));
+    }
+    Ok(steam_infos)
+}
+
+/// The public groups `player` is in, from their community profile. Private profiles don't show
+/// any.
+pub async fn request_groups(
+    http: &reqwest::Client,
+    limits: &mut RateLimits,
+    player: SteamID,
+) -> Result<Vec<SteamGroup>> {
+    limits.check(Endpoint::Community)?;
+    let profile = request_profile_xml(http, player).await;
+    limits.record(Endpoint::Community, &profile);
+    let profile = profile?;
+    if let Some(error) = tag(&profile, "error") {
+        return Err(anyhow!("Community profile of {}: {}", player, error));
+    }
+
+    let Some(groups) = tag(&profile, "groups") else {
+        return Ok(Vec::new());
+    };
+    Ok(groups
+        .split("</group>")
+        .filter_map(|group| {
+            Some(SteamGroup {
+                id: tag(group, "groupID64")?.into(),
+                name: tag(group, "groupName").map(Into::into),
+            })
+        })
+        .collect())
+}
+
+async fn request_profile_xml(http: &reqwest::Client, player: SteamID) -> Result<String> {
+    http.get(format!("{}/profiles/{}/", COMMUNITY_URL, u64::from(player)))
+        .query(&[("xml", "1")])
+        .send()
+        .await
+        .and_then(reqwest::Response::error_for_status)
+        .context("Failed to get community profile.")?
+        .text()
+        .await
+        .context("Failed to read community profile.")
+}
+
+fn parse_profile(player: SteamID, xml: &str) -> Result<SteamInfo> {
+    // Accounts that don't exist are answered with a page that only has this in it
+    if let Some(error) = tag(xml, "error") {
+        return Err(anyhow::Error::new(SteamAPIError::NotFound)
+            .context(format!("Community profile of {}: {}", player, error)));
+    }
+    let name = tag(xml, "steamID")
+        .ok_or_else(|| anyhow!("Community profile of {} has no name", player))?;
+
+    let pfp_url = tag(xml, "avatarFull").unwrap_or_default();
+    // Avatars are named after their hash, e.g. `.../<hash>_full.jpg`
+    let pfp_hash = pfp_url
+        .rsplit('/')
+        .next()
+        .and_then(|file| file.split('_').next())
+        .unwrap_or_default();
+    let profile_url = match tag(xml, "customURL").filter(|url| !url.is_empty()) {
+        Some(custom) => format!("{}/id/{}/", COMMUNITY_URL, custom),
+        None => format!("{}/profiles/{}/", COMMUNITY_URL, u64::from(player)),
+    };
+    let profile_visibility = match tag(xml, "privacyState") {
+        Some("public") => ProfileVisibility::Public,
+        Some("friendsonly") => ProfileVisibility::FriendsOnly,
+        _ => ProfileVisibility::Private,
+    };
+    let vac_bans = tag(xml, "vacBanned")
+        .and_then(|banned| banned.parse().ok())
+        .unwrap_or(0);
+
+    Ok(SteamInfo {
+        account_name: name.into(),
+        pfp_url: pfp_url.into(),
+        profile_url: profile_url.into(),
+        pfp_hash: pfp_hash.into(),
+        profile_visibility,
+        time_created: tag(xml, "memberSince").and_then(parse_member_since),
+        country_code: None,
+        vac_bans,
+        game_bans: 0,
+        days_since_last_ban: None,
+        community_banned: None,
+        economy_ban: tag(xml, "tradeBanState").and_then(EconomyBan::from_steam),
+        has_background: None,
+        showcases: None,
+        games_visible: None,
+        tf2_hours: None,
+        steam_level: None,
+        risk: None,
+    })
+}
+
+/// The text inside the first `<name>` element, without any CDATA wrapping
+fn tag<'a>(xml: &'a str, name: &str) -> Option<&'a str> {
+    let open = format!("<{}>", name);
+    let close = format!("</{}>", name);
+    let start = xml.find(&open)? + open.len();
+    let end = start + xml[start..].find(&close)?;
+    let text = xml[start..end].trim();
+    Some(
+        text.strip_prefix("<![CDATA[")
+            .and_then(|text| text.strip_suffix("]]>"))
+            .unwrap_or(text)
+            .trim(),
+    )
+}
+
+/// Unix timestamp (seconds) of a date like `March 4, 2012`
+fn parse_member_since(date: &str) -> Option<i64> {
+    let (month, rest) = date.split_once(' ')?;
+    let (day, year) = rest.split_once(',')?;
+    let month = MONTHS.iter().position(|m| *m == month)? as i64 + 1;
+    let day: i64 = day.trim().parse().ok()?;
+    let year: i64 = year.trim().parse().ok()?;
+
+    // A civil date to days since 1970-01-01, see http://howardhinnant.github.io/date_algorithms.html
+    let y = if month <= 2 { year - 1 } else { year };
+    let era = y.div_euclid(400);
+    let yoe = y.rem_euclid(400);
+    let mp = if month > 2 { month - 3 } else { month + 9 };
+    let doy = (153 * mp + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    Some((era * 146097 + doe - 719468) * 86400)
+}
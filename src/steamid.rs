@@ -0,0 +1,182 @@
+use std::{
+    fmt::{Display, Formatter},
+    str::FromStr,
+};
+
+use serde::{
+    de::{self, Visitor},
+    Deserialize, Deserializer, Serialize, Serializer,
+};
+use serde_json::json;
+use thiserror::Error;
+
+#[derive(Debug, Error, Clone, PartialEq, Eq)]
+pub enum SteamIDError {
+    #[error("\"{0}\" is not a valid SteamID64 or SteamID3")]
+    Invalid(String),
+}
+
+/// A Steam account, wrapping [steamid_ng::SteamID].
+///
+/// This is the only SteamID type that should be used throughout the crate so that every
+/// API payload, event and record agrees on how an account is written:
+/// * Serializes as a SteamID64 string, e.g. `"76561198210264393"`
+/// * Deserializes from a SteamID64 (as a string or number) or a SteamID3 string
+/// * Displays as a SteamID64 with `{}`, or as a SteamID3 (e.g. `[U:1:249998665]`) with `{:#}`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SteamID(steamid_ng::SteamID);
+
+impl SteamID {
+    /// Parse a SteamID3 such as `[U:1:249998665]`
+    pub fn from_steam3(steam3: &str) -> Result<SteamID, SteamIDError> {
+        steamid_ng::SteamID::from_steam3(steam3)
+            .map(SteamID)
+            .map_err(|_| SteamIDError::Invalid(steam3.to_string()))
+    }
+
+    pub fn steam3(&self) -> String {
+        self.0.steam3()
+    }
+
+    pub fn account_id(&self) -> u32 {
+        self.0.account_id()
+    }
+
+    /// JSON schema describing how a [SteamID] appears in API payloads. `steamID64` is how one is
+    /// always written, and `steamID` is everything that is accepted when reading one. It is
+    /// checked in as `data/schemas/steamid.json`, which the other schemas there refer to.
+    pub fn json_schema() -> serde_json::Value {
+        let steamid64 = json!({
+            "title": "SteamID64",
+            "type": "string",
+            "pattern": "^\\d{17}$",
+            "examples": ["76561198210264393"]
+        });
+        json!({
+            "$schema": "http://json-schema.org/draft-07/schema#",
+            "$id": "steamid.json",
+            "definitions": {
+                "steamID64": steamid64,
+                "steamID": {
+                    "title": "SteamID",
+                    "anyOf": [
+                        steamid64,
+                        {
+                            "type": "string",
+                            "pattern": "^\\[U:1:\\d+\\]$",
+                            "examples": ["[U:1:249998665]"]
+                        },
+                        {
+                            "type": "integer",
+                            "examples": [76561198210264393u64]
+                        }
+                    ]
+                }
+            }
+        })
+    }
+
+    /// [SteamID::json_schema] laid out like the rest of `data/schemas`, indented with tabs and
+    /// with CRLF line endings
+    pub fn schema_document() -> String {
+        let mut document = Vec::new();
+        let formatter = serde_json::ser::PrettyFormatter::with_indent(b"\t");
+        let mut serializer = serde_json::Serializer::with_formatter(&mut document, formatter);
+        SteamID::json_schema()
+            .serialize(&mut serializer)
+            .expect("Serialize SteamID schema");
+        let document = String::from_utf8(document).expect("Schema is UTF-8");
+        format!("{}\n", document).replace('\n', "\r\n")
+    }
+}
+
+impl From<u64> for SteamID {
+    fn from(value: u64) -> Self {
+        SteamID(steamid_ng::SteamID::from(value))
+    }
+}
+
+impl From<SteamID> for u64 {
+    fn from(value: SteamID) -> Self {
+        u64::from(value.0)
+    }
+}
+
+impl From<steamid_ng::SteamID> for SteamID {
+    fn from(value: steamid_ng::SteamID) -> Self {
+        SteamID(value)
+    }
+}
+
+impl From<SteamID> for steamid_ng::SteamID {
+    fn from(value: SteamID) -> Self {
+        value.0
+    }
+}
+
+impl PartialOrd for SteamID {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for SteamID {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        u64::from(*self).cmp(&u64::from(*other))
+    }
+}
+
+impl FromStr for SteamID {
+    type Err = SteamIDError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        if s.starts_with('[') {
+            return SteamID::from_steam3(s);
+        }
+
+        s.parse::<u64>()
+            .map(SteamID::from)
+            .map_err(|_| SteamIDError::Invalid(s.to_string()))
+    }
+}
+
+impl Display for SteamID {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        if f.alternate() {
+            f.write_str(&self.steam3())
+        } else {
+            write!(f, "{}", u64::from(*self))
+        }
+    }
+}
+
+impl Serialize for SteamID {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for SteamID {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_any(SteamIDVisitor)
+    }
+}
+
+struct SteamIDVisitor;
+
+impl<'de> Visitor<'de> for SteamIDVisitor {
+    type Value = SteamID;
+
+    fn expecting(&self, f: &mut Formatter) -> std::fmt::Result {
+        f.write_str("a SteamID64 or SteamID3")
+    }
+
+    fn visit_u64<E: de::Error>(self, v: u64) -> Result<Self::Value, E> {
+        Ok(SteamID::from(v))
+    }
+
+    fn visit_str<E: de::Error>(self, v: &str) -> Result<Self::Value, E> {
+        v.parse().map_err(E::custom)
+    }
+}
@@ -0,0 +1,294 @@
+//! Where player records and caches are kept. By default each is a JSON file in the config
+//! directory, which is easy to read, back up and fix by hand. Users with hundreds of thousands of
+//! records can switch to an SQLite database instead (in builds with the `sqlite` feature), which
+//! keeps each record and cache entry in its own indexed row so saving only writes the ones that
+//! have changed.
+//!
+//! The backend is picked in the settings and takes effect on the next start. The first time the
+//! database is opened, the records and caches are copied over from the JSON files.
+//!
+//! Either way, writes of the records are held back briefly and coalesced (see [WriteBehind]), so
+//! marking many players in a row doesn't rewrite the records for every one of them.
+
+use std::{
+    collections::HashMap,
+    fmt::Debug,
+    io::ErrorKind,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+};
+
+use serde::{de::IgnoredAny, Deserialize, Serialize};
+
+use crate::{
+    args::Args,
+    persistence,
+    player_records::PlayerRecords,
+    settings::{ConfigFilesError, Settings},
+    steamid::SteamID,
+};
+
+#[cfg(feature = "sqlite")]
+mod sqlite;
+mod write_behind;
+
+#[cfg(feature = "sqlite")]
+pub use sqlite::Sqlite;
+pub use write_behind::WriteBehind;
+
+/// Name of the document cached Steam profiles and friend lists were kept in, before they had
+/// tables of their own. It is only read if the tables have never been written.
+pub const STEAM_CACHE: &str = "steam_cache";
+/// Names of the tables cached Steam profiles and friend lists are kept in
+pub const STEAM_PROFILES: &str = "steam_profiles";
+pub const STEAM_FRIENDS: &str = "steam_friends";
+/// Every document, to be copied over when switching backend
+#[cfg(feature = "sqlite")]
+const DOCUMENTS: &[&str] = &[STEAM_CACHE];
+/// Every table, to be copied over when switching backend
+#[cfg(feature = "sqlite")]
+const TABLES: &[&str] = &[STEAM_PROFILES, STEAM_FRIENDS];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum StorageBackend {
+    /// A JSON file for each document, and one for all the records
+    #[default]
+    Json,
+    /// A single SQLite database
+    Sqlite,
+}
+
+/// The player records, or rows of a table, that have changed since they were last written
+#[derive(Debug, Default)]
+pub struct RowChanges {
+    /// Those that have been added or changed, serialized as JSON
+    pub changed: HashMap<SteamID, String>,
+    /// Those that have been deleted
+    pub removed: Vec<SteamID>,
+}
+
+/// Somewhere player records, other documents and tables (e.g. caches) can be kept
+pub trait Storage: Debug + Send + Sync {
+    /// A document as it was last written, [None] if it never has been
+    fn read_document(&self, name: &str) -> Result<Option<String>, ConfigFilesError>;
+    fn write_document(&self, name: &str, contents: &str) -> Result<(), ConfigFilesError>;
+    /// Every row of the table `name`, serialized as JSON. [None] if it has never been written.
+    /// By default a table is kept as a document holding all of its rows.
+    fn read_table(&self, name: &str) -> Result<Option<HashMap<SteamID, String>>, ConfigFilesError> {
+        let Some(contents) = self.read_document(name)? else {
+            return Ok(None);
+        };
+        let rows: HashMap<SteamID, serde_json::Value> =
+            serde_json::from_str(&contents).map_err(|e| ConfigFilesError::Json(name.into(), e))?;
+        Ok(Some(
+            rows.into_iter()
+                .map(|(steamid, row)| (steamid, row.to_string()))
+                .collect(),
+        ))
+    }
+    /// Write only the rows of the table `name` that have changed. By default the whole document
+    /// holding the table is rewritten.
+    fn write_table(&self, name: &str, changes: RowChanges) -> Result<(), ConfigFilesError> {
+        let mut rows = self.read_table(name)?.unwrap_or_default();
+        for steamid in &changes.removed {
+            rows.remove(steamid);
+        }
+        rows.extend(changes.changed);
+
+        let mut contents = String::from("{");
+        for (i, (steamid, row)) in rows.iter().enumerate() {
+            if i > 0 {
+                contents.push(',');
+            }
+            contents.push_str(&format!("\"{}\":{}", steamid, row));
+        }
+        contents.push('}');
+        self.write_document(name, &contents)
+    }
+    /// Every player record, serialized as JSON. [None] if they have never been written.
+    fn read_records(&self) -> Result<Option<HashMap<SteamID, String>>, ConfigFilesError>;
+    /// Write only the records that have changed
+    fn write_records(&self, changes: RowChanges) -> Result<(), ConfigFilesError>;
+    /// Finish any writes that have been held back, e.g. before exiting
+    fn flush(&self) -> Result<(), ConfigFilesError> {
+        Ok(())
+    }
+}
+
+/// Open the storage the user picked, falling back to the JSON files if it can't be
+pub fn open(backend: StorageBackend, args: &Args) -> Arc<dyn Storage> {
+    let files = JsonFiles::new(args);
+    let storage = match backend {
+        StorageBackend::Json => Arc::new(files),
+        StorageBackend::Sqlite => open_sqlite(files),
+    };
+    Arc::new(WriteBehind::new(storage))
+}
+
+#[cfg(feature = "sqlite")]
+fn open_sqlite(files: JsonFiles) -> Arc<dyn Storage> {
+    let path = files.dir.join("storage.sqlite");
+    let opened = Sqlite::open(&path).and_then(|db| {
+        if db.is_empty()? {
+            tracing::info!("Copying records and caches into {:?}", path);
+            copy(&files, &db)?;
+        }
+        Ok(db)
+    });
+    match opened {
+        Ok(db) => Arc::new(db),
+        Err(e) => {
+            tracing::error!(
+                "Could not open {:?}, using JSON files instead: {:?}",
+                path,
+                e
+            );
+            Arc::new(files)
+        }
+    }
+}
+
+#[cfg(not(feature = "sqlite"))]
+fn open_sqlite(files: JsonFiles) -> Arc<dyn Storage> {
+    tracing::warn!("This build doesn't support SQLite storage, using JSON files instead.");
+    Arc::new(files)
+}
+
+/// Copy the records and every document from one storage to another
+#[cfg(feature = "sqlite")]
+fn copy(from: &dyn Storage, to: &dyn Storage) -> Result<(), ConfigFilesError> {
+    if let Some(changed) = from.read_records()? {
+        to.write_records(RowChanges {
+            changed,
+            removed: Vec::new(),
+        })?;
+    }
+    for name in DOCUMENTS {
+        if let Some(contents) = from.read_document(name)? {
+            to.write_document(name, &contents)?;
+        }
+    }
+    for name in TABLES {
+        if let Some(changed) = from.read_table(name)? {
+            to.write_table(
+                name,
+                RowChanges {
+                    changed,
+                    removed: Vec::new(),
+                },
+            )?;
+        }
+    }
+    Ok(())
+}
+
+/// Documents as `<name>.json` in the config directory, and the records in the playerlist file
+#[derive(Debug)]
+pub struct JsonFiles {
+    dir: PathBuf,
+    playerlist: PathBuf,
+    /// Every record as of the last read or write, serialized as JSON, as the whole playerlist
+    /// file is rewritten whenever any of them change. [None] until the file is first read.
+    records: Mutex<Option<HashMap<SteamID, String>>>,
+}
+
+/// How the records are laid out in the playerlist file
+#[derive(Deserialize)]
+struct RecordsFile {
+    records: HashMap<SteamID, serde_json::Value>,
+}
+
+impl JsonFiles {
+    /// Use the config directory, and the playerlist file given in `args` if there is one
+    pub fn new(args: &Args) -> JsonFiles {
+        let dir = Settings::locate_config_directory()
+            .map_err(|e| tracing::error!("Could not find the config directory: {}", e))
+            .unwrap_or_default();
+        let playerlist = args
+            .playerlist
+            .as_ref()
+            .map(|i| Ok(i.into()))
+            .unwrap_or(PlayerRecords::locate_playerlist_file())
+            .map_err(|e| {
+                tracing::error!("Could not find a suitable location for the playerlist: {} \nPlease specify a file path manually with --playerlist otherwise information may not be saved.", e);
+            })
+            .unwrap_or(PathBuf::from("playerlist.json"));
+        JsonFiles {
+            dir,
+            playerlist,
+            records: Mutex::new(None),
+        }
+    }
+
+    fn document_path(&self, name: &str) -> PathBuf {
+        self.dir.join(format!("{}.json", name))
+    }
+
+    fn read_playerlist(&self) -> Result<Option<HashMap<SteamID, String>>, ConfigFilesError> {
+        let path = &self.playerlist;
+        let read = persistence::read_recovering(path, |contents| {
+            serde_json::from_str::<RecordsFile>(contents)
+                .map_err(|e| ConfigFilesError::Json(path.to_string_lossy().into(), e))
+        });
+        match read {
+            Ok(file) => Ok(Some(
+                file.records
+                    .into_iter()
+                    .map(|(steamid, record)| (steamid, record.to_string()))
+                    .collect(),
+            )),
+            Err(ConfigFilesError::IO(_, e)) if e.kind() == ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+impl Storage for JsonFiles {
+    fn read_document(&self, name: &str) -> Result<Option<String>, ConfigFilesError> {
+        let path = self.document_path(name);
+        // Anything that isn't JSON is damaged, and should be recovered from a backup
+        let read = persistence::read_recovering(&path, |contents| {
+            serde_json::from_str::<IgnoredAny>(contents)
+                .map(|_| contents.to_string())
+                .map_err(|e| ConfigFilesError::Json(path.to_string_lossy().into(), e))
+        });
+        match read {
+            Ok(contents) => Ok(Some(contents)),
+            Err(ConfigFilesError::IO(_, e)) if e.kind() == ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn write_document(&self, name: &str, contents: &str) -> Result<(), ConfigFilesError> {
+        persistence::write_atomic(&self.document_path(name), contents)
+    }
+
+    fn read_records(&self) -> Result<Option<HashMap<SteamID, String>>, ConfigFilesError> {
+        let read = self.read_playerlist()?;
+        *self.records.lock().unwrap() = Some(read.clone().unwrap_or_default());
+        Ok(read)
+    }
+
+    fn write_records(&self, changes: RowChanges) -> Result<(), ConfigFilesError> {
+        let mut records = self.records.lock().unwrap();
+        if records.is_none() {
+            *records = Some(self.read_playerlist()?.unwrap_or_default());
+        }
+        let records = records.as_mut().expect("Records were just read");
+        for steamid in &changes.removed {
+            records.remove(steamid);
+        }
+        records.extend(changes.changed);
+
+        let mut contents = String::from("{\"records\":{");
+        for (i, (steamid, record)) in records.iter().enumerate() {
+            if i > 0 {
+                contents.push(',');
+            }
+            contents.push_str(&format!("\"{}\":{}", steamid, record));
+        }
+        contents.push_str("}}");
+        persistence::write_atomic(&self.playerlist, &contents)
+    }
+}
@@ -0,0 +1,180 @@
+use std::{collections::HashMap, path::Path, sync::Mutex};
+
+use rusqlite::{params, Connection, OptionalExtension};
+
+use super::{RowChanges, Storage};
+use crate::{settings::ConfigFilesError, steamid::SteamID};
+
+const SCHEMA: &str = "
+    PRAGMA journal_mode = WAL;
+    CREATE TABLE IF NOT EXISTS records (
+        steamid INTEGER PRIMARY KEY,
+        record TEXT NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS documents (
+        name TEXT PRIMARY KEY,
+        contents TEXT NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS rows (
+        name TEXT NOT NULL,
+        steamid INTEGER NOT NULL,
+        contents TEXT NOT NULL,
+        PRIMARY KEY (name, steamid)
+    );
+";
+
+fn sqlite_error(e: rusqlite::Error) -> ConfigFilesError {
+    ConfigFilesError::Other(anyhow::Error::new(e).context("SQLite storage failed."))
+}
+
+/// Records, documents and the rows of tables in an SQLite database, records and rows keyed by
+/// SteamID
+#[derive(Debug)]
+pub struct Sqlite {
+    connection: Mutex<Connection>,
+}
+
+impl Sqlite {
+    /// Open the database at `path`, creating it if it doesn't exist yet
+    pub fn open(path: &Path) -> Result<Sqlite, ConfigFilesError> {
+        let connection = Connection::open(path).map_err(sqlite_error)?;
+        connection.execute_batch(SCHEMA).map_err(sqlite_error)?;
+        Ok(Sqlite {
+            connection: Mutex::new(connection),
+        })
+    }
+
+    /// Whether nothing has been written to the database yet
+    pub fn is_empty(&self) -> Result<bool, ConfigFilesError> {
+        let connection = self.connection.lock().unwrap();
+        connection
+            .query_row(
+                "SELECT NOT EXISTS (SELECT 1 FROM records)
+                    AND NOT EXISTS (SELECT 1 FROM documents)
+                    AND NOT EXISTS (SELECT 1 FROM rows)",
+                [],
+                |row| row.get(0),
+            )
+            .map_err(sqlite_error)
+    }
+}
+
+impl Storage for Sqlite {
+    fn read_document(&self, name: &str) -> Result<Option<String>, ConfigFilesError> {
+        let connection = self.connection.lock().unwrap();
+        connection
+            .query_row(
+                "SELECT contents FROM documents WHERE name = ?1",
+                [name],
+                |row| row.get(0),
+            )
+            .optional()
+            .map_err(sqlite_error)
+    }
+
+    fn write_document(&self, name: &str, contents: &str) -> Result<(), ConfigFilesError> {
+        let connection = self.connection.lock().unwrap();
+        connection
+            .execute(
+                "INSERT INTO documents (name, contents) VALUES (?1, ?2)
+                 ON CONFLICT (name) DO UPDATE SET contents = excluded.contents",
+                params![name, contents],
+            )
+            .map_err(sqlite_error)?;
+        Ok(())
+    }
+
+    fn read_table(&self, name: &str) -> Result<Option<HashMap<SteamID, String>>, ConfigFilesError> {
+        let connection = self.connection.lock().unwrap();
+        let mut select = connection
+            .prepare("SELECT steamid, contents FROM rows WHERE name = ?1")
+            .map_err(sqlite_error)?;
+        let rows = select
+            .query_map([name], |row| {
+                Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?))
+            })
+            .map_err(sqlite_error)?;
+
+        let mut table = HashMap::new();
+        for row in rows {
+            let (steamid, contents) = row.map_err(sqlite_error)?;
+            table.insert(SteamID::from(steamid as u64), contents);
+        }
+        Ok(Some(table).filter(|table| !table.is_empty()))
+    }
+
+    fn write_table(&self, name: &str, changes: RowChanges) -> Result<(), ConfigFilesError> {
+        let mut connection = self.connection.lock().unwrap();
+        let transaction = connection.transaction().map_err(sqlite_error)?;
+        {
+            let mut upsert = transaction
+                .prepare(
+                    "INSERT INTO rows (name, steamid, contents) VALUES (?1, ?2, ?3)
+                     ON CONFLICT (name, steamid) DO UPDATE SET contents = excluded.contents",
+                )
+                .map_err(sqlite_error)?;
+            for (steamid, contents) in &changes.changed {
+                upsert
+                    .execute(params![name, u64::from(*steamid) as i64, contents])
+                    .map_err(sqlite_error)?;
+            }
+
+            let mut delete = transaction
+                .prepare("DELETE FROM rows WHERE name = ?1 AND steamid = ?2")
+                .map_err(sqlite_error)?;
+            for steamid in &changes.removed {
+                delete
+                    .execute(params![name, u64::from(*steamid) as i64])
+                    .map_err(sqlite_error)?;
+            }
+        }
+        transaction.commit().map_err(sqlite_error)
+    }
+
+    fn read_records(&self) -> Result<Option<HashMap<SteamID, String>>, ConfigFilesError> {
+        let connection = self.connection.lock().unwrap();
+        let mut select = connection
+            .prepare("SELECT steamid, record FROM records")
+            .map_err(sqlite_error)?;
+        let rows = select
+            .query_map([], |row| {
+                Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?))
+            })
+            .map_err(sqlite_error)?;
+
+        let mut records = HashMap::new();
+        for row in rows {
+            let (steamid, record) = row.map_err(sqlite_error)?;
+            records.insert(SteamID::from(steamid as u64), record);
+        }
+        Ok(Some(records).filter(|records| !records.is_empty()))
+    }
+
+    fn write_records(&self, changes: RowChanges) -> Result<(), ConfigFilesError> {
+        let mut connection = self.connection.lock().unwrap();
+        let transaction = connection.transaction().map_err(sqlite_error)?;
+        {
+            let mut upsert = transaction
+                .prepare(
+                    "INSERT INTO records (steamid, record) VALUES (?1, ?2)
+                     ON CONFLICT (steamid) DO UPDATE SET record = excluded.record",
+                )
+                .map_err(sqlite_error)?;
+            for (steamid, record) in &changes.changed {
+                upsert
+                    .execute(params![u64::from(*steamid) as i64, record])
+                    .map_err(sqlite_error)?;
+            }
+
+            let mut delete = transaction
+                .prepare("DELETE FROM records WHERE steamid = ?1")
+                .map_err(sqlite_error)?;
+            for steamid in &changes.removed {
+                delete
+                    .execute([u64::from(*steamid) as i64])
+                    .map_err(sqlite_error)?;
+            }
+        }
+        transaction.commit().map_err(sqlite_error)
+    }
+}
@@ -0,0 +1,164 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::Debug,
+    sync::{Arc, Mutex, Weak},
+    time::Duration,
+};
+
+use super::{RowChanges, Storage};
+use crate::{settings::ConfigFilesError, steamid::SteamID};
+
+/// How long record changes are held before they are written
+pub const FLUSH_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Holds back writes of the player records to another [Storage], so that many changes in quick
+/// succession (e.g. marking a wave of bots) are written together instead of rewriting the records
+/// after each one. Changes are written within [FLUSH_INTERVAL] by a background thread, and
+/// whenever [Storage::flush] is called or this is dropped. Writes that fail are tried again on
+/// the next flush. Documents and tables are written straight through.
+#[derive(Debug)]
+pub struct WriteBehind {
+    shared: Arc<Shared>,
+}
+
+#[derive(Debug)]
+struct Shared {
+    inner: Arc<dyn Storage>,
+    pending: Mutex<Pending>,
+    /// Held while writing, so flushes from different threads don't overlap
+    writing: Mutex<()>,
+}
+
+/// The records that have changed since they were last written
+#[derive(Debug, Default)]
+struct Pending {
+    /// The latest version of each changed record, serialized as JSON
+    changed: HashMap<SteamID, String>,
+    removed: HashSet<SteamID>,
+}
+
+impl Pending {
+    fn change(&mut self, steamid: SteamID, record: String) {
+        self.removed.remove(&steamid);
+        self.changed.insert(steamid, record);
+    }
+
+    fn remove(&mut self, steamid: SteamID) {
+        self.changed.remove(&steamid);
+        self.removed.insert(steamid);
+    }
+}
+
+impl WriteBehind {
+    pub fn new(inner: Arc<dyn Storage>) -> WriteBehind {
+        let shared = Arc::new(Shared {
+            inner,
+            pending: Mutex::new(Pending::default()),
+            writing: Mutex::new(()),
+        });
+
+        let weak = Arc::downgrade(&shared);
+        std::thread::spawn(move || flush_loop(weak));
+        WriteBehind { shared }
+    }
+}
+
+/// Flush every [FLUSH_INTERVAL] until the storage is dropped
+fn flush_loop(shared: Weak<Shared>) {
+    loop {
+        std::thread::sleep(FLUSH_INTERVAL);
+        let Some(shared) = shared.upgrade() else {
+            return;
+        };
+        if let Err(e) = shared.flush() {
+            tracing::error!("Failed to write player records: {:?}", e);
+        }
+    }
+}
+
+impl Shared {
+    fn flush(&self) -> Result<(), ConfigFilesError> {
+        let _writing = self.writing.lock().unwrap();
+
+        // Taken out of the lock so records can keep changing while they're written
+        let Pending { changed, removed } = std::mem::take(&mut *self.pending.lock().unwrap());
+        if changed.is_empty() && removed.is_empty() {
+            return Ok(());
+        }
+
+        let written = self.inner.write_records(RowChanges {
+            changed: changed.clone(),
+            removed: removed.iter().copied().collect(),
+        });
+        if written.is_err() {
+            // Anything that hasn't changed again since has to be written next time
+            let mut pending = self.pending.lock().unwrap();
+            for (steamid, record) in changed {
+                if !pending.changed.contains_key(&steamid) && !pending.removed.contains(&steamid) {
+                    pending.change(steamid, record);
+                }
+            }
+            for steamid in removed {
+                if !pending.changed.contains_key(&steamid) {
+                    pending.remove(steamid);
+                }
+            }
+        }
+        written
+    }
+}
+
+impl Storage for WriteBehind {
+    fn read_document(&self, name: &str) -> Result<Option<String>, ConfigFilesError> {
+        self.shared.inner.read_document(name)
+    }
+
+    fn write_document(&self, name: &str, contents: &str) -> Result<(), ConfigFilesError> {
+        self.shared.inner.write_document(name, contents)
+    }
+
+    fn read_table(&self, name: &str) -> Result<Option<HashMap<SteamID, String>>, ConfigFilesError> {
+        self.shared.inner.read_table(name)
+    }
+
+    fn write_table(&self, name: &str, changes: RowChanges) -> Result<(), ConfigFilesError> {
+        self.shared.inner.write_table(name, changes)
+    }
+
+    fn read_records(&self) -> Result<Option<HashMap<SteamID, String>>, ConfigFilesError> {
+        let mut records = self.shared.inner.read_records()?;
+        let pending = self.shared.pending.lock().unwrap();
+        if !pending.changed.is_empty() || !pending.removed.is_empty() {
+            let records = records.get_or_insert_with(HashMap::new);
+            for steamid in &pending.removed {
+                records.remove(steamid);
+            }
+            records.extend(pending.changed.clone());
+        }
+        Ok(records)
+    }
+
+    fn write_records(&self, changes: RowChanges) -> Result<(), ConfigFilesError> {
+        let mut pending = self.shared.pending.lock().unwrap();
+        for (steamid, record) in changes.changed {
+            pending.change(steamid, record);
+        }
+        for steamid in changes.removed {
+            pending.remove(steamid);
+        }
+        Ok(())
+    }
+
+    fn flush(&self) -> Result<(), ConfigFilesError> {
+        self.shared.flush()?;
+        self.shared.inner.flush()
+    }
+}
+
+impl Drop for WriteBehind {
+    fn drop(&mut self) {
+        if let Err(e) = self.shared.flush() {
+            tracing::error!("Failed to write player records: {:?}", e);
+        }
+    }
+}
@@ -0,0 +1,230 @@
+use std::{collections::HashMap, sync::Arc};
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::{
+    analytics::history::now, interlocks::InterlockError, locale::tr, player_records::Verdict,
+    steamid::SteamID, votes::Signal,
+};
+
+/// Something automation thinks should be done to a player, which is only carried out once the
+/// user confirms it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum SuggestedAction {
+    Mark {
+        verdict: Verdict,
+    },
+    /// Call a votekick for cheating
+    Votekick,
+    /// Report the player to Valve, confirming gives the evidence to attach
+    Report,
+}
+
+impl SuggestedAction {
+    /// What to suggest for a player with `verdict` who has been marked by `signals`, which add up
+    /// to `score` (see [crate::votes::VoteFeedback::score]). A player is only taken to be a
+    /// cheater once the score reaches `min_score`.
+    pub fn for_player(
+        verdict: Verdict,
+        signals: &[Signal],
+        score: f32,
+        min_score: f32,
+    ) -> Vec<SuggestedAction> {
+        match verdict {
+            _ if signals.is_empty() => Vec::new(),
+            Verdict::Player if signals.iter().any(Signal::indicates_bot) => {
+                vec![SuggestedAction::Mark {
+                    verdict: Verdict::Bot,
+                }]
+            }
+            Verdict::Player if score < min_score => vec![SuggestedAction::Mark {
+                verdict: Verdict::Suspicious,
+            }],
+            Verdict::Player | Verdict::Suspicious if score >= min_score => {
+                vec![SuggestedAction::Mark {
+                    verdict: Verdict::Cheater,
+                }]
+            }
+            Verdict::Cheater | Verdict::Bot => {
+                let mut actions = vec![SuggestedAction::Votekick];
+                // Only worth reporting if a demo has something to show for it
+                if signals.iter().any(|s| matches!(s, Signal::Heuristic(_))) {
+                    actions.push(SuggestedAction::Report);
+                }
+                actions
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    /// Seconds the suggestion stays open for. A votekick is only worth calling while the player
+    /// is still around.
+    fn lifetime(&self) -> u64 {
+        match self {
+            SuggestedAction::Votekick => 2 * 60,
+            SuggestedAction::Mark { .. } | SuggestedAction::Report => 30 * 60,
+        }
+    }
+
+    /// Seconds after a suggestion is confirmed, dismissed or expires before the same one may be
+    /// made again, so the user isn't asked about the same thing over and over
+    fn cooldown(&self) -> u64 {
+        match self {
+            SuggestedAction::Votekick => 10 * 60,
+            SuggestedAction::Mark { .. } => 60 * 60,
+            SuggestedAction::Report => 24 * 60 * 60,
+        }
+    }
+}
+
+/// A suggested action waiting for the user to confirm or dismiss it
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Suggestion {
+    pub id: u64,
+    pub steamid: SteamID,
+    pub name: Option<Arc<str>>,
+    pub action: SuggestedAction,
+    /// What led to the suggestion
+    pub signals: Vec<Signal>,
+    /// Unix timestamps (seconds)
+    pub created: u64,
+    pub expires: u64,
+}
+
+impl Suggestion {
+    /// The question put to the user
+    pub fn describe(&self) -> String {
+        let player: &dyn std::fmt::Display = match &self.name {
+            Some(name) => name,
+            None => &self.steamid,
+        };
+        match self.action {
+            SuggestedAction::Mark { verdict } => tr(
+                "suggestion-mark",
+                &[("player", player), ("verdict", &verdict)],
+            ),
+            SuggestedAction::Votekick => tr("suggestion-votekick", &[("player", player)]),
+            SuggestedAction::Report => tr("suggestion-report", &[("player", player)]),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum SuggestionDecision {
+    Confirm,
+    Dismiss,
+}
+
+#[derive(Debug, Error, PartialEq)]
+pub enum SuggestionError {
+    #[error("No such suggestion, it may have expired")]
+    NotFound,
+    #[error("Player is not connected")]
+    PlayerLeft,
+    /// A votekick was suggested by automation, so it is held to the same limits
+    #[error("{0}")]
+    Interlock(InterlockError),
+}
+
+impl From<InterlockError> for SuggestionError {
+    fn from(e: InterlockError) -> Self {
+        match e {
+            InterlockError::UnknownPlayer => SuggestionError::PlayerLeft,
+            e => SuggestionError::Interlock(e),
+        }
+    }
+}
+
+/// Actions suggested by automation, waiting on the user. Automation can afford to suggest
+/// freely, since nothing happens until the user agrees.
+/// Serializes as the list of open suggestions.
+#[derive(Debug, Default, Serialize)]
+#[serde(transparent)]
+pub struct Suggestions {
+    #[serde(skip)]
+    next_id: u64,
+    /// Oldest first
+    open: Vec<Suggestion>,
+    /// Suggestions that have been settled one way or another, and when they may be made again
+    #[serde(skip)]
+    settled: HashMap<(SteamID, SuggestedAction), u64>,
+}
+
+impl Suggestions {
+    /// Suggest an action, unless the same one is already open or was settled recently. Returns
+    /// the new suggestion if one was made.
+    pub fn suggest(
+        &mut self,
+        steamid: SteamID,
+        name: Option<Arc<str>>,
+        action: SuggestedAction,
+        signals: Vec<Signal>,
+    ) -> Option<&Suggestion> {
+        let now = now();
+        if self
+            .settled
+            .get(&(steamid, action))
+            .is_some_and(|&until| until > now)
+        {
+            return None;
+        }
+
+        if let Some(open) = self
+            .open
+            .iter_mut()
+            .find(|s| s.steamid == steamid && s.action == action)
+        {
+            open.signals = signals;
+            return None;
+        }
+
+        self.next_id += 1;
+        self.open.push(Suggestion {
+            id: self.next_id,
+            steamid,
+            name,
+            action,
+            signals,
+            created: now,
+            expires: now + action.lifetime(),
+        });
+        self.open.last()
+    }
+
+    /// Close the suggestions that have expired, or that `keep` no longer wants
+    pub fn expire(&mut self, mut keep: impl FnMut(&Suggestion) -> bool) {
+        let now = now();
+        let (open, closed): (Vec<_>, Vec<_>) = std::mem::take(&mut self.open)
+            .into_iter()
+            .partition(|s| s.expires > now && keep(s));
+        self.open = open;
+        for suggestion in closed {
+            self.settle(&suggestion);
+        }
+        self.settled.retain(|_, until| *until > now);
+    }
+
+    /// Take a suggestion out of the queue, settling it
+    pub fn take(&mut self, id: u64) -> Result<Suggestion, SuggestionError> {
+        let now = now();
+        let i = self
+            .open
+            .iter()
+            .position(|s| s.id == id && s.expires > now)
+            .ok_or(SuggestionError::NotFound)?;
+        let suggestion = self.open.remove(i);
+        self.settle(&suggestion);
+        Ok(suggestion)
+    }
+
+    fn settle(&mut self, suggestion: &Suggestion) {
+        self.settled.insert(
+            (suggestion.steamid, suggestion.action),
+            now() + suggestion.action.cooldown(),
+        );
+    }
+}
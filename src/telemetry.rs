@@ -0,0 +1,220 @@
+use std::{
+    collections::HashSet,
+    io::ErrorKind,
+    path::PathBuf,
+    sync::{Arc, RwLock},
+    time::Duration,
+};
+
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    analytics::history::now,
+    persistence,
+    server::Server,
+    settings::{ConfigFilesError, Settings},
+    steamid::SteamID,
+};
+
+/// How long (seconds) counts are gathered for before they are submitted
+const REPORT_PERIOD: u64 = 24 * 60 * 60;
+/// How often to check whether a report is due
+const CHECK_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// Aggregate counts gathered over a reporting period. These are the only figures ever
+/// submitted, nothing here can identify a player or the user.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TelemetryCounters {
+    /// Different bots seen on the servers the user played on
+    pub bots_encountered: u64,
+    /// Votekicks called against players that had been marked by a rule, list or heuristic
+    pub votekicks_called: u64,
+    /// How many of those votekicks passed
+    pub votekicks_passed: u64,
+}
+
+impl TelemetryCounters {
+    fn add(&mut self, other: &TelemetryCounters) {
+        self.bots_encountered += other.bots_encountered;
+        self.votekicks_called += other.votekicks_called;
+        self.votekicks_passed += other.votekicks_passed;
+    }
+}
+
+/// Exactly what is sent to the statistics endpoint
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TelemetryReport {
+    pub client_version: &'static str,
+    /// Unix timestamps (seconds) of the period the counts cover
+    pub period_start: u64,
+    pub period_end: u64,
+    #[serde(flatten)]
+    pub counters: TelemetryCounters,
+}
+
+/// Counts kept towards the next anonymous statistics report. They are only ever submitted if
+/// the user has opted in, see [Settings::get_telemetry_endpoint].
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Telemetry {
+    #[serde(skip)]
+    path: PathBuf,
+    /// Bots already counted this period. Only used to avoid counting the same bot twice, these
+    /// are never saved or submitted.
+    #[serde(skip)]
+    seen_bots: HashSet<SteamID>,
+    period_start: u64,
+    counters: TelemetryCounters,
+}
+
+impl Telemetry {
+    /// Attempts to load the counts from the default location, starting fresh if they don't exist
+    /// or can't be read.
+    pub fn load_or_create() -> Telemetry {
+        let path = Self::locate_telemetry_file()
+            .map_err(|e| tracing::warn!("Failed to find telemetry location: {:?}", e))
+            .unwrap_or("telemetry.json".into());
+
+        match Self::load_from(path.clone()) {
+            Ok(telemetry) => telemetry,
+            Err(ConfigFilesError::IO(_, e)) if e.kind() == ErrorKind::NotFound => {
+                Telemetry::new(path)
+            }
+            Err(e) => {
+                tracing::warn!("Could not load telemetry counts, starting fresh: {:?}", e);
+                Telemetry::new(path)
+            }
+        }
+    }
+
+    fn new(path: PathBuf) -> Telemetry {
+        Telemetry {
+            path,
+            seen_bots: HashSet::new(),
+            period_start: now(),
+            counters: TelemetryCounters::default(),
+        }
+    }
+
+    /// Attempt to load the [Telemetry] counts from the provided file
+    pub fn load_from(path: PathBuf) -> Result<Telemetry, ConfigFilesError> {
+        let mut telemetry: Telemetry = persistence::read_recovering(&path, |contents| {
+            serde_json::from_str(contents)
+                .map_err(|e| ConfigFilesError::Json(path.to_string_lossy().into(), e))
+        })?;
+        telemetry.path = path;
+        Ok(telemetry)
+    }
+
+    /// Attempt to save the [Telemetry] counts to the file they were loaded from
+    pub fn save(&self) -> Result<(), ConfigFilesError> {
+        let contents =
+            serde_json::to_string(self).context("Failed to serialize telemetry counts.")?;
+        persistence::write_atomic(&self.path, &contents)
+    }
+
+    /// Attempt to save the [Telemetry] counts, log errors and ignore result
+    pub fn save_ok(&self) {
+        if let Err(e) = self.save() {
+            tracing::error!("Failed to save telemetry counts: {:?}", e);
+        }
+    }
+
+    pub fn locate_telemetry_file() -> Result<PathBuf, ConfigFilesError> {
+        Settings::locate_config_directory().map(|dir| dir.join("telemetry.json"))
+    }
+
+    /// Count a bot, unless it has already been counted this period. Returns true if it was
+    /// counted.
+    pub fn bot_seen(&mut self, steamid: SteamID) -> bool {
+        let new = self.seen_bots.insert(steamid);
+        if new {
+            self.counters.bots_encountered += 1;
+        }
+        new
+    }
+
+    pub fn votekick_resolved(&mut self, passed: bool) {
+        self.counters.votekicks_called += 1;
+        if passed {
+            self.counters.votekicks_passed += 1;
+        }
+    }
+
+    /// The report that would be submitted if the period ended now
+    pub fn report(&self) -> TelemetryReport {
+        TelemetryReport {
+            client_version: env!("CARGO_PKG_VERSION"),
+            period_start: self.period_start,
+            period_end: now(),
+            counters: self.counters.clone(),
+        }
+    }
+
+    /// Whether the current period has run long enough to be reported
+    pub fn report_due(&self) -> bool {
+        now() >= self.period_start + REPORT_PERIOD
+    }
+
+    /// End the current period, returning its report and starting the counts again
+    pub fn take_report(&mut self) -> TelemetryReport {
+        let report = self.report();
+        self.period_start = report.period_end;
+        self.counters = TelemetryCounters::default();
+        self.seen_bots.clear();
+        self.save_ok();
+        report
+    }
+
+    /// Put back the counts from a report that couldn't be submitted, so they go out with the next
+    pub fn restore(&mut self, report: &TelemetryReport) {
+        self.period_start = self.period_start.min(report.period_start);
+        self.counters.add(&report.counters);
+        self.save_ok();
+    }
+}
+
+async fn submit(endpoint: &str, report: &TelemetryReport) -> anyhow::Result<()> {
+    reqwest::Client::new()
+        .post(endpoint)
+        .json(report)
+        .timeout(Duration::from_secs(30))
+        .send()
+        .await
+        .context("Failed to send telemetry report.")?
+        .error_for_status()
+        .context("Statistics endpoint rejected telemetry report.")?;
+    Ok(())
+}
+
+/// Periodically submit the telemetry report, if the user has opted in. This will never return,
+/// so it should be spawned in a separate `tokio::task`.
+pub async fn telemetry_loop(server: Arc<RwLock<Server>>, settings: Arc<RwLock<Settings>>) {
+    let mut interval = tokio::time::interval(CHECK_INTERVAL);
+    loop {
+        interval.tick().await;
+
+        let report = {
+            let mut server = server.write().unwrap();
+            if !server.telemetry().report_due() {
+                continue;
+            }
+            server.telemetry_mut().take_report()
+        };
+        // Counts are thrown away at the end of each period unless the user has opted in
+        let Some(endpoint) = settings.read().unwrap().get_telemetry_endpoint() else {
+            continue;
+        };
+
+        match submit(&endpoint, &report).await {
+            Ok(()) => tracing::info!("Submitted anonymous statistics: {:?}", report),
+            Err(e) => {
+                tracing::warn!("Could not submit anonymous statistics: {:?}", e);
+                server.write().unwrap().telemetry_mut().restore(&report);
+            }
+        }
+    }
+}
@@ -0,0 +1,181 @@
+use std::{collections::VecDeque, sync::Mutex, time::Duration};
+
+use serde::Serialize;
+
+use crate::analytics::history::now;
+
+/// Number of seconds rates and peak lag are averaged over
+const WINDOW: u64 = 10;
+
+/// A stage that data passes through on its way from the game to the UI
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Pipeline {
+    /// Lines read from the console log
+    Console,
+    /// Packets parsed from the demo being recorded
+    Demo,
+    /// Events pushed to subscribers of the events stream
+    Events,
+}
+
+impl Pipeline {
+    pub const ALL: [Pipeline; 3] = [Pipeline::Console, Pipeline::Demo, Pipeline::Events];
+
+    fn name(&self) -> &'static str {
+        match self {
+            Pipeline::Console => "console",
+            Pipeline::Demo => "demo",
+            Pipeline::Events => "events",
+        }
+    }
+}
+
+struct Bucket {
+    /// Unix timestamp (seconds) this bucket covers
+    second: u64,
+    count: u64,
+    peak_lag: Option<Duration>,
+}
+
+struct Meter {
+    total: u64,
+    buckets: VecDeque<Bucket>,
+    last_lag: Option<Duration>,
+}
+
+impl Meter {
+    const fn new() -> Meter {
+        Meter {
+            total: 0,
+            buckets: VecDeque::new(),
+            last_lag: None,
+        }
+    }
+
+    /// The bucket for the current second, dropping any that have fallen out of the window
+    fn bucket(&mut self) -> &mut Bucket {
+        let second = now();
+        while self
+            .buckets
+            .front()
+            .is_some_and(|b| b.second + WINDOW < second)
+        {
+            self.buckets.pop_front();
+        }
+        if self.buckets.back().map(|b| b.second) != Some(second) {
+            self.buckets.push_back(Bucket {
+                second,
+                count: 0,
+                peak_lag: None,
+            });
+        }
+        self.buckets.back_mut().expect("Just pushed a bucket")
+    }
+}
+
+static METERS: Mutex<[Meter; 3]> = Mutex::new([Meter::new(), Meter::new(), Meter::new()]);
+
+/// How a pipeline has been performing recently
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PipelineStats {
+    pub pipeline: Pipeline,
+    /// Items handled since the client started
+    pub total: u64,
+    /// Items handled per second, averaged over the last few seconds
+    pub per_second: f32,
+    /// How long after being written the most recent data was handled, in milliseconds
+    pub lag_ms: Option<u64>,
+    /// The worst lag over the last few seconds, in milliseconds
+    pub peak_lag_ms: Option<u64>,
+}
+
+/// Count `count` items as having gone through `pipeline`
+pub fn record(pipeline: Pipeline, count: u64) {
+    let mut meters = METERS.lock().unwrap();
+    let meter = &mut meters[pipeline as usize];
+    meter.total += count;
+    meter.bucket().count += count;
+}
+
+/// Note how long after being written the latest data for `pipeline` was handled
+pub fn record_lag(pipeline: Pipeline, lag: Duration) {
+    let mut meters = METERS.lock().unwrap();
+    let meter = &mut meters[pipeline as usize];
+    meter.last_lag = Some(lag);
+    let bucket = meter.bucket();
+    bucket.peak_lag = bucket.peak_lag.max(Some(lag));
+}
+
+/// Recent performance of every pipeline
+pub fn stats() -> Vec<PipelineStats> {
+    let now = now();
+    let meters = METERS.lock().unwrap();
+    Pipeline::ALL
+        .iter()
+        .map(|&pipeline| {
+            let meter = &meters[pipeline as usize];
+            // Only count whole seconds, the current one is still filling up
+            let recent = meter
+                .buckets
+                .iter()
+                .filter(|b| b.second + WINDOW >= now && b.second < now);
+            PipelineStats {
+                pipeline,
+                total: meter.total,
+                per_second: recent.clone().map(|b| b.count).sum::<u64>() as f32 / WINDOW as f32,
+                lag_ms: meter.last_lag.map(|lag| lag.as_millis() as u64),
+                peak_lag_ms: recent
+                    .filter_map(|b| b.peak_lag)
+                    .max()
+                    .map(|lag| lag.as_millis() as u64),
+            }
+        })
+        .collect()
+}
+
+/// Every pipeline's stats in the Prometheus text format, along with the number of connected
+/// players
+pub fn prometheus(connected_players: usize) -> String {
+    let mut out = String::new();
+    let stats = stats();
+
+    let mut metric = |name: &str, help: &str, kind: &str, value: &dyn Fn(&PipelineStats) -> f64| {
+        out.push_str(&format!(
+            "# HELP {} {}\n# TYPE {} {}\n",
+            name, help, name, kind
+        ));
+        for s in &stats {
+            out.push_str(&format!(
+                "{}{{pipeline=\"{}\"}} {}\n",
+                name,
+                s.pipeline.name(),
+                value(s)
+            ));
+        }
+    };
+    metric(
+        "mac_pipeline_items_total",
+        "Items handled by each pipeline since the client started.",
+        "counter",
+        &|s| s.total as f64,
+    );
+    metric(
+        "mac_pipeline_items_per_second",
+        "Items handled per second by each pipeline, averaged over the last few seconds.",
+        "gauge",
+        &|s| s.per_second as f64,
+    );
+    metric(
+        "mac_pipeline_lag_seconds",
+        "How long after being written the most recent data was handled.",
+        "gauge",
+        &|s| s.lag_ms.unwrap_or_default() as f64 / 1000.0,
+    );
+
+    out.push_str("# HELP mac_players_connected Players on the current server.\n");
+    out.push_str("# TYPE mac_players_connected gauge\n");
+    out.push_str(&format!("mac_players_connected {}\n", connected_players));
+    out
+}
@@ -0,0 +1,401 @@
+use std::{
+    collections::VecDeque,
+    fmt::Display,
+    io::ErrorKind,
+    path::PathBuf,
+    sync::Arc,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    demo::suspicion::SuspicionKind,
+    events::{self, SystemEventLevel},
+    locale::tr,
+    persistence,
+    player::{KillLine, Team},
+    player_records::Verdict,
+    settings::{ConfigFilesError, Settings},
+    steamid::SteamID,
+};
+
+/// How long (seconds) a votekick is given to pass. If the player is still on the server after
+/// this, the vote is taken to have failed.
+const VOTE_TIMEOUT: u64 = 45;
+/// Number of automatic adjustments to keep in the audit log
+const MAX_AUDIT_LEN: usize = 500;
+/// Number of finished votes to keep, along with their snapshots
+const MAX_HISTORY_LEN: usize = 100;
+/// How far back (seconds) chat and kills are included in a [VoteSnapshot]
+pub const SNAPSHOT_WINDOW: u64 = 2 * 60;
+
+const MIN_WEIGHT: f32 = 0.1;
+const MAX_WEIGHT: f32 = 2.0;
+const PASSED_FACTOR: f32 = 1.1;
+const FAILED_FACTOR: f32 = 0.8;
+/// A signal is flagged for review once it has failed at least this many votes...
+const REVIEW_MIN_FAILED: u32 = 3;
+/// ...and at least this fraction of all the votes it was involved in
+const REVIEW_FAILED_RATIO: f32 = 2.0 / 3.0;
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+/// Something that marked a player as worth kicking
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(tag = "type", content = "name", rename_all = "camelCase")]
+pub enum Signal {
+    /// A rule from the rules folder
+    Rule(Arc<str>),
+    /// An imported playerlist
+    List(Arc<str>),
+    /// A demo analysis heuristic
+    Heuristic(SuspicionKind),
+    /// A console analyser
+    Detector(Arc<str>),
+    /// The Steam profile looks like a bot's, see [crate::player::SteamInfo::looks_like_bot]
+    BotProfile,
+}
+
+impl Signal {
+    /// Whether this points to the player being a bot rather than a cheater
+    pub fn indicates_bot(&self) -> bool {
+        matches!(
+            self,
+            Signal::BotProfile | Signal::Heuristic(SuspicionKind::VoiceSpam)
+        )
+    }
+}
+
+impl Display for Signal {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let message = match self {
+            Signal::Rule(rule) => tr("signal-rule", &[("name", rule)]),
+            Signal::List(list) => tr("signal-list", &[("name", list)]),
+            Signal::Heuristic(kind) => tr("signal-heuristic", &[("name", &format!("{:?}", kind))]),
+            Signal::Detector(name) => tr("signal-detector", &[("name", name)]),
+            Signal::BotProfile => tr("signal-bot-profile", &[]),
+        };
+        f.write_str(&message)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum VoteOutcome {
+    Passed,
+    Failed,
+}
+
+/// How reliable a [Signal] has turned out to be at getting players kicked
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SignalStats {
+    pub signal: Signal,
+    /// Starts at 1, goes up when votes pass and down when they fail
+    pub weight: f32,
+    pub votes_passed: u32,
+    pub votes_failed: u32,
+    pub flagged_for_review: bool,
+}
+
+/// An automatic change to a [SignalStats], kept so users can see why weights have changed
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Adjustment {
+    /// Unix timestamp (seconds)
+    pub time: u64,
+    pub signal: Signal,
+    pub steamid: SteamID,
+    pub outcome: VoteOutcome,
+    pub old_weight: f32,
+    pub new_weight: f32,
+    /// Whether this adjustment caused the signal to be flagged for review
+    pub flagged_for_review: bool,
+}
+
+/// A player who was on the server when a votekick was called
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SnapshotPlayer {
+    pub steamid: SteamID,
+    pub name: Arc<str>,
+    pub team: Team,
+    pub verdict: Verdict,
+    pub signals: Vec<Signal>,
+}
+
+/// A chat message sent shortly before a votekick was called
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SnapshotChat {
+    /// Unix timestamp (seconds)
+    pub time: u64,
+    pub steamid: SteamID,
+    pub name: Arc<str>,
+    pub message: Arc<str>,
+}
+
+/// What the game looked like when a votekick was called, so the vote can be reviewed afterwards
+/// (particularly one that failed) with everything that was known at the time
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VoteSnapshot {
+    pub map: Option<Arc<str>>,
+    /// Tick of the demo being recorded, to find the moment in it
+    pub demo_tick: Option<u32>,
+    pub players: Vec<SnapshotPlayer>,
+    /// Chat from the last [SNAPSHOT_WINDOW] seconds, oldest first
+    pub chat: Vec<SnapshotChat>,
+    /// Kills from the last [SNAPSHOT_WINDOW] seconds, oldest first
+    pub kills: Vec<KillLine>,
+}
+
+/// A votekick that has finished
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VoteRecord {
+    pub steamid: SteamID,
+    /// Unix timestamp (seconds)
+    pub called: u64,
+    pub signals: Vec<Signal>,
+    pub outcome: VoteOutcome,
+    pub snapshot: VoteSnapshot,
+}
+
+#[derive(Debug, Clone)]
+struct PendingVote {
+    steamid: SteamID,
+    called: u64,
+    signals: Vec<Signal>,
+    snapshot: VoteSnapshot,
+}
+
+/// Persistent record of how every [Signal] has fared in votekicks, along with an audit log of
+/// each automatic adjustment and the most recent votes.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct VoteFeedback {
+    #[serde(skip)]
+    path: PathBuf,
+    #[serde(skip)]
+    pending: Vec<PendingVote>,
+    signals: Vec<SignalStats>,
+    audit: VecDeque<Adjustment>,
+    #[serde(default)]
+    history: VecDeque<VoteRecord>,
+}
+
+impl VoteFeedback {
+    /// Attempts to load the vote feedback from the default location, starting fresh if it
+    /// doesn't exist or can't be read.
+    pub fn load_or_create() -> VoteFeedback {
+        let path = Self::locate_feedback_file()
+            .map_err(|e| tracing::warn!("Failed to find vote feedback location: {:?}", e))
+            .unwrap_or("vote_feedback.json".into());
+
+        match Self::load_from(path.clone()) {
+            Ok(feedback) => feedback,
+            Err(ConfigFilesError::IO(_, e)) if e.kind() == ErrorKind::NotFound => {
+                VoteFeedback::new(path)
+            }
+            Err(e) => {
+                tracing::warn!("Could not load vote feedback, starting fresh: {:?}", e);
+                VoteFeedback::new(path)
+            }
+        }
+    }
+
+    fn new(path: PathBuf) -> VoteFeedback {
+        VoteFeedback {
+            path,
+            pending: Vec::new(),
+            signals: Vec::new(),
+            audit: VecDeque::new(),
+            history: VecDeque::new(),
+        }
+    }
+
+    /// Attempt to load the [VoteFeedback] from the provided file
+    pub fn load_from(path: PathBuf) -> Result<VoteFeedback, ConfigFilesError> {
+        let mut feedback: VoteFeedback = persistence::read_recovering(&path, |contents| {
+            serde_json::from_str(contents)
+                .map_err(|e| ConfigFilesError::Json(path.to_string_lossy().into(), e))
+        })?;
+        feedback.path = path;
+        Ok(feedback)
+    }
+
+    /// Attempt to save the [VoteFeedback] to the file it was loaded from
+    pub fn save(&self) -> Result<(), ConfigFilesError> {
+        let contents = serde_json::to_string(self).context("Failed to serialize vote feedback.")?;
+        persistence::write_atomic(&self.path, &contents)
+    }
+
+    /// Attempt to save the [VoteFeedback], log errors and ignore result
+    pub fn save_ok(&self) {
+        if let Err(e) = self.save() {
+            tracing::error!("Failed to save vote feedback: {:?}", e);
+        }
+    }
+
+    pub fn locate_feedback_file() -> Result<PathBuf, ConfigFilesError> {
+        Settings::locate_config_directory().map(|dir| dir.join("vote_feedback.json"))
+    }
+
+    pub fn signals(&self) -> &[SignalStats] {
+        &self.signals
+    }
+
+    /// Every automatic adjustment, most recent last
+    pub fn audit(&self) -> &VecDeque<Adjustment> {
+        &self.audit
+    }
+
+    /// The most recent finished votes, most recent last
+    pub fn history(&self) -> &VecDeque<VoteRecord> {
+        &self.history
+    }
+
+    /// The current weight of a signal, 1 if it hasn't been involved in any votes yet
+    pub fn weight(&self, signal: &Signal) -> f32 {
+        self.signals
+            .iter()
+            .find(|s| &s.signal == signal)
+            .map(|s| s.weight)
+            .unwrap_or(1.0)
+    }
+
+    /// How much `signals` add up to as reasons to act on a player: the sum of their weights, so
+    /// signals whose votekicks have passed count for more than ones whose votekicks keep failing
+    pub fn score(&self, signals: &[Signal]) -> f32 {
+        signals.iter().map(|signal| self.weight(signal)).sum()
+    }
+
+    /// Start tracking a votekick against a player, who was marked by `signals`. The vote is kept
+    /// with `snapshot` once it has finished, whether or not anything marked the player.
+    pub fn vote_called(&mut self, steamid: SteamID, signals: Vec<Signal>, snapshot: VoteSnapshot) {
+        self.pending.retain(|v| v.steamid != steamid);
+        self.pending.push(PendingVote {
+            steamid,
+            called: now(),
+            signals,
+            snapshot,
+        });
+    }
+
+    /// Work out the outcome of any votes that have finished. A vote has passed if the player has
+    /// left the server, or failed if they are still there once the vote would have timed out.
+    /// Returns the outcome of each vote that finished.
+    pub fn resolve_votes(&mut self, connected: &[SteamID]) -> Vec<VoteOutcome> {
+        let now = now();
+        let (finished, pending): (Vec<_>, Vec<_>) = self.pending.drain(..).partition(|vote| {
+            !connected.contains(&vote.steamid) || now >= vote.called + VOTE_TIMEOUT
+        });
+        self.pending = pending;
+
+        if finished.is_empty() {
+            return Vec::new();
+        }
+        let mut outcomes = Vec::new();
+        for vote in finished {
+            let outcome = if connected.contains(&vote.steamid) {
+                VoteOutcome::Failed
+            } else {
+                VoteOutcome::Passed
+            };
+            tracing::info!("Votekick against {} {:?}", vote.steamid, outcome);
+            for signal in vote.signals.iter().cloned() {
+                self.adjust(signal, vote.steamid, outcome);
+            }
+            outcomes.push(outcome);
+
+            if self.history.len() >= MAX_HISTORY_LEN {
+                self.history.pop_front();
+            }
+            self.history.push_back(VoteRecord {
+                steamid: vote.steamid,
+                called: vote.called,
+                signals: vote.signals,
+                outcome,
+                snapshot: vote.snapshot,
+            });
+        }
+        self.save_ok();
+        outcomes
+    }
+
+    fn adjust(&mut self, signal: Signal, steamid: SteamID, outcome: VoteOutcome) {
+        let index = match self.signals.iter().position(|s| s.signal == signal) {
+            Some(index) => index,
+            None => {
+                self.signals.push(SignalStats {
+                    signal: signal.clone(),
+                    weight: 1.0,
+                    votes_passed: 0,
+                    votes_failed: 0,
+                    flagged_for_review: false,
+                });
+                self.signals.len() - 1
+            }
+        };
+        let stats = &mut self.signals[index];
+
+        let old_weight = stats.weight;
+        match outcome {
+            VoteOutcome::Passed => {
+                stats.votes_passed += 1;
+                stats.weight = (stats.weight * PASSED_FACTOR).min(MAX_WEIGHT);
+            }
+            VoteOutcome::Failed => {
+                stats.votes_failed += 1;
+                stats.weight = (stats.weight * FAILED_FACTOR).max(MIN_WEIGHT);
+            }
+        }
+
+        let votes = stats.votes_passed + stats.votes_failed;
+        let flag = !stats.flagged_for_review
+            && stats.votes_failed >= REVIEW_MIN_FAILED
+            && stats.votes_failed as f32 / votes as f32 >= REVIEW_FAILED_RATIO;
+        if flag {
+            stats.flagged_for_review = true;
+            events::emit_system(
+                SystemEventLevel::Warning,
+                tr(
+                    "signal-needs-review",
+                    &[
+                        ("signal", &signal),
+                        ("failed", &stats.votes_failed),
+                        ("votes", &votes),
+                    ],
+                ),
+            );
+        }
+
+        if self.audit.len() >= MAX_AUDIT_LEN {
+            self.audit.pop_front();
+        }
+        self.audit.push_back(Adjustment {
+            time: now(),
+            signal,
+            steamid,
+            outcome,
+            old_weight,
+            new_weight: stats.weight,
+            flagged_for_review: flag,
+        });
+    }
+
+    /// Clear the review flag on a signal once the user has looked at it
+    pub fn clear_review(&mut self, signal: &Signal) {
+        if let Some(stats) = self.signals.iter_mut().find(|s| &s.signal == signal) {
+            stats.flagged_for_review = false;
+            self.save_ok();
+        }
+    }
+}
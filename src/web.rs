@@ -1,34 +1,88 @@
 use std::{
-    collections::HashMap,
+    collections::{HashMap, VecDeque},
     convert::Infallible,
     net::SocketAddr,
-    ops::Deref,
     path::{Path, PathBuf},
-    sync::{Arc, Mutex, RwLock},
+    sync::{Arc, RwLock},
+    time::Duration,
 };
 
 use axum::{
-    extract::{Query, State},
+    extract::{
+        ws::{Message, WebSocket, WebSocketUpgrade},
+        ConnectInfo, DefaultBodyLimit, Query, State,
+    },
     http::{header, StatusCode},
-    response::{sse::Event, IntoResponse, Redirect, Sse},
+    middleware,
+    response::{sse::Event, IntoResponse, Redirect, Response, Sse},
     routing::{get, post, put},
     Json, Router,
 };
 use include_dir::Dir;
 use serde::{Deserialize, Serialize};
-use steamid_ng::SteamID;
-use tokio::sync::mpsc::{Sender, UnboundedSender};
-use tokio_stream::{wrappers::ReceiverStream, Stream};
+use tokio::{
+    select,
+    sync::{broadcast::error::RecvError, mpsc::UnboundedSender},
+};
+use tokio_stream::{wrappers::ReceiverStream, StreamExt};
 
 use crate::{
-    io::{Command, IOManagerMessage},
+    alts::{LinkReason, LinkStatus},
+    analytics::{TrendFilter, Trends},
+    demo::{
+        reprocess::Reprocessor,
+        storage::{self, DemoSpace, DemoStorage},
+        DemoManager,
+    },
+    doctor::Checkup,
+    events::{self, SystemEventLevel},
+    evidence::{
+        export::{self, ExportRequest},
+        EvidencePackage,
+    },
+    friendgraph,
+    groups::{FlaggedGroup, GroupMatch, SteamGroup},
+    interlocks::{self, AutomationLimits},
+    io::{
+        audit::{AuditFilter, CommandAudit, CommandOrigin},
+        noise::{self, ConsoleLineStats, NoiseFilter},
+        Command, IOManagerMessage,
+    },
+    lists::{
+        quarantine::{self, ListCorrection, QuarantineError},
+        ImportedLists,
+    },
+    locale::tr,
+    logging::{self, LogFilterError, LogFilterUpdate},
+    masterbase::{self, AuthStatus},
+    pause,
     player::Player,
-    player_records::Verdict,
-    server::Server,
+    player_records::{
+        archive::{ImportMode, RecordsArchive},
+        ChatSearch, Verdict,
+    },
+    secrets,
+    server::{Confirmed, Server},
     settings::{FriendsAPIUsage, Settings},
-    steamapi::SteamAPIMessage,
+    snapshot::{PendingPatch, SharedSnapshot, PATCH_INTERVAL},
+    steamapi::{
+        avatars::{AvatarCache, AvatarError},
+        connection::SteamConnection,
+        metrics, schema, LookupBatching, LookupPriority, SteamAPIMessage,
+    },
+    steamid::SteamID,
+    storage::StorageBackend,
+    suggestions::{SuggestionDecision, SuggestionError},
+    telemetry::TelemetryReport,
+    throughput::{self, PipelineStats},
+    votes::{Adjustment, Signal, SignalStats, VoteRecord},
+    webhooks::Webhook,
 };
 
+use self::ratelimit::RateLimiter;
+
+pub mod ratelimit;
+
 const HEADERS: [(header::HeaderName, &str); 2] = [
     (header::CONTENT_TYPE, "application/json"),
     (header::ACCESS_CONTROL_ALLOW_ORIGIN, "*"),
@@ -40,11 +94,58 @@ pub struct SharedState {
     pub io: UnboundedSender<IOManagerMessage>,
     pub api: UnboundedSender<SteamAPIMessage>,
     pub server: Arc<RwLock<Server>>,
+    pub snapshot: SharedSnapshot,
     pub settings: Arc<RwLock<Settings>>,
+    pub reprocessor: Reprocessor,
+    pub limiter: RateLimiter,
+    pub audit: CommandAudit,
+    pub avatars: AvatarCache,
 }
 
 type AState = axum::extract::State<SharedState>;
 
+impl SharedState {
+    /// Forward a message to the IO manager. Returns false (and logs) if it has shut down.
+    fn send_io(&self, message: IOManagerMessage) -> bool {
+        if let Err(e) = self.io.send(message) {
+            tracing::error!("Failed to forward {:?}, the IO manager has stopped.", e.0);
+            return false;
+        }
+        true
+    }
+
+    /// Forward a message to the Steam API manager. Returns false (and logs) if it has shut down.
+    fn send_api(&self, message: SteamAPIMessage) -> bool {
+        if let Err(e) = self.api.send(message) {
+            tracing::error!(
+                "Failed to forward {:?}, the Steam API manager has stopped.",
+                e.0
+            );
+            return false;
+        }
+        true
+    }
+}
+
+fn too_many_streams() -> Response {
+    (
+        StatusCode::TOO_MANY_REQUESTS,
+        HEADERS,
+        "Too many open streams",
+    )
+        .into_response()
+}
+
+/// Status to respond with once a request has been handled, depending on whether any messages
+/// could not be forwarded to the background tasks.
+fn forwarded_status(forwarded: bool) -> StatusCode {
+    if forwarded {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    }
+}
+
 /// Start the web API server
 pub async fn web_main(state: SharedState, port: u16) {
     let api = Router::new()
@@ -52,21 +153,71 @@ pub async fn web_main(state: SharedState, port: u16) {
         .route("/ui", get(ui_redirect))
         .route("/ui/*ui", get(get_ui))
         .route("/mac/game/v1", get(get_game))
+        .route("/mac/game/ws/v1", get(get_game_ws))
         .route("/mac/user/v1", post(post_user))
         .route("/mac/user/v1", put(put_user))
+        .route("/mac/friends/v1", post(post_friends))
+        .route("/mac/friends/lobby/v1", get(get_lobby_friends))
+        .route("/mac/friends/marked/v1", get(get_marked_friends))
+        .route("/mac/vanity/v1", post(post_vanity))
+        .route("/mac/player/:steamid/refresh/v1", post(post_player_refresh))
+        .route("/mac/player/:steamid/groups/v1", get(get_player_groups))
+        .route("/mac/avatar/:hash/v1", get(get_avatar))
+        .route("/mac/groups/flagged/v1", get(get_flagged_groups))
+        .route("/mac/groups/flagged/v1", put(put_flagged_groups))
         .route("/mac/pref/v1", get(get_prefs))
         .route("/mac/pref/v1", put(put_prefs))
+        .route("/mac/masterbase/login/v1", post(post_masterbase_login))
+        .route(masterbase::CALLBACK_PATH, get(get_masterbase_callback))
+        .route("/mac/masterbase/logout/v1", post(post_masterbase_logout))
         .route("/mac/game/events/v1", get(get_events))
         .route("/mac/history/v1", get(get_history))
         .route("/mac/playerlist/v1", get(get_playerlist))
+        .route("/mac/lists/v1", get(get_lists))
+        .route("/mac/lists/quarantine/v1", get(get_list_quarantine))
+        .route("/mac/lists/quarantine/v1", post(post_list_quarantine))
+        .route("/mac/analytics/v1", get(get_analytics))
+        .route("/mac/bans/v1", get(get_bans))
+        .route("/mac/steamapi/stats/v1", get(get_steamapi_stats))
+        .route("/mac/steamapi/drift/v1", get(get_steamapi_drift))
+        .route("/mac/votes/v1", get(get_votes))
+        .route("/mac/votes/v1", put(put_votes))
+        .route("/mac/links/v1", get(get_links))
+        .route("/mac/links/v1", put(put_links))
         .route("/mac/commands/v1", post(post_commands))
+        .route("/mac/commands/audit/v1", get(get_command_audit))
+        .route("/mac/evidence/v1", get(get_evidence))
+        .route("/mac/evidence/export/v1", post(post_evidence_export))
+        .route("/mac/records/export/v1", get(get_records_export))
+        .route("/mac/records/import/v1", post(post_records_import))
+        .route("/mac/reprocess/v1", get(get_reprocess))
+        .route("/mac/reprocess/v1", post(post_reprocess))
+        .route("/mac/demos/analyse/v1", post(post_demo_analyse))
+        .route("/mac/chat/v1", get(get_chat))
+        .route("/mac/telemetry/v1", get(get_telemetry))
+        .route("/mac/suggestions/v1", get(get_suggestions))
+        .route("/mac/suggestions/v1", put(put_suggestions))
+        .route("/mac/automation/v1", get(get_automation))
+        .route("/mac/automation/v1", put(put_automation))
+        .route("/mac/webhooks/v1", get(get_webhooks))
+        .route("/mac/webhooks/v1", put(put_webhooks))
+        .route("/mac/status/v1", get(get_status))
+        .route("/mac/doctor/v1", get(get_doctor))
+        .route("/mac/metrics/v1", get(get_metrics))
+        .route("/mac/logging/v1", get(get_logging))
+        .route("/mac/logging/v1", put(put_logging))
+        .layer(DefaultBodyLimit::max(ratelimit::MAX_BODY_SIZE))
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            ratelimit::limit,
+        ))
         .layer(tower_http::cors::CorsLayer::permissive())
         .with_state(state);
 
     let addr = SocketAddr::from(([127, 0, 0, 1], port));
     tracing::info!("Starting web interface at http://{addr}");
     axum::Server::bind(&addr)
-        .serve(api.into_make_service())
+        .serve(api.into_make_service_with_connect_info::<SocketAddr>())
         .await
         .expect("Failed to start web service");
 }
@@ -136,30 +287,242 @@ fn guess_content_type(path: &Path) -> &'static str {
 
 // Game
 
-/// API endpoint to retrieve the current server state
+/// API endpoint to retrieve the current server state. Served from the latest snapshot so that
+/// polling never has to wait for the server lock.
 async fn get_game(State(state): AState) -> impl IntoResponse {
     tracing::debug!("State requested");
-    let server = state.server.read().unwrap();
     (
         StatusCode::OK,
         HEADERS,
-        serde_json::to_string(server.deref()).expect("Serialize game state"),
+        state.snapshot.load().json.to_string(),
     )
 }
 
+/// Follow the game state over a WebSocket. The full state is sent first, followed by a JSON
+/// patch whenever it changes, at most every [PATCH_INTERVAL].
+async fn get_game_ws(
+    State(state): AState,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    ws: WebSocketUpgrade,
+) -> Response {
+    tracing::debug!("Game state subscription sent.");
+    let Some(guard) = state.limiter.open_stream(addr) else {
+        return too_many_streams();
+    };
+    ws.on_upgrade(move |socket| async move {
+        follow_game(socket, state.snapshot).await;
+        drop(guard);
+    })
+}
+
+async fn follow_game(mut socket: WebSocket, snapshot: SharedSnapshot) {
+    let mut diffs = snapshot.subscribe();
+    let current = snapshot.load();
+    // The version the client will be at once everything pending has been sent
+    let mut version = current.version;
+    let mut pending = PendingPatch::default();
+    let mut patch_interval = tokio::time::interval(PATCH_INTERVAL);
+    if socket
+        .send(Message::Text(current.full_update()))
+        .await
+        .is_err()
+    {
+        return;
+    }
+
+    loop {
+        select! {
+            diff = diffs.recv() => match diff {
+                Ok(diff) if diff.version <= version => {}
+                Ok(diff) if diff.from == version => {
+                    version = diff.version;
+                    pending.push(diff);
+                }
+                // Missed some changes, start again from the latest state
+                Ok(_) | Err(RecvError::Lagged(_)) => {
+                    pending.clear();
+                    let current = snapshot.load();
+                    version = current.version;
+                    if socket.send(Message::Text(current.full_update())).await.is_err() {
+                        return;
+                    }
+                }
+                Err(RecvError::Closed) => return,
+            },
+            _ = patch_interval.tick() => {
+                if let Some(message) = pending.take_message() {
+                    if socket.send(Message::Text(message)).await.is_err() {
+                        return;
+                    }
+                }
+            }
+            incoming = socket.recv() => match incoming {
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => return,
+                Some(Ok(_)) => {}
+            }
+        }
+    }
+}
+
 // User
 
 #[derive(Debug, Clone, Deserialize)]
-#[allow(dead_code)]
 struct UserRequest {
-    users: Vec<u64>,
+    users: Vec<SteamID>,
 }
 
-/// Posts a list of SteamIDs to lookup, returns the players.
-async fn post_user(users: Json<UserRequest>) -> impl IntoResponse {
+/// Posts a list of SteamIDs to lookup, returns the players. This includes players who have left
+/// and were looked up recently. Anyone the client doesn't already know about is left out, and
+/// looked up ahead of everything else so they can be requested again shortly.
+async fn post_user(State(state): AState, users: Json<UserRequest>) -> impl IntoResponse {
     tracing::debug!("Players requested: {:?}", users);
-    // TODO
-    (StatusCode::OK, HEADERS, "Not implemented".to_string())
+
+    let server = state.server.read().unwrap();
+    let mut players: Vec<Player> = Vec::new();
+    for steamid in &users.0.users {
+        match server.players().get_serializable_player(steamid) {
+            Some(player) => players.push(player),
+            None => {
+                state.send_api(SteamAPIMessage::Lookup(*steamid, LookupPriority::High));
+            }
+        }
+    }
+
+    (
+        StatusCode::OK,
+        HEADERS,
+        serde_json::to_string(&players).expect("Serialize players"),
+    )
+}
+
+/// Posts a list of SteamIDs whose friend lists should be fetched again, even if they were
+/// fetched recently.
+async fn post_friends(State(state): AState, users: Json<UserRequest>) -> impl IntoResponse {
+    tracing::debug!("Friend list refresh requested: {:?}", users);
+    let forwarded = state.send_api(SteamAPIMessage::ForceCheckFriends(users.0.users));
+    (forwarded_status(forwarded), HEADERS)
+}
+
+/// Fetches everything about a player again straight away, ignoring anything cached, for when the
+/// user is looking into them right now. Rules and heuristics are checked again once it arrives.
+async fn post_player_refresh(
+    State(state): AState,
+    axum::extract::Path(steamid): axum::extract::Path<String>,
+) -> impl IntoResponse {
+    tracing::debug!("Refresh requested for {}", steamid);
+    let Ok(steamid) = steamid.parse::<SteamID>() else {
+        return (StatusCode::BAD_REQUEST, HEADERS);
+    };
+    let forwarded = state.send_api(SteamAPIMessage::Refresh(steamid));
+    (forwarded_status(forwarded), HEADERS)
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct PlayerGroups<'a> {
+    /// `None` if they haven't been looked up yet
+    groups: Option<&'a [SteamGroup]>,
+    /// Groups that are flagged or shared with marked players
+    matches: Vec<GroupMatch>,
+}
+
+/// Gets the Steam groups a player is in. If they haven't been looked up yet, they are, and
+/// arrive with a later request.
+async fn get_player_groups(
+    State(state): AState,
+    axum::extract::Path(steamid): axum::extract::Path<String>,
+) -> impl IntoResponse {
+    tracing::debug!("Groups requested for {}", steamid);
+    let Ok(steamid) = steamid.parse::<SteamID>() else {
+        return (StatusCode::BAD_REQUEST, HEADERS, String::new());
+    };
+
+    let server = state.server.read().unwrap();
+    let groups = server.groups();
+    let mut forwarded = true;
+    if !groups.is_known(steamid) {
+        forwarded = state.send_api(SteamAPIMessage::CheckGroups(steamid));
+    }
+    let response = PlayerGroups {
+        groups: groups.get(steamid),
+        matches: groups.matches(steamid, &server.players().records),
+    };
+    (
+        forwarded_status(forwarded),
+        HEADERS,
+        serde_json::to_string(&response).expect("Serialize player groups"),
+    )
+}
+
+/// Gets the full size avatar with the given hash (a player's `pfpHash`), downloading it the first
+/// time it is asked for. Avatars never change, so browsers are told to keep them.
+async fn get_avatar(
+    State(state): AState,
+    axum::extract::Path(hash): axum::extract::Path<String>,
+) -> Response {
+    match state.avatars.get(&hash).await {
+        Ok(image) => (
+            StatusCode::OK,
+            [
+                (header::CONTENT_TYPE, "image/jpeg"),
+                (header::ACCESS_CONTROL_ALLOW_ORIGIN, "*"),
+                (header::CACHE_CONTROL, "public, max-age=31536000, immutable"),
+            ],
+            image,
+        )
+            .into_response(),
+        Err(AvatarError::InvalidHash) => {
+            (StatusCode::BAD_REQUEST, HEADERS, String::new()).into_response()
+        }
+        Err(e) => {
+            tracing::warn!("Failed to get avatar {}: {}", hash, e);
+            (StatusCode::BAD_GATEWAY, HEADERS, String::new()).into_response()
+        }
+    }
+}
+
+/// Gets groups of connected players who are friends with each other
+async fn get_lobby_friends(State(state): AState) -> impl IntoResponse {
+    let server = state.server.read().unwrap();
+    let groups = friendgraph::lobby_friend_groups(server.players());
+    (
+        StatusCode::OK,
+        HEADERS,
+        serde_json::to_string(&groups).expect("Serialize friend groups"),
+    )
+}
+
+#[derive(Debug, Deserialize)]
+struct MarkedFriendsRequest {
+    steamid: SteamID,
+}
+
+/// Gets the cheaters and bots a player is friends with or shares friends with
+async fn get_marked_friends(
+    State(state): AState,
+    request: Query<MarkedFriendsRequest>,
+) -> impl IntoResponse {
+    tracing::debug!("Marked friends requested for {}", request.steamid);
+    let server = state.server.read().unwrap();
+    let connections = friendgraph::marked_connections(server.players(), request.steamid);
+    (
+        StatusCode::OK,
+        HEADERS,
+        serde_json::to_string(&connections).expect("Serialize marked connections"),
+    )
+}
+
+#[derive(Debug, Deserialize)]
+struct VanityRequest {
+    vanity: String,
+}
+
+/// Posts a custom profile URL (or just its name) to find the account of. The account is looked
+/// up, and a system event says which it was.
+async fn post_vanity(State(state): AState, request: Json<VanityRequest>) -> impl IntoResponse {
+    tracing::debug!("Vanity URL resolution requested: {:?}", request);
+    let forwarded = state.send_api(SteamAPIMessage::ResolveVanityUrl(request.0.vanity));
+    (forwarded_status(forwarded), HEADERS)
 }
 
 #[derive(Debug, Deserialize)]
@@ -179,23 +542,22 @@ async fn put_user(
 
     let mut server = state.server.write().unwrap();
     for (k, v) in users.0 {
-        // Insert record if it didn't exist
-        let record = server.players_mut().records.entry(k).or_default();
-
         if let Some(custom_data) = v.custom_data {
-            record.custom_data = custom_data;
+            // Insert record if it didn't exist
+            let records = &mut server.players_mut().records;
+            records.entry(k).or_default().custom_data = custom_data;
+            if records.get(&k).is_some_and(|r| r.is_empty()) {
+                records.remove(&k);
+            }
         }
 
         if let Some(verdict) = v.local_verdict {
-            record.verdict = verdict;
-        }
-
-        if record.is_empty() {
-            server.players_mut().records.remove(&k);
+            server.players_mut().set_verdict(k, verdict);
         }
     }
 
     server.players().records.save_ok();
+    state.snapshot.publish(&server);
 
     (StatusCode::OK, HEADERS)
 }
@@ -206,10 +568,43 @@ async fn put_user(
 #[serde(rename_all = "camelCase")]
 struct InternalPreferences {
     pub friends_api_usage: Option<FriendsAPIUsage>,
+    /// Seconds
+    pub friends_cache_ttl: Option<u64>,
+    /// Seconds, 0 to not cache Steam profiles
+    pub steam_info_cache_ttl: Option<u64>,
+    /// Private friend lists that may be checked again each session
+    pub private_recheck_budget: Option<u32>,
+    /// Scrape community profiles when there is no valid Steam API key
+    pub profile_scraping: Option<bool>,
+    pub lookup_batching: Option<LookupBatching>,
+    /// Proxy and base URL for requests to Steam
+    pub steam_connection: Option<SteamConnection>,
+    /// Takes effect on restart
+    pub storage_backend: Option<StorageBackend>,
+    /// Days, 0 to keep chat forever
+    pub chat_retention_days: Option<u64>,
+    /// Opt in to submitting anonymous statistics
+    pub telemetry: Option<bool>,
+    pub command_allowlist: Option<Vec<Arc<str>>>,
+    /// Classes of console lines dropped before they are parsed
+    pub noise_filters: Option<Vec<NoiseFilter>>,
+    /// Answer `!who` commands from party members in party chat
+    pub party_commands: Option<bool>,
     pub tf2_directory: Option<Arc<str>>,
     pub rcon_password: Option<Arc<str>>,
     pub steam_api_key: Option<Arc<str>>,
+    /// Taken in turns with `steam_api_key` for lookups
+    pub extra_steam_api_keys: Option<Vec<Arc<str>>>,
     pub rcon_port: Option<u16>,
+    pub locale: Option<Arc<str>>,
+    pub demo_storage: Option<DemoStorage>,
+    /// Milliseconds, 0 to never suppress duplicate events
+    pub event_dedup_window_ms: Option<u64>,
+    /// Empty to not use a masterbase
+    pub masterbase_endpoint: Option<Arc<str>>,
+    /// Whether the user is signed in to the masterbase. Can't be set, see `/mac/masterbase/`.
+    #[serde(skip_deserializing)]
+    pub masterbase_auth: Option<AuthStatus>,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -226,10 +621,39 @@ async fn get_prefs(State(state): AState) -> impl IntoResponse {
     let prefs = Preferences {
         internal: Some(InternalPreferences {
             friends_api_usage: Some(*settings.get_friends_api_usage()),
+            friends_cache_ttl: Some(settings.get_friends_cache_ttl().as_secs()),
+            steam_info_cache_ttl: Some(settings.get_steam_info_cache_ttl().as_secs()),
+            private_recheck_budget: Some(settings.get_private_recheck_budget()),
+            profile_scraping: Some(settings.get_profile_scraping()),
+            lookup_batching: Some(settings.get_lookup_batching()),
+            steam_connection: Some(settings.get_steam_connection()),
+            storage_backend: Some(settings.get_storage_backend()),
+            chat_retention_days: Some(
+                settings
+                    .get_chat_retention()
+                    .map(|r| r.as_secs() / (24 * 60 * 60))
+                    .unwrap_or(0),
+            ),
+            telemetry: Some(settings.get_telemetry()),
+            command_allowlist: Some(settings.get_command_allowlist().commands().to_vec()),
+            noise_filters: Some(settings.get_noise_filters()),
+            party_commands: Some(settings.get_party_commands()),
             tf2_directory: Some(settings.get_tf2_directory().to_string_lossy().into()),
             rcon_password: Some(settings.get_rcon_password()),
-            steam_api_key: Some(settings.get_steam_api_key()),
+            steam_api_key: Some(secrets::redact(&settings.get_steam_api_key())),
+            extra_steam_api_keys: Some(
+                settings
+                    .get_extra_steam_api_keys()
+                    .iter()
+                    .map(|key| secrets::redact(key))
+                    .collect(),
+            ),
             rcon_port: Some(settings.get_rcon_port()),
+            locale: Some(settings.get_locale()),
+            demo_storage: Some(settings.get_demo_storage()),
+            event_dedup_window_ms: Some(settings.get_event_dedup_window().as_millis() as u64),
+            masterbase_endpoint: Some(settings.get_masterbase_endpoint().unwrap_or_default()),
+            masterbase_auth: Some(AuthStatus::new(&settings)),
         }),
         external: Some(settings.get_external_preferences().clone()),
     };
@@ -246,41 +670,116 @@ async fn put_prefs(State(state): AState, prefs: Json<Preferences>) -> impl IntoR
     tracing::debug!("Preferences updates sent.");
 
     let mut settings = state.settings.write().unwrap();
+    let mut forwarded = true;
     if let Some(internal) = prefs.0.internal {
         if let Some(tf2_dir) = internal.tf2_directory {
             let path: PathBuf = tf2_dir.to_string().into();
-            state
-                .io
-                .send(IOManagerMessage::SetLogFilePath(
-                    path.join("tf/console.log"),
-                ))
-                .unwrap();
+            let log_file_path = settings.get_game_profile().log_file_path(&path);
+            forwarded &= state.send_io(IOManagerMessage::SetLogFilePath(log_file_path));
             settings.set_tf2_directory(path);
         }
         if let Some(rcon_pwd) = internal.rcon_password {
-            state
-                .io
-                .send(IOManagerMessage::SetRconPassword(rcon_pwd.clone()))
-                .unwrap();
+            forwarded &= state.send_io(IOManagerMessage::SetRconPassword(rcon_pwd.clone()));
             settings.set_rcon_password(rcon_pwd);
         }
         if let Some(rcon_port) = internal.rcon_port {
-            state
-                .io
-                .send(IOManagerMessage::SetRconPort(rcon_port))
-                .unwrap();
+            forwarded &= state.send_io(IOManagerMessage::SetRconPort(rcon_port));
             settings.set_rcon_port(rcon_port);
         }
-        if let Some(steam_api_key) = internal.steam_api_key {
-            state
-                .api
-                .send(SteamAPIMessage::SetAPIKey(steam_api_key.clone()))
-                .unwrap();
+        // Keys are only ever sent out redacted, so one that comes back that way is unchanged
+        if let Some(steam_api_key) = internal
+            .steam_api_key
+            .filter(|key| !secrets::is_redacted(key))
+        {
+            forwarded &= state.send_api(SteamAPIMessage::SetAPIKey(steam_api_key.clone()));
             settings.set_steam_api_key(steam_api_key);
         }
+        if let Some(keys) = internal.extra_steam_api_keys {
+            let current = settings.get_extra_steam_api_keys();
+            let keys: Vec<Arc<str>> = keys
+                .into_iter()
+                .filter_map(|key| {
+                    if !secrets::is_redacted(&key) {
+                        return Some(key);
+                    }
+                    current
+                        .iter()
+                        .find(|current| secrets::redact(current) == key)
+                        .cloned()
+                })
+                .collect();
+            forwarded &= state.send_api(SteamAPIMessage::SetExtraAPIKeys(keys.clone()));
+            settings.set_extra_steam_api_keys(keys);
+        }
         if let Some(friends_api_usage) = internal.friends_api_usage {
             settings.set_friends_api_usage(friends_api_usage);
         }
+        if let Some(ttl) = internal.friends_cache_ttl {
+            let ttl = Duration::from_secs(ttl);
+            forwarded &= state.send_api(SteamAPIMessage::SetFriendsCacheTTL(ttl));
+            settings.set_friends_cache_ttl(ttl);
+        }
+        if let Some(ttl) = internal.steam_info_cache_ttl {
+            let ttl = Duration::from_secs(ttl);
+            forwarded &= state.send_api(SteamAPIMessage::SetSteamInfoCacheTTL(ttl));
+            settings.set_steam_info_cache_ttl(ttl);
+        }
+        if let Some(budget) = internal.private_recheck_budget {
+            forwarded &= state.send_api(SteamAPIMessage::SetPrivateRecheckBudget(budget));
+            settings.set_private_recheck_budget(budget);
+        }
+        if let Some(enabled) = internal.profile_scraping {
+            forwarded &= state.send_api(SteamAPIMessage::SetProfileScraping(enabled));
+            settings.set_profile_scraping(enabled);
+        }
+        if let Some(batching) = internal.lookup_batching {
+            settings.set_lookup_batching(batching);
+            forwarded &= state.send_api(SteamAPIMessage::Configure(settings.get_lookup_batching()));
+        }
+        if let Some(connection) = internal.steam_connection {
+            forwarded &= state.send_api(SteamAPIMessage::SetConnection(connection.clone()));
+            settings.set_steam_connection(connection);
+        }
+        if let Some(backend) = internal.storage_backend {
+            settings.set_storage_backend(backend);
+        }
+        if let Some(days) = internal.chat_retention_days {
+            settings.set_chat_retention_days(days);
+            let mut server = state.server.write().unwrap();
+            let records = &mut server.players_mut().records;
+            records.set_chat_retention(settings.get_chat_retention());
+            records.save_if_dirty();
+        }
+        if let Some(commands) = internal.command_allowlist {
+            settings.set_command_allowlist(commands);
+            forwarded &= state.send_io(IOManagerMessage::SetCommandAllowlist(
+                settings.get_command_allowlist(),
+            ));
+        }
+        if let Some(filters) = internal.noise_filters {
+            forwarded &= state.send_io(IOManagerMessage::SetNoiseFilters(filters.clone()));
+            settings.set_noise_filters(filters);
+        }
+        if let Some(enabled) = internal.party_commands {
+            settings.set_party_commands(enabled);
+            state.server.write().unwrap().set_party_commands(enabled);
+        }
+        if let Some(telemetry) = internal.telemetry {
+            settings.set_telemetry(telemetry);
+        }
+        if let Some(locale) = internal.locale {
+            settings.set_locale(locale);
+        }
+        if let Some(storage) = internal.demo_storage {
+            settings.set_demo_storage(storage);
+        }
+        if let Some(window) = internal.event_dedup_window_ms {
+            settings.set_event_dedup_window(Duration::from_millis(window));
+        }
+        if let Some(endpoint) = internal.masterbase_endpoint {
+            let endpoint = endpoint.trim_end_matches('/');
+            settings.set_masterbase_endpoint((!endpoint.is_empty()).then(|| endpoint.into()));
+        }
     }
 
     if let Some(external) = prefs.0.external {
@@ -289,48 +788,52 @@ async fn put_prefs(State(state): AState, prefs: Json<Preferences>) -> impl IntoR
 
     settings.save_ok();
 
-    (StatusCode::OK, HEADERS)
+    (forwarded_status(forwarded), HEADERS)
 }
 
 // Events
 
-type Subscriber = Sender<Result<Event, Infallible>>;
-static SUBSCRIBERS: Mutex<Option<Vec<Subscriber>>> = Mutex::new(None);
-
 /// Gets a SSE stream to listen for any updates the client can provide.
-async fn get_events() -> Sse<impl Stream<Item = Result<Event, Infallible>>> {
+async fn get_events(State(state): AState, ConnectInfo(addr): ConnectInfo<SocketAddr>) -> Response {
     tracing::debug!("Events subcription sent.");
+    let Some(guard) = state.limiter.open_stream(addr) else {
+        return too_many_streams();
+    };
 
-    let (tx, rx) = tokio::sync::mpsc::channel::<Result<Event, Infallible>>(16);
-
-    let mut subscribers = SUBSCRIBERS.lock().unwrap();
-    if subscribers.is_none() {
-        *subscribers = Some(Vec::new());
-    }
-
-    subscribers.as_mut().unwrap().push(tx);
-
-    Sse::new(ReceiverStream::new(rx))
+    // The guard is dropped along with the stream once the client disconnects
+    let events: ReceiverStream<Result<Event, Infallible>> =
+        ReceiverStream::new(events::subscribe());
+    Sse::new(events.map(move |event| {
+        let _guard = &guard;
+        event
+    }))
+    .into_response()
 }
 
 // History
 
 #[derive(Deserialize)]
 #[serde(default)]
-struct Pagination {
+struct HistoryRequest {
     pub from: usize,
     pub to: usize,
+    /// Only include players last seen on this map
+    pub map: Option<Arc<str>>,
 }
 
-impl Default for Pagination {
+impl Default for HistoryRequest {
     fn default() -> Self {
-        Pagination { from: 0, to: 100 }
+        HistoryRequest {
+            from: 0,
+            to: 100,
+            map: None,
+        }
     }
 }
 
 /// Gets a historical record of the last (up to) 100 players that the user has
 /// been on servers with.
-async fn get_history(State(state): AState, page: Query<Pagination>) -> impl IntoResponse {
+async fn get_history(State(state): AState, page: Query<HistoryRequest>) -> impl IntoResponse {
     tracing::debug!("History requested");
 
     let server = state.server.read().unwrap();
@@ -340,9 +843,10 @@ async fn get_history(State(state): AState, page: Query<Pagination>) -> impl Into
         .history
         .iter()
         .rev()
+        .flat_map(|s| server.players().get_serializable_player(s))
+        .filter(|p| page.map.is_none() || p.gameInfo.is_some_and(|gi| gi.map == page.map))
         .skip(page.0.from)
         .take(page.0.to - page.0.from)
-        .flat_map(|s| server.players().get_serializable_player(s))
         .collect();
 
     (
@@ -362,6 +866,199 @@ async fn get_playerlist(State(state): AState) -> impl IntoResponse {
             .expect("Serialize player records"),
     )
 }
+
+/// Gets every imported list along with how often it has matched players and how often
+/// the user has agreed with it.
+async fn get_lists(State(state): AState) -> impl IntoResponse {
+    tracing::debug!("Imported lists requested");
+    let server = state.server.read().unwrap();
+    (
+        StatusCode::OK,
+        HEADERS,
+        serde_json::to_string(&server.players().lists.summaries())
+            .expect("Serialize imported lists"),
+    )
+}
+
+/// Gets every entry of the imported lists that couldn't be imported, along with why
+async fn get_list_quarantine(State(state): AState) -> impl IntoResponse {
+    tracing::debug!("List quarantine requested");
+    let server = state.server.read().unwrap();
+    (
+        StatusCode::OK,
+        HEADERS,
+        serde_json::to_string(&server.players().lists.quarantine())
+            .expect("Serialize list quarantine"),
+    )
+}
+
+/// Posts a corrected version of a quarantined entry, which is written back into the list file
+/// and imported when the list is reloaded
+async fn post_list_quarantine(correction: Json<ListCorrection>) -> impl IntoResponse {
+    tracing::debug!("List correction posted: {:?}", correction.0);
+    let result = ImportedLists::locate_lists_directory()
+        .map_err(QuarantineError::from)
+        .and_then(|dir| quarantine::correct(&dir, correction.0));
+    match result {
+        Ok(()) => (StatusCode::OK, HEADERS, String::new()),
+        Err(e @ QuarantineError::Invalid(_)) => (StatusCode::BAD_REQUEST, HEADERS, e.to_string()),
+        Err(e @ (QuarantineError::UnknownList(_) | QuarantineError::UnknownPosition(_))) => {
+            (StatusCode::NOT_FOUND, HEADERS, e.to_string())
+        }
+        Err(e @ QuarantineError::File(_)) => {
+            tracing::error!("Failed to correct list entry: {}", e);
+            (StatusCode::INTERNAL_SERVER_ERROR, HEADERS, e.to_string())
+        }
+    }
+}
+
+/// Gets trends in the bots and cheaters encountered over the session history, by time of day,
+/// map, gamemode and region.
+async fn get_analytics(State(state): AState, filter: Query<TrendFilter>) -> impl IntoResponse {
+    tracing::debug!("Analytics requested");
+    let server = state.server.read().unwrap();
+    let trends = Trends::compute(server.sessions(), &server.players().records, &filter);
+    (
+        StatusCode::OK,
+        HEADERS,
+        serde_json::to_string(&trends).expect("Serialize analytics"),
+    )
+}
+
+/// Gets how many of the accounts the user has marked as cheaters, bots or suspicious Valve has
+/// since banned
+async fn get_bans(State(state): AState) -> impl IntoResponse {
+    tracing::debug!("Ban stats requested");
+    let stats = state.server.read().unwrap().players().records.ban_stats();
+    (
+        StatusCode::OK,
+        HEADERS,
+        serde_json::to_string(&stats).expect("Serialize ban stats"),
+    )
+}
+
+/// Gets the requests made to the Steam Web API, and how much of today's quota they have used
+async fn get_steamapi_stats() -> impl IntoResponse {
+    tracing::debug!("Steam API stats requested");
+    (
+        StatusCode::OK,
+        HEADERS,
+        serde_json::to_string(&metrics::stats()).expect("Serialize Steam API stats"),
+    )
+}
+
+/// Recent Steam API responses that didn't have the expected format, see [schema]
+async fn get_steamapi_drift() -> impl IntoResponse {
+    tracing::debug!("Steam API schema drift requested");
+    (
+        StatusCode::OK,
+        HEADERS,
+        serde_json::to_string(&schema::recent()).expect("Serialize Steam API schema drift"),
+    )
+}
+
+#[derive(Serialize)]
+struct VotesResponse<'a> {
+    signals: &'a [SignalStats],
+    audit: &'a VecDeque<Adjustment>,
+    history: &'a VecDeque<VoteRecord>,
+}
+
+/// Gets how each rule, list and heuristic has fared in votekicks, along with a log of every
+/// automatic adjustment made to their weights and the most recent votes with a snapshot of the
+/// game when each was called.
+async fn get_votes(State(state): AState) -> impl IntoResponse {
+    tracing::debug!("Vote feedback requested");
+    let server = state.server.read().unwrap();
+    let votes = VotesResponse {
+        signals: server.votes().signals(),
+        audit: server.votes().audit(),
+        history: server.votes().history(),
+    };
+    (
+        StatusCode::OK,
+        HEADERS,
+        serde_json::to_string(&votes).expect("Serialize vote feedback"),
+    )
+}
+
+#[derive(Deserialize, Debug)]
+struct ReviewedSignals {
+    reviewed: Vec<Signal>,
+}
+
+/// Marks signals that were flagged for review as having been reviewed
+async fn put_votes(State(state): AState, signals: Json<ReviewedSignals>) -> impl IntoResponse {
+    tracing::debug!("Reviewed signals sent: {:?}", signals);
+    let mut server = state.server.write().unwrap();
+    for signal in &signals.reviewed {
+        server.votes_mut().clear_review(signal);
+    }
+    (StatusCode::OK, HEADERS)
+}
+
+// Alt accounts
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct SuggestedLink<'a> {
+    steamid: SteamID,
+    linked: SteamID,
+    reasons: &'a [LinkReason],
+    suggested: u64,
+}
+
+/// Gets every suggested link between accounts that hasn't been confirmed or dismissed yet.
+/// Each link is only listed once, even though it is stored on both records.
+async fn get_links(State(state): AState) -> impl IntoResponse {
+    tracing::debug!("Account links requested");
+    let server = state.server.read().unwrap();
+    let links: Vec<SuggestedLink> = server
+        .players()
+        .records
+        .iter()
+        .flat_map(|(&steamid, record)| {
+            record
+                .linked_accounts
+                .iter()
+                .filter(move |link| link.status == LinkStatus::Suggested && steamid < link.steamid)
+                .map(move |link| SuggestedLink {
+                    steamid,
+                    linked: link.steamid,
+                    reasons: &link.reasons,
+                    suggested: link.suggested,
+                })
+        })
+        .collect();
+    (
+        StatusCode::OK,
+        HEADERS,
+        serde_json::to_string(&links).expect("Serialize account links"),
+    )
+}
+
+#[derive(Deserialize, Debug)]
+struct LinkDecision {
+    steamid: SteamID,
+    linked: SteamID,
+    status: LinkStatus,
+}
+
+/// Confirms or dismisses a suggested link between two accounts
+async fn put_links(State(state): AState, decision: Json<LinkDecision>) -> impl IntoResponse {
+    tracing::debug!("Account link decision sent: {:?}", decision);
+    let found = state.server.write().unwrap().set_link_status(
+        decision.steamid,
+        decision.linked,
+        decision.status,
+    );
+    if found {
+        (StatusCode::OK, HEADERS, "").into_response()
+    } else {
+        (StatusCode::NOT_FOUND, HEADERS, "Link not found").into_response()
+    }
+}
+
 // Commands
 
 #[derive(Deserialize, Debug)]
@@ -375,12 +1072,570 @@ async fn post_commands(
 ) -> impl IntoResponse {
     tracing::debug!("Commands sent: {:?}", commands);
 
+    let mut forwarded = true;
     for command in commands.0.commands {
-        state
-            .io
-            .send(IOManagerMessage::RunCommand(command))
-            .unwrap();
+        if let Command::Kick { player, .. } = &command {
+            state.server.write().unwrap().vote_called(player);
+        }
+        forwarded &= state.send_io(IOManagerMessage::RunCommand(command, CommandOrigin::User));
     }
 
+    (forwarded_status(forwarded), HEADERS)
+}
+
+/// Gets the most recent commands sent over RCON (or blocked by the allowlist), most recent first
+async fn get_command_audit(State(state): AState, filter: Query<AuditFilter>) -> impl IntoResponse {
+    (
+        StatusCode::OK,
+        HEADERS,
+        serde_json::to_string(&state.audit.query(&filter)).expect("Serialize command audit"),
+    )
+}
+
+// Evidence
+
+#[derive(Deserialize, Debug)]
+struct EvidenceRequest {
+    steamid: SteamID,
+}
+
+/// Builds a zip archive of everything known about a player, ready to be attached to a report
+async fn get_evidence(State(state): AState, request: Query<EvidenceRequest>) -> impl IntoResponse {
+    tracing::debug!("Evidence requested for {}", request.steamid);
+
+    let package = EvidencePackage::build(&state.server.read().unwrap(), request.steamid);
+    let Some(package) = package else {
+        return (StatusCode::NOT_FOUND, HEADERS, "Player not found").into_response();
+    };
+
+    match package.to_zip() {
+        Ok(zip) => {
+            let headers = [
+                (header::CONTENT_TYPE, "application/zip".to_string()),
+                (
+                    header::CONTENT_DISPOSITION,
+                    format!("attachment; filename=\"evidence-{}.zip\"", package.steamid),
+                ),
+                (header::ACCESS_CONTROL_ALLOW_ORIGIN, "*".to_string()),
+            ];
+            (StatusCode::OK, headers, zip).into_response()
+        }
+        Err(e) => {
+            tracing::error!("Failed to build evidence package: {:?}", e);
+            (StatusCode::INTERNAL_SERVER_ERROR, HEADERS).into_response()
+        }
+    }
+}
+
+/// Renders a selection of chat lines or kills as a block of text to paste into a report
+async fn post_evidence_export(
+    State(state): AState,
+    request: Json<ExportRequest>,
+) -> impl IntoResponse {
+    tracing::debug!("Evidence export requested: {:?}", request.0);
+
+    let headers = [
+        (header::CONTENT_TYPE, "text/plain; charset=utf-8"),
+        (header::ACCESS_CONTROL_ALLOW_ORIGIN, "*"),
+    ];
+    match export::render(&state.server.read().unwrap(), &request) {
+        Some(text) => (StatusCode::OK, headers, text),
+        None => (
+            StatusCode::NOT_FOUND,
+            headers,
+            "Nothing matched".to_string(),
+        ),
+    }
+}
+
+// Records archive
+
+/// Exports the whole records database in the native archive format, see
+/// [crate::player_records::archive]
+async fn get_records_export(State(state): AState) -> impl IntoResponse {
+    let archive = state.server.read().unwrap().players().records.export();
+    (
+        StatusCode::OK,
+        HEADERS,
+        serde_json::to_string(&archive).expect("Serialize records archive"),
+    )
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct ImportQuery {
+    mode: ImportMode,
+}
+
+/// Imports a records archive, merging it into the records database or replacing it
+async fn post_records_import(
+    State(state): AState,
+    query: Query<ImportQuery>,
+    archive: Json<RecordsArchive>,
+) -> impl IntoResponse {
+    tracing::debug!("Records import requested: {:?}", query.0);
+
+    let mut server = state.server.write().unwrap();
+    let records = &mut server.players_mut().records;
+    match records.import(archive.0, query.mode) {
+        Ok(summary) => {
+            tracing::info!("Imported records archive: {:?}", summary);
+            records.save_if_dirty();
+            state.snapshot.publish(&server);
+            (
+                StatusCode::OK,
+                HEADERS,
+                serde_json::to_string(&summary).expect("Serialize import summary"),
+            )
+        }
+        Err(e) => (StatusCode::BAD_REQUEST, HEADERS, e.to_string()),
+    }
+}
+
+// Reprocessing
+
+/// Gets the progress of the current (or last) job re-running the demo analysers over recordings
+async fn get_reprocess(State(state): AState) -> impl IntoResponse {
+    (
+        StatusCode::OK,
+        HEADERS,
+        serde_json::to_string(&state.reprocessor.status()).expect("Serialize reprocess status"),
+    )
+}
+
+/// Starts re-running the demo analysers over the recordings of every past session, replacing the
+/// demo suspicion on player records with the new results
+async fn post_reprocess(State(state): AState) -> impl IntoResponse {
+    tracing::debug!("Reprocessing requested");
+    let recordings: Vec<_> = state
+        .server
+        .read()
+        .unwrap()
+        .sessions()
+        .sessions()
+        .iter()
+        .flat_map(|session| session.recordings.iter().cloned())
+        .collect();
+
+    if state.reprocessor.start(recordings) {
+        (StatusCode::OK, HEADERS, "")
+    } else {
+        (StatusCode::CONFLICT, HEADERS, "Already reprocessing")
+    }
+}
+
+// Offline demo analysis
+
+#[derive(Debug, Deserialize)]
+struct DemoAnalyseRequest {
+    /// The demo file to analyse
+    path: PathBuf,
+}
+
+/// Analyses a complete demo file, e.g. one of a suspected cheater from a past match, see
+/// [DemoManager::analyse_file]
+async fn post_demo_analyse(request: Json<DemoAnalyseRequest>) -> impl IntoResponse {
+    tracing::debug!("Demo analysis requested: {:?}", request.path);
+    let path = request.0.path;
+    match tokio::task::spawn_blocking(move || DemoManager::analyse_file(&path)).await {
+        Ok(Ok(analysis)) => (
+            StatusCode::OK,
+            HEADERS,
+            serde_json::to_string(&analysis).expect("Serialize demo analysis"),
+        ),
+        Ok(Err(e)) => (StatusCode::BAD_REQUEST, HEADERS, format!("{:?}", e)),
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, HEADERS, e.to_string()),
+    }
+}
+
+// Chat
+
+/// Search the chat messages stored on player records, most recent first
+async fn get_chat(State(state): AState, search: Query<ChatSearch>) -> impl IntoResponse {
+    tracing::debug!("Chat search requested: {:?}", search.0);
+    let results = state
+        .server
+        .read()
+        .unwrap()
+        .players()
+        .records
+        .search_chat(&search);
+    (
+        StatusCode::OK,
+        HEADERS,
+        serde_json::to_string(&results).expect("Serialize chat"),
+    )
+}
+
+// Telemetry
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct TelemetryPreview {
+    /// Whether the report will be submitted at the end of the period
+    enabled: bool,
+    endpoint: Option<Arc<str>>,
+    report: TelemetryReport,
+}
+
+/// Shows exactly what would be submitted as anonymous statistics if the period ended now
+async fn get_telemetry(State(state): AState) -> impl IntoResponse {
+    let endpoint = state.settings.read().unwrap().get_telemetry_endpoint();
+    let preview = TelemetryPreview {
+        enabled: endpoint.is_some(),
+        endpoint,
+        report: state.server.read().unwrap().telemetry().report(),
+    };
+
+    (
+        StatusCode::OK,
+        HEADERS,
+        serde_json::to_string(&preview).expect("Serialize telemetry"),
+    )
+}
+
+// Suggestions
+
+/// Gets the actions automation has suggested that are waiting on the user, oldest first
+async fn get_suggestions(State(state): AState) -> impl IntoResponse {
+    (
+        StatusCode::OK,
+        HEADERS,
+        serde_json::to_string(state.server.read().unwrap().suggestions())
+            .expect("Serialize suggestions"),
+    )
+}
+
+#[derive(Deserialize, Debug)]
+struct SuggestionReply {
+    id: u64,
+    decision: SuggestionDecision,
+}
+
+/// Confirms or dismisses a suggested action. Confirming carries it out, or for a report responds
+/// with the evidence to attach.
+async fn put_suggestions(State(state): AState, reply: Json<SuggestionReply>) -> impl IntoResponse {
+    tracing::debug!("Suggestion decision sent: {:?}", reply);
+
+    let mut server = state.server.write().unwrap();
+    let confirmed = match server.decide_suggestion(reply.id, reply.decision) {
+        Ok(confirmed) => confirmed,
+        Err(e @ SuggestionError::NotFound) => {
+            return (StatusCode::NOT_FOUND, HEADERS, e.to_string()).into_response()
+        }
+        Err(e) => return (StatusCode::CONFLICT, HEADERS, e.to_string()).into_response(),
+    };
+
+    let response = match confirmed {
+        None | Some(Confirmed::Marked) => {
+            server.players().records.save_ok();
+            (StatusCode::OK, HEADERS, String::new())
+        }
+        Some(Confirmed::Votekick(command)) => {
+            let forwarded =
+                state.send_io(IOManagerMessage::RunCommand(command, CommandOrigin::User));
+            (forwarded_status(forwarded), HEADERS, String::new())
+        }
+        Some(Confirmed::Report(package)) => (
+            StatusCode::OK,
+            HEADERS,
+            serde_json::to_string(&package).expect("Serialize evidence package"),
+        ),
+    };
+    state.snapshot.publish(&server);
+    response.into_response()
+}
+
+// Automation
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct AutomationStatus {
+    enabled: bool,
+    limits: AutomationLimits,
+    votekicks_last_hour: usize,
+}
+
+#[derive(Deserialize)]
+struct AutomationUpdate {
+    /// Master kill switch for every in-game action the client takes by itself
+    enabled: Option<bool>,
+    limits: Option<AutomationLimits>,
+}
+
+fn automation_status(state: &SharedState) -> AutomationStatus {
+    let server = state.server.read().unwrap();
+    AutomationStatus {
+        enabled: interlocks::automation_enabled(),
+        limits: server.interlocks().limits().clone(),
+        votekicks_last_hour: server.interlocks().votekicks_last_hour(),
+    }
+}
+
+/// Gets whether in-game automation is enabled and the limits it is held to
+async fn get_automation(State(state): AState) -> impl IntoResponse {
+    (
+        StatusCode::OK,
+        HEADERS,
+        serde_json::to_string(&automation_status(&state)).expect("Serialize automation status"),
+    )
+}
+
+/// Turns in-game automation on or off and changes the limits it is held to
+async fn put_automation(State(state): AState, update: Json<AutomationUpdate>) -> impl IntoResponse {
+    {
+        let mut settings = state.settings.write().unwrap();
+        if let Some(enabled) = update.enabled {
+            settings.set_automation_enabled(enabled);
+        }
+        if let Some(limits) = &update.limits {
+            settings.set_automation_limits(limits.clone());
+            state
+                .server
+                .write()
+                .unwrap()
+                .interlocks_mut()
+                .set_limits(limits.clone());
+        }
+        settings.save_ok();
+    }
+
+    (
+        StatusCode::OK,
+        HEADERS,
+        serde_json::to_string(&automation_status(&state)).expect("Serialize automation status"),
+    )
+}
+
+// Webhooks
+
+/// A webhook as shown to the UI, without its token
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct WebhookView {
+    url: Arc<str>,
+    has_token: bool,
+}
+
+fn webhook_views(settings: &Settings) -> Vec<WebhookView> {
+    settings
+        .get_mark_webhooks()
+        .into_iter()
+        .map(|webhook| WebhookView {
+            url: webhook.url,
+            has_token: webhook.token.is_some(),
+        })
+        .collect()
+}
+
+/// Gets the webhooks the user's marks are sent to
+async fn get_webhooks(State(state): AState) -> impl IntoResponse {
+    let webhooks = webhook_views(&state.settings.read().unwrap());
+    (
+        StatusCode::OK,
+        HEADERS,
+        serde_json::to_string(&webhooks).expect("Serialize webhooks"),
+    )
+}
+
+/// Replaces the webhooks the user's marks are sent to
+async fn put_webhooks(State(state): AState, webhooks: Json<Vec<Webhook>>) -> impl IntoResponse {
+    let mut settings = state.settings.write().unwrap();
+    settings.set_mark_webhooks(webhooks.0);
+    settings.save_ok();
+
+    (
+        StatusCode::OK,
+        HEADERS,
+        serde_json::to_string(&webhook_views(&settings)).expect("Serialize webhooks"),
+    )
+}
+
+/// Gets the Steam groups the user has flagged as bot hosters or cheating communities
+async fn get_flagged_groups(State(state): AState) -> impl IntoResponse {
+    let groups = state.settings.read().unwrap().get_flagged_groups();
+    (
+        StatusCode::OK,
+        HEADERS,
+        serde_json::to_string(&groups).expect("Serialize flagged groups"),
+    )
+}
+
+/// Replaces the Steam groups the user has flagged
+async fn put_flagged_groups(
+    State(state): AState,
+    groups: Json<Vec<FlaggedGroup>>,
+) -> impl IntoResponse {
+    let mut settings = state.settings.write().unwrap();
+    settings.set_flagged_groups(groups.0);
+    settings.save_ok();
+    state
+        .server
+        .write()
+        .unwrap()
+        .groups_mut()
+        .set_flagged(settings.get_flagged_groups());
+
+    (
+        StatusCode::OK,
+        HEADERS,
+        serde_json::to_string(&settings.get_flagged_groups()).expect("Serialize flagged groups"),
+    )
+}
+
+// Masterbase
+
+#[derive(Serialize)]
+struct MasterbaseLogin {
+    /// The Steam page to sign in on
+    url: String,
+}
+
+/// Starts signing in to the masterbase, returning the Steam page the user has to sign in on.
+/// Steam sends them back to [get_masterbase_callback] afterwards.
+async fn post_masterbase_login(State(state): AState) -> impl IntoResponse {
+    tracing::debug!("Masterbase sign in requested");
+    let settings = state.settings.read().unwrap();
+    if settings.get_masterbase_endpoint().is_none() {
+        return (StatusCode::CONFLICT, HEADERS, String::new());
+    }
+
+    let web_root = format!("http://localhost:{}", settings.get_webui_port());
+    match masterbase::login_url(&web_root) {
+        Ok(url) => (
+            StatusCode::OK,
+            HEADERS,
+            serde_json::to_string(&MasterbaseLogin { url: url.into() })
+                .expect("Serialize masterbase login"),
+        ),
+        Err(e) => {
+            tracing::error!("Could not start masterbase sign in: {:?}", e);
+            (StatusCode::INTERNAL_SERVER_ERROR, HEADERS, String::new())
+        }
+    }
+}
+
+/// Where Steam sends the user back to after signing in. The sign in is exchanged for a masterbase
+/// session, and the user is sent back to the UI with a system event saying how it went.
+async fn get_masterbase_callback(
+    State(state): AState,
+    Query(params): Query<HashMap<String, String>>,
+) -> impl IntoResponse {
+    let endpoint = state.settings.read().unwrap().get_masterbase_endpoint();
+    let session = match endpoint {
+        Some(endpoint) => masterbase::complete_login(&endpoint, params).await,
+        None => Err(anyhow::anyhow!("No masterbase is configured.")),
+    };
+
+    match session {
+        Ok(session) => {
+            tracing::info!("Signed in to masterbase as {}", session.steamid);
+            events::emit_system(
+                SystemEventLevel::Info,
+                tr("masterbase-signed-in", &[("steamid", &session.steamid)]),
+            );
+            let mut settings = state.settings.write().unwrap();
+            settings.set_masterbase_session(Some(session));
+            settings.save_ok();
+        }
+        Err(e) => {
+            tracing::warn!("Could not sign in to masterbase: {:?}", e);
+            events::emit_system(
+                SystemEventLevel::Warning,
+                tr("masterbase-sign-in-failed", &[("error", &e)]),
+            );
+        }
+    }
+
+    Redirect::to("/ui/index.html")
+}
+
+/// Signs out of the masterbase, forgetting the session
+async fn post_masterbase_logout(State(state): AState) -> impl IntoResponse {
+    tracing::debug!("Masterbase sign out requested");
+    let mut settings = state.settings.write().unwrap();
+    settings.set_masterbase_session(None);
+    settings.save_ok();
     (StatusCode::OK, HEADERS)
 }
+
+// Status
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Status {
+    connected_players: usize,
+    pipelines: Vec<PipelineStats>,
+    /// What became of the lines read from the console log
+    console: ConsoleLineStats,
+    /// Free space for demos, once it has been checked
+    demo_space: Option<DemoSpace>,
+    /// Whether background work is paused while the user isn't on a server
+    paused: bool,
+}
+
+/// Player count, how each stage of getting data from the game to the UI is keeping up, how much
+/// of the console was understood and how much space is left for demos
+async fn get_status(State(state): AState) -> impl IntoResponse {
+    let status = Status {
+        connected_players: state.server.read().unwrap().players().connected.len(),
+        pipelines: throughput::stats(),
+        console: noise::stats(),
+        demo_space: storage::latest(),
+        paused: pause::paused(),
+    };
+
+    (
+        StatusCode::OK,
+        HEADERS,
+        serde_json::to_string(&status).expect("Serialize status"),
+    )
+}
+
+/// Runs every self-test, the same as `client-backend doctor`
+async fn get_doctor(State(state): AState) -> impl IntoResponse {
+    let checkup = Checkup::new(&state.settings.read().unwrap(), true);
+    let report = checkup.run().await;
+
+    (
+        StatusCode::OK,
+        HEADERS,
+        serde_json::to_string(&report).expect("Serialize doctor report"),
+    )
+}
+
+/// Gets the filter console logs are currently written with
+async fn get_logging() -> impl IntoResponse {
+    match logging::current() {
+        Some(filter) => (
+            StatusCode::OK,
+            HEADERS,
+            serde_json::to_string(&filter).expect("Serialize log filter"),
+        ),
+        None => (StatusCode::SERVICE_UNAVAILABLE, HEADERS, String::new()),
+    }
+}
+
+/// Changes what is written to the console log, until the client is restarted
+async fn put_logging(update: Json<LogFilterUpdate>) -> impl IntoResponse {
+    tracing::debug!("Log filter change requested: {:?}", update.0);
+    match logging::set(update.0) {
+        Ok(filter) => (
+            StatusCode::OK,
+            HEADERS,
+            serde_json::to_string(&filter).expect("Serialize log filter"),
+        ),
+        Err(e @ LogFilterError::Invalid(_)) => (StatusCode::BAD_REQUEST, HEADERS, e.to_string()),
+        Err(e) => (StatusCode::SERVICE_UNAVAILABLE, HEADERS, e.to_string()),
+    }
+}
+
+/// The same as [get_status], in the Prometheus text format
+async fn get_metrics(State(state): AState) -> impl IntoResponse {
+    let connected = state.server.read().unwrap().players().connected.len();
+    (
+        StatusCode::OK,
+        [(header::CONTENT_TYPE, "text/plain; version=0.0.4")],
+        throughput::prometheus(connected),
+    )
+}
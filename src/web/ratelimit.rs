@@ -0,0 +1,142 @@
+use std::{
+    collections::HashMap,
+    net::{IpAddr, SocketAddr},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use axum::{
+    body::Body,
+    extract::{ConnectInfo, State},
+    http::{header, Request, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+
+use super::SharedState;
+
+/// Largest request body accepted, in bytes
+pub const MAX_BODY_SIZE: usize = 2 * 1024 * 1024;
+/// Requests each client can make per second on average...
+const REQUESTS_PER_SECOND: f64 = 20.0;
+/// ...and in a single burst
+const BURST: f64 = 100.0;
+/// Open WebSockets and event streams each client can have at once
+const MAX_STREAMS_PER_CLIENT: usize = 8;
+/// Most clients kept track of at once. Past this, the one that made a request least recently is
+/// forgotten to make room.
+const MAX_CLIENTS: usize = 1024;
+
+/// Token bucket of the requests a client is allowed to make
+#[derive(Debug)]
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+impl Bucket {
+    /// Take a token if there is one, otherwise return how long until there will be
+    fn take(&mut self) -> Result<(), Duration> {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.updated).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * REQUESTS_PER_SECOND).min(BURST);
+        self.updated = now;
+
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64(
+                (1.0 - self.tokens) / REQUESTS_PER_SECOND,
+            ))
+        }
+    }
+}
+
+/// Keeps any one client (e.g. a buggy overlay polling as fast as it can) from starving the rest of
+/// the backend of time. Clients are told apart by their address alone, as anything else about a
+/// request (e.g. its `Origin`) is up to the client and could be changed to get a fresh allowance.
+#[derive(Clone, Default)]
+pub struct RateLimiter {
+    buckets: Arc<Mutex<HashMap<IpAddr, Bucket>>>,
+    streams: Arc<Mutex<HashMap<IpAddr, usize>>>,
+}
+
+impl RateLimiter {
+    fn check(&self, ip: IpAddr) -> Result<(), Duration> {
+        let mut buckets = self.buckets.lock().unwrap();
+        if buckets.len() >= MAX_CLIENTS && !buckets.contains_key(&ip) {
+            let least_recent = buckets
+                .iter()
+                .min_by_key(|(_, bucket)| bucket.updated)
+                .map(|(&ip, _)| ip);
+            if let Some(least_recent) = least_recent {
+                buckets.remove(&least_recent);
+            }
+        }
+        buckets
+            .entry(ip)
+            .or_insert_with(|| Bucket {
+                tokens: BURST,
+                updated: Instant::now(),
+            })
+            .take()
+    }
+
+    /// Reserve one of a client's streams, which is released when the guard is dropped.
+    /// Returns `None` if the client already has too many open.
+    pub fn open_stream(&self, addr: SocketAddr) -> Option<StreamGuard> {
+        let ip = addr.ip();
+        let mut streams = self.streams.lock().unwrap();
+        let open = streams.entry(ip).or_default();
+        if *open >= MAX_STREAMS_PER_CLIENT {
+            tracing::warn!("Refused a stream to {}, it has too many open", ip);
+            return None;
+        }
+        *open += 1;
+        Some(StreamGuard {
+            limiter: self.clone(),
+            ip,
+        })
+    }
+}
+
+/// An open WebSocket or event stream, counted against its client until dropped
+pub struct StreamGuard {
+    limiter: RateLimiter,
+    ip: IpAddr,
+}
+
+impl Drop for StreamGuard {
+    fn drop(&mut self) {
+        let mut streams = self.limiter.streams.lock().unwrap();
+        if let Some(open) = streams.get_mut(&self.ip) {
+            *open = open.saturating_sub(1);
+            if *open == 0 {
+                streams.remove(&self.ip);
+            }
+        }
+    }
+}
+
+/// Middleware that rejects requests from clients that have used up their allowance
+pub async fn limit(
+    State(state): State<SharedState>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    request: Request<Body>,
+    next: Next<Body>,
+) -> Response {
+    match state.limiter.check(addr.ip()) {
+        Ok(()) => next.run(request).await,
+        Err(retry_after) => {
+            tracing::debug!("Rate limited request to {}", request.uri());
+            let retry_after = retry_after.as_secs().max(1).to_string();
+            (
+                StatusCode::TOO_MANY_REQUESTS,
+                [(header::RETRY_AFTER, retry_after)],
+                "Too many requests",
+            )
+                .into_response()
+        }
+    }
+}
@@ -0,0 +1,155 @@
+use std::{
+    sync::{Arc, Mutex, RwLock},
+    time::Duration,
+};
+
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc::{unbounded_channel, UnboundedSender};
+
+use crate::{
+    analytics::history::now, events, player_records::Verdict, secrets::Secret, settings::Settings,
+    steamid::SteamID,
+};
+
+/// Attempts made to deliver each event to each webhook
+const ATTEMPTS: u32 = 3;
+/// Wait before the first retry, doubled for each one after
+const RETRY_DELAY: Duration = Duration::from_secs(2);
+
+/// Events waiting to be delivered, once [webhook_loop] has started
+static QUEUE: Mutex<Option<UnboundedSender<MarkEvent>>> = Mutex::new(None);
+
+/// Somewhere the user's marks are sent to as they change
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Webhook {
+    pub url: Arc<str>,
+    /// Sent as a bearer token, so the receiver can check events came from this user
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub token: Option<Secret>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+pub enum MarkAction {
+    #[serde(rename = "mark.added")]
+    Added,
+    #[serde(rename = "mark.changed")]
+    Changed,
+    #[serde(rename = "mark.removed")]
+    Removed,
+}
+
+/// The user's mark on a player changing. This is exactly what is posted to each webhook.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MarkEvent {
+    pub event: MarkAction,
+    /// Unix timestamp (seconds)
+    pub time: u64,
+    pub steamid: SteamID,
+    pub name: Option<Arc<str>>,
+    pub verdict: Verdict,
+    pub previous_verdict: Verdict,
+}
+
+impl MarkEvent {
+    /// The event for a player's verdict changing from `previous` to `verdict`, if that changed
+    /// whether or how they are marked
+    pub fn new(
+        steamid: SteamID,
+        name: Option<Arc<str>>,
+        previous: Verdict,
+        verdict: Verdict,
+    ) -> Option<MarkEvent> {
+        let event = match (previous, verdict) {
+            (previous, verdict) if previous == verdict => return None,
+            (Verdict::Player, _) => MarkAction::Added,
+            (_, Verdict::Player) => MarkAction::Removed,
+            _ => MarkAction::Changed,
+        };
+        Some(MarkEvent {
+            event,
+            time: now(),
+            steamid,
+            name,
+            verdict,
+            previous_verdict: previous,
+        })
+    }
+}
+
+/// Queue an event to be sent to every webhook. Does nothing if webhooks aren't running, or if the
+/// same change was just sent.
+pub fn notify(event: MarkEvent) {
+    let identity = (
+        "webhook",
+        event.event,
+        event.steamid,
+        event.verdict,
+        event.previous_verdict,
+    );
+    if !events::first_occurrence(&identity) {
+        tracing::debug!("Suppressed duplicate webhook event {:?}", event);
+        return;
+    }
+    if let Some(queue) = QUEUE.lock().unwrap().as_ref() {
+        let _ = queue.send(event);
+    }
+}
+
+async fn deliver(
+    client: &reqwest::Client,
+    webhook: &Webhook,
+    event: &MarkEvent,
+) -> anyhow::Result<()> {
+    let mut request = client
+        .post(webhook.url.as_ref())
+        .json(event)
+        .timeout(Duration::from_secs(10));
+    if let Some(token) = webhook.token.as_ref().and_then(Secret::expose) {
+        request = request.bearer_auth(token);
+    }
+    request
+        .send()
+        .await
+        .context("Failed to send webhook event.")?
+        .error_for_status()
+        .context("Webhook rejected event.")?;
+    Ok(())
+}
+
+/// Send the user's mark changes to their webhooks as they happen. Events are delivered one at a
+/// time so receivers see them in the order they happened. This will never return, so it should
+/// be spawned in a separate `tokio::task`.
+pub async fn webhook_loop(settings: Arc<RwLock<Settings>>) {
+    let (queue, mut events) = unbounded_channel();
+    *QUEUE.lock().unwrap() = Some(queue);
+    let client = reqwest::Client::new();
+
+    while let Some(event) = events.recv().await {
+        let webhooks = settings.read().unwrap().get_mark_webhooks();
+        for webhook in &webhooks {
+            let mut delay = RETRY_DELAY;
+            for attempt in 1..=ATTEMPTS {
+                match deliver(&client, webhook, &event).await {
+                    Ok(()) => break,
+                    Err(e) if attempt == ATTEMPTS => {
+                        tracing::warn!(
+                            "Giving up sending {:?} for {} to {}: {:?}",
+                            event.event,
+                            event.steamid,
+                            webhook.url,
+                            e
+                        );
+                    }
+                    Err(e) => {
+                        tracing::debug!("Failed to send webhook event, retrying: {:?}", e);
+                        tokio::time::sleep(delay).await;
+                        delay *= 2;
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,184 @@
+//! Runs the default demo analysers over made up recordings, checking a player who lands a
+//! headshot with nearly every kill as one class is flagged while ordinary players are not.
+
+use std::sync::Arc;
+
+use client_backend::{
+    demo::{
+        analyser::{default_analysers, DemoAnalyserPlugin},
+        classes::ClassTracker,
+        recording::{PlayerSnapshot, RecordedEvent},
+        report::MatchReport,
+        suspicion::{MatchSuspicion, SuspicionFlag, SuspicionKind},
+    },
+    steamid::SteamID,
+};
+
+const SCOUT: u8 = 1;
+const SNIPER: u8 = 2;
+const RED: u8 = 2;
+const BLUE: u8 = 3;
+const HEADSHOT: u16 = 1;
+/// Ticks in a minute at the default tick rate
+const MINUTE: u32 = 4000;
+
+fn steamid(n: u64) -> SteamID {
+    SteamID::from(76561197960265728 + n)
+}
+
+/// Builds up a recording of a match one event at a time
+struct Match {
+    events: Vec<RecordedEvent>,
+    tick: u32,
+}
+
+impl Match {
+    fn new() -> Match {
+        Match {
+            events: vec![RecordedEvent::Tick(0)],
+            tick: 0,
+        }
+    }
+
+    fn join(&mut self, user_id: u16, class: u8, team: u8) -> &mut Self {
+        self.events.push(RecordedEvent::Joined {
+            user_id,
+            steamid: steamid(user_id.into()),
+            name: Arc::from(format!("Player {}", user_id)),
+        });
+        self.class(user_id, class, team)
+    }
+
+    fn class(&mut self, user_id: u16, class: u8, team: u8) -> &mut Self {
+        self.events.push(RecordedEvent::Player(PlayerSnapshot {
+            user_id,
+            position: [0, 0, 0],
+            yaw: 0,
+            pitch: 0,
+            health: 125,
+            class,
+            team,
+            alive: true,
+        }));
+        self
+    }
+
+    fn wait(&mut self, ticks: u32) -> &mut Self {
+        self.tick += ticks;
+        self.events.push(RecordedEvent::Tick(self.tick));
+        self
+    }
+
+    /// `attacker` kills `victim`, `headshots` of every `kills` times, over a few seconds each
+    fn kills(&mut self, attacker: u16, victim: u16, kills: u32, headshots: u32) -> &mut Self {
+        for i in 0..kills {
+            self.wait(200);
+            self.events.push(RecordedEvent::Death {
+                victim,
+                attacker,
+                weapon: Arc::from("sniperrifle"),
+                custom_kill: if i < headshots { HEADSHOT } else { 0 },
+            });
+        }
+        self
+    }
+
+    /// Every flag the default analysers raise over the match
+    fn flags(&self) -> Vec<SuspicionFlag> {
+        let mut analysers: Vec<Box<dyn DemoAnalyserPlugin>> =
+            default_analysers().iter().map(|create| create()).collect();
+        for event in &self.events {
+            for analyser in &mut analysers {
+                analyser.on_recorded_event(event);
+            }
+        }
+        analysers
+            .iter_mut()
+            .flat_map(|analyser| analyser.finalize())
+            .collect()
+    }
+}
+
+#[test]
+fn headshots_as_one_class_are_flagged() {
+    let flags = Match::new()
+        .join(1, SNIPER, RED)
+        .join(2, SCOUT, BLUE)
+        .wait(6 * MINUTE)
+        .kills(1, 2, 12, 12)
+        .flags();
+
+    assert_eq!(flags.len(), 1);
+    assert_eq!(flags[0].steamid, steamid(1));
+    assert_eq!(flags[0].kind, SuspicionKind::Headshots);
+    assert!(flags[0].confidence >= 0.5);
+
+    let mut suspicion = MatchSuspicion::new();
+    for flag in flags {
+        suspicion.add_flag(flag);
+    }
+    assert_eq!(suspicion.get(&steamid(1)).unwrap().total_flags, 1);
+}
+
+#[test]
+fn ordinary_players_are_not_flagged() {
+    // A good sniper lands about half
+    let flags = Match::new()
+        .join(1, SNIPER, RED)
+        .join(2, SCOUT, BLUE)
+        .wait(6 * MINUTE)
+        .kills(1, 2, 20, 10)
+        .flags();
+    assert!(flags.is_empty());
+
+    // Too few kills or too short a stay to tell
+    let flags = Match::new()
+        .join(1, SNIPER, RED)
+        .join(2, SCOUT, BLUE)
+        .wait(6 * MINUTE)
+        .kills(1, 2, 5, 5)
+        .flags();
+    assert!(flags.is_empty());
+    let flags = Match::new()
+        .join(1, SNIPER, RED)
+        .join(2, SCOUT, BLUE)
+        .kills(1, 2, 12, 12)
+        .flags();
+    assert!(flags.is_empty());
+
+    // Spending most of the match on another class
+    let flags = Match::new()
+        .join(1, SCOUT, RED)
+        .join(2, SCOUT, BLUE)
+        .wait(6 * MINUTE)
+        .class(1, SNIPER, RED)
+        .kills(1, 2, 12, 12)
+        .flags();
+    assert!(flags.is_empty());
+}
+
+#[test]
+fn class_usage_is_reported() {
+    let mut classes = ClassTracker::default();
+    let recording = Match::new()
+        .join(1, SCOUT, RED)
+        .wait(MINUTE)
+        .class(1, SNIPER, RED)
+        .wait(2 * MINUTE)
+        .kills(1, 2, 3, 1)
+        .events
+        .clone();
+    for event in &recording {
+        classes.on_recorded_event(event);
+    }
+
+    let mut report = MatchReport::new("test.dem".into(), None, None, MatchSuspicion::new());
+    classes.report(&mut report);
+    let usage = &report.classes[&steamid(1)];
+    assert_eq!(usage.switches, 1);
+    assert_eq!(usage.seconds["scout"], 60);
+    assert_eq!(usage.seconds["sniper"], 129);
+    assert_eq!(usage.kills["sniper"], 3);
+    assert_eq!(usage.headshots["sniper"], 1);
+    assert_eq!(usage.weapons["sniperrifle"], 3);
+}
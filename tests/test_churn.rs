@@ -0,0 +1,59 @@
+//! Checks a burst of players joining or leaving is alerted about once, while the same number
+//! spread out over a busy server, or arriving as the server loads, is not.
+
+use client_backend::{
+    churn::{ChurnKind, ChurnMonitor},
+    steamid::SteamID,
+};
+
+fn players(from: u64, count: u64) -> Vec<SteamID> {
+    (from..from + count)
+        .map(|i| SteamID::from(76561197960265728 + i))
+        .collect()
+}
+
+#[test]
+fn bursts_are_alerted_once() {
+    let mut churn = ChurnMonitor::default();
+    churn.settle(0);
+
+    // Everyone already on the server shows up while it loads
+    churn.record_joins(10, &players(0, 20));
+    assert!(churn.check(10).is_empty());
+
+    // A couple of players coming and going every few minutes is normal
+    for minute in 3..15 {
+        churn.record_joins(minute * 60, &players(100 + minute, 1));
+        churn.record_leaves(minute * 60, &players(200 + minute, 1));
+        assert!(churn.check(minute * 60).is_empty());
+    }
+
+    let bots = players(300, 6);
+    churn.record_joins(15 * 60 + 5, &bots[..3]);
+    churn.record_joins(15 * 60 + 20, &bots[3..]);
+    let spikes = churn.check(15 * 60 + 30);
+    assert_eq!(spikes.len(), 1);
+    assert_eq!(spikes[0].kind, ChurnKind::Joins);
+    assert_eq!(spikes[0].players, bots);
+    assert!(churn.check(15 * 60 + 40).is_empty());
+}
+
+#[test]
+fn busy_servers_need_bigger_bursts() {
+    let mut churn = ChurnMonitor::default();
+    churn.settle(0);
+
+    // Two players leaving a minute is usual here
+    for minute in 3..15 {
+        churn.record_leaves(minute * 60, &players(minute * 2, 2));
+        assert!(churn.check(minute * 60).is_empty());
+    }
+    churn.record_leaves(15 * 60, &players(100, 3));
+    assert!(churn.check(15 * 60).is_empty());
+
+    churn.record_leaves(15 * 60 + 10, &players(200, 5));
+    let spikes = churn.check(15 * 60 + 10);
+    assert_eq!(spikes.len(), 1);
+    assert_eq!(spikes[0].kind, ChurnKind::Leaves);
+    assert_eq!(spikes[0].players.len(), 8);
+}
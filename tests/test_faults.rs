@@ -0,0 +1,257 @@
+//! Injects each kind of fault from `--inject_faults` into the console log and the Steam API, and
+//! checks the client gets everything it would have without them: no console lines are lost or
+//! split by short reads, and lookups that time out or come back malformed are retried until they
+//! get through.
+
+use std::{
+    collections::HashMap,
+    fs::{self, OpenOptions},
+    io::Write,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use client_backend::{
+    faults,
+    io::filewatcher::FileWatcher,
+    player::Friend,
+    settings::ConfigFilesError,
+    steamapi::{
+        backend::SteamApiBackend, schema, LookupBatching, LookupPriority, SteamAPIManager,
+        SteamAPIMessage, SteamAPIResponse,
+    },
+    steamid::SteamID,
+    storage::{RowChanges, Storage},
+};
+use serde::de::DeserializeOwned;
+use serde_json::json;
+use tappet::response_types::{
+    GetPlayerBansResponseBase, GetPlayerSummariesResponseBase, PlayerBans, PlayerSummary,
+};
+use tokio::sync::{mpsc::unbounded_channel, Mutex, MutexGuard};
+
+/// Looks like a real key, so the manager uses the API rather than scraping
+const API_KEY: &str = "0123456789abcdef0123456789abcdef";
+const RESPONSE_TIMEOUT: Duration = Duration::from_secs(5);
+/// Long enough for every retry of a lookup, on the paused clock
+const LOOKUP_TIMEOUT: Duration = Duration::from_secs(10 * 60);
+
+/// Faults are injected for the whole process, so only one test can have them at a time
+static FAULTS: Mutex<()> = Mutex::const_new(());
+
+fn steamid(n: u64) -> SteamID {
+    SteamID::from(76561197960265728 + n)
+}
+
+/// Inject the faults in `config` until the guard is dropped
+async fn inject(config: &str) -> MutexGuard<'static, ()> {
+    let guard = FAULTS.lock().await;
+    faults::enable(config.parse().expect("Fault config"));
+    guard
+}
+
+/// The Web API with a single public profile, whose responses go through the injected faults the
+/// same way Steam's do
+#[derive(Default)]
+struct FaultySteam {
+    requests: AtomicUsize,
+}
+
+impl FaultySteam {
+    async fn respond<T: DeserializeOwned>(
+        &self,
+        method: &str,
+        body: serde_json::Value,
+    ) -> Result<T> {
+        self.requests.fetch_add(1, Ordering::SeqCst);
+        let body = faults::steam_api(method, body.to_string()).await?;
+        Ok(schema::parse(method, &body)?)
+    }
+}
+
+#[async_trait]
+impl SteamApiBackend for FaultySteam {
+    async fn verify_api_key(&self, _api_key: &str) -> Result<bool> {
+        Ok(true)
+    }
+
+    async fn player_summaries(
+        &self,
+        _api_key: &str,
+        players: &[SteamID],
+    ) -> Result<Vec<PlayerSummary>> {
+        let players: Vec<_> = players
+            .iter()
+            .filter(|&&player| player == steamid(1))
+            .map(|steamid| {
+                json!({
+                    "steamid": steamid.to_string(),
+                    "communityvisibilitystate": 3,
+                    "personaname": "one",
+                    "profileurl": format!("https://steamcommunity.com/profiles/{}/", steamid),
+                    "avatar": "",
+                    "avatarmedium": "",
+                    "avatarfull": "",
+                    "avatarhash": "",
+                    "personastate": 0,
+                    "timecreated": 1262304000,
+                })
+            })
+            .collect();
+        let summaries: GetPlayerSummariesResponseBase = self
+            .respond(
+                "GetPlayerSummaries",
+                json!({ "response": { "players": players } }),
+            )
+            .await?;
+        Ok(summaries.response.players)
+    }
+
+    async fn player_bans(&self, _api_key: &str, players: &[SteamID]) -> Result<Vec<PlayerBans>> {
+        let players: Vec<_> = players
+            .iter()
+            .filter(|&&player| player == steamid(1))
+            .map(|steamid| {
+                json!({
+                    "steam_id": steamid.to_string(),
+                    "community_banned": false,
+                    "vac_banned": false,
+                    "number_of_vac_bans": 0,
+                    "days_since_last_ban": 0,
+                    "number_of_game_bans": 0,
+                    "economy_ban": "none",
+                })
+            })
+            .collect();
+        let bans: GetPlayerBansResponseBase = self
+            .respond("GetPlayerBans", json!({ "players": players }))
+            .await?;
+        Ok(bans.players)
+    }
+
+    async fn friend_list(&self, _api_key: &str, player: SteamID) -> Result<Vec<Friend>> {
+        Err(anyhow!("No friend list for {}", player))
+    }
+
+    async fn resolve_vanity_url(&self, _api_key: &str, name: &str) -> Result<SteamID> {
+        Err(anyhow!("No profile found for {}", name))
+    }
+
+    async fn player_service(
+        &self,
+        _api_key: &str,
+        _method: &str,
+        _player: SteamID,
+        _params: &[(&str, &str)],
+    ) -> Result<serde_json::Map<String, serde_json::Value>> {
+        Ok(serde_json::Map::new())
+    }
+}
+
+/// Nothing is ever saved, so the lookup can't be answered from the cache
+#[derive(Debug)]
+struct NoStorage;
+
+impl Storage for NoStorage {
+    fn read_document(&self, _name: &str) -> Result<Option<String>, ConfigFilesError> {
+        Ok(None)
+    }
+
+    fn write_document(&self, _name: &str, _contents: &str) -> Result<(), ConfigFilesError> {
+        Ok(())
+    }
+
+    fn read_records(&self) -> Result<Option<HashMap<SteamID, String>>, ConfigFilesError> {
+        Ok(None)
+    }
+
+    fn write_records(&self, _changes: RowChanges) -> Result<(), ConfigFilesError> {
+        Ok(())
+    }
+}
+
+#[tokio::test]
+async fn console_lines_survive_short_reads() {
+    let _faults = inject("truncate=0.7,seed=3").await;
+    let path = std::env::temp_dir().join(format!(
+        "mac-test-faults-console-{}.log",
+        std::process::id()
+    ));
+    fs::write(&path, "Logged before the client started\n").expect("Create console log");
+
+    let (_commands, command_recv) = unbounded_channel();
+    let (mut lines, mut watcher) = FileWatcher::new(path.clone(), command_recv);
+    let watcher = tokio::task::spawn(async move { watcher.file_watch_loop().await });
+    tokio::time::sleep(Duration::from_millis(50)).await;
+
+    let expected: Vec<String> = (0..60).map(|i| format!("Player {} connected", i)).collect();
+    let mut log = OpenOptions::new()
+        .append(true)
+        .open(&path)
+        .expect("Open console log");
+    for chunk in expected.chunks(6) {
+        let text: String = chunk.iter().map(|line| format!("{}\n", line)).collect();
+        log.write_all(text.as_bytes()).expect("Write console log");
+        tokio::time::sleep(Duration::from_millis(15)).await;
+    }
+
+    let mut received = Vec::new();
+    while received.len() < expected.len() {
+        let line = tokio::time::timeout(RESPONSE_TIMEOUT, lines.recv())
+            .await
+            .expect("Timed out waiting for a console line")
+            .expect("File watcher stopped");
+        received.push(line.to_string());
+    }
+    watcher.abort();
+    let _ = fs::remove_file(path);
+
+    assert_eq!(received, expected);
+}
+
+#[tokio::test(start_paused = true)]
+async fn lookups_survive_slow_failed_and_malformed_responses() {
+    let _faults = inject("latency=500,timeout=0.3,malformed=0.3,seed=2").await;
+    let steam = Arc::new(FaultySteam::default());
+
+    let (requests, request_recv) = unbounded_channel();
+    let (mut responses, mut manager) = SteamAPIManager::new(
+        API_KEY.into(),
+        Arc::new(NoStorage),
+        Duration::from_secs(60 * 60),
+        Duration::from_secs(60 * 60),
+        false,
+        LookupBatching::default(),
+        request_recv,
+    );
+    manager.set_backend(steam.clone());
+    tokio::task::spawn(async move { manager.api_loop().await });
+
+    requests
+        .send(SteamAPIMessage::Lookup(steamid(1), LookupPriority::Normal))
+        .unwrap();
+
+    // Each failed attempt is reported before it is tried again
+    let mut failures = 0;
+    let info = loop {
+        let response = tokio::time::timeout(LOOKUP_TIMEOUT, responses.recv())
+            .await
+            .expect("Gave up on the lookup")
+            .expect("Steam API manager stopped");
+        match response {
+            SteamAPIResponse::SteamInfo((player, info)) if player == steamid(1) => break info,
+            SteamAPIResponse::LookupFailed(player, _) if player == steamid(1) => failures += 1,
+            _ => {}
+        }
+    };
+
+    assert_eq!(&*info.account_name, "one");
+    assert!(failures > 0, "No faults were injected");
+    // Both requests of the attempt that got through, after one or more for each failed attempt
+    assert!(steam.requests.load(Ordering::SeqCst) >= failures + 2);
+}
@@ -0,0 +1,155 @@
+//! Runs the demo analysers over a corpus of labelled fixtures and checks how many known cheaters
+//! they catch and how many clean players they wrongly flag, so a change to a heuristic can be
+//! measured instead of guessed at.
+//!
+//! Fixtures are recordings (`.mrec`) of demos rather than the demos themselves, as they are what
+//! the analysers replay when reprocessing and are a fraction of the size. Copy one from the
+//! `recordings` folder in the config directory into `tests/data/demos`, cut down to the segment
+//! of interest, and list it in `corpus.json` with the players known to be cheating and those
+//! known to be clean:
+//!
+//! ```json
+//! {
+//!   "recording": "2023-08-01_21-14-02.mrec",
+//!   "cheaters": ["76561198000000001"],
+//!   "clean": ["76561198000000002", "76561198000000003"]
+//! }
+//! ```
+//!
+//! `synthetic-headshots.mrec` is made up rather than recorded, so the corpus is never empty: a
+//! sniper landing a headshot with every kill among players with ordinary stats, including
+//! another sniper hitting half their shots and someone who only picks sniper at the end.
+//!
+//! Run with `cargo test --test test_heuristics -- --nocapture` to see the rates for every fixture.
+
+use std::{collections::HashSet, fs, path::Path};
+
+use client_backend::{demo::reprocess::analyse_recording, steamid::SteamID};
+use serde::Deserialize;
+
+const CORPUS_DIR: &str = "tests/data/demos";
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Corpus {
+    /// Lowest share of labelled cheaters that must be flagged
+    min_detection_rate: f32,
+    /// Highest share of labelled clean players that may be flagged
+    max_false_positive_rate: f32,
+    fixtures: Vec<Fixture>,
+}
+
+#[derive(Deserialize)]
+struct Fixture {
+    recording: String,
+    #[serde(default)]
+    cheaters: Vec<SteamID>,
+    #[serde(default)]
+    clean: Vec<SteamID>,
+}
+
+/// How many of the labelled players in some fixtures were flagged
+#[derive(Default)]
+struct Tally {
+    cheaters: usize,
+    detected: usize,
+    clean: usize,
+    false_positives: usize,
+}
+
+impl Tally {
+    fn add(&mut self, other: &Tally) {
+        self.cheaters += other.cheaters;
+        self.detected += other.detected;
+        self.clean += other.clean;
+        self.false_positives += other.false_positives;
+    }
+
+    fn detection_rate(&self) -> Option<f32> {
+        (self.cheaters > 0).then(|| self.detected as f32 / self.cheaters as f32)
+    }
+
+    fn false_positive_rate(&self) -> Option<f32> {
+        (self.clean > 0).then(|| self.false_positives as f32 / self.clean as f32)
+    }
+
+    fn print(&self, name: &str) {
+        println!(
+            "{:<40} detected {}/{} cheaters, flagged {}/{} clean players",
+            name, self.detected, self.cheaters, self.false_positives, self.clean
+        );
+    }
+}
+
+fn load_corpus() -> Corpus {
+    let path = Path::new(CORPUS_DIR).join("corpus.json");
+    let contents = fs::read_to_string(&path).expect("No corpus.json in tests/data/demos?");
+    serde_json::from_str(&contents).expect("Failed to parse corpus.json")
+}
+
+fn run_fixture(fixture: &Fixture) -> Tally {
+    let path = Path::new(CORPUS_DIR).join(&fixture.recording);
+    let replay = analyse_recording(&path)
+        .unwrap_or_else(|e| panic!("Failed to analyse {}: {:?}", fixture.recording, e));
+    let flagged: HashSet<&SteamID> = replay.suspicion.players().keys().collect();
+
+    Tally {
+        cheaters: fixture.cheaters.len(),
+        detected: fixture
+            .cheaters
+            .iter()
+            .filter(|s| flagged.contains(s))
+            .count(),
+        clean: fixture.clean.len(),
+        false_positives: fixture.clean.iter().filter(|s| flagged.contains(s)).count(),
+    }
+}
+
+#[test]
+fn test_corpus_rates() {
+    let corpus = load_corpus();
+    assert!(
+        !corpus.fixtures.is_empty(),
+        "No fixtures in the heuristic corpus, nothing to measure"
+    );
+
+    let mut total = Tally::default();
+    for fixture in &corpus.fixtures {
+        let tally = run_fixture(fixture);
+        tally.print(&fixture.recording);
+        total.add(&tally);
+    }
+    total.print("total");
+
+    if let Some(rate) = total.detection_rate() {
+        assert!(
+            rate >= corpus.min_detection_rate,
+            "Detection rate {:.2} is below {:.2}",
+            rate,
+            corpus.min_detection_rate
+        );
+    }
+    if let Some(rate) = total.false_positive_rate() {
+        assert!(
+            rate <= corpus.max_false_positive_rate,
+            "False positive rate {:.2} is above {:.2}",
+            rate,
+            corpus.max_false_positive_rate
+        );
+    }
+}
+
+#[test]
+fn test_corpus_fixtures_exist() {
+    let corpus = load_corpus();
+    for fixture in &corpus.fixtures {
+        let path = Path::new(CORPUS_DIR).join(&fixture.recording);
+        assert!(path.is_file(), "Missing fixture {:?}", path);
+        let labelled: HashSet<&SteamID> = fixture.cheaters.iter().collect();
+        assert!(
+            fixture.clean.iter().all(|s| !labelled.contains(s)),
+            "{} labels a player as both cheating and clean",
+            fixture.recording
+        );
+    }
+}
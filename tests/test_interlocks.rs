@@ -0,0 +1,72 @@
+//! Checks automated votekicks are held to each of the [AutomationLimits]: how many may be called
+//! in an hour, how long after joining a server, and how strongly the player has to be marked.
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use client_backend::interlocks::{AutomationLimits, InterlockError, Interlocks};
+
+/// Enough signals for the default limits
+const SCORE: f32 = 2.0;
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
+}
+
+/// Joined long enough ago for the join grace period to have passed
+fn joined() -> Option<u64> {
+    Some(now() - 10 * 60)
+}
+
+#[test]
+fn votekicks_per_hour_are_limited() {
+    let mut interlocks = Interlocks::default();
+    for _ in 0..2 {
+        assert_eq!(interlocks.check_votekick(joined(), SCORE), Ok(()));
+        interlocks.votekick_called();
+    }
+    assert_eq!(interlocks.votekicks_last_hour(), 2);
+    assert_eq!(
+        interlocks.check_votekick(joined(), SCORE),
+        Err(InterlockError::VotekickLimit(2))
+    );
+
+    // Raising the limit lets another through
+    interlocks.set_limits(AutomationLimits {
+        max_votekicks_per_hour: 3,
+        ..Default::default()
+    });
+    assert_eq!(interlocks.check_votekick(joined(), SCORE), Ok(()));
+}
+
+#[test]
+fn nothing_is_done_straight_after_joining() {
+    let interlocks = Interlocks::default();
+    assert_eq!(
+        interlocks.check_votekick(None, SCORE),
+        Err(InterlockError::NotJoined)
+    );
+
+    match interlocks.check_votekick(Some(now() - 20), SCORE) {
+        Err(InterlockError::JoinGrace(left)) => assert!((99..=100).contains(&left)),
+        other => panic!("Expected to be in the join grace period, got {:?}", other),
+    }
+    assert_eq!(interlocks.check_votekick(Some(now() - 120), SCORE), Ok(()));
+}
+
+#[test]
+fn players_need_enough_signals() {
+    let mut interlocks = Interlocks::default();
+    assert_eq!(
+        interlocks.check_votekick(joined(), 1.5),
+        Err(InterlockError::TooFewSignals { have: 1.5, need: 2 })
+    );
+
+    interlocks.set_limits(AutomationLimits {
+        min_signals: 1,
+        ..Default::default()
+    });
+    assert_eq!(interlocks.check_votekick(joined(), 1.5), Ok(()));
+}
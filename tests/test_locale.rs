@@ -0,0 +1,60 @@
+//! Checks the builtin locale files are valid Fluent with the same messages in each, and that
+//! messages are formatted with their arguments filled in.
+
+use std::collections::BTreeSet;
+
+use client_backend::{locale::tr, steamid::SteamID};
+use fluent_bundle::FluentResource;
+
+const BUILTIN: &[(&str, &str)] = &[
+    ("en", include_str!("../locales/en.ftl")),
+    ("de", include_str!("../locales/de.ftl")),
+];
+
+/// The IDs of every message in a resource file
+fn message_ids(contents: &str) -> BTreeSet<&str> {
+    contents
+        .lines()
+        .filter(|line| !line.starts_with(['#', ' ']))
+        .filter_map(|line| line.split_once('='))
+        .map(|(id, _)| id.trim())
+        .collect()
+}
+
+#[test]
+fn builtin_locales_parse_and_match() {
+    for (locale, contents) in BUILTIN {
+        if let Err((_, errors)) = FluentResource::try_new(contents.to_string()) {
+            panic!("Invalid messages in {}: {:?}", locale, errors);
+        }
+    }
+
+    let english = message_ids(BUILTIN[0].1);
+    assert!(!english.is_empty());
+    for (locale, contents) in &BUILTIN[1..] {
+        assert_eq!(message_ids(contents), english, "Messages in {}", locale);
+    }
+    for id in english {
+        assert_ne!(tr(id, &[]), id, "{} isn't translated", id);
+    }
+}
+
+#[test]
+fn arguments_are_filled_in() {
+    let steamid = SteamID::from(76561197960265729);
+    assert_eq!(
+        tr(
+            "player-flagged",
+            &[
+                ("player", &"Bot"),
+                ("detector", &steamid),
+                ("reason", &2.50)
+            ]
+        ),
+        "Bot was flagged by 76561197960265729: 2.5"
+    );
+
+    // Missing arguments are left obvious
+    assert_eq!(tr("summary-names", &[]), "Previously seen as {$names}.");
+    assert_eq!(tr("no-such-message", &[]), "no-such-message");
+}
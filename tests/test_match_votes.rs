@@ -0,0 +1,74 @@
+//! Checks how players voted on kicks is only kept for players who already have a record, and
+//! that those who keep voting to keep bots are flagged once.
+
+use std::{collections::HashMap, sync::Arc};
+
+use client_backend::{
+    demo::{
+        report::MatchReport,
+        suspicion::MatchSuspicion,
+        votes::{Ballot, KickVote},
+    },
+    player_records::{PlayerRecord, PlayerRecords, Verdict},
+    steamid::SteamID,
+};
+
+fn steamid(n: u64) -> SteamID {
+    SteamID::from(76561197960265728 + n)
+}
+
+fn report(demo: &str, target: SteamID, ballots: &[(SteamID, Ballot)]) -> MatchReport {
+    let mut report = MatchReport::new(demo.into(), None, None, MatchSuspicion::new());
+    report.votes.push(KickVote {
+        tick: 1000,
+        time: None,
+        target: Some(target),
+        target_name: Arc::from("bot"),
+        caller: None,
+        passed: Some(false),
+        ballots: ballots.iter().copied().collect::<HashMap<_, _>>(),
+    });
+    report
+}
+
+fn record(verdict: Verdict) -> PlayerRecord {
+    let mut record = PlayerRecord::new();
+    record.verdict = verdict;
+    record
+}
+
+#[test]
+fn votes_are_kept_for_known_players() {
+    let bot = steamid(1);
+    let known = steamid(2);
+    let stranger = steamid(3);
+    let mut records = PlayerRecords::default();
+    records.insert(bot, record(Verdict::Bot));
+    records.insert(known, record(Verdict::Suspicious));
+
+    let ballots = [
+        (known, Ballot::No),
+        (stranger, Ballot::No),
+        (bot, Ballot::No),
+    ];
+    assert!(records
+        .add_match_votes(&report("1.dem", bot, &ballots))
+        .is_empty());
+    assert!(records
+        .add_match_votes(&report("2.dem", bot, &ballots))
+        .is_empty());
+    assert_eq!(
+        records.add_match_votes(&report("3.dem", bot, &ballots)),
+        vec![known]
+    );
+
+    assert_eq!(records.bot_votes(known), (3, 3));
+    assert!(records.get(&stranger).is_none());
+    assert!(records[&bot].votes.is_empty());
+
+    // Going over a demo again replaces its votes rather than adding to them
+    assert!(records
+        .add_match_votes(&report("3.dem", bot, &ballots))
+        .is_empty());
+    assert_eq!(records.bot_votes(known), (3, 3));
+}
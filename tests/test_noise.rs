@@ -0,0 +1,38 @@
+//! Checks the default noise filters drop the spam they are meant to without eating any of the
+//! lines the client reads.
+
+use client_backend::io::noise::{default_filters, matching};
+
+fn dropped_by(line: &str) -> Option<String> {
+    matching(&default_filters(), line).map(|f| f.name.to_string())
+}
+
+#[test]
+fn spam_is_dropped() {
+    assert_eq!(
+        dropped_by("Saxton Hale has found: Strange Scattergun").as_deref(),
+        Some("itemDrops")
+    );
+    assert_eq!(
+        dropped_by("Saxton Hale has earned the achievement First Blood").as_deref(),
+        Some("achievements")
+    );
+    assert_eq!(
+        dropped_by("[PartyClient] Requesting queue for 12v12 Casual Match").as_deref(),
+        Some("itemServer")
+    );
+}
+
+#[test]
+fn lines_the_client_reads_are_kept() {
+    for line in [
+        "Saxton Hale :  just found a Strange Scattergun",
+        "*DEAD* Saxton Hale :  Saxton Hale has found: nothing",
+        "Saxton Hale killed Pyro with scattergun. (crit)",
+        "hostname: Valve Matchmaking Server (Virginia srcds1001-iad1 #42)",
+        "players : 23 humans, 0 bots (24 max)",
+        "#    411 \"Saxton Hale\"       [U:1:12345]         00:53       59    0 active",
+    ] {
+        assert_eq!(dropped_by(line), None, "{:?} was dropped", line);
+    }
+}
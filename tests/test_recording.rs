@@ -0,0 +1,168 @@
+//! Checks every kind of recorded event reads back the same as it was written, and that an event
+//! cut off part way through is an error rather than a made up event, while a recording cut off
+//! part way through still has the events before it.
+
+use std::{
+    io::{ErrorKind, Write},
+    sync::Arc,
+};
+
+use client_backend::{
+    demo::recording::{read_recording, PlayerSnapshot, RecordedEvent, FORMAT_VERSION},
+    steamid::SteamID,
+};
+use flate2::{write::GzEncoder, Compression};
+
+fn events() -> Vec<RecordedEvent> {
+    vec![
+        RecordedEvent::Tick(0),
+        RecordedEvent::Tick(u32::MAX),
+        RecordedEvent::Joined {
+            user_id: 12,
+            steamid: SteamID::from(76561197960287930),
+            name: Arc::from("Gabe ☕"),
+        },
+        RecordedEvent::Joined {
+            user_id: 13,
+            steamid: SteamID::from(76561197960287931),
+            name: Arc::from(""),
+        },
+        RecordedEvent::Player(PlayerSnapshot {
+            user_id: 12,
+            position: [-1024, 0, i16::MAX],
+            yaw: 16384,
+            pitch: u16::MAX,
+            health: 450,
+            class: 6,
+            team: 3,
+            alive: true,
+        }),
+        RecordedEvent::Player(PlayerSnapshot {
+            user_id: 13,
+            position: [i16::MIN, 5, -5],
+            yaw: 0,
+            pitch: 0,
+            health: 0,
+            class: 0,
+            team: 1,
+            alive: false,
+        }),
+        RecordedEvent::Death {
+            victim: 13,
+            attacker: 12,
+            weapon: Arc::from("minigun"),
+            custom_kill: 0,
+        },
+        RecordedEvent::Death {
+            victim: 12,
+            attacker: 13,
+            weapon: Arc::from("sniperrifle"),
+            custom_kill: 1,
+        },
+        RecordedEvent::Left { user_id: 13 },
+    ]
+}
+
+fn write(event: &RecordedEvent) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    event.write(&mut bytes).expect("Write event");
+    bytes
+}
+
+#[test]
+fn events_read_back_the_same() {
+    let events = events();
+    let bytes: Vec<u8> = events.iter().flat_map(write).collect();
+
+    let mut input = bytes.as_slice();
+    let mut read = Vec::new();
+    while let Some(event) = RecordedEvent::read(&mut input, FORMAT_VERSION).expect("Read event") {
+        read.push(event);
+    }
+    assert_eq!(read, events);
+}
+
+#[test]
+fn truncated_events_are_errors() {
+    for event in events() {
+        let bytes = write(&event);
+        assert!(RecordedEvent::read(&mut &bytes[..0], FORMAT_VERSION)
+            .unwrap()
+            .is_none());
+        for len in 1..bytes.len() {
+            let err = RecordedEvent::read(&mut &bytes[..len], FORMAT_VERSION)
+                .expect_err(&format!("{:?} cut off after {} bytes", event, len));
+            assert_eq!(err.kind(), ErrorKind::UnexpectedEof);
+        }
+    }
+}
+
+/// A recording of `events`, with the last `cut` bytes of them missing. When `finished` is false
+/// the compressed stream is left unfinished too, as it is when the client crashes.
+fn recording(
+    name: &str,
+    events: &[RecordedEvent],
+    cut: usize,
+    finished: bool,
+) -> Vec<RecordedEvent> {
+    let mut bytes: Vec<u8> = b"MACR".iter().copied().chain([FORMAT_VERSION]).collect();
+    bytes.extend(events.iter().flat_map(write));
+    bytes.truncate(bytes.len() - cut);
+
+    let mut out = GzEncoder::new(Vec::new(), Compression::default());
+    out.write_all(&bytes).expect("Compress recording");
+    let compressed = if finished {
+        out.finish().expect("Finish recording")
+    } else {
+        out.flush().expect("Flush recording");
+        out.get_ref().clone()
+    };
+
+    let path = std::env::temp_dir().join(format!(
+        "mac-test-recording-{}-{}.mrec",
+        name,
+        std::process::id()
+    ));
+    std::fs::write(&path, compressed).expect("Write recording");
+    let read = read_recording(&path).expect("Read recording");
+    std::fs::remove_file(&path).ok();
+    read
+}
+
+#[test]
+fn cut_off_recordings_keep_the_events_before() {
+    let events = events();
+    assert_eq!(recording("whole", &events, 0, true), events);
+    let before = &events[..events.len() - 1];
+    assert_eq!(recording("cut", &events, 1, true), before);
+    assert_eq!(recording("crashed", &events, 1, false), before);
+}
+
+#[test]
+fn unknown_events_are_errors() {
+    let err = RecordedEvent::read(&mut [9, 0, 0, 0, 0].as_slice(), FORMAT_VERSION).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::InvalidData);
+}
+
+#[test]
+fn old_deaths_are_not_headshots() {
+    let mut bytes = write(&RecordedEvent::Death {
+        victim: 1,
+        attacker: 2,
+        weapon: Arc::from("sniperrifle"),
+        custom_kill: 1,
+    });
+    // Version 1 deaths end with the weapon
+    bytes.truncate(bytes.len() - 2);
+    let mut input = bytes.as_slice();
+    assert_eq!(
+        RecordedEvent::read(&mut input, 1).unwrap(),
+        Some(RecordedEvent::Death {
+            victim: 1,
+            attacker: 2,
+            weapon: Arc::from("sniperrifle"),
+            custom_kill: 0,
+        })
+    );
+    assert!(input.is_empty());
+}
@@ -0,0 +1,113 @@
+//! Checks that reprocessing a recording only replaces the flags that the analysers able to replay
+//! recordings could have raised, so flags only a demo can show (e.g. voice spam) are kept.
+
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+};
+
+use client_backend::{
+    demo::{
+        report::MatchReport,
+        suspicion::{MatchSuspicion, SuspicionFlag, SuspicionKind},
+    },
+    player_records::PlayerRecords,
+    settings::ConfigFilesError,
+    steamid::SteamID,
+    storage::{RowChanges, Storage},
+};
+
+const DEMO: &str = "2023-08-01_21-14-02.dem";
+
+fn steamid(n: u64) -> SteamID {
+    SteamID::from(76561197960265728 + n)
+}
+
+/// Storage that never has anything in it and forgets whatever is written
+#[derive(Debug)]
+struct NullStorage;
+
+impl Storage for NullStorage {
+    fn read_document(&self, _name: &str) -> Result<Option<String>, ConfigFilesError> {
+        Ok(None)
+    }
+
+    fn write_document(&self, _name: &str, _contents: &str) -> Result<(), ConfigFilesError> {
+        Ok(())
+    }
+
+    fn read_records(&self) -> Result<Option<HashMap<SteamID, String>>, ConfigFilesError> {
+        Ok(None)
+    }
+
+    fn write_records(&self, _changes: RowChanges) -> Result<(), ConfigFilesError> {
+        Ok(())
+    }
+}
+
+fn report(flags: &[(SteamID, SuspicionKind, u32)]) -> MatchReport {
+    let mut suspicion = MatchSuspicion::new();
+    for &(steamid, kind, tick) in flags {
+        suspicion.add_flag(SuspicionFlag {
+            steamid,
+            kind,
+            tick,
+            confidence: 0.8,
+        });
+    }
+    MatchReport::new(DEMO.into(), Some(Arc::from("cp_badlands")), None, suspicion)
+}
+
+fn kinds(records: &PlayerRecords, steamid: SteamID) -> Vec<SuspicionKind> {
+    let mut kinds: Vec<SuspicionKind> = records
+        .get(&steamid)
+        .into_iter()
+        .flat_map(|r| r.suspicion.iter())
+        .filter(|e| &*e.demo == DEMO)
+        .flat_map(|e| e.suspicion.flags.keys().copied())
+        .collect();
+    kinds.sort_by_key(|kind| format!("{:?}", kind));
+    kinds
+}
+
+#[test]
+fn flags_that_were_not_replayed_are_kept() {
+    let mut records = PlayerRecords::load_or_create(Arc::new(NullStorage));
+    let original = report(&[
+        (steamid(1), SuspicionKind::VoiceSpam, 100),
+        (steamid(1), SuspicionKind::Headshots, 9000),
+        (steamid(2), SuspicionKind::VoiceSpam, 200),
+    ]);
+    for steamid in original.suspicion.players().keys() {
+        let encounter = original.encounter_suspicion(steamid).unwrap();
+        records.add_match_suspicion(*steamid, encounter);
+    }
+
+    // The headshot flag no longer holds up, and someone else is flagged instead
+    let replayed = report(&[(steamid(3), SuspicionKind::Headshots, 12000)]);
+    let replayed_kinds = HashSet::from([SuspicionKind::Headshots]);
+    assert_eq!(
+        records.replace_match_suspicion(&replayed, &replayed_kinds),
+        3
+    );
+
+    assert_eq!(kinds(&records, steamid(1)), vec![SuspicionKind::VoiceSpam]);
+    assert_eq!(kinds(&records, steamid(2)), vec![SuspicionKind::VoiceSpam]);
+    assert_eq!(kinds(&records, steamid(3)), vec![SuspicionKind::Headshots]);
+
+    let first = &records.get(&steamid(1)).unwrap().suspicion[0].suspicion;
+    assert_eq!(first.total_flags, 1);
+    assert_eq!(first.first_tick, 100);
+}
+
+#[test]
+fn matches_are_dropped_once_nothing_is_left() {
+    let mut records = PlayerRecords::load_or_create(Arc::new(NullStorage));
+    let original = report(&[(steamid(1), SuspicionKind::Headshots, 9000)]);
+    let encounter = original.encounter_suspicion(&steamid(1)).unwrap();
+    records.add_match_suspicion(steamid(1), encounter);
+
+    let replayed_kinds = HashSet::from([SuspicionKind::Headshots]);
+    records.replace_match_suspicion(&report(&[]), &replayed_kinds);
+    assert!(records.get(&steamid(1)).is_none());
+}
@@ -0,0 +1,76 @@
+//! Checks how new an account seems agrees with its risk assessment, as both come from the same
+//! age and level thresholds.
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use client_backend::{
+    player::{ProfileVisibility, SteamInfo},
+    steamapi::risk::{AccountNewness, RiskFactor, RiskLevel},
+};
+
+const DAY: i64 = 24 * 60 * 60;
+
+/// A profile with nothing against it besides its age, level and visibility
+fn profile(age_days: i64, steam_level: u32, visibility: ProfileVisibility) -> SteamInfo {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs() as i64;
+    let mut info = SteamInfo {
+        account_name: "player".into(),
+        profile_url: "".into(),
+        pfp_url: "".into(),
+        pfp_hash: "".into(),
+        profile_visibility: visibility,
+        time_created: Some(now - age_days * DAY),
+        country_code: None,
+        vac_bans: 0,
+        game_bans: 0,
+        days_since_last_ban: None,
+        community_banned: Some(false),
+        economy_ban: None,
+        has_background: None,
+        showcases: None,
+        games_visible: None,
+        tf2_hours: None,
+        steam_level: Some(steam_level),
+        risk: None,
+    };
+    info.assess_risk();
+    info
+}
+
+#[test]
+fn fresh_accounts_are_high_risk() {
+    let info = profile(10, 0, ProfileVisibility::Private);
+    let risk = info.risk.as_ref().unwrap();
+    assert_eq!(
+        risk.factors,
+        [
+            RiskFactor::NewAccount,
+            RiskFactor::HiddenProfile,
+            RiskFactor::NoLevel
+        ]
+    );
+    assert_eq!(risk.level, RiskLevel::High);
+    assert_eq!(info.newness(None), Some(AccountNewness::Fresh));
+}
+
+#[test]
+fn young_accounts_match_between_risk_and_newness() {
+    // Young by the risk assessment's six months, and with a low level
+    let info = profile(100, 3, ProfileVisibility::Public);
+    let risk = info.risk.as_ref().unwrap();
+    assert_eq!(
+        risk.factors,
+        [RiskFactor::YoungAccount, RiskFactor::LowLevel]
+    );
+    assert_eq!(info.newness(None), Some(AccountNewness::Young));
+
+    // Past six months and levelled up, neither counts it as new any more
+    let info = profile(200, 10, ProfileVisibility::Public);
+    assert!(info.risk.as_ref().unwrap().factors.is_empty());
+    assert_eq!(info.newness(None), Some(AccountNewness::Established));
+    // Though a near empty friend list still makes it seem young
+    assert_eq!(info.newness(Some(1)), Some(AccountNewness::Young));
+}
@@ -0,0 +1,40 @@
+//! Checks responses that don't fit are reported with the field that changed, without anything
+//! that could identify a player.
+
+use client_backend::steamapi::schema::{self, DriftKind};
+use serde::Deserialize;
+
+#[allow(dead_code)]
+#[derive(Debug, Deserialize)]
+struct Response {
+    players: Vec<Player>,
+}
+
+#[allow(dead_code)]
+#[derive(Debug, Deserialize)]
+struct Player {
+    steamid: String,
+    timecreated: i64,
+}
+
+#[test]
+fn changed_fields_are_found() {
+    let body = r#"{"players":[{"steamid":"76561197960265729","timecreated":"yesterday"}]}"#;
+    let drift = schema::parse::<Response>("GetPlayerSummaries", body).unwrap_err();
+    assert_eq!(drift.kind, DriftKind::WrongType);
+    assert_eq!(drift.path, "players[0].timecreated");
+    assert!(!drift.payload.contains("76561197960265729"));
+
+    let body = r#"{"players":[{"steamid":"76561197960265729"}]}"#;
+    let drift = schema::parse::<Response>("GetPlayerSummaries", body).unwrap_err();
+    assert_eq!(drift.kind, DriftKind::MissingField);
+    assert_eq!(drift.path, "players[0].timecreated");
+}
+
+#[test]
+fn error_pages_are_not_json() {
+    let body = "<html><body>502 Bad Gateway</body></html>";
+    let drift = schema::parse::<Response>("GetPlayerSummaries", body).unwrap_err();
+    assert_eq!(drift.kind, DriftKind::NotJson);
+    assert!(schema::recent().iter().any(|d| d.payload == body));
+}
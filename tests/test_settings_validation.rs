@@ -0,0 +1,100 @@
+//! Checks settings files are read as far as they can be: bad values fall back to their defaults,
+//! unknown keys are reported with the setting they were probably meant to be, and missing
+//! settings are added to an upgraded copy of the file.
+
+use client_backend::settings::{
+    validation::{self, ConfigIssue},
+    Settings,
+};
+
+#[test]
+fn complete_files_are_accepted() {
+    let contents = serde_yaml::to_string(&Settings::default()).unwrap();
+    let validated = validation::parse(&contents).unwrap();
+    assert_eq!(validated.issues, vec![]);
+    assert!(validated.upgraded.is_none());
+
+    let validated = validation::parse("webui_port: 4000\ntelemetry: true\n").unwrap();
+    assert_eq!(validated.settings.get_webui_port(), 4000);
+    assert!(validated.settings.get_telemetry());
+}
+
+#[test]
+fn invalid_values_fall_back_to_defaults() {
+    let contents = "telemetry: true\nwebui_port: not a port\nautolaunch_ui: true\n";
+    let validated = validation::parse(contents).unwrap();
+
+    assert_eq!(validated.settings.get_webui_port(), 3621);
+    assert!(validated.settings.get_telemetry());
+    assert!(validated.settings.get_autolaunch_ui());
+    assert!(matches!(
+        &validated.issues[0],
+        ConfigIssue::Invalid { line: 2, key, .. } if key == "webui_port"
+    ));
+    // A file with a mistake in it is left for the user to fix
+    assert!(validated.upgraded.is_none());
+}
+
+#[test]
+fn unknown_keys_are_reported() {
+    let contents = "telemetry: true\ntelemtry: false\nsomething_else: 1\nautomation_limits:\n  maxVotekicksPerHr: 3\n  joinGraceSecs: 60\nexternal:\n  anything: goes\n";
+    let validated = validation::parse(contents).unwrap();
+
+    let unknown: Vec<&ConfigIssue> = validated
+        .issues
+        .iter()
+        .filter(|i| matches!(i, ConfigIssue::Unknown { .. }))
+        .collect();
+    assert_eq!(
+        unknown,
+        vec![
+            &ConfigIssue::Unknown {
+                line: Some(2),
+                key: "telemtry".into(),
+                suggestion: Some("telemetry".into()),
+            },
+            &ConfigIssue::Unknown {
+                line: Some(3),
+                key: "something_else".into(),
+                suggestion: None,
+            },
+            &ConfigIssue::Unknown {
+                line: Some(5),
+                key: "automation_limits.maxVotekicksPerHr".into(),
+                suggestion: Some("automation_limits.maxVotekicksPerHour".into()),
+            },
+        ]
+    );
+    assert!(validated.settings.get_telemetry());
+    assert_eq!(
+        validated.settings.get_automation_limits().join_grace_secs,
+        60
+    );
+}
+
+#[test]
+fn missing_settings_are_added() {
+    let validated = validation::parse("webui_port: 4000\n").unwrap();
+    let Some(ConfigIssue::Missing(missing)) = validated.issues.last() else {
+        panic!("Expected missing settings, got {:?}", validated.issues);
+    };
+    assert!(missing.iter().any(|k| k == "telemetry"));
+    assert!(!missing.iter().any(|k| k == "webui_port"));
+
+    let upgraded = validated.upgraded.expect("Upgraded file");
+    assert!(upgraded.starts_with("webui_port: 4000\n"));
+    let revalidated = validation::parse(&upgraded).unwrap();
+    assert_eq!(revalidated.issues, vec![]);
+    assert_eq!(revalidated.settings.get_webui_port(), 4000);
+
+    let validated = validation::parse("").unwrap();
+    assert!(matches!(
+        validated.issues.as_slice(),
+        [ConfigIssue::Missing(_)]
+    ));
+}
+
+#[test]
+fn broken_files_are_rejected() {
+    assert!(validation::parse("  webui_port: [4000\n").is_err());
+}
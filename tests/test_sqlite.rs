@@ -0,0 +1,164 @@
+//! Checks the SQLite storage keeps records, documents and tables across reopening the database,
+//! and that saving only writes the records and rows that changed. Only built with the `sqlite`
+//! feature.
+
+#![cfg(feature = "sqlite")]
+
+use std::{collections::HashMap, fs, path::PathBuf};
+
+use client_backend::{
+    steamid::SteamID,
+    storage::{RowChanges, Sqlite, Storage, STEAM_FRIENDS, STEAM_PROFILES},
+};
+
+fn steamid(n: u64) -> SteamID {
+    SteamID::from(76561197960265728 + n)
+}
+
+/// A database file of its own for each test, removed when it is dropped
+struct TempDatabase(PathBuf);
+
+impl TempDatabase {
+    fn new(name: &str) -> TempDatabase {
+        let path =
+            std::env::temp_dir().join(format!("mac-test-{}-{}.sqlite", name, std::process::id()));
+        let database = TempDatabase(path);
+        database.remove();
+        database
+    }
+
+    fn open(&self) -> Sqlite {
+        Sqlite::open(&self.0).expect("Open database")
+    }
+
+    fn remove(&self) {
+        for suffix in ["", "-wal", "-shm"] {
+            let _ = fs::remove_file(format!("{}{}", self.0.display(), suffix));
+        }
+    }
+}
+
+impl Drop for TempDatabase {
+    fn drop(&mut self) {
+        self.remove();
+    }
+}
+
+fn write_all(storage: &Sqlite, all: &HashMap<SteamID, String>) {
+    storage
+        .write_records(RowChanges {
+            changed: all.clone(),
+            removed: Vec::new(),
+        })
+        .expect("Write records");
+}
+
+#[test]
+fn records_and_documents_survive_reopening() {
+    let database = TempDatabase::new("sqlite-round-trip");
+    let all = HashMap::from([
+        (steamid(1), r#"{"verdict":"Cheater"}"#.to_string()),
+        (steamid(2), r#"{"verdict":"Bot"}"#.to_string()),
+    ]);
+    {
+        let storage = database.open();
+        assert!(storage.is_empty().unwrap());
+        assert_eq!(storage.read_records().unwrap(), None);
+        assert_eq!(storage.read_document("steam_cache").unwrap(), None);
+
+        write_all(&storage, &all);
+        storage.write_document("steam_cache", "{}").unwrap();
+        storage.write_document("steam_cache", "[]").unwrap();
+    }
+
+    let storage = database.open();
+    assert!(!storage.is_empty().unwrap());
+    assert_eq!(storage.read_records().unwrap(), Some(all));
+    assert_eq!(
+        storage.read_document("steam_cache").unwrap().as_deref(),
+        Some("[]")
+    );
+}
+
+#[test]
+fn only_changed_records_are_written() {
+    let database = TempDatabase::new("sqlite-incremental");
+    let storage = database.open();
+    let all: HashMap<SteamID, String> = (1..=3)
+        .map(|n| (steamid(n), format!(r#"{{"n":{}}}"#, n)))
+        .collect();
+    write_all(&storage, &all);
+
+    // Records that aren't mentioned are left as they were
+    storage
+        .write_records(RowChanges {
+            changed: HashMap::from([(steamid(1), r#"{"n":10}"#.to_string())]),
+            removed: vec![steamid(2)],
+        })
+        .unwrap();
+
+    let records = database.open().read_records().unwrap().unwrap();
+    assert_eq!(
+        records,
+        HashMap::from([
+            (steamid(1), r#"{"n":10}"#.to_string()),
+            (steamid(3), r#"{"n":3}"#.to_string()),
+        ])
+    );
+}
+
+#[test]
+fn table_rows_are_kept_apart() {
+    let database = TempDatabase::new("sqlite-tables");
+    let storage = database.open();
+    assert_eq!(storage.read_table(STEAM_PROFILES).unwrap(), None);
+
+    let changed: HashMap<SteamID, String> = (1..=2)
+        .map(|n| (steamid(n), format!(r#"{{"fetched":{}}}"#, n)))
+        .collect();
+    storage
+        .write_table(
+            STEAM_PROFILES,
+            RowChanges {
+                changed,
+                removed: Vec::new(),
+            },
+        )
+        .unwrap();
+    storage
+        .write_table(
+            STEAM_FRIENDS,
+            RowChanges {
+                changed: HashMap::from([(steamid(1), r#"{"friends":null}"#.to_string())]),
+                removed: Vec::new(),
+            },
+        )
+        .unwrap();
+
+    // Rows that aren't mentioned are left as they were, in this table and the other
+    storage
+        .write_table(
+            STEAM_PROFILES,
+            RowChanges {
+                changed: HashMap::from([(steamid(3), r#"{"fetched":3}"#.to_string())]),
+                removed: vec![steamid(1)],
+            },
+        )
+        .unwrap();
+
+    let storage = database.open();
+    assert_eq!(
+        storage.read_table(STEAM_PROFILES).unwrap(),
+        Some(HashMap::from([
+            (steamid(2), r#"{"fetched":2}"#.to_string()),
+            (steamid(3), r#"{"fetched":3}"#.to_string()),
+        ]))
+    );
+    assert_eq!(
+        storage.read_table(STEAM_FRIENDS).unwrap(),
+        Some(HashMap::from([(
+            steamid(1),
+            r#"{"friends":null}"#.to_string()
+        )]))
+    );
+}
@@ -0,0 +1,592 @@
+//! Runs the Steam API manager's loop against a mock of the Web API, to check lookups are batched,
+//! cached, retried and answered the way the rest of the client expects without making any
+//! requests to Steam.
+
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use client_backend::{
+    player::Friend,
+    settings::ConfigFilesError,
+    steamapi::{
+        backend::SteamApiBackend,
+        retry::{Endpoint, RateLimited},
+        risk::RiskLevel,
+        LookupBatching, LookupPriority, SteamAPIError, SteamAPIManager, SteamAPIMessage,
+        SteamAPIResponse,
+    },
+    steamid::SteamID,
+    storage::{RowChanges, Storage, STEAM_CACHE},
+};
+use serde_json::json;
+use tappet::response_types::{PlayerBans, PlayerSummary};
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
+
+/// Looks like a real key, so the manager uses the API rather than scraping
+const API_KEY: &str = "0123456789abcdef0123456789abcdef";
+const EXTRA_API_KEY: &str = "fedcba9876543210fedcba9876543210";
+const RESPONSE_TIMEOUT: Duration = Duration::from_secs(5);
+
+fn steamid(n: u64) -> SteamID {
+    SteamID::from(76561197960265728 + n)
+}
+
+/// The Web API, with a public profile for each account it is given
+#[derive(Default)]
+struct MockSteam {
+    names: HashMap<SteamID, &'static str>,
+    friends: HashMap<SteamID, Vec<Friend>>,
+    /// Rate limit the next request for summaries
+    rate_limit: AtomicBool,
+    /// The players in each request for summaries, in order
+    summary_requests: Mutex<Vec<Vec<SteamID>>>,
+    /// The API key each request for summaries was made with
+    summary_keys: Mutex<Vec<String>>,
+    friend_list_requests: Mutex<Vec<SteamID>>,
+    /// The response to each IPlayerService method for a player, empty if there isn't one
+    services: HashMap<(SteamID, &'static str), serde_json::Value>,
+    /// The player and method of each IPlayerService request
+    service_requests: Mutex<Vec<(SteamID, String)>>,
+}
+
+impl MockSteam {
+    fn with_players(players: &[(SteamID, &'static str)]) -> MockSteam {
+        MockSteam {
+            names: players.iter().copied().collect(),
+            ..Default::default()
+        }
+    }
+
+    fn summary_requests(&self) -> Vec<Vec<SteamID>> {
+        self.summary_requests.lock().unwrap().clone()
+    }
+}
+
+#[async_trait]
+impl SteamApiBackend for MockSteam {
+    async fn verify_api_key(&self, _api_key: &str) -> Result<bool> {
+        Ok(true)
+    }
+
+    async fn player_summaries(
+        &self,
+        api_key: &str,
+        players: &[SteamID],
+    ) -> Result<Vec<PlayerSummary>> {
+        self.summary_requests.lock().unwrap().push(players.to_vec());
+        self.summary_keys.lock().unwrap().push(api_key.to_string());
+        if self.rate_limit.swap(false, Ordering::SeqCst) {
+            return Err(RateLimited(Endpoint::Summaries, Duration::from_millis(100)).into());
+        }
+
+        Ok(players
+            .iter()
+            .filter_map(|steamid| {
+                let name = self.names.get(steamid)?;
+                let summary = json!({
+                    "steamid": steamid.to_string(),
+                    "communityvisibilitystate": 3,
+                    "personaname": name,
+                    "profileurl": format!("https://steamcommunity.com/profiles/{}/", steamid),
+                    "avatar": "",
+                    "avatarmedium": "",
+                    "avatarfull": "",
+                    "avatarhash": "",
+                    "personastate": 0,
+                    "timecreated": 1262304000,
+                });
+                Some(serde_json::from_value(summary).expect("Mock player summary"))
+            })
+            .collect())
+    }
+
+    async fn player_bans(&self, _api_key: &str, players: &[SteamID]) -> Result<Vec<PlayerBans>> {
+        Ok(players
+            .iter()
+            .filter(|steamid| self.names.contains_key(steamid))
+            .map(|steamid| {
+                let bans = json!({
+                    "steam_id": steamid.to_string(),
+                    "community_banned": false,
+                    "vac_banned": false,
+                    "number_of_vac_bans": 0,
+                    "days_since_last_ban": 0,
+                    "number_of_game_bans": 0,
+                    "economy_ban": "none",
+                });
+                serde_json::from_value(bans).expect("Mock player bans")
+            })
+            .collect())
+    }
+
+    async fn friend_list(&self, _api_key: &str, player: SteamID) -> Result<Vec<Friend>> {
+        self.friend_list_requests.lock().unwrap().push(player);
+        self.friends
+            .get(&player)
+            .cloned()
+            .ok_or_else(|| anyhow!("No friend list for {}", player))
+    }
+
+    async fn resolve_vanity_url(&self, _api_key: &str, name: &str) -> Result<SteamID> {
+        Err(anyhow!("No profile found for {}", name))
+    }
+
+    async fn player_service(
+        &self,
+        _api_key: &str,
+        method: &str,
+        player: SteamID,
+        _params: &[(&str, &str)],
+    ) -> Result<serde_json::Map<String, serde_json::Value>> {
+        self.service_requests
+            .lock()
+            .unwrap()
+            .push((player, method.to_string()));
+        Ok(self
+            .services
+            .iter()
+            .find(|((p, m), _)| *p == player && *m == method)
+            .and_then(|(_, response)| response.as_object().cloned())
+            .unwrap_or_default())
+    }
+}
+
+/// Nothing is ever saved, so each test starts with an empty cache
+#[derive(Debug)]
+struct NoStorage;
+
+impl Storage for NoStorage {
+    fn read_document(&self, _name: &str) -> Result<Option<String>, ConfigFilesError> {
+        Ok(None)
+    }
+
+    fn write_document(&self, _name: &str, _contents: &str) -> Result<(), ConfigFilesError> {
+        Ok(())
+    }
+
+    fn read_records(&self) -> Result<Option<HashMap<SteamID, String>>, ConfigFilesError> {
+        Ok(None)
+    }
+
+    fn write_records(&self, _changes: RowChanges) -> Result<(), ConfigFilesError> {
+        Ok(())
+    }
+}
+
+/// A cache saved as a single document by an earlier version, which is never saved over
+#[derive(Debug)]
+struct SavedCache(String);
+
+impl Storage for SavedCache {
+    fn read_document(&self, name: &str) -> Result<Option<String>, ConfigFilesError> {
+        Ok(Some(self.0.clone()).filter(|_| name == STEAM_CACHE))
+    }
+
+    fn write_document(&self, _name: &str, _contents: &str) -> Result<(), ConfigFilesError> {
+        Ok(())
+    }
+
+    fn read_records(&self) -> Result<Option<HashMap<SteamID, String>>, ConfigFilesError> {
+        Ok(None)
+    }
+
+    fn write_records(&self, _changes: RowChanges) -> Result<(), ConfigFilesError> {
+        Ok(())
+    }
+}
+
+/// Start the manager's loop against `steam`, waiting until the first batch (which goes out as
+/// soon as the loop starts) has passed so every request made afterwards waits for the next one
+async fn start(
+    steam: Arc<MockSteam>,
+) -> (
+    UnboundedSender<SteamAPIMessage>,
+    UnboundedReceiver<SteamAPIResponse>,
+) {
+    start_with(steam, Arc::new(NoStorage)).await
+}
+
+/// [start], with the cache loaded from `storage`
+async fn start_with(
+    steam: Arc<MockSteam>,
+    storage: Arc<dyn Storage>,
+) -> (
+    UnboundedSender<SteamAPIMessage>,
+    UnboundedReceiver<SteamAPIResponse>,
+) {
+    let (request_send, request_recv) = unbounded_channel();
+    let (response_recv, mut manager) = SteamAPIManager::new(
+        API_KEY.into(),
+        storage,
+        Duration::from_secs(60 * 60),
+        Duration::from_secs(60 * 60),
+        false,
+        LookupBatching::default(),
+        request_recv,
+    );
+    manager.set_backend(steam);
+    tokio::task::spawn(async move { manager.api_loop().await });
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    (request_send, response_recv)
+}
+
+async fn next_response(responses: &mut UnboundedReceiver<SteamAPIResponse>) -> SteamAPIResponse {
+    tokio::time::timeout(RESPONSE_TIMEOUT, responses.recv())
+        .await
+        .expect("Timed out waiting for a response")
+        .expect("Steam API manager stopped")
+}
+
+/// The name each player was looked up with, or why they couldn't be, waiting for `n` responses
+async fn lookups(
+    responses: &mut UnboundedReceiver<SteamAPIResponse>,
+    n: usize,
+) -> HashMap<SteamID, Result<String, SteamAPIError>> {
+    let mut results = HashMap::new();
+    while results.len() < n {
+        match next_response(responses).await {
+            SteamAPIResponse::SteamInfo((steamid, info)) => {
+                results.insert(steamid, Ok(info.account_name.to_string()));
+            }
+            SteamAPIResponse::LookupFailed(steamid, error) => {
+                results.insert(steamid, Err(error));
+            }
+            _ => {}
+        }
+    }
+    results
+}
+
+#[tokio::test]
+async fn lookups_are_batched() {
+    let players = [
+        (steamid(1), "one"),
+        (steamid(2), "two"),
+        (steamid(3), "three"),
+    ];
+    let steam = Arc::new(MockSteam::with_players(&players));
+    let (requests, mut responses) = start(steam.clone()).await;
+
+    for (steamid, _) in players {
+        requests
+            .send(SteamAPIMessage::Lookup(steamid, LookupPriority::Normal))
+            .unwrap();
+    }
+    // Asking again before the batch goes out doesn't look them up twice
+    requests
+        .send(SteamAPIMessage::Lookup(steamid(1), LookupPriority::Normal))
+        .unwrap();
+
+    let results = lookups(&mut responses, players.len()).await;
+    for (steamid, name) in players {
+        assert_eq!(results[&steamid], Ok(name.to_string()));
+    }
+    assert_eq!(
+        steam.summary_requests(),
+        vec![vec![steamid(1), steamid(2), steamid(3)]]
+    );
+}
+
+#[tokio::test]
+async fn high_priority_lookups_go_first() {
+    let players = [(steamid(1), "one"), (steamid(2), "two")];
+    let steam = Arc::new(MockSteam::with_players(&players));
+    let (requests, mut responses) = start(steam.clone()).await;
+
+    requests
+        .send(SteamAPIMessage::Lookup(steamid(1), LookupPriority::Normal))
+        .unwrap();
+    requests
+        .send(SteamAPIMessage::Lookup(steamid(2), LookupPriority::High))
+        .unwrap();
+
+    lookups(&mut responses, players.len()).await;
+    assert_eq!(steam.summary_requests(), vec![vec![steamid(2), steamid(1)]]);
+}
+
+#[tokio::test]
+async fn cached_profiles_are_not_looked_up_again() {
+    let steam = Arc::new(MockSteam::with_players(&[(steamid(1), "one")]));
+    let (requests, mut responses) = start(steam.clone()).await;
+
+    for _ in 0..2 {
+        requests
+            .send(SteamAPIMessage::Lookup(steamid(1), LookupPriority::Normal))
+            .unwrap();
+        let results = lookups(&mut responses, 1).await;
+        assert_eq!(results[&steamid(1)], Ok("one".to_string()));
+    }
+    assert_eq!(steam.summary_requests().len(), 1);
+}
+
+#[tokio::test]
+async fn missing_accounts_fail_on_their_own() {
+    let steam = Arc::new(MockSteam::with_players(&[(steamid(1), "one")]));
+    let (requests, mut responses) = start(steam.clone()).await;
+
+    for steamid in [steamid(1), steamid(2)] {
+        requests
+            .send(SteamAPIMessage::Lookup(steamid, LookupPriority::Normal))
+            .unwrap();
+    }
+
+    let results = lookups(&mut responses, 2).await;
+    assert_eq!(results[&steamid(1)], Ok("one".to_string()));
+    assert_eq!(results[&steamid(2)], Err(SteamAPIError::NotFound));
+}
+
+#[tokio::test]
+async fn rate_limited_lookups_are_retried() {
+    let steam = Arc::new(MockSteam::with_players(&[(steamid(1), "one")]));
+    steam.rate_limit.store(true, Ordering::SeqCst);
+    let (requests, mut responses) = start(steam.clone()).await;
+
+    requests
+        .send(SteamAPIMessage::Lookup(steamid(1), LookupPriority::Normal))
+        .unwrap();
+
+    let results = lookups(&mut responses, 1).await;
+    assert_eq!(results[&steamid(1)], Err(SteamAPIError::RateLimited));
+    let results = lookups(&mut responses, 1).await;
+    assert_eq!(results[&steamid(1)], Ok("one".to_string()));
+    assert_eq!(steam.summary_requests().len(), 2);
+}
+
+#[tokio::test]
+async fn friend_lists_are_fetched_once() {
+    let friend = Friend {
+        steamid: steamid(2),
+        friend_since: 1262304000,
+    };
+    let mut steam = MockSteam::with_players(&[(steamid(1), "one")]);
+    steam.friends.insert(steamid(1), vec![friend.clone()]);
+    let steam = Arc::new(steam);
+    let (requests, mut responses) = start(steam.clone()).await;
+
+    for _ in 0..2 {
+        requests
+            .send(SteamAPIMessage::CheckFriends(vec![steamid(1)]))
+            .unwrap();
+        loop {
+            if let SteamAPIResponse::FriendLists((steamid, friends)) =
+                next_response(&mut responses).await
+            {
+                assert_eq!(steamid, self::steamid(1));
+                let friends: Vec<SteamID> = friends.unwrap().iter().map(|f| f.steamid).collect();
+                assert_eq!(friends, vec![friend.steamid]);
+                break;
+            }
+        }
+    }
+    assert_eq!(
+        *steam.friend_list_requests.lock().unwrap(),
+        vec![steamid(1)]
+    );
+}
+
+#[tokio::test]
+async fn cancelled_lookups_are_not_sent() {
+    let players = [(steamid(1), "one"), (steamid(2), "two")];
+    let steam = Arc::new(MockSteam::with_players(&players));
+    let (requests, mut responses) = start(steam.clone()).await;
+
+    for (steamid, _) in players {
+        requests
+            .send(SteamAPIMessage::Lookup(steamid, LookupPriority::Normal))
+            .unwrap();
+    }
+    requests
+        .send(SteamAPIMessage::CancelPending(Some(vec![steamid(1)])))
+        .unwrap();
+
+    let results = lookups(&mut responses, 1).await;
+    assert_eq!(results[&steamid(2)], Ok("two".to_string()));
+    assert_eq!(steam.summary_requests(), vec![vec![steamid(2)]]);
+}
+
+#[tokio::test]
+async fn cancelled_lookups_are_not_retried() {
+    let steam = Arc::new(MockSteam::with_players(&[(steamid(1), "one")]));
+    steam.rate_limit.store(true, Ordering::SeqCst);
+    let (requests, mut responses) = start(steam.clone()).await;
+
+    requests
+        .send(SteamAPIMessage::Lookup(steamid(1), LookupPriority::Normal))
+        .unwrap();
+    let results = lookups(&mut responses, 1).await;
+    assert_eq!(results[&steamid(1)], Err(SteamAPIError::RateLimited));
+
+    requests.send(SteamAPIMessage::CancelPending(None)).unwrap();
+    let retried = tokio::time::timeout(Duration::from_secs(2), responses.recv()).await;
+    assert!(retried.is_err(), "Cancelled lookup was retried");
+    assert_eq!(steam.summary_requests().len(), 1);
+}
+
+#[tokio::test]
+async fn looked_up_profiles_have_their_risk_assessed() {
+    let steam = Arc::new(MockSteam::with_players(&[(steamid(1), "one")]));
+    let (requests, mut responses) = start(steam).await;
+
+    requests
+        .send(SteamAPIMessage::Lookup(steamid(1), LookupPriority::Normal))
+        .unwrap();
+    let info = loop {
+        if let SteamAPIResponse::SteamInfo((_, info)) = next_response(&mut responses).await {
+            break info;
+        }
+    };
+
+    // An old public profile with no bans, and nothing else known about it
+    let risk = info.risk.expect("Risk assessed");
+    assert_eq!(risk.score, 0);
+    assert_eq!(risk.level, RiskLevel::Low);
+    assert!(risk.factors.is_empty());
+}
+
+#[tokio::test]
+async fn profile_details_come_from_one_request_each() {
+    let mut steam = MockSteam::with_players(&[(steamid(1), "one"), (steamid(2), "two")]);
+    steam.services = HashMap::from([
+        (
+            (steamid(1), "GetOwnedGames"),
+            json!({
+                "game_count": 3,
+                "games": [
+                    { "appid": 440, "playtime_forever": 6000 },
+                    { "appid": 620, "playtime_forever": 30 },
+                    { "appid": 730, "playtime_forever": 0 },
+                ],
+            }),
+        ),
+        ((steamid(1), "GetSteamLevel"), json!({ "player_level": 12 })),
+        (
+            (steamid(1), "GetProfileCustomization"),
+            json!({ "customizations": [{}, {}] }),
+        ),
+    ]);
+    let steam = Arc::new(steam);
+    let (requests, mut responses) = start(steam.clone()).await;
+
+    for n in 1..=2 {
+        requests
+            .send(SteamAPIMessage::Lookup(steamid(n), LookupPriority::Normal))
+            .unwrap();
+    }
+    let mut infos = HashMap::new();
+    while infos.len() < 2 {
+        if let SteamAPIResponse::SteamInfo((steamid, info)) = next_response(&mut responses).await {
+            infos.insert(steamid, info);
+        }
+    }
+
+    let one = &infos[&steamid(1)];
+    assert_eq!(one.games_visible, Some(2));
+    assert_eq!(one.tf2_hours, Some(100));
+    assert_eq!(one.steam_level, Some(12));
+    assert_eq!(one.showcases, Some(2));
+    // Nothing could be seen of the other profile
+    let two = &infos[&steamid(2)];
+    assert_eq!(two.games_visible, None);
+    assert_eq!(two.steam_level, None);
+
+    let mut service_requests = steam.service_requests.lock().unwrap().clone();
+    service_requests.sort();
+    for n in 1..=2 {
+        let methods: Vec<&str> = service_requests
+            .iter()
+            .filter(|(player, _)| *player == steamid(n))
+            .map(|(_, method)| method.as_str())
+            .collect();
+        assert_eq!(
+            methods,
+            [
+                "GetOwnedGames",
+                "GetProfileCustomization",
+                "GetProfileItemsEquipped",
+                "GetSteamLevel"
+            ]
+        );
+    }
+}
+
+#[tokio::test]
+async fn lookups_take_turns_with_api_keys() {
+    let steam = Arc::new(MockSteam::with_players(&[
+        (steamid(1), "one"),
+        (steamid(2), "two"),
+        (steamid(3), "three"),
+    ]));
+    let (requests, mut responses) = start(steam.clone()).await;
+    requests
+        .send(SteamAPIMessage::SetExtraAPIKeys(vec![EXTRA_API_KEY.into()]))
+        .unwrap();
+
+    for n in 1..=3 {
+        requests
+            .send(SteamAPIMessage::Lookup(steamid(n), LookupPriority::Normal))
+            .unwrap();
+        lookups(&mut responses, 1).await;
+    }
+
+    let keys = steam.summary_keys.lock().unwrap().clone();
+    assert_eq!(keys.len(), 3);
+    assert_ne!(keys[0], keys[1]);
+    assert_eq!(keys[0], keys[2]);
+    assert!(keys.contains(&EXTRA_API_KEY.to_string()));
+}
+
+#[tokio::test]
+async fn private_friend_lists_are_rechecked_within_budget() {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs();
+    let day = 24 * 60 * 60;
+    // Private for a week when last checked a week ago, so due another check
+    let due = json!({"fetched": now - 7 * day, "friends": null, "privateSince": now - 14 * day});
+    // Private for two weeks when last checked an hour ago
+    let checked =
+        json!({"fetched": now - 60 * 60, "friends": null, "privateSince": now - 14 * day});
+    let cache = json!({
+        "players": {},
+        "friends": {
+            steamid(1).to_string(): due,
+            steamid(2).to_string(): due,
+            steamid(3).to_string(): checked,
+        },
+    });
+
+    let steam = Arc::new(MockSteam::default());
+    let (requests, mut responses) =
+        start_with(steam.clone(), Arc::new(SavedCache(cache.to_string()))).await;
+    requests
+        .send(SteamAPIMessage::SetPrivateRecheckBudget(1))
+        .unwrap();
+    requests
+        .send(SteamAPIMessage::CheckFriends(vec![
+            steamid(1),
+            steamid(2),
+            steamid(3),
+        ]))
+        .unwrap();
+
+    let mut answered = 0;
+    while answered < 3 {
+        if let SteamAPIResponse::FriendLists((_, friends)) = next_response(&mut responses).await {
+            assert!(friends.is_err());
+            answered += 1;
+        }
+    }
+    assert_eq!(
+        *steam.friend_list_requests.lock().unwrap(),
+        vec![steamid(1)]
+    );
+}
@@ -0,0 +1,56 @@
+//! Checks the SteamID schema checked in to `data/schemas` is the one the code generates, and that
+//! the API's schemas refer to it. Run with `UPDATE_SCHEMAS=1` to write it again after changing
+//! [SteamID::json_schema].
+
+use std::{fs, path::Path};
+
+use client_backend::steamid::SteamID;
+
+/// Where the generated schema is checked in
+const SCHEMA_FILE: &str = "data/schemas/steamid.json";
+
+/// Schemas with SteamIDs in them, and the definition each one needs
+const REFERENCES: &[(&str, &str)] = &[
+    (
+        "validate_playerPost.json",
+        "steamid.json#/definitions/steamID",
+    ),
+    (
+        "validate_playerResponse.json",
+        "steamid.json#/definitions/steamID64",
+    ),
+];
+
+fn schema_path(name: &str) -> std::path::PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join(name)
+}
+
+#[test]
+fn checked_in_schema_is_generated() {
+    let path = schema_path(SCHEMA_FILE);
+    let generated = SteamID::schema_document();
+    if std::env::var_os("UPDATE_SCHEMAS").is_some() {
+        fs::write(&path, &generated).expect("Write SteamID schema");
+    }
+
+    let checked_in = fs::read_to_string(&path).expect("Read SteamID schema");
+    assert!(
+        checked_in == generated,
+        "{} is out of date, run the tests with UPDATE_SCHEMAS=1 to generate it again",
+        SCHEMA_FILE
+    );
+}
+
+#[test]
+fn api_schemas_refer_to_it() {
+    for (name, reference) in REFERENCES {
+        let path = schema_path(&format!("data/schemas/{}", name));
+        let schema = fs::read_to_string(&path).expect("Read schema");
+        assert!(
+            schema.contains(&format!("\"$ref\": \"{}\"", reference)),
+            "{} doesn't refer to {}",
+            name,
+            reference
+        );
+    }
+}
@@ -0,0 +1,129 @@
+//! Checks that writes of the player records are coalesced by [WriteBehind] without any changes
+//! being lost, and that only the records that changed are written, using a storage that just
+//! keeps track of what it was asked to write.
+
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+};
+
+use anyhow::anyhow;
+use client_backend::{
+    player_records::{PlayerRecords, Verdict},
+    settings::ConfigFilesError,
+    steamid::SteamID,
+    storage::{RowChanges, Storage, WriteBehind},
+};
+
+fn steamid(n: u64) -> SteamID {
+    SteamID::from(76561197960265728 + n)
+}
+
+/// The records as last written, the number of times they were, and how many changed in the last
+/// write
+#[derive(Debug, Default)]
+struct Recorded {
+    records: Mutex<HashMap<SteamID, String>>,
+    writes: Mutex<usize>,
+    last_changes: Mutex<(usize, usize)>,
+    failing: AtomicBool,
+}
+
+#[derive(Debug, Clone, Default)]
+struct RecordingStorage(Arc<Recorded>);
+
+impl Storage for RecordingStorage {
+    fn read_document(&self, _name: &str) -> Result<Option<String>, ConfigFilesError> {
+        Ok(None)
+    }
+
+    fn write_document(&self, _name: &str, _contents: &str) -> Result<(), ConfigFilesError> {
+        Ok(())
+    }
+
+    fn read_records(&self) -> Result<Option<HashMap<SteamID, String>>, ConfigFilesError> {
+        Ok(None)
+    }
+
+    fn write_records(&self, changes: RowChanges) -> Result<(), ConfigFilesError> {
+        if self.0.failing.load(Ordering::Relaxed) {
+            return Err(ConfigFilesError::Other(anyhow!("Disk full")));
+        }
+        *self.0.last_changes.lock().unwrap() = (changes.changed.len(), changes.removed.len());
+        let mut records = self.0.records.lock().unwrap();
+        for steamid in &changes.removed {
+            records.remove(steamid);
+        }
+        records.extend(changes.changed);
+        *self.0.writes.lock().unwrap() += 1;
+        Ok(())
+    }
+}
+
+/// Records kept in `inner`, through a [WriteBehind]
+fn records(inner: &RecordingStorage) -> PlayerRecords {
+    PlayerRecords::load_or_create(Arc::new(WriteBehind::new(Arc::new(inner.clone()))))
+}
+
+#[test]
+fn rapid_changes_are_written_once() {
+    let inner = RecordingStorage::default();
+    let mut records = records(&inner);
+    for n in 0..50 {
+        records.entry(steamid(n)).or_default().verdict = Verdict::Bot;
+        records.save_ok();
+    }
+    assert_eq!(*inner.0.writes.lock().unwrap(), 0);
+
+    records.flush_ok();
+    assert_eq!(*inner.0.writes.lock().unwrap(), 1);
+    assert_eq!(inner.0.records.lock().unwrap().len(), 50);
+}
+
+#[test]
+fn failed_writes_are_retried() {
+    let inner = RecordingStorage::default();
+    let mut records = records(&inner);
+    records.entry(steamid(1)).or_default().verdict = Verdict::Cheater;
+    inner.0.failing.store(true, Ordering::Relaxed);
+    records.flush_ok();
+    assert!(inner.0.records.lock().unwrap().is_empty());
+
+    inner.0.failing.store(false, Ordering::Relaxed);
+    records.flush_ok();
+    assert!(inner.0.records.lock().unwrap().contains_key(&steamid(1)));
+}
+
+#[test]
+fn changes_are_written_when_dropped() {
+    let inner = RecordingStorage::default();
+    let mut records = records(&inner);
+    records.entry(steamid(1)).or_default().verdict = Verdict::Suspicious;
+    records.save_ok();
+    drop(records);
+    assert!(inner.0.records.lock().unwrap().contains_key(&steamid(1)));
+}
+
+#[test]
+fn only_changed_records_are_written() {
+    let inner = RecordingStorage::default();
+    let mut records = records(&inner);
+    for n in 0..20 {
+        records.entry(steamid(n)).or_default().verdict = Verdict::Bot;
+    }
+    records.flush_ok();
+    assert_eq!(*inner.0.last_changes.lock().unwrap(), (20, 0));
+
+    records.get_mut(&steamid(1)).unwrap().verdict = Verdict::Cheater;
+    records.remove(&steamid(2));
+    records.flush_ok();
+    assert_eq!(*inner.0.last_changes.lock().unwrap(), (1, 1));
+    assert_eq!(inner.0.records.lock().unwrap().len(), 19);
+
+    // Nothing changed, so nothing is written
+    records.flush_ok();
+    assert_eq!(*inner.0.writes.lock().unwrap(), 2);
+}
@@ -0,0 +1,74 @@
+//! Checks how votekicks have gone for each signal changes what is suggested for a player, so a
+//! rule whose votekicks keep failing no longer gets players marked as cheaters on its own.
+
+use std::{fs, path::PathBuf, sync::Arc};
+
+use client_backend::{
+    demo::suspicion::SuspicionKind,
+    player_records::Verdict,
+    suggestions::SuggestedAction,
+    votes::{Signal, VoteFeedback},
+};
+
+/// Load feedback where `failing` has failed most of its votekicks
+fn feedback_with_failures(failing: &Signal) -> VoteFeedback {
+    let path: PathBuf = std::env::temp_dir().join(format!(
+        "mac-test-vote-feedback-{}.json",
+        std::process::id()
+    ));
+    let contents = serde_json::json!({
+        "signals": [{
+            "signal": failing,
+            "weight": 0.8 * 0.8 * 0.8,
+            "votesPassed": 0,
+            "votesFailed": 3,
+            "flaggedForReview": true,
+        }],
+        "audit": [],
+    });
+    fs::write(&path, contents.to_string()).expect("Write vote feedback");
+    let feedback = VoteFeedback::load_from(path.clone()).expect("Load vote feedback");
+    let _ = fs::remove_file(path);
+    feedback
+}
+
+#[test]
+fn failed_votekicks_weaken_suggestions() {
+    let failing = Signal::Rule(Arc::from("Sniper named like a bot"));
+    let signals = vec![failing.clone(), Signal::List(Arc::from("friends.json"))];
+    let min_score = 2.0;
+
+    let fresh = feedback_with_failures(&Signal::BotProfile);
+    assert_eq!(fresh.score(&signals), 2.0);
+    assert_eq!(
+        SuggestedAction::for_player(Verdict::Player, &signals, fresh.score(&signals), min_score),
+        vec![SuggestedAction::Mark {
+            verdict: Verdict::Cheater
+        }]
+    );
+
+    let feedback = feedback_with_failures(&failing);
+    assert!(feedback.score(&signals) < min_score);
+    assert_eq!(
+        SuggestedAction::for_player(
+            Verdict::Player,
+            &signals,
+            feedback.score(&signals),
+            min_score
+        ),
+        vec![SuggestedAction::Mark {
+            verdict: Verdict::Suspicious
+        }]
+    );
+}
+
+#[test]
+fn marked_players_are_votekicked() {
+    let signals = vec![Signal::Heuristic(SuspicionKind::Headshots)];
+    assert_eq!(
+        SuggestedAction::for_player(Verdict::Cheater, &signals, 1.0, 2.0),
+        vec![SuggestedAction::Votekick, SuggestedAction::Report]
+    );
+    assert!(SuggestedAction::for_player(Verdict::Trusted, &signals, 1.0, 2.0).is_empty());
+    assert!(SuggestedAction::for_player(Verdict::Cheater, &[], 0.0, 2.0).is_empty());
+}
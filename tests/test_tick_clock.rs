@@ -0,0 +1,34 @@
+//! Checks demo ticks are placed in time at the rate the server ran at.
+
+use std::time::{Duration, UNIX_EPOCH};
+
+use client_backend::demo::clock::{TickClock, DEFAULT_TICK_RATE};
+
+#[test]
+fn ticks_follow_the_server_tick_rate() {
+    let at = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+
+    let usual = TickClock::anchored(6600, at, DEFAULT_TICK_RATE);
+    assert_eq!(usual.time_ms(6600), 1_700_000_000_000);
+    assert_eq!(usual.time_ms(0), 1_700_000_000_000 - 99_000);
+
+    // A server running at 33 ticks a second
+    let slow = TickClock::anchored(6600, at, 1000.0 / 30.0);
+    assert_eq!(slow.time_ms(0), 1_700_000_000_000 - 198_000);
+    assert_eq!(slow.iso8601(6630), "2023-11-14T22:13:20.900Z");
+}
+
+#[test]
+fn unknown_tick_rates_fall_back_to_the_usual_one() {
+    let at = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+    for rate in [0.0, f32::NAN, f32::INFINITY] {
+        let clock = TickClock::anchored(100, at, rate);
+        assert_eq!(clock.time_ms(0), 1_700_000_000_000 - 1500);
+    }
+}
+
+#[test]
+fn saved_clocks_without_a_tick_interval_use_the_usual_one() {
+    let clock: TickClock = serde_json::from_str(r#"{"startMs": 1000}"#).unwrap();
+    assert_eq!(clock.time_ms(100), 2500);
+}